@@ -25,8 +25,9 @@ use common::executor::Timer;
 use common::mm_ctx::{MmArc, MmWeak};
 use common::{block_on, now_ms, slurp_url, small_rng, DEX_FEE_ADDR_RAW_PUBKEY};
 use ethabi::{Contract, Token};
-use ethcore_transaction::{Action, Transaction as UnSignedEthTx, UnverifiedTransaction};
-use ethereum_types::{Address, H160, U256};
+use ethcore_transaction::{Action, SignedTransaction as SignedLegacyEthTx, Transaction as UnSignedEthTx,
+                          UnverifiedTransaction};
+use ethereum_types::{Address, H160, H256, U256};
 use ethkey::{public_to_address, KeyPair, Public};
 use futures::compat::Future01CompatExt;
 use futures::future::{join_all, select, Either, FutureExt, TryFutureExt};
@@ -40,7 +41,6 @@ use rpc::v1::types::Bytes as BytesJson;
 use secp256k1::PublicKey;
 use serde_json::{self as json, Value as Json};
 use sha3::{Digest, Keccak256};
-use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::ops::Deref;
 use std::path::PathBuf;
@@ -51,16 +51,21 @@ use std::thread;
 use std::time::Duration;
 use web3::types::{Action as TraceAction, BlockId, BlockNumber, Bytes, CallRequest, FilterBuilder, Log, Trace,
                   TraceFilterBuilder, Transaction as Web3Transaction, TransactionId};
-use web3::{self, Web3};
+use web3::{self, Transport, Web3};
 
 use super::{CoinProtocol, CoinTransportMetrics, CoinsContext, FeeApproxStage, FoundSwapTxSpend, HistorySyncState,
-            MarketCoinOps, MmCoin, RpcClientType, RpcTransportEventHandler, RpcTransportEventHandlerShared, SwapOps,
-            TradeFee, TradePreimageError, TradePreimageValue, Transaction, TransactionDetails, TransactionEnum,
-            TransactionFut, ValidateAddressResult, WithdrawFee, WithdrawRequest};
+            HtlcScriptType, MarketCoinOps, MmCoin, RpcClientType, RpcTransportEventHandler,
+            RpcTransportEventHandlerShared, SwapOps, TradeFee, TradePreimageError, TradePreimageValue, Transaction,
+            TransactionDetails, TransactionEnum, TransactionFut, ValidateAddressResult, WithdrawBatchRecipient,
+            WithdrawBatchRequest, WithdrawBatchResponse, WithdrawFee, WithdrawRequest};
 
-pub use ethcore_transaction::SignedTransaction as SignedEthTx;
 pub use rlp;
 
+mod eip1559;
+pub use self::eip1559::{Eip1559Fee, SignedEip1559Tx};
+use self::eip1559::{Eip1559RpcTx, Eip1559Tx, FeeHistory, EIP1559_TX_TYPE, FEE_HISTORY_BLOCK_COUNT,
+                    FEE_HISTORY_REWARD_PERCENTILE};
+
 mod web3_transport;
 use self::web3_transport::Web3Transport;
 
@@ -74,6 +79,9 @@ const SWAP_CONTRACT_ABI: &str = r#"[{"constant":false,"inputs":[{"name":"_id","t
 /// https://github.com/ethereum/EIPs/blob/master/EIPS/eip-20.md
 const ERC20_ABI: &str = r#"[{"constant":true,"inputs":[],"name":"name","outputs":[{"name":"","type":"string"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[{"name":"_spender","type":"address"},{"name":"_value","type":"uint256"}],"name":"approve","outputs":[{"name":"","type":"bool"}],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":true,"inputs":[],"name":"totalSupply","outputs":[{"name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[{"name":"_from","type":"address"},{"name":"_to","type":"address"},{"name":"_value","type":"uint256"}],"name":"transferFrom","outputs":[{"name":"","type":"bool"}],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":true,"inputs":[],"name":"decimals","outputs":[{"name":"","type":"uint8"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[{"name":"_spender","type":"address"},{"name":"_subtractedValue","type":"uint256"}],"name":"decreaseApproval","outputs":[{"name":"","type":"bool"}],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":true,"inputs":[{"name":"_owner","type":"address"}],"name":"balanceOf","outputs":[{"name":"balance","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[],"name":"symbol","outputs":[{"name":"","type":"string"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[{"name":"_to","type":"address"},{"name":"_value","type":"uint256"}],"name":"transfer","outputs":[{"name":"","type":"bool"}],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":false,"inputs":[{"name":"_spender","type":"address"},{"name":"_addedValue","type":"uint256"}],"name":"increaseApproval","outputs":[{"name":"","type":"bool"}],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":true,"inputs":[{"name":"_owner","type":"address"},{"name":"_spender","type":"address"}],"name":"allowance","outputs":[{"name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"inputs":[],"payable":false,"stateMutability":"nonpayable","type":"constructor"},{"anonymous":false,"inputs":[{"indexed":true,"name":"owner","type":"address"},{"indexed":true,"name":"spender","type":"address"},{"indexed":false,"name":"value","type":"uint256"}],"name":"Approval","type":"event"},{"anonymous":false,"inputs":[{"indexed":true,"name":"from","type":"address"},{"indexed":true,"name":"to","type":"address"},{"indexed":false,"name":"value","type":"uint256"}],"name":"Transfer","type":"event"}]"#;
 
+/// The subset of Disperse contract ABI: https://github.com/banteg/disperse-research/blob/master/contracts/Disperse.sol
+const MULTISEND_ABI: &str = r#"[{"constant":false,"inputs":[{"name":"recipients","type":"address[]"},{"name":"values","type":"uint256[]"}],"name":"disperseEther","outputs":[],"payable":true,"stateMutability":"payable","type":"function"},{"constant":false,"inputs":[{"name":"token","type":"address"},{"name":"recipients","type":"address[]"},{"name":"values","type":"uint256[]"}],"name":"disperseToken","outputs":[],"payable":false,"stateMutability":"nonpayable","type":"function"}]"#;
+
 /// Payment states from etomic swap smart contract: https://github.com/artemii235/etomic-swap/blob/master/contracts/EtomicSwap.sol#L5
 pub const PAYMENT_STATE_UNINITIALIZED: u8 = 0;
 pub const PAYMENT_STATE_SENT: u8 = 1;
@@ -92,12 +100,18 @@ const GAS_PRICE_APPROXIMATION_PERCENT_ON_ORDER_ISSUE: u64 = 5;
 /// - it may increase by 2% until a swap is started;
 /// - it may increase by 3% during the swap.
 const GAS_PRICE_APPROXIMATION_PERCENT_ON_TRADE_PREIMAGE: u64 = 7;
+/// Geth and Parity require the replacement transaction to pay at least 10% more, use some margin.
+const GAS_PRICE_REPLACEMENT_PERCENT: u64 = 15;
 
 const APPROVE_GAS_LIMIT: u64 = 50_000;
+/// The gas limit of the `disperseToken` call that can't be estimated since it follows the pending `approve` transaction.
+const MULTISEND_TOKEN_BASE_GAS: u64 = 50_000;
+const MULTISEND_TOKEN_GAS_PER_RECIPIENT: u64 = 60_000;
 
 lazy_static! {
     pub static ref SWAP_CONTRACT: Contract = unwrap!(Contract::load(SWAP_CONTRACT_ABI.as_bytes()));
     pub static ref ERC20_CONTRACT: Contract = unwrap!(Contract::load(ERC20_ABI.as_bytes()));
+    pub static ref MULTISEND_CONTRACT: Contract = unwrap!(Contract::load(MULTISEND_ABI.as_bytes()));
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -137,10 +151,14 @@ pub struct EthCoinImpl {
     key_pair: KeyPair,
     my_address: Address,
     swap_contract_address: Address,
+    /// The Disperse-like contract paying many recipients by one call, used by the batch withdraw if requested.
+    multisend_contract_address: Option<Address>,
     web3: Web3<Web3Transport>,
     /// The separate web3 instances kept to get nonce, will replace the web3 completely soon
     web3_instances: Vec<Web3Instance>,
     decimals: u8,
+    /// The chain id is set if the coin sends EIP-1559 (type 2) transactions, legacy transactions are sent otherwise.
+    eip1559_chain_id: Option<u64>,
     gas_station_url: Option<String>,
     history_sync_state: Mutex<HistorySyncState>,
     required_confirmations: AtomicU64,
@@ -292,6 +310,90 @@ impl EthCoinImpl {
         Box::new(fut)
     }
 
+    /// Get the fee parameters of the next transaction.
+    /// The EIP-1559 fees are derived from `eth_feeHistory` if the coin supports them, the legacy gas price is used otherwise.
+    fn get_gas_fee(&self) -> Box<dyn Future<Item = EthGasFee, Error = String> + Send> {
+        if self.eip1559_chain_id.is_none() {
+            return Box::new(self.get_gas_price().map(|gas_price| EthGasFee::Legacy { gas_price }));
+        }
+
+        let params = vec![
+            try_fus!(json::to_value(U256::from(FEE_HISTORY_BLOCK_COUNT))),
+            "latest".into(),
+            json!([FEE_HISTORY_REWARD_PERCENTILE]),
+        ];
+        let fut = self
+            .web3
+            .transport()
+            .execute("eth_feeHistory", params)
+            .map_err(|e| ERRL!("{}", e))
+            .and_then(|res| -> Result<EthGasFee, String> {
+                let history: FeeHistory = try_s!(json::from_value(res));
+                Ok(EthGasFee::Eip1559(try_s!(Eip1559Fee::from_fee_history(&history))))
+            });
+        Box::new(fut)
+    }
+
+    /// Signs the transaction of the type corresponding to the `gas_fee`.
+    fn sign_transaction(
+        &self,
+        nonce: U256,
+        value: U256,
+        action: Action,
+        data: Vec<u8>,
+        gas: U256,
+        gas_fee: &EthGasFee,
+    ) -> Result<SignedEthTx, String> {
+        match gas_fee {
+            EthGasFee::Legacy { gas_price } => {
+                let tx = UnSignedEthTx {
+                    nonce,
+                    value,
+                    action,
+                    data,
+                    gas,
+                    gas_price: *gas_price,
+                };
+                Ok(SignedEthTx::Legacy(tx.sign(self.key_pair.secret(), None)))
+            },
+            EthGasFee::Eip1559(fee) => {
+                let chain_id = match self.eip1559_chain_id {
+                    Some(id) => id,
+                    None => return ERR!("{} doesn't support EIP-1559 transactions", self.ticker),
+                };
+                let tx = Eip1559Tx {
+                    chain_id,
+                    nonce,
+                    max_priority_fee_per_gas: fee.max_priority_fee_per_gas,
+                    max_fee_per_gas: fee.max_fee_per_gas,
+                    gas,
+                    action,
+                    value,
+                    data,
+                };
+                Ok(SignedEthTx::Eip1559(try_s!(tx.sign(self.key_pair.secret()))))
+            },
+        }
+    }
+
+    /// Gets the transaction by hash taking its type into account.
+    /// `web3::types::Transaction` doesn't contain EIP-1559 fields, so the transaction is requested and parsed manually.
+    fn signed_transaction(&self, hash: H256) -> Box<dyn Future<Item = Option<SignedEthTx>, Error = String> + Send> {
+        let params = vec![try_fus!(json::to_value(hash))];
+        let fut = self
+            .web3
+            .transport()
+            .execute("eth_getTransactionByHash", params)
+            .map_err(|e| ERRL!("{}", e))
+            .and_then(|res| -> Result<Option<SignedEthTx>, String> {
+                if res.is_null() {
+                    return Ok(None);
+                }
+                Ok(Some(try_s!(signed_tx_from_rpc_json(res))))
+            });
+        Box::new(fut)
+    }
+
     fn estimate_gas(
         &self,
         req: CallRequest,
@@ -338,8 +440,7 @@ impl EthCoinImpl {
         swap_contract_address: Address,
         search_from_block: u64,
     ) -> Result<Option<FoundSwapTxSpend>, String> {
-        let unverified: UnverifiedTransaction = try_s!(rlp::decode(tx));
-        let tx = try_s!(SignedEthTx::new(unverified));
+        let tx = try_s!(signed_eth_tx_from_bytes(tx));
 
         let func_name = match self.coin_type {
             EthCoinType::Eth => "ethPayment",
@@ -347,7 +448,7 @@ impl EthCoinImpl {
         };
 
         let payment_func = try_s!(SWAP_CONTRACT.function(func_name));
-        let decoded = try_s!(payment_func.decode_input(&tx.data));
+        let decoded = try_s!(payment_func.decode_input(tx.data()));
         let id = match &decoded[0] {
             Token::FixedBytes(bytes) => bytes.clone(),
             _ => panic!(),
@@ -359,14 +460,12 @@ impl EthCoinImpl {
         if let Some(event) = found {
             match event.transaction_hash {
                 Some(tx_hash) => {
-                    let transaction = match try_s!(self.web3.eth().transaction(TransactionId::Hash(tx_hash)).wait()) {
+                    let transaction = match try_s!(self.signed_transaction(tx_hash).wait()) {
                         Some(t) => t,
                         None => return ERR!("Found ReceiverSpent event, but transaction {:02x} is missing", tx_hash),
                     };
 
-                    return Ok(Some(FoundSwapTxSpend::Spent(TransactionEnum::from(try_s!(
-                        signed_tx_from_web3_tx(transaction)
-                    )))));
+                    return Ok(Some(FoundSwapTxSpend::Spent(TransactionEnum::from(transaction))));
                 },
                 None => return ERR!("Found ReceiverSpent event, but it doesn't have tx_hash"),
             }
@@ -378,14 +477,12 @@ impl EthCoinImpl {
         if let Some(event) = found {
             match event.transaction_hash {
                 Some(tx_hash) => {
-                    let transaction = match try_s!(self.web3.eth().transaction(TransactionId::Hash(tx_hash)).wait()) {
+                    let transaction = match try_s!(self.signed_transaction(tx_hash).wait()) {
                         Some(t) => t,
                         None => return ERR!("Found SenderRefunded event, but transaction {:02x} is missing", tx_hash),
                     };
 
-                    return Ok(Some(FoundSwapTxSpend::Refunded(TransactionEnum::from(try_s!(
-                        signed_tx_from_web3_tx(transaction)
-                    )))));
+                    return Ok(Some(FoundSwapTxSpend::Refunded(TransactionEnum::from(transaction))));
                 },
                 None => return ERR!("Found SenderRefunded event, but it doesn't have tx_hash"),
             }
@@ -401,6 +498,12 @@ impl EthCoinImpl {
 }
 
 async fn withdraw_impl(ctx: MmArc, coin: EthCoin, req: WithdrawRequest) -> Result<TransactionDetails, String> {
+    if req.has_coin_control() {
+        return ERR!("{} doesn't support the coin control", coin.ticker);
+    }
+    if req.psbt {
+        return ERR!("{} doesn't support PSBT", coin.ticker);
+    }
     let to_addr = try_s!(coin.address_from_str(&req.to));
     let my_balance = try_s!(coin.my_balance().compat().await);
     let mut wei_amount = if req.max {
@@ -411,35 +514,13 @@ async fn withdraw_impl(ctx: MmArc, coin: EthCoin, req: WithdrawRequest) -> Resul
     if wei_amount > my_balance {
         return ERR!("The amount {} to withdraw is larger than balance", req.amount);
     };
-    let (mut eth_value, data, call_addr) = match coin.coin_type {
-        EthCoinType::Eth => (wei_amount, vec![], to_addr),
-        EthCoinType::Erc20(token_addr) => {
-            let function = try_s!(ERC20_CONTRACT.function("transfer"));
-            let data = try_s!(function.encode_input(&[Token::Address(to_addr), Token::Uint(wei_amount)]));
-            (0.into(), data, token_addr)
-        },
-    };
-
-    let (gas, gas_price) = match req.fee {
-        Some(WithdrawFee::EthGas { gas_price, gas }) => (gas.into(), try_s!(wei_from_big_decimal(&gas_price, 9))),
-        Some(_) => return ERR!("Unsupported input fee type"),
-        None => {
-            let gas_price = try_s!(coin.get_gas_price().compat().await);
-            let estimate_gas_req = CallRequest {
-                value: Some(eth_value),
-                data: Some(data.clone().into()),
-                from: Some(coin.my_address),
-                to: call_addr,
-                gas: None,
-                // gas price must be supplied because some smart contracts base their
-                // logic on gas price, e.g. TUSD: https://github.com/KomodoPlatform/atomicDEX-API/issues/643
-                gas_price: Some(gas_price),
-            };
-            let gas_fut = coin.estimate_gas(estimate_gas_req, None).compat();
-            (try_s!(gas_fut.await), gas_price)
-        },
+    let (mut eth_value, data, call_addr) = try_s!(transfer_call(&coin, to_addr, wei_amount));
+    let (gas, gas_fee) = match try_s!(gas_from_withdraw_fee(req.fee)) {
+        Some(gas_and_fee) => gas_and_fee,
+        None => try_s!(estimate_gas_and_fee(&coin, eth_value, data.clone(), call_addr).await),
     };
-    let total_fee = gas * gas_price;
+    // EIP-1559 transactions can't pay more than `max_fee_per_gas`, the unspent fee part stays on the balance
+    let total_fee = gas * gas_fee.max_gas_price();
 
     if req.max && coin.coin_type == EthCoinType::Eth {
         if eth_value < total_fee || wei_amount < total_fee {
@@ -463,42 +544,275 @@ async fn withdraw_impl(ctx: MmArc, coin: EthCoin, req: WithdrawRequest) -> Resul
         Either::Left((nonce_res, _)) => try_s!(nonce_res),
         Either::Right(_) => return ERR!("Get address nonce timed out"),
     };
-    let tx = UnSignedEthTx {
-        nonce,
-        value: eth_value,
-        action: Action::Call(call_addr),
-        data,
-        gas,
-        gas_price,
+    let signed = try_s!(coin.sign_transaction(nonce, eth_value, Action::Call(call_addr), data, gas, &gas_fee));
+    withdraw_tx_details(&coin, &signed, vec![to_addr], wei_amount, gas, &gas_fee)
+}
+
+/// Returns the value, the data and the destination of the transaction sending `wei_amount` coins to `to_addr`.
+fn transfer_call(coin: &EthCoin, to_addr: Address, wei_amount: U256) -> Result<(U256, Vec<u8>, Address), String> {
+    match coin.coin_type {
+        EthCoinType::Eth => Ok((wei_amount, vec![], to_addr)),
+        EthCoinType::Erc20(token_addr) => {
+            let function = try_s!(ERC20_CONTRACT.function("transfer"));
+            let data = try_s!(function.encode_input(&[Token::Address(to_addr), Token::Uint(wei_amount)]));
+            Ok((0.into(), data, token_addr))
+        },
+    }
+}
+
+/// Returns the gas limit and the gas fee set by the user or None if the fee isn't set explicitly.
+fn gas_from_withdraw_fee(fee: Option<WithdrawFee>) -> Result<Option<(U256, EthGasFee)>, String> {
+    match fee {
+        Some(WithdrawFee::EthGas { gas_price, gas }) => {
+            let gas_price = try_s!(wei_from_big_decimal(&gas_price, 9));
+            Ok(Some((gas.into(), EthGasFee::Legacy { gas_price })))
+        },
+        Some(WithdrawFee::EthGasEip1559 {
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+            gas,
+        }) => {
+            let max_fee_per_gas = try_s!(wei_from_big_decimal(&max_fee_per_gas, 9));
+            let max_priority_fee_per_gas = try_s!(wei_from_big_decimal(&max_priority_fee_per_gas, 9));
+            if max_priority_fee_per_gas > max_fee_per_gas {
+                return ERR!(
+                    "max_priority_fee_per_gas {} is greater than max_fee_per_gas {}",
+                    max_priority_fee_per_gas,
+                    max_fee_per_gas
+                );
+            }
+            let fee = Eip1559Fee {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            };
+            Ok(Some((gas.into(), EthGasFee::Eip1559(fee))))
+        },
+        Some(_) => ERR!("Unsupported input fee type"),
+        None => Ok(None),
+    }
+}
+
+async fn estimate_gas_and_fee(
+    coin: &EthCoin,
+    value: U256,
+    data: Vec<u8>,
+    call_addr: Address,
+) -> Result<(U256, EthGasFee), String> {
+    let gas_fee = try_s!(coin.get_gas_fee().compat().await);
+    let estimate_gas_req = CallRequest {
+        value: Some(value),
+        data: Some(data.into()),
+        from: Some(coin.my_address),
+        to: call_addr,
+        gas: None,
+        // gas price must be supplied because some smart contracts base their
+        // logic on gas price, e.g. TUSD: https://github.com/KomodoPlatform/atomicDEX-API/issues/643
+        gas_price: Some(gas_fee.max_gas_price()),
+    };
+    let gas = try_s!(coin.estimate_gas(estimate_gas_req, None).compat().await);
+    Ok((gas, gas_fee))
+}
+
+async fn withdraw_batch_impl(
+    ctx: MmArc,
+    coin: EthCoin,
+    req: WithdrawBatchRequest,
+) -> Result<WithdrawBatchResponse, String> {
+    let my_balance = try_s!(coin.my_balance().compat().await);
+    let mut addresses = Vec::with_capacity(req.recipients.len());
+    let mut amounts = Vec::with_capacity(req.recipients.len());
+    for recipient in req.recipients.iter() {
+        addresses.push(try_s!(coin.address_from_str(&recipient.address)));
+        amounts.push(try_s!(wei_from_big_decimal(&recipient.amount, coin.decimals)));
+    }
+    let total_amount = amounts.iter().fold(U256::zero(), |total, amount| total + *amount);
+    if total_amount > my_balance {
+        return ERR!("The total amount {} to withdraw is larger than balance", total_amount);
+    }
+    let requested_gas = try_s!(gas_from_withdraw_fee(req.fee));
+
+    let _nonce_lock = try_s!(
+        NONCE_LOCK
+            .lock(|_start, _now| {
+                if ctx.is_stopping() {
+                    return ERR!("MM is stopping, aborting withdraw_batch_impl in NONCE_LOCK");
+                }
+                Ok(0.5)
+            })
+            .await
+    );
+    let nonce_fut = get_addr_nonce(coin.my_address, coin.web3_instances.clone()).compat();
+    let mut nonce = match select(nonce_fut, Timer::sleep(30.)).await {
+        Either::Left((nonce_res, _)) => try_s!(nonce_res),
+        Either::Right(_) => return ERR!("Get address nonce timed out"),
     };
 
-    let signed = tx.sign(coin.key_pair.secret(), None);
-    let bytes = rlp::encode(&signed);
+    let mut transactions = Vec::new();
+    let mut tx_hashes = Vec::with_capacity(addresses.len());
+    if req.use_multisend {
+        let multisend_addr = match coin.multisend_contract_address {
+            Some(addr) => addr,
+            None => {
+                return ERR!(
+                    "multisend_contract_address is not set in the {} enable request",
+                    coin.ticker
+                )
+            },
+        };
+        let recipients = Token::Array(addresses.iter().map(|addr| Token::Address(*addr)).collect());
+        let values = Token::Array(amounts.iter().map(|amount| Token::Uint(*amount)).collect());
+        let (eth_value, data, gas_and_fee) = match coin.coin_type {
+            EthCoinType::Eth => {
+                let function = try_s!(MULTISEND_CONTRACT.function("disperseEther"));
+                let data = try_s!(function.encode_input(&[recipients, values]));
+                (total_amount, data, requested_gas)
+            },
+            EthCoinType::Erc20(token_addr) => {
+                let function = try_s!(MULTISEND_CONTRACT.function("disperseToken"));
+                let data = try_s!(function.encode_input(&[Token::Address(token_addr), recipients, values]));
+                let allowance = try_s!(coin.allowance(multisend_addr).compat().await);
+                if allowance >= total_amount {
+                    (0.into(), data, requested_gas)
+                } else {
+                    // the multisend contract must be approved to spend the tokens first
+                    let approve = try_s!(ERC20_CONTRACT.function("approve"));
+                    let approve_data =
+                        try_s!(approve.encode_input(&[Token::Address(multisend_addr), Token::Uint(total_amount)]));
+                    let gas_fee = match requested_gas {
+                        Some((_, ref gas_fee)) => gas_fee.clone(),
+                        None => try_s!(coin.get_gas_fee().compat().await),
+                    };
+                    let approve_gas = U256::from(APPROVE_GAS_LIMIT);
+                    let signed = try_s!(coin.sign_transaction(
+                        nonce,
+                        0.into(),
+                        Action::Call(token_addr),
+                        approve_data,
+                        approve_gas,
+                        &gas_fee
+                    ));
+                    transactions.push(try_s!(withdraw_tx_details(
+                        &coin,
+                        &signed,
+                        vec![multisend_addr],
+                        0.into(),
+                        approve_gas,
+                        &gas_fee
+                    )));
+                    nonce += 1.into();
+                    let gas = match requested_gas {
+                        Some((gas, _)) => gas,
+                        None => {
+                            U256::from(MULTISEND_TOKEN_BASE_GAS)
+                                + U256::from(MULTISEND_TOKEN_GAS_PER_RECIPIENT) * U256::from(addresses.len())
+                        },
+                    };
+                    (0.into(), data, Some((gas, gas_fee)))
+                }
+            },
+        };
+        let (gas, gas_fee) = match gas_and_fee {
+            Some(gas_and_fee) => gas_and_fee,
+            None => try_s!(estimate_gas_and_fee(&coin, eth_value, data.clone(), multisend_addr).await),
+        };
+        let signed = try_s!(coin.sign_transaction(nonce, eth_value, Action::Call(multisend_addr), data, gas, &gas_fee));
+        let details = try_s!(withdraw_tx_details(
+            &coin,
+            &signed,
+            addresses.clone(),
+            total_amount,
+            gas,
+            &gas_fee
+        ));
+        tx_hashes.resize(addresses.len(), details.tx_hash.clone());
+        transactions.push(details);
+    } else {
+        for (to_addr, wei_amount) in addresses.iter().zip(amounts.iter()) {
+            let (eth_value, data, call_addr) = try_s!(transfer_call(&coin, *to_addr, *wei_amount));
+            let (gas, gas_fee) = match requested_gas {
+                Some((gas, ref gas_fee)) => (gas, gas_fee.clone()),
+                None => try_s!(estimate_gas_and_fee(&coin, eth_value, data.clone(), call_addr).await),
+            };
+            let signed = try_s!(coin.sign_transaction(nonce, eth_value, Action::Call(call_addr), data, gas, &gas_fee));
+            let details = try_s!(withdraw_tx_details(
+                &coin,
+                &signed,
+                vec![*to_addr],
+                *wei_amount,
+                gas,
+                &gas_fee
+            ));
+            tx_hashes.push(details.tx_hash.clone());
+            transactions.push(details);
+            nonce += 1.into();
+        }
+    }
+
+    if coin.coin_type == EthCoinType::Eth {
+        let total_spent = transactions
+            .iter()
+            .fold(BigDecimal::from(0), |total, tx| total + &tx.spent_by_me);
+        if total_spent > try_s!(u256_to_big_decimal(my_balance, coin.decimals)) {
+            return ERR!(
+                "The total amount {} to withdraw with the fee is larger than balance",
+                total_spent
+            );
+        }
+    }
+    let recipients = req
+        .recipients
+        .into_iter()
+        .zip(tx_hashes)
+        .map(|(recipient, tx_hash)| WithdrawBatchRecipient {
+            address: recipient.address,
+            amount: recipient.amount,
+            tx_hash,
+        })
+        .collect();
+    Ok(WithdrawBatchResponse {
+        transactions,
+        recipients,
+    })
+}
+
+/// Builds the withdraw details of the `signed` transaction sending `wei_amount` coins in total to the `to` addresses.
+fn withdraw_tx_details(
+    coin: &EthCoin,
+    signed: &SignedEthTx,
+    to: Vec<Address>,
+    wei_amount: U256,
+    gas: U256,
+    gas_fee: &EthGasFee,
+) -> Result<TransactionDetails, String> {
     let amount_decimal = try_s!(u256_to_big_decimal(wei_amount, coin.decimals));
     let mut spent_by_me = amount_decimal.clone();
-    let received_by_me = if to_addr == coin.my_address {
+    let received_by_me = if to.contains(&coin.my_address) {
         amount_decimal.clone()
     } else {
         0.into()
     };
-    let fee_details = try_s!(EthTxFeeDetails::new(gas, gas_price, "ETH"));
+    let fee_details = try_s!(EthTxFeeDetails::from_gas_fee(gas, gas_fee, "ETH"));
     if coin.coin_type == EthCoinType::Eth {
         spent_by_me += &fee_details.total_fee;
     }
     Ok(TransactionDetails {
-        to: vec![checksum_address(&format!("{:#02x}", to_addr))],
+        to: to
+            .iter()
+            .map(|addr| checksum_address(&format!("{:#02x}", addr)))
+            .collect(),
         from: vec![try_s!(coin.my_address())],
         total_amount: amount_decimal,
         my_balance_change: &received_by_me - &spent_by_me,
         spent_by_me,
         received_by_me,
-        tx_hex: bytes.into(),
+        tx_hex: signed.tx_hex().into(),
         tx_hash: signed.tx_hash(),
         block_height: 0,
         fee_details: Some(fee_details.into()),
         coin: coin.ticker.clone(),
         internal_id: vec![].into(),
         timestamp: now_ms() / 1000,
+        psbt: None,
     })
 }
 
@@ -526,6 +840,9 @@ impl SwapOps for EthCoin {
         secret_hash: &[u8],
         amount: BigDecimal,
         swap_contract_address: &Option<BytesJson>,
+        _htlc_script_type: HtlcScriptType,
+        _replaceable: bool,
+        _swap_unique_data: &[u8],
     ) -> TransactionFut {
         let taker_addr = try_fus!(addr_from_raw_pubkey(taker_pub));
         let swap_contract_address = try_fus!(swap_contract_address.try_to_address());
@@ -550,6 +867,9 @@ impl SwapOps for EthCoin {
         secret_hash: &[u8],
         amount: BigDecimal,
         swap_contract_address: &Option<BytesJson>,
+        _htlc_script_type: HtlcScriptType,
+        _replaceable: bool,
+        _swap_unique_data: &[u8],
     ) -> TransactionFut {
         let maker_addr = try_fus!(addr_from_raw_pubkey(maker_pub));
         let swap_contract_address = try_fus!(swap_contract_address.try_to_address());
@@ -574,9 +894,9 @@ impl SwapOps for EthCoin {
         _taker_pub: &[u8],
         secret: &[u8],
         swap_contract_address: &Option<BytesJson>,
+        _swap_unique_data: &[u8],
     ) -> TransactionFut {
-        let tx: UnverifiedTransaction = try_fus!(rlp::decode(taker_payment_tx));
-        let signed = try_fus!(SignedEthTx::new(tx));
+        let signed = try_fus!(signed_eth_tx_from_bytes(taker_payment_tx));
         let swap_contract_address = try_fus!(swap_contract_address.try_to_address());
 
         Box::new(
@@ -592,9 +912,9 @@ impl SwapOps for EthCoin {
         _maker_pub: &[u8],
         secret: &[u8],
         swap_contract_address: &Option<BytesJson>,
+        _swap_unique_data: &[u8],
     ) -> TransactionFut {
-        let tx: UnverifiedTransaction = try_fus!(rlp::decode(maker_payment_tx));
-        let signed = try_fus!(SignedEthTx::new(tx));
+        let signed = try_fus!(signed_eth_tx_from_bytes(maker_payment_tx));
         let swap_contract_address = try_fus!(swap_contract_address.try_to_address());
         Box::new(
             self.spend_hash_time_locked_payment(signed, swap_contract_address, secret)
@@ -609,9 +929,9 @@ impl SwapOps for EthCoin {
         _maker_pub: &[u8],
         _secret_hash: &[u8],
         swap_contract_address: &Option<BytesJson>,
+        _swap_unique_data: &[u8],
     ) -> TransactionFut {
-        let tx: UnverifiedTransaction = try_fus!(rlp::decode(taker_payment_tx));
-        let signed = try_fus!(SignedEthTx::new(tx));
+        let signed = try_fus!(signed_eth_tx_from_bytes(taker_payment_tx));
         let swap_contract_address = try_fus!(swap_contract_address.try_to_address());
 
         Box::new(
@@ -627,9 +947,9 @@ impl SwapOps for EthCoin {
         _taker_pub: &[u8],
         _secret_hash: &[u8],
         swap_contract_address: &Option<BytesJson>,
+        _swap_unique_data: &[u8],
     ) -> TransactionFut {
-        let tx: UnverifiedTransaction = try_fus!(rlp::decode(maker_payment_tx));
-        let signed = try_fus!(SignedEthTx::new(tx));
+        let signed = try_fus!(signed_eth_tx_from_bytes(maker_payment_tx));
         let swap_contract_address = try_fus!(swap_contract_address.try_to_address());
 
         Box::new(
@@ -658,7 +978,7 @@ impl SwapOps for EthCoin {
                 selfi
                     .web3
                     .eth()
-                    .transaction(TransactionId::Hash(tx.hash))
+                    .transaction(TransactionId::Hash(tx.hash()))
                     .compat()
                     .await
             );
@@ -729,6 +1049,8 @@ impl SwapOps for EthCoin {
         secret_hash: &[u8],
         amount: BigDecimal,
         swap_contract_address: &Option<BytesJson>,
+        _htlc_script_type: HtlcScriptType,
+        _swap_unique_data: &[u8],
     ) -> Box<dyn Future<Item = (), Error = String> + Send> {
         let swap_contract_address = try_fus!(swap_contract_address.try_to_address());
         self.validate_payment(
@@ -749,6 +1071,8 @@ impl SwapOps for EthCoin {
         secret_hash: &[u8],
         amount: BigDecimal,
         swap_contract_address: &Option<BytesJson>,
+        _htlc_script_type: HtlcScriptType,
+        _swap_unique_data: &[u8],
     ) -> Box<dyn Future<Item = (), Error = String> + Send> {
         let swap_contract_address = try_fus!(swap_contract_address.try_to_address());
         self.validate_payment(
@@ -768,6 +1092,8 @@ impl SwapOps for EthCoin {
         secret_hash: &[u8],
         from_block: u64,
         swap_contract_address: &Option<BytesJson>,
+        _htlc_script_type: HtlcScriptType,
+        _swap_unique_data: &[u8],
     ) -> Box<dyn Future<Item = Option<TransactionEnum>, Error = String> + Send> {
         let id = self.etomic_swap_id(time_lock, secret_hash);
         let swap_contract_address = try_fus!(swap_contract_address.try_to_address());
@@ -793,18 +1119,8 @@ impl SwapOps for EthCoin {
 
             match found {
                 Some(event) => {
-                    let transaction = try_s!(
-                        selfi
-                            .web3
-                            .eth()
-                            .transaction(TransactionId::Hash(event.transaction_hash.unwrap()))
-                            .compat()
-                            .await
-                    );
-                    match transaction {
-                        Some(t) => Ok(Some(try_s!(signed_tx_from_web3_tx(t)).into())),
-                        None => Ok(None),
-                    }
+                    let transaction = try_s!(selfi.signed_transaction(event.transaction_hash.unwrap()).compat().await);
+                    Ok(transaction.map(TransactionEnum::from))
                 },
                 None => Ok(None),
             }
@@ -820,6 +1136,7 @@ impl SwapOps for EthCoin {
         tx: &[u8],
         search_from_block: u64,
         swap_contract_address: &Option<BytesJson>,
+        _swap_unique_data: &[u8],
     ) -> Result<Option<FoundSwapTxSpend>, String> {
         let swap_contract_address = try_s!(swap_contract_address.try_to_address());
         self.search_for_swap_tx_spend(tx, swap_contract_address, search_from_block)
@@ -833,15 +1150,16 @@ impl SwapOps for EthCoin {
         tx: &[u8],
         search_from_block: u64,
         swap_contract_address: &Option<BytesJson>,
+        _swap_unique_data: &[u8],
     ) -> Result<Option<FoundSwapTxSpend>, String> {
         let swap_contract_address = try_s!(swap_contract_address.try_to_address());
         self.search_for_swap_tx_spend(tx, swap_contract_address, search_from_block)
     }
 
     fn extract_secret(&self, _secret_hash: &[u8], spend_tx: &[u8]) -> Result<Vec<u8>, String> {
-        let unverified: UnverifiedTransaction = try_s!(rlp::decode(spend_tx));
+        let spend_tx = try_s!(signed_eth_tx_from_bytes(spend_tx));
         let function = try_s!(SWAP_CONTRACT.function("receiverSpend"));
-        let tokens = try_s!(function.decode_input(&unverified.data));
+        let tokens = try_s!(function.decode_input(spend_tx.data()));
         if tokens.len() < 3 {
             return ERR!("Invalid arguments in 'receiverSpend' call: {:?}", tokens);
         }
@@ -853,6 +1171,34 @@ impl SwapOps for EthCoin {
             ),
         }
     }
+
+    /// The swap contract checks `msg.sender` against the parties addresses and the account pays the gas,
+    /// so the account key is used and the swaps are linkable by the account address.
+    fn derive_htlc_pubkey(&self, _swap_unique_data: &[u8]) -> Result<Vec<u8>, String> {
+        let mut uncompressed = vec![4];
+        uncompressed.extend_from_slice(&*self.key_pair.public());
+        let public = try_s!(PublicKey::parse_slice(&uncompressed, None).map_err(|e| ERRL!("{:?}", e)));
+        Ok(public.serialize_compressed().to_vec())
+    }
+
+    fn bump_payment_fee(&self, payment_tx: &[u8]) -> TransactionFut {
+        let payment = try_fus!(signed_eth_tx_from_bytes(payment_tx));
+        if payment.sender() != self.my_address {
+            return Box::new(futures01::future::err(ERRL!(
+                "Payment {:?} is not sent by my address {:?}",
+                payment.hash(),
+                self.my_address
+            )));
+        }
+        Box::new(self.replace_transaction(payment).map(TransactionEnum::from))
+    }
+
+    fn send_cpfp_child(&self, _parent_tx: &[u8]) -> TransactionFut {
+        Box::new(futures01::future::err(ERRL!(
+            "{} doesn't support child-pays-for-parent transactions",
+            self.ticker()
+        )))
+    }
 }
 
 impl MarketCoinOps for EthCoin {
@@ -902,8 +1248,7 @@ impl MarketCoinOps for EthCoin {
         status.status(&[&self.ticker], "Waiting for confirmations…");
         status.deadline(wait_until * 1000);
 
-        let unsigned: UnverifiedTransaction = try_fus!(rlp::decode(tx));
-        let tx = try_fus!(SignedEthTx::new(unsigned));
+        let tx = try_fus!(signed_eth_tx_from_bytes(tx));
 
         let required_confirms = U256::from(confirmations);
         let selfi = self.clone();
@@ -965,8 +1310,7 @@ impl MarketCoinOps for EthCoin {
         from_block: u64,
         swap_contract_address: &Option<BytesJson>,
     ) -> TransactionFut {
-        let unverified: UnverifiedTransaction = try_fus!(rlp::decode(tx_bytes));
-        let tx = try_fus!(SignedEthTx::new(unverified));
+        let tx = try_fus!(signed_eth_tx_from_bytes(tx_bytes));
         let swap_contract_address = try_fus!(swap_contract_address.try_to_address());
 
         let func_name = match self.coin_type {
@@ -975,7 +1319,7 @@ impl MarketCoinOps for EthCoin {
         };
 
         let payment_func = try_fus!(SWAP_CONTRACT.function(func_name));
-        let decoded = try_fus!(payment_func.decode_input(&tx.data));
+        let decoded = try_fus!(payment_func.decode_input(tx.data()));
         let id = match &decoded[0] {
             Token::FixedBytes(bytes) => bytes.clone(),
             _ => panic!(),
@@ -997,13 +1341,7 @@ impl MarketCoinOps for EthCoin {
 
                 if let Some(event) = found {
                     if let Some(tx_hash) = event.transaction_hash {
-                        let transaction = match selfi
-                            .web3
-                            .eth()
-                            .transaction(TransactionId::Hash(tx_hash))
-                            .compat()
-                            .await
-                        {
+                        let transaction = match selfi.signed_transaction(tx_hash).compat().await {
                             Ok(Some(t)) => t,
                             Ok(None) => {
                                 log!("Tx " (tx_hash) " not found yet");
@@ -1017,7 +1355,7 @@ impl MarketCoinOps for EthCoin {
                             },
                         };
 
-                        return Ok(TransactionEnum::from(try_s!(signed_tx_from_web3_tx(transaction))));
+                        return Ok(TransactionEnum::from(transaction));
                     }
                 }
 
@@ -1057,9 +1395,13 @@ impl MarketCoinOps for EthCoin {
 }
 
 pub fn signed_eth_tx_from_bytes(bytes: &[u8]) -> Result<SignedEthTx, String> {
+    // EIP-2718 typed transactions start with the type byte, while legacy transactions are RLP lists starting with 0xc0..0xff
+    if bytes.first() == Some(&EIP1559_TX_TYPE) {
+        return Ok(SignedEthTx::Eip1559(try_s!(SignedEip1559Tx::decode(bytes))));
+    }
     let tx: UnverifiedTransaction = try_s!(rlp::decode(bytes));
-    let signed = try_s!(SignedEthTx::new(tx));
-    Ok(signed)
+    let signed = try_s!(SignedLegacyEthTx::new(tx));
+    Ok(SignedEthTx::Legacy(signed))
 }
 
 // We can use a shared nonce lock for all ETH coins.
@@ -1079,6 +1421,7 @@ async fn sign_and_send_transaction_impl(
     action: Action,
     data: Vec<u8>,
     gas: U256,
+    replaced: Option<SignedEthTx>,
 ) -> Result<SignedEthTx, String> {
     let mut status = ctx.log.status_handle();
     macro_rules! tags {
@@ -1097,24 +1440,26 @@ async fn sign_and_send_transaction_impl(
             Ok(0.5)
         })
         .await;
-    status.status(tags!(), "get_addr_nonce…");
-    let nonce = try_s!(
-        get_addr_nonce(coin.my_address, coin.web3_instances.clone())
-            .compat()
-            .await
-    );
-    status.status(tags!(), "get_gas_price…");
-    let gas_price = try_s!(coin.get_gas_price().compat().await);
-    let tx = UnSignedEthTx {
-        nonce,
-        value,
-        action,
-        data,
-        gas,
-        gas_price,
+    status.status(tags!(), "get_gas_fee…");
+    let current_gas_fee = try_s!(coin.get_gas_fee().compat().await);
+    let (nonce, gas_fee) = match replaced {
+        // the replacement must reuse the nonce of the stuck transaction and pay more than it
+        Some(replaced) => (
+            replaced.nonce(),
+            replacement_gas_fee(replaced.gas_fee(), current_gas_fee),
+        ),
+        None => {
+            status.status(tags!(), "get_addr_nonce…");
+            let nonce = try_s!(
+                get_addr_nonce(coin.my_address, coin.web3_instances.clone())
+                    .compat()
+                    .await
+            );
+            (nonce, current_gas_fee)
+        },
     };
-    let signed = tx.sign(coin.key_pair.secret(), None);
-    let bytes = web3::types::Bytes(rlp::encode(&signed).to_vec());
+    let signed = try_s!(coin.sign_transaction(nonce, value, action, data, gas, &gas_fee));
+    let bytes = web3::types::Bytes(signed.tx_hex());
     status.status(tags!(), "send_raw_transaction…");
     try_s!(
         coin.web3
@@ -1160,6 +1505,22 @@ impl EthCoin {
             action,
             data,
             gas,
+            None,
+        ));
+        Box::new(fut.compat())
+    }
+
+    /// Resends the `replaced` transaction with the same nonce paying the higher gas fee.
+    fn replace_transaction(&self, replaced: SignedEthTx) -> EthTxFut {
+        let ctx = try_fus!(MmArc::from_weak(&self.ctx).ok_or("!ctx"));
+        let fut = Box::pin(sign_and_send_transaction_impl(
+            ctx,
+            self.clone(),
+            replaced.value(),
+            replaced.action(),
+            replaced.data().to_vec(),
+            replaced.gas(),
+            Some(replaced),
         ));
         Box::new(fut.compat())
     }
@@ -1249,7 +1610,7 @@ impl EthCoin {
         match self.coin_type {
             EthCoinType::Eth => {
                 let payment_func = try_fus!(SWAP_CONTRACT.function("ethPayment"));
-                let decoded = try_fus!(payment_func.decode_input(&payment.data));
+                let decoded = try_fus!(payment_func.decode_input(payment.data()));
 
                 let state_f = self.payment_status(swap_contract_address, decoded[0].clone());
                 Box::new(state_f.and_then(move |state| -> EthTxFut {
@@ -1261,7 +1622,7 @@ impl EthCoin {
                         )));
                     }
 
-                    let value = payment.value;
+                    let value = payment.value();
                     let data = try_fus!(spend_func.encode_input(&[
                         decoded[0].clone(),
                         Token::Uint(value),
@@ -1280,7 +1641,7 @@ impl EthCoin {
             },
             EthCoinType::Erc20(token_addr) => {
                 let payment_func = try_fus!(SWAP_CONTRACT.function("erc20Payment"));
-                let decoded = try_fus!(payment_func.decode_input(&payment.data));
+                let decoded = try_fus!(payment_func.decode_input(payment.data()));
                 let state_f = self.payment_status(swap_contract_address, decoded[0].clone());
 
                 Box::new(state_f.and_then(move |state| -> EthTxFut {
@@ -1317,7 +1678,7 @@ impl EthCoin {
         match self.coin_type {
             EthCoinType::Eth => {
                 let payment_func = try_fus!(SWAP_CONTRACT.function("ethPayment"));
-                let decoded = try_fus!(payment_func.decode_input(&payment.data));
+                let decoded = try_fus!(payment_func.decode_input(payment.data()));
 
                 let state_f = self.payment_status(swap_contract_address, decoded[0].clone());
                 Box::new(state_f.and_then(move |state| -> EthTxFut {
@@ -1329,7 +1690,7 @@ impl EthCoin {
                         )));
                     }
 
-                    let value = payment.value;
+                    let value = payment.value();
                     let data = try_fus!(refund_func.encode_input(&[
                         decoded[0].clone(),
                         Token::Uint(value),
@@ -1348,7 +1709,7 @@ impl EthCoin {
             },
             EthCoinType::Erc20(token_addr) => {
                 let payment_func = try_fus!(SWAP_CONTRACT.function("erc20Payment"));
-                let decoded = try_fus!(payment_func.decode_input(&payment.data));
+                let decoded = try_fus!(payment_func.decode_input(payment.data()));
                 let state_f = self.payment_status(swap_contract_address, decoded[0].clone());
                 Box::new(state_f.and_then(move |state| -> EthTxFut {
                     if state != PAYMENT_STATE_SENT.into() {
@@ -1494,8 +1855,7 @@ impl EthCoin {
         amount: BigDecimal,
         expected_swap_contract_address: Address,
     ) -> Box<dyn Future<Item = (), Error = String> + Send> {
-        let unsigned: UnverifiedTransaction = try_fus!(rlp::decode(payment_tx));
-        let tx = try_fus!(SignedEthTx::new(unsigned));
+        let tx = try_fus!(signed_eth_tx_from_bytes(payment_tx));
         let sender = try_fus!(addr_from_raw_pubkey(sender_pub));
         let expected_value = try_fus!(wei_from_big_decimal(&amount, self.decimals));
         let selfi = self.clone();
@@ -1516,7 +1876,7 @@ impl EthCoin {
                 selfi
                     .web3
                     .eth()
-                    .transaction(TransactionId::Hash(tx.hash))
+                    .transaction(TransactionId::Hash(tx.hash()))
                     .compat()
                     .await
             );
@@ -1845,7 +2205,7 @@ impl EthCoin {
             all_events.sort_by(|a, b| b.block_number.unwrap().cmp(&a.block_number.unwrap()));
 
             for event in all_events {
-                let mut existing_history = self.load_history_from_file(ctx);
+                let existing_history = self.load_history_from_db(ctx);
                 let internal_id = BytesJson::from(sha256(&json::to_vec(&event).unwrap()).to_vec());
                 if existing_history.iter().any(|item| item.internal_id == internal_id) {
                     // the transaction already imported
@@ -1972,23 +2332,14 @@ impl EthCoin {
                     coin: self.ticker.clone(),
                     fee_details: fee_details.map(|d| d.into()),
                     block_height: block_number.into(),
-                    tx_hash: BytesJson(raw.hash.to_vec()),
-                    tx_hex: BytesJson(rlp::encode(&raw)),
+                    tx_hash: raw.tx_hash(),
+                    tx_hex: BytesJson(raw.tx_hex()),
                     internal_id: BytesJson(internal_id.to_vec()),
                     timestamp: block.timestamp.into(),
+                    psbt: None,
                 };
 
-                existing_history.push(details);
-                existing_history.sort_unstable_by(|a, b| {
-                    if a.block_height == 0 {
-                        Ordering::Less
-                    } else if b.block_height == 0 {
-                        Ordering::Greater
-                    } else {
-                        b.block_height.cmp(&a.block_height)
-                    }
-                });
-                self.save_history_to_file(&unwrap!(json::to_vec(&existing_history)), &ctx);
+                self.add_history_to_db(ctx, &[&details]);
             }
             if saved_events.earliest_block == 0.into() {
                 if success_iteration == 0 {
@@ -2056,7 +2407,7 @@ impl EthCoin {
             *unwrap!(self.history_sync_state.lock()) = HistorySyncState::InProgress(json!({
                 "blocks_left": u64::from(saved_traces.earliest_block),
             }));
-            let mut existing_history = self.load_history_from_file(ctx);
+            let mut existing_history = self.load_history_from_db(ctx);
 
             // AP: AFAIK ETH RPC doesn't support conditional filters like `get this OR this` so we have
             // to run several queries to get trace events including our address as sender `or` receiver
@@ -2311,23 +2662,15 @@ impl EthCoin {
                     coin: self.ticker.clone(),
                     fee_details: fee_details.map(|d| d.into()),
                     block_height: trace.block_number,
-                    tx_hash: BytesJson(raw.hash.to_vec()),
-                    tx_hex: BytesJson(rlp::encode(&raw)),
+                    tx_hash: raw.tx_hash(),
+                    tx_hex: BytesJson(raw.tx_hex()),
                     internal_id,
                     timestamp: block.timestamp.into(),
+                    psbt: None,
                 };
 
+                self.add_history_to_db(ctx, &[&details]);
                 existing_history.push(details);
-                existing_history.sort_unstable_by(|a, b| {
-                    if a.block_height == 0 {
-                        Ordering::Less
-                    } else if b.block_height == 0 {
-                        Ordering::Greater
-                    } else {
-                        b.block_height.cmp(&a.block_height)
-                    }
-                });
-                self.save_history_to_file(&unwrap!(json::to_vec(&existing_history)), &ctx);
             }
             if saved_traces.earliest_block == 0.into() {
                 if success_iteration == 0 {
@@ -2352,8 +2695,14 @@ impl EthCoin {
 pub struct EthTxFeeDetails {
     coin: String,
     gas: u64,
-    /// WEI units per 1 gas
+    /// WEI units per 1 gas, equals to `max_fee_per_gas` for EIP-1559 transactions
     gas_price: BigDecimal,
+    /// WEI units per 1 gas, is set for EIP-1559 transactions only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_fee_per_gas: Option<BigDecimal>,
+    /// WEI units per 1 gas, is set for EIP-1559 transactions only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_priority_fee_per_gas: Option<BigDecimal>,
     total_fee: BigDecimal,
 }
 
@@ -2368,9 +2717,38 @@ impl EthTxFeeDetails {
             coin: coin.to_owned(),
             gas: gas.into(),
             gas_price,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
             total_fee,
         })
     }
+
+    /// The `total_fee` of EIP-1559 transactions is the upper bound calculated from `max_fee_per_gas`.
+    fn from_gas_fee(gas: U256, gas_fee: &EthGasFee, coin: &str) -> Result<EthTxFeeDetails, String> {
+        let mut details = try_s!(EthTxFeeDetails::new(gas, gas_fee.max_gas_price(), coin));
+        if let EthGasFee::Eip1559(fee) = gas_fee {
+            details.max_fee_per_gas = Some(try_s!(u256_to_big_decimal(fee.max_fee_per_gas, 18)));
+            details.max_priority_fee_per_gas = Some(try_s!(u256_to_big_decimal(fee.max_priority_fee_per_gas, 18)));
+        }
+        Ok(details)
+    }
+}
+
+/// The fee parameters of the transaction to be sent.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EthGasFee {
+    Legacy { gas_price: U256 },
+    Eip1559(Eip1559Fee),
+}
+
+impl EthGasFee {
+    /// The max price of 1 gas the transaction may pay.
+    fn max_gas_price(&self) -> U256 {
+        match self {
+            EthGasFee::Legacy { gas_price } => *gas_price,
+            EthGasFee::Eip1559(fee) => fee.max_fee_per_gas,
+        }
+    }
 }
 
 impl MmCoin for EthCoin {
@@ -2383,6 +2761,14 @@ impl MmCoin for EthCoin {
         Box::new(Box::pin(withdraw_impl(ctx, self.clone(), req)).compat())
     }
 
+    fn withdraw_batch(
+        &self,
+        req: WithdrawBatchRequest,
+    ) -> Box<dyn Future<Item = WithdrawBatchResponse, Error = String> + Send> {
+        let ctx = try_fus!(MmArc::from_weak(&self.ctx).ok_or("!ctx"));
+        Box::new(Box::pin(withdraw_batch_impl(ctx, self.clone(), req)).compat())
+    }
+
     fn decimals(&self) -> u8 { self.decimals }
 
     fn convert_to_address(&self, from: &str, to_address_format: Json) -> Result<String, String> {
@@ -2415,11 +2801,13 @@ impl MmCoin for EthCoin {
     fn history_sync_status(&self) -> HistorySyncState { unwrap!(self.history_sync_state.lock()).clone() }
 
     fn get_trade_fee(&self) -> Box<dyn Future<Item = TradeFee, Error = String> + Send> {
-        Box::new(self.get_gas_price().and_then(|gas_price| {
-            let fee = gas_price * U256::from(150_000);
+        Box::new(self.get_gas_fee().and_then(|gas_fee| {
+            let gas_limit = U256::from(150_000);
+            let fee = gas_limit * gas_fee.max_gas_price();
             Ok(TradeFee {
                 coin: "ETH".into(),
                 amount: try_s!(u256_to_big_decimal(fee, 18)).into(),
+                fee_details: Some(try_s!(EthTxFeeDetails::from_gas_fee(gas_limit, &gas_fee, "ETH")).into()),
             })
         }))
     }
@@ -2431,8 +2819,8 @@ impl MmCoin for EthCoin {
     ) -> Box<dyn Future<Item = TradeFee, Error = TradePreimageError> + Send> {
         let coin = self.clone();
         let fut = async move {
-            let gas_price = try_map!(coin.get_gas_price().compat().await, TradePreimageError::Other);
-            let gas_price = increase_gas_price_by_stage(gas_price, &stage);
+            let gas_fee = try_map!(coin.get_gas_fee().compat().await, TradePreimageError::Other);
+            let gas_fee = increase_gas_fee_by_stage(gas_fee, &stage);
             let gas_limit = match coin.coin_type {
                 EthCoinType::Eth => {
                     // this gas_limit includes gas for `ethPayment` and `senderRefund` contract calls
@@ -2458,11 +2846,16 @@ impl MmCoin for EthCoin {
                 },
             };
 
-            let total_fee = gas_limit * gas_price;
+            let total_fee = gas_limit * gas_fee.max_gas_price();
             let amount = try_map!(u256_to_big_decimal(total_fee, 18), TradePreimageError::Other);
+            let fee_details = try_map!(
+                EthTxFeeDetails::from_gas_fee(gas_limit, &gas_fee, "ETH"),
+                TradePreimageError::Other
+            );
             Ok(TradeFee {
                 coin: "ETH".to_owned(),
                 amount: amount.into(),
+                fee_details: Some(fee_details.into()),
             })
         };
         Box::new(fut.boxed().compat())
@@ -2474,18 +2867,31 @@ impl MmCoin for EthCoin {
     ) -> Box<dyn Future<Item = TradeFee, Error = TradePreimageError> + Send> {
         let coin = self.clone();
         let fut = async move {
-            let gas_price = try_map!(coin.get_gas_price().compat().await, TradePreimageError::Other);
-            let gas_price = increase_gas_price_by_stage(gas_price, &stage);
-            let total_fee = gas_price * U256::from(150_000);
+            let gas_fee = try_map!(coin.get_gas_fee().compat().await, TradePreimageError::Other);
+            let gas_fee = increase_gas_fee_by_stage(gas_fee, &stage);
+            let gas_limit = U256::from(150_000);
+            let total_fee = gas_limit * gas_fee.max_gas_price();
             let amount = try_map!(u256_to_big_decimal(total_fee, 18), TradePreimageError::Other);
+            let fee_details = try_map!(
+                EthTxFeeDetails::from_gas_fee(gas_limit, &gas_fee, "ETH"),
+                TradePreimageError::Other
+            );
             Ok(TradeFee {
                 coin: "ETH".to_owned(),
                 amount: amount.into(),
+                fee_details: Some(fee_details.into()),
             })
         };
         Box::new(fut.boxed().compat())
     }
 
+    fn get_cpfp_child_fee(
+        &self,
+        _stage: FeeApproxStage,
+    ) -> Box<dyn Future<Item = Option<TradeFee>, Error = TradePreimageError> + Send> {
+        Box::new(futures01::future::ok(None))
+    }
+
     fn get_fee_to_send_taker_fee(
         &self,
         dex_fee_amount: BigDecimal,
@@ -2513,8 +2919,8 @@ impl MmCoin for EthCoin {
                 },
             };
 
-            let gas_price = try_map!(coin.get_gas_price().compat().await, TradePreimageError::Other);
-            let gas_price = increase_gas_price_by_stage(gas_price, &stage);
+            let gas_fee = try_map!(coin.get_gas_fee().compat().await, TradePreimageError::Other);
+            let gas_fee = increase_gas_fee_by_stage(gas_fee, &stage);
             let estimate_gas_req = CallRequest {
                 value: Some(eth_value),
                 data: Some(data.clone().into()),
@@ -2523,7 +2929,7 @@ impl MmCoin for EthCoin {
                 gas: None,
                 // gas price must be supplied because some smart contracts base their
                 // logic on gas price, e.g. TUSD: https://github.com/KomodoPlatform/atomicDEX-API/issues/643
-                gas_price: Some(gas_price),
+                gas_price: Some(gas_fee.max_gas_price()),
             };
 
             // Please note if the wallet's balance is insufficient to withdraw, then `estimate_gas` may fail with the `Exception` error.
@@ -2533,11 +2939,16 @@ impl MmCoin for EthCoin {
                 TradePreimageError::Other
             );
 
-            let total_fee = gas_limit * gas_price;
+            let total_fee = gas_limit * gas_fee.max_gas_price();
             let amount = try_map!(u256_to_big_decimal(total_fee, 18), TradePreimageError::Other);
+            let fee_details = try_map!(
+                EthTxFeeDetails::from_gas_fee(gas_limit, &gas_fee, "ETH"),
+                TradePreimageError::Other
+            );
             Ok(TradeFee {
                 coin: "ETH".to_owned(),
                 amount: amount.into(),
+                fee_details: Some(fee_details.into()),
             })
         };
         Box::new(fut.boxed().compat())
@@ -2564,6 +2975,8 @@ impl MmCoin for EthCoin {
     fn swap_contract_address(&self) -> Option<BytesJson> {
         Some(BytesJson::from(self.swap_contract_address.0.as_ref()))
     }
+
+    fn is_p2wsh_htlc_supported(&self) -> bool { false }
 }
 
 pub trait TryToAddress {
@@ -2630,10 +3043,96 @@ pub fn wei_from_big_decimal(amount: &BigDecimal, decimals: u8) -> Result<U256, S
     Ok(try_s!(U256::from_dec_str(&amount).map_err(|e| ERRL!("{:?}", e))))
 }
 
+/// The signed transaction of any type supported by ETH and ERC20 coins.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SignedEthTx {
+    Legacy(SignedLegacyEthTx),
+    /// https://eips.ethereum.org/EIPS/eip-1559
+    Eip1559(SignedEip1559Tx),
+}
+
+impl SignedEthTx {
+    pub fn data(&self) -> &[u8] {
+        match self {
+            SignedEthTx::Legacy(tx) => &tx.data,
+            SignedEthTx::Eip1559(tx) => &tx.unsigned.data,
+        }
+    }
+
+    pub fn value(&self) -> U256 {
+        match self {
+            SignedEthTx::Legacy(tx) => tx.value,
+            SignedEthTx::Eip1559(tx) => tx.unsigned.value,
+        }
+    }
+
+    pub fn sender(&self) -> Address {
+        match self {
+            SignedEthTx::Legacy(tx) => tx.sender(),
+            SignedEthTx::Eip1559(tx) => tx.sender(),
+        }
+    }
+
+    pub fn hash(&self) -> H256 {
+        match self {
+            SignedEthTx::Legacy(tx) => tx.hash(),
+            SignedEthTx::Eip1559(tx) => tx.hash,
+        }
+    }
+
+    pub fn nonce(&self) -> U256 {
+        match self {
+            SignedEthTx::Legacy(tx) => tx.nonce,
+            SignedEthTx::Eip1559(tx) => tx.unsigned.nonce,
+        }
+    }
+
+    pub fn gas(&self) -> U256 {
+        match self {
+            SignedEthTx::Legacy(tx) => tx.gas,
+            SignedEthTx::Eip1559(tx) => tx.unsigned.gas,
+        }
+    }
+
+    pub fn action(&self) -> Action {
+        match self {
+            SignedEthTx::Legacy(tx) => tx.action.clone(),
+            SignedEthTx::Eip1559(tx) => tx.unsigned.action.clone(),
+        }
+    }
+
+    pub fn gas_fee(&self) -> EthGasFee {
+        match self {
+            SignedEthTx::Legacy(tx) => EthGasFee::Legacy {
+                gas_price: tx.gas_price,
+            },
+            SignedEthTx::Eip1559(tx) => EthGasFee::Eip1559(Eip1559Fee {
+                max_fee_per_gas: tx.unsigned.max_fee_per_gas,
+                max_priority_fee_per_gas: tx.unsigned.max_priority_fee_per_gas,
+            }),
+        }
+    }
+}
+
 impl Transaction for SignedEthTx {
-    fn tx_hex(&self) -> Vec<u8> { rlp::encode(self).to_vec() }
+    fn tx_hex(&self) -> Vec<u8> {
+        match self {
+            SignedEthTx::Legacy(tx) => rlp::encode(tx).to_vec(),
+            SignedEthTx::Eip1559(tx) => tx.encode(),
+        }
+    }
+
+    fn tx_hash(&self) -> BytesJson { self.hash().to_vec().into() }
+}
 
-    fn tx_hash(&self) -> BytesJson { self.hash.to_vec().into() }
+/// Parses the `eth_getTransactionByHash` result.
+fn signed_tx_from_rpc_json(transaction: Json) -> Result<SignedEthTx, String> {
+    if transaction["type"].as_str() == Some("0x2") {
+        let tx: Eip1559RpcTx = try_s!(json::from_value(transaction));
+        return Ok(SignedEthTx::Eip1559(try_s!(tx.into_signed())));
+    }
+    let tx: Web3Transaction = try_s!(json::from_value(transaction));
+    signed_tx_from_web3_tx(tx)
 }
 
 fn signed_tx_from_web3_tx(transaction: Web3Transaction) -> Result<SignedEthTx, String> {
@@ -2655,7 +3154,7 @@ fn signed_tx_from_web3_tx(transaction: Web3Transaction) -> Result<SignedEthTx, S
         },
     };
 
-    Ok(try_s!(SignedEthTx::new(unverified)))
+    Ok(SignedEthTx::Legacy(try_s!(SignedLegacyEthTx::new(unverified))))
 }
 
 #[derive(Deserialize, Debug)]
@@ -2764,6 +3263,8 @@ pub async fn eth_coin_from_conf_and_request(
     if swap_contract_address == Address::default() {
         return ERR!("swap_contract_address can't be zero address");
     }
+    let multisend_contract_address: Option<Address> =
+        try_s!(json::from_value(req["multisend_contract_address"].clone()));
 
     let key_pair: KeyPair = try_s!(KeyPair::from_secret_slice(priv_key));
     let my_address = key_pair.address();
@@ -2819,6 +3320,20 @@ pub async fn eth_coin_from_conf_and_request(
         log!("Warning: requires_notarization doesn't take any effect on ETH/ERC20 coins");
     }
 
+    let eip1559_chain_id = if conf["eip1559"].as_bool().unwrap_or(false) {
+        match conf["chain_id"].as_u64() {
+            Some(chain_id) => Some(chain_id),
+            None => {
+                return ERR!(
+                    "'chain_id' must be set in the {} config to send EIP-1559 transactions",
+                    ticker
+                )
+            },
+        }
+    } else {
+        None
+    };
+
     let initial_history_state = if req["tx_history"].as_bool().unwrap_or(false) {
         HistorySyncState::NotStarted
     } else {
@@ -2829,7 +3344,9 @@ pub async fn eth_coin_from_conf_and_request(
         my_address,
         coin_type,
         swap_contract_address,
+        multisend_contract_address,
         decimals,
+        eip1559_chain_id,
         ticker: ticker.into(),
         gas_station_url: try_s!(json::from_value(req["gas_station_url"].clone())),
         web3,
@@ -2940,6 +3457,42 @@ fn increase_by_percent_one_gwei(num: U256, percent: u64) -> U256 {
     }
 }
 
+/// Nodes accept the replacement transaction only if it increases both the gas price and the priority fee
+/// by at least 10%, the current network fee is used if it's higher.
+fn replacement_gas_fee(replaced: EthGasFee, current: EthGasFee) -> EthGasFee {
+    match (replaced, current) {
+        (EthGasFee::Eip1559(replaced), EthGasFee::Eip1559(current)) => EthGasFee::Eip1559(Eip1559Fee {
+            max_fee_per_gas: std::cmp::max(
+                increase_by_percent_one_gwei(replaced.max_fee_per_gas, GAS_PRICE_REPLACEMENT_PERCENT),
+                current.max_fee_per_gas,
+            ),
+            max_priority_fee_per_gas: std::cmp::max(
+                increase_by_percent_one_gwei(replaced.max_priority_fee_per_gas, GAS_PRICE_REPLACEMENT_PERCENT),
+                current.max_priority_fee_per_gas,
+            ),
+        }),
+        (replaced, current) => EthGasFee::Legacy {
+            gas_price: std::cmp::max(
+                increase_by_percent_one_gwei(replaced.max_gas_price(), GAS_PRICE_REPLACEMENT_PERCENT),
+                current.max_gas_price(),
+            ),
+        },
+    }
+}
+
+/// Increases the max gas price only, the priority fee is not expected to change noticeably during the swap.
+fn increase_gas_fee_by_stage(gas_fee: EthGasFee, level: &FeeApproxStage) -> EthGasFee {
+    match gas_fee {
+        EthGasFee::Legacy { gas_price } => EthGasFee::Legacy {
+            gas_price: increase_gas_price_by_stage(gas_price, level),
+        },
+        EthGasFee::Eip1559(fee) => EthGasFee::Eip1559(Eip1559Fee {
+            max_fee_per_gas: increase_gas_price_by_stage(fee.max_fee_per_gas, level),
+            max_priority_fee_per_gas: fee.max_priority_fee_per_gas,
+        }),
+    }
+}
+
 fn increase_gas_price_by_stage(gas_price: U256, level: &FeeApproxStage) -> U256 {
     match level {
         FeeApproxStage::WithoutApprox => gas_price,
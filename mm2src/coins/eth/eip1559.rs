@@ -0,0 +1,252 @@
+//! EIP-1559 (type 2) transactions.
+//! https://eips.ethereum.org/EIPS/eip-1559
+//! https://eips.ethereum.org/EIPS/eip-2718
+
+use ethcore_transaction::Action;
+use ethereum_types::{Address, H256, U256};
+use ethkey::{public_to_address, recover, sign, Secret, Signature};
+use rlp::{Rlp, RlpStream};
+use sha3::{Digest, Keccak256};
+use web3::types::Bytes;
+
+/// The EIP-2718 transaction type of EIP-1559 transactions.
+pub const EIP1559_TX_TYPE: u8 = 2;
+/// The number of the latest blocks requested by `eth_feeHistory`.
+pub const FEE_HISTORY_BLOCK_COUNT: u64 = 10;
+/// The percentile of the effective priority fees requested by `eth_feeHistory`.
+pub const FEE_HISTORY_REWARD_PERCENTILE: u8 = 50;
+/// The priority fee used when `eth_feeHistory` has no rewards, e.g. the latest blocks are empty.
+const DEFAULT_PRIORITY_FEE_PER_GAS: u64 = 1_500_000_000;
+
+/// The result of the `eth_feeHistory` RPC.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeeHistory {
+    /// The base fees of the requested blocks, the last item is the base fee of the next block.
+    pub base_fee_per_gas: Vec<U256>,
+    /// The priority fees of the requested blocks at the requested percentiles.
+    #[serde(default)]
+    pub reward: Vec<Vec<U256>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Eip1559Fee {
+    /// WEI units per 1 gas
+    pub max_fee_per_gas: U256,
+    /// WEI units per 1 gas
+    pub max_priority_fee_per_gas: U256,
+}
+
+impl Eip1559Fee {
+    /// Suggests the fee using the median priority fee of the latest blocks.
+    /// The max fee covers the next block base fee doubled, that stays valid for at least 6 consecutive full blocks.
+    pub fn from_fee_history(history: &FeeHistory) -> Result<Eip1559Fee, String> {
+        let next_base_fee = match history.base_fee_per_gas.last() {
+            Some(fee) => *fee,
+            None => return ERR!("eth_feeHistory returned no base fees, EIP-1559 might be not activated"),
+        };
+
+        let mut rewards: Vec<U256> = history
+            .reward
+            .iter()
+            .filter_map(|block_rewards| block_rewards.first().cloned())
+            .filter(|reward| !reward.is_zero())
+            .collect();
+        rewards.sort();
+        let max_priority_fee_per_gas = match rewards.get(rewards.len() / 2) {
+            Some(reward) => *reward,
+            None => DEFAULT_PRIORITY_FEE_PER_GAS.into(),
+        };
+
+        Ok(Eip1559Fee {
+            max_fee_per_gas: next_base_fee * U256::from(2) + max_priority_fee_per_gas,
+            max_priority_fee_per_gas,
+        })
+    }
+}
+
+/// The unsigned EIP-1559 transaction with an empty access list.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Eip1559Tx {
+    pub chain_id: u64,
+    pub nonce: U256,
+    pub max_priority_fee_per_gas: U256,
+    pub max_fee_per_gas: U256,
+    pub gas: U256,
+    pub action: Action,
+    pub value: U256,
+    pub data: Vec<u8>,
+}
+
+impl Eip1559Tx {
+    fn rlp_append_unsigned(&self, stream: &mut RlpStream) {
+        stream.append(&self.chain_id);
+        stream.append(&self.nonce);
+        stream.append(&self.max_priority_fee_per_gas);
+        stream.append(&self.max_fee_per_gas);
+        stream.append(&self.gas);
+        stream.append(&self.action);
+        stream.append(&self.value);
+        stream.append(&self.data);
+        // access list
+        stream.begin_list(0);
+    }
+
+    /// `keccak256(0x02 || rlp([chain_id, nonce, max_priority_fee_per_gas, max_fee_per_gas, gas, to, value, data, access_list]))`
+    pub fn signature_hash(&self) -> H256 {
+        let mut stream = RlpStream::new_list(9);
+        self.rlp_append_unsigned(&mut stream);
+        keccak256_typed(&stream.out())
+    }
+
+    pub fn sign(self, secret: &Secret) -> Result<SignedEip1559Tx, String> {
+        let signature = try_s!(sign(secret, &self.signature_hash()));
+        SignedEip1559Tx::new(
+            self,
+            signature.v(),
+            U256::from_big_endian(signature.r()),
+            U256::from_big_endian(signature.s()),
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignedEip1559Tx {
+    pub unsigned: Eip1559Tx,
+    pub y_parity: u8,
+    pub r: U256,
+    pub s: U256,
+    pub hash: H256,
+    sender: Address,
+}
+
+impl SignedEip1559Tx {
+    /// Checks the signature and recovers the sender address.
+    pub fn new(unsigned: Eip1559Tx, y_parity: u8, r: U256, s: U256) -> Result<SignedEip1559Tx, String> {
+        if y_parity > 1 {
+            return ERR!("Invalid y_parity {}", y_parity);
+        }
+        let signature = Signature::from_rsv(&u256_to_h256(r), &u256_to_h256(s), y_parity);
+        if !signature.is_low_s() {
+            return ERR!("Signature s value {} is too high", s);
+        }
+        let public = try_s!(recover(&signature, &unsigned.signature_hash()));
+        let mut tx = SignedEip1559Tx {
+            unsigned,
+            y_parity,
+            r,
+            s,
+            hash: H256::default(),
+            sender: public_to_address(&public),
+        };
+        tx.hash = keccak256(&tx.encode());
+        Ok(tx)
+    }
+
+    pub fn sender(&self) -> Address { self.sender }
+
+    /// `0x02 || rlp([chain_id, nonce, max_priority_fee_per_gas, max_fee_per_gas, gas, to, value, data, access_list, y_parity, r, s])`
+    pub fn encode(&self) -> Vec<u8> {
+        let mut stream = RlpStream::new_list(12);
+        self.unsigned.rlp_append_unsigned(&mut stream);
+        stream.append(&self.y_parity);
+        stream.append(&self.r);
+        stream.append(&self.s);
+
+        let mut encoded = vec![EIP1559_TX_TYPE];
+        encoded.extend_from_slice(&stream.out());
+        encoded
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<SignedEip1559Tx, String> {
+        match bytes.first() {
+            Some(&EIP1559_TX_TYPE) => (),
+            _ => return ERR!("Expected EIP-1559 transaction type prefix"),
+        }
+        let rlp = Rlp::new(&bytes[1..]);
+        let item_count = try_s!(rlp.item_count());
+        if item_count != 12 {
+            return ERR!("Invalid EIP-1559 transaction item count {}", item_count);
+        }
+        if try_s!(try_s!(rlp.at(8)).item_count()) != 0 {
+            return ERR!("EIP-1559 transactions with non-empty access list are not supported");
+        }
+
+        let unsigned = Eip1559Tx {
+            chain_id: try_s!(rlp.val_at(0)),
+            nonce: try_s!(rlp.val_at(1)),
+            max_priority_fee_per_gas: try_s!(rlp.val_at(2)),
+            max_fee_per_gas: try_s!(rlp.val_at(3)),
+            gas: try_s!(rlp.val_at(4)),
+            action: try_s!(rlp.val_at(5)),
+            value: try_s!(rlp.val_at(6)),
+            data: try_s!(rlp.val_at(7)),
+        };
+        SignedEip1559Tx::new(
+            unsigned,
+            try_s!(rlp.val_at(9)),
+            try_s!(rlp.val_at(10)),
+            try_s!(rlp.val_at(11)),
+        )
+    }
+}
+
+/// EIP-1559 transaction as it's returned by `eth_getTransactionByHash`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Eip1559RpcTx {
+    hash: H256,
+    chain_id: U256,
+    nonce: U256,
+    max_priority_fee_per_gas: U256,
+    max_fee_per_gas: U256,
+    gas: U256,
+    to: Option<Address>,
+    value: U256,
+    input: Bytes,
+    v: U256,
+    r: U256,
+    s: U256,
+}
+
+impl Eip1559RpcTx {
+    pub fn into_signed(self) -> Result<SignedEip1559Tx, String> {
+        let unsigned = Eip1559Tx {
+            chain_id: self.chain_id.as_u64(),
+            nonce: self.nonce,
+            max_priority_fee_per_gas: self.max_priority_fee_per_gas,
+            max_fee_per_gas: self.max_fee_per_gas,
+            gas: self.gas,
+            action: match self.to {
+                Some(addr) => Action::Call(addr),
+                None => Action::Create,
+            },
+            value: self.value,
+            data: self.input.0,
+        };
+        let signed = try_s!(SignedEip1559Tx::new(unsigned, self.v.as_u64() as u8, self.r, self.s));
+        if signed.hash != self.hash {
+            return ERR!(
+                "Transaction hash {:?} doesn't match the hash {:?} returned by RPC",
+                signed.hash,
+                self.hash
+            );
+        }
+        Ok(signed)
+    }
+}
+
+fn keccak256(bytes: &[u8]) -> H256 { H256::from_slice(Keccak256::digest(bytes).as_slice()) }
+
+fn keccak256_typed(payload: &[u8]) -> H256 {
+    let mut hasher = Keccak256::default();
+    hasher.input(&[EIP1559_TX_TYPE]);
+    hasher.input(payload);
+    H256::from_slice(hasher.result().as_slice())
+}
+
+fn u256_to_h256(num: U256) -> H256 {
+    let mut bytes = [0u8; 32];
+    num.to_big_endian(&mut bytes);
+    H256::from(bytes)
+}
@@ -31,11 +31,13 @@ fn eth_coin_for_test(coin_type: EthCoinType, urls: Vec<String>) -> (MmArc, EthCo
     let eth_coin = EthCoin(Arc::new(EthCoinImpl {
         coin_type,
         decimals: 18,
+        eip1559_chain_id: None,
         gas_station_url: None,
         history_sync_state: Mutex::new(HistorySyncState::NotEnabled),
         my_address: key_pair.address(),
         key_pair,
         swap_contract_address: Address::from("0x7Bc1bBDD6A0a722fC9bffC49c921B685ECB84b94"),
+        multisend_contract_address: None,
         ticker: "ETH".into(),
         web3_instances: vec![Web3Instance {
             web3: web3.clone(),
@@ -192,12 +194,14 @@ fn send_and_refund_erc20_payment() {
         my_address: key_pair.address(),
         key_pair,
         swap_contract_address: Address::from("0x7Bc1bBDD6A0a722fC9bffC49c921B685ECB84b94"),
+        multisend_contract_address: None,
         web3_instances: vec![Web3Instance {
             web3: web3.clone(),
             is_parity: true,
         }],
         web3,
         decimals: 18,
+        eip1559_chain_id: None,
         gas_station_url: None,
         history_sync_state: Mutex::new(HistorySyncState::NotStarted),
         ctx: ctx.weak(),
@@ -211,6 +215,9 @@ fn send_and_refund_erc20_payment() {
             &[1; 20],
             "0.001".parse().unwrap(),
             &coin.swap_contract_address(),
+            HtlcScriptType::P2SH,
+            false,
+            &[],
         )
         .wait()
         .unwrap();
@@ -226,6 +233,7 @@ fn send_and_refund_erc20_payment() {
             &DEX_FEE_ADDR_RAW_PUBKEY,
             &[1; 20],
             &coin.swap_contract_address(),
+            &[],
         )
         .wait()
         .unwrap();
@@ -250,12 +258,14 @@ fn send_and_refund_eth_payment() {
         my_address: key_pair.address(),
         key_pair,
         swap_contract_address: Address::from("0x7Bc1bBDD6A0a722fC9bffC49c921B685ECB84b94"),
+        multisend_contract_address: None,
         web3_instances: vec![Web3Instance {
             web3: web3.clone(),
             is_parity: true,
         }],
         web3,
         decimals: 18,
+        eip1559_chain_id: None,
         gas_station_url: None,
         history_sync_state: Mutex::new(HistorySyncState::NotStarted),
         ctx: ctx.weak(),
@@ -269,6 +279,9 @@ fn send_and_refund_eth_payment() {
             &[1; 20],
             "0.001".parse().unwrap(),
             &coin.swap_contract_address(),
+            HtlcScriptType::P2SH,
+            false,
+            &[],
         )
         .wait()
         .unwrap();
@@ -284,6 +297,7 @@ fn send_and_refund_eth_payment() {
             &DEX_FEE_ADDR_RAW_PUBKEY,
             &[1; 20],
             &coin.swap_contract_address(),
+            &[],
         )
         .wait()
         .unwrap();
@@ -317,6 +331,7 @@ fn test_nonce_several_urls() {
         my_address: key_pair.address(),
         key_pair,
         swap_contract_address: Address::from("0x7Bc1bBDD6A0a722fC9bffC49c921B685ECB84b94"),
+        multisend_contract_address: None,
         web3_instances: vec![
             Web3Instance {
                 web3: web3_infura.clone(),
@@ -333,6 +348,7 @@ fn test_nonce_several_urls() {
         ],
         web3: web3_infura,
         decimals: 18,
+        eip1559_chain_id: None,
         gas_station_url: Some("https://ethgasstation.info/json/ethgasAPI.json".into()),
         history_sync_state: Mutex::new(HistorySyncState::NotStarted),
         ctx: ctx.weak(),
@@ -365,11 +381,13 @@ fn test_wait_for_payment_spend_timeout() {
     let coin = EthCoinImpl {
         coin_type: EthCoinType::Eth,
         decimals: 18,
+        eip1559_chain_id: None,
         gas_station_url: None,
         history_sync_state: Mutex::new(HistorySyncState::NotEnabled),
         my_address: key_pair.address(),
         key_pair,
         swap_contract_address: Address::from("0x7Bc1bBDD6A0a722fC9bffC49c921B685ECB84b94"),
+        multisend_contract_address: None,
         ticker: "ETH".into(),
         web3_instances: vec![Web3Instance {
             web3: web3.clone(),
@@ -420,11 +438,13 @@ fn test_search_for_swap_tx_spend_was_spent() {
     let coin = EthCoin(Arc::new(EthCoinImpl {
         coin_type: EthCoinType::Eth,
         decimals: 18,
+        eip1559_chain_id: None,
         gas_station_url: None,
         history_sync_state: Mutex::new(HistorySyncState::NotEnabled),
         my_address: key_pair.address(),
         key_pair,
         swap_contract_address,
+        multisend_contract_address: None,
         ticker: "ETH".into(),
         web3_instances: vec![Web3Instance {
             web3: web3.clone(),
@@ -488,11 +508,13 @@ fn test_search_for_swap_tx_spend_was_refunded() {
     let coin = EthCoin(Arc::new(EthCoinImpl {
         coin_type: EthCoinType::Erc20(Address::from("0xc0eb7aed740e1796992a08962c15661bdeb58003")),
         decimals: 18,
+        eip1559_chain_id: None,
         gas_station_url: None,
         history_sync_state: Mutex::new(HistorySyncState::NotEnabled),
         my_address: key_pair.address(),
         key_pair,
         swap_contract_address,
+        multisend_contract_address: None,
         ticker: "ETH".into(),
         web3_instances: vec![Web3Instance {
             web3: web3.clone(),
@@ -559,6 +581,7 @@ fn test_withdraw_impl_manual_fee() {
             gas: 150000,
             gas_price: 1.into(),
         }),
+        ..Default::default()
     };
     coin.my_balance().wait().unwrap();
 
@@ -568,6 +591,8 @@ fn test_withdraw_impl_manual_fee() {
             coin: "ETH".into(),
             gas_price: "0.000000001".parse().unwrap(),
             gas: 150000,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
             total_fee: "0.00015".parse().unwrap(),
         }
         .into(),
@@ -589,6 +614,7 @@ fn test_nonce_lock() {
             Action::Call(coin.my_address),
             vec![],
             21000.into(),
+            None,
         ));
     }
     let results = block_on(join_all(futures));
@@ -662,12 +688,14 @@ mod wasm_bindgen_tests {
                 my_address: key_pair.address(),
                 key_pair,
                 swap_contract_address: Address::from("0x7Bc1bBDD6A0a722fC9bffC49c921B685ECB84b94"),
+                multisend_contract_address: None,
                 web3_instances: vec![Web3Instance {
                     web3: web3.clone(),
                     is_parity: true,
                 }],
                 web3,
                 decimals: 18,
+                eip1559_chain_id: None,
                 gas_station_url: None,
                 history_sync_state: Mutex::new(HistorySyncState::NotStarted),
                 ctx: ctx.weak(),
@@ -746,9 +774,11 @@ fn get_sender_trade_preimage() {
     /// Trade fee for the ETH coin is `2 * 150_000 * gas_price` always.
     fn expected_fee(gas_price: u64) -> TradeFee {
         let amount = u256_to_big_decimal((2 * 150_000 * gas_price).into(), 18).expect("!u256_to_big_decimal");
+        let fee_details = EthTxFeeDetails::new(300_000.into(), gas_price.into(), "ETH").expect("!EthTxFeeDetails::new");
         TradeFee {
             coin: "ETH".to_owned(),
             amount: amount.into(),
+            fee_details: Some(fee_details.into()),
         }
     }
 
@@ -801,9 +831,12 @@ fn get_erc20_sender_trade_preimage() {
 
     fn expected_trade_fee(gas_limit: u64, gas_price: u64) -> TradeFee {
         let amount = u256_to_big_decimal((gas_limit * gas_price).into(), 18).expect("!u256_to_big_decimal");
+        let fee_details =
+            EthTxFeeDetails::new(gas_limit.into(), gas_price.into(), "ETH").expect("!EthTxFeeDetails::new");
         TradeFee {
             coin: "ETH".to_owned(),
             amount: amount.into(),
+            fee_details: Some(fee_details.into()),
         }
     }
 
@@ -865,9 +898,11 @@ fn get_receiver_trade_preimage() {
 
     let (_ctx, coin) = eth_coin_for_test(EthCoinType::Eth, vec!["http://dummy.dummy".into()]);
     let amount = u256_to_big_decimal((150_000 * GAS_PRICE).into(), 18).expect("!u256_to_big_decimal");
+    let fee_details = EthTxFeeDetails::new(150_000.into(), GAS_PRICE.into(), "ETH").expect("!EthTxFeeDetails::new");
     let expected_fee = TradeFee {
         coin: "ETH".to_owned(),
         amount: amount.into(),
+        fee_details: Some(fee_details.into()),
     };
 
     let actual = coin
@@ -888,9 +923,12 @@ fn test_get_fee_to_send_taker_fee() {
 
     // fee to send taker fee is `TRANSFER_GAS_LIMIT * gas_price` always.
     let amount = u256_to_big_decimal((TRANSFER_GAS_LIMIT * GAS_PRICE).into(), 18).expect("!u256_to_big_decimal");
+    let fee_details =
+        EthTxFeeDetails::new(TRANSFER_GAS_LIMIT.into(), GAS_PRICE.into(), "ETH").expect("!EthTxFeeDetails::new");
     let expected_fee = TradeFee {
         coin: "ETH".to_owned(),
         amount: amount.into(),
+        fee_details: Some(fee_details.into()),
     };
 
     let dex_fee_amount = u256_to_big_decimal(DEX_FEE_AMOUNT.into(), 18).expect("!u256_to_big_decimal");
@@ -934,3 +972,97 @@ fn test_get_fee_to_send_taker_fee_insufficient_balance() {
         "Expected TradePreimageError::NotSufficientBalance"
     );
 }
+
+#[test]
+fn test_eip1559_fee_from_history() {
+    let gwei = |n: u64| U256::from(n) * U256::exp10(9);
+
+    let history = FeeHistory {
+        base_fee_per_gas: vec![gwei(90), gwei(95), gwei(100)],
+        reward: vec![vec![gwei(3)], vec![0.into()], vec![gwei(1)], vec![gwei(2)]],
+    };
+    let expected = Eip1559Fee {
+        max_fee_per_gas: gwei(202),
+        max_priority_fee_per_gas: gwei(2),
+    };
+    assert_eq!(Eip1559Fee::from_fee_history(&history).unwrap(), expected);
+
+    // the latest blocks are empty
+    let history = FeeHistory {
+        base_fee_per_gas: vec![gwei(10)],
+        reward: vec![vec![0.into()]],
+    };
+    let expected = Eip1559Fee {
+        max_fee_per_gas: gwei(20) + U256::from(1_500_000_000),
+        max_priority_fee_per_gas: 1_500_000_000.into(),
+    };
+    assert_eq!(Eip1559Fee::from_fee_history(&history).unwrap(), expected);
+
+    let history = FeeHistory {
+        base_fee_per_gas: vec![],
+        reward: vec![],
+    };
+    assert!(Eip1559Fee::from_fee_history(&history).is_err());
+}
+
+#[test]
+fn test_replacement_gas_fee() {
+    let gwei = |n: u64| U256::from(n) * U256::exp10(9);
+
+    // the replaced gas price is increased by 15%
+    let replaced = EthGasFee::Legacy { gas_price: gwei(100) };
+    let current = EthGasFee::Legacy { gas_price: gwei(90) };
+    let expected = EthGasFee::Legacy { gas_price: gwei(115) };
+    assert_eq!(replacement_gas_fee(replaced, current), expected);
+
+    // the current gas price is used if it's higher
+    let replaced = EthGasFee::Legacy { gas_price: gwei(100) };
+    let current = EthGasFee::Legacy { gas_price: gwei(150) };
+    let expected = EthGasFee::Legacy { gas_price: gwei(150) };
+    assert_eq!(replacement_gas_fee(replaced, current), expected);
+
+    // the priority fee is increased by 1 gwei at least
+    let replaced = EthGasFee::Eip1559(Eip1559Fee {
+        max_fee_per_gas: gwei(200),
+        max_priority_fee_per_gas: gwei(2),
+    });
+    let current = EthGasFee::Eip1559(Eip1559Fee {
+        max_fee_per_gas: gwei(250),
+        max_priority_fee_per_gas: gwei(2),
+    });
+    let expected = EthGasFee::Eip1559(Eip1559Fee {
+        max_fee_per_gas: gwei(250),
+        max_priority_fee_per_gas: gwei(3),
+    });
+    assert_eq!(replacement_gas_fee(replaced, current), expected);
+}
+
+#[test]
+fn test_eip1559_tx_sign_and_decode() {
+    let key_pair = KeyPair::from_secret_slice(
+        &hex::decode("809465b17d0a4ddb3e4c69e8f23c2cabad868f51f8bed5c765ad1d6516c3306f").unwrap(),
+    )
+    .unwrap();
+    let unsigned = Eip1559Tx {
+        chain_id: 1,
+        nonce: 7.into(),
+        max_priority_fee_per_gas: 2_000_000_000u64.into(),
+        max_fee_per_gas: 100_000_000_000u64.into(),
+        gas: 150_000.into(),
+        action: Action::Call(Address::from("0x7Bc1bBDD6A0a722fC9bffC49c921B685ECB84b94")),
+        value: 1_000_000.into(),
+        data: vec![1, 2, 3],
+    };
+    let signed = SignedEthTx::Eip1559(unsigned.sign(key_pair.secret()).unwrap());
+    assert_eq!(signed.sender(), key_pair.address());
+
+    let tx_hex = signed.tx_hex();
+    assert_eq!(tx_hex[0], EIP1559_TX_TYPE);
+    let hash = H256::from_slice(Keccak256::digest(&tx_hex).as_slice());
+    assert_eq!(signed.hash(), hash);
+
+    let decoded = signed_eth_tx_from_bytes(&tx_hex).unwrap();
+    assert_eq!(decoded, signed);
+    assert_eq!(decoded.data(), &[1, 2, 3]);
+    assert_eq!(decoded.value(), 1_000_000.into());
+}
@@ -0,0 +1,82 @@
+//! BIP44 accounts of the coins activated in the HD wallet mode.
+//! https://github.com/bitcoin/bips/blob/master/bip-0044.mediawiki
+
+use common::mm_ctx::MmArc;
+use common::privkey::{ChildNumber, DerivationPath, ExtendedPrivateKey, HARDENED_KEY_OFFSET};
+use serde_json::Value as Json;
+
+/// The chain of the addresses that are used to receive the funds.
+pub const RECEIVE_CHAIN: u32 = 0;
+/// The chain of the addresses that are used to receive the change.
+pub const CHANGE_CHAIN: u32 = 1;
+/// The number of consecutive unused addresses after which the address discovery stops.
+pub const DEFAULT_GAP_LIMIT: u32 = 20;
+
+/// The `m/44'/coin_type'/account'` BIP44 account of the coin.
+#[derive(Clone, Debug)]
+pub struct HDAccount {
+    pub account_id: u32,
+    pub derivation_path: DerivationPath,
+    account_key: ExtendedPrivateKey,
+}
+
+impl HDAccount {
+    /// Derives the key of the `m/44'/coin_type'/account'/chain/index` address.
+    pub fn derive_address_key(&self, chain: u32, index: u32) -> Result<ExtendedPrivateKey, String> {
+        let chain_key = try_s!(self.account_key.derive_child(ChildNumber::Normal(chain)));
+        chain_key.derive_child(ChildNumber::Normal(index))
+    }
+
+    pub fn address_derivation_path(&self, chain: u32, index: u32) -> DerivationPath {
+        self.derivation_path
+            .child(ChildNumber::Normal(chain))
+            .child(ChildNumber::Normal(index))
+    }
+}
+
+/// Returns the BIP44 account of the coin if the HD wallet mode is enabled.
+/// The coin type is set by the `derivation_path` coin config field, e.g. `m/44'/141'`,
+/// and the account is chosen by the `hd_account_id` enable request field (0 by default).
+pub fn hd_account_from_conf_and_request(
+    ctx: &MmArc,
+    ticker: &str,
+    conf: &Json,
+    req: &Json,
+) -> Result<Option<HDAccount>, String> {
+    let master_key = match ctx.hd_master_key.as_option() {
+        Some(key) => key,
+        None => return Ok(None),
+    };
+
+    let coin_path: DerivationPath = match conf["derivation_path"].as_str() {
+        Some(path) => try_s!(path.parse()),
+        None => {
+            return ERR!(
+                "HD wallet mode is enabled, but 'derivation_path' is not set in the {} config",
+                ticker
+            )
+        },
+    };
+    match coin_path.as_slice() {
+        [ChildNumber::Hardened(44), ChildNumber::Hardened(_)] => (),
+        _ => {
+            return ERR!(
+                "Expected 'derivation_path' in the m/44'/coin_type' form, found {}",
+                coin_path
+            )
+        },
+    }
+
+    let account_id = req["hd_account_id"].as_u64().unwrap_or(0);
+    if account_id >= HARDENED_KEY_OFFSET as u64 {
+        return ERR!("Invalid 'hd_account_id' {}", account_id);
+    }
+    let derivation_path = coin_path.child(ChildNumber::Hardened(account_id as u32));
+    let account_key = try_s!(master_key.derive_path(&derivation_path));
+
+    Ok(Some(HDAccount {
+        account_id: account_id as u32,
+        derivation_path,
+        account_key,
+    }))
+}
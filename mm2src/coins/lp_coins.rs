@@ -41,7 +41,6 @@ use common::{block_on, calc_total_pages, rpc_err_response, rpc_response, HyRes,
 use futures::compat::Future01CompatExt;
 use futures::lock::Mutex as AsyncMutex;
 use futures01::Future;
-use gstuff::slurp;
 use http::Response;
 use rpc::v1::types::Bytes as BytesJson;
 use serde::{Deserialize, Deserializer};
@@ -70,16 +69,21 @@ pub mod coins_tests;
 
 pub mod eth;
 use self::eth::{eth_coin_from_conf_and_request, EthCoin, EthTxFeeDetails, SignedEthTx};
+pub mod hd_wallet;
+use self::hd_wallet::{hd_account_from_conf_and_request, RECEIVE_CHAIN};
 pub mod utxo;
 use self::utxo::qtum::{self, qtum_coin_from_conf_and_request, QtumCoin};
 use self::utxo::utxo_standard::{utxo_standard_coin_from_conf_and_request, UtxoStandardCoin};
-use self::utxo::{GenerateTransactionError, UtxoFeeDetails, UtxoTx};
+use self::utxo::{GenerateTransactionError, UtxoCoinFields, UtxoFeeDetails, UtxoTx};
 pub mod qrc20;
 use qrc20::{qrc20_coin_from_conf_and_request, Qrc20Coin, Qrc20FeeDetails};
 #[doc(hidden)]
 #[allow(unused_variables)]
 pub mod test_coin;
 pub use test_coin::TestCoin;
+pub mod tx_history_db;
+use tx_history_db::{count_tx_history, import_tx_history_file, load_tx_history, replace_tx_history, select_tx_history,
+                    tx_history_position, upsert_tx_history, TxHistoryFilter};
 
 pub trait Transaction: fmt::Debug + 'static {
     /// Raw transaction bytes of the transaction
@@ -115,10 +119,27 @@ pub enum FoundSwapTxSpend {
     Refunded(TransactionEnum),
 }
 
+/// The type of the output script locking the swap payment (HTLC).
+/// Note it's taken into account by the UTXO coins only.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum HtlcScriptType {
+    /// The legacy Pay-To-Script-Hash output supported by all peers.
+    P2SH,
+    /// The SegWit Pay-To-Witness-Script-Hash output.
+    /// https://github.com/bitcoin/bips/blob/master/bip-0141.mediawiki#p2wsh
+    P2WSH,
+}
+
+impl Default for HtlcScriptType {
+    fn default() -> Self { HtlcScriptType::P2SH }
+}
+
 /// Swap operations (mostly based on the Hash/Time locked transactions implemented by coin wallets).
 pub trait SwapOps {
     fn send_taker_fee(&self, fee_addr: &[u8], amount: BigDecimal) -> TransactionFut;
 
+    /// Sends the maker payment, `replaceable` allows its fee to be bumped by the replacement (BIP125)
+    /// and must be set only if the taker waits for the payment confirmations.
     fn send_maker_payment(
         &self,
         time_lock: u32,
@@ -126,8 +147,13 @@ pub trait SwapOps {
         secret_hash: &[u8],
         amount: BigDecimal,
         swap_contract_address: &Option<BytesJson>,
+        htlc_script_type: HtlcScriptType,
+        replaceable: bool,
+        swap_unique_data: &[u8],
     ) -> TransactionFut;
 
+    /// Sends the taker payment, `replaceable` allows its fee to be bumped by the replacement (BIP125)
+    /// and must be set only if the maker waits for the payment confirmations.
     fn send_taker_payment(
         &self,
         time_lock: u32,
@@ -135,6 +161,9 @@ pub trait SwapOps {
         secret_hash: &[u8],
         amount: BigDecimal,
         swap_contract_address: &Option<BytesJson>,
+        htlc_script_type: HtlcScriptType,
+        replaceable: bool,
+        swap_unique_data: &[u8],
     ) -> TransactionFut;
 
     fn send_maker_spends_taker_payment(
@@ -144,6 +173,7 @@ pub trait SwapOps {
         taker_pub: &[u8],
         secret: &[u8],
         swap_contract_address: &Option<BytesJson>,
+        swap_unique_data: &[u8],
     ) -> TransactionFut;
 
     fn send_taker_spends_maker_payment(
@@ -153,6 +183,7 @@ pub trait SwapOps {
         maker_pub: &[u8],
         secret: &[u8],
         swap_contract_address: &Option<BytesJson>,
+        swap_unique_data: &[u8],
     ) -> TransactionFut;
 
     fn send_taker_refunds_payment(
@@ -162,6 +193,7 @@ pub trait SwapOps {
         maker_pub: &[u8],
         secret_hash: &[u8],
         swap_contract_address: &Option<BytesJson>,
+        swap_unique_data: &[u8],
     ) -> TransactionFut;
 
     fn send_maker_refunds_payment(
@@ -171,6 +203,7 @@ pub trait SwapOps {
         taker_pub: &[u8],
         secret_hash: &[u8],
         swap_contract_address: &Option<BytesJson>,
+        swap_unique_data: &[u8],
     ) -> TransactionFut;
 
     fn validate_fee(
@@ -188,6 +221,8 @@ pub trait SwapOps {
         priv_bn_hash: &[u8],
         amount: BigDecimal,
         swap_contract_address: &Option<BytesJson>,
+        htlc_script_type: HtlcScriptType,
+        swap_unique_data: &[u8],
     ) -> Box<dyn Future<Item = (), Error = String> + Send>;
 
     fn validate_taker_payment(
@@ -198,6 +233,8 @@ pub trait SwapOps {
         priv_bn_hash: &[u8],
         amount: BigDecimal,
         swap_contract_address: &Option<BytesJson>,
+        htlc_script_type: HtlcScriptType,
+        swap_unique_data: &[u8],
     ) -> Box<dyn Future<Item = (), Error = String> + Send>;
 
     fn check_if_my_payment_sent(
@@ -207,6 +244,8 @@ pub trait SwapOps {
         secret_hash: &[u8],
         search_from_block: u64,
         swap_contract_address: &Option<BytesJson>,
+        htlc_script_type: HtlcScriptType,
+        swap_unique_data: &[u8],
     ) -> Box<dyn Future<Item = Option<TransactionEnum>, Error = String> + Send>;
 
     fn search_for_swap_tx_spend_my(
@@ -217,6 +256,7 @@ pub trait SwapOps {
         tx: &[u8],
         search_from_block: u64,
         swap_contract_address: &Option<BytesJson>,
+        swap_unique_data: &[u8],
     ) -> Result<Option<FoundSwapTxSpend>, String>;
 
     fn search_for_swap_tx_spend_other(
@@ -227,9 +267,25 @@ pub trait SwapOps {
         tx: &[u8],
         search_from_block: u64,
         swap_contract_address: &Option<BytesJson>,
+        swap_unique_data: &[u8],
     ) -> Result<Option<FoundSwapTxSpend>, String>;
 
     fn extract_secret(&self, secret_hash: &[u8], spend_tx: &[u8]) -> Result<Vec<u8>, String>;
+
+    /// Derives the pubkey used in the HTLC scripts of the swap identified by `swap_unique_data`.
+    /// The key is unique for each swap if the coin supports it, otherwise the coin account pubkey is returned.
+    /// Only the UTXO coins support it. The ETH/ERC20 and QRC20 swap contracts identify the parties by the addresses
+    /// the contract calls are sent from, so a per-swap key would need its own funded address to pay the gas.
+    /// Their swaps are therefore linkable on-chain by the account address.
+    fn derive_htlc_pubkey(&self, swap_unique_data: &[u8]) -> Result<Vec<u8>, String>;
+
+    /// Replaces the not yet confirmed `payment_tx` sent by this node with the same payment paying the higher fee.
+    /// UTXO coins use the BIP125 replace-by-fee, ETH/ERC20 resend the transaction with the same nonce.
+    fn bump_payment_fee(&self, payment_tx: &[u8]) -> TransactionFut;
+
+    /// Sends the child transaction spending the outputs of the not yet confirmed `parent_tx` paid to this node,
+    /// e.g. the swap spend or refund, so the child pays for both transactions at the current fee rate.
+    fn send_cpfp_child(&self, parent_tx: &[u8]) -> TransactionFut;
 }
 
 /// Operations that coins have independently from the MarketMaker.
@@ -290,6 +346,14 @@ pub enum WithdrawFee {
         gas_price: BigDecimal,
         gas: u64,
     },
+    /// https://eips.ethereum.org/EIPS/eip-1559
+    EthGasEip1559 {
+        /// in gwei
+        max_fee_per_gas: BigDecimal,
+        /// in gwei
+        max_priority_fee_per_gas: BigDecimal,
+        gas: u64,
+    },
     Qrc20Gas {
         /// in satoshi
         gas_limit: u64,
@@ -297,8 +361,28 @@ pub enum WithdrawFee {
     },
 }
 
+/// The strategy to select the inputs of UTXO withdraw transaction.
+/// The unspents are spent in the ascending order of their amounts if the strategy isn't set.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CoinSelectionStrategy {
+    /// Spend the largest unspents first to minimize the number of inputs.
+    LargestFirst,
+    /// Spend the unspents mined earlier first.
+    OldestFirst,
+    /// Search for the unspents that pay the outputs without the change,
+    /// the largest unspents are spent first if there are no such unspents.
+    BranchAndBound,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct WithdrawOutput {
+    address: String,
+    amount: BigDecimal,
+}
+
 #[allow(dead_code)]
-#[derive(Deserialize)]
+#[derive(Default, Deserialize)]
 pub struct WithdrawRequest {
     coin: String,
     to: String,
@@ -307,6 +391,57 @@ pub struct WithdrawRequest {
     #[serde(default)]
     max: bool,
     fee: Option<WithdrawFee>,
+    /// The unspents in the `txid:vout` form the transaction is allowed to spend.
+    #[serde(default)]
+    inputs: Vec<String>,
+    /// The outputs paid in addition to `to`.
+    #[serde(default)]
+    outputs: Vec<WithdrawOutput>,
+    /// The text recorded by the OP_RETURN output.
+    memo: Option<String>,
+    /// The address receiving the change instead of my address.
+    change_address: Option<String>,
+    coin_selection: Option<CoinSelectionStrategy>,
+    /// UTXO only: return the unsigned BIP174 PSBT to be signed externally instead of signing the transaction.
+    #[serde(default)]
+    psbt: bool,
+}
+
+#[derive(Deserialize)]
+pub struct WithdrawBatchRequest {
+    coin: String,
+    recipients: Vec<WithdrawOutput>,
+    fee: Option<WithdrawFee>,
+    /// ETH/ERC20 only: pay all the recipients by one call to the coin `multisend_contract_address`
+    /// instead of the transaction per recipient.
+    #[serde(default)]
+    use_multisend: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WithdrawBatchRecipient {
+    pub address: String,
+    pub amount: BigDecimal,
+    /// The hash of the transaction paying the recipient.
+    pub tx_hash: BytesJson,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WithdrawBatchResponse {
+    /// The transactions must be broadcast in this order, ETH/ERC20 transactions have the sequential nonces.
+    pub transactions: Vec<TransactionDetails>,
+    pub recipients: Vec<WithdrawBatchRecipient>,
+}
+
+impl WithdrawRequest {
+    /// Whether any of the coin control fields supported by UTXO coins only is set.
+    fn has_coin_control(&self) -> bool {
+        !self.inputs.is_empty()
+            || !self.outputs.is_empty()
+            || self.memo.is_some()
+            || self.change_address.is_some()
+            || self.coin_selection.is_some()
+    }
 }
 
 /// Please note that no type should have the same structure as another type,
@@ -384,6 +519,9 @@ pub struct TransactionDetails {
     coin: String,
     /// Internal MM2 id used for internal transaction identification, for some coins it might be equal to transaction hash
     internal_id: BytesJson,
+    /// Base64 encoded unsigned BIP174 PSBT, it's set instead of tx_hex and tx_hash if the withdraw is signed externally
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    psbt: Option<String>,
 }
 
 impl TransactionDetails {
@@ -406,6 +544,8 @@ impl TransactionDetails {
 pub struct TradeFee {
     pub coin: String,
     pub amount: MmNumber,
+    /// The coin specific fee details, e.g. the gas price and the EIP-1559 fees of ETH/ERC20 coins.
+    pub fee_details: Option<TxFeeDetails>,
 }
 
 /// The approximation is needed to cover the dynamic miner fee changing during a swap.
@@ -483,6 +623,13 @@ pub trait MmCoin: SwapOps + MarketCoinOps + fmt::Debug + Send + Sync + 'static {
 
     fn withdraw(&self, req: WithdrawRequest) -> Box<dyn Future<Item = TransactionDetails, Error = String> + Send>;
 
+    /// Pays many recipients at once: UTXO coins build one transaction with the output per recipient,
+    /// ETH/ERC20 coins sign the nonce-ordered transactions or the one multisend contract call.
+    fn withdraw_batch(
+        &self,
+        req: WithdrawBatchRequest,
+    ) -> Box<dyn Future<Item = WithdrawBatchResponse, Error = String> + Send>;
+
     /// Maximum number of digits after decimal point used to denominate integer coin units (satoshis, wei, etc.)
     fn decimals(&self) -> u8;
 
@@ -494,7 +641,7 @@ pub trait MmCoin: SwapOps + MarketCoinOps + fmt::Debug + Send + Sync + 'static {
     /// Loop collecting coin transaction history and saving it to local DB
     fn process_history_loop(&self, ctx: MmArc);
 
-    /// Path to tx history file
+    /// Path to the legacy tx history file, the history is imported to the SQLite database from it if it's present
     fn tx_history_path(&self, ctx: &MmArc) -> PathBuf {
         let my_address = self.my_address().unwrap_or_default();
         // BCH cash address format has colon after prefix, e.g. bitcoincash:
@@ -505,36 +652,57 @@ pub trait MmCoin: SwapOps + MarketCoinOps + fmt::Debug + Send + Sync + 'static {
             .join(format!("{}_{}.json", self.ticker(), my_address))
     }
 
-    /// Loads existing tx history from file, returns empty vector if file is not found
-    /// Cleans the existing file if deserialization fails
-    fn load_history_from_file(&self, ctx: &MmArc) -> Vec<TransactionDetails> {
-        let content = slurp(&self.tx_history_path(&ctx));
-        let history: Vec<TransactionDetails> = if content.is_empty() {
-            vec![]
-        } else {
-            match json::from_slice(&content) {
-                Ok(c) => c,
-                Err(e) => {
-                    ctx.log.log(
-                        "🌋",
-                        &[&"tx_history", &self.ticker().to_string()],
-                        &ERRL!("Error {} on history deserialization, resetting the cache.", e),
-                    );
-                    unwrap!(std::fs::remove_file(&self.tx_history_path(&ctx)));
-                    vec![]
-                },
-            }
+    /// Loads existing tx history from the SQLite database, returns empty vector if the history can't be loaded.
+    /// Imports the legacy tx history file first if it's present.
+    fn load_history_from_db(&self, ctx: &MmArc) -> Vec<TransactionDetails> {
+        let conn = match ctx.sqlite_connection.as_option() {
+            Some(conn) => conn.lock().unwrap(),
+            None => {
+                log!("SQLite connection is not initialized, can't load " (self.ticker()) " tx history");
+                return vec![];
+            },
         };
-        history
+        let my_address = self.my_address().unwrap_or_default();
+        if let Err(e) = import_tx_history_file(ctx, &conn, self.ticker(), &my_address, &self.tx_history_path(ctx)) {
+            log!("Error " (e) " on " (self.ticker()) " tx history file import");
+        }
+        match load_tx_history(&conn, self.ticker(), &my_address) {
+            Ok(history) => history,
+            Err(e) => {
+                log!("Error " (e) " on " (self.ticker()) " tx history loading");
+                vec![]
+            },
+        }
     }
 
-    fn save_history_to_file(&self, content: &[u8], ctx: &MmArc) {
-        let tmp_file = format!("{}.tmp", self.tx_history_path(&ctx).display());
-        if let Err(e) = std::fs::write(&tmp_file, content) {
-            log!("Error " (e) " writing history to the tmp file " (tmp_file));
+    /// Saves the whole tx history to the SQLite database,
+    /// the stored transactions missing in `txs` are removed, e.g. the reorged ones.
+    fn save_history_to_db(&self, ctx: &MmArc, txs: &[&TransactionDetails]) {
+        let conn = match ctx.sqlite_connection.as_option() {
+            Some(conn) => conn.lock().unwrap(),
+            None => {
+                log!("SQLite connection is not initialized, can't save " (self.ticker()) " tx history");
+                return;
+            },
+        };
+        let my_address = self.my_address().unwrap_or_default();
+        if let Err(e) = replace_tx_history(&conn, self.ticker(), &my_address, txs) {
+            log!("Error " (e) " on " (self.ticker()) " tx history saving");
         }
-        if let Err(e) = std::fs::rename(&tmp_file, self.tx_history_path(&ctx)) {
-            log!("Error " (e) " renaming file " (tmp_file) " to " [self.tx_history_path(&ctx)]);
+    }
+
+    /// Inserts the new or updates the existing transactions of the tx history in the SQLite database keeping the others.
+    fn add_history_to_db(&self, ctx: &MmArc, txs: &[&TransactionDetails]) {
+        let conn = match ctx.sqlite_connection.as_option() {
+            Some(conn) => conn.lock().unwrap(),
+            None => {
+                log!("SQLite connection is not initialized, can't save " (self.ticker()) " tx history");
+                return;
+            },
+        };
+        let my_address = self.my_address().unwrap_or_default();
+        if let Err(e) = upsert_tx_history(&conn, self.ticker(), &my_address, txs) {
+            log!("Error " (e) " on " (self.ticker()) " tx history saving");
         }
     }
 
@@ -557,6 +725,13 @@ pub trait MmCoin: SwapOps + MarketCoinOps + fmt::Debug + Send + Sync + 'static {
         stage: FeeApproxStage,
     ) -> Box<dyn Future<Item = TradeFee, Error = TradePreimageError> + Send>;
 
+    /// Get the max fee of the child-pays-for-parent transaction accelerating the swap spend or refund.
+    /// Returns `None` if the coin doesn't support CPFP.
+    fn get_cpfp_child_fee(
+        &self,
+        stage: FeeApproxStage,
+    ) -> Box<dyn Future<Item = Option<TradeFee>, Error = TradePreimageError> + Send>;
+
     /// Get transaction fee the Taker has to pay to send a `TakerFee` transaction and check if the wallet has sufficient balance to pay the fee.
     fn get_fee_to_send_taker_fee(
         &self,
@@ -581,6 +756,9 @@ pub trait MmCoin: SwapOps + MarketCoinOps + fmt::Debug + Send + Sync + 'static {
 
     /// Get swap contract address if the coin uses it in Atomic Swaps.
     fn swap_contract_address(&self) -> Option<BytesJson>;
+
+    /// Whether the coin is able to send, spend and refund the P2WSH swap payments.
+    fn is_p2wsh_htlc_supported(&self) -> bool;
 }
 
 #[derive(Clone, Debug)]
@@ -827,8 +1005,6 @@ pub async fn lp_coininit(ctx: &MmArc, ticker: &str, req: &Json) -> Result<MmCoin
             "assuming that coin is not supported"
         ));
     }
-    let secret = &*ctx.secp256k1_key_pair().private().secret;
-
     if coins_en["protocol"].is_null() {
         return ERR!(
             r#""protocol" field is missing in coins file. The file format is deprecated, please execute ./mm2 update_config command to convert it or download a new one"#
@@ -836,12 +1012,29 @@ pub async fn lp_coininit(ctx: &MmArc, ticker: &str, req: &Json) -> Result<MmCoin
     }
     let protocol: CoinProtocol = try_s!(json::from_value(coins_en["protocol"].clone()));
 
+    // The secret is not available in the watch-only mode,
+    // the UTXO based coins are signed by the external signer then, see `UtxoCoinBuilder::build_utxo_fields`.
+    let secret = if ctx.is_watch_only() {
+        None
+    } else {
+        // The coin spends from the first receive address of the BIP44 account only in the HD wallet mode.
+        match try_s!(hd_account_from_conf_and_request(ctx, ticker, &coins_en, req)) {
+            Some(hd_account) => Some(try_s!(hd_account.derive_address_key(RECEIVE_CHAIN, 0)).secret()),
+            None => Some(ctx.secp256k1_key_pair().private().secret),
+        }
+    };
+    let secret = secret.as_ref().map(|secret| &secret[..]);
+
     let coin: MmCoinEnum = match &protocol {
         CoinProtocol::UTXO => {
             try_s!(utxo_standard_coin_from_conf_and_request(ctx, ticker, &coins_en, req, secret).await).into()
         },
         CoinProtocol::QTUM => try_s!(qtum_coin_from_conf_and_request(ctx, ticker, &coins_en, req, secret).await).into(),
         CoinProtocol::ETH | CoinProtocol::ERC20 { .. } => {
+            let secret = match secret {
+                Some(secret) => secret,
+                None => return ERR!("{} is not supported in the watch-only mode yet", ticker),
+            };
             try_s!(eth_coin_from_conf_and_request(ctx, ticker, &coins_en, req, secret, protocol).await).into()
         },
         CoinProtocol::QRC20 {
@@ -925,6 +1118,49 @@ pub async fn convert_address(ctx: MmArc, req: Json) -> Result<Response<Vec<u8>>,
     Ok(try_s!(Response::builder().body(body)))
 }
 
+/// Finds the activated UTXO coin that can be used in the HD wallet RPCs.
+async fn find_hd_wallet_coin(ctx: &MmArc, ticker: &str) -> Result<MmCoinEnum, String> {
+    match lp_coinfind(ctx, ticker).await {
+        Ok(Some(coin)) => match coin {
+            MmCoinEnum::UtxoCoin(_) | MmCoinEnum::QtumCoin(_) => Ok(coin),
+            _ => ERR!("HD wallet RPCs are supported for UTXO coins only"),
+        },
+        Ok(None) => ERR!("No such coin: {}", ticker),
+        Err(err) => ERR!("!lp_coinfind({}): {}", ticker, err),
+    }
+}
+
+fn hd_wallet_utxo_fields(coin: &MmCoinEnum) -> &UtxoCoinFields {
+    match coin {
+        MmCoinEnum::UtxoCoin(c) => c.as_ref(),
+        MmCoinEnum::QtumCoin(c) => c.as_ref(),
+        _ => unreachable!("find_hd_wallet_coin returns UTXO coins only"),
+    }
+}
+
+#[derive(Deserialize)]
+struct HDWalletCoinReq {
+    coin: String,
+}
+
+/// Lists the spendable receive address of the coin BIP44 account and the ones discovered by the account scans.
+pub async fn hd_receive_addresses(ctx: MmArc, req: Json) -> Result<Response<Vec<u8>>, String> {
+    let req: HDWalletCoinReq = try_s!(json::from_value(req));
+    let coin = try_s!(find_hd_wallet_coin(&ctx, &req.coin).await);
+    let addresses = try_s!(utxo::hd_wallet::hd_receive_addresses(hd_wallet_utxo_fields(&coin)));
+    let res = try_s!(json::to_vec(&json!({ "result": addresses })));
+    Ok(try_s!(Response::builder().body(res)))
+}
+
+/// Scans the coin BIP44 account up to the gap limit and returns the aggregated balance and transaction history.
+pub async fn hd_account_balance(ctx: MmArc, req: Json) -> Result<Response<Vec<u8>>, String> {
+    let req: HDWalletCoinReq = try_s!(json::from_value(req));
+    let coin = try_s!(find_hd_wallet_coin(&ctx, &req.coin).await);
+    let balance = try_s!(utxo::hd_wallet::scan_hd_account(hd_wallet_utxo_fields(&coin)).await);
+    let res = try_s!(json::to_vec(&json!({ "result": balance })));
+    Ok(try_s!(Response::builder().body(res)))
+}
+
 pub async fn kmd_rewards_info(ctx: MmArc) -> Result<Response<Vec<u8>>, String> {
     let coin = match lp_coinfind(&ctx, "KMD").await {
         Ok(Some(MmCoinEnum::UtxoCoin(t))) => t,
@@ -979,6 +1215,44 @@ pub async fn withdraw(ctx: MmArc, req: Json) -> Result<Response<Vec<u8>>, String
     Ok(try_s!(Response::builder().body(body)))
 }
 
+pub async fn withdraw_batch(ctx: MmArc, req: Json) -> Result<Response<Vec<u8>>, String> {
+    let ticker = try_s!(req["coin"].as_str().ok_or("No 'coin' field")).to_owned();
+    let coin = match lp_coinfind(&ctx, &ticker).await {
+        Ok(Some(t)) => t,
+        Ok(None) => return ERR!("No such coin: {}", ticker),
+        Err(err) => return ERR!("!lp_coinfind({}): {}", ticker, err),
+    };
+    let withdraw_req: WithdrawBatchRequest = try_s!(json::from_value(req));
+    if withdraw_req.recipients.is_empty() {
+        return ERR!("No recipients to withdraw to");
+    }
+    let res = try_s!(coin.withdraw_batch(withdraw_req).compat().await);
+    let body = try_s!(json::to_vec(&res));
+    Ok(try_s!(Response::builder().body(body)))
+}
+
+/// Broadcasts the UTXO transaction of the PSBT returned by `withdraw` and signed externally.
+pub async fn finalize_psbt(ctx: MmArc, req: Json) -> Result<Response<Vec<u8>>, String> {
+    let ticker = try_s!(req["coin"].as_str().ok_or("No 'coin' field")).to_owned();
+    let psbt = try_s!(req["psbt"].as_str().ok_or("No 'psbt' field"));
+    let coin = match lp_coinfind(&ctx, &ticker).await {
+        Ok(Some(coin)) => coin,
+        Ok(None) => return ERR!("No such coin: {}", ticker),
+        Err(err) => return ERR!("!lp_coinfind({}): {}", ticker, err),
+    };
+    let fields = match &coin {
+        MmCoinEnum::UtxoCoin(c) => c.as_ref(),
+        MmCoinEnum::QtumCoin(c) => c.as_ref(),
+        _ => return ERR!("PSBT is supported for UTXO coins only"),
+    };
+    let (tx_hex, tx_hash) = try_s!(utxo::utxo_common::finalize_psbt(fields, psbt).await);
+    let res = try_s!(json::to_vec(&json!({
+        "tx_hex": tx_hex,
+        "tx_hash": tx_hash,
+    })));
+    Ok(try_s!(Response::builder().body(res)))
+}
+
 pub async fn send_raw_transaction(ctx: MmArc, req: Json) -> Result<Response<Vec<u8>>, String> {
     let ticker = try_s!(req["coin"].as_str().ok_or("No 'coin' field")).to_owned();
     let coin = match lp_coinfind(&ctx, &ticker).await {
@@ -1013,49 +1287,63 @@ struct MyTxHistoryRequest {
     #[serde(default = "ten")]
     limit: usize,
     page_number: Option<NonZeroUsize>,
+    #[serde(flatten)]
+    filter: TxHistoryFilter,
 }
 
 /// Returns the transaction history of selected coin. Returns no more than `limit` records (default: 10).
-/// Skips the first records up to from_id (skipping the from_id too).
+/// Skips the first records up to from_id (skipping the from_id too) or up to the `page_number` page.
+/// The records can be filtered by the timestamp range, direction, counterparty address and the absolute "my" balance change.
 /// Transactions are sorted by number of confirmations in ascending order.
 pub fn my_tx_history(ctx: MmArc, req: Json) -> HyRes {
     let request: MyTxHistoryRequest = try_h!(json::from_value(req));
+    try_h!(request.filter.validate());
     // Should remove `block_on` when my_tx_history is async.
     let coin = match block_on(lp_coinfind(&ctx, &request.coin)) {
         Ok(Some(t)) => t,
         Ok(None) => return rpc_err_response(500, &fomat!("No such coin: "(request.coin))),
         Err(err) => return rpc_err_response(500, &fomat!("!lp_coinfind(" (request.coin) "): " (err))),
     };
-    let file_path = coin.tx_history_path(&ctx);
-    let content = slurp(&file_path);
-    let history: Vec<TransactionDetails> = match json::from_slice(&content) {
-        Ok(h) => h,
-        Err(e) => {
-            if !content.is_empty() {
-                log!("Error " (e) " on attempt to deserialize file " (file_path.display()) " content as Vec<TransactionDetails>");
-            }
-            vec![]
-        },
-    };
-    let total_records = history.len();
-    let limit = if request.max { total_records } else { request.limit };
-
-    Box::new(coin.current_block().and_then(move |block_number| {
+    let my_address = coin.my_address().unwrap_or_default();
+    let (history, total_records, limit, skip) = {
+        let conn = try_h!(ctx
+            .sqlite_connection
+            .as_option()
+            .ok_or("SQLite connection is not initialized"))
+        .lock()
+        .unwrap();
+        let total_records = try_h!(count_tx_history(&conn, &request.coin, &my_address, &request.filter));
+        let limit = if request.max { total_records } else { request.limit };
         let skip = match &request.from_id {
             Some(id) => {
-                try_h!(history
-                    .iter()
-                    .position(|item| item.internal_id == *id)
-                    .ok_or(format!("from_id {:02x} is not found", id)))
-                    + 1
+                let position = try_h!(tx_history_position(
+                    &conn,
+                    &request.coin,
+                    &my_address,
+                    &request.filter,
+                    id
+                ));
+                try_h!(position.ok_or(format!("from_id {:02x} is not found", id))) + 1
             },
             None => match request.page_number {
                 Some(page_n) => (page_n.get() - 1) * request.limit,
                 None => 0,
             },
         };
-        let history = history.into_iter().skip(skip).take(limit);
+        let history = try_h!(select_tx_history(
+            &conn,
+            &request.coin,
+            &my_address,
+            &request.filter,
+            skip,
+            Some(limit)
+        ));
+        (history, total_records, limit, skip)
+    };
+
+    Box::new(coin.current_block().and_then(move |block_number| {
         let history: Vec<Json> = history
+            .into_iter()
             .map(|item| {
                 let tx_block = item.block_height;
                 let mut json = unwrap!(json::to_value(item));
@@ -1188,6 +1476,12 @@ pub async fn set_requires_notarization(ctx: MmArc, req: Json) -> Result<Response
 }
 
 pub async fn show_priv_key(ctx: MmArc, req: Json) -> Result<Response<Vec<u8>>, String> {
+    if ctx.conf["disable_show_priv_key"].as_bool().unwrap_or(false) {
+        return ERR!("show_priv_key is disabled by the disable_show_priv_key config option");
+    }
+    if ctx.is_watch_only() {
+        return ERR!("The private keys are not available in the watch-only mode");
+    }
     let ticker = try_s!(req["coin"].as_str().ok_or("No 'coin' field")).to_owned();
     let coin = match lp_coinfind(&ctx, &ticker).await {
         Ok(Some(t)) => t,
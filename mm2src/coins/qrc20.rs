@@ -5,11 +5,11 @@ use crate::utxo::qtum::QtumBasedCoin;
 use crate::utxo::rpc_clients::{ElectrumClient, NativeClient, UnspentInfo, UtxoRpcClientEnum, UtxoRpcClientOps};
 use crate::utxo::utxo_common::{self, big_decimal_from_sat};
 use crate::utxo::{coin_daemon_data_dir, qtum, sign_tx, ActualTxFee, AdditionalTxData, FeePolicy,
-                  GenerateTransactionError, RecentlySpentOutPoints, UtxoCoinBuilder, UtxoCoinFields, UtxoCommonOps,
-                  UtxoTx, VerboseTransactionFrom, UTXO_LOCK};
-use crate::{FeeApproxStage, FoundSwapTxSpend, HistorySyncState, MarketCoinOps, MmCoin, SwapOps, TradeFee,
-            TradePreimageError, TradePreimageValue, TransactionDetails, TransactionEnum, TransactionFut,
-            ValidateAddressResult, WithdrawFee, WithdrawRequest};
+                  GenerateTransactionError, RecentlySpentOutPoints, SigningKey, UtxoCoinBuilder, UtxoCoinFields,
+                  UtxoCommonOps, UtxoTx, VerboseTransactionFrom, UTXO_LOCK};
+use crate::{FeeApproxStage, FoundSwapTxSpend, HistorySyncState, HtlcScriptType, MarketCoinOps, MmCoin, SwapOps,
+            TradeFee, TradePreimageError, TradePreimageValue, TransactionDetails, TransactionEnum, TransactionFut,
+            ValidateAddressResult, WithdrawBatchRequest, WithdrawBatchResponse, WithdrawFee, WithdrawRequest};
 use async_trait::async_trait;
 use bigdecimal::BigDecimal;
 use bitcrypto::{dhash160, sha256};
@@ -63,7 +63,7 @@ struct Qrc20CoinBuilder<'a> {
     ticker: &'a str,
     conf: &'a Json,
     req: &'a Json,
-    priv_key: &'a [u8],
+    priv_key: Option<&'a [u8]>,
     platform: String,
     contract_address: H160,
 }
@@ -74,7 +74,7 @@ impl<'a> Qrc20CoinBuilder<'a> {
         ticker: &'a str,
         conf: &'a Json,
         req: &'a Json,
-        priv_key: &'a [u8],
+        priv_key: Option<&'a [u8]>,
         platform: String,
         contract_address: H160,
     ) -> Qrc20CoinBuilder<'a> {
@@ -123,7 +123,7 @@ impl UtxoCoinBuilder for Qrc20CoinBuilder<'_> {
 
     fn ticker(&self) -> &str { self.ticker }
 
-    fn priv_key(&self) -> &[u8] { self.priv_key }
+    fn priv_key(&self) -> Option<&[u8]> { self.priv_key }
 
     async fn decimals(&self, rpc_client: &UtxoRpcClientEnum) -> Result<u8, String> {
         if let Some(d) = self.conf()["decimals"].as_u64() {
@@ -195,7 +195,7 @@ pub async fn qrc20_coin_from_conf_and_request(
     platform: &str,
     conf: &Json,
     req: &Json,
-    priv_key: &[u8],
+    priv_key: Option<&[u8]>,
     contract_address: H160,
 ) -> Result<Qrc20Coin, String> {
     let builder = Qrc20CoinBuilder::new(ctx, ticker, conf, req, priv_key, platform.to_owned(), contract_address);
@@ -480,6 +480,7 @@ impl UtxoCommonOps for Qrc20Coin {
         outputs: Vec<TransactionOutput>,
         script_data: Script,
         sequence: u32,
+        key_pair: &SigningKey,
     ) -> Result<UtxoTx, String> {
         utxo_common::p2sh_spending_tx(
             &self.utxo,
@@ -488,6 +489,7 @@ impl UtxoCommonOps for Qrc20Coin {
             outputs,
             script_data,
             sequence,
+            key_pair,
         )
     }
 
@@ -558,6 +560,9 @@ impl SwapOps for Qrc20Coin {
         secret_hash: &[u8],
         amount: BigDecimal,
         swap_contract_address: &Option<BytesJson>,
+        _htlc_script_type: HtlcScriptType,
+        _replaceable: bool,
+        _swap_unique_data: &[u8],
     ) -> TransactionFut {
         let taker_addr = try_fus!(self.contract_address_from_raw_pubkey(taker_pub));
         let id = qrc20_swap_id(time_lock, secret_hash);
@@ -581,6 +586,9 @@ impl SwapOps for Qrc20Coin {
         secret_hash: &[u8],
         amount: BigDecimal,
         swap_contract_address: &Option<BytesJson>,
+        _htlc_script_type: HtlcScriptType,
+        _replaceable: bool,
+        _swap_unique_data: &[u8],
     ) -> TransactionFut {
         let maker_addr = try_fus!(self.contract_address_from_raw_pubkey(maker_pub));
         let id = qrc20_swap_id(time_lock, secret_hash);
@@ -604,6 +612,7 @@ impl SwapOps for Qrc20Coin {
         _taker_pub: &[u8],
         secret: &[u8],
         swap_contract_address: &Option<BytesJson>,
+        _swap_unique_data: &[u8],
     ) -> TransactionFut {
         let payment_tx: UtxoTx = try_fus!(deserialize(taker_payment_tx).map_err(|e| ERRL!("{:?}", e)));
         let swap_contract_address = try_fus!(swap_contract_address.try_to_address());
@@ -625,6 +634,7 @@ impl SwapOps for Qrc20Coin {
         _maker_pub: &[u8],
         secret: &[u8],
         swap_contract_address: &Option<BytesJson>,
+        _swap_unique_data: &[u8],
     ) -> TransactionFut {
         let payment_tx: UtxoTx = try_fus!(deserialize(maker_payment_tx).map_err(|e| ERRL!("{:?}", e)));
         let secret = secret.to_vec();
@@ -646,6 +656,7 @@ impl SwapOps for Qrc20Coin {
         _maker_pub: &[u8],
         _secret_hash: &[u8],
         swap_contract_address: &Option<BytesJson>,
+        _swap_unique_data: &[u8],
     ) -> TransactionFut {
         let payment_tx: UtxoTx = try_fus!(deserialize(taker_payment_tx).map_err(|e| ERRL!("{:?}", e)));
         let swap_contract_address = try_fus!(swap_contract_address.try_to_address());
@@ -666,6 +677,7 @@ impl SwapOps for Qrc20Coin {
         _taker_pub: &[u8],
         _secret_hash: &[u8],
         swap_contract_address: &Option<BytesJson>,
+        _swap_unique_data: &[u8],
     ) -> TransactionFut {
         let payment_tx: UtxoTx = try_fus!(deserialize(maker_payment_tx).map_err(|e| ERRL!("{:?}", e)));
         let swap_contract_address = try_fus!(swap_contract_address.try_to_address());
@@ -705,6 +717,8 @@ impl SwapOps for Qrc20Coin {
         secret_hash: &[u8],
         amount: BigDecimal,
         swap_contract_address: &Option<BytesJson>,
+        _htlc_script_type: HtlcScriptType,
+        _swap_unique_data: &[u8],
     ) -> Box<dyn Future<Item = (), Error = String> + Send> {
         let payment_tx: UtxoTx = try_fus!(deserialize(payment_tx).map_err(|e| ERRL!("{:?}", e)));
         let sender = try_fus!(self.contract_address_from_raw_pubkey(maker_pub));
@@ -735,6 +749,8 @@ impl SwapOps for Qrc20Coin {
         secret_hash: &[u8],
         amount: BigDecimal,
         swap_contract_address: &Option<BytesJson>,
+        _htlc_script_type: HtlcScriptType,
+        _swap_unique_data: &[u8],
     ) -> Box<dyn Future<Item = (), Error = String> + Send> {
         let swap_contract_address = try_fus!(swap_contract_address.try_to_address());
         let payment_tx: UtxoTx = try_fus!(deserialize(payment_tx).map_err(|e| ERRL!("{:?}", e)));
@@ -764,6 +780,8 @@ impl SwapOps for Qrc20Coin {
         secret_hash: &[u8],
         search_from_block: u64,
         swap_contract_address: &Option<BytesJson>,
+        _htlc_script_type: HtlcScriptType,
+        _swap_unique_data: &[u8],
     ) -> Box<dyn Future<Item = Option<TransactionEnum>, Error = String> + Send> {
         let swap_id = qrc20_swap_id(time_lock, secret_hash);
         let swap_contract_address = try_fus!(swap_contract_address.try_to_address());
@@ -785,6 +803,7 @@ impl SwapOps for Qrc20Coin {
         tx: &[u8],
         search_from_block: u64,
         _swap_contract_address: &Option<BytesJson>,
+        _swap_unique_data: &[u8],
     ) -> Result<Option<FoundSwapTxSpend>, String> {
         let tx: UtxoTx = try_s!(deserialize(tx).map_err(|e| ERRL!("{:?}", e)));
 
@@ -801,6 +820,7 @@ impl SwapOps for Qrc20Coin {
         tx: &[u8],
         search_from_block: u64,
         _swap_contract_address: &Option<BytesJson>,
+        _swap_unique_data: &[u8],
     ) -> Result<Option<FoundSwapTxSpend>, String> {
         let tx: UtxoTx = try_s!(deserialize(tx).map_err(|e| ERRL!("{:?}", e)));
 
@@ -812,6 +832,26 @@ impl SwapOps for Qrc20Coin {
     fn extract_secret(&self, secret_hash: &[u8], spend_tx: &[u8]) -> Result<Vec<u8>, String> {
         self.extract_secret_impl(secret_hash, spend_tx)
     }
+
+    /// Unlike the UTXO coins, the swap contract checks `msg.sender` against the parties addresses,
+    /// and the contract calls are sent from the account UTXOs, so the account key is used.
+    fn derive_htlc_pubkey(&self, _swap_unique_data: &[u8]) -> Result<Vec<u8>, String> {
+        utxo_common::derive_htlc_pubkey(&self.utxo, &[])
+    }
+
+    fn bump_payment_fee(&self, _payment_tx: &[u8]) -> TransactionFut {
+        Box::new(futures01::future::err(ERRL!(
+            "{} doesn't support replacing the payment transaction",
+            self.ticker()
+        )))
+    }
+
+    fn send_cpfp_child(&self, _parent_tx: &[u8]) -> TransactionFut {
+        Box::new(futures01::future::err(ERRL!(
+            "{} doesn't support child-pays-for-parent transactions",
+            self.ticker()
+        )))
+    }
 }
 
 impl MarketCoinOps for Qrc20Coin {
@@ -905,6 +945,15 @@ impl MmCoin for Qrc20Coin {
         Box::new(qrc20_withdraw(self.clone(), req).boxed().compat())
     }
 
+    fn withdraw_batch(
+        &self,
+        _req: WithdrawBatchRequest,
+    ) -> Box<dyn Future<Item = WithdrawBatchResponse, Error = String> + Send> {
+        Box::new(futures01::future::err(ERRL!(
+            "QRC20 coins do not support batch withdraw"
+        )))
+    }
+
     fn decimals(&self) -> u8 { utxo_common::decimals(&self.utxo) }
 
     fn convert_to_address(&self, from: &str, to_address_format: Json) -> Result<String, String> {
@@ -928,6 +977,7 @@ impl MmCoin for Qrc20Coin {
             Ok(TradeFee {
                 coin: selfi.platform.clone(),
                 amount: big_decimal_from_sat(fee as i64, selfi.utxo.decimals).into(),
+                fee_details: None,
             })
         };
         Box::new(fut.boxed().compat())
@@ -996,6 +1046,7 @@ impl MmCoin for Qrc20Coin {
             Ok(TradeFee {
                 coin: selfi.platform.clone(),
                 amount: total_fee.into(),
+                fee_details: None,
             })
         };
         Box::new(fut.boxed().compat())
@@ -1026,11 +1077,19 @@ impl MmCoin for Qrc20Coin {
             Ok(TradeFee {
                 coin: selfi.platform.clone(),
                 amount: total_fee.into(),
+                fee_details: None,
             })
         };
         Box::new(fut.boxed().compat())
     }
 
+    fn get_cpfp_child_fee(
+        &self,
+        _stage: FeeApproxStage,
+    ) -> Box<dyn Future<Item = Option<TradeFee>, Error = TradePreimageError> + Send> {
+        Box::new(futures01::future::ok(None))
+    }
+
     fn get_fee_to_send_taker_fee(
         &self,
         dex_fee_amount: BigDecimal,
@@ -1057,6 +1116,7 @@ impl MmCoin for Qrc20Coin {
             Ok(TradeFee {
                 coin: selfi.platform.clone(),
                 amount: total_fee.into(),
+                fee_details: None,
             })
         };
         Box::new(fut.boxed().compat())
@@ -1082,6 +1142,8 @@ impl MmCoin for Qrc20Coin {
     fn swap_contract_address(&self) -> Option<BytesJson> {
         Some(BytesJson::from(self.swap_contract_address.0.as_ref()))
     }
+
+    fn is_p2wsh_htlc_supported(&self) -> bool { false }
 }
 
 pub fn qrc20_swap_id(time_lock: u32, secret_hash: &[u8]) -> Vec<u8> {
@@ -1108,6 +1170,12 @@ pub struct Qrc20FeeDetails {
 }
 
 async fn qrc20_withdraw(coin: Qrc20Coin, req: WithdrawRequest) -> Result<TransactionDetails, String> {
+    if req.has_coin_control() {
+        return ERR!("{} doesn't support the coin control", coin.ticker());
+    }
+    if req.psbt {
+        return ERR!("{} doesn't support PSBT", coin.ticker());
+    }
     let to_addr = try_s!(UtxoAddress::from_str(&req.to));
     let conf = &coin.utxo.conf;
     let is_p2pkh = to_addr.prefix == conf.pub_addr_prefix && to_addr.t_addr_prefix == conf.pub_t_addr_prefix;
@@ -1197,6 +1265,7 @@ async fn qrc20_withdraw(coin: Qrc20Coin, req: WithdrawRequest) -> Result<Transac
         coin: conf.ticker.clone(),
         internal_id: vec![].into(),
         timestamp: now_ms() / 1000,
+        psbt: None,
     })
 }
 
@@ -9,7 +9,6 @@ use common::mm_metrics::MetricsArc;
 use futures01::Future as Future01;
 use itertools::Itertools;
 use script_pubkey::{extract_contract_call_from_script, extract_gas_from_script, ExtractGasEnum};
-use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::io::Cursor;
 use std::thread;
@@ -81,7 +80,7 @@ enum ProcessCachedTransferMapResult {
 impl Qrc20Coin {
     pub fn history_loop(&self, ctx: MmArc) {
         let mut my_balance: Option<BigDecimal> = None;
-        let mut history_map = self.try_load_history_from_file(&ctx);
+        let mut history_map = self.try_load_history_from_db(&ctx);
 
         let mut success_iteration = 0i32;
         loop {
@@ -167,20 +166,13 @@ impl Qrc20Coin {
             }
 
             // `history_map` has been updated.
-            let mut to_write: Vec<&TransactionDetails> = history_map
+            let to_write: Vec<&TransactionDetails> = history_map
                 .iter()
                 .map(|(_, value)| value)
                 .flatten()
                 .map(|(_tx_id, tx)| tx)
                 .collect();
-            to_write.sort_unstable_by(|a, b| {
-                match sort_newest_to_oldest(a.block_height, b.block_height) {
-                    // do not reverse `transfer` events in one transaction
-                    Ordering::Equal => a.internal_id.cmp(&b.internal_id),
-                    ord => ord,
-                }
-            });
-            self.save_history_to_file(&unwrap!(json::to_vec(&to_write)), &ctx);
+            self.save_history_to_db(&ctx, &to_write);
         }
     }
 
@@ -503,8 +495,8 @@ impl Qrc20Coin {
         updated
     }
 
-    fn try_load_history_from_file(&self, ctx: &MmArc) -> HistoryMapByHash {
-        let history = self.load_history_from_file(&ctx);
+    fn try_load_history_from_db(&self, ctx: &MmArc) -> HistoryMapByHash {
+        let history = self.load_history_from_db(&ctx);
         let mut history_map: HistoryMapByHash = HashMap::default();
 
         for tx in history {
@@ -514,7 +506,7 @@ impl Qrc20Coin {
                     ctx.log.log(
                         "😟",
                         &[&"tx_history", &self.utxo.conf.ticker],
-                        &ERRL!("Error {:?} on load history from file", e),
+                        &ERRL!("Error {:?} on load history from DB", e),
                     );
                     return HistoryMapByHash::default();
                 },
@@ -524,7 +516,7 @@ impl Qrc20Coin {
                 ctx.log.log(
                     "😟",
                     &[&"tx_history", &self.utxo.conf.ticker],
-                    &ERRL!("History file contains entries with the same 'internal_id'"),
+                    &ERRL!("History contains entries with the same 'internal_id'"),
                 );
                 return HistoryMapByHash::default();
             }
@@ -738,17 +730,6 @@ fn is_transferred_to_contract(script_pubkey: &Script) -> bool {
     }
 }
 
-fn sort_newest_to_oldest(x_height: u64, y_height: u64) -> Ordering {
-    // the transactions with block_height == 0 are the most recent
-    if x_height == 0 {
-        Ordering::Less
-    } else if y_height == 0 {
-        Ordering::Greater
-    } else {
-        y_height.cmp(&x_height)
-    }
-}
-
 fn is_transfer_event_log(log: &LogEntry) -> bool {
     match log.topics.first() {
         Some(first_topic) => first_topic == QRC20_TRANSFER_TOPIC,
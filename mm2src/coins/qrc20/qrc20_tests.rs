@@ -35,7 +35,7 @@ pub fn qrc20_coin_for_test(priv_key: &[u8]) -> (MmArc, Qrc20Coin) {
         "QTUM",
         &conf,
         &req,
-        priv_key,
+        Some(priv_key),
         contract_address
     )));
     (ctx, coin)
@@ -76,6 +76,7 @@ fn test_withdraw_impl_fee_details() {
             gas_limit: 2_500_000,
             gas_price: 40,
         }),
+        ..Default::default()
     };
     let tx_details = unwrap!(coin.withdraw(withdraw_req).wait());
 
@@ -119,7 +120,9 @@ fn test_validate_maker_payment() {
             &maker_pub,
             secret_hash,
             amount.clone(),
-            &coin.swap_contract_address()
+            &coin.swap_contract_address(),
+            HtlcScriptType::P2SH,
+            &[],
         )
         .wait());
 
@@ -131,7 +134,9 @@ fn test_validate_maker_payment() {
             &maker_pub_dif,
             secret_hash,
             amount.clone(),
-            &coin.swap_contract_address()
+            &coin.swap_contract_address(),
+            HtlcScriptType::P2SH,
+            &[],
         )
         .wait()
         .err());
@@ -148,7 +153,9 @@ fn test_validate_maker_payment() {
             &maker_pub,
             secret_hash,
             amount_dif,
-            &coin.swap_contract_address()
+            &coin.swap_contract_address(),
+            HtlcScriptType::P2SH,
+            &[],
         )
         .wait()
         .err());
@@ -163,7 +170,9 @@ fn test_validate_maker_payment() {
             &maker_pub,
             secret_hash_dif,
             amount.clone(),
-            &coin.swap_contract_address()
+            &coin.swap_contract_address(),
+            HtlcScriptType::P2SH,
+            &[],
         )
         .wait()
         .err());
@@ -178,7 +187,9 @@ fn test_validate_maker_payment() {
             &maker_pub,
             secret_hash,
             amount,
-            &coin.swap_contract_address()
+            &coin.swap_contract_address(),
+            HtlcScriptType::P2SH,
+            &[],
         )
         .wait()
         .err());
@@ -463,6 +474,7 @@ fn test_transfer_details_by_hash() {
         )
         .unwrap()
         .into(),
+        psbt: None,
     };
     assert_eq!(actual, expected);
 
@@ -485,6 +497,7 @@ fn test_transfer_details_by_hash() {
         )
         .unwrap()
         .into(),
+        psbt: None,
     };
     assert_eq!(actual, expected);
 
@@ -507,6 +520,7 @@ fn test_transfer_details_by_hash() {
         )
         .unwrap()
         .into(),
+        psbt: None,
     };
     assert_eq!(actual, expected);
 
@@ -529,6 +543,7 @@ fn test_transfer_details_by_hash() {
         )
         .unwrap()
         .into(),
+        psbt: None,
     };
     assert_eq!(actual, expected);
 
@@ -551,6 +566,7 @@ fn test_transfer_details_by_hash() {
         )
         .unwrap()
         .into(),
+        psbt: None,
     };
     assert_eq!(actual, expected);
     assert!(it.next().is_none());
@@ -573,6 +589,7 @@ fn test_get_trade_fee() {
     let expected = TradeFee {
         coin: "QTUM".into(),
         amount: expected_trade_fee_amount.into(),
+        fee_details: None,
     };
     assert_eq!(actual_trade_fee, expected);
 }
@@ -606,6 +623,7 @@ fn test_sender_trade_preimage_zero_allowance() {
     let expected = TradeFee {
         coin: "QTUM".to_owned(),
         amount: (erc20_payment_fee_with_one_approve + sender_refund_fee).into(),
+        fee_details: None,
     };
     assert_eq!(actual, expected);
 }
@@ -642,6 +660,7 @@ fn test_sender_trade_preimage_with_allowance() {
     let expected = TradeFee {
         coin: "QTUM".to_owned(),
         amount: (erc20_payment_fee_without_approve + sender_refund_fee.clone()).into(),
+        fee_details: None,
     };
     assert_eq!(actual, expected);
 
@@ -653,6 +672,7 @@ fn test_sender_trade_preimage_with_allowance() {
     let expected = TradeFee {
         coin: "QTUM".to_owned(),
         amount: (erc20_payment_fee_with_two_approves + sender_refund_fee).into(),
+        fee_details: None,
     };
     assert_eq!(actual, expected);
 }
@@ -679,6 +699,7 @@ fn test_receiver_trade_preimage() {
     let expected = TradeFee {
         coin: "QTUM".to_owned(),
         amount: expected_receiver_fee.into(),
+        fee_details: None,
     };
     assert_eq!(actual, expected);
 }
@@ -707,6 +728,7 @@ fn test_taker_fee_tx_fee() {
     let expected = TradeFee {
         coin: "QTUM".to_owned(),
         amount: expected_receiver_fee.into(),
+        fee_details: None,
     };
     assert_eq!(actual, expected);
 }
@@ -742,7 +764,7 @@ fn test_coin_from_conf_without_decimals() {
         "QTUM",
         &conf,
         &req,
-        &priv_key,
+        Some(&priv_key),
         contract_address
     )));
 
@@ -816,6 +838,8 @@ fn test_validate_maker_payment_malicious() {
             secret_hash,
             amount,
             &coin.swap_contract_address(),
+            HtlcScriptType::P2SH,
+            &[],
         )
         .wait()
         .err()
@@ -1,7 +1,7 @@
-use super::{HistorySyncState, MarketCoinOps, MmCoin, SwapOps, TradeFee, TransactionDetails, TransactionEnum,
-            TransactionFut};
+use super::{HistorySyncState, HtlcScriptType, MarketCoinOps, MmCoin, SwapOps, TradeFee, TransactionDetails,
+            TransactionEnum, TransactionFut};
 use crate::{FeeApproxStage, FoundSwapTxSpend, TradePreimageError, TradePreimageValue, ValidateAddressResult,
-            WithdrawRequest};
+            WithdrawBatchRequest, WithdrawBatchResponse, WithdrawRequest};
 use bigdecimal::BigDecimal;
 use common::mm_ctx::MmArc;
 use futures01::Future;
@@ -72,6 +72,9 @@ impl SwapOps for TestCoin {
         secret_hash: &[u8],
         amount: BigDecimal,
         swap_contract_address: &Option<BytesJson>,
+        htlc_script_type: HtlcScriptType,
+        replaceable: bool,
+        swap_unique_data: &[u8],
     ) -> TransactionFut {
         unimplemented!()
     }
@@ -83,6 +86,9 @@ impl SwapOps for TestCoin {
         secret_hash: &[u8],
         amount: BigDecimal,
         swap_contract_address: &Option<BytesJson>,
+        htlc_script_type: HtlcScriptType,
+        replaceable: bool,
+        swap_unique_data: &[u8],
     ) -> TransactionFut {
         unimplemented!()
     }
@@ -94,6 +100,7 @@ impl SwapOps for TestCoin {
         taker_pub: &[u8],
         secret: &[u8],
         swap_contract_address: &Option<BytesJson>,
+        swap_unique_data: &[u8],
     ) -> TransactionFut {
         unimplemented!()
     }
@@ -105,6 +112,7 @@ impl SwapOps for TestCoin {
         maker_pub: &[u8],
         secret: &[u8],
         swap_contract_address: &Option<BytesJson>,
+        swap_unique_data: &[u8],
     ) -> TransactionFut {
         unimplemented!()
     }
@@ -116,6 +124,7 @@ impl SwapOps for TestCoin {
         maker_pub: &[u8],
         secret_hash: &[u8],
         swap_contract_address: &Option<BytesJson>,
+        swap_unique_data: &[u8],
     ) -> TransactionFut {
         unimplemented!()
     }
@@ -127,6 +136,7 @@ impl SwapOps for TestCoin {
         taker_pub: &[u8],
         secret_hash: &[u8],
         swap_contract_address: &Option<BytesJson>,
+        swap_unique_data: &[u8],
     ) -> TransactionFut {
         unimplemented!()
     }
@@ -148,6 +158,8 @@ impl SwapOps for TestCoin {
         priv_bn_hash: &[u8],
         amount: BigDecimal,
         swap_contract_address: &Option<BytesJson>,
+        htlc_script_type: HtlcScriptType,
+        swap_unique_data: &[u8],
     ) -> Box<dyn Future<Item = (), Error = String> + Send> {
         unimplemented!()
     }
@@ -160,6 +172,8 @@ impl SwapOps for TestCoin {
         priv_bn_hash: &[u8],
         amount: BigDecimal,
         swap_contract_address: &Option<BytesJson>,
+        htlc_script_type: HtlcScriptType,
+        swap_unique_data: &[u8],
     ) -> Box<dyn Future<Item = (), Error = String> + Send> {
         unimplemented!()
     }
@@ -171,6 +185,8 @@ impl SwapOps for TestCoin {
         secret_hash: &[u8],
         search_from_block: u64,
         swap_contract_address: &Option<BytesJson>,
+        htlc_script_type: HtlcScriptType,
+        swap_unique_data: &[u8],
     ) -> Box<dyn Future<Item = Option<TransactionEnum>, Error = String> + Send> {
         unimplemented!()
     }
@@ -183,6 +199,7 @@ impl SwapOps for TestCoin {
         tx: &[u8],
         search_from_block: u64,
         swap_contract_address: &Option<BytesJson>,
+        swap_unique_data: &[u8],
     ) -> Result<Option<FoundSwapTxSpend>, String> {
         unimplemented!()
     }
@@ -195,11 +212,18 @@ impl SwapOps for TestCoin {
         tx: &[u8],
         search_from_block: u64,
         swap_contract_address: &Option<BytesJson>,
+        swap_unique_data: &[u8],
     ) -> Result<Option<FoundSwapTxSpend>, String> {
         unimplemented!()
     }
 
     fn extract_secret(&self, secret_hash: &[u8], spend_tx: &[u8]) -> Result<Vec<u8>, String> { unimplemented!() }
+
+    fn derive_htlc_pubkey(&self, swap_unique_data: &[u8]) -> Result<Vec<u8>, String> { unimplemented!() }
+
+    fn bump_payment_fee(&self, payment_tx: &[u8]) -> TransactionFut { unimplemented!() }
+
+    fn send_cpfp_child(&self, parent_tx: &[u8]) -> TransactionFut { unimplemented!() }
 }
 
 #[mockable]
@@ -213,6 +237,13 @@ impl MmCoin for TestCoin {
         unimplemented!()
     }
 
+    fn withdraw_batch(
+        &self,
+        req: WithdrawBatchRequest,
+    ) -> Box<dyn Future<Item = WithdrawBatchResponse, Error = String> + Send> {
+        unimplemented!()
+    }
+
     fn decimals(&self) -> u8 { unimplemented!() }
 
     fn convert_to_address(&self, from: &str, to_address_format: Json) -> Result<String, String> { unimplemented!() }
@@ -241,6 +272,13 @@ impl MmCoin for TestCoin {
         unimplemented!()
     }
 
+    fn get_cpfp_child_fee(
+        &self,
+        stage: FeeApproxStage,
+    ) -> Box<dyn Future<Item = Option<TradeFee>, Error = TradePreimageError> + Send> {
+        unimplemented!()
+    }
+
     fn get_fee_to_send_taker_fee(
         &self,
         dex_fee_amount: BigDecimal,
@@ -260,4 +298,6 @@ impl MmCoin for TestCoin {
     fn my_unspendable_balance(&self) -> Box<dyn Future<Item = BigDecimal, Error = String> + Send> { unimplemented!() }
 
     fn swap_contract_address(&self) -> Option<BytesJson> { unimplemented!() }
+
+    fn is_p2wsh_htlc_supported(&self) -> bool { unimplemented!() }
 }
@@ -0,0 +1,520 @@
+//! The transaction history stored in the `tx_history` table of the SQLite database.
+//!
+//! Every record keeps the whole `TransactionDetails` as JSON along with the columns used for filtering and sorting.
+//! The absolute "my" balance change is stored as the zero-padded integer in the units of `10^-AMOUNT_SCALE`,
+//! so the amounts are compared exactly as the strings.
+//! The history was kept in the `DB/<address>/TRANSACTIONS/<coin>_<my_address>.json` files previously,
+//! such a file is imported to the table once it's found and removed then.
+
+use super::TransactionDetails;
+use bigdecimal::BigDecimal;
+use common::mm_ctx::MmArc;
+use common::rusqlite::types::Type;
+use common::rusqlite::{Connection, Error as SqlError, OptionalExtension, Result as SqlResult, ToSql};
+use gstuff::slurp;
+use rpc::v1::types::Bytes as BytesJson;
+use serde_json::{self as json};
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::path::Path;
+
+/// The scale of the stored amounts, no coin has more decimals.
+const AMOUNT_SCALE: i64 = 18;
+/// The number of digits of the stored amounts.
+const AMOUNT_WIDTH: usize = 60;
+
+pub const CREATE_TX_HISTORY_TABLE: &str = "CREATE TABLE IF NOT EXISTS tx_history (
+    id INTEGER NOT NULL PRIMARY KEY,
+    coin VARCHAR(255) NOT NULL,
+    my_address VARCHAR(255) NOT NULL,
+    internal_id VARCHAR(255) NOT NULL,
+    tx_hash VARCHAR(255) NOT NULL,
+    block_height INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL,
+    my_balance_change_sign INTEGER NOT NULL,
+    my_balance_change_abs VARCHAR(60) NOT NULL,
+    addresses TEXT NOT NULL,
+    details_json TEXT NOT NULL,
+    UNIQUE (coin, my_address, internal_id)
+);";
+
+pub const ADD_TX_HISTORY_HEIGHT_INDEX: &str =
+    "CREATE INDEX tx_history_height_index ON tx_history (coin, my_address, block_height);";
+
+pub const ADD_TX_HISTORY_TIMESTAMP_INDEX: &str =
+    "CREATE INDEX tx_history_timestamp_index ON tx_history (coin, my_address, timestamp);";
+
+const UPSERT_TX: &str = "INSERT INTO tx_history (
+    coin,
+    my_address,
+    internal_id,
+    tx_hash,
+    block_height,
+    timestamp,
+    my_balance_change_sign,
+    my_balance_change_abs,
+    addresses,
+    details_json
+) VALUES (
+    :coin,
+    :my_address,
+    :internal_id,
+    :tx_hash,
+    :block_height,
+    :timestamp,
+    :my_balance_change_sign,
+    :my_balance_change_abs,
+    :addresses,
+    :details_json
+)
+ON CONFLICT (coin, my_address, internal_id) DO UPDATE SET
+    tx_hash = excluded.tx_hash,
+    block_height = excluded.block_height,
+    timestamp = excluded.timestamp,
+    my_balance_change_sign = excluded.my_balance_change_sign,
+    my_balance_change_abs = excluded.my_balance_change_abs,
+    addresses = excluded.addresses,
+    details_json = excluded.details_json;";
+
+const SELECT_INTERNAL_IDS: &str = "SELECT internal_id FROM tx_history WHERE coin = :coin AND my_address = :my_address;";
+
+const DELETE_TX: &str =
+    "DELETE FROM tx_history WHERE coin = :coin AND my_address = :my_address AND internal_id = :internal_id;";
+
+/// The unconfirmed transactions (`block_height == 0`) are the most recent, so they go first.
+const ORDER_BY_NEWEST: &str = "ORDER BY block_height = 0 DESC, block_height DESC, internal_id ASC";
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TxHistoryDirection {
+    /// The transactions increasing "my" balance.
+    Incoming,
+    /// The transactions decreasing "my" balance.
+    Outgoing,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct TxHistoryFilter {
+    /// The inclusive lower bound of the transaction timestamp.
+    #[serde(default)]
+    pub from_timestamp: Option<u64>,
+    /// The exclusive upper bound of the transaction timestamp.
+    #[serde(default)]
+    pub to_timestamp: Option<u64>,
+    #[serde(default)]
+    pub direction: Option<TxHistoryDirection>,
+    /// The address the coins are sent from or to.
+    #[serde(default)]
+    pub address: Option<String>,
+    /// The inclusive lower bound of the absolute "my" balance change.
+    #[serde(default)]
+    pub min_amount: Option<BigDecimal>,
+    /// The inclusive upper bound of the absolute "my" balance change.
+    #[serde(default)]
+    pub max_amount: Option<BigDecimal>,
+}
+
+impl TxHistoryFilter {
+    pub fn validate(&self) -> Result<(), String> {
+        let max_amount = BigDecimal::new(1.into(), -((AMOUNT_WIDTH as i64) - AMOUNT_SCALE));
+        for amount in self.min_amount.iter().chain(self.max_amount.iter()) {
+            if *amount < BigDecimal::from(0) || *amount >= max_amount {
+                return ERR!(
+                    "The amount {} must be non-negative and less than {}",
+                    amount,
+                    max_amount
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the zero-padded absolute `amount` in the units of `10^-AMOUNT_SCALE`,
+/// the fractional units are rounded down or up if `round_up` is set.
+fn amount_units(amount: &BigDecimal, round_up: bool) -> String {
+    let amount = amount.abs();
+    let mut units = amount.with_scale(AMOUNT_SCALE);
+    if round_up && units < amount {
+        units = (units + BigDecimal::new(1.into(), AMOUNT_SCALE)).with_scale(AMOUNT_SCALE);
+    }
+    let (units, _scale) = units.as_bigint_and_exponent();
+    format!("{:0>width$}", units.to_string(), width = AMOUNT_WIDTH)
+}
+
+fn amount_sign(amount: &BigDecimal) -> i64 {
+    let zero = BigDecimal::from(0);
+    if *amount > zero {
+        1
+    } else if *amount < zero {
+        -1
+    } else {
+        0
+    }
+}
+
+/// The addresses are joined with the leading and trailing commas so every address can be matched exactly
+/// by `instr(addresses, ',' || :address || ',')`.
+fn joined_addresses(tx: &TransactionDetails) -> String {
+    let mut addresses = String::from(",");
+    for address in tx.from.iter().chain(tx.to.iter()) {
+        addresses.push_str(address);
+        addresses.push(',');
+    }
+    addresses
+}
+
+fn sql_u64(value: u64) -> i64 { i64::try_from(value).unwrap_or(i64::MAX) }
+
+/// Returns the `WHERE` clause and its named params selecting the `coin` history of `my_address` matching the filter.
+fn where_clause(
+    coin: &str,
+    my_address: &str,
+    filter: &TxHistoryFilter,
+) -> (String, Vec<(&'static str, Box<dyn ToSql>)>) {
+    let mut conditions = vec!["coin = :coin", "my_address = :my_address"];
+    let mut params: Vec<(&'static str, Box<dyn ToSql>)> = vec![
+        (":coin", Box::new(coin.to_owned())),
+        (":my_address", Box::new(my_address.to_owned())),
+    ];
+    if let Some(from_timestamp) = filter.from_timestamp {
+        conditions.push("timestamp >= :from_timestamp");
+        params.push((":from_timestamp", Box::new(sql_u64(from_timestamp))));
+    }
+    if let Some(to_timestamp) = filter.to_timestamp {
+        conditions.push("timestamp < :to_timestamp");
+        params.push((":to_timestamp", Box::new(sql_u64(to_timestamp))));
+    }
+    match filter.direction {
+        Some(TxHistoryDirection::Incoming) => conditions.push("my_balance_change_sign > 0"),
+        Some(TxHistoryDirection::Outgoing) => conditions.push("my_balance_change_sign < 0"),
+        None => (),
+    }
+    if let Some(ref address) = filter.address {
+        conditions.push("instr(addresses, ',' || :address || ',') > 0");
+        params.push((":address", Box::new(address.clone())));
+    }
+    // the stored amounts have no more than `AMOUNT_SCALE` decimals, so the rounding keeps the bounds exact
+    if let Some(ref min_amount) = filter.min_amount {
+        conditions.push("my_balance_change_abs >= :min_amount");
+        params.push((":min_amount", Box::new(amount_units(min_amount, true))));
+    }
+    if let Some(ref max_amount) = filter.max_amount {
+        conditions.push("my_balance_change_abs <= :max_amount");
+        params.push((":max_amount", Box::new(amount_units(max_amount, false))));
+    }
+    (format!("WHERE {}", conditions.join(" AND ")), params)
+}
+
+fn params_refs<'a>(params: &'a [(&'static str, Box<dyn ToSql>)]) -> Vec<(&'static str, &'a dyn ToSql)> {
+    params.iter().map(|(name, value)| (*name, value.as_ref())).collect()
+}
+
+fn tx_from_json(json_str: String) -> SqlResult<TransactionDetails> {
+    json::from_str(&json_str).map_err(|e| SqlError::FromSqlConversionFailure(0, Type::Text, Box::new(e)))
+}
+
+fn upsert_txs(conn: &Connection, coin: &str, my_address: &str, txs: &[&TransactionDetails]) -> SqlResult<()> {
+    let mut stmt = conn.prepare_cached(UPSERT_TX)?;
+    for tx in txs {
+        let details_json = json::to_string(tx).expect("TransactionDetails serialization should not fail");
+        let params: &[(&str, &dyn ToSql)] = &[
+            (":coin", &coin),
+            (":my_address", &my_address),
+            (":internal_id", &format!("{:02x}", tx.internal_id)),
+            (":tx_hash", &format!("{:02x}", tx.tx_hash)),
+            (":block_height", &sql_u64(tx.block_height)),
+            (":timestamp", &sql_u64(tx.timestamp)),
+            (":my_balance_change_sign", &amount_sign(&tx.my_balance_change)),
+            (":my_balance_change_abs", &amount_units(&tx.my_balance_change, false)),
+            (":addresses", &joined_addresses(tx)),
+            (":details_json", &details_json),
+        ];
+        stmt.execute_named(params)?;
+    }
+    Ok(())
+}
+
+/// Inserts the transactions or updates them if they are already stored, the other transactions are kept.
+pub fn upsert_tx_history(
+    conn: &Connection,
+    coin: &str,
+    my_address: &str,
+    txs: &[&TransactionDetails],
+) -> SqlResult<()> {
+    let transaction = conn.unchecked_transaction()?;
+    upsert_txs(&transaction, coin, my_address, txs)?;
+    transaction.commit()
+}
+
+/// Replaces the whole `coin` history of `my_address` by the transactions,
+/// so the stored transactions missing in `txs` (e.g. the reorged ones) are removed.
+pub fn replace_tx_history(
+    conn: &Connection,
+    coin: &str,
+    my_address: &str,
+    txs: &[&TransactionDetails],
+) -> SqlResult<()> {
+    let transaction = conn.unchecked_transaction()?;
+    upsert_txs(&transaction, coin, my_address, txs)?;
+    let keep: HashSet<String> = txs.iter().map(|tx| format!("{:02x}", tx.internal_id)).collect();
+    let stored = {
+        let mut stmt = transaction.prepare(SELECT_INTERNAL_IDS)?;
+        let params: &[(&str, &dyn ToSql)] = &[(":coin", &coin), (":my_address", &my_address)];
+        let rows = stmt.query_map_named(params, |row| row.get::<_, String>(0))?;
+        rows.collect::<SqlResult<Vec<_>>>()?
+    };
+    {
+        let mut stmt = transaction.prepare(DELETE_TX)?;
+        for internal_id in stored.iter().filter(|internal_id| !keep.contains(*internal_id)) {
+            let params: &[(&str, &dyn ToSql)] = &[
+                (":coin", &coin),
+                (":my_address", &my_address),
+                (":internal_id", internal_id),
+            ];
+            stmt.execute_named(params)?;
+        }
+    }
+    transaction.commit()
+}
+
+/// Returns the whole `coin` history of `my_address` sorted from the newest to the oldest transaction.
+pub fn load_tx_history(conn: &Connection, coin: &str, my_address: &str) -> SqlResult<Vec<TransactionDetails>> {
+    select_tx_history(conn, coin, my_address, &TxHistoryFilter::default(), 0, None)
+}
+
+/// Returns the number of the transactions matching the filter.
+pub fn count_tx_history(conn: &Connection, coin: &str, my_address: &str, filter: &TxHistoryFilter) -> SqlResult<usize> {
+    let (where_clause, params) = where_clause(coin, my_address, filter);
+    let sql = format!("SELECT COUNT(id) FROM tx_history {};", where_clause);
+    let count: i64 = conn.query_row_named(&sql, &params_refs(&params), |row| row.get(0))?;
+    Ok(count as usize)
+}
+
+/// Returns the number of the transactions matching the filter preceding the `internal_id` one
+/// or `None` if the transaction is not found.
+pub fn tx_history_position(
+    conn: &Connection,
+    coin: &str,
+    my_address: &str,
+    filter: &TxHistoryFilter,
+    internal_id: &BytesJson,
+) -> SqlResult<Option<usize>> {
+    let (where_clause, mut params) = where_clause(coin, my_address, filter);
+    params.push((":internal_id", Box::new(format!("{:02x}", internal_id))));
+    let sql = format!(
+        "SELECT position FROM (
+            SELECT internal_id, ROW_NUMBER() OVER ({}) - 1 AS position FROM tx_history {}
+        ) WHERE internal_id = :internal_id;",
+        ORDER_BY_NEWEST, where_clause
+    );
+    let position: Option<i64> = conn
+        .query_row_named(&sql, &params_refs(&params), |row| row.get(0))
+        .optional()?;
+    Ok(position.map(|position| position as usize))
+}
+
+/// Returns the transactions matching the filter sorted from the newest to the oldest one,
+/// skipping the first `offset` of them and returning no more than `limit` if it's set.
+pub fn select_tx_history(
+    conn: &Connection,
+    coin: &str,
+    my_address: &str,
+    filter: &TxHistoryFilter,
+    offset: usize,
+    limit: Option<usize>,
+) -> SqlResult<Vec<TransactionDetails>> {
+    let (where_clause, mut params) = where_clause(coin, my_address, filter);
+    // the negative LIMIT means there is no upper bound
+    let limit = limit.map(|limit| limit as i64).unwrap_or(-1);
+    params.push((":limit", Box::new(limit)));
+    params.push((":offset", Box::new(offset as i64)));
+    let sql = format!(
+        "SELECT details_json FROM tx_history {} {} LIMIT :limit OFFSET :offset;",
+        where_clause, ORDER_BY_NEWEST
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map_named(&params_refs(&params), |row| row.get::<_, String>(0))?;
+    let mut txs = Vec::new();
+    for json_str in rows {
+        txs.push(tx_from_json(json_str?)?);
+    }
+    Ok(txs)
+}
+
+/// Imports the legacy JSON history file of the `coin` to the database and removes the file.
+/// The file is removed without importing if it can't be deserialized.
+pub fn import_tx_history_file(
+    ctx: &MmArc,
+    conn: &Connection,
+    coin: &str,
+    my_address: &str,
+    path: &Path,
+) -> Result<(), String> {
+    let content = slurp(&path);
+    if content.is_empty() {
+        return Ok(());
+    }
+    match json::from_slice::<Vec<TransactionDetails>>(&content) {
+        Ok(history) => {
+            let txs: Vec<_> = history.iter().collect();
+            try_s!(upsert_tx_history(conn, coin, my_address, &txs));
+            ctx.log.log(
+                "📂",
+                &[&"tx_history", &coin],
+                &format!("{} transactions are imported from {}", txs.len(), path.display()),
+            );
+        },
+        Err(e) => ctx.log.log(
+            "🌋",
+            &[&"tx_history", &coin],
+            &ERRL!("Error {} on history deserialization, resetting the cache.", e),
+        ),
+    }
+    try_s!(std::fs::remove_file(path));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tx_history_db_tests {
+    use super::*;
+    use common::rusqlite::NO_PARAMS;
+
+    /// Creates the `tx_history` table and its indexes, the statements are run by the migrations otherwise.
+    fn init_tx_history_table(conn: &Connection) -> SqlResult<()> {
+        conn.execute(CREATE_TX_HISTORY_TABLE, NO_PARAMS)?;
+        conn.execute(ADD_TX_HISTORY_HEIGHT_INDEX, NO_PARAMS)?;
+        conn.execute(ADD_TX_HISTORY_TIMESTAMP_INDEX, NO_PARAMS)?;
+        Ok(())
+    }
+
+    fn tx(internal_id: u8, block_height: u64, my_balance_change: &str, other_address: &str) -> TransactionDetails {
+        let my_balance_change: BigDecimal = my_balance_change.parse().unwrap();
+        let (from, to) = if my_balance_change > 0.into() {
+            (vec![other_address.to_owned()], vec!["my_address".to_owned()])
+        } else {
+            (vec!["my_address".to_owned()], vec![other_address.to_owned()])
+        };
+        TransactionDetails {
+            tx_hex: BytesJson::from(vec![internal_id]),
+            tx_hash: BytesJson::from(vec![internal_id]),
+            from,
+            to,
+            total_amount: my_balance_change.abs(),
+            spent_by_me: 0.into(),
+            received_by_me: 0.into(),
+            my_balance_change,
+            block_height,
+            timestamp: 1000 + block_height,
+            fee_details: None,
+            coin: "RICK".into(),
+            internal_id: BytesJson::from(vec![internal_id]),
+            psbt: None,
+        }
+    }
+
+    fn internal_ids(txs: &[TransactionDetails]) -> Vec<u8> { txs.iter().map(|tx| tx.internal_id.0[0]).collect() }
+
+    #[test]
+    fn test_tx_history_filter_and_paging() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_tx_history_table(&conn).unwrap();
+
+        let txs = vec![
+            tx(1, 10, "1.5", "alice"),
+            tx(2, 20, "-0.5", "bob"),
+            tx(3, 0, "-2", "alice"),
+            tx(4, 30, "3", "carol"),
+        ];
+        let refs: Vec<_> = txs.iter().collect();
+        upsert_tx_history(&conn, "RICK", "my_address", &refs).unwrap();
+        // the updated transaction is not duplicated
+        upsert_tx_history(&conn, "RICK", "my_address", &[&tx(3, 40, "-2", "alice")]).unwrap();
+        upsert_tx_history(&conn, "MORTY", "my_address", &[&tx(5, 50, "1", "alice")]).unwrap();
+
+        let history = load_tx_history(&conn, "RICK", "my_address").unwrap();
+        assert_eq!(internal_ids(&history), vec![3, 4, 2, 1]);
+
+        let filter = TxHistoryFilter {
+            direction: Some(TxHistoryDirection::Incoming),
+            ..Default::default()
+        };
+        assert_eq!(count_tx_history(&conn, "RICK", "my_address", &filter).unwrap(), 2);
+
+        let filter = TxHistoryFilter {
+            address: Some("alice".into()),
+            min_amount: Some("1.5".parse().unwrap()),
+            max_amount: Some("2".parse().unwrap()),
+            ..Default::default()
+        };
+        let history = select_tx_history(&conn, "RICK", "my_address", &filter, 0, None).unwrap();
+        assert_eq!(internal_ids(&history), vec![3, 1]);
+
+        // the bounds are exact
+        let filter = TxHistoryFilter {
+            min_amount: Some("1.500000000000000000001".parse().unwrap()),
+            max_amount: Some("2.999999999999999999999".parse().unwrap()),
+            ..Default::default()
+        };
+        let history = select_tx_history(&conn, "RICK", "my_address", &filter, 0, None).unwrap();
+        assert_eq!(internal_ids(&history), vec![3]);
+
+        let filter = TxHistoryFilter {
+            from_timestamp: Some(1020),
+            to_timestamp: Some(1040),
+            ..Default::default()
+        };
+        let history = select_tx_history(&conn, "RICK", "my_address", &filter, 1, Some(1)).unwrap();
+        assert_eq!(internal_ids(&history), vec![2]);
+
+        let position = tx_history_position(&conn, "RICK", "my_address", &filter, &BytesJson::from(vec![2]));
+        assert_eq!(position.unwrap(), Some(1));
+        let position = tx_history_position(&conn, "RICK", "my_address", &filter, &BytesJson::from(vec![1]));
+        assert_eq!(position.unwrap(), None);
+    }
+
+    #[test]
+    fn test_replace_tx_history() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_tx_history_table(&conn).unwrap();
+
+        let txs = vec![tx(1, 10, "1", "alice"), tx(2, 20, "-1", "bob"), tx(3, 0, "2", "carol")];
+        let refs: Vec<_> = txs.iter().collect();
+        replace_tx_history(&conn, "RICK", "my_address", &refs).unwrap();
+        upsert_tx_history(&conn, "MORTY", "my_address", &[&tx(2, 20, "1", "alice")]).unwrap();
+
+        // the transaction 2 is reorged out
+        replace_tx_history(&conn, "RICK", "my_address", &[&txs[0], &tx(3, 30, "2", "carol")]).unwrap();
+        let history = load_tx_history(&conn, "RICK", "my_address").unwrap();
+        assert_eq!(internal_ids(&history), vec![3, 1]);
+        assert_eq!(history[0].block_height, 30);
+        // the other coins are not affected
+        let history = load_tx_history(&conn, "MORTY", "my_address").unwrap();
+        assert_eq!(internal_ids(&history), vec![2]);
+    }
+
+    #[test]
+    fn test_amount_units() {
+        assert_eq!(
+            amount_units(&"-1.5".parse().unwrap(), false),
+            format!("{:0>60}", "1500000000000000000")
+        );
+        assert_eq!(amount_units(&"1e-19".parse().unwrap(), false), format!("{:0>60}", "0"));
+        assert_eq!(amount_units(&"1e-19".parse().unwrap(), true), format!("{:0>60}", "1"));
+        assert_eq!(
+            amount_units(&"1e3".parse().unwrap(), true),
+            format!("{:0>60}", "1000000000000000000000")
+        );
+
+        let filter = TxHistoryFilter {
+            min_amount: Some("-1".parse().unwrap()),
+            ..Default::default()
+        };
+        assert!(filter.validate().is_err());
+        let filter = TxHistoryFilter {
+            max_amount: Some("1e42".parse().unwrap()),
+            ..Default::default()
+        };
+        assert!(filter.validate().is_err());
+    }
+}
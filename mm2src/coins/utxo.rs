@@ -21,8 +21,11 @@
 
 #![cfg_attr(not(feature = "native"), allow(unused_imports))]
 
+pub mod hd_wallet;
+pub mod psbt;
 pub mod qtum;
 pub mod rpc_clients;
+pub mod segwit_address;
 pub mod utxo_common;
 pub mod utxo_standard;
 
@@ -32,8 +35,10 @@ use async_trait::async_trait;
 use base64::{encode_config as base64_encode, URL_SAFE};
 use bigdecimal::BigDecimal;
 pub use bitcrypto::{dhash160, sha256, ChecksumType};
+use chain::constants::SEQUENCE_FINAL;
 use chain::{OutPoint, TransactionInput, TransactionOutput, TxHashAlgo};
 use common::executor::{spawn, Timer};
+use common::external_signer::{der_signature, ExternalSigner};
 use common::jsonrpc_client::JsonRpcError;
 use common::mm_ctx::MmArc;
 use common::mm_metrics::MetricsArc;
@@ -45,15 +50,15 @@ use futures::lock::{Mutex as AsyncMutex, MutexGuard as AsyncMutexGuard};
 use futures::stream::StreamExt;
 use futures01::Future;
 use keys::bytes::Bytes;
-pub use keys::{Address, KeyPair, Private, Public, Secret};
+pub use keys::{Address, AddressHash, KeyPair, Private, Public, Secret, Signature};
 #[cfg(test)] use mocktopus::macros::*;
 use num_traits::ToPrimitive;
 use primitives::hash::{H256, H264, H512};
 use rand::seq::SliceRandom;
 use rpc::v1::types::{Bytes as BytesJson, Transaction as RpcTransaction, H256 as H256Json};
-use script::{Builder, Script, SignatureVersion, TransactionInputSigner};
+use script::{Builder, Opcode, Script, SignatureVersion, TransactionInputSigner};
 use serde_json::{self as json, Value as Json};
-use serialization::serialize;
+use serialization::{deserialize, serialize, serialize_with_flags, SERIALIZE_TRANSACTION_WITNESS};
 use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
 use std::num::NonZeroU64;
@@ -66,12 +71,15 @@ use utxo_common::big_decimal_from_sat;
 
 pub use chain::Transaction as UtxoTx;
 
+use self::hd_wallet::UtxoHDWallet;
 use self::rpc_clients::{ElectrumClient, ElectrumClientImpl, EstimateFeeMethod, EstimateFeeMode, NativeClient,
                         UnspentInfo, UtxoRpcClientEnum};
+use self::segwit_address::SegwitAddress;
 use super::{CoinTransportMetrics, CoinsContext, FeeApproxStage, FoundSwapTxSpend, HistorySyncState, MarketCoinOps,
             MmCoin, RpcClientType, RpcTransportEventHandler, RpcTransportEventHandlerShared, TradeFee,
-            TradePreimageError, Transaction, TransactionDetails, TransactionEnum, TransactionFut, WithdrawFee,
-            WithdrawRequest};
+            TradePreimageError, Transaction, TransactionDetails, TransactionEnum, TransactionFut,
+            WithdrawBatchRecipient, WithdrawBatchRequest, WithdrawBatchResponse, WithdrawFee, WithdrawRequest};
+use crate::hd_wallet::{hd_account_from_conf_and_request, DEFAULT_GAP_LIMIT};
 use crate::utxo::rpc_clients::{ElectrumRpcRequest, NativeClientImpl};
 use crate::utxo::utxo_common::display_address;
 
@@ -82,7 +90,25 @@ const KILO_BYTE: u64 = 1000;
 /// https://bitcoin.stackexchange.com/a/77192
 const MAX_DER_SIGNATURE_LEN: usize = 72;
 const COMPRESSED_PUBKEY_LEN: usize = 33;
+/// The virtual size of P2WPKH input witness: the items count, the signature and the pubkey with their lengths
+/// and the segwit marker and flag bytes, divided by the witness scale factor 4 and rounded up.
+/// https://github.com/bitcoin/bips/blob/master/bip-0141.mediawiki#transaction-size-calculations
+const P2WPKH_WITNESS_VSIZE: usize = 28;
 const P2PKH_OUTPUT_LEN: u64 = 34;
+/// The inputs having sequence less than `SEQUENCE_FINAL - 1` signal that the transaction can be replaced.
+/// https://github.com/bitcoin/bips/blob/master/bip-0125.mediawiki
+const BIP125_REPLACEABLE_SEQUENCE: u32 = SEQUENCE_FINAL - 2;
+/// The replacement transaction must pay for its own bandwidth at least 1 sat/byte above the replaced transaction fee.
+const INCREMENTAL_RELAY_FEE_PER_KB: u64 = 1000;
+/// The size of the child-pays-for-parent transaction spending the parent output and one of my other P2PKH outputs
+/// to the P2PKH output and the change.
+const CPFP_CHILD_TX_SIZE: u64 = 374;
+/// The max size of the data pushed by the standard OP_RETURN output.
+const MAX_OP_RETURN_DATA_LEN: usize = 80;
+/// The size of the transaction version, the inputs and outputs count and the lock time.
+const TX_OVERHEAD_LEN: u64 = 10;
+/// The max number of the branch and bound coin selection steps.
+const BNB_MAX_TRIES: usize = 100_000;
 const MATURE_CONFIRMATIONS_DEFAULT: u32 = 100;
 const UTXO_DUST_AMOUNT: u64 = 1000;
 /// Block count for KMD median time past calculation
@@ -117,7 +143,7 @@ fn get_special_folder_path() -> PathBuf {
 fn get_special_folder_path() -> PathBuf { panic!("!windows") }
 
 impl Transaction for UtxoTx {
-    fn tx_hex(&self) -> Vec<u8> { serialize(self).into() }
+    fn tx_hex(&self) -> Vec<u8> { serialize_with_flags(self, SERIALIZE_TRANSACTION_WITNESS).into() }
 
     fn tx_hash(&self) -> BytesJson { self.hash().reversed().to_vec().into() }
 }
@@ -170,12 +196,34 @@ pub enum UtxoAddressFormat {
     /// https://github.com/bitcoincashorg/bitcoincash.org/blob/master/spec/cashaddr.md
     #[serde(rename = "cashaddress")]
     CashAddress { network: String },
+    /// Native SegWit (P2WPKH) address format encoded in Bech32.
+    /// https://github.com/bitcoin/bips/blob/master/bip-0173.mediawiki
+    #[serde(rename = "segwit")]
+    Segwit { hrp: String },
+}
+
+/// The kind of the script the parsed address pays to.
+/// Note the keys `Address` can't tell a P2PKH address from the P2WPKH one paying to the same pubkey hash.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AddressScriptType {
+    P2PKH,
+    P2SH,
+    P2WPKH,
 }
 
 impl Default for UtxoAddressFormat {
     fn default() -> Self { UtxoAddressFormat::Standard }
 }
 
+impl UtxoAddressFormat {
+    pub fn is_segwit(&self) -> bool {
+        match self {
+            UtxoAddressFormat::Segwit { .. } => true,
+            _ => false,
+        }
+    }
+}
+
 /// The cache of recently send transactions used to track the spent UTXOs and replace them with new outputs
 /// The daemon needs some time to update the listunspent list for address which makes it return already spent UTXOs
 /// This cache helps to prevent UTXO reuse in such cases
@@ -319,10 +367,13 @@ pub struct UtxoCoinConf {
     /// https://github.com/zcash/zips/blob/master/zip-0243.rst
     pub tx_version: i32,
     /// If true - allow coins withdraw to P2SH addresses (Segwit).
-    /// the flag will also affect the address that MM2 generates by default in the future
-    /// will be the Segwit (starting from 3 for BTC case) instead of legacy
+    /// The flag is also required to activate the native SegWit (P2WPKH) address format.
     /// https://en.bitcoin.it/wiki/Segregated_Witness
     pub segwit: bool,
+    /// The human-readable part of the Bech32 addresses, e.g. "bc" for BTC.
+    /// Taken from the "bech32_hrp" config field or from the segwit `address_format`.
+    /// Required to send and spend the P2WSH swap payments.
+    pub bech32_hrp: Option<String>,
     /// Does coin require transactions to be notarized to be considered as confirmed?
     /// https://komodoplatform.com/security-delayed-proof-of-work-dpow/
     pub requires_notarization: AtomicBool,
@@ -375,8 +426,8 @@ pub struct UtxoCoinFields {
     pub dust_amount: u64,
     /// RPC client
     pub rpc_client: UtxoRpcClientEnum,
-    /// ECDSA key pair
-    pub key_pair: KeyPair,
+    /// ECDSA key pair, or the public key and the external signer if the node is started in the watch-only mode
+    pub key_pair: SigningKey,
     /// Lock the mutex when we deal with address utxos
     pub my_address: Address,
     pub history_sync_state: Mutex<HistorySyncState>,
@@ -387,6 +438,9 @@ pub struct UtxoCoinFields {
     /// This cache helps to prevent UTXO reuse in such cases
     pub recently_spent_outpoints: AsyncMutex<RecentlySpentOutPoints>,
     pub tx_hash_algo: TxHashAlgo,
+    /// The BIP44 account addresses if the coin is activated in the HD wallet mode.
+    /// `key_pair` and `my_address` are the first receive address of the account then.
+    pub hd_wallet: Option<UtxoHDWallet>,
 }
 
 #[cfg_attr(test, mockable)]
@@ -444,6 +498,7 @@ pub trait UtxoCommonOps {
         outputs: Vec<TransactionOutput>,
         script_data: Script,
         sequence: u32,
+        key_pair: &SigningKey,
     ) -> Result<UtxoTx, String>;
 
     /// Get transaction outputs available to spend.
@@ -747,7 +802,12 @@ impl<'a> UtxoConfBuilder<'a> {
 
         let wif_prefix = self.wif_prefix();
 
+        let segwit = self.segwit();
         let address_format = try_s!(self.address_format());
+        if address_format.is_segwit() && !segwit {
+            return ERR!("'segwit' must be enabled in the coin config to use the segwit address format");
+        }
+        let bech32_hrp = self.bech32_hrp(&address_format);
 
         let asset_chain = self.asset_chain();
         let tx_version = self.tx_version();
@@ -768,7 +828,6 @@ impl<'a> UtxoConfBuilder<'a> {
         let mature_confirmations = self.mature_confirmations();
 
         let is_pos = self.is_pos();
-        let segwit = self.segwit();
         let force_min_relay_fee = self.conf["force_min_relay_fee"].as_bool().unwrap_or(false);
         let mtp_block_count = self.mtp_block_count();
         let estimate_fee_mode = self.estimate_fee_mode();
@@ -784,6 +843,7 @@ impl<'a> UtxoConfBuilder<'a> {
             pub_t_addr_prefix,
             p2sh_t_addr_prefix,
             segwit,
+            bech32_hrp,
             wif_prefix,
             tx_version,
             address_format,
@@ -845,6 +905,13 @@ impl<'a> UtxoConfBuilder<'a> {
         }
     }
 
+    fn bech32_hrp(&self, address_format: &UtxoAddressFormat) -> Option<String> {
+        match address_format {
+            UtxoAddressFormat::Segwit { hrp } => Some(hrp.clone()),
+            _ => self.conf["bech32_hrp"].as_str().map(|hrp| hrp.to_owned()),
+        }
+    }
+
     fn asset_chain(&self) -> bool { self.conf["asset"].as_str().is_some() }
 
     fn tx_version(&self) -> i32 { self.conf["txversion"].as_i64().unwrap_or(1) as i32 }
@@ -964,25 +1031,41 @@ pub trait UtxoCoinBuilder {
 
     fn ticker(&self) -> &str;
 
-    fn priv_key(&self) -> &[u8];
+    /// The private key of the coin, `None` in the watch-only mode.
+    fn priv_key(&self) -> Option<&[u8]>;
 
     async fn build_utxo_fields(&self) -> Result<UtxoCoinFields, String> {
         let conf = try_s!(UtxoConfBuilder::new(self.conf(), self.req(), self.ticker()).build());
 
-        let private = Private {
-            prefix: conf.wif_prefix,
-            secret: H256::from(self.priv_key()),
-            compressed: true,
-            checksum_type: conf.checksum_type,
+        // The coin is signed by the external signer if the node is watch-only.
+        let key_pair = match (self.ctx().watch_only.as_option(), self.priv_key()) {
+            (Some(watch_only), None) => SigningKey::External {
+                public: watch_only.public.clone(),
+                derivation: Vec::new(),
+                signer: watch_only.signer.clone(),
+            },
+            (None, Some(priv_key)) => {
+                if priv_key.len() != 32 {
+                    return ERR!("Expected the 32 bytes private key, got {} bytes", priv_key.len());
+                }
+                let private = Private {
+                    prefix: conf.wif_prefix,
+                    secret: H256::from(priv_key),
+                    compressed: true,
+                    checksum_type: conf.checksum_type,
+                };
+                try_s!(KeyPair::from_private(private)).into()
+            },
+            (Some(_), Some(_)) => return ERR!("The private key must not be set in the watch-only mode"),
+            (None, None) => return ERR!("The private key is not set"),
         };
-        let key_pair = try_s!(KeyPair::from_private(private));
         let my_address = Address {
             prefix: conf.pub_addr_prefix,
             t_addr_prefix: conf.pub_t_addr_prefix,
             hash: key_pair.public().address_hash(),
             checksum_type: conf.checksum_type,
         };
-        let my_script_pubkey = Builder::build_p2pkh(&my_address.hash).to_bytes();
+        let my_script_pubkey = output_script(&my_address, &conf.address_format).to_bytes();
         let rpc_client = try_s!(self.rpc_client().await);
         let tx_fee = try_s!(self.tx_fee(&rpc_client).await);
         let decimals = try_s!(self.decimals(&rpc_client).await);
@@ -991,8 +1074,8 @@ pub trait UtxoCoinBuilder {
         let initial_history_state = self.initial_history_state();
         let tx_cache_directory = Some(self.ctx().dbdir().join("TX_CACHE"));
         let tx_hash_algo = self.tx_hash_algo();
+        let hd_wallet = try_s!(self.hd_wallet());
 
-        let _my_script_pubkey = Builder::build_p2pkh(&my_address.hash).to_bytes();
         let coin = UtxoCoinFields {
             conf,
             decimals,
@@ -1005,10 +1088,30 @@ pub trait UtxoCoinBuilder {
             recently_spent_outpoints: AsyncMutex::new(RecentlySpentOutPoints::new(my_script_pubkey)),
             tx_fee,
             tx_hash_algo,
+            hd_wallet,
         };
         Ok(coin)
     }
 
+    fn hd_wallet(&self) -> Result<Option<UtxoHDWallet>, String> {
+        let account = match try_s!(hd_account_from_conf_and_request(
+            self.ctx(),
+            self.ticker(),
+            self.conf(),
+            self.req()
+        )) {
+            Some(account) => account,
+            None => return Ok(None),
+        };
+        let gap_limit = self.req()["gap_limit"].as_u64().unwrap_or(DEFAULT_GAP_LIMIT as u64) as u32;
+        let state_path =
+            self.ctx()
+                .dbdir()
+                .join("HD_WALLET")
+                .join(format!("{}_{}.json", self.ticker(), account.account_id));
+        Ok(Some(UtxoHDWallet::new(account, gap_limit, state_path)))
+    }
+
     fn dust_amount(&self) -> u64 { json::from_value(self.conf()["dust"].clone()).unwrap_or(UTXO_DUST_AMOUNT) }
 
     fn network(&self) -> Result<BlockchainNetwork, String> {
@@ -1424,7 +1527,12 @@ where
 
     let utxo = coin.as_ref();
     let rpc_client = &utxo.rpc_client;
-    let mut unspents = try_s!(rpc_client.list_unspent(&utxo.my_address, utxo.decimals).compat().await);
+    let mut unspents = try_s!(
+        rpc_client
+            .list_unspent(&utxo.my_address, &utxo.conf.address_format, utxo.decimals)
+            .compat()
+            .await
+    );
     // list_unspent_ordered() returns ordered from lowest to highest by value unspent outputs.
     // reverse it to reorder from highest to lowest outputs.
     unspents.reverse();
@@ -1485,28 +1593,95 @@ pub fn sat_from_big_decimal(amount: &BigDecimal, decimals: u8) -> Result<u64, St
         ))
 }
 
+/// The key signing the coin transactions.
+pub enum SigningKey {
+    KeyPair(KeyPair),
+    /// The private key is kept by the external signer, see `ExternalSigner`.
+    External {
+        public: Public,
+        /// Selects the key of the signer, empty for the node key.
+        derivation: Vec<u8>,
+        signer: Arc<dyn ExternalSigner>,
+    },
+}
+
+impl std::fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SigningKey::KeyPair(key_pair) => f.debug_tuple("KeyPair").field(key_pair).finish(),
+            SigningKey::External { public, derivation, .. } => f
+                .debug_struct("External")
+                .field("public", public)
+                .field("derivation", derivation)
+                .finish(),
+        }
+    }
+}
+
+impl From<KeyPair> for SigningKey {
+    fn from(key_pair: KeyPair) -> Self { SigningKey::KeyPair(key_pair) }
+}
+
+impl SigningKey {
+    pub fn public(&self) -> &Public {
+        match self {
+            SigningKey::KeyPair(key_pair) => key_pair.public(),
+            SigningKey::External { public, .. } => public,
+        }
+    }
+
+    /// Returns the private key if it's available, i.e. the node isn't watch-only.
+    pub fn private(&self) -> Option<&Private> {
+        match self {
+            SigningKey::KeyPair(key_pair) => Some(key_pair.private()),
+            SigningKey::External { .. } => None,
+        }
+    }
+
+    /// Signs the `message` hash, returns the DER encoded signature.
+    pub fn sign(&self, message: &H256) -> Result<Signature, String> {
+        match self {
+            SigningKey::KeyPair(key_pair) => key_pair.private().sign(message).map_err(|e| ERRL!("{}", e)),
+            SigningKey::External { derivation, signer, .. } => {
+                let recoverable = try_s!(signer.sign_hash(derivation, message));
+                Ok(try_s!(der_signature(&recoverable)).into())
+            },
+        }
+    }
+}
+
 pub(crate) fn sign_tx(
     unsigned: TransactionInputSigner,
-    key_pair: &KeyPair,
+    key_pair: &SigningKey,
     prev_script: Script,
     signature_version: SignatureVersion,
     fork_id: u32,
 ) -> Result<UtxoTx, String> {
     let mut signed_inputs = vec![];
     for (i, _) in unsigned.inputs.iter().enumerate() {
-        signed_inputs.push(try_s!(p2pkh_spend(
-            &unsigned,
-            i,
-            key_pair,
-            &prev_script,
-            signature_version,
-            fork_id
-        )));
+        let signed_input = if prev_script.is_pay_to_witness_key_hash() {
+            try_s!(p2wpkh_spend(&unsigned, i, key_pair, &prev_script, fork_id))
+        } else {
+            try_s!(p2pkh_spend(
+                &unsigned,
+                i,
+                key_pair,
+                &prev_script,
+                signature_version,
+                fork_id
+            ))
+        };
+        signed_inputs.push(signed_input);
     }
-    Ok(UtxoTx {
-        inputs: signed_inputs,
+    Ok(tx_from_signer(unsigned, signed_inputs))
+}
+
+/// Builds the transaction of the `unsigned` one with the given (signed or not) `inputs`.
+fn tx_from_signer(unsigned: TransactionInputSigner, inputs: Vec<TransactionInput>) -> UtxoTx {
+    UtxoTx {
+        inputs,
         n_time: unsigned.n_time,
-        outputs: unsigned.outputs.clone(),
+        outputs: unsigned.outputs,
         version: unsigned.version,
         overwintered: unsigned.overwintered,
         lock_time: unsigned.lock_time,
@@ -1522,7 +1697,7 @@ pub(crate) fn sign_tx(
         zcash: unsigned.zcash,
         str_d_zeel: unsigned.str_d_zeel,
         tx_hash_algo: unsigned.hash_algo.into(),
-    })
+    }
 }
 
 async fn send_outputs_from_my_address_impl<T>(coin: T, outputs: Vec<TransactionOutput>) -> Result<UtxoTx, String>
@@ -1530,7 +1705,19 @@ where
     T: AsRef<UtxoCoinFields> + UtxoCommonOps,
 {
     let (unspents, recently_sent_txs) = try_s!(coin.list_unspent_ordered(&coin.as_ref().my_address).await);
-    generate_and_send_tx(&coin, unspents, outputs, FeePolicy::SendExact, recently_sent_txs).await
+    generate_and_send_tx(&coin, unspents, outputs, FeePolicy::SendExact, recently_sent_txs, false).await
+}
+
+/// Sends the outputs by BIP125 replaceable transaction so its fee can be bumped if it stalls in the mempool.
+async fn send_replaceable_outputs_from_my_address_impl<T>(
+    coin: T,
+    outputs: Vec<TransactionOutput>,
+) -> Result<UtxoTx, String>
+where
+    T: AsRef<UtxoCoinFields> + UtxoCommonOps,
+{
+    let (unspents, recently_sent_txs) = try_s!(coin.list_unspent_ordered(&coin.as_ref().my_address).await);
+    generate_and_send_tx(&coin, unspents, outputs, FeePolicy::SendExact, recently_sent_txs, true).await
 }
 
 /// Generates and sends tx using unspents and outputs adding new record to the recently_spent in case of success
@@ -1539,16 +1726,232 @@ async fn generate_and_send_tx<T>(
     unspents: Vec<UnspentInfo>,
     outputs: Vec<TransactionOutput>,
     fee_policy: FeePolicy,
-    mut recently_spent: AsyncMutexGuard<'_, RecentlySpentOutPoints>,
+    recently_spent: AsyncMutexGuard<'_, RecentlySpentOutPoints>,
+    replaceable: bool,
 ) -> Result<UtxoTx, String>
 where
     T: AsRef<UtxoCoinFields> + UtxoCommonOps,
 {
-    let (unsigned, _) = try_s!(
+    let (mut unsigned, _) = try_s!(
         coin.generate_transaction(unspents, outputs, fee_policy, None, None)
             .await
     );
+    if replaceable {
+        try_s!(set_replaceable_sequence(coin, &mut unsigned).await);
+    }
+    sign_and_send_tx(coin, unsigned, recently_spent).await
+}
 
+/// Replaces the not yet confirmed transaction sent by `generate_and_send_tx` with `replaceable = true`.
+/// The replacement spends the same inputs, keeps all the outputs except for the change and pays the higher fee.
+async fn replace_tx_paying_higher_fee<T>(coin: T, replaced: UtxoTx) -> Result<UtxoTx, String>
+where
+    T: AsRef<UtxoCoinFields> + UtxoCommonOps,
+{
+    if replaced.inputs.iter().all(|input| input.sequence >= SEQUENCE_FINAL - 1) {
+        return ERR!(
+            "Transaction {:?} doesn't signal BIP125 replaceability",
+            replaced.hash().reversed()
+        );
+    }
+
+    let replaced_inputs = try_s!(spent_outputs(&coin, &replaced).await);
+    let replaced_fee = try_s!(paid_fee(&replaced, &replaced_inputs));
+    let tx_size = serialize(&replaced).len() as u64;
+    let network_fee = match try_s!(coin.get_tx_fee().await) {
+        ActualTxFee::Fixed(fee) => fee,
+        ActualTxFee::Dynamic(fee_per_kb) => fee_per_kb * tx_size / KILO_BYTE,
+    };
+    let fee = std::cmp::max(
+        network_fee,
+        std::cmp::max(
+            replaced_fee + replaced_fee / 2,
+            replaced_fee + INCREMENTAL_RELAY_FEE_PER_KB * tx_size / KILO_BYTE,
+        ),
+    );
+
+    let change_script_pubkey = output_script(&coin.as_ref().my_address, &coin.as_ref().conf.address_format).to_bytes();
+    let outputs: Vec<_> = replaced
+        .outputs
+        .iter()
+        .filter(|output| output.script_pubkey != change_script_pubkey)
+        .cloned()
+        .collect();
+
+    // the replaced inputs go first to be spent by the replacement, the other unspents cover the fee increase if required
+    let replaced_hash = replaced.hash();
+    let (unspents, recently_spent) = try_s!(coin.list_unspent_ordered(&coin.as_ref().my_address).await);
+    let unspents = replaced_inputs
+        .clone()
+        .into_iter()
+        .chain(unspents.into_iter().filter(|unspent| {
+            unspent.outpoint.hash != replaced_hash
+                && replaced_inputs.iter().all(|input| input.outpoint != unspent.outpoint)
+        }));
+
+    let (mut unsigned, _) = try_s!(
+        coin.generate_transaction(
+            unspents.collect(),
+            outputs,
+            FeePolicy::SendExact,
+            Some(ActualTxFee::Fixed(fee)),
+            None
+        )
+        .await
+    );
+    try_s!(set_replaceable_sequence(&coin, &mut unsigned).await);
+    sign_and_send_tx(&coin, unsigned, recently_spent).await
+}
+
+/// Sends the child-pays-for-parent transaction spending my outputs of the not yet confirmed `parent`
+/// so the fee of both transactions corresponds to the current network fee rate.
+/// The child fee is funded from my other unspents, it's deducted from the spent outputs if the unspents don't cover it.
+async fn send_cpfp_child_impl<T>(coin: T, parent: UtxoTx) -> Result<UtxoTx, String>
+where
+    T: AsRef<UtxoCoinFields> + UtxoCommonOps,
+{
+    let my_script_pubkey = output_script(&coin.as_ref().my_address, &coin.as_ref().conf.address_format).to_bytes();
+    let parent_hash = parent.hash();
+    let parent_outputs: Vec<_> = parent
+        .outputs
+        .iter()
+        .enumerate()
+        .filter(|(_, output)| output.script_pubkey == my_script_pubkey)
+        .map(|(index, output)| UnspentInfo {
+            outpoint: OutPoint {
+                hash: parent_hash.clone(),
+                index: index as u32,
+            },
+            value: output.value,
+            height: None,
+        })
+        .collect();
+    if parent_outputs.is_empty() {
+        return ERR!("Transaction {:?} has no outputs to my address", parent_hash.reversed());
+    }
+
+    let fee_per_kb = match try_s!(coin.get_tx_fee().await) {
+        ActualTxFee::Dynamic(fee_per_kb) => fee_per_kb,
+        ActualTxFee::Fixed(_) => return ERR!("{} uses the fixed tx fee", coin.as_ref().conf.ticker),
+    };
+    let parent_inputs = try_s!(spent_outputs(&coin, &parent).await);
+    let parent_fee = try_s!(paid_fee(&parent, &parent_inputs));
+    let parent_size = serialize(&parent).len() as u64;
+    let child_fee = match cpfp_child_fee(fee_per_kb, parent_size, parent_fee) {
+        Some(fee) => fee,
+        None => {
+            return ERR!(
+                "Transaction {:?} already pays the current network fee",
+                parent_hash.reversed()
+            )
+        },
+    };
+
+    let parent_value: u64 = parent_outputs.iter().map(|output| output.value).sum();
+    let (unspents, recently_spent) = try_s!(coin.list_unspent_ordered(&coin.as_ref().my_address).await);
+    let other_unspents: Vec<_> = unspents
+        .into_iter()
+        .filter(|unspent| unspent.outpoint.hash != parent_hash)
+        .collect();
+    let other_value: u64 = other_unspents.iter().map(|unspent| unspent.value).sum();
+    let (unspents, fee_policy) = if other_value >= child_fee {
+        // the parent outputs go first to be spent by the child in any case
+        let unspents = parent_outputs.into_iter().chain(other_unspents).collect();
+        (unspents, FeePolicy::SendExact)
+    } else if parent_value >= child_fee + coin.as_ref().dust_amount {
+        (parent_outputs, FeePolicy::DeductFromOutput(0))
+    } else {
+        return ERR!(
+            "Not enough funds to pay the child fee {} for {:?}",
+            child_fee,
+            parent_hash.reversed()
+        );
+    };
+    let outputs = vec![TransactionOutput {
+        value: parent_value,
+        script_pubkey: my_script_pubkey,
+    }];
+
+    let (unsigned, _) = try_s!(
+        coin.generate_transaction(unspents, outputs, fee_policy, Some(ActualTxFee::Fixed(child_fee)), None)
+            .await
+    );
+    sign_and_send_tx(&coin, unsigned, recently_spent).await
+}
+
+/// Returns the fee the child has to pay so the parent and child package pays `fee_per_kb`,
+/// or `None` if the parent pays this rate itself.
+/// The child pays at least its own size at `fee_per_kb`.
+fn cpfp_child_fee(fee_per_kb: u64, parent_size: u64, parent_fee: u64) -> Option<u64> {
+    if parent_fee * KILO_BYTE >= fee_per_kb * parent_size {
+        return None;
+    }
+    let package_fee = fee_per_kb * (parent_size + CPFP_CHILD_TX_SIZE) / KILO_BYTE;
+    let child_own_fee = fee_per_kb * CPFP_CHILD_TX_SIZE / KILO_BYTE;
+    Some(std::cmp::max(package_fee.saturating_sub(parent_fee), child_own_fee))
+}
+
+/// Fetches the outputs spent by the `tx` inputs.
+async fn spent_outputs<T>(coin: &T, tx: &UtxoTx) -> Result<Vec<UnspentInfo>, String>
+where
+    T: AsRef<UtxoCoinFields>,
+{
+    let mut spent = Vec::with_capacity(tx.inputs.len());
+    for input in tx.inputs.iter() {
+        let prev_tx_bytes = try_s!(
+            coin.as_ref()
+                .rpc_client
+                .get_transaction_bytes(input.previous_output.hash.reversed().into())
+                .compat()
+                .await
+        );
+        let prev_tx: UtxoTx = try_s!(deserialize(prev_tx_bytes.0.as_slice()).map_err(|e| ERRL!("{:?}", e)));
+        let prev_output = match prev_tx.outputs.get(input.previous_output.index as usize) {
+            Some(output) => output,
+            None => return ERR!("Previous output {:?} doesn't exist", input.previous_output),
+        };
+        spent.push(UnspentInfo {
+            outpoint: input.previous_output.clone(),
+            value: prev_output.value,
+            height: None,
+        });
+    }
+    Ok(spent)
+}
+
+/// Returns the fee paid by the `tx` spending the `spent` outputs.
+fn paid_fee(tx: &UtxoTx, spent: &[UnspentInfo]) -> Result<u64, String> {
+    let inputs_value: u64 = spent.iter().map(|input| input.value).sum();
+    let outputs_value: u64 = tx.outputs.iter().map(|output| output.value).sum();
+    match inputs_value.checked_sub(outputs_value) {
+        Some(fee) => Ok(fee),
+        None => ERR!("Transaction outputs value exceeds the inputs value"),
+    }
+}
+
+/// Marks all inputs as BIP125 replaceable.
+/// Such inputs enable the lock time check, so it's set to the current median time past at most.
+async fn set_replaceable_sequence<T>(coin: &T, unsigned: &mut TransactionInputSigner) -> Result<(), String>
+where
+    T: AsRef<UtxoCoinFields> + UtxoCommonOps,
+{
+    let mtp = try_s!(coin.get_current_mtp().await);
+    unsigned.lock_time = std::cmp::min(unsigned.lock_time, mtp);
+    for input in unsigned.inputs.iter_mut() {
+        input.sequence = BIP125_REPLACEABLE_SEQUENCE;
+    }
+    Ok(())
+}
+
+/// Signs and sends tx adding new record to the recently_spent in case of success
+async fn sign_and_send_tx<T>(
+    coin: &T,
+    unsigned: TransactionInputSigner,
+    mut recently_spent: AsyncMutexGuard<'_, RecentlySpentOutPoints>,
+) -> Result<UtxoTx, String>
+where
+    T: AsRef<UtxoCoinFields> + UtxoCommonOps,
+{
     let spent_unspents = unsigned
         .inputs
         .iter()
@@ -1559,7 +1962,7 @@ where
         })
         .collect();
 
-    let prev_script = Builder::build_p2pkh(&coin.as_ref().my_address.hash);
+    let prev_script = output_script(&coin.as_ref().my_address, &coin.as_ref().conf.address_format);
     let signed = try_s!(sign_tx(
         unsigned,
         &coin.as_ref().key_pair,
@@ -1586,7 +1989,7 @@ where
 fn p2pkh_spend(
     signer: &TransactionInputSigner,
     input_index: usize,
-    key_pair: &KeyPair,
+    key_pair: &SigningKey,
     prev_script: &Script,
     signature_version: SignatureVersion,
     fork_id: u32,
@@ -1618,7 +2021,45 @@ fn p2pkh_spend(
     })
 }
 
-fn script_sig_with_pub(message: &H256, key_pair: &KeyPair, fork_id: u32) -> Result<Bytes, String> {
+/// Creates signed input spending p2wpkh output.
+/// https://github.com/bitcoin/bips/blob/master/bip-0143.mediawiki
+fn p2wpkh_spend(
+    signer: &TransactionInputSigner,
+    input_index: usize,
+    key_pair: &SigningKey,
+    prev_script: &Script,
+    fork_id: u32,
+) -> Result<TransactionInput, String> {
+    let script = build_p2wpkh(&key_pair.public().address_hash());
+    if script != *prev_script {
+        return ERR!(
+            "p2wpkh script {} built from input key pair doesn't match expected prev script {}",
+            script,
+            prev_script
+        );
+    }
+    // the scriptCode of P2WPKH input is the P2PKH script of the same pubkey hash
+    let script_code = Builder::build_p2pkh(&key_pair.public().address_hash());
+    let sighash_type = 1 | fork_id;
+    let sighash = signer.signature_hash(
+        input_index,
+        signer.inputs[input_index].amount,
+        &script_code,
+        SignatureVersion::WitnessV0,
+        sighash_type,
+    );
+
+    let sig = try_s!(script_sig(&sighash, key_pair, fork_id));
+
+    Ok(TransactionInput {
+        script_sig: Bytes::default(),
+        sequence: signer.inputs[input_index].sequence,
+        script_witness: vec![sig, key_pair.public().to_vec().into()],
+        previous_output: signer.inputs[input_index].previous_output.clone(),
+    })
+}
+
+fn script_sig_with_pub(message: &H256, key_pair: &SigningKey, fork_id: u32) -> Result<Bytes, String> {
     let sig_script = try_s!(script_sig(message, key_pair, fork_id));
 
     let builder = Builder::default();
@@ -1629,8 +2070,8 @@ fn script_sig_with_pub(message: &H256, key_pair: &KeyPair, fork_id: u32) -> Resu
         .into_bytes())
 }
 
-fn script_sig(message: &H256, key_pair: &KeyPair, fork_id: u32) -> Result<Bytes, String> {
-    let signature = try_s!(key_pair.private().sign(message));
+fn script_sig(message: &H256, key_pair: &SigningKey, fork_id: u32) -> Result<Bytes, String> {
+    let signature = try_s!(key_pair.sign(message));
 
     let mut sig_script = Bytes::default();
     sig_script.append(&mut Bytes::from((*signature).to_vec()));
@@ -1640,6 +2081,41 @@ fn script_sig(message: &H256, key_pair: &KeyPair, fork_id: u32) -> Result<Bytes,
     Ok(sig_script)
 }
 
+/// Builds the P2WPKH script pubkey: `OP_0 <20-byte pubkey hash>`.
+pub fn build_p2wpkh(hash: &AddressHash) -> Script {
+    Builder::default()
+        .push_opcode(Opcode::OP_0)
+        .push_bytes(hash)
+        .into_script()
+}
+
+/// Builds the script pubkey paying to the pubkey hash of the given `address`.
+/// The script is P2WPKH if the native SegWit address format is activated and P2PKH otherwise.
+pub fn output_script(address: &Address, address_format: &UtxoAddressFormat) -> Script {
+    match address_format {
+        UtxoAddressFormat::Segwit { .. } => build_p2wpkh(&address.hash),
+        UtxoAddressFormat::Standard | UtxoAddressFormat::CashAddress { .. } => Builder::build_p2pkh(&address.hash),
+    }
+}
+
+/// Builds the script pubkey paying to the `address` of the given script type.
+pub fn address_script(address: &Address, script_type: AddressScriptType) -> Script {
+    match script_type {
+        AddressScriptType::P2PKH => Builder::build_p2pkh(&address.hash),
+        AddressScriptType::P2SH => Builder::build_p2sh(&address.hash),
+        AddressScriptType::P2WPKH => build_p2wpkh(&address.hash),
+    }
+}
+
+/// Returns the string representation of the pubkey hash `address` that can be understood by the coin daemon.
+/// Note the legacy format is used for the cashaddress coins as the daemons support it.
+pub fn pubkey_hash_address_string(address: &Address, address_format: &UtxoAddressFormat) -> Result<String, String> {
+    match address_format {
+        UtxoAddressFormat::Segwit { hrp } => SegwitAddress::p2wpkh(hrp, &address.hash).encode(),
+        UtxoAddressFormat::Standard | UtxoAddressFormat::CashAddress { .. } => Ok(address.to_string()),
+    }
+}
+
 pub fn address_by_conf_and_pubkey_str(coin: &str, conf: &Json, pubkey: &str) -> Result<String, String> {
     let null = Json::Null;
     let conf_builder = UtxoConfBuilder::new(&conf, &null, coin);
@@ -0,0 +1,241 @@
+//! The receive addresses of the UTXO coin activated in the HD wallet mode and the BIP44 account discovery.
+
+use super::rpc_clients::{electrum_script_hash, UtxoRpcClientEnum, UtxoRpcClientOps};
+use super::utxo_common::display_address;
+use super::{output_script, Address, UtxoCoinConf, UtxoCoinFields};
+use crate::hd_wallet::{HDAccount, CHANGE_CHAIN, RECEIVE_CHAIN};
+use bigdecimal::BigDecimal;
+use common::write;
+use futures::compat::Future01CompatExt;
+use gstuff::slurp;
+use rpc::v1::types::H256 as H256Json;
+use serde_json::{self as json};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// The index of the receive address the node spends from, it's the coin `my_address`.
+/// The node can't spend the funds of the other account addresses, they are discovered by the account scan only.
+const SPENDABLE_RECEIVE_INDEX: u32 = 0;
+
+/// The account state saved between the restarts.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct HDAccountState {
+    /// The used flags of the receive addresses discovered so far, ordered by the derivation index.
+    used_receive_addresses: Vec<bool>,
+}
+
+#[derive(Debug)]
+pub struct UtxoHDWallet {
+    pub account: HDAccount,
+    /// The number of consecutive unused addresses after which the account discovery stops.
+    pub gap_limit: u32,
+    /// The path of the account state file, `DB/<rmd160>/HD_WALLET/<ticker>_<account_id>.json`.
+    state_path: PathBuf,
+    state: Mutex<HDAccountState>,
+}
+
+impl UtxoHDWallet {
+    /// Loads the account state saved at `state_path` by the previous account scans, if any.
+    pub fn new(account: HDAccount, gap_limit: u32, state_path: PathBuf) -> UtxoHDWallet {
+        let state = json::from_slice(&slurp(&state_path)).unwrap_or_default();
+        UtxoHDWallet {
+            account,
+            gap_limit,
+            state_path,
+            state: Mutex::new(state),
+        }
+    }
+
+    pub fn derive_address(&self, conf: &UtxoCoinConf, chain: u32, index: u32) -> Result<Address, String> {
+        let key_pair = try_s!(try_s!(self.account.derive_address_key(chain, index)).key_pair());
+        Ok(Address {
+            prefix: conf.pub_addr_prefix,
+            t_addr_prefix: conf.pub_t_addr_prefix,
+            hash: key_pair.public().address_hash(),
+            checksum_type: conf.checksum_type,
+        })
+    }
+
+    /// Marks the receive addresses as used and saves the account state.
+    pub fn mark_receive_addresses_used(&self, indexes: &[u32]) -> Result<(), String> {
+        let mut state = try_s!(self.state.lock());
+        for index in indexes {
+            let index = *index as usize;
+            if state.used_receive_addresses.len() <= index {
+                state.used_receive_addresses.resize(index + 1, false);
+            }
+            state.used_receive_addresses[index] = true;
+        }
+        let content = try_s!(json::to_vec(&*state));
+        try_s!(write(&self.state_path, &content));
+        Ok(())
+    }
+
+    fn receive_address_info(&self, conf: &UtxoCoinConf, index: u32, used: bool) -> Result<HDAddressInfo, String> {
+        let address = try_s!(self.derive_address(conf, RECEIVE_CHAIN, index));
+        Ok(HDAddressInfo {
+            index,
+            address: try_s!(display_address(conf, &address)),
+            derivation_path: self.account.address_derivation_path(RECEIVE_CHAIN, index).to_string(),
+            used,
+            spendable: index == SPENDABLE_RECEIVE_INDEX,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct HDAddressInfo {
+    pub index: u32,
+    pub address: String,
+    pub derivation_path: String,
+    /// Whether the address had any transactions at the moment of the last account scan.
+    pub used: bool,
+    /// Whether the node can spend the funds of the address.
+    /// Only the spendable address should be given out to receive the funds.
+    pub spendable: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HDReceiveAddresses {
+    pub account_id: u32,
+    pub derivation_path: String,
+    pub gap_limit: u32,
+    pub addresses: Vec<HDAddressInfo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HDAddressBalance {
+    pub address: String,
+    pub derivation_path: String,
+    pub balance: BigDecimal,
+    pub tx_count: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HDAccountTxHistoryItem {
+    pub tx_hash: H256Json,
+    /// 0 or negative for the unconfirmed transactions.
+    pub height: i64,
+    /// The account addresses the transaction is related to.
+    pub addresses: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HDAccountBalance {
+    pub account_id: u32,
+    pub derivation_path: String,
+    pub balance: BigDecimal,
+    /// The used addresses of both receive and change chains.
+    pub addresses: Vec<HDAddressBalance>,
+    /// The transactions of the used addresses, the unconfirmed go first and then the newest.
+    pub history: Vec<HDAccountTxHistoryItem>,
+}
+
+fn hd_wallet(coin: &UtxoCoinFields) -> Result<&UtxoHDWallet, String> {
+    match &coin.hd_wallet {
+        Some(hd_wallet) => Ok(hd_wallet),
+        None => ERR!("{} is not activated in the HD wallet mode", coin.conf.ticker),
+    }
+}
+
+/// Lists the spendable receive address and the receive addresses discovered by the account scans.
+pub fn hd_receive_addresses(coin: &UtxoCoinFields) -> Result<HDReceiveAddresses, String> {
+    let hd_wallet = try_s!(hd_wallet(coin));
+    let state = try_s!(hd_wallet.state.lock());
+    let count = state
+        .used_receive_addresses
+        .len()
+        .max(SPENDABLE_RECEIVE_INDEX as usize + 1);
+    let addresses = try_s!((0..count)
+        .map(|index| {
+            let used = state.used_receive_addresses.get(index).copied().unwrap_or(false);
+            hd_wallet.receive_address_info(&coin.conf, index as u32, used)
+        })
+        .collect());
+    Ok(HDReceiveAddresses {
+        account_id: hd_wallet.account.account_id,
+        derivation_path: hd_wallet.account.derivation_path.to_string(),
+        gap_limit: hd_wallet.gap_limit,
+        addresses,
+    })
+}
+
+/// Discovers the used addresses of the account until `gap_limit` unused addresses in a row are found on each chain,
+/// and aggregates their balances and transaction histories.
+/// The discovered receive addresses are added to the known ones and saved.
+/// Note the balance includes the funds the node can't spend, e.g. received by other wallets sharing the seed.
+pub async fn scan_hd_account(coin: &UtxoCoinFields) -> Result<HDAccountBalance, String> {
+    let hd_wallet = try_s!(hd_wallet(coin));
+    let client = match &coin.rpc_client {
+        UtxoRpcClientEnum::Electrum(client) => client,
+        UtxoRpcClientEnum::Native(_) => return ERR!("HD account scan is supported in Electrum mode only"),
+    };
+
+    let mut balance = BigDecimal::from(0);
+    let mut addresses = Vec::new();
+    let mut history: Vec<HDAccountTxHistoryItem> = Vec::new();
+    let mut history_index_by_hash: HashMap<H256Json, usize> = HashMap::new();
+    let mut used_receive_indexes = Vec::new();
+
+    for chain in [RECEIVE_CHAIN, CHANGE_CHAIN].iter() {
+        let mut index = 0;
+        let mut unused_in_row = 0;
+        while unused_in_row < hd_wallet.gap_limit {
+            let address = try_s!(hd_wallet.derive_address(&coin.conf, *chain, index));
+            let script = output_script(&address, &coin.conf.address_format);
+            let script_hash = hex::encode(electrum_script_hash(&script));
+            let address_history = try_s!(client.scripthash_get_history(&script_hash).compat().await);
+            if address_history.is_empty() {
+                unused_in_row += 1;
+                index += 1;
+                continue;
+            }
+            unused_in_row = 0;
+            if *chain == RECEIVE_CHAIN {
+                used_receive_indexes.push(index);
+            }
+
+            let address_balance = try_s!(
+                client
+                    .display_balance(address.clone(), &coin.conf.address_format, coin.decimals)
+                    .compat()
+                    .await
+            );
+            let address_str = try_s!(display_address(&coin.conf, &address));
+            for item in address_history.iter() {
+                match history_index_by_hash.get(&item.tx_hash) {
+                    Some(i) => history[*i].addresses.push(address_str.clone()),
+                    None => {
+                        history_index_by_hash.insert(item.tx_hash.clone(), history.len());
+                        history.push(HDAccountTxHistoryItem {
+                            tx_hash: item.tx_hash.clone(),
+                            height: item.height,
+                            addresses: vec![address_str.clone()],
+                        });
+                    },
+                }
+            }
+            balance += &address_balance;
+            addresses.push(HDAddressBalance {
+                address: address_str,
+                derivation_path: hd_wallet.account.address_derivation_path(*chain, index).to_string(),
+                balance: address_balance,
+                tx_count: address_history.len(),
+            });
+            index += 1;
+        }
+    }
+
+    history.sort_by_key(|item| if item.height <= 0 { i64::MIN } else { -item.height });
+
+    try_s!(hd_wallet.mark_receive_addresses_used(&used_receive_indexes));
+
+    Ok(HDAccountBalance {
+        account_id: hd_wallet.account.account_id,
+        derivation_path: hd_wallet.account.derivation_path.to_string(),
+        balance,
+        addresses,
+        history,
+    })
+}
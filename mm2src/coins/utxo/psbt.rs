@@ -0,0 +1,319 @@
+//! Partially Signed Bitcoin Transaction format: https://github.com/bitcoin/bips/blob/master/bip-0174.mediawiki
+//!
+//! The unsigned transaction is kept in the coin native serialization, so Zcash overwinter/sapling transactions
+//! are supported too. The inputs of the amount committing sighash (SegWit, fork-id, Zcash) carry `WITNESS_UTXO`,
+//! the legacy sighash inputs carry the full previous transaction in `NON_WITNESS_UTXO`.
+
+use super::{build_p2wpkh, UtxoTx};
+use chain::TransactionOutput;
+use keys::bytes::Bytes;
+use keys::Public;
+use script::{Builder, Script};
+use serialization::{deserialize, serialize, serialize_with_flags, SERIALIZE_TRANSACTION_WITNESS};
+
+const PSBT_MAGIC: &[u8] = b"psbt\xff";
+
+const PSBT_GLOBAL_UNSIGNED_TX: u8 = 0x00;
+
+const PSBT_IN_NON_WITNESS_UTXO: u8 = 0x00;
+const PSBT_IN_WITNESS_UTXO: u8 = 0x01;
+const PSBT_IN_PARTIAL_SIG: u8 = 0x02;
+const PSBT_IN_SIGHASH_TYPE: u8 = 0x03;
+const PSBT_IN_FINAL_SCRIPTSIG: u8 = 0x07;
+const PSBT_IN_FINAL_SCRIPTWITNESS: u8 = 0x08;
+
+/// The key-value pairs this implementation doesn't interpret, they are kept to be serialized back as is.
+type UnknownPairs = Vec<(Vec<u8>, Vec<u8>)>;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PsbtInput {
+    pub non_witness_utxo: Option<UtxoTx>,
+    pub witness_utxo: Option<TransactionOutput>,
+    /// The pubkey and the signature with the sighash type byte appended.
+    pub partial_sigs: Vec<(Bytes, Bytes)>,
+    pub sighash_type: Option<u32>,
+    pub final_script_sig: Option<Bytes>,
+    pub final_script_witness: Option<Vec<Bytes>>,
+    pub unknown: UnknownPairs,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PsbtOutput {
+    pub unknown: UnknownPairs,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Psbt {
+    /// The transaction with empty script sigs and witnesses.
+    pub unsigned_tx: UtxoTx,
+    pub inputs: Vec<PsbtInput>,
+    pub outputs: Vec<PsbtOutput>,
+    pub unknown: UnknownPairs,
+}
+
+impl Psbt {
+    pub fn new(unsigned_tx: UtxoTx, inputs: Vec<PsbtInput>) -> Psbt {
+        let outputs = vec![PsbtOutput::default(); unsigned_tx.outputs.len()];
+        Psbt {
+            unsigned_tx,
+            inputs,
+            outputs,
+            unknown: Vec::new(),
+        }
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = PSBT_MAGIC.to_vec();
+        write_pair(&mut buf, &[PSBT_GLOBAL_UNSIGNED_TX], &serialize(&self.unsigned_tx));
+        write_unknown(&mut buf, &self.unknown);
+        buf.push(0);
+
+        for input in self.inputs.iter() {
+            if let Some(prev_tx) = &input.non_witness_utxo {
+                let prev_tx = serialize_with_flags(prev_tx, SERIALIZE_TRANSACTION_WITNESS);
+                write_pair(&mut buf, &[PSBT_IN_NON_WITNESS_UTXO], &prev_tx);
+            }
+            if let Some(prev_output) = &input.witness_utxo {
+                write_pair(&mut buf, &[PSBT_IN_WITNESS_UTXO], &serialize(prev_output));
+            }
+            for (pubkey, sig) in input.partial_sigs.iter() {
+                let mut key = vec![PSBT_IN_PARTIAL_SIG];
+                key.extend_from_slice(pubkey);
+                write_pair(&mut buf, &key, sig);
+            }
+            if let Some(sighash_type) = input.sighash_type {
+                write_pair(&mut buf, &[PSBT_IN_SIGHASH_TYPE], &sighash_type.to_le_bytes());
+            }
+            if let Some(script_sig) = &input.final_script_sig {
+                write_pair(&mut buf, &[PSBT_IN_FINAL_SCRIPTSIG], script_sig);
+            }
+            if let Some(witness) = &input.final_script_witness {
+                let mut value = Vec::new();
+                write_compact_size(&mut value, witness.len() as u64);
+                for item in witness.iter() {
+                    write_compact_size(&mut value, item.len() as u64);
+                    value.extend_from_slice(item);
+                }
+                write_pair(&mut buf, &[PSBT_IN_FINAL_SCRIPTWITNESS], &value);
+            }
+            write_unknown(&mut buf, &input.unknown);
+            buf.push(0);
+        }
+
+        for output in self.outputs.iter() {
+            write_unknown(&mut buf, &output.unknown);
+            buf.push(0);
+        }
+        buf
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<Psbt, String> {
+        let mut reader = PsbtReader { bytes, pos: 0 };
+        if try_s!(reader.read_bytes(PSBT_MAGIC.len())) != PSBT_MAGIC {
+            return ERR!("Invalid PSBT magic bytes");
+        }
+
+        let mut unsigned_tx = None;
+        let mut unknown = Vec::new();
+        while let Some((key, value)) = try_s!(reader.read_pair()) {
+            if key == [PSBT_GLOBAL_UNSIGNED_TX] {
+                let tx: UtxoTx = try_s!(deserialize(value.as_slice()).map_err(|e| ERRL!("{:?}", e)));
+                unsigned_tx = Some(tx);
+            } else {
+                unknown.push((key, value));
+            }
+        }
+        let unsigned_tx = match unsigned_tx {
+            Some(tx) => tx,
+            None => return ERR!("PSBT doesn't contain the unsigned transaction"),
+        };
+        if unsigned_tx
+            .inputs
+            .iter()
+            .any(|input| !input.script_sig.is_empty() || !input.script_witness.is_empty())
+        {
+            return ERR!("PSBT unsigned transaction must have empty script sigs and witnesses");
+        }
+
+        let mut inputs = Vec::with_capacity(unsigned_tx.inputs.len());
+        for _ in unsigned_tx.inputs.iter() {
+            inputs.push(try_s!(reader.read_input()));
+        }
+        let mut outputs = Vec::with_capacity(unsigned_tx.outputs.len());
+        for _ in unsigned_tx.outputs.iter() {
+            let mut output = PsbtOutput::default();
+            while let Some(pair) = try_s!(reader.read_pair()) {
+                output.unknown.push(pair);
+            }
+            outputs.push(output);
+        }
+        if reader.pos != bytes.len() {
+            return ERR!("PSBT has {} unexpected trailing bytes", bytes.len() - reader.pos);
+        }
+
+        Ok(Psbt {
+            unsigned_tx,
+            inputs,
+            outputs,
+            unknown,
+        })
+    }
+
+    /// Builds the signed transaction from the final scripts or the partial signatures of the P2PKH/P2WPKH inputs.
+    pub fn finalize(self) -> Result<UtxoTx, String> {
+        let mut tx = self.unsigned_tx;
+        for (i, (tx_input, input)) in tx.inputs.iter_mut().zip(self.inputs.into_iter()).enumerate() {
+            if input.final_script_sig.is_some() || input.final_script_witness.is_some() {
+                tx_input.script_sig = input.final_script_sig.unwrap_or_default();
+                tx_input.script_witness = input.final_script_witness.unwrap_or_default();
+                continue;
+            }
+
+            let prev_script: Script = match (&input.witness_utxo, &input.non_witness_utxo) {
+                (Some(prev_output), _) => prev_output.script_pubkey.clone().into(),
+                (None, Some(prev_tx)) => match prev_tx.outputs.get(tx_input.previous_output.index as usize) {
+                    Some(prev_output) => prev_output.script_pubkey.clone().into(),
+                    None => return ERR!("Input {} previous output doesn't exist", i),
+                },
+                (None, None) => return ERR!("Input {} doesn't have the previous output", i),
+            };
+            let (pubkey, sig) = match input.partial_sigs.into_iter().next() {
+                Some(partial_sig) => partial_sig,
+                None => return ERR!("Input {} is not signed", i),
+            };
+            let public = try_s!(Public::from_slice(&pubkey).map_err(|e| ERRL!("{:?}", e)));
+
+            if prev_script.is_pay_to_witness_key_hash() {
+                if prev_script != build_p2wpkh(&public.address_hash()) {
+                    return ERR!("Input {} is signed by the key not matching the prev script", i);
+                }
+                tx_input.script_witness = vec![sig, pubkey];
+            } else if prev_script.is_pay_to_public_key_hash() {
+                if prev_script != Builder::build_p2pkh(&public.address_hash()) {
+                    return ERR!("Input {} is signed by the key not matching the prev script", i);
+                }
+                tx_input.script_sig = Builder::default().push_data(&sig).push_data(&pubkey).into_bytes();
+            } else {
+                return ERR!("Input {} prev script {} is not supported", i, prev_script);
+            }
+        }
+        Ok(tx)
+    }
+}
+
+fn write_compact_size(buf: &mut Vec<u8>, n: u64) {
+    match n {
+        0..=0xfc => buf.push(n as u8),
+        0xfd..=0xffff => {
+            buf.push(0xfd);
+            buf.extend_from_slice(&(n as u16).to_le_bytes());
+        },
+        0x10000..=0xffff_ffff => {
+            buf.push(0xfe);
+            buf.extend_from_slice(&(n as u32).to_le_bytes());
+        },
+        _ => {
+            buf.push(0xff);
+            buf.extend_from_slice(&n.to_le_bytes());
+        },
+    }
+}
+
+fn write_pair(buf: &mut Vec<u8>, key: &[u8], value: &[u8]) {
+    write_compact_size(buf, key.len() as u64);
+    buf.extend_from_slice(key);
+    write_compact_size(buf, value.len() as u64);
+    buf.extend_from_slice(value);
+}
+
+fn write_unknown(buf: &mut Vec<u8>, unknown: &[(Vec<u8>, Vec<u8>)]) {
+    for (key, value) in unknown.iter() {
+        write_pair(buf, key, value);
+    }
+}
+
+struct PsbtReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PsbtReader<'a> {
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() - self.pos < len {
+            return ERR!("Unexpected end of PSBT");
+        }
+        let bytes = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn read_compact_size(&mut self) -> Result<u64, String> {
+        let n = match try_s!(self.read_bytes(1))[0] {
+            0xfd => {
+                let mut n = [0; 2];
+                n.copy_from_slice(try_s!(self.read_bytes(2)));
+                u16::from_le_bytes(n) as u64
+            },
+            0xfe => {
+                let mut n = [0; 4];
+                n.copy_from_slice(try_s!(self.read_bytes(4)));
+                u32::from_le_bytes(n) as u64
+            },
+            0xff => {
+                let mut n = [0; 8];
+                n.copy_from_slice(try_s!(self.read_bytes(8)));
+                u64::from_le_bytes(n)
+            },
+            n => n as u64,
+        };
+        Ok(n)
+    }
+
+    /// Reads the next key-value pair of the map, returns None if the map separator is reached.
+    fn read_pair(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>, String> {
+        let key_len = try_s!(self.read_compact_size()) as usize;
+        if key_len == 0 {
+            return Ok(None);
+        }
+        let key = try_s!(self.read_bytes(key_len)).to_vec();
+        let value_len = try_s!(self.read_compact_size()) as usize;
+        let value = try_s!(self.read_bytes(value_len)).to_vec();
+        Ok(Some((key, value)))
+    }
+
+    fn read_input(&mut self) -> Result<PsbtInput, String> {
+        let mut input = PsbtInput::default();
+        while let Some((key, value)) = try_s!(self.read_pair()) {
+            match key[0] {
+                PSBT_IN_NON_WITNESS_UTXO if key.len() == 1 => {
+                    let prev_tx: UtxoTx = try_s!(deserialize(value.as_slice()).map_err(|e| ERRL!("{:?}", e)));
+                    input.non_witness_utxo = Some(prev_tx);
+                },
+                PSBT_IN_WITNESS_UTXO if key.len() == 1 => {
+                    let prev_output: TransactionOutput =
+                        try_s!(deserialize(value.as_slice()).map_err(|e| ERRL!("{:?}", e)));
+                    input.witness_utxo = Some(prev_output);
+                },
+                PSBT_IN_PARTIAL_SIG => input.partial_sigs.push((key[1..].to_vec().into(), value.into())),
+                PSBT_IN_SIGHASH_TYPE if key.len() == 1 && value.len() == 4 => {
+                    let mut sighash_type = [0; 4];
+                    sighash_type.copy_from_slice(&value);
+                    input.sighash_type = Some(u32::from_le_bytes(sighash_type));
+                },
+                PSBT_IN_FINAL_SCRIPTSIG if key.len() == 1 => input.final_script_sig = Some(value.into()),
+                PSBT_IN_FINAL_SCRIPTWITNESS if key.len() == 1 => {
+                    let mut reader = PsbtReader { bytes: &value, pos: 0 };
+                    let items_count = try_s!(reader.read_compact_size());
+                    let mut witness = Vec::new();
+                    for _ in 0..items_count {
+                        let item_len = try_s!(reader.read_compact_size()) as usize;
+                        witness.push(try_s!(reader.read_bytes(item_len)).to_vec().into());
+                    }
+                    input.final_script_witness = Some(witness);
+                },
+                _ => input.unknown.push((key, value)),
+            }
+        }
+        Ok(input)
+    }
+}
@@ -1,5 +1,5 @@
 use super::*;
-use crate::{eth, SwapOps, TradePreimageError, TradePreimageValue, ValidateAddressResult};
+use crate::{eth, HtlcScriptType, SwapOps, TradePreimageError, TradePreimageValue, ValidateAddressResult};
 use common::mm_metrics::MetricsArc;
 use ethereum_types::H160;
 use futures::{FutureExt, TryFutureExt};
@@ -115,7 +115,7 @@ pub async fn qtum_coin_from_conf_and_request(
     ticker: &str,
     conf: &Json,
     req: &Json,
-    priv_key: &[u8],
+    priv_key: Option<&[u8]>,
 ) -> Result<QtumCoin, String> {
     let coin: QtumCoin = try_s!(utxo_common::utxo_arc_from_conf_and_request(ctx, ticker, conf, req, priv_key).await);
     Ok(coin)
@@ -177,6 +177,7 @@ impl UtxoCommonOps for QtumCoin {
         outputs: Vec<TransactionOutput>,
         script_data: Script,
         sequence: u32,
+        key_pair: &SigningKey,
     ) -> Result<UtxoTx, String> {
         utxo_common::p2sh_spending_tx(
             &self.utxo_arc,
@@ -185,6 +186,7 @@ impl UtxoCommonOps for QtumCoin {
             outputs,
             script_data,
             sequence,
+            key_pair,
         )
     }
 
@@ -257,8 +259,20 @@ impl SwapOps for QtumCoin {
         secret_hash: &[u8],
         amount: BigDecimal,
         _swap_contract_address: &Option<BytesJson>,
+        htlc_script_type: HtlcScriptType,
+        replaceable: bool,
+        swap_unique_data: &[u8],
     ) -> TransactionFut {
-        utxo_common::send_maker_payment(self.clone(), time_lock, taker_pub, secret_hash, amount)
+        utxo_common::send_maker_payment(
+            self.clone(),
+            time_lock,
+            taker_pub,
+            secret_hash,
+            amount,
+            htlc_script_type,
+            replaceable,
+            swap_unique_data,
+        )
     }
 
     fn send_taker_payment(
@@ -268,8 +282,20 @@ impl SwapOps for QtumCoin {
         secret_hash: &[u8],
         amount: BigDecimal,
         _swap_contract_address: &Option<BytesJson>,
+        htlc_script_type: HtlcScriptType,
+        replaceable: bool,
+        swap_unique_data: &[u8],
     ) -> TransactionFut {
-        utxo_common::send_taker_payment(self.clone(), time_lock, maker_pub, secret_hash, amount)
+        utxo_common::send_taker_payment(
+            self.clone(),
+            time_lock,
+            maker_pub,
+            secret_hash,
+            amount,
+            htlc_script_type,
+            replaceable,
+            swap_unique_data,
+        )
     }
 
     fn send_maker_spends_taker_payment(
@@ -279,8 +305,16 @@ impl SwapOps for QtumCoin {
         taker_pub: &[u8],
         secret: &[u8],
         _swap_contract_address: &Option<BytesJson>,
+        swap_unique_data: &[u8],
     ) -> TransactionFut {
-        utxo_common::send_maker_spends_taker_payment(self.clone(), taker_payment_tx, time_lock, taker_pub, secret)
+        utxo_common::send_maker_spends_taker_payment(
+            self.clone(),
+            taker_payment_tx,
+            time_lock,
+            taker_pub,
+            secret,
+            swap_unique_data,
+        )
     }
 
     fn send_taker_spends_maker_payment(
@@ -290,8 +324,16 @@ impl SwapOps for QtumCoin {
         maker_pub: &[u8],
         secret: &[u8],
         _swap_contract_address: &Option<BytesJson>,
+        swap_unique_data: &[u8],
     ) -> TransactionFut {
-        utxo_common::send_taker_spends_maker_payment(self.clone(), maker_payment_tx, time_lock, maker_pub, secret)
+        utxo_common::send_taker_spends_maker_payment(
+            self.clone(),
+            maker_payment_tx,
+            time_lock,
+            maker_pub,
+            secret,
+            swap_unique_data,
+        )
     }
 
     fn send_taker_refunds_payment(
@@ -301,8 +343,16 @@ impl SwapOps for QtumCoin {
         maker_pub: &[u8],
         secret_hash: &[u8],
         _swap_contract_address: &Option<BytesJson>,
+        swap_unique_data: &[u8],
     ) -> TransactionFut {
-        utxo_common::send_taker_refunds_payment(self.clone(), taker_payment_tx, time_lock, maker_pub, secret_hash)
+        utxo_common::send_taker_refunds_payment(
+            self.clone(),
+            taker_payment_tx,
+            time_lock,
+            maker_pub,
+            secret_hash,
+            swap_unique_data,
+        )
     }
 
     fn send_maker_refunds_payment(
@@ -312,8 +362,16 @@ impl SwapOps for QtumCoin {
         taker_pub: &[u8],
         secret_hash: &[u8],
         _swap_contract_address: &Option<BytesJson>,
+        swap_unique_data: &[u8],
     ) -> TransactionFut {
-        utxo_common::send_maker_refunds_payment(self.clone(), maker_payment_tx, time_lock, taker_pub, secret_hash)
+        utxo_common::send_maker_refunds_payment(
+            self.clone(),
+            maker_payment_tx,
+            time_lock,
+            taker_pub,
+            secret_hash,
+            swap_unique_data,
+        )
     }
 
     fn validate_fee(
@@ -333,8 +391,19 @@ impl SwapOps for QtumCoin {
         priv_bn_hash: &[u8],
         amount: BigDecimal,
         _swap_contract_address: &Option<BytesJson>,
+        htlc_script_type: HtlcScriptType,
+        swap_unique_data: &[u8],
     ) -> Box<dyn Future<Item = (), Error = String> + Send> {
-        utxo_common::validate_maker_payment(self, payment_tx, time_lock, maker_pub, priv_bn_hash, amount)
+        utxo_common::validate_maker_payment(
+            self,
+            payment_tx,
+            time_lock,
+            maker_pub,
+            priv_bn_hash,
+            amount,
+            htlc_script_type,
+            swap_unique_data,
+        )
     }
 
     fn validate_taker_payment(
@@ -345,8 +414,19 @@ impl SwapOps for QtumCoin {
         priv_bn_hash: &[u8],
         amount: BigDecimal,
         _swap_contract_address: &Option<BytesJson>,
+        htlc_script_type: HtlcScriptType,
+        swap_unique_data: &[u8],
     ) -> Box<dyn Future<Item = (), Error = String> + Send> {
-        utxo_common::validate_taker_payment(self, payment_tx, time_lock, taker_pub, priv_bn_hash, amount)
+        utxo_common::validate_taker_payment(
+            self,
+            payment_tx,
+            time_lock,
+            taker_pub,
+            priv_bn_hash,
+            amount,
+            htlc_script_type,
+            swap_unique_data,
+        )
     }
 
     fn check_if_my_payment_sent(
@@ -356,8 +436,17 @@ impl SwapOps for QtumCoin {
         secret_hash: &[u8],
         _search_from_block: u64,
         _swap_contract_address: &Option<BytesJson>,
+        htlc_script_type: HtlcScriptType,
+        swap_unique_data: &[u8],
     ) -> Box<dyn Future<Item = Option<TransactionEnum>, Error = String> + Send> {
-        utxo_common::check_if_my_payment_sent(self.clone(), time_lock, other_pub, secret_hash)
+        utxo_common::check_if_my_payment_sent(
+            self.clone(),
+            time_lock,
+            other_pub,
+            secret_hash,
+            htlc_script_type,
+            swap_unique_data,
+        )
     }
 
     fn search_for_swap_tx_spend_my(
@@ -368,6 +457,7 @@ impl SwapOps for QtumCoin {
         tx: &[u8],
         search_from_block: u64,
         _swap_contract_address: &Option<BytesJson>,
+        swap_unique_data: &[u8],
     ) -> Result<Option<FoundSwapTxSpend>, String> {
         utxo_common::search_for_swap_tx_spend_my(
             &self.utxo_arc,
@@ -376,6 +466,7 @@ impl SwapOps for QtumCoin {
             secret_hash,
             tx,
             search_from_block,
+            swap_unique_data,
         )
     }
 
@@ -387,6 +478,7 @@ impl SwapOps for QtumCoin {
         tx: &[u8],
         search_from_block: u64,
         _swap_contract_address: &Option<BytesJson>,
+        swap_unique_data: &[u8],
     ) -> Result<Option<FoundSwapTxSpend>, String> {
         utxo_common::search_for_swap_tx_spend_other(
             &self.utxo_arc,
@@ -395,12 +487,24 @@ impl SwapOps for QtumCoin {
             secret_hash,
             tx,
             search_from_block,
+            swap_unique_data,
         )
     }
 
     fn extract_secret(&self, secret_hash: &[u8], spend_tx: &[u8]) -> Result<Vec<u8>, String> {
         utxo_common::extract_secret(secret_hash, spend_tx)
     }
+
+    fn derive_htlc_pubkey(&self, swap_unique_data: &[u8]) -> Result<Vec<u8>, String> {
+        utxo_common::derive_htlc_pubkey(&self.utxo_arc, swap_unique_data)
+    }
+    fn bump_payment_fee(&self, payment_tx: &[u8]) -> TransactionFut {
+        utxo_common::bump_payment_fee(self.clone(), payment_tx)
+    }
+
+    fn send_cpfp_child(&self, parent_tx: &[u8]) -> TransactionFut {
+        utxo_common::send_cpfp_child(self.clone(), parent_tx)
+    }
 }
 
 impl MarketCoinOps for QtumCoin {
@@ -474,6 +578,13 @@ impl MmCoin for QtumCoin {
         Box::new(utxo_common::withdraw(self.clone(), req).boxed().compat())
     }
 
+    fn withdraw_batch(
+        &self,
+        req: WithdrawBatchRequest,
+    ) -> Box<dyn Future<Item = WithdrawBatchResponse, Error = String> + Send> {
+        Box::new(utxo_common::withdraw_batch(self.clone(), req).boxed().compat())
+    }
+
     fn decimals(&self) -> u8 { utxo_common::decimals(&self.utxo_arc) }
 
     /// Check if the `to_address_format` is standard and if the `from` address is standard UTXO address.
@@ -506,6 +617,13 @@ impl MmCoin for QtumCoin {
         utxo_common::get_receiver_trade_fee(self)
     }
 
+    fn get_cpfp_child_fee(
+        &self,
+        stage: FeeApproxStage,
+    ) -> Box<dyn Future<Item = Option<TradeFee>, Error = TradePreimageError> + Send> {
+        utxo_common::get_cpfp_child_fee(self.clone(), stage)
+    }
+
     fn get_fee_to_send_taker_fee(
         &self,
         dex_fee_amount: BigDecimal,
@@ -531,6 +649,8 @@ impl MmCoin for QtumCoin {
     }
 
     fn swap_contract_address(&self) -> Option<BytesJson> { utxo_common::swap_contract_address() }
+
+    fn is_p2wsh_htlc_supported(&self) -> bool { utxo_common::is_p2wsh_htlc_supported(&self.utxo_arc.conf) }
 }
 
 /// Parse contract address (H160) from string.
@@ -2,7 +2,7 @@
 #![cfg_attr(not(feature = "native"), allow(unused_macros))]
 #![cfg_attr(not(feature = "native"), allow(dead_code))]
 
-use crate::utxo::sat_from_big_decimal;
+use crate::utxo::{output_script, pubkey_hash_address_string, sat_from_big_decimal, UtxoAddressFormat};
 use crate::{RpcTransportEventHandler, RpcTransportEventHandlerShared};
 use bigdecimal::BigDecimal;
 use chain::{BlockHeader, OutPoint, Transaction as UtxoTx};
@@ -33,9 +33,9 @@ use keys::Address;
 #[cfg(test)] use mocktopus::macros::*;
 use rpc::v1::types::{Bytes as BytesJson, Transaction as RpcTransaction, VerboseBlockClient, H256 as H256Json};
 #[cfg(feature = "native")] use rustls::{self};
-use script::Builder;
 use serde_json::{self as json, Value as Json};
-use serialization::{deserialize, serialize, CompactInteger, Reader};
+use serialization::{deserialize, serialize, serialize_with_flags, CompactInteger, Reader,
+                    SERIALIZE_TRANSACTION_WITNESS};
 use sha2::{Digest, Sha256};
 use std::collections::hash_map::{Entry, HashMap};
 use std::fmt;
@@ -179,7 +179,12 @@ pub type UtxoRpcRes<T> = Box<dyn Future<Item = T, Error = String> + Send + 'stat
 
 /// Common operations that both types of UTXO clients have but implement them differently
 pub trait UtxoRpcClientOps: fmt::Debug + Send + Sync + 'static {
-    fn list_unspent(&self, address: &Address, decimals: u8) -> UtxoRpcRes<Vec<UnspentInfo>>;
+    fn list_unspent(
+        &self,
+        address: &Address,
+        address_format: &UtxoAddressFormat,
+        decimals: u8,
+    ) -> UtxoRpcRes<Vec<UnspentInfo>>;
 
     fn send_transaction(&self, tx: &UtxoTx) -> UtxoRpcRes<H256Json>;
 
@@ -191,7 +196,12 @@ pub trait UtxoRpcClientOps: fmt::Debug + Send + Sync + 'static {
 
     fn get_block_count(&self) -> RpcRes<u64>;
 
-    fn display_balance(&self, address: Address, decimals: u8) -> RpcRes<BigDecimal>;
+    fn display_balance(
+        &self,
+        address: Address,
+        address_format: &UtxoAddressFormat,
+        decimals: u8,
+    ) -> UtxoRpcRes<BigDecimal>;
 
     /// returns fee estimation per KByte in satoshis
     fn estimate_fee_sat(
@@ -459,9 +469,15 @@ impl JsonRpcClient for NativeClientImpl {
 
 #[cfg_attr(test, mockable)]
 impl UtxoRpcClientOps for NativeClient {
-    fn list_unspent(&self, address: &Address, decimals: u8) -> UtxoRpcRes<Vec<UnspentInfo>> {
+    fn list_unspent(
+        &self,
+        address: &Address,
+        address_format: &UtxoAddressFormat,
+        decimals: u8,
+    ) -> UtxoRpcRes<Vec<UnspentInfo>> {
+        let address = try_fus!(pubkey_hash_address_string(address, address_format));
         let fut = self
-            .list_unspent_impl(0, std::i32::MAX, vec![address.to_string()])
+            .list_unspent_impl(0, std::i32::MAX, vec![address])
             .map_err(|e| ERRL!("{}", e))
             .and_then(move |unspents| {
                 let unspents: Result<Vec<_>, _> = unspents
@@ -483,7 +499,7 @@ impl UtxoRpcClientOps for NativeClient {
     }
 
     fn send_transaction(&self, tx: &UtxoTx) -> UtxoRpcRes<H256Json> {
-        let tx_bytes = BytesJson::from(serialize(tx));
+        let tx_bytes = BytesJson::from(serialize_with_flags(tx, SERIALIZE_TRANSACTION_WITNESS));
         Box::new(self.send_raw_transaction(tx_bytes).map_err(|e| ERRL!("{}", e)))
     }
 
@@ -498,9 +514,16 @@ impl UtxoRpcClientOps for NativeClient {
 
     fn get_block_count(&self) -> RpcRes<u64> { self.0.get_block_count() }
 
-    fn display_balance(&self, address: Address, _decimals: u8) -> RpcRes<BigDecimal> {
+    fn display_balance(
+        &self,
+        address: Address,
+        address_format: &UtxoAddressFormat,
+        _decimals: u8,
+    ) -> UtxoRpcRes<BigDecimal> {
+        let address = try_fus!(pubkey_hash_address_string(&address, address_format));
         Box::new(
-            self.list_unspent_impl(0, std::i32::MAX, vec![address.to_string()])
+            self.list_unspent_impl(0, std::i32::MAX, vec![address])
+                .map_err(|e| ERRL!("{}", e))
                 .map(|unspents| {
                     unspents
                         .iter()
@@ -1469,8 +1492,13 @@ impl ElectrumClient {
 
 #[cfg_attr(test, mockable)]
 impl UtxoRpcClientOps for ElectrumClient {
-    fn list_unspent(&self, address: &Address, _decimals: u8) -> UtxoRpcRes<Vec<UnspentInfo>> {
-        let script = Builder::build_p2pkh(&address.hash);
+    fn list_unspent(
+        &self,
+        address: &Address,
+        address_format: &UtxoAddressFormat,
+        _decimals: u8,
+    ) -> UtxoRpcRes<Vec<UnspentInfo>> {
+        let script = output_script(address, address_format);
         let script_hash = electrum_script_hash(&script);
         Box::new(
             self.scripthash_list_unspent(&hex::encode(script_hash))
@@ -1492,7 +1520,7 @@ impl UtxoRpcClientOps for ElectrumClient {
     }
 
     fn send_transaction(&self, tx: &UtxoTx) -> UtxoRpcRes<H256Json> {
-        let bytes = BytesJson::from(serialize(tx));
+        let bytes = BytesJson::from(serialize_with_flags(tx, SERIALIZE_TRANSACTION_WITNESS));
         Box::new(self.blockchain_transaction_broadcast(bytes).map_err(|e| ERRL!("{}", e)))
     }
 
@@ -1514,12 +1542,22 @@ impl UtxoRpcClientOps for ElectrumClient {
 
     fn get_block_count(&self) -> RpcRes<u64> { Box::new(self.blockchain_headers_subscribe().map(|r| r.block_height())) }
 
-    fn display_balance(&self, address: Address, decimals: u8) -> RpcRes<BigDecimal> {
-        let hash = electrum_script_hash(&Builder::build_p2pkh(&address.hash));
+    fn display_balance(
+        &self,
+        address: Address,
+        address_format: &UtxoAddressFormat,
+        decimals: u8,
+    ) -> UtxoRpcRes<BigDecimal> {
+        let hash = electrum_script_hash(&output_script(&address, address_format));
         let hash_str = hex::encode(hash);
-        Box::new(self.scripthash_get_balance(&hash_str).map(move |result| {
-            BigDecimal::from(result.confirmed + result.unconfirmed) / BigDecimal::from(10u64.pow(decimals as u32))
-        }))
+        Box::new(
+            self.scripthash_get_balance(&hash_str)
+                .map_err(|e| ERRL!("{}", e))
+                .map(move |result| {
+                    BigDecimal::from(result.confirmed + result.unconfirmed)
+                        / BigDecimal::from(10u64.pow(decimals as u32))
+                }),
+        )
     }
 
     fn estimate_fee_sat(
@@ -0,0 +1,63 @@
+//! Native SegWit addresses encoded in Bech32 format.
+//! https://github.com/bitcoin/bips/blob/master/bip-0173.mediawiki
+
+use bech32::{self, u5, FromBase32, ToBase32};
+use keys::AddressHash;
+
+/// The only witness version supported now.
+pub const WITNESS_VERSION_0: u8 = 0;
+/// The witness program length of P2WPKH (the hash160 of a public key).
+const P2WPKH_PROGRAM_LEN: usize = 20;
+/// The witness program length of P2WSH (the sha256 of a witness script).
+const P2WSH_PROGRAM_LEN: usize = 32;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SegwitAddress {
+    /// The human-readable part, e.g. "bc" for Bitcoin mainnet or "ltc" for Litecoin.
+    pub hrp: String,
+    pub version: u8,
+    pub program: Vec<u8>,
+}
+
+impl SegwitAddress {
+    /// Creates P2WPKH address paying to the given pubkey hash.
+    pub fn p2wpkh(hrp: &str, hash: &AddressHash) -> SegwitAddress {
+        SegwitAddress {
+            hrp: hrp.to_owned(),
+            version: WITNESS_VERSION_0,
+            program: hash.to_vec(),
+        }
+    }
+
+    pub fn is_p2wpkh(&self) -> bool { self.version == WITNESS_VERSION_0 && self.program.len() == P2WPKH_PROGRAM_LEN }
+
+    pub fn is_p2wsh(&self) -> bool { self.version == WITNESS_VERSION_0 && self.program.len() == P2WSH_PROGRAM_LEN }
+
+    pub fn encode(&self) -> Result<String, String> {
+        let mut data = vec![try_s!(u5::try_from_u8(self.version))];
+        data.extend(self.program.to_base32());
+        bech32::encode(&self.hrp, data).map_err(|e| ERRL!("{}", e))
+    }
+
+    /// Decodes and validates the Bech32 address according to the BIP173 rules.
+    pub fn decode(address: &str) -> Result<SegwitAddress, String> {
+        let (hrp, data) = try_s!(bech32::decode(address));
+        let (version, program) = match data.split_first() {
+            Some((version, program)) => (version.to_u8(), program),
+            None => return ERR!("Empty data part of the Bech32 address {}", address),
+        };
+        if version > 16 {
+            return ERR!("Invalid witness version {}", version);
+        }
+
+        let program = try_s!(Vec::<u8>::from_base32(program));
+        if program.len() < 2 || program.len() > 40 {
+            return ERR!("Invalid witness program length {}", program.len());
+        }
+        if version == WITNESS_VERSION_0 && program.len() != P2WPKH_PROGRAM_LEN && program.len() != P2WSH_PROGRAM_LEN {
+            return ERR!("Invalid witness v0 program length {}", program.len());
+        }
+
+        Ok(SegwitAddress { hrp, version, program })
+    }
+}
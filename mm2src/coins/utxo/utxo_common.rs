@@ -19,7 +19,7 @@ use rpc::v1::types::{Bytes as BytesJson, H256 as H256Json};
 use script::{Builder, Opcode, Script, ScriptAddress, SignatureVersion, TransactionInputSigner,
              UnsignedTransactionInput};
 use serde_json::{self as json};
-use serialization::{deserialize, serialize};
+use serialization::{deserialize, serialize, serialize_with_flags, SERIALIZE_TRANSACTION_WITNESS};
 use std::cmp::Ordering;
 use std::collections::hash_map::{Entry, HashMap};
 use std::str::FromStr;
@@ -30,8 +30,10 @@ use std::time::Duration;
 pub use chain::Transaction as UtxoTx;
 
 use self::rpc_clients::{electrum_script_hash, UnspentInfo, UtxoRpcClientEnum};
+use self::segwit_address::{SegwitAddress, WITNESS_VERSION_0};
 use crate::utxo::rpc_clients::UtxoRpcClientOps;
-use crate::{FeeApproxStage, TradePreimageError, TradePreimageValue, ValidateAddressResult};
+use crate::{CoinSelectionStrategy, FeeApproxStage, HtlcScriptType, TradePreimageError, TradePreimageValue,
+            ValidateAddressResult};
 use common::{block_on, Traceable};
 
 macro_rules! true_or {
@@ -56,7 +58,7 @@ pub struct UtxoArcBuilder<'a> {
     ticker: &'a str,
     conf: &'a Json,
     req: &'a Json,
-    priv_key: &'a [u8],
+    priv_key: Option<&'a [u8]>,
 }
 
 impl<'a> UtxoArcBuilder<'a> {
@@ -65,7 +67,7 @@ impl<'a> UtxoArcBuilder<'a> {
         ticker: &'a str,
         conf: &'a Json,
         req: &'a Json,
-        priv_key: &'a [u8],
+        priv_key: Option<&'a [u8]>,
     ) -> UtxoArcBuilder<'a> {
         UtxoArcBuilder {
             ctx,
@@ -94,7 +96,7 @@ impl UtxoCoinBuilder for UtxoArcBuilder<'_> {
 
     fn ticker(&self) -> &str { self.ticker }
 
-    fn priv_key(&self) -> &[u8] { self.priv_key }
+    fn priv_key(&self) -> Option<&[u8]> { self.priv_key }
 }
 
 pub async fn utxo_arc_from_conf_and_request<T>(
@@ -102,7 +104,7 @@ pub async fn utxo_arc_from_conf_and_request<T>(
     ticker: &str,
     conf: &Json,
     req: &Json,
-    priv_key: &[u8],
+    priv_key: Option<&[u8]>,
 ) -> Result<T, String>
 where
     T: From<UtxoArc> + AsRef<UtxoCoinFields> + UtxoCommonOps + Send + Sync + 'static,
@@ -175,22 +177,43 @@ where
 }
 
 pub fn addresses_from_script(conf: &UtxoCoinConf, script: &Script) -> Result<Vec<Address>, String> {
+    let addresses = try_s!(addresses_with_script_types_from_script(conf, script));
+    Ok(addresses.into_iter().map(|(address, _script_type)| address).collect())
+}
+
+/// Extracts the destination addresses of the `script` with the types of the script paying to them.
+pub fn addresses_with_script_types_from_script(
+    conf: &UtxoCoinConf,
+    script: &Script,
+) -> Result<Vec<(Address, AddressScriptType)>, String> {
+    if conf.address_format.is_segwit() && script.is_pay_to_witness_key_hash() {
+        // P2WPKH script is `OP_0 OP_PUSHBYTES_20 <pubkey hash>`
+        let address = Address {
+            hash: AddressHash::from(&script[2..22]),
+            checksum_type: conf.checksum_type,
+            prefix: conf.pub_addr_prefix,
+            t_addr_prefix: conf.pub_t_addr_prefix,
+        };
+        return Ok(vec![(address, AddressScriptType::P2WPKH)]);
+    }
+
     let destinations: Vec<ScriptAddress> = try_s!(script.extract_destinations());
 
     let addresses = destinations
         .into_iter()
         .map(|dst| {
-            let (prefix, t_addr_prefix) = match dst.kind {
-                Type::P2PKH => (conf.pub_addr_prefix, conf.pub_t_addr_prefix),
-                Type::P2SH => (conf.p2sh_addr_prefix, conf.p2sh_t_addr_prefix),
+            let (prefix, t_addr_prefix, script_type) = match dst.kind {
+                Type::P2PKH => (conf.pub_addr_prefix, conf.pub_t_addr_prefix, AddressScriptType::P2PKH),
+                Type::P2SH => (conf.p2sh_addr_prefix, conf.p2sh_t_addr_prefix, AddressScriptType::P2SH),
             };
 
-            Address {
+            let address = Address {
                 hash: dst.hash,
                 checksum_type: conf.checksum_type,
                 prefix,
                 t_addr_prefix,
-            }
+            };
+            (address, script_type)
         })
         .collect();
 
@@ -214,11 +237,50 @@ pub fn display_address(conf: &UtxoCoinConf, address: &Address) -> Result<String,
         UtxoAddressFormat::CashAddress { network } => address
             .to_cashaddress(&network, conf.pub_addr_prefix, conf.p2sh_addr_prefix)
             .and_then(|cashaddress| cashaddress.encode()),
+        UtxoAddressFormat::Segwit { hrp } => {
+            if address.prefix == conf.p2sh_addr_prefix && address.t_addr_prefix == conf.p2sh_t_addr_prefix {
+                // P2SH addresses are still displayed in the legacy format
+                Ok(address.to_string())
+            } else {
+                SegwitAddress::p2wpkh(hrp, &address.hash).encode()
+            }
+        },
+    }
+}
+
+/// Displays the `address` paying by the script of the given type.
+/// Unlike `display_address`, the legacy P2PKH addresses are kept in the legacy format when the native SegWit
+/// address format is activated.
+pub fn display_address_with_script_type(
+    conf: &UtxoCoinConf,
+    address: &Address,
+    script_type: AddressScriptType,
+) -> Result<String, String> {
+    match (script_type, &conf.address_format) {
+        (AddressScriptType::P2WPKH, UtxoAddressFormat::Segwit { hrp }) => {
+            SegwitAddress::p2wpkh(hrp, &address.hash).encode()
+        },
+        (AddressScriptType::P2WPKH, _) => match &conf.bech32_hrp {
+            Some(hrp) => SegwitAddress::p2wpkh(hrp, &address.hash).encode(),
+            None => ERR!("{} doesn't support P2WPKH addresses", conf.ticker),
+        },
+        (AddressScriptType::P2PKH, UtxoAddressFormat::Segwit { .. })
+        | (AddressScriptType::P2SH, UtxoAddressFormat::Segwit { .. }) => Ok(address.to_string()),
+        (AddressScriptType::P2PKH, _) | (AddressScriptType::P2SH, _) => display_address(conf, address),
     }
 }
 
 pub fn address_from_str(conf: &UtxoCoinConf, address: &str) -> Result<Address, String> {
-    match &conf.address_format {
+    address_with_script_type_from_str(conf, address).map(|(address, _script_type)| address)
+}
+
+/// Parses the `address` and returns it with the type of the script paying to it.
+/// The legacy P2PKH and P2SH addresses are accepted when the native SegWit address format is activated.
+pub fn address_with_script_type_from_str(
+    conf: &UtxoCoinConf,
+    address: &str,
+) -> Result<(Address, AddressScriptType), String> {
+    let address = match &conf.address_format {
         UtxoAddressFormat::Standard => Address::from_str(address)
             .or_else(|e| match Address::from_cashaddress(
                 &address,
@@ -236,8 +298,40 @@ pub fn address_from_str(conf: &UtxoCoinConf, address: &str) -> Result<Address, S
             .or_else(|e| match Address::from_str(&address) {
                 Ok(_) => ERR!("Cashaddress address format activated for {}, but legacy format used instead. Try to call 'convertaddress'", conf.ticker),
                 Err(_) => ERR!("{}", e),
-            })
+            }),
+        UtxoAddressFormat::Segwit { hrp } => match address_from_segwit(conf, hrp, address) {
+            Ok(a) => return Ok((a, AddressScriptType::P2WPKH)),
+            Err(e) => Address::from_str(address).map_err(|_| e),
+        },
+    }?;
+    let script_type = legacy_address_script_type(conf, &address);
+    Ok((address, script_type))
+}
+
+/// Returns the script type of the legacy or cashaddress `address` by its prefixes.
+fn legacy_address_script_type(conf: &UtxoCoinConf, address: &Address) -> AddressScriptType {
+    if address.prefix == conf.p2sh_addr_prefix && address.t_addr_prefix == conf.p2sh_t_addr_prefix {
+        AddressScriptType::P2SH
+    } else {
+        AddressScriptType::P2PKH
+    }
+}
+
+/// Parses the Bech32 `address` and converts it to the pubkey hash `Address` if it's P2WPKH.
+fn address_from_segwit(conf: &UtxoCoinConf, hrp: &str, address: &str) -> Result<Address, String> {
+    let segwit_address = try_s!(SegwitAddress::decode(address));
+    if segwit_address.hrp != hrp {
+        return ERR!("Address {} has invalid human-readable part, expected {}", address, hrp);
     }
+    if !segwit_address.is_p2wpkh() {
+        return ERR!("Only P2WPKH segwit addresses are supported, {} given", address);
+    }
+    Ok(Address {
+        prefix: conf.pub_addr_prefix,
+        t_addr_prefix: conf.pub_t_addr_prefix,
+        hash: AddressHash::from(segwit_address.program.as_slice()),
+        checksum_type: conf.checksum_type,
+    })
 }
 
 pub async fn get_current_mtp(coin: &UtxoCoinFields) -> Result<u32, String> {
@@ -256,6 +350,49 @@ where
     Box::new(fut.boxed().compat().map(|tx| tx.into()))
 }
 
+/// Sends the swap payment outputs by BIP125 replaceable transaction, see [`bump_payment_fee`].
+pub fn send_replaceable_outputs_from_my_address<T>(coin: T, outputs: Vec<TransactionOutput>) -> TransactionFut
+where
+    T: AsRef<UtxoCoinFields> + UtxoCommonOps + Send + Sync + 'static,
+{
+    let fut = send_replaceable_outputs_from_my_address_impl(coin, outputs);
+    Box::new(fut.boxed().compat().map(|tx| tx.into()))
+}
+
+/// Sends the swap payment outputs by BIP125 replaceable transaction only if `replaceable` is set,
+/// the payment accepted by the counterparty without confirmations must not be replaceable.
+fn send_payment_outputs<T>(coin: T, outputs: Vec<TransactionOutput>, replaceable: bool) -> TransactionFut
+where
+    T: AsRef<UtxoCoinFields> + UtxoCommonOps + Send + Sync + 'static,
+{
+    if replaceable {
+        send_replaceable_outputs_from_my_address(coin, outputs)
+    } else {
+        send_outputs_from_my_address(coin, outputs)
+    }
+}
+
+/// Replaces the stuck swap payment with the same payment paying the higher fee.
+pub fn bump_payment_fee<T>(coin: T, payment_tx: &[u8]) -> TransactionFut
+where
+    T: AsRef<UtxoCoinFields> + UtxoCommonOps + Send + Sync + 'static,
+{
+    let mut payment: UtxoTx = try_fus!(deserialize(payment_tx).map_err(|e| ERRL!("{:?}", e)));
+    payment.tx_hash_algo = coin.as_ref().tx_hash_algo;
+    let fut = replace_tx_paying_higher_fee(coin, payment);
+    Box::new(fut.boxed().compat().map(|tx| tx.into()))
+}
+
+pub fn send_cpfp_child<T>(coin: T, parent_tx: &[u8]) -> TransactionFut
+where
+    T: AsRef<UtxoCoinFields> + UtxoCommonOps + Send + Sync + 'static,
+{
+    let mut parent: UtxoTx = try_fus!(deserialize(parent_tx).map_err(|e| ERRL!("{:?}", e)));
+    parent.tx_hash_algo = coin.as_ref().tx_hash_algo;
+    let fut = send_cpfp_child_impl(coin, parent);
+    Box::new(fut.boxed().compat().map(|tx| tx.into()))
+}
+
 /// Generates unsigned transaction (TransactionInputSigner) from specified utxos and outputs.
 /// This function expects that utxos are sorted by amounts in ascending order
 /// Consider sorting before calling this function
@@ -278,7 +415,7 @@ where
 {
     let dust: u64 = coin.as_ref().dust_amount;
     let lock_time = (now_ms() / 1000) as u32;
-    let change_script_pubkey = Builder::build_p2pkh(&coin.as_ref().my_address.hash).to_bytes();
+    let change_script_pubkey = output_script(&coin.as_ref().my_address, &coin.as_ref().conf.address_format).to_bytes();
     let coin_tx_fee = match fee {
         Some(f) => f,
         None => try_map!(coin.get_tx_fee().await, GenerateTransactionError::Other),
@@ -366,9 +503,13 @@ where
             ActualTxFee::Dynamic(f) => {
                 let transaction = UtxoTx::from(tx.clone());
                 let transaction_bytes = serialize(&transaction);
-                // 2 bytes are used to indicate the length of signature and pubkey
-                // total is 107
-                let additional_len = 2 + MAX_DER_SIGNATURE_LEN + COMPRESSED_PUBKEY_LEN;
+                let additional_len = if coin.as_ref().conf.address_format.is_segwit() {
+                    P2WPKH_WITNESS_VSIZE
+                } else {
+                    // 2 bytes are used to indicate the length of signature and pubkey
+                    // total is 107
+                    2 + MAX_DER_SIGNATURE_LEN + COMPRESSED_PUBKEY_LEN
+                };
                 let tx_size = transaction_bytes.len() + transaction.inputs().len() * additional_len;
                 (f * tx_size as u64) / KILO_BYTE
             },
@@ -529,6 +670,8 @@ where
     Ok((unsigned, data))
 }
 
+/// Generates the transaction spending the HTLC output 0 of the `prev_transaction`.
+/// Note the output can be either P2SH or P2WSH, the type is detected by the output script.
 pub fn p2sh_spending_tx(
     coin: &UtxoCoinFields,
     prev_transaction: UtxoTx,
@@ -536,6 +679,7 @@ pub fn p2sh_spending_tx(
     outputs: Vec<TransactionOutput>,
     script_data: Script,
     sequence: u32,
+    key_pair: &SigningKey,
 ) -> Result<UtxoTx, String> {
     // https://github.com/bitcoin/bitcoin/blob/master/doc/release-notes/release-notes-0.11.2.md#bip113-mempool-only-locktime-enforcement-using-getmediantimepast
     // Implication for users: GetMedianTimePast() always trails behind the current time,
@@ -584,15 +728,27 @@ pub fn p2sh_spending_tx(
         str_d_zeel,
         hash_algo,
     };
-    let signed_input = try_s!(p2sh_spend(
-        &unsigned,
-        0,
-        &coin.key_pair,
-        script_data,
-        redeem_script.into(),
-        coin.conf.signature_version,
-        coin.conf.fork_id
-    ));
+    let prev_script_pubkey: Script = prev_transaction.outputs[0].script_pubkey.clone().into();
+    let signed_input = if prev_script_pubkey.is_pay_to_witness_script_hash() {
+        try_s!(p2wsh_spend(
+            &unsigned,
+            0,
+            key_pair,
+            script_data,
+            redeem_script.into(),
+            coin.conf.fork_id
+        ))
+    } else {
+        try_s!(p2sh_spend(
+            &unsigned,
+            0,
+            key_pair,
+            script_data,
+            redeem_script.into(),
+            coin.conf.signature_version,
+            coin.conf.fork_id
+        ))
+    };
     Ok(UtxoTx {
         version: unsigned.version,
         n_time: unsigned.n_time,
@@ -639,6 +795,9 @@ pub fn send_maker_payment<T>(
     taker_pub: &[u8],
     secret_hash: &[u8],
     amount: BigDecimal,
+    htlc_script_type: HtlcScriptType,
+    replaceable: bool,
+    swap_unique_data: &[u8],
 ) -> TransactionFut
 where
     T: AsRef<UtxoCoinFields> + UtxoCommonOps + Clone + Send + Sync + 'static,
@@ -651,19 +810,18 @@ where
         time_lock,
         taker_pub,
         secret_hash,
-        amount
+        amount,
+        htlc_script_type,
+        swap_unique_data,
     ));
     let send_fut = match &coin.as_ref().rpc_client {
-        UtxoRpcClientEnum::Electrum(_) => Either::A(send_outputs_from_my_address(coin, outputs)),
-        UtxoRpcClientEnum::Native(client) => {
-            let addr_string = try_fus!(coin.display_address(&payment_address));
-            Either::B(
-                client
-                    .import_address(&addr_string, &addr_string, false)
-                    .map_err(|e| ERRL!("{}", e))
-                    .and_then(move |_| send_outputs_from_my_address(coin, outputs)),
-            )
-        },
+        UtxoRpcClientEnum::Electrum(_) => Either::A(send_payment_outputs(coin, outputs, replaceable)),
+        UtxoRpcClientEnum::Native(client) => Either::B(
+            client
+                .import_address(&payment_address, &payment_address, false)
+                .map_err(|e| ERRL!("{}", e))
+                .and_then(move |_| send_payment_outputs(coin, outputs, replaceable)),
+        ),
     };
     Box::new(send_fut)
 }
@@ -674,6 +832,9 @@ pub fn send_taker_payment<T>(
     maker_pub: &[u8],
     secret_hash: &[u8],
     amount: BigDecimal,
+    htlc_script_type: HtlcScriptType,
+    replaceable: bool,
+    swap_unique_data: &[u8],
 ) -> TransactionFut
 where
     T: AsRef<UtxoCoinFields> + UtxoCommonOps + Clone + Send + Sync + 'static,
@@ -686,19 +847,18 @@ where
         time_lock,
         maker_pub,
         secret_hash,
-        amount
+        amount,
+        htlc_script_type,
+        swap_unique_data,
     ));
     let send_fut = match &coin.as_ref().rpc_client {
-        UtxoRpcClientEnum::Electrum(_) => Either::A(send_outputs_from_my_address(coin, outputs)),
-        UtxoRpcClientEnum::Native(client) => {
-            let addr_string = try_fus!(coin.display_address(&payment_address));
-            Either::B(
-                client
-                    .import_address(&addr_string, &addr_string, false)
-                    .map_err(|e| ERRL!("{}", e))
-                    .and_then(move |_| send_outputs_from_my_address(coin, outputs)),
-            )
-        },
+        UtxoRpcClientEnum::Electrum(_) => Either::A(send_payment_outputs(coin, outputs, replaceable)),
+        UtxoRpcClientEnum::Native(client) => Either::B(
+            client
+                .import_address(&payment_address, &payment_address, false)
+                .map_err(|e| ERRL!("{}", e))
+                .and_then(move |_| send_payment_outputs(coin, outputs, replaceable)),
+        ),
     };
     Box::new(send_fut)
 }
@@ -709,6 +869,7 @@ pub fn send_maker_spends_taker_payment<T>(
     time_lock: u32,
     taker_pub: &[u8],
     secret: &[u8],
+    swap_unique_data: &[u8],
 ) -> TransactionFut
 where
     T: AsRef<UtxoCoinFields> + UtxoCommonOps + Send + Sync + 'static,
@@ -719,20 +880,27 @@ where
         .push_data(secret)
         .push_opcode(Opcode::OP_0)
         .into_script();
+    let key_pair = try_fus!(derive_htlc_key_pair(coin.as_ref(), swap_unique_data));
     let redeem_script = payment_script(
         time_lock,
         &*dhash160(secret),
         &try_fus!(Public::from_slice(taker_pub)),
-        coin.as_ref().key_pair.public(),
+        key_pair.public(),
     );
     let fut = async move {
         let fee = try_s!(coin.get_htlc_spend_fee().await);
         let output = TransactionOutput {
             value: prev_tx.outputs[0].value - fee,
-            script_pubkey: Builder::build_p2pkh(&coin.as_ref().key_pair.public().address_hash()).to_bytes(),
+            script_pubkey: output_script(&coin.as_ref().my_address, &coin.as_ref().conf.address_format).to_bytes(),
         };
-        let transaction =
-            try_s!(coin.p2sh_spending_tx(prev_tx, redeem_script.into(), vec![output], script_data, SEQUENCE_FINAL,));
+        let transaction = try_s!(coin.p2sh_spending_tx(
+            prev_tx,
+            redeem_script.into(),
+            vec![output],
+            script_data,
+            SEQUENCE_FINAL,
+            &key_pair,
+        ));
         let tx_fut = coin.as_ref().rpc_client.send_transaction(&transaction).compat();
         try_s!(tx_fut.await);
         Ok(transaction.into())
@@ -746,6 +914,7 @@ pub fn send_taker_spends_maker_payment<T>(
     time_lock: u32,
     maker_pub: &[u8],
     secret: &[u8],
+    swap_unique_data: &[u8],
 ) -> TransactionFut
 where
     T: AsRef<UtxoCoinFields> + UtxoCommonOps + Send + Sync + 'static,
@@ -756,20 +925,27 @@ where
         .push_data(secret)
         .push_opcode(Opcode::OP_0)
         .into_script();
+    let key_pair = try_fus!(derive_htlc_key_pair(coin.as_ref(), swap_unique_data));
     let redeem_script = payment_script(
         time_lock,
         &*dhash160(secret),
         &try_fus!(Public::from_slice(maker_pub)),
-        coin.as_ref().key_pair.public(),
+        key_pair.public(),
     );
     let fut = async move {
         let fee = try_s!(coin.get_htlc_spend_fee().await);
         let output = TransactionOutput {
             value: prev_tx.outputs[0].value - fee,
-            script_pubkey: Builder::build_p2pkh(&coin.as_ref().key_pair.public().address_hash()).to_bytes(),
+            script_pubkey: output_script(&coin.as_ref().my_address, &coin.as_ref().conf.address_format).to_bytes(),
         };
-        let transaction =
-            try_s!(coin.p2sh_spending_tx(prev_tx, redeem_script.into(), vec![output], script_data, SEQUENCE_FINAL,));
+        let transaction = try_s!(coin.p2sh_spending_tx(
+            prev_tx,
+            redeem_script.into(),
+            vec![output],
+            script_data,
+            SEQUENCE_FINAL,
+            &key_pair,
+        ));
         let tx_fut = coin.as_ref().rpc_client.send_transaction(&transaction).compat();
         try_s!(tx_fut.await);
         Ok(transaction.into())
@@ -783,6 +959,7 @@ pub fn send_taker_refunds_payment<T>(
     time_lock: u32,
     maker_pub: &[u8],
     secret_hash: &[u8],
+    swap_unique_data: &[u8],
 ) -> TransactionFut
 where
     T: AsRef<UtxoCoinFields> + UtxoCommonOps + Send + Sync + 'static,
@@ -790,17 +967,18 @@ where
     let mut prev_tx: UtxoTx = try_fus!(deserialize(taker_payment_tx).map_err(|e| ERRL!("{:?}", e)));
     prev_tx.tx_hash_algo = coin.as_ref().tx_hash_algo;
     let script_data = Builder::default().push_opcode(Opcode::OP_1).into_script();
+    let key_pair = try_fus!(derive_htlc_key_pair(coin.as_ref(), swap_unique_data));
     let redeem_script = payment_script(
         time_lock,
         secret_hash,
-        coin.as_ref().key_pair.public(),
+        key_pair.public(),
         &try_fus!(Public::from_slice(maker_pub)),
     );
     let fut = async move {
         let fee = try_s!(coin.get_htlc_spend_fee().await);
         let output = TransactionOutput {
             value: prev_tx.outputs[0].value - fee,
-            script_pubkey: Builder::build_p2pkh(&coin.as_ref().key_pair.public().address_hash()).to_bytes(),
+            script_pubkey: output_script(&coin.as_ref().my_address, &coin.as_ref().conf.address_format).to_bytes(),
         };
         let transaction = try_s!(coin.p2sh_spending_tx(
             prev_tx,
@@ -808,6 +986,7 @@ where
             vec![output],
             script_data,
             SEQUENCE_FINAL - 1,
+            &key_pair,
         ));
         let tx_fut = coin.as_ref().rpc_client.send_transaction(&transaction).compat();
         try_s!(tx_fut.await);
@@ -822,6 +1001,7 @@ pub fn send_maker_refunds_payment<T>(
     time_lock: u32,
     taker_pub: &[u8],
     secret_hash: &[u8],
+    swap_unique_data: &[u8],
 ) -> TransactionFut
 where
     T: AsRef<UtxoCoinFields> + UtxoCommonOps + Send + Sync + 'static,
@@ -829,17 +1009,18 @@ where
     let mut prev_tx: UtxoTx = try_fus!(deserialize(maker_payment_tx).map_err(|e| ERRL!("{:?}", e)));
     prev_tx.tx_hash_algo = coin.as_ref().tx_hash_algo;
     let script_data = Builder::default().push_opcode(Opcode::OP_1).into_script();
+    let key_pair = try_fus!(derive_htlc_key_pair(coin.as_ref(), swap_unique_data));
     let redeem_script = payment_script(
         time_lock,
         secret_hash,
-        coin.as_ref().key_pair.public(),
+        key_pair.public(),
         &try_fus!(Public::from_slice(taker_pub)),
     );
     let fut = async move {
         let fee = try_s!(coin.get_htlc_spend_fee().await);
         let output = TransactionOutput {
             value: prev_tx.outputs[0].value - fee,
-            script_pubkey: Builder::build_p2pkh(&coin.as_ref().key_pair.public().address_hash()).to_bytes(),
+            script_pubkey: output_script(&coin.as_ref().my_address, &coin.as_ref().conf.address_format).to_bytes(),
         };
         let transaction = try_s!(coin.p2sh_spending_tx(
             prev_tx,
@@ -847,6 +1028,7 @@ where
             vec![output],
             script_data,
             SEQUENCE_FINAL - 1,
+            &key_pair,
         ));
         let tx_fut = coin.as_ref().rpc_client.send_transaction(&transaction).compat();
         try_s!(tx_fut.await);
@@ -886,7 +1068,7 @@ where
                 .await
         );
 
-        if tx_from_rpc.0 != serialize(&tx).take() {
+        if tx_from_rpc.0 != serialize_with_flags(&tx, SERIALIZE_TRANSACTION_WITNESS).take() {
             return ERR!(
                 "Provided dex fee tx {:?} doesn't match tx data from rpc {:?}",
                 tx,
@@ -928,19 +1110,22 @@ pub fn validate_maker_payment<T>(
     maker_pub: &[u8],
     priv_bn_hash: &[u8],
     amount: BigDecimal,
+    htlc_script_type: HtlcScriptType,
+    swap_unique_data: &[u8],
 ) -> Box<dyn Future<Item = (), Error = String> + Send>
 where
     T: AsRef<UtxoCoinFields> + Clone + Send + Sync + 'static,
 {
-    let my_public = coin.as_ref().key_pair.public();
+    let my_key_pair = try_fus!(derive_htlc_key_pair(coin.as_ref(), swap_unique_data));
     validate_payment(
         coin.clone(),
         payment_tx,
         time_lock,
         &try_fus!(Public::from_slice(maker_pub)),
-        my_public,
+        my_key_pair.public(),
         priv_bn_hash,
         amount,
+        htlc_script_type,
     )
 }
 
@@ -951,19 +1136,22 @@ pub fn validate_taker_payment<T>(
     taker_pub: &[u8],
     priv_bn_hash: &[u8],
     amount: BigDecimal,
+    htlc_script_type: HtlcScriptType,
+    swap_unique_data: &[u8],
 ) -> Box<dyn Future<Item = (), Error = String> + Send>
 where
     T: AsRef<UtxoCoinFields> + Clone + Send + Sync + 'static,
 {
-    let my_public = coin.as_ref().key_pair.public();
+    let my_key_pair = try_fus!(derive_htlc_key_pair(coin.as_ref(), swap_unique_data));
     validate_payment(
         coin.clone(),
         payment_tx,
         time_lock,
         &try_fus!(Public::from_slice(taker_pub)),
-        my_public,
+        my_key_pair.public(),
         priv_bn_hash,
         amount,
+        htlc_script_type,
     )
 }
 
@@ -972,19 +1160,22 @@ pub fn check_if_my_payment_sent<T>(
     time_lock: u32,
     other_pub: &[u8],
     secret_hash: &[u8],
+    htlc_script_type: HtlcScriptType,
+    swap_unique_data: &[u8],
 ) -> Box<dyn Future<Item = Option<TransactionEnum>, Error = String> + Send>
 where
     T: AsRef<UtxoCoinFields> + UtxoCommonOps + Send + Sync + 'static,
 {
+    let my_key_pair = try_fus!(derive_htlc_key_pair(coin.as_ref(), swap_unique_data));
     let script = payment_script(
         time_lock,
         secret_hash,
-        coin.as_ref().key_pair.public(),
+        my_key_pair.public(),
         &try_fus!(Public::from_slice(other_pub)),
     );
-    let hash = dhash160(&script);
-    let p2sh = Builder::build_p2sh(&hash);
-    let script_hash = electrum_script_hash(&p2sh);
+    let htlc_script_pubkey = htlc_script_pubkey(&script, htlc_script_type);
+    let script_hash = electrum_script_hash(&htlc_script_pubkey);
+    let target_addr = try_fus!(htlc_address_string(&coin.as_ref().conf, &script, htlc_script_type));
     let fut = async move {
         match &coin.as_ref().rpc_client {
             UtxoRpcClientEnum::Electrum(client) => {
@@ -1000,21 +1191,21 @@ where
                 }
             },
             UtxoRpcClientEnum::Native(client) => {
-                let target_addr = Address {
-                    t_addr_prefix: coin.as_ref().conf.p2sh_t_addr_prefix,
-                    prefix: coin.as_ref().conf.p2sh_addr_prefix,
-                    hash,
-                    checksum_type: coin.as_ref().conf.checksum_type,
-                };
-                let target_addr = target_addr.to_string();
                 let is_imported = try_s!(client.is_address_imported(&target_addr).await);
                 if !is_imported {
                     return Ok(None);
                 }
                 let received_by_addr = try_s!(client.list_received_by_address(0, true, true).compat().await);
                 for item in received_by_addr {
-                    if item.address == target_addr && !item.txids.is_empty() {
-                        let tx_bytes = try_s!(client.get_transaction_bytes(item.txids[0].clone()).compat().await);
+                    if item.address != target_addr {
+                        continue;
+                    }
+                    // the wallet keeps the payments replaced by `bump_payment_fee`, but they can't be found anymore
+                    for txid in item.txids {
+                        let tx_bytes = match client.get_transaction_bytes(txid).compat().await {
+                            Ok(bytes) => bytes,
+                            Err(_) => continue,
+                        };
                         let mut tx: UtxoTx = try_s!(deserialize(tx_bytes.0.as_slice()).map_err(|e| ERRL!("{:?}", e)));
                         tx.tx_hash_algo = coin.as_ref().tx_hash_algo;
                         return Ok(Some(tx.into()));
@@ -1034,11 +1225,13 @@ pub fn search_for_swap_tx_spend_my(
     secret_hash: &[u8],
     tx: &[u8],
     search_from_block: u64,
+    swap_unique_data: &[u8],
 ) -> Result<Option<FoundSwapTxSpend>, String> {
+    let my_key_pair = try_s!(derive_htlc_key_pair(coin, swap_unique_data));
     block_on(search_for_swap_tx_spend(
         coin,
         time_lock,
-        coin.key_pair.public(),
+        my_key_pair.public(),
         &try_s!(Public::from_slice(other_pub)),
         secret_hash,
         tx,
@@ -1053,12 +1246,14 @@ pub fn search_for_swap_tx_spend_other(
     secret_hash: &[u8],
     tx: &[u8],
     search_from_block: u64,
+    swap_unique_data: &[u8],
 ) -> Result<Option<FoundSwapTxSpend>, String> {
+    let my_key_pair = try_s!(derive_htlc_key_pair(coin, swap_unique_data));
     block_on(search_for_swap_tx_spend(
         coin,
         time_lock,
         &try_s!(Public::from_slice(other_pub)),
-        coin.key_pair.public(),
+        my_key_pair.public(),
         secret_hash,
         tx,
         search_from_block,
@@ -1066,10 +1261,21 @@ pub fn search_for_swap_tx_spend_other(
 }
 
 /// Extract a secret from the `spend_tx`.
-/// Note spender could generate the spend with several inputs where the only one input is the p2sh script.
+/// Note spender could generate the spend with several inputs where the only one input is the p2sh or p2wsh script.
 pub fn extract_secret(secret_hash: &[u8], spend_tx: &[u8]) -> Result<Vec<u8>, String> {
     let spend_tx: UtxoTx = try_s!(deserialize(spend_tx).map_err(|e| ERRL!("{:?}", e)));
     for (input_idx, input) in spend_tx.inputs.into_iter().enumerate() {
+        if !input.script_witness.is_empty() {
+            // the p2wsh spend witness is `<sig> <secret> OP_0 <witness script>`
+            match input.script_witness.get(1) {
+                Some(secret) if secret.len() == 32 && &*dhash160(secret) == secret_hash => return Ok(secret.to_vec()),
+                _ => {
+                    log!("Warning: couldn't find secret in the witness of "[input_idx]" input");
+                    continue;
+                },
+            }
+        }
+
         let script: Script = input.script_sig.clone().into();
         let instruction = match script.get_instruction(1) {
             Some(Ok(instr)) => instr,
@@ -1116,7 +1322,7 @@ where
 pub fn my_balance(coin: &UtxoCoinFields) -> Box<dyn Future<Item = BigDecimal, Error = String> + Send> {
     Box::new(
         coin.rpc_client
-            .display_balance(coin.my_address.clone(), coin.decimals)
+            .display_balance(coin.my_address.clone(), &coin.conf.address_format, coin.decimals)
             .map_err(|e| ERRL!("{}", e)),
     )
 }
@@ -1202,7 +1408,13 @@ where
     coin.display_address(&addr)
 }
 
-pub fn display_priv_key(coin: &UtxoCoinFields) -> String { format!("{}", coin.key_pair.private()) }
+/// The private key isn't available in the watch-only mode, returns the empty string then.
+pub fn display_priv_key(coin: &UtxoCoinFields) -> String {
+    match coin.key_pair.private() {
+        Some(private) => format!("{}", private),
+        None => String::new(),
+    }
+}
 
 pub fn min_tx_amount(coin: &UtxoCoinFields) -> BigDecimal {
     big_decimal_from_sat(coin.dust_amount as i64, coin.decimals)
@@ -1214,91 +1426,119 @@ pub async fn withdraw<T>(coin: T, req: WithdrawRequest) -> Result<TransactionDet
 where
     T: AsRef<UtxoCoinFields> + UtxoCommonOps + MarketCoinOps,
 {
-    let to = try_s!(coin.address_from_str(&req.to));
-
-    let conf = &coin.as_ref().conf;
-    let is_p2pkh = to.prefix == conf.pub_addr_prefix && to.t_addr_prefix == conf.pub_t_addr_prefix;
-    let is_p2sh = to.prefix == conf.p2sh_addr_prefix && to.t_addr_prefix == conf.p2sh_t_addr_prefix && conf.segwit;
-
-    let script_pubkey = if is_p2pkh {
-        Builder::build_p2pkh(&to.hash)
-    } else if is_p2sh {
-        Builder::build_p2sh(&to.hash)
-    } else {
-        return ERR!("Address {} has invalid format", to);
-    };
-
-    if to.checksum_type != coin.as_ref().conf.checksum_type {
-        return ERR!(
-            "Address {} has invalid checksum type, it must be {:?}",
-            to,
-            coin.as_ref().conf.checksum_type
-        );
+    let decimals = coin.as_ref().decimals;
+    let (to, script_pubkey) = try_s!(withdraw_address_script(&coin, &req.to));
+    let mut to_addresses = vec![to];
+
+    let mut additional_outputs = Vec::with_capacity(req.outputs.len() + 1);
+    for output in req.outputs.iter() {
+        let (address, script_pubkey) = try_s!(withdraw_address_script(&coin, &output.address));
+        let value = try_s!(sat_from_big_decimal(&output.amount, decimals));
+        to_addresses.push(address);
+        additional_outputs.push(TransactionOutput { value, script_pubkey });
     }
-
-    let script_pubkey = script_pubkey.to_bytes();
+    if let Some(memo) = &req.memo {
+        additional_outputs.push(try_s!(memo_output(memo)));
+    }
+    let change_script_pubkey = match &req.change_address {
+        Some(address) => Some(try_s!(withdraw_address_script(&coin, address)).1),
+        None => None,
+    };
 
     let _utxo_lock = UTXO_LOCK.lock().await;
-    let unspents = try_s!(coin
+    let mut unspents = try_s!(coin
         .ordered_mature_unspents(&coin.as_ref().my_address)
         .compat()
         .await
         .map_err(|e| ERRL!("{}", e)));
+    if !req.inputs.is_empty() {
+        unspents = try_s!(requested_unspents(unspents, &req.inputs));
+    }
     let (value, fee_policy) = if req.max {
-        (
-            unspents.iter().fold(0, |sum, unspent| sum + unspent.value),
-            FeePolicy::DeductFromOutput(0),
-        )
+        let unspents_value = unspents.iter().fold(0, |sum, unspent| sum + unspent.value);
+        let additional_value = additional_outputs.iter().fold(0, |sum, output| sum + output.value);
+        match unspents_value.checked_sub(additional_value) {
+            Some(value) => (value, FeePolicy::DeductFromOutput(0)),
+            None => return ERR!("The additional outputs value {} exceeds the balance", additional_value),
+        }
     } else {
         (
-            try_s!(sat_from_big_decimal(&req.amount, coin.as_ref().decimals)),
+            try_s!(sat_from_big_decimal(&req.amount, decimals)),
             FeePolicy::SendExact,
         )
     };
-    let outputs = vec![TransactionOutput { value, script_pubkey }];
-    let fee = match req.fee {
-        Some(WithdrawFee::UtxoFixed { amount }) => Some(ActualTxFee::Fixed(try_s!(sat_from_big_decimal(
-            &amount,
-            coin.as_ref().decimals
-        )))),
-        Some(WithdrawFee::UtxoPerKbyte { amount }) => Some(ActualTxFee::Dynamic(try_s!(sat_from_big_decimal(
-            &amount,
-            coin.as_ref().decimals
-        )))),
+    let mut outputs = vec![TransactionOutput { value, script_pubkey }];
+    outputs.extend(additional_outputs);
+    let requested_outputs_count = outputs.len();
+
+    let mut fee = match req.fee {
+        Some(WithdrawFee::UtxoFixed { amount }) => {
+            Some(ActualTxFee::Fixed(try_s!(sat_from_big_decimal(&amount, decimals))))
+        },
+        Some(WithdrawFee::UtxoPerKbyte { amount }) => {
+            Some(ActualTxFee::Dynamic(try_s!(sat_from_big_decimal(&amount, decimals))))
+        },
         Some(_) => return ERR!("Unsupported input fee type"),
         None => None,
     };
+    // all the unspents are spent if `max` is set
+    if let Some(strategy) = req.coin_selection.filter(|_| !req.max) {
+        let tx_fee = match fee {
+            Some(fee) => fee,
+            None => try_s!(coin.get_tx_fee().await),
+        };
+        unspents = select_unspents(coin.as_ref(), unspents, &outputs, &tx_fee, strategy);
+        fee = Some(tx_fee);
+    }
     let gas_fee = None;
-    let (unsigned, data) = try_s!(
+    let (mut unsigned, mut data) = try_s!(
         coin.generate_transaction(unspents, outputs, fee_policy, fee, gas_fee)
             .await
     );
-    let prev_script = Builder::build_p2pkh(&coin.as_ref().my_address.hash);
-    let signed = try_s!(sign_tx(
-        unsigned,
-        &coin.as_ref().key_pair,
-        prev_script,
-        coin.as_ref().conf.signature_version,
-        coin.as_ref().conf.fork_id
-    ));
+
+    // `generate_transaction` appends the change to my address
+    let my_script_pubkey = output_script(&coin.as_ref().my_address, &coin.as_ref().conf.address_format).to_bytes();
+    if let Some(change_script_pubkey) = change_script_pubkey.filter(|script| *script != my_script_pubkey) {
+        for output in unsigned.outputs.iter_mut().skip(requested_outputs_count) {
+            output.script_pubkey = change_script_pubkey.clone();
+            data.received_by_me -= output.value;
+        }
+    }
+
+    let prev_script = output_script(&coin.as_ref().my_address, &coin.as_ref().conf.address_format);
+    let (tx_hex, tx_hash, psbt) = if req.psbt {
+        let psbt = try_s!(unsigned_psbt(&coin, unsigned, &prev_script).await);
+        (
+            BytesJson::default(),
+            BytesJson::default(),
+            Some(base64::encode(&psbt.serialize())),
+        )
+    } else {
+        let signed = try_s!(sign_tx(
+            unsigned,
+            &coin.as_ref().key_pair,
+            prev_script,
+            coin.as_ref().conf.signature_version,
+            coin.as_ref().conf.fork_id
+        ));
+        let tx_hex = serialize_with_flags(&signed, SERIALIZE_TRANSACTION_WITNESS).into();
+        (tx_hex, signed.hash().reversed().to_vec().into(), None)
+    };
     let fee_amount = data.fee_amount + data.unused_change.unwrap_or_default();
     let fee_details = UtxoFeeDetails {
-        amount: big_decimal_from_sat(fee_amount as i64, coin.as_ref().decimals),
+        amount: big_decimal_from_sat(fee_amount as i64, decimals),
     };
     let my_address = try_s!(coin.my_address());
-    let to_address = try_s!(coin.display_address(&to));
     Ok(TransactionDetails {
         from: vec![my_address],
-        to: vec![to_address],
-        total_amount: big_decimal_from_sat(data.spent_by_me as i64, coin.as_ref().decimals),
-        spent_by_me: big_decimal_from_sat(data.spent_by_me as i64, coin.as_ref().decimals),
-        received_by_me: big_decimal_from_sat(data.received_by_me as i64, coin.as_ref().decimals),
-        my_balance_change: big_decimal_from_sat(
-            data.received_by_me as i64 - data.spent_by_me as i64,
-            coin.as_ref().decimals,
-        ),
-        tx_hash: signed.hash().reversed().to_vec().into(),
-        tx_hex: serialize(&signed).into(),
+        to: to_addresses,
+        total_amount: big_decimal_from_sat(data.spent_by_me as i64, decimals),
+        spent_by_me: big_decimal_from_sat(data.spent_by_me as i64, decimals),
+        received_by_me: big_decimal_from_sat(data.received_by_me as i64, decimals),
+        my_balance_change: big_decimal_from_sat(data.received_by_me as i64 - data.spent_by_me as i64, decimals),
+        tx_hash,
+        tx_hex,
+        psbt,
         fee_details: Some(fee_details.into()),
         block_height: 0,
         coin: coin.as_ref().conf.ticker.clone(),
@@ -1307,6 +1547,308 @@ where
     })
 }
 
+/// Creates the PSBT of the `unsigned` transaction spending my `prev_script` outputs to be signed externally.
+async fn unsigned_psbt<T>(
+    coin: &T,
+    unsigned: TransactionInputSigner,
+    prev_script: &Script,
+) -> Result<psbt::Psbt, String>
+where
+    T: AsRef<UtxoCoinFields>,
+{
+    let conf = &coin.as_ref().conf;
+    // the legacy sighash doesn't commit to the spent amounts, the signer has to check them by the previous transactions
+    let legacy_sighash = matches!(conf.signature_version, SignatureVersion::Base)
+        && !unsigned.overwintered
+        && !prev_script.is_pay_to_witness_key_hash();
+    let mut psbt_inputs = Vec::with_capacity(unsigned.inputs.len());
+    let mut tx_inputs = Vec::with_capacity(unsigned.inputs.len());
+    for input in unsigned.inputs.iter() {
+        let mut psbt_input = psbt::PsbtInput {
+            sighash_type: Some(1 | conf.fork_id),
+            ..Default::default()
+        };
+        if legacy_sighash {
+            let prev_tx_bytes = try_s!(
+                coin.as_ref()
+                    .rpc_client
+                    .get_transaction_bytes(input.previous_output.hash.reversed().into())
+                    .compat()
+                    .await
+            );
+            let prev_tx: UtxoTx = try_s!(deserialize(prev_tx_bytes.0.as_slice()).map_err(|e| ERRL!("{:?}", e)));
+            psbt_input.non_witness_utxo = Some(prev_tx);
+        } else {
+            psbt_input.witness_utxo = Some(TransactionOutput {
+                value: input.amount,
+                script_pubkey: prev_script.to_bytes(),
+            });
+        }
+        psbt_inputs.push(psbt_input);
+        tx_inputs.push(TransactionInput {
+            previous_output: input.previous_output.clone(),
+            script_sig: Bytes::default(),
+            sequence: input.sequence,
+            script_witness: vec![],
+        });
+    }
+    Ok(psbt::Psbt::new(tx_from_signer(unsigned, tx_inputs), psbt_inputs))
+}
+
+/// Builds the transaction of the externally signed base64 `psbt` and broadcasts it.
+/// Returns the raw transaction and its hash.
+pub async fn finalize_psbt(coin: &UtxoCoinFields, psbt: &str) -> Result<(BytesJson, String), String> {
+    let psbt_bytes = try_s!(base64::decode(psbt));
+    let psbt = try_s!(psbt::Psbt::deserialize(&psbt_bytes));
+    let tx_hex: BytesJson = serialize_with_flags(&try_s!(psbt.finalize()), SERIALIZE_TRANSACTION_WITNESS).into();
+    let tx_hash = try_s!(send_raw_tx(coin, &hex::encode(&tx_hex.0)).compat().await);
+    Ok((tx_hex, tx_hash))
+}
+
+/// Pays all the recipients by the single transaction with one output per recipient.
+pub async fn withdraw_batch<T>(coin: T, req: WithdrawBatchRequest) -> Result<WithdrawBatchResponse, String>
+where
+    T: AsRef<UtxoCoinFields> + UtxoCommonOps + MarketCoinOps,
+{
+    if req.use_multisend {
+        return ERR!("use_multisend is supported by ETH/ERC20 coins only");
+    }
+    let mut recipients = req.recipients.into_iter();
+    let first = match recipients.next() {
+        Some(r) => r,
+        None => return ERR!("recipients list is empty"),
+    };
+    let outputs: Vec<_> = recipients.collect();
+    let withdraw_req = WithdrawRequest {
+        coin: req.coin,
+        to: first.address.clone(),
+        amount: first.amount.clone(),
+        fee: req.fee,
+        outputs: outputs.clone(),
+        ..Default::default()
+    };
+    let tx = try_s!(withdraw(coin, withdraw_req).await);
+    let recipients = std::iter::once(first)
+        .chain(outputs)
+        .map(|output| WithdrawBatchRecipient {
+            address: output.address,
+            amount: output.amount,
+            tx_hash: tx.tx_hash.clone(),
+        })
+        .collect();
+    Ok(WithdrawBatchResponse {
+        transactions: vec![tx],
+        recipients,
+    })
+}
+
+/// Parses the withdraw destination address and returns its display string with the output script paying to it.
+fn withdraw_address_script<T>(coin: &T, address: &str) -> Result<(String, Bytes), String>
+where
+    T: AsRef<UtxoCoinFields> + UtxoCommonOps,
+{
+    let conf = &coin.as_ref().conf;
+    let (address, script_type) = try_s!(address_with_script_type_from_str(conf, address));
+
+    let is_p2pkh = address.prefix == conf.pub_addr_prefix && address.t_addr_prefix == conf.pub_t_addr_prefix;
+    let is_p2sh =
+        address.prefix == conf.p2sh_addr_prefix && address.t_addr_prefix == conf.p2sh_t_addr_prefix && conf.segwit;
+
+    let script_pubkey = match script_type {
+        AddressScriptType::P2PKH | AddressScriptType::P2WPKH if is_p2pkh => address_script(&address, script_type),
+        AddressScriptType::P2SH if is_p2sh => address_script(&address, script_type),
+        _ => return ERR!("Address {} has invalid format", address),
+    };
+
+    if address.checksum_type != conf.checksum_type {
+        return ERR!(
+            "Address {} has invalid checksum type, it must be {:?}",
+            address,
+            conf.checksum_type
+        );
+    }
+
+    let display = try_s!(display_address_with_script_type(conf, &address, script_type));
+    Ok((display, script_pubkey.to_bytes()))
+}
+
+/// Returns the OP_RETURN output recording the `memo`.
+fn memo_output(memo: &str) -> Result<TransactionOutput, String> {
+    if memo.len() > MAX_OP_RETURN_DATA_LEN {
+        return ERR!(
+            "Memo is {} bytes long, it can't exceed {} bytes",
+            memo.len(),
+            MAX_OP_RETURN_DATA_LEN
+        );
+    }
+    let script_pubkey = Builder::default()
+        .push_opcode(Opcode::OP_RETURN)
+        .push_bytes(memo.as_bytes())
+        .into_bytes();
+    Ok(TransactionOutput {
+        value: 0,
+        script_pubkey,
+    })
+}
+
+/// Parses the `txid:vout` outpoint.
+fn outpoint_from_str(input: &str) -> Result<OutPoint, String> {
+    let mut parts = input.split(':');
+    let (txid, vout) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(txid), Some(vout), None) => (txid, vout),
+        _ => return ERR!("Input {} must have the txid:vout form", input),
+    };
+    let txid = try_s!(hex::decode(txid));
+    if txid.len() != 32 {
+        return ERR!("Input {} txid must be 32 bytes long", input);
+    }
+    Ok(OutPoint {
+        // txid is displayed in the reversed byte order
+        hash: H256::from(txid.as_slice()).reversed(),
+        index: try_s!(vout.parse()),
+    })
+}
+
+/// Returns the unspents requested by the `txid:vout` inputs.
+fn requested_unspents(unspents: Vec<UnspentInfo>, inputs: &[String]) -> Result<Vec<UnspentInfo>, String> {
+    let mut outpoints = Vec::with_capacity(inputs.len());
+    for input in inputs.iter() {
+        outpoints.push(try_s!(outpoint_from_str(input)));
+    }
+    for (input, outpoint) in inputs.iter().zip(outpoints.iter()) {
+        if !unspents.iter().any(|unspent| unspent.outpoint == *outpoint) {
+            return ERR!("Input {} is not found among the mature unspents of my address", input);
+        }
+    }
+    Ok(unspents
+        .into_iter()
+        .filter(|unspent| outpoints.contains(&unspent.outpoint))
+        .collect())
+}
+
+/// Orders the unspents by the coin selection `strategy`, `generate_transaction` spends them in this order.
+/// The branch and bound strategy leaves only the unspents that pay the outputs without the change, if they're found.
+fn select_unspents(
+    coin: &UtxoCoinFields,
+    mut unspents: Vec<UnspentInfo>,
+    outputs: &[TransactionOutput],
+    fee: &ActualTxFee,
+    strategy: CoinSelectionStrategy,
+) -> Vec<UnspentInfo> {
+    match strategy {
+        CoinSelectionStrategy::LargestFirst => unspents.sort_by(|a, b| b.value.cmp(&a.value)),
+        CoinSelectionStrategy::OldestFirst => unspents.sort_by_key(|unspent| unspent.height.unwrap_or(u64::MAX)),
+        CoinSelectionStrategy::BranchAndBound => {
+            let outputs_value: u64 = outputs.iter().map(|output| output.value).sum();
+            let (input_fee, target, cost_of_change) = match fee {
+                ActualTxFee::Fixed(fee) => (0, outputs_value + fee, coin.dust_amount),
+                ActualTxFee::Dynamic(fee_per_kb) => {
+                    // the value, the script length and the script
+                    let outputs_len: u64 = outputs.iter().map(|output| 9 + output.script_pubkey.len() as u64).sum();
+                    let tx_fee = fee_per_kb * (TX_OVERHEAD_LEN + outputs_len) / KILO_BYTE;
+                    (
+                        fee_per_kb * my_input_len(&coin.conf) / KILO_BYTE,
+                        outputs_value + tx_fee,
+                        coin.dust_amount + fee_per_kb * P2PKH_OUTPUT_LEN / KILO_BYTE,
+                    )
+                },
+            };
+            match branch_and_bound(&unspents, target, input_fee, cost_of_change) {
+                Some(selected) => return selected,
+                None => unspents.sort_by(|a, b| b.value.cmp(&a.value)),
+            }
+        },
+    }
+    unspents
+}
+
+/// The estimated size of my input including the signature and the pubkey.
+fn my_input_len(conf: &UtxoCoinConf) -> u64 {
+    // the outpoint, the script length and the sequence
+    let base_len = 32 + 4 + 1 + 4;
+    let additional_len = if conf.address_format.is_segwit() {
+        P2WPKH_WITNESS_VSIZE
+    } else {
+        2 + MAX_DER_SIGNATURE_LEN + COMPRESSED_PUBKEY_LEN
+    };
+    (base_len + additional_len) as u64
+}
+
+/// Searches for the unspents whose value minus their input fee covers the `target`
+/// and exceeds it by less than `cost_of_change`, so the transaction doesn't need the change output.
+/// https://murch.one/wp-content/uploads/2016/11/erhardt2016coinselection.pdf
+fn branch_and_bound(
+    unspents: &[UnspentInfo],
+    target: u64,
+    input_fee: u64,
+    cost_of_change: u64,
+) -> Option<Vec<UnspentInfo>> {
+    struct Search<'a> {
+        /// The effective values of the unspents in the descending order.
+        candidates: Vec<(u64, &'a UnspentInfo)>,
+        /// The sum of the candidates effective values starting from the index.
+        remaining: Vec<u64>,
+        selected: Vec<bool>,
+        target: u64,
+        upper_bound: u64,
+        tries: usize,
+    }
+
+    impl<'a> Search<'a> {
+        fn run(&mut self, mut index: usize, value: u64) -> bool {
+            loop {
+                self.tries += 1;
+                if self.tries > BNB_MAX_TRIES || value > self.upper_bound || value + self.remaining[index] < self.target
+                {
+                    return false;
+                }
+                if value >= self.target {
+                    return true;
+                }
+                // try the branch including the candidate, then exclude it and go on
+                self.selected[index] = true;
+                if self.run(index + 1, value + self.candidates[index].0) {
+                    return true;
+                }
+                self.selected[index] = false;
+                index += 1;
+            }
+        }
+    }
+
+    let mut candidates: Vec<_> = unspents
+        .iter()
+        .filter(|unspent| unspent.value > input_fee)
+        .map(|unspent| (unspent.value - input_fee, unspent))
+        .collect();
+    candidates.sort_by(|a, b| b.0.cmp(&a.0));
+    let mut remaining = vec![0; candidates.len() + 1];
+    for (i, (value, _)) in candidates.iter().enumerate().rev() {
+        remaining[i] = remaining[i + 1] + value;
+    }
+
+    let mut search = Search {
+        selected: vec![false; candidates.len()],
+        candidates,
+        remaining,
+        target,
+        upper_bound: target + cost_of_change,
+        tries: 0,
+    };
+    if !search.run(0, 0) {
+        return None;
+    }
+    Some(
+        search
+            .candidates
+            .iter()
+            .zip(search.selected.iter())
+            .filter(|(_, selected)| **selected)
+            .map(|((_, unspent), _)| (*unspent).clone())
+            .collect(),
+    )
+}
+
 pub fn decimals(coin: &UtxoCoinFields) -> u8 { coin.decimals }
 
 pub fn convert_to_address<T>(coin: &T, from: &str, to_address_format: Json) -> Result<String, String>
@@ -1325,6 +1867,16 @@ where
                 coin.as_ref().conf.p2sh_addr_prefix
             )
             .and_then(|cashaddress| cashaddress.encode()))),
+        UtxoAddressFormat::Segwit { hrp } => {
+            let conf = &coin.as_ref().conf;
+            if from_address.prefix != conf.pub_addr_prefix || from_address.t_addr_prefix != conf.pub_t_addr_prefix {
+                return ERR!(
+                    "Only pubkey hash addresses can be converted to segwit format, {} given",
+                    from
+                );
+            }
+            SegwitAddress::p2wpkh(&hrp, &from_address.hash).encode()
+        },
     }
 }
 
@@ -1368,7 +1920,7 @@ where
     T: AsRef<UtxoCoinFields> + UtxoStandardOps + UtxoCommonOps + MmCoin + MarketCoinOps,
 {
     let mut my_balance: Option<BigDecimal> = None;
-    let history = coin.load_history_from_file(&ctx);
+    let history = coin.load_history_from_db(&ctx);
     let mut history_map: HashMap<H256Json, TransactionDetails> = history
         .into_iter()
         .map(|tx| (H256Json::from(tx.tx_hash.as_slice()), tx))
@@ -1505,18 +2057,8 @@ where
                 },
             }
             if updated {
-                let mut to_write: Vec<&TransactionDetails> = history_map.iter().map(|(_, value)| value).collect();
-                // the transactions with block_height == 0 are the most recent so we need to separately handle them while sorting
-                to_write.sort_unstable_by(|a, b| {
-                    if a.block_height == 0 {
-                        Ordering::Less
-                    } else if b.block_height == 0 {
-                        Ordering::Greater
-                    } else {
-                        b.block_height.cmp(&a.block_height)
-                    }
-                });
-                coin.save_history_to_file(&unwrap!(json::to_vec(&to_write)), &ctx);
+                let to_write: Vec<&TransactionDetails> = history_map.iter().map(|(_, value)| value).collect();
+                coin.save_history_to_db(&ctx, &to_write);
             }
         }
         *unwrap!(coin.as_ref().history_sync_state.lock()) = HistorySyncState::Finished;
@@ -1588,7 +2130,7 @@ where
                 .collect()
         },
         UtxoRpcClientEnum::Electrum(client) => {
-            let script = Builder::build_p2pkh(&coin.as_ref().my_address.hash);
+            let script = output_script(&coin.as_ref().my_address, &coin.as_ref().conf.address_format);
             let script_hash = electrum_script_hash(&script);
 
             mm_counter!(metrics, "tx.history.request.count", 1,
@@ -1673,13 +2215,14 @@ where
             Entry::Occupied(e) => e.into_mut(),
         };
         input_amount += input_tx.outputs[input.previous_output.index as usize].value;
-        let from: Vec<Address> = try_s!(coin.addresses_from_script(
+        let from = try_s!(addresses_with_script_types_from_script(
+            &coin.as_ref().conf,
             &input_tx.outputs[input.previous_output.index as usize]
                 .script_pubkey
                 .clone()
                 .into()
         ));
-        if from.contains(&coin.as_ref().my_address) {
+        if from.iter().any(|(address, _)| *address == coin.as_ref().my_address) {
             spent_by_me += input_tx.outputs[input.previous_output.index as usize].value;
         }
         from_addresses.push(from);
@@ -1687,8 +2230,11 @@ where
 
     for output in tx.outputs.iter() {
         output_amount += output.value;
-        let to = try_s!(coin.addresses_from_script(&output.script_pubkey.clone().into()));
-        if to.contains(&coin.as_ref().my_address) {
+        let to = try_s!(addresses_with_script_types_from_script(
+            &coin.as_ref().conf,
+            &output.script_pubkey.clone().into()
+        ));
+        if to.iter().any(|(address, _)| *address == coin.as_ref().my_address) {
             received_by_me += output.value;
         }
         to_addresses.push(to);
@@ -1698,14 +2244,14 @@ where
     let mut from_addresses: Vec<String> = try_s!(from_addresses
         .into_iter()
         .flatten()
-        .map(|addr| coin.display_address(&addr))
+        .map(|(addr, script_type)| display_address_with_script_type(&coin.as_ref().conf, &addr, script_type))
         .collect());
     from_addresses.sort();
     from_addresses.dedup();
     let mut to_addresses: Vec<String> = try_s!(to_addresses
         .into_iter()
         .flatten()
-        .map(|addr| coin.display_address(&addr))
+        .map(|(addr, script_type)| display_address_with_script_type(&coin.as_ref().conf, &addr, script_type))
         .collect());
     to_addresses.sort();
     to_addresses.dedup();
@@ -1725,6 +2271,7 @@ where
         coin: coin.as_ref().conf.ticker.clone(),
         internal_id: tx.hash().reversed().to_vec().into(),
         timestamp: verbose_tx.time.into(),
+        psbt: None,
     })
 }
 
@@ -1747,6 +2294,7 @@ where
         Ok(TradeFee {
             coin: ticker,
             amount: big_decimal_from_sat(amount as i64, decimals).into(),
+            fee_details: None,
         })
     };
     Box::new(fut.boxed().compat())
@@ -1830,8 +2378,14 @@ where
         let time_lock = (now_ms() / 1000) as u32;
         let other_pub = &[0; 33]; // H264 is 33 bytes
         let secret_hash = &[0; 20]; // H160 is 20 bytes
+                                    // P2WSH output is larger than P2SH, so take the worst case if it can be negotiated
+        let htlc_script_type = if is_p2wsh_htlc_supported(&coin.as_ref().conf) {
+            HtlcScriptType::P2WSH
+        } else {
+            HtlcScriptType::P2SH
+        };
         let SwapPaymentOutputsResult { outputs, .. } = try_map!(
-            generate_swap_payment_outputs(&coin, time_lock, other_pub, secret_hash, amount),
+            generate_swap_payment_outputs(&coin, time_lock, other_pub, secret_hash, amount, htlc_script_type),
             TradePreimageError::Other
         );
         let gas_fee = None;
@@ -1842,6 +2396,7 @@ where
         Ok(TradeFee {
             coin: coin.as_ref().conf.ticker.clone(),
             amount: fee_amount.into(),
+            fee_details: None,
         })
     };
     Box::new(fut.boxed().compat())
@@ -1856,10 +2411,35 @@ where
     let trade_fee = TradeFee {
         coin: coin.as_ref().conf.ticker.clone(),
         amount: 0.into(),
+        fee_details: None,
     };
     Box::new(futures01::future::ok(trade_fee))
 }
 
+/// The child-pays-for-parent transaction pays for the swap spend or refund and itself at the current fee rate in the worst case.
+/// It's paid only if the spend stalls, so the balance isn't checked.
+pub fn get_cpfp_child_fee<T>(
+    coin: T,
+    stage: FeeApproxStage,
+) -> Box<dyn Future<Item = Option<TradeFee>, Error = TradePreimageError> + Send>
+where
+    T: AsRef<UtxoCoinFields> + UtxoCommonOps + Send + Sync + 'static,
+{
+    let fut = async move {
+        let fee_per_kb = match try_map!(coin.get_tx_fee().await, TradePreimageError::Other) {
+            ActualTxFee::Dynamic(fee_per_kb) => coin.increase_dynamic_fee_by_stage(fee_per_kb, &stage),
+            ActualTxFee::Fixed(_) => return Ok(None),
+        };
+        let amount = fee_per_kb * (SWAP_TX_SPEND_SIZE + CPFP_CHILD_TX_SIZE) / KILO_BYTE;
+        Ok(Some(TradeFee {
+            coin: coin.as_ref().conf.ticker.clone(),
+            amount: big_decimal_from_sat(amount as i64, coin.as_ref().decimals).into(),
+            fee_details: None,
+        }))
+    };
+    Box::new(fut.boxed().compat())
+}
+
 pub fn get_fee_to_send_taker_fee<T>(
     coin: T,
     dex_fee_amount: BigDecimal,
@@ -1886,6 +2466,7 @@ where
         Ok(TradeFee {
             coin: coin.ticker().to_owned(),
             amount: fee_amount.into(),
+            fee_details: None,
         })
     };
     Box::new(fut.boxed().compat())
@@ -2086,6 +2667,10 @@ where
 /// Swap contract address is not used by standard UTXO coins.
 pub fn swap_contract_address() -> Option<BytesJson> { None }
 
+/// P2WSH swap payments require the SegWit support and the Bech32 human-readable part
+/// to import the payment address to the native daemon.
+pub fn is_p2wsh_htlc_supported(conf: &UtxoCoinConf) -> bool { conf.segwit && conf.bech32_hrp.is_some() }
+
 /// Convert satoshis to BigDecimal amount of coin units
 pub fn big_decimal_from_sat(satoshis: i64, decimals: u8) -> BigDecimal {
     BigDecimal::from(satoshis) / BigDecimal::from(10u64.pow(decimals as u32))
@@ -2105,7 +2690,7 @@ pub fn address_from_raw_pubkey(
     })
 }
 
-/// Try to parse address from either cashaddress or standard UTXO address format.
+/// Try to parse address from either cashaddress, segwit or standard UTXO address format.
 fn address_from_any_format(conf: &UtxoCoinConf, from: &str) -> Result<Address, String> {
     let standard_err = match Address::from_str(from) {
         Ok(a) => return Ok(a),
@@ -2118,10 +2703,24 @@ fn address_from_any_format(conf: &UtxoCoinConf, from: &str) -> Result<Address, S
             Err(e) => e,
         };
 
+    let segwit_err = match SegwitAddress::decode(from) {
+        Ok(segwit) if segwit.is_p2wpkh() => {
+            return Ok(Address {
+                prefix: conf.pub_addr_prefix,
+                t_addr_prefix: conf.pub_t_addr_prefix,
+                hash: AddressHash::from(segwit.program.as_slice()),
+                checksum_type: conf.checksum_type,
+            })
+        },
+        Ok(_) => ERRL!("Only P2WPKH segwit addresses are supported"),
+        Err(e) => e,
+    };
+
     ERR!(
-        "error on parse standard address: {:?}, error on parse cashaddress: {:?}",
+        "error on parse standard address: {:?}, error on parse cashaddress: {:?}, error on parse segwit address: {:?}",
         standard_err,
         cashaddress_err,
+        segwit_err,
     )
 }
 
@@ -2133,6 +2732,7 @@ fn validate_payment<T>(
     second_pub0: &Public,
     priv_bn_hash: &[u8],
     amount: BigDecimal,
+    htlc_script_type: HtlcScriptType,
 ) -> Box<dyn Future<Item = (), Error = String> + Send>
 where
     T: AsRef<UtxoCoinFields> + Send + Sync + 'static,
@@ -2172,7 +2772,7 @@ where
                     continue;
                 },
             };
-            if serialize(&tx).take() != tx_from_rpc.0 {
+            if serialize_with_flags(&tx, SERIALIZE_TRANSACTION_WITNESS).take() != tx_from_rpc.0 {
                 return ERR!(
                     "Provided payment tx {:?} doesn't match tx data from rpc {:?}",
                     tx,
@@ -2182,7 +2782,7 @@ where
 
             let expected_output = TransactionOutput {
                 value: amount,
-                script_pubkey: Builder::build_p2sh(&dhash160(&expected_redeem)).into(),
+                script_pubkey: htlc_script_pubkey(&expected_redeem, htlc_script_type).into(),
             };
 
             if tx.outputs[0] != expected_output {
@@ -2210,8 +2810,9 @@ async fn search_for_swap_tx_spend(
     let mut tx: UtxoTx = try_s!(deserialize(tx).map_err(|e| ERRL!("{:?}", e)));
     tx.tx_hash_algo = coin.tx_hash_algo;
     let script = payment_script(time_lock, secret_hash, first_pub, second_pub);
-    let expected_script_pubkey = Builder::build_p2sh(&dhash160(&script)).to_bytes();
-    if tx.outputs[0].script_pubkey != expected_script_pubkey {
+    let is_p2wsh = tx.outputs[0].script_pubkey == htlc_script_pubkey(&script, HtlcScriptType::P2WSH).to_bytes();
+    let expected_script_pubkey = htlc_script_pubkey(&script, HtlcScriptType::P2SH).to_bytes();
+    if !is_p2wsh && tx.outputs[0].script_pubkey != expected_script_pubkey {
         return ERR!(
             "Transaction {:?} output 0 script_pubkey doesn't match expected {:?}",
             tx,
@@ -2226,6 +2827,20 @@ async fn search_for_swap_tx_spend(
             .await
     );
     match spend {
+        Some(mut tx) if is_p2wsh => {
+            tx.tx_hash_algo = coin.tx_hash_algo;
+            // the spend witness is `<sig> <secret> OP_0 <witness script>`
+            // and the refund witness is `<sig> OP_1 <witness script>`
+            let witness = &tx.inputs[0].script_witness;
+            if witness.len() == 4 && witness[2].is_empty() {
+                return Ok(Some(FoundSwapTxSpend::Spent(tx.into())));
+            }
+            if witness.len() == 3 && witness[1].as_slice() == [1] {
+                return Ok(Some(FoundSwapTxSpend::Refunded(tx.into())));
+            }
+
+            ERR!("Couldn't find required witness items in input 0 of tx {:?}", tx)
+        },
         Some(mut tx) => {
             tx.tx_hash_algo = coin.tx_hash_algo;
             let script: Script = tx.inputs[0].script_sig.clone().into();
@@ -2251,7 +2866,8 @@ async fn search_for_swap_tx_spend(
 }
 
 struct SwapPaymentOutputsResult {
-    payment_address: Address,
+    /// The HTLC address string that can be imported to the native daemon.
+    payment_address: String,
     outputs: Vec<TransactionOutput>,
 }
 
@@ -2261,21 +2877,23 @@ fn generate_swap_payment_outputs<T>(
     other_pub: &[u8],
     secret_hash: &[u8],
     amount: BigDecimal,
+    htlc_script_type: HtlcScriptType,
+    swap_unique_data: &[u8],
 ) -> Result<SwapPaymentOutputsResult, String>
 where
     T: AsRef<UtxoCoinFields>,
 {
+    let my_key_pair = try_s!(derive_htlc_key_pair(coin.as_ref(), swap_unique_data));
     let redeem_script = payment_script(
         time_lock,
         secret_hash,
-        coin.as_ref().key_pair.public(),
+        my_key_pair.public(),
         &try_s!(Public::from_slice(other_pub)),
     );
-    let redeem_script_hash = dhash160(&redeem_script);
     let amount = try_s!(sat_from_big_decimal(&amount, coin.as_ref().decimals));
     let htlc_out = TransactionOutput {
         value: amount,
-        script_pubkey: Builder::build_p2sh(&redeem_script_hash).into(),
+        script_pubkey: htlc_script_pubkey(&redeem_script, htlc_script_type).into(),
     };
     // record secret hash to blockchain too making it impossible to lose
     // lock time may be easily brute forced so it is not mandatory to record it
@@ -2288,12 +2906,11 @@ where
         script_pubkey: secret_hash_op_return_script,
     };
 
-    let payment_address = Address {
-        checksum_type: coin.as_ref().conf.checksum_type,
-        hash: redeem_script_hash,
-        prefix: coin.as_ref().conf.p2sh_addr_prefix,
-        t_addr_prefix: coin.as_ref().conf.p2sh_t_addr_prefix,
-    };
+    let payment_address = try_s!(htlc_address_string(
+        &coin.as_ref().conf,
+        &redeem_script,
+        htlc_script_type
+    ));
     let result = SwapPaymentOutputsResult {
         payment_address,
         outputs: vec![htlc_out, secret_hash_op_return_out],
@@ -2301,6 +2918,97 @@ where
     Ok(result)
 }
 
+/// Derives the HTLC key pair unique for the swap from the coin private key and the `swap_unique_data`,
+/// so the swaps can't be linked to each other and to the node pubkey on-chain.
+/// The coin key pair is used if `swap_unique_data` is empty, that's the case of the swaps with the legacy peers.
+pub fn derive_htlc_key_pair(coin: &UtxoCoinFields, swap_unique_data: &[u8]) -> Result<SigningKey, String> {
+    let private = match &coin.key_pair {
+        SigningKey::KeyPair(key_pair) => key_pair.private(),
+        // the external signer derives the key the same way, see `ExternalSigner`
+        SigningKey::External { public, signer, .. } => {
+            let public = if swap_unique_data.is_empty() {
+                public.clone()
+            } else {
+                try_s!(signer.public_key(swap_unique_data))
+            };
+            return Ok(SigningKey::External {
+                public,
+                derivation: swap_unique_data.to_vec(),
+                signer: signer.clone(),
+            });
+        },
+    };
+    if swap_unique_data.is_empty() {
+        return KeyPair::from_private(private.clone())
+            .map(SigningKey::from)
+            .map_err(|e| ERRL!("{}", e));
+    }
+
+    let mut preimage = private.secret.to_vec();
+    preimage.extend_from_slice(swap_unique_data);
+    let secret = sha256(&preimage);
+    // the hash is zero or exceeds the curve order with a negligible probability, but the key must not be invalid
+    if let Err(e) = secp256k1::SecretKey::parse(&secret.take()) {
+        return ERR!("The derived HTLC secret is not a valid secp256k1 scalar: {:?}", e);
+    }
+    let private = Private {
+        prefix: private.prefix,
+        secret,
+        compressed: true,
+        checksum_type: private.checksum_type,
+    };
+    KeyPair::from_private(private)
+        .map(SigningKey::from)
+        .map_err(|e| ERRL!("{}", e))
+}
+
+pub fn derive_htlc_pubkey(coin: &UtxoCoinFields, swap_unique_data: &[u8]) -> Result<Vec<u8>, String> {
+    let key_pair = try_s!(derive_htlc_key_pair(coin, swap_unique_data));
+    Ok(key_pair.public().to_vec())
+}
+
+/// Builds the output script locking the swap payment with the given `redeem_script`.
+fn htlc_script_pubkey(redeem_script: &Script, htlc_script_type: HtlcScriptType) -> Script {
+    match htlc_script_type {
+        HtlcScriptType::P2SH => Builder::build_p2sh(&dhash160(redeem_script)),
+        HtlcScriptType::P2WSH => Builder::default()
+            .push_opcode(Opcode::OP_0)
+            .push_bytes(&*sha256(redeem_script))
+            .into_script(),
+    }
+}
+
+/// Returns the address string of the swap payment output that can be understood by the coin daemon.
+fn htlc_address_string(
+    conf: &UtxoCoinConf,
+    redeem_script: &Script,
+    htlc_script_type: HtlcScriptType,
+) -> Result<String, String> {
+    match htlc_script_type {
+        HtlcScriptType::P2SH => {
+            let address = Address {
+                checksum_type: conf.checksum_type,
+                hash: dhash160(redeem_script),
+                prefix: conf.p2sh_addr_prefix,
+                t_addr_prefix: conf.p2sh_t_addr_prefix,
+            };
+            display_address(conf, &address)
+        },
+        HtlcScriptType::P2WSH => {
+            let hrp = match &conf.bech32_hrp {
+                Some(hrp) => hrp,
+                None => return ERR!("{} doesn't support P2WSH swap payments", conf.ticker),
+            };
+            let address = SegwitAddress {
+                hrp: hrp.clone(),
+                version: WITNESS_VERSION_0,
+                program: sha256(redeem_script).to_vec(),
+            };
+            address.encode()
+        },
+    }
+}
+
 fn payment_script(time_lock: u32, secret_hash: &[u8], pub_0: &Public, pub_1: &Public) -> Script {
     let builder = Builder::default();
     builder
@@ -2327,7 +3035,7 @@ fn payment_script(time_lock: u32, secret_hash: &[u8], pub_0: &Public, pub_1: &Pu
 fn p2sh_spend(
     signer: &TransactionInputSigner,
     input_index: usize,
-    key_pair: &KeyPair,
+    key_pair: &SigningKey,
     script_data: Script,
     redeem_script: Script,
     signature_version: SignatureVersion,
@@ -2359,6 +3067,55 @@ fn p2sh_spend(
     })
 }
 
+/// Creates signed input spending hash time locked p2wsh output
+/// https://github.com/bitcoin/bips/blob/master/bip-0143.mediawiki
+fn p2wsh_spend(
+    signer: &TransactionInputSigner,
+    input_index: usize,
+    key_pair: &SigningKey,
+    script_data: Script,
+    witness_script: Script,
+    fork_id: u32,
+) -> Result<TransactionInput, String> {
+    let sighash = signer.signature_hash(
+        input_index,
+        signer.inputs[input_index].amount,
+        &witness_script,
+        SignatureVersion::WitnessV0,
+        1 | fork_id,
+    );
+
+    let sig = try_s!(script_sig(&sighash, &key_pair, fork_id));
+
+    let mut script_witness = vec![sig];
+    script_witness.extend(try_s!(script_data_to_witness(&script_data)));
+    script_witness.push(witness_script.to_bytes());
+
+    Ok(TransactionInput {
+        script_sig: Bytes::default(),
+        sequence: signer.inputs[input_index].sequence,
+        script_witness,
+        previous_output: signer.inputs[input_index].previous_output.clone(),
+    })
+}
+
+/// Converts the `script_data` pushes to the witness stack items.
+/// Note OP_0 and OP_1 are converted to the minimal OP_IF arguments required by the SegWit policy.
+fn script_data_to_witness(script_data: &Script) -> Result<Vec<Bytes>, String> {
+    script_data
+        .iter()
+        .map(|instruction| {
+            let instruction = try_s!(instruction.map_err(|e| ERRL!("{:?}", e)));
+            match (instruction.opcode, instruction.data) {
+                (Opcode::OP_0, _) => Ok(Bytes::default()),
+                (Opcode::OP_1, _) => Ok(vec![1].into()),
+                (_, Some(data)) => Ok(data.to_vec().into()),
+                (opcode, None) => ERR!("Unexpected opcode {:?} in script data", opcode),
+            }
+        })
+        .collect()
+}
+
 #[allow(clippy::needless_lifetimes)]
 pub async fn list_unspent_ordered<'a, T>(
     coin: &'a T,
@@ -2371,7 +3128,7 @@ where
     let mut unspents = try_s!(
         coin.as_ref()
             .rpc_client
-            .list_unspent(address, decimals)
+            .list_unspent(address, &coin.as_ref().conf.address_format, decimals)
             .map_err(|e| ERRL!("{}", e))
             .compat()
             .await
@@ -2446,7 +3203,7 @@ where
             let unspents: Vec<_> = unspents.into_iter().take(max_merge_at_once).collect();
             info!("Trying to merge {} UTXOs of coin {}", unspents.len(), ticker);
             let value = unspents.iter().fold(0, |sum, unspent| sum + unspent.value);
-            let script_pubkey = Builder::build_p2pkh(&coin.as_ref().my_address.hash).to_bytes();
+            let script_pubkey = output_script(&coin.as_ref().my_address, &coin.as_ref().conf.address_format).to_bytes();
             let output = TransactionOutput { value, script_pubkey };
             let merge_tx_fut = generate_and_send_tx(
                 &coin,
@@ -2454,6 +3211,7 @@ where
                 vec![output],
                 FeePolicy::DeductFromOutput(0),
                 recently_spent,
+                false,
             );
             match merge_tx_fut.await {
                 Ok(tx) => info!(
@@ -2,8 +2,9 @@ use super::rpc_clients::{ElectrumProtocol, ListSinceBlockRes, NetworkInfo};
 use super::*;
 use crate::utxo::rpc_clients::{GetAddressInfoRes, UtxoRpcClientOps, ValidateAddressRes};
 use crate::utxo::utxo_standard::{utxo_standard_coin_from_conf_and_request, UtxoStandardCoin};
-use crate::{SwapOps, TradePreimageValue, WithdrawFee};
+use crate::{CoinSelectionStrategy, SwapOps, TradePreimageValue, WithdrawFee, WithdrawOutput};
 use bigdecimal::BigDecimal;
+use chain::constants::SEQUENCE_FINAL;
 use chain::OutPoint;
 use common::mm_ctx::MmCtxBuilder;
 use common::privkey::key_pair_from_seed;
@@ -12,6 +13,7 @@ use futures::future::join_all;
 use gstuff::now_ms;
 use mocktopus::mocking::*;
 use rpc::v1::types::{VerboseBlockClient, H256 as H256Json};
+use script::UnsignedTransactionInput;
 use serialization::deserialize;
 use std::collections::HashMap;
 use std::thread;
@@ -77,6 +79,7 @@ fn utxo_coin_fields_for_test(rpc_client: UtxoRpcClientEnum, force_seed: Option<&
             requires_notarization: false.into(),
             overwintered: true,
             segwit: false,
+            bech32_hrp: None,
             tx_version: 4,
             address_format: UtxoAddressFormat::Standard,
             asset_chain: true,
@@ -104,12 +107,13 @@ fn utxo_coin_fields_for_test(rpc_client: UtxoRpcClientEnum, force_seed: Option<&
         dust_amount: UTXO_DUST_AMOUNT,
         tx_fee: TxFee::Fixed(1000),
         rpc_client,
-        key_pair,
+        key_pair: key_pair.into(),
         my_address,
         history_sync_state: Mutex::new(HistorySyncState::NotEnabled),
         tx_cache_directory: None,
         recently_spent_outpoints: AsyncMutex::new(RecentlySpentOutPoints::new(my_script_pubkey)),
         tx_hash_algo: TxHashAlgo::DSHA256,
+        hd_wallet: None,
     }
 }
 
@@ -416,6 +420,7 @@ fn test_search_for_swap_tx_spend_electrum_was_spent() {
         &payment_tx_bytes,
         0,
         &None,
+        &[],
     )));
     assert_eq!(FoundSwapTxSpend::Spent(spend_tx), found);
 }
@@ -443,6 +448,7 @@ fn test_search_for_swap_tx_spend_electrum_was_refunded() {
         &payment_tx_bytes,
         0,
         &None,
+        &[],
     )));
     assert_eq!(FoundSwapTxSpend::Refunded(refund_tx), found);
 }
@@ -473,6 +479,7 @@ fn test_withdraw_impl_set_fixed_fee() {
         fee: Some(WithdrawFee::UtxoFixed {
             amount: "0.1".parse().unwrap(),
         }),
+        ..Default::default()
     };
     let expected = Some(
         UtxoFeeDetails {
@@ -510,6 +517,7 @@ fn test_withdraw_impl_sat_per_kb_fee() {
         fee: Some(WithdrawFee::UtxoPerKbyte {
             amount: "0.1".parse().unwrap(),
         }),
+        ..Default::default()
     };
     // The resulting transaction size might be 244 or 245 bytes depending on signature size
     // MM2 always expects the worst case during fee calculation
@@ -550,6 +558,7 @@ fn test_withdraw_impl_sat_per_kb_fee_amount_equal_to_max() {
         fee: Some(WithdrawFee::UtxoPerKbyte {
             amount: "0.1".parse().unwrap(),
         }),
+        ..Default::default()
     };
     let tx_details = unwrap!(coin.withdraw(withdraw_req).wait());
     // The resulting transaction size might be 210 or 211 bytes depending on signature size
@@ -592,6 +601,7 @@ fn test_withdraw_impl_sat_per_kb_fee_amount_equal_to_max_dust_included_to_fee()
         fee: Some(WithdrawFee::UtxoPerKbyte {
             amount: "0.09999999".parse().unwrap(),
         }),
+        ..Default::default()
     };
     let tx_details = unwrap!(coin.withdraw(withdraw_req).wait());
     // The resulting transaction size might be 210 or 211 bytes depending on signature size
@@ -634,6 +644,7 @@ fn test_withdraw_impl_sat_per_kb_fee_amount_over_max() {
         fee: Some(WithdrawFee::UtxoPerKbyte {
             amount: "0.1".parse().unwrap(),
         }),
+        ..Default::default()
     };
     unwrap_err!(coin.withdraw(withdraw_req).wait());
 }
@@ -664,6 +675,7 @@ fn test_withdraw_impl_sat_per_kb_fee_max() {
         fee: Some(WithdrawFee::UtxoPerKbyte {
             amount: "0.1".parse().unwrap(),
         }),
+        ..Default::default()
     };
     // The resulting transaction size might be 210 or 211 bytes depending on signature size
     // MM2 always expects the worst case during fee calculation
@@ -678,6 +690,301 @@ fn test_withdraw_impl_sat_per_kb_fee_max() {
     assert_eq!(expected, tx_details.fee_details);
 }
 
+#[test]
+fn test_withdraw_impl_coin_control() {
+    UtxoStandardCoin::ordered_mature_unspents.mock_safe(|_, _| {
+        let unspents = vec![
+            UnspentInfo {
+                outpoint: OutPoint {
+                    hash: 1.into(),
+                    index: 0,
+                },
+                value: 1000000000,
+                height: Default::default(),
+            },
+            UnspentInfo {
+                outpoint: OutPoint {
+                    hash: 2.into(),
+                    index: 1,
+                },
+                value: 2000000000,
+                height: Default::default(),
+            },
+        ];
+        MockResult::Return(Box::new(futures01::future::ok(unspents)))
+    });
+
+    let client = NativeClient(Arc::new(NativeClientImpl::default()));
+
+    let coin = utxo_coin_for_test(UtxoRpcClientEnum::Native(client), None);
+
+    let spent_hash: H256 = 2.into();
+    let withdraw_req = WithdrawRequest {
+        amount: 1.into(),
+        to: "RQq6fWoy8aGGMLjvRfMY5mBNVm2RQxJyLa".to_string(),
+        coin: TEST_COIN_NAME.into(),
+        fee: Some(WithdrawFee::UtxoFixed {
+            amount: "0.1".parse().unwrap(),
+        }),
+        inputs: vec![format!("{}:1", hex::encode(&*spent_hash.reversed()))],
+        outputs: vec![WithdrawOutput {
+            address: "R9o9xTocqr6CeEDGDH6mEYpwLoMz6jNjMW".into(),
+            amount: "0.5".parse().unwrap(),
+        }],
+        memo: Some("payout".into()),
+        change_address: Some("RQq6fWoy8aGGMLjvRfMY5mBNVm2RQxJyLa".into()),
+        ..Default::default()
+    };
+    let tx_details = unwrap!(coin.withdraw(withdraw_req).wait());
+    let tx: UtxoTx = deserialize(tx_details.tx_hex.0.as_slice()).unwrap();
+
+    assert_eq!(tx.inputs.len(), 1);
+    assert_eq!(tx.inputs[0].previous_output.hash, spent_hash);
+    let values: Vec<_> = tx.outputs.iter().map(|output| output.value).collect();
+    // the change is 20 - 1 - 0.5 - 0.1
+    assert_eq!(values, vec![100000000, 50000000, 0, 1840000000]);
+    // the additional output pays to my address, the change goes to the `to` address
+    let my_script_pubkey = output_script(&coin.as_ref().my_address, &coin.as_ref().conf.address_format).to_bytes();
+    assert_eq!(tx.outputs[1].script_pubkey, my_script_pubkey);
+    assert_eq!(tx.outputs[3].script_pubkey, tx.outputs[0].script_pubkey);
+    let memo_script: Script = tx.outputs[2].script_pubkey.clone().into();
+    assert_eq!(memo_script.opcodes().next(), Some(Ok(Opcode::OP_RETURN)));
+    assert_eq!(tx_details.my_balance_change, "-19.5".parse().unwrap());
+}
+
+#[test]
+fn test_withdraw_batch() {
+    UtxoStandardCoin::ordered_mature_unspents.mock_safe(|_, _| {
+        let unspents = vec![UnspentInfo {
+            outpoint: OutPoint {
+                hash: 1.into(),
+                index: 0,
+            },
+            value: 1000000000,
+            height: Default::default(),
+        }];
+        MockResult::Return(Box::new(futures01::future::ok(unspents)))
+    });
+
+    let client = NativeClient(Arc::new(NativeClientImpl::default()));
+
+    let coin = utxo_coin_for_test(UtxoRpcClientEnum::Native(client), None);
+
+    let batch_req = WithdrawBatchRequest {
+        coin: TEST_COIN_NAME.into(),
+        recipients: vec![
+            WithdrawOutput {
+                address: "RQq6fWoy8aGGMLjvRfMY5mBNVm2RQxJyLa".into(),
+                amount: 1.into(),
+            },
+            WithdrawOutput {
+                address: "RQq6fWoy8aGGMLjvRfMY5mBNVm2RQxJyLa".into(),
+                amount: "0.5".parse().unwrap(),
+            },
+        ],
+        fee: Some(WithdrawFee::UtxoFixed {
+            amount: "0.1".parse().unwrap(),
+        }),
+        use_multisend: false,
+    };
+    let response = unwrap!(coin.withdraw_batch(batch_req).wait());
+    assert_eq!(response.transactions.len(), 1);
+    let tx_details = &response.transactions[0];
+    let tx: UtxoTx = deserialize(tx_details.tx_hex.0.as_slice()).unwrap();
+
+    let values: Vec<_> = tx.outputs.iter().map(|output| output.value).collect();
+    // the change is 10 - 1 - 0.5 - 0.1
+    assert_eq!(values, vec![100000000, 50000000, 840000000]);
+    assert_eq!(tx_details.my_balance_change, "-1.6".parse().unwrap());
+    assert_eq!(response.recipients.len(), 2);
+    assert!(response
+        .recipients
+        .iter()
+        .all(|recipient| recipient.tx_hash == tx_details.tx_hash));
+}
+
+#[test]
+fn test_withdraw_segwit_to_legacy_and_segwit_recipients() {
+    UtxoStandardCoin::ordered_mature_unspents.mock_safe(|_, _| {
+        let unspents = vec![UnspentInfo {
+            outpoint: OutPoint {
+                hash: 1.into(),
+                index: 0,
+            },
+            value: 1000000000,
+            height: Default::default(),
+        }];
+        MockResult::Return(Box::new(futures01::future::ok(unspents)))
+    });
+
+    let client = NativeClient(Arc::new(NativeClientImpl::default()));
+    let mut fields = utxo_coin_fields_for_test(UtxoRpcClientEnum::Native(client), None);
+    fields.conf.segwit = true;
+    fields.conf.address_format = UtxoAddressFormat::Segwit { hrp: "rck".into() };
+    let coin = utxo_coin_from_fields(fields);
+
+    let legacy = "RQq6fWoy8aGGMLjvRfMY5mBNVm2RQxJyLa";
+    let hash = Address::from_str(legacy).unwrap().hash;
+    let segwit = SegwitAddress::p2wpkh("rck", &hash).encode().unwrap();
+    let withdraw_req = WithdrawRequest {
+        amount: 1.into(),
+        to: legacy.to_string(),
+        outputs: vec![WithdrawOutput {
+            address: segwit.clone(),
+            amount: "0.5".parse().unwrap(),
+        }],
+        coin: TEST_COIN_NAME.into(),
+        fee: Some(WithdrawFee::UtxoFixed {
+            amount: "0.1".parse().unwrap(),
+        }),
+        ..Default::default()
+    };
+    let tx_details = unwrap!(coin.withdraw(withdraw_req).wait());
+    let tx: UtxoTx = deserialize(tx_details.tx_hex.0.as_slice()).unwrap();
+
+    // the legacy recipient is paid by P2PKH, not by P2WPKH to the same pubkey hash
+    assert_eq!(tx.outputs[0].script_pubkey, Builder::build_p2pkh(&hash).to_bytes());
+    assert_eq!(tx.outputs[1].script_pubkey, build_p2wpkh(&hash).to_bytes());
+    assert_eq!(tx_details.to, vec![legacy.to_string(), segwit]);
+}
+
+#[test]
+fn test_withdraw_psbt_and_finalize() {
+    UtxoStandardCoin::ordered_mature_unspents.mock_safe(|_, _| {
+        let unspents = vec![UnspentInfo {
+            outpoint: OutPoint {
+                hash: 1.into(),
+                index: 0,
+            },
+            value: 1000000000,
+            height: Default::default(),
+        }];
+        MockResult::Return(Box::new(futures01::future::ok(unspents)))
+    });
+
+    let client = NativeClient(Arc::new(NativeClientImpl::default()));
+
+    let coin = utxo_coin_for_test(UtxoRpcClientEnum::Native(client), None);
+
+    let withdraw_req = WithdrawRequest {
+        amount: 1.into(),
+        to: "RQq6fWoy8aGGMLjvRfMY5mBNVm2RQxJyLa".to_string(),
+        coin: TEST_COIN_NAME.into(),
+        fee: Some(WithdrawFee::UtxoFixed {
+            amount: "0.1".parse().unwrap(),
+        }),
+        psbt: true,
+        ..Default::default()
+    };
+    let tx_details = unwrap!(coin.withdraw(withdraw_req).wait());
+    assert!(tx_details.tx_hex.0.is_empty());
+    let psbt_bytes = unwrap!(base64::decode(unwrap!(tx_details.psbt.as_ref())));
+    let mut psbt = unwrap!(psbt::Psbt::deserialize(&psbt_bytes));
+    assert_eq!(psbt.serialize(), psbt_bytes);
+
+    let values: Vec<_> = psbt.unsigned_tx.outputs.iter().map(|output| output.value).collect();
+    assert_eq!(values, vec![100000000, 890000000]);
+    // the test coin is overwintered, so its sighash commits to the spent amounts
+    let prev_script = output_script(&coin.as_ref().my_address, &coin.as_ref().conf.address_format);
+    let expected_prev_output = TransactionOutput {
+        value: 1000000000,
+        script_pubkey: prev_script.to_bytes(),
+    };
+    assert_eq!(psbt.inputs[0].witness_utxo, Some(expected_prev_output));
+    assert_eq!(psbt.inputs[0].non_witness_utxo, None);
+
+    // sign the transaction as the external signer would do
+    let unsigned_tx = psbt.unsigned_tx.clone();
+    let signer = TransactionInputSigner {
+        inputs: vec![UnsignedTransactionInput {
+            previous_output: unsigned_tx.inputs[0].previous_output.clone(),
+            sequence: unsigned_tx.inputs[0].sequence,
+            amount: 1000000000,
+        }],
+        outputs: unsigned_tx.outputs,
+        lock_time: unsigned_tx.lock_time,
+        version: unsigned_tx.version,
+        n_time: unsigned_tx.n_time,
+        overwintered: unsigned_tx.overwintered,
+        expiry_height: unsigned_tx.expiry_height,
+        join_splits: vec![],
+        shielded_spends: vec![],
+        shielded_outputs: vec![],
+        value_balance: 0,
+        version_group_id: unsigned_tx.version_group_id,
+        consensus_branch_id: coin.as_ref().conf.consensus_branch_id,
+        zcash: unsigned_tx.zcash,
+        str_d_zeel: None,
+        hash_algo: coin.as_ref().tx_hash_algo.into(),
+    };
+    let signed = unwrap!(sign_tx(
+        signer,
+        &coin.as_ref().key_pair,
+        prev_script,
+        SignatureVersion::Base,
+        0
+    ));
+    // the P2PKH script sig is `<sig> <pubkey>`, both are pushed by the single length byte
+    let script_sig = &signed.inputs[0].script_sig;
+    let sig_len = script_sig[0] as usize;
+    let sig = script_sig[1..sig_len + 1].to_vec();
+    let pubkey = script_sig[sig_len + 2..].to_vec();
+    assert_eq!(pubkey, coin.as_ref().key_pair.public().to_vec());
+    psbt.inputs[0].partial_sigs.push((pubkey.into(), sig.into()));
+
+    let signed_psbt = unwrap!(psbt::Psbt::deserialize(&psbt.serialize()));
+    let finalized = unwrap!(signed_psbt.finalize());
+    assert_eq!(serialize(&finalized), serialize(&signed));
+}
+
+#[test]
+fn test_withdraw_impl_branch_and_bound() {
+    UtxoStandardCoin::ordered_mature_unspents.mock_safe(|_, _| {
+        let unspents = [100000000, 200000000, 400000000, 500000000]
+            .iter()
+            .enumerate()
+            .map(|(i, value)| UnspentInfo {
+                outpoint: OutPoint {
+                    hash: (i as u64 + 1).into(),
+                    index: 0,
+                },
+                value: *value,
+                height: Default::default(),
+            })
+            .collect();
+        MockResult::Return(Box::new(futures01::future::ok(unspents)))
+    });
+
+    let client = NativeClient(Arc::new(NativeClientImpl::default()));
+
+    let coin = utxo_coin_for_test(UtxoRpcClientEnum::Native(client), None);
+
+    let withdraw_req = WithdrawRequest {
+        amount: "5.9".parse().unwrap(),
+        to: "RQq6fWoy8aGGMLjvRfMY5mBNVm2RQxJyLa".to_string(),
+        coin: TEST_COIN_NAME.into(),
+        fee: Some(WithdrawFee::UtxoFixed {
+            amount: "0.1".parse().unwrap(),
+        }),
+        coin_selection: Some(CoinSelectionStrategy::BranchAndBound),
+        ..Default::default()
+    };
+    let tx_details = unwrap!(coin.withdraw(withdraw_req).wait());
+    let tx: UtxoTx = deserialize(tx_details.tx_hex.0.as_slice()).unwrap();
+
+    // 5 + 1 pay the amount and the fee exactly, so there is no change
+    let spent: Vec<H256> = tx
+        .inputs
+        .iter()
+        .map(|input| input.previous_output.hash.clone())
+        .collect();
+    assert_eq!(spent.len(), 2);
+    assert!(spent.contains(&1.into()));
+    assert!(spent.contains(&4.into()));
+    assert_eq!(tx.outputs.len(), 1);
+    assert_eq!(tx_details.spent_by_me, BigDecimal::from(6));
+}
+
 #[test]
 fn test_ordered_mature_unspents_without_tx_cache() {
     let client = electrum_client_for_test(&["electrum1.cipig.net:10017", "electrum2.cipig.net:10017"]);
@@ -747,7 +1054,11 @@ fn get_tx_details_doge() {
 
     use common::executor::spawn;
     let coin = unwrap!(block_on(utxo_standard_coin_from_conf_and_request(
-        &ctx, "DOGE", &conf, &req, &[1u8; 32]
+        &ctx,
+        "DOGE",
+        &conf,
+        &req,
+        Some(&[1u8; 32])
     )));
 
     let coin1 = coin.clone();
@@ -1084,7 +1395,11 @@ fn test_cashaddresses_in_tx_details_by_hash() {
     let ctx = MmCtxBuilder::new().into_mm_arc();
 
     let coin = unwrap!(block_on(utxo_standard_coin_from_conf_and_request(
-        &ctx, "BCH", &conf, &req, &[1u8; 32]
+        &ctx,
+        "BCH",
+        &conf,
+        &req,
+        Some(&[1u8; 32])
     )));
 
     let hash = hex::decode("0f2f6e0c8f440c641895023782783426c3aca1acc78d7c0db7751995e8aa5751").unwrap();
@@ -1122,7 +1437,11 @@ fn test_address_from_str_with_cashaddress_activated() {
     let ctx = MmCtxBuilder::new().into_mm_arc();
 
     let coin = unwrap!(block_on(utxo_standard_coin_from_conf_and_request(
-        &ctx, "BCH", &conf, &req, &[1u8; 32]
+        &ctx,
+        "BCH",
+        &conf,
+        &req,
+        Some(&[1u8; 32])
     )));
 
     assert_eq!(
@@ -1160,7 +1479,11 @@ fn test_address_from_str_with_legacy_address_activated() {
     let ctx = MmCtxBuilder::new().into_mm_arc();
 
     let coin = unwrap!(block_on(utxo_standard_coin_from_conf_and_request(
-        &ctx, "BCH", &conf, &req, &[1u8; 32]
+        &ctx,
+        "BCH",
+        &conf,
+        &req,
+        Some(&[1u8; 32])
     )));
 
     let expected = Address::from_cashaddress(
@@ -1214,7 +1537,11 @@ fn test_unavailable_electrum_proto_version() {
 
     let ctx = MmCtxBuilder::new().into_mm_arc();
     let error = unwrap!(block_on(utxo_standard_coin_from_conf_and_request(
-        &ctx, "RICK", &conf, &req, &[1u8; 32]
+        &ctx,
+        "RICK",
+        &conf,
+        &req,
+        Some(&[1u8; 32])
     ))
     .err());
     log!("Error: "(error));
@@ -1239,7 +1566,7 @@ fn test_spam_rick() {
         "RICK",
         &conf,
         &req,
-        &*key_pair.private().secret
+        Some(&*key_pair.private().secret)
     )));
 
     let output = TransactionOutput {
@@ -1293,7 +1620,11 @@ fn test_one_unavailable_electrum_proto_version() {
 
     let ctx = MmCtxBuilder::new().into_mm_arc();
     let coin = unwrap!(block_on(utxo_standard_coin_from_conf_and_request(
-        &ctx, "BTC", &conf, &req, &[1u8; 32]
+        &ctx,
+        "BTC",
+        &conf,
+        &req,
+        Some(&[1u8; 32])
     )));
 
     block_on(async { Timer::sleep(0.5).await });
@@ -1361,7 +1692,11 @@ fn test_unspendable_balance_failed_once() {
         184, 102, 137, 37, 78, 214, 113, 78,
     ];
     let coin = unwrap!(block_on(utxo_standard_coin_from_conf_and_request(
-        &ctx, "RICK", &conf, &req, &priv_key
+        &ctx,
+        "RICK",
+        &conf,
+        &req,
+        Some(&priv_key)
     )));
 
     let balance = coin.my_balance().wait().unwrap();
@@ -1410,7 +1745,11 @@ fn test_unspendable_balance_failed() {
         184, 102, 137, 37, 78, 214, 113, 78,
     ];
     let coin = unwrap!(block_on(utxo_standard_coin_from_conf_and_request(
-        &ctx, "RICK", &conf, &req, &priv_key
+        &ctx,
+        "RICK",
+        &conf,
+        &req,
+        Some(&priv_key)
     )));
 
     let balance = coin.my_balance().wait().unwrap();
@@ -2112,3 +2451,320 @@ fn test_get_sender_trade_fee_dynamic_tx_fee() {
         .expect("!get_sender_trade_fee");
     assert_eq!(fee1, fee3);
 }
+
+#[test]
+fn test_segwit_address_encode_decode() {
+    // https://github.com/bitcoin/bips/blob/master/bip-0173.mediawiki#examples
+    let hash: AddressHash = "751e76e8199196d454941c45d1b3a323f1433bd6".into();
+    let segwit = SegwitAddress::p2wpkh("bc", &hash);
+    assert_eq!(segwit.encode(), Ok("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4".into()));
+
+    let decoded = SegwitAddress::decode("BC1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KV8F3T4").unwrap();
+    assert_eq!(decoded, segwit);
+    assert!(decoded.is_p2wpkh());
+
+    let p2wsh = SegwitAddress::decode("bc1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3qccfmv3").unwrap();
+    assert!(p2wsh.is_p2wsh());
+
+    // invalid checksum
+    assert!(SegwitAddress::decode("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t5").is_err());
+    // invalid witness v0 program length
+    assert!(SegwitAddress::decode("BC1QR508D6QEJXTDG4Y5R3ZARVARYV98GJ9P").is_err());
+}
+
+#[test]
+fn test_address_from_str_with_segwit_activated() {
+    let conf = json!({
+        "coin": "BTC",
+        "pubtype": 0,
+        "p2shtype": 5,
+        "segwit": true,
+        "address_format":{"format":"segwit","hrp":"bc"},
+    });
+    let conf = UtxoConfBuilder::new(&conf, &Json::Null, "BTC").build().unwrap();
+
+    let expected = Address::from_str("1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH").unwrap();
+    let address = utxo_common::address_from_str(&conf, "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4").unwrap();
+    assert_eq!(address, expected);
+    assert_eq!(
+        utxo_common::display_address(&conf, &address),
+        Ok("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4".into())
+    );
+
+    // P2SH addresses are still accepted in the legacy format
+    let p2sh = utxo_common::address_from_str(&conf, "3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy").unwrap();
+    assert_eq!(
+        utxo_common::display_address(&conf, &p2sh),
+        Ok("3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy".into())
+    );
+
+    let (address, script_type) =
+        utxo_common::address_with_script_type_from_str(&conf, "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4").unwrap();
+    assert_eq!(address, expected);
+    assert_eq!(script_type, AddressScriptType::P2WPKH);
+
+    // legacy P2PKH addresses are accepted too and keep their script type and format
+    let (legacy, script_type) =
+        utxo_common::address_with_script_type_from_str(&conf, "1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH").unwrap();
+    assert_eq!(legacy, expected);
+    assert_eq!(script_type, AddressScriptType::P2PKH);
+    assert_eq!(
+        utxo_common::display_address_with_script_type(&conf, &legacy, script_type),
+        Ok("1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH".into())
+    );
+
+    let error = utxo_common::address_from_str(&conf, "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx")
+        .err()
+        .unwrap();
+    assert!(error.contains("invalid human-readable part"));
+
+    let error = utxo_common::address_from_str(&conf, "bc1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3qccfmv3")
+        .err()
+        .unwrap();
+    assert!(error.contains("Only P2WPKH segwit addresses are supported"));
+}
+
+#[test]
+fn test_segwit_address_format_requires_segwit_enabled() {
+    let conf = json!({
+        "coin": "BTC",
+        "address_format":{"format":"segwit","hrp":"bc"},
+    });
+    let error = UtxoConfBuilder::new(&conf, &Json::Null, "BTC").build().err().unwrap();
+    assert!(error.contains("'segwit' must be enabled"));
+}
+
+#[test]
+fn test_output_script_segwit() {
+    let address = Address::from_str("1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH").unwrap();
+    let format = UtxoAddressFormat::Segwit { hrp: "bc".into() };
+    let script = output_script(&address, &format);
+    assert!(script.is_pay_to_witness_key_hash());
+    assert_eq!(
+        script.to_bytes(),
+        Bytes::from("0014751e76e8199196d454941c45d1b3a323f1433bd6")
+    );
+    assert_eq!(
+        output_script(&address, &UtxoAddressFormat::Standard),
+        Builder::build_p2pkh(&address.hash)
+    );
+}
+
+#[test]
+fn test_p2wsh_spend_extract_secret() {
+    let mut fields = utxo_coin_fields_for_test(native_client_for_test().into(), None);
+    fields.conf.segwit = true;
+    fields.conf.bech32_hrp = Some("bc".into());
+    fields.conf.zcash = false;
+    fields.conf.overwintered = false;
+    fields.conf.tx_version = 2;
+
+    let secret = [1; 32];
+    let secret_hash = &*dhash160(&secret);
+    let redeem_script = Builder::default()
+        .push_opcode(Opcode::OP_HASH160)
+        .push_bytes(secret_hash)
+        .push_opcode(Opcode::OP_EQUALVERIFY)
+        .push_bytes(fields.key_pair.public())
+        .push_opcode(Opcode::OP_CHECKSIG)
+        .into_script();
+
+    // any transaction will do, the payment output is replaced with P2WSH one
+    let mut prev_tx: UtxoTx = deserialize(hex::decode("0100000001de7aa8d29524906b2b54ee2e0281f3607f75662cbc9080df81d1047b78e21dbc00000000d7473044022079b6c50820040b1fbbe9251ced32ab334d33830f6f8d0bf0a40c7f1336b67d5b0220142ccf723ddabb34e542ed65c395abc1fbf5b6c3e730396f15d25c49b668a1a401209da937e5609680cb30bff4a7661364ca1d1851c2506fa80c443f00a3d3bf7365004c6b6304f62b0e5cb175210270e75970bb20029b3879ec76c4acd320a8d0589e003636264d01a7d566504bfbac6782012088a9142fb610d856c19fd57f2d0cffe8dff689074b3d8a882103f368228456c940ac113e53dad5c104cf209f2f102a409207269383b6ab9b03deac68ffffffff01d0dc9800000000001976a9146d9d2b554d768232320587df75c4338ecc8bf37d88ac40280e5c").unwrap().as_slice()).unwrap();
+    prev_tx.outputs[0].script_pubkey = Builder::default()
+        .push_opcode(Opcode::OP_0)
+        .push_bytes(&*sha256(&redeem_script))
+        .into_script()
+        .to_bytes();
+
+    let output = TransactionOutput {
+        value: prev_tx.outputs[0].value - 1000,
+        script_pubkey: Builder::build_p2pkh(&fields.my_address.hash).to_bytes(),
+    };
+    let script_data = Builder::default().push_data(&secret).into_script();
+    let spend_tx = utxo_common::p2sh_spending_tx(
+        &fields,
+        prev_tx,
+        redeem_script.to_bytes(),
+        vec![output],
+        script_data,
+        SEQUENCE_FINAL,
+        &fields.key_pair,
+    )
+    .unwrap();
+
+    assert!(spend_tx.inputs[0].script_sig.is_empty());
+    assert_eq!(spend_tx.inputs[0].script_witness.len(), 3);
+    assert_eq!(spend_tx.inputs[0].script_witness[2], redeem_script.to_bytes());
+
+    let spend_tx_bytes = serialize_with_flags(&spend_tx, SERIALIZE_TRANSACTION_WITNESS);
+    let actual = utxo_common::extract_secret(secret_hash, &spend_tx_bytes).unwrap();
+    assert_eq!(actual, secret.to_vec());
+}
+
+#[test]
+fn test_hd_wallet_receive_addresses() {
+    use crate::hd_wallet::hd_account_from_conf_and_request;
+    use crate::utxo::hd_wallet::{hd_receive_addresses, UtxoHDWallet};
+    use common::privkey::{bip39_seed_from_mnemonic, ExtendedPrivateKey};
+
+    let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+    let seed = bip39_seed_from_mnemonic(mnemonic, "").unwrap();
+    let ctx = MmCtxBuilder::new().into_mm_arc();
+    ctx.hd_master_key
+        .pin(ExtendedPrivateKey::new_master(&seed).unwrap())
+        .unwrap();
+
+    let conf = json!({"coin": "BTC", "derivation_path": "m/44'/0'"});
+    let account = hd_account_from_conf_and_request(&ctx, "BTC", &conf, &json!({}))
+        .unwrap()
+        .unwrap();
+    assert_eq!(account.derivation_path.to_string(), "m/44'/0'/0'");
+
+    let state_path = std::env::temp_dir().join(format!("test_hd_wallet_receive_addresses_{}.json", now_ms()));
+    let mut fields = utxo_coin_fields_for_test(native_client_for_test().into(), None);
+    fields.conf.pub_addr_prefix = 0;
+    fields.hd_wallet = Some(UtxoHDWallet::new(account.clone(), 2, state_path.clone()));
+
+    // the first BIP44 address of the well-known test mnemonic is the only spendable one
+    let addresses = hd_receive_addresses(&fields).unwrap();
+    assert_eq!(addresses.addresses.len(), 1);
+    assert_eq!(addresses.addresses[0].address, "1LqBGSKuX5yYUonjxT5qGfpUsXKYYWeabA");
+    assert_eq!(addresses.addresses[0].derivation_path, "m/44'/0'/0'/0/0");
+    assert!(addresses.addresses[0].spendable);
+    assert!(!addresses.addresses[0].used);
+
+    // the addresses discovered by the account scan are saved and loaded on restart
+    unwrap!(fields.hd_wallet.as_ref().unwrap().mark_receive_addresses_used(&[2]));
+    fields.hd_wallet = Some(UtxoHDWallet::new(account, 2, state_path.clone()));
+    let addresses = hd_receive_addresses(&fields).unwrap();
+    assert_eq!(addresses.addresses.len(), 3);
+    assert_eq!(addresses.addresses[2].derivation_path, "m/44'/0'/0'/0/2");
+    assert!(addresses.addresses[2].used);
+    assert!(!addresses.addresses[2].spendable);
+    assert!(!addresses.addresses[1].used);
+
+    std::fs::remove_file(&state_path).unwrap();
+}
+
+#[test]
+fn test_hd_account_requires_derivation_path() {
+    use crate::hd_wallet::hd_account_from_conf_and_request;
+    use common::privkey::ExtendedPrivateKey;
+
+    let ctx = MmCtxBuilder::new().into_mm_arc();
+    // no HD wallet mode
+    assert!(hd_account_from_conf_and_request(&ctx, "BTC", &json!({}), &json!({}))
+        .unwrap()
+        .is_none());
+
+    ctx.hd_master_key
+        .pin(ExtendedPrivateKey::new_master(&[1; 32]).unwrap())
+        .unwrap();
+    let error = hd_account_from_conf_and_request(&ctx, "BTC", &json!({}), &json!({})).unwrap_err();
+    assert!(error.contains("'derivation_path' is not set in the BTC config"));
+
+    let conf = json!({"derivation_path": "m/44'/0'/0'"});
+    let error = hd_account_from_conf_and_request(&ctx, "BTC", &conf, &json!({})).unwrap_err();
+    assert!(error.contains("Expected 'derivation_path' in the m/44'/coin_type' form"));
+}
+
+#[test]
+fn test_derive_htlc_key_pair() {
+    let fields = utxo_coin_fields_for_test(native_client_for_test().into(), None);
+
+    // the legacy swaps are locked with the coin key pair
+    let key_pair = utxo_common::derive_htlc_key_pair(&fields, &[]).unwrap();
+    assert_eq!(key_pair.public(), fields.key_pair.public());
+
+    let swap_uuid = [1; 16];
+    let key_pair = utxo_common::derive_htlc_key_pair(&fields, &swap_uuid).unwrap();
+    assert_ne!(key_pair.public(), fields.key_pair.public());
+    assert_eq!(key_pair.public().len(), 33);
+    assert_eq!(
+        utxo_common::derive_htlc_pubkey(&fields, &swap_uuid).unwrap(),
+        key_pair.public().to_vec()
+    );
+
+    let other_swap_key_pair = utxo_common::derive_htlc_key_pair(&fields, &[2; 16]).unwrap();
+    assert_ne!(other_swap_key_pair.public(), key_pair.public());
+}
+
+/// Signs by the secret known to the test only, derives the keys the same way as `derive_htlc_key_pair`.
+struct TestExternalSigner {
+    secret: H256,
+}
+
+impl TestExternalSigner {
+    fn secret(&self, derivation: &[u8]) -> H256 {
+        if derivation.is_empty() {
+            return self.secret;
+        }
+        let mut preimage = self.secret.to_vec();
+        preimage.extend_from_slice(derivation);
+        sha256(&preimage)
+    }
+}
+
+impl ExternalSigner for TestExternalSigner {
+    fn public_key(&self, derivation: &[u8]) -> Result<Public, String> {
+        let private = Private {
+            prefix: 0,
+            secret: self.secret(derivation),
+            compressed: true,
+            checksum_type: ChecksumType::DSHA256,
+        };
+        Ok(try_s!(KeyPair::from_private(private).map_err(|e| ERRL!("{}", e)))
+            .public()
+            .clone())
+    }
+
+    fn sign_hash(&self, derivation: &[u8], message: &H256) -> Result<[u8; 65], String> {
+        let secret = try_s!(secp256k1::SecretKey::parse(&self.secret(derivation).take()).map_err(|e| ERRL!("{:?}", e)));
+        let (signature, recovery_id) = secp256k1::sign(&secp256k1::Message::parse(&message.take()), &secret);
+        let mut recoverable = [0; 65];
+        recoverable[..64].copy_from_slice(&signature.serialize());
+        recoverable[64] = recovery_id.serialize();
+        Ok(recoverable)
+    }
+}
+
+#[test]
+fn test_external_signing_key() {
+    let fields = utxo_coin_fields_for_test(native_client_for_test().into(), None);
+    let signer = TestExternalSigner {
+        secret: fields.key_pair.private().unwrap().secret,
+    };
+    let mut watch_only_fields = utxo_coin_fields_for_test(native_client_for_test().into(), None);
+    watch_only_fields.key_pair = SigningKey::External {
+        public: fields.key_pair.public().clone(),
+        derivation: Vec::new(),
+        signer: Arc::new(signer),
+    };
+    assert_eq!(utxo_common::display_priv_key(&watch_only_fields), "");
+
+    let message = H256::from([1; 32]);
+    let signature = watch_only_fields.key_pair.sign(&message).unwrap();
+    assert!(fields.key_pair.public().verify(&message, &signature).unwrap());
+
+    // the swap keys are derived by the signer
+    let swap_uuid = [1; 16];
+    let key_pair = utxo_common::derive_htlc_key_pair(&fields, &swap_uuid).unwrap();
+    let external = utxo_common::derive_htlc_key_pair(&watch_only_fields, &swap_uuid).unwrap();
+    assert_eq!(external.public(), key_pair.public());
+    let signature = external.sign(&message).unwrap();
+    assert!(key_pair.public().verify(&message, &signature).unwrap());
+}
+
+#[test]
+fn test_cpfp_child_fee() {
+    // 10 sat/byte, the parent is 305 bytes, so the package of 679 bytes should pay 6790 sat
+    assert_eq!(cpfp_child_fee(10000, 305, 1000), Some(5790));
+    assert_eq!(cpfp_child_fee(10000, 305, 3000), Some(3790));
+    // the parent pays the current fee rate already
+    assert_eq!(cpfp_child_fee(10000, 305, 3050), None);
+    assert_eq!(cpfp_child_fee(10000, 305, 5000), None);
+    // the child pays its own size at least
+    assert_eq!(cpfp_child_fee(10000, 305, 3049), Some(3741));
+    assert_eq!(cpfp_child_fee(1000, 1000, 999), Some(375));
+}
@@ -69,10 +69,12 @@ pub mod mm_metrics;
 pub mod big_int_str;
 pub mod custom_futures;
 pub mod duplex_mutex;
+pub mod external_signer;
 pub mod file_lock;
 #[cfg(feature = "native")] pub mod for_c;
 pub mod header;
 pub mod iguana_utils;
+pub mod keystore;
 pub mod mm_ctx;
 pub mod mm_number;
 pub mod privkey;
@@ -0,0 +1,133 @@
+//! Signing by the keys kept outside of the node, e.g. in an isolated process or an HSM gateway.
+//! The node runs in the watch-only mode then: it knows the public key only, see `lp_passphrase_init`.
+
+use keys::Public;
+use primitives::hash::H256;
+use std::sync::Arc;
+
+/// The signer holding the node private key.
+///
+/// The `derivation` selects the key: the node key itself if it's empty,
+/// or the key with the `sha256(node_secret || derivation)` secret otherwise.
+/// The derived keys lock the swap payments, see `utxo_common::derive_htlc_key_pair`.
+pub trait ExternalSigner: Send + Sync {
+    /// Returns the compressed public key of the selected key.
+    fn public_key(&self, derivation: &[u8]) -> Result<Public, String>;
+
+    /// Returns the recoverable ECDSA signature `r || s || recovery_id` of the 32-byte `message` hash.
+    fn sign_hash(&self, derivation: &[u8], message: &H256) -> Result<[u8; 65], String>;
+}
+
+/// The node keys of the watch-only mode.
+pub struct WatchOnlyKeys {
+    /// The node public key.
+    pub public: Public,
+    pub signer: Arc<dyn ExternalSigner>,
+}
+
+/// The signer of the watch-only node started without the signer configured, it can't sign anything.
+pub struct NoSigner;
+
+impl ExternalSigner for NoSigner {
+    fn public_key(&self, _derivation: &[u8]) -> Result<Public, String> {
+        ERR!("The node is watch-only and no external signer is configured")
+    }
+
+    fn sign_hash(&self, _derivation: &[u8], _message: &H256) -> Result<[u8; 65], String> {
+        ERR!("The node is watch-only and no external signer is configured")
+    }
+}
+
+/// Converts the recoverable signature to the DER encoding used by the UTXO script sigs.
+pub fn der_signature(recoverable: &[u8; 65]) -> Result<Vec<u8>, String> {
+    let mut compact = [0; 64];
+    compact.copy_from_slice(&recoverable[..64]);
+    let mut signature = secp256k1::Signature::parse(&compact);
+    // the UTXO coins accept the low S signatures only
+    signature.normalize_s();
+    Ok(signature.serialize_der().as_ref().to_vec())
+}
+
+/// The signer stand-in talking to the local signing process over the Unix socket.
+///
+/// Every request is the single JSON line, e.g.
+/// `{"method":"sign_hash","derivation":"<hex>","hash":"<hex>"}` or `{"method":"public_key","derivation":"<hex>"}`,
+/// the response is the single JSON line `{"result":"<hex>"}` or `{"error":"<message>"}`.
+/// The connection is opened per request. The signers are called from the async code, the futures01 `wait` calls
+/// and the swap threads, so the request runs on its own thread that doesn't depend on any runtime,
+/// the caller waits for it within the socket timeouts.
+#[cfg(all(unix, feature = "native"))]
+pub struct UnixSocketSigner {
+    path: std::path::PathBuf,
+}
+
+#[cfg(all(unix, feature = "native"))]
+impl UnixSocketSigner {
+    pub fn new(path: impl Into<std::path::PathBuf>) -> UnixSocketSigner { UnixSocketSigner { path: path.into() } }
+
+    fn request(&self, request: serde_json::Value) -> Result<Vec<u8>, String> {
+        let path = self.path.clone();
+        let thread = try_s!(std::thread::Builder::new()
+            .name("external_signer".into())
+            .spawn(move || UnixSocketSigner::request_blocking(&path, request)));
+        match thread.join() {
+            Ok(result) => result,
+            Err(_) => ERR!("External signer request thread panicked"),
+        }
+    }
+
+    fn request_blocking(path: &std::path::Path, request: serde_json::Value) -> Result<Vec<u8>, String> {
+        use std::io::{BufRead, BufReader, Write};
+        use std::os::unix::net::UnixStream;
+        use std::time::Duration;
+
+        let mut stream = try_s!(UnixStream::connect(path));
+        try_s!(stream.set_read_timeout(Some(Duration::from_secs(30))));
+        try_s!(stream.set_write_timeout(Some(Duration::from_secs(30))));
+        let mut line = try_s!(serde_json::to_vec(&request));
+        line.push(b'\n');
+        try_s!(stream.write_all(&line));
+
+        let mut response = String::new();
+        try_s!(BufReader::new(stream).read_line(&mut response));
+        let response: serde_json::Value = try_s!(serde_json::from_str(&response));
+        if let Some(error) = response["error"].as_str() {
+            return ERR!("External signer error: {}", error);
+        }
+        match response["result"].as_str() {
+            Some(result) => Ok(try_s!(hex::decode(result))),
+            None => ERR!("External signer returned no result: {}", response),
+        }
+    }
+}
+
+#[cfg(all(unix, feature = "native"))]
+impl ExternalSigner for UnixSocketSigner {
+    fn public_key(&self, derivation: &[u8]) -> Result<Public, String> {
+        let public = try_s!(self.request(json!({
+            "method": "public_key",
+            "derivation": hex::encode(derivation),
+        })));
+        if public.len() != 33 {
+            return ERR!("Expected the compressed public key, got {} bytes", public.len());
+        }
+        Public::from_slice(&public).map_err(|e| ERRL!("{}", e))
+    }
+
+    fn sign_hash(&self, derivation: &[u8], message: &H256) -> Result<[u8; 65], String> {
+        let signature = try_s!(self.request(json!({
+            "method": "sign_hash",
+            "derivation": hex::encode(derivation),
+            "hash": hex::encode(&**message),
+        })));
+        if signature.len() != 65 {
+            return ERR!(
+                "Expected the 65 bytes recoverable signature, got {} bytes",
+                signature.len()
+            );
+        }
+        let mut recoverable = [0; 65];
+        recoverable.copy_from_slice(&signature);
+        Ok(recoverable)
+    }
+}
@@ -35,16 +35,18 @@ use crate::mm_metrics::{MetricType, MetricsJson};
 #[cfg(feature = "native")] use crate::wio::{slurp_req, POOL};
 use crate::{now_float, slurp};
 
-pub const MAKER_SUCCESS_EVENTS: [&str; 11] = [
+pub const MAKER_SUCCESS_EVENTS: [&str; 13] = [
     "Started",
     "Negotiated",
     "TakerFeeValidated",
     "MakerPaymentSent",
+    "MakerPaymentReplaced",
     "TakerPaymentReceived",
     "TakerPaymentWaitConfirmStarted",
     "TakerPaymentValidatedAndConfirmed",
     "TakerPaymentSpent",
     "TakerPaymentSpendConfirmStarted",
+    "TakerPaymentSpendAccelerated",
     "TakerPaymentSpendConfirmed",
     "Finished",
 ];
@@ -65,7 +67,7 @@ pub const MAKER_ERROR_EVENTS: [&str; 13] = [
     "MakerPaymentRefundFailed",
 ];
 
-pub const TAKER_SUCCESS_EVENTS: [&str; 10] = [
+pub const TAKER_SUCCESS_EVENTS: [&str; 11] = [
     "Started",
     "Negotiated",
     "TakerFeeSent",
@@ -73,11 +75,28 @@ pub const TAKER_SUCCESS_EVENTS: [&str; 10] = [
     "MakerPaymentWaitConfirmStarted",
     "MakerPaymentValidatedAndConfirmed",
     "TakerPaymentSent",
+    "TakerPaymentReplaced",
     "TakerPaymentSpent",
     "MakerPaymentSpent",
     "Finished",
 ];
 
+/// The success events that occur only if the stuck transaction is replaced or accelerated.
+pub const OPTIONAL_SUCCESS_EVENTS: [&str; 3] = [
+    "MakerPaymentReplaced",
+    "TakerPaymentReplaced",
+    "TakerPaymentSpendAccelerated",
+];
+
+/// Returns the success events expected in the swap that has no stuck transactions.
+fn required_success_events<'a>(success_events: &[&'a str]) -> Vec<&'a str> {
+    success_events
+        .iter()
+        .filter(|event| !OPTIONAL_SUCCESS_EVENTS.contains(*event))
+        .copied()
+        .collect()
+}
+
 pub const TAKER_ERROR_EVENTS: [&str; 13] = [
     "StartFailed",
     "NegotiateFailed",
@@ -798,7 +817,7 @@ pub async fn check_my_swap_status(
     assert_eq!(taker_amount, actual_taker_amount);
     let actual_events = events_array.iter().map(|item| unwrap!(item["event"]["type"].as_str()));
     let actual_events: Vec<&str> = actual_events.collect();
-    assert_eq!(expected_success_events, actual_events.as_slice());
+    assert_eq!(required_success_events(expected_success_events), actual_events);
 }
 
 pub async fn check_stats_swap_status(
@@ -828,8 +847,8 @@ pub async fn check_stats_swap_status(
         .iter()
         .map(|item| unwrap!(item["event"]["type"].as_str()));
     let taker_actual_events: Vec<&str> = taker_actual_events.collect();
-    assert_eq!(maker_expected_events, maker_actual_events.as_slice());
-    assert_eq!(taker_expected_events, taker_actual_events.as_slice());
+    assert_eq!(required_success_events(maker_expected_events), maker_actual_events);
+    assert_eq!(required_success_events(taker_expected_events), taker_actual_events);
 }
 
 pub async fn check_recent_swaps(mm: &MarketMakerIt, expected_len: usize) {
@@ -0,0 +1,217 @@
+//! The keystore file keeping the passphrase encrypted at rest.
+//!
+//! The AES-256-GCM key is derived from the password with PBKDF2-HMAC-SHA256.
+//! The node unlocks the keystore configured with the `keystore` config option on start,
+//! see `lp_passphrase_init`.
+
+use aes_gcm::aead::generic_array::GenericArray;
+use aes_gcm::aead::{Aead, NewAead};
+use aes_gcm::Aes256Gcm;
+use gstuff::slurp;
+use hmac::Hmac;
+use serde_json as json;
+use sha2::Sha256;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// The environment variable to read the keystore password from, the password is prompted otherwise.
+pub const KEYSTORE_PASSWORD_ENV: &str = "MM2_KEYSTORE_PASSWORD";
+
+const KEYSTORE_VERSION: u32 = 1;
+const KEYSTORE_ITERATIONS: u32 = 100_000;
+
+/// The content of the keystore file.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Keystore {
+    pub version: u32,
+    /// The number of the PBKDF2 iterations.
+    pub iterations: u32,
+    /// Hex encoded PBKDF2 salt.
+    pub salt: String,
+    /// Hex encoded AES-GCM nonce.
+    pub nonce: String,
+    /// Hex encoded encrypted passphrase followed by the authentication tag.
+    pub ciphertext: String,
+}
+
+impl Keystore {
+    /// Encrypts the `passphrase` by the `password`.
+    pub fn encrypt(passphrase: &str, password: &str) -> Result<Keystore, String> {
+        Keystore::encrypt_with_iterations(passphrase, password, KEYSTORE_ITERATIONS)
+    }
+
+    fn encrypt_with_iterations(passphrase: &str, password: &str, iterations: u32) -> Result<Keystore, String> {
+        if password.is_empty() {
+            return ERR!("The keystore password must not be empty");
+        }
+        let salt: [u8; 32] = rand::random();
+        let nonce: [u8; 12] = rand::random();
+        let cipher = cipher(password, &salt, iterations);
+        let ciphertext = try_s!(cipher
+            .encrypt(GenericArray::from_slice(&nonce), passphrase.as_bytes())
+            .map_err(|_| "Error encrypting the passphrase"));
+        Ok(Keystore {
+            version: KEYSTORE_VERSION,
+            iterations,
+            salt: hex::encode(&salt),
+            nonce: hex::encode(&nonce),
+            ciphertext: hex::encode(&ciphertext),
+        })
+    }
+
+    /// Decrypts the passphrase, fails if the `password` is wrong.
+    pub fn decrypt(&self, password: &str) -> Result<String, String> {
+        if self.version != KEYSTORE_VERSION {
+            return ERR!("Unsupported keystore version {}", self.version);
+        }
+        let salt = try_s!(hex::decode(&self.salt));
+        let nonce = try_s!(hex::decode(&self.nonce));
+        if nonce.len() != 12 {
+            return ERR!("Invalid keystore nonce length {}", nonce.len());
+        }
+        let ciphertext = try_s!(hex::decode(&self.ciphertext));
+        let cipher = cipher(password, &salt, self.iterations);
+        let passphrase = try_s!(cipher
+            .decrypt(GenericArray::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| "Invalid keystore password"));
+        Ok(try_s!(String::from_utf8(passphrase)))
+    }
+
+    /// Re-encrypts the passphrase by the `new_password`.
+    pub fn change_password(&self, old_password: &str, new_password: &str) -> Result<Keystore, String> {
+        let passphrase = try_s!(self.decrypt(old_password));
+        Keystore::encrypt_with_iterations(&passphrase, new_password, self.iterations)
+    }
+
+    pub fn read(path: &Path) -> Result<Keystore, String> {
+        let content = slurp(&path);
+        if content.is_empty() {
+            return ERR!("Keystore {} is not found or empty", path.display());
+        }
+        Ok(try_s!(json::from_slice(&content)))
+    }
+
+    /// Writes the keystore readable by the owner only.
+    /// The content is synced to the temporary file renamed over the `path` then,
+    /// so the existing keystore is never left partially written.
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let content = try_s!(json::to_vec_pretty(self));
+        let tmp_path = PathBuf::from(format!("{}.tmp", path.display()));
+        // the stale temporary file is removed, so the new one is created with the owner only permissions
+        if tmp_path.exists() {
+            try_s!(fs::remove_file(&tmp_path));
+        }
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(all(unix, feature = "native"))]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = try_s!(options.open(&tmp_path));
+        try_s!(file.write_all(&content));
+        try_s!(file.sync_all());
+        try_s!(fs::rename(&tmp_path, path));
+        Ok(())
+    }
+}
+
+fn cipher(password: &str, salt: &[u8], iterations: u32) -> Aes256Gcm {
+    let mut key = [0; 32];
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(password.as_bytes(), salt, iterations as usize, &mut key);
+    Aes256Gcm::new(GenericArray::from_slice(&key))
+}
+
+/// Reads the keystore at `path` and decrypts the passphrase by the password
+/// taken from the `MM2_KEYSTORE_PASSWORD` environment variable or prompted from the terminal.
+pub fn unlock_keystore(path: &Path) -> Result<String, String> {
+    let keystore = try_s!(Keystore::read(path));
+    let password = match std::env::var(KEYSTORE_PASSWORD_ENV) {
+        Ok(password) => password,
+        Err(_) => try_s!(prompt_password(&format!(
+            "Password of the keystore {}: ",
+            path.display()
+        ))),
+    };
+    keystore.decrypt(&password)
+}
+
+/// Reads the password from the terminal with the echo turned off.
+#[cfg(all(unix, feature = "native"))]
+fn prompt_password(prompt: &str) -> Result<String, String> {
+    use std::io::{self, BufRead, Write};
+
+    print!("{}", prompt);
+    try_s!(io::stdout().flush());
+
+    let fd = libc::STDIN_FILENO;
+    let mut term: libc::termios = unsafe { std::mem::zeroed() };
+    let is_tty = unsafe { libc::tcgetattr(fd, &mut term) } == 0;
+    if is_tty {
+        let mut silent = term;
+        silent.c_lflag &= !libc::ECHO;
+        unsafe { libc::tcsetattr(fd, libc::TCSANOW, &silent) };
+    }
+
+    let mut password = String::new();
+    let read = io::stdin().lock().read_line(&mut password);
+    if is_tty {
+        unsafe { libc::tcsetattr(fd, libc::TCSANOW, &term) };
+        println!();
+    }
+    try_s!(read);
+    Ok(password.trim_end_matches(&['\r', '\n'][..]).to_owned())
+}
+
+#[cfg(not(all(unix, feature = "native")))]
+fn prompt_password(_prompt: &str) -> Result<String, String> {
+    ERR!(
+        "The keystore password should be set with the {} env variable",
+        KEYSTORE_PASSWORD_ENV
+    )
+}
+
+#[cfg(test)]
+mod keystore_tests {
+    use super::*;
+
+    #[test]
+    fn test_keystore_encrypt_decrypt() {
+        let keystore = Keystore::encrypt_with_iterations("my passphrase", "password", 10).unwrap();
+        assert_eq!(keystore.decrypt("password").unwrap(), "my passphrase");
+        assert!(keystore.decrypt("wrong password").is_err());
+
+        let serialized = json::to_string(&keystore).unwrap();
+        let deserialized: Keystore = json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized.decrypt("password").unwrap(), "my passphrase");
+
+        let changed = keystore.change_password("password", "new password").unwrap();
+        assert_eq!(changed.iterations, 10);
+        assert_ne!(changed.salt, keystore.salt);
+        assert_eq!(changed.decrypt("new password").unwrap(), "my passphrase");
+        assert!(changed.decrypt("password").is_err());
+        assert!(keystore.change_password("wrong password", "new password").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn test_keystore_write() {
+        let path = std::env::temp_dir().join(format!("test_keystore_write_{}.json", rand::random::<u64>()));
+        let keystore = Keystore::encrypt_with_iterations("my passphrase", "password", 10).unwrap();
+        keystore.write(&path).unwrap();
+        let changed = keystore.change_password("password", "new password").unwrap();
+        changed.write(&path).unwrap();
+
+        let read = Keystore::read(&path).unwrap();
+        assert_eq!(read.decrypt("new password").unwrap(), "my passphrase");
+        assert!(!PathBuf::from(format!("{}.tmp", path.display())).exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
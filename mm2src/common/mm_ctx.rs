@@ -1,6 +1,6 @@
 use gstuff::Constructible;
 #[cfg(not(feature = "native"))] use http::Response;
-use keys::KeyPair;
+use keys::{KeyPair, Public};
 use primitives::hash::H160;
 use rand::Rng;
 use rusqlite::Connection;
@@ -17,8 +17,10 @@ use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex, MutexGuard, Weak};
 
 use crate::executor::Timer;
+use crate::external_signer::WatchOnlyKeys;
 use crate::log::{self, LogState};
 use crate::mm_metrics::{prometheus, MetricsArc};
+use crate::privkey::ExtendedPrivateKey;
 use crate::{bits256, small_rng};
 
 /// Default interval to export and record metrics to log.
@@ -78,10 +80,25 @@ pub struct MmCtx {
     /// secp256k1 key pair derived from passphrase.
     /// cf. `key_pair_from_seed`.
     pub secp256k1_key_pair: Constructible<KeyPair>,
+    /// BIP32 master key derived from the BIP39 mnemonic passphrase.
+    /// Available if the HD wallet mode is enabled with the `enable_hd` config option.
+    pub hd_master_key: Constructible<ExtendedPrivateKey>,
+    /// The node public key and the external signer holding its private key.
+    /// Available if the node is started in the watch-only mode with the `watch_only` config option,
+    /// the `secp256k1_key_pair` is not available then.
+    pub watch_only: Constructible<WatchOnlyKeys>,
     /// Coins that should be enabled to kick start the interrupted swaps and orders.
     pub coins_needed_for_kick_start: Mutex<HashSet<String>>,
     /// The context belonging to the `lp_swap` mod: `SwapsContext`.
     pub swaps_ctx: Mutex<Option<Arc<dyn Any + 'static + Send + Sync>>>,
+    /// The context belonging to the `event_stream` mod: `EventStreamContext`.
+    pub event_stream_ctx: Mutex<Option<Arc<dyn Any + 'static + Send + Sync>>>,
+    /// The context belonging to the `lp_webhooks` mod: `WebhooksContext`.
+    pub webhooks_ctx: Mutex<Option<Arc<dyn Any + 'static + Send + Sync>>>,
+    /// The context belonging to the `rpc_credentials` mod: `RpcCredentialsContext`.
+    pub rpc_credentials_ctx: Mutex<Option<Arc<dyn Any + 'static + Send + Sync>>>,
+    /// The context belonging to the `simple_market_maker` mod: `SimpleMarketMakerContext`.
+    pub simple_market_maker_ctx: Mutex<Option<Arc<dyn Any + 'static + Send + Sync>>>,
     pub sqlite_connection: Constructible<Mutex<Connection>>,
 }
 impl MmCtx {
@@ -101,8 +118,14 @@ impl MmCtx {
             coins_ctx: Mutex::new(None),
             rmd160: Constructible::default(),
             secp256k1_key_pair: Constructible::default(),
+            hd_master_key: Constructible::default(),
+            watch_only: Constructible::default(),
             coins_needed_for_kick_start: Mutex::new(HashSet::new()),
             swaps_ctx: Mutex::new(None),
+            event_stream_ctx: Mutex::new(None),
+            webhooks_ctx: Mutex::new(None),
+            rpc_credentials_ctx: Mutex::new(None),
+            simple_market_maker_ctx: Mutex::new(None),
             sqlite_connection: Constructible::default(),
         }
     }
@@ -203,18 +226,31 @@ impl MmCtx {
         }
     }
 
+    /// Whether the node is started in the watch-only mode, i.e. without the private key.
+    pub fn is_watch_only(&self) -> bool { self.watch_only.as_option().is_some() }
+
+    /// Get a reference to the node secp256k1 public key, either derived from passphrase or the watch-only one.
+    /// Panics if the public key is not available.
+    pub fn secp256k1_public(&self) -> &Public {
+        if let Some(watch_only) = self.watch_only.as_option() {
+            return &watch_only.public;
+        }
+        self.secp256k1_key_pair().public()
+    }
+
     /// This is our public ID, allowing us to be different from other peers.
     /// This should also be our public key which we'd use for message verification.
     pub fn public_id(&self) -> Result<bits256, String> {
-        self.secp256k1_key_pair
-            .ok_or(ERRL!("Public ID is not yet available"))
-            .map(|keypair| {
-                let public = keypair.public(); // Compressed public key is going to be 33 bytes.
-                                               // First byte is a prefix, https://davidederosa.com/basic-blockchain-programming/elliptic-curve-keys/.
-                bits256 {
-                    bytes: *array_ref!(public, 1, 32),
-                }
-            })
+        let public = match (self.watch_only.as_option(), self.secp256k1_key_pair.as_option()) {
+            (Some(watch_only), _) => &watch_only.public,
+            (None, Some(keypair)) => keypair.public(),
+            (None, None) => return ERR!("Public ID is not yet available"),
+        };
+        // Compressed public key is going to be 33 bytes.
+        // First byte is a prefix, https://davidederosa.com/basic-blockchain-programming/elliptic-curve-keys/.
+        Ok(bits256 {
+            bytes: *array_ref!(public, 1, 32),
+        })
     }
 
     pub fn gui(&self) -> Option<&str> { self.conf["gui"].as_str() }
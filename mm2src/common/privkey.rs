@@ -17,9 +17,18 @@
 //  marketmaker
 //
 
+use bip39::{Language, Mnemonic, Seed};
 use bitcrypto::{sha256, ChecksumType};
+use hmac::{Hmac, Mac};
 use keys::{Error as KeysError, KeyPair, Private};
 use primitives::hash::H256;
+use secp256k1::{PublicKey, SecretKey};
+use sha2::Sha512;
+use std::fmt;
+use std::str::FromStr;
+
+/// The index of the child key starting from which the keys are hardened.
+pub const HARDENED_KEY_OFFSET: u32 = 0x8000_0000;
 
 fn private_from_seed(seed: &str) -> Result<Private, String> {
     match seed.parse() {
@@ -67,3 +76,218 @@ pub fn key_pair_from_seed(seed: &str) -> Result<KeyPair, String> {
     assert_eq!(pair.public().len(), 33);
     Ok(pair)
 }
+
+/// Returns the BIP39 seed of the given `mnemonic` phrase protected by the optional `password`.
+/// https://github.com/bitcoin/bips/blob/master/bip-0039.mediawiki#from-mnemonic-to-seed
+pub fn bip39_seed_from_mnemonic(mnemonic: &str, password: &str) -> Result<Vec<u8>, String> {
+    let mnemonic = try_s!(Mnemonic::from_phrase(mnemonic, Language::English).map_err(|e| ERRL!("{}", e)));
+    Ok(Seed::new(&mnemonic, password).as_bytes().to_vec())
+}
+
+/// The index of the BIP32 child key.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ChildNumber {
+    Normal(u32),
+    Hardened(u32),
+}
+
+impl ChildNumber {
+    fn raw_index(&self) -> u32 {
+        match self {
+            ChildNumber::Normal(index) => *index,
+            ChildNumber::Hardened(index) => index | HARDENED_KEY_OFFSET,
+        }
+    }
+}
+
+impl fmt::Display for ChildNumber {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChildNumber::Normal(index) => write!(f, "{}", index),
+            ChildNumber::Hardened(index) => write!(f, "{}'", index),
+        }
+    }
+}
+
+impl FromStr for ChildNumber {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (index, hardened) = match s.strip_suffix('\'') {
+            Some(index) => (index, true),
+            None => (s, false),
+        };
+        let index: u32 = try_s!(index.parse());
+        if index >= HARDENED_KEY_OFFSET {
+            return ERR!("Child index {} is out of range", index);
+        }
+        if hardened {
+            Ok(ChildNumber::Hardened(index))
+        } else {
+            Ok(ChildNumber::Normal(index))
+        }
+    }
+}
+
+/// BIP32 derivation path like `m/44'/141'/0'/0/0`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DerivationPath(Vec<ChildNumber>);
+
+impl DerivationPath {
+    /// Returns the path extended with the given `child`.
+    pub fn child(&self, child: ChildNumber) -> DerivationPath {
+        let mut path = self.0.clone();
+        path.push(child);
+        DerivationPath(path)
+    }
+
+    pub fn as_slice(&self) -> &[ChildNumber] { &self.0 }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "m")?;
+        for child in self.0.iter() {
+            write!(f, "/{}", child)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('/');
+        if parts.next() != Some("m") {
+            return ERR!("Derivation path {} must start with 'm'", s);
+        }
+        let children: Vec<ChildNumber> = try_s!(parts.map(ChildNumber::from_str).collect());
+        Ok(DerivationPath(children))
+    }
+}
+
+/// BIP32 extended private key.
+/// https://github.com/bitcoin/bips/blob/master/bip-0032.mediawiki
+#[derive(Clone)]
+pub struct ExtendedPrivateKey {
+    secret: SecretKey,
+    chain_code: [u8; 32],
+}
+
+impl fmt::Debug for ExtendedPrivateKey {
+    // Don't leak the secret to the logs.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "ExtendedPrivateKey {{ .. }}") }
+}
+
+impl ExtendedPrivateKey {
+    /// Generates the master key from the BIP39 (or any other) `seed`.
+    pub fn new_master(seed: &[u8]) -> Result<ExtendedPrivateKey, String> {
+        let mut mac = try_s!(Hmac::<Sha512>::new_varkey(b"Bitcoin seed").map_err(|e| ERRL!("{:?}", e)));
+        mac.input(seed);
+        ExtendedPrivateKey::from_hmac_output(&mac.result().code())
+    }
+
+    fn from_hmac_output(output: &[u8]) -> Result<ExtendedPrivateKey, String> {
+        let secret = try_s!(SecretKey::parse_slice(&output[..32]).map_err(|e| ERRL!("{:?}", e)));
+        let mut chain_code = [0; 32];
+        chain_code.copy_from_slice(&output[32..]);
+        Ok(ExtendedPrivateKey { secret, chain_code })
+    }
+
+    pub fn derive_child(&self, child: ChildNumber) -> Result<ExtendedPrivateKey, String> {
+        let mut mac = try_s!(Hmac::<Sha512>::new_varkey(&self.chain_code).map_err(|e| ERRL!("{:?}", e)));
+        match child {
+            ChildNumber::Hardened(_) => {
+                mac.input(&[0]);
+                mac.input(&self.secret.serialize());
+            },
+            ChildNumber::Normal(_) => mac.input(&PublicKey::from_secret_key(&self.secret).serialize_compressed()),
+        }
+        mac.input(&child.raw_index().to_be_bytes());
+
+        let mut derived = try_s!(ExtendedPrivateKey::from_hmac_output(&mac.result().code()));
+        try_s!(derived
+            .secret
+            .tweak_add_assign(&self.secret)
+            .map_err(|e| ERRL!("{:?}", e)));
+        Ok(derived)
+    }
+
+    pub fn derive_path(&self, path: &DerivationPath) -> Result<ExtendedPrivateKey, String> {
+        let mut key = self.clone();
+        for child in path.as_slice() {
+            key = try_s!(key.derive_child(*child));
+        }
+        Ok(key)
+    }
+
+    pub fn secret(&self) -> H256 { H256::from(self.secret.serialize()) }
+
+    pub fn key_pair(&self) -> Result<KeyPair, String> {
+        let private = Private {
+            prefix: 0,
+            secret: self.secret(),
+            compressed: true,
+            checksum_type: ChecksumType::DSHA256,
+        };
+        KeyPair::from_private(private).map_err(|e| ERRL!("{}", e))
+    }
+}
+
+#[cfg(test)]
+mod privkey_tests {
+    use super::*;
+
+    #[test]
+    fn test_derivation_path_from_str() {
+        let path: DerivationPath = "m/44'/141'/0'/0/1".parse().unwrap();
+        assert_eq!(path.as_slice(), &[
+            ChildNumber::Hardened(44),
+            ChildNumber::Hardened(141),
+            ChildNumber::Hardened(0),
+            ChildNumber::Normal(0),
+            ChildNumber::Normal(1),
+        ]);
+        assert_eq!(path.to_string(), "m/44'/141'/0'/0/1");
+
+        assert!("44'/141'".parse::<DerivationPath>().is_err());
+        assert!("m/44'/x".parse::<DerivationPath>().is_err());
+        assert!("m/2147483648".parse::<DerivationPath>().is_err());
+    }
+
+    /// https://github.com/bitcoin/bips/blob/master/bip-0032.mediawiki#test-vector-1
+    #[test]
+    fn test_bip32_derivation() {
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let master = ExtendedPrivateKey::new_master(&seed).unwrap();
+        assert_eq!(
+            hex::encode(&*master.secret()),
+            "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35"
+        );
+        assert_eq!(
+            hex::encode(master.chain_code),
+            "873dff81c02f525623fd1fe5167eac3a55a049de3d314bb42ee227ffed37d508"
+        );
+
+        let child = master.derive_path(&"m/0'".parse().unwrap()).unwrap();
+        assert_eq!(
+            hex::encode(&*child.secret()),
+            "edb2e14f9ee77d26dd93b4ecede8d16ed408ce149b6cd80b0715a2d911a0afea"
+        );
+
+        let child = master.derive_path(&"m/0'/1".parse().unwrap()).unwrap();
+        assert_eq!(
+            hex::encode(&*child.secret()),
+            "3c6cb8d0f6a264c91ea8b5030fadaa8e538b020f0a387421a12de9319dc93368"
+        );
+    }
+
+    #[test]
+    fn test_bip39_seed_from_invalid_mnemonic() {
+        // the checksum word is invalid
+        let mnemonic =
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon";
+        assert!(bip39_seed_from_mnemonic(mnemonic, "").is_err());
+    }
+}
@@ -5,6 +5,7 @@ pub mod my_swaps;
 #[path = "database/stats_swaps.rs"] pub mod stats_swaps;
 
 use crate::CREATE_MY_SWAPS_TABLE;
+use coins::tx_history_db;
 use common::{log::{debug, error, info},
              mm_ctx::MmArc,
              rusqlite::{Connection, Result as SqlResult, NO_PARAMS}};
@@ -65,11 +66,23 @@ fn migration_2(ctx: &MmArc) -> Vec<(&'static str, Vec<String>)> {
 
 fn migration_3() -> Vec<(&'static str, Vec<String>)> { vec![(stats_swaps::ADD_STARTED_AT_INDEX, vec![])] }
 
+fn migration_4() -> Vec<(&'static str, Vec<String>)> { vec![(stats_swaps::ADD_PAIR_STARTED_AT_INDEX, vec![])] }
+
+fn migration_5() -> Vec<(&'static str, Vec<String>)> {
+    vec![
+        (tx_history_db::CREATE_TX_HISTORY_TABLE, vec![]),
+        (tx_history_db::ADD_TX_HISTORY_HEIGHT_INDEX, vec![]),
+        (tx_history_db::ADD_TX_HISTORY_TIMESTAMP_INDEX, vec![]),
+    ]
+}
+
 fn statements_for_migration(ctx: &MmArc, current_migration: i64) -> Option<Vec<(&'static str, Vec<String>)>> {
     match current_migration {
         1 => Some(migration_1(ctx)),
         2 => Some(migration_2(ctx)),
         3 => Some(migration_3()),
+        4 => Some(migration_4()),
+        5 => Some(migration_5()),
         _ => None,
     }
 }
@@ -1,11 +1,14 @@
 use crate::mm2::lp_swap::{stats_maker_swap_dir, stats_taker_swap_dir, MakerSavedSwap, SavedSwap, TakerSavedSwap};
+use bigdecimal::BigDecimal;
 use common::{log::{debug, error, warn},
              mm_ctx::MmArc,
              read_dir,
-             rusqlite::{Connection, OptionalExtension},
+             rusqlite::{Connection, OptionalExtension, Result as SqlResult, ToSql},
              slurp};
 use serde_json::{self as json};
 use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::str::FromStr;
 use uuid::Uuid;
 
 const CREATE_STATS_SWAPS_TABLE: &str = "CREATE TABLE IF NOT EXISTS stats_swaps (
@@ -33,6 +36,19 @@ const INSERT_STATS_SWAP: &str = "INSERT INTO stats_swaps (
 
 pub const ADD_STARTED_AT_INDEX: &str = "CREATE INDEX timestamp_index ON stats_swaps (started_at);";
 
+pub const ADD_PAIR_STARTED_AT_INDEX: &str =
+    "CREATE INDEX pair_timestamp_index ON stats_swaps (maker_coin, taker_coin, started_at);";
+
+/// The amounts are cast to TEXT since the DECIMAL column affinity could store them as REAL.
+const SELECT_PAIR_SWAPS: &str =
+    "SELECT uuid, maker_coin, CAST(maker_amount AS TEXT), CAST(taker_amount AS TEXT), started_at
+    FROM stats_swaps
+    WHERE is_success = 1
+        AND ((maker_coin = :base AND taker_coin = :rel) OR (maker_coin = :rel AND taker_coin = :base))
+        AND started_at >= :from_timestamp AND started_at < :to_timestamp
+    ORDER BY started_at DESC
+    LIMIT :limit";
+
 const SELECT_ID_BY_UUID: &str = "SELECT id FROM stats_swaps WHERE uuid = ?1";
 
 /// Returns SQL statements to initially fill stats_swaps table using existing DB with JSON files
@@ -197,3 +213,146 @@ pub fn add_swap_to_index(conn: &Connection, swap: &SavedSwap) {
         error!("Error {} on query {} with params {:?}", e, sql, params);
     };
 }
+
+/// The successful swap of the pair indexed in the stats_swaps table.
+#[derive(Debug)]
+pub struct StatsPairSwap {
+    pub uuid: Uuid,
+    /// Whether the base coin of the requested pair is the maker coin of the swap.
+    pub base_is_maker_coin: bool,
+    pub maker_amount: BigDecimal,
+    pub taker_amount: BigDecimal,
+    pub started_at: u64,
+}
+
+/// Selects the successful swaps of the pair in both directions started within `[from_timestamp, to_timestamp)`,
+/// the latest swaps go first.
+pub fn select_pair_swaps(
+    conn: &Connection,
+    base: &str,
+    rel: &str,
+    from_timestamp: u64,
+    to_timestamp: u64,
+    limit: usize,
+) -> SqlResult<Vec<StatsPairSwap>> {
+    // the timestamps above i64::MAX are clamped instead of wrapping to negative values
+    let from_timestamp = i64::try_from(from_timestamp).unwrap_or(i64::MAX);
+    let to_timestamp = i64::try_from(to_timestamp).unwrap_or(i64::MAX);
+    let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+    let params: [(&str, &dyn ToSql); 5] = [
+        (":base", &base),
+        (":rel", &rel),
+        (":from_timestamp", &from_timestamp),
+        (":to_timestamp", &to_timestamp),
+        (":limit", &limit),
+    ];
+    debug!(
+        "Trying to execute SQL query {} with params {} {} {} {} {}",
+        SELECT_PAIR_SWAPS, base, rel, from_timestamp, to_timestamp, limit
+    );
+    let mut stmt = conn.prepare(SELECT_PAIR_SWAPS)?;
+    let rows = stmt
+        .query_map_named(&params, |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, i64>(4)?,
+            ))
+        })?
+        .collect::<SqlResult<Vec<_>>>()?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|(uuid, maker_coin, maker_amount, taker_amount, started_at)| {
+            let parsed = (
+                Uuid::from_str(&uuid),
+                BigDecimal::from_str(&maker_amount),
+                BigDecimal::from_str(&taker_amount),
+            );
+            match parsed {
+                (Ok(uuid), Ok(maker_amount), Ok(taker_amount)) => Some(StatsPairSwap {
+                    uuid,
+                    base_is_maker_coin: maker_coin == base,
+                    maker_amount,
+                    taker_amount,
+                    started_at: started_at as u64,
+                }),
+                _ => {
+                    error!(
+                        "Error parsing the stats swap {} amounts {} {}",
+                        uuid, maker_amount, taker_amount
+                    );
+                    None
+                },
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod stats_swaps_tests {
+    use super::*;
+    use common::rusqlite::NO_PARAMS;
+
+    fn insert_swap(
+        conn: &Connection,
+        maker_coin: &str,
+        taker_coin: &str,
+        uuid: &Uuid,
+        started_at: u64,
+        is_success: bool,
+    ) {
+        let params = vec![
+            maker_coin.to_owned(),
+            taker_coin.to_owned(),
+            uuid.to_string(),
+            started_at.to_string(),
+            (started_at + 100).to_string(),
+            "1.5".to_owned(),
+            "3".to_owned(),
+            (is_success as u32).to_string(),
+        ];
+        conn.execute(INSERT_STATS_SWAP, &params).unwrap();
+    }
+
+    #[test]
+    fn test_select_pair_swaps() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(CREATE_STATS_SWAPS_TABLE, NO_PARAMS).unwrap();
+
+        let rick_morty = Uuid::new_v4();
+        let morty_rick = Uuid::new_v4();
+        let failed = Uuid::new_v4();
+        let old = Uuid::new_v4();
+        insert_swap(&conn, "RICK", "MORTY", &rick_morty, 1000, true);
+        insert_swap(&conn, "MORTY", "RICK", &morty_rick, 2000, true);
+        insert_swap(&conn, "RICK", "MORTY", &failed, 3000, false);
+        insert_swap(&conn, "RICK", "MORTY", &old, 10, true);
+        insert_swap(&conn, "RICK", "ETH", &Uuid::new_v4(), 1500, true);
+
+        let swaps = select_pair_swaps(&conn, "RICK", "MORTY", 100, 5000, 10).unwrap();
+        let uuids: Vec<_> = swaps.iter().map(|swap| swap.uuid).collect();
+        // the failed swap, the swap started before the range and the other pair are skipped
+        assert_eq!(uuids, vec![morty_rick, rick_morty]);
+        assert!(!swaps[0].base_is_maker_coin);
+        assert!(swaps[1].base_is_maker_coin);
+        assert_eq!(swaps[1].maker_amount, "1.5".parse().unwrap());
+        assert_eq!(swaps[1].taker_amount, "3".parse().unwrap());
+        assert_eq!(swaps[1].started_at, 1000);
+
+        // the reversed pair selects the same swaps
+        let swaps = select_pair_swaps(&conn, "MORTY", "RICK", 100, 5000, 10).unwrap();
+        let uuids: Vec<_> = swaps.iter().map(|swap| swap.uuid).collect();
+        assert_eq!(uuids, vec![morty_rick, rick_morty]);
+        assert!(swaps[0].base_is_maker_coin);
+        assert!(!swaps[1].base_is_maker_coin);
+
+        // the upper bound is exclusive and the limit is applied to the latest swaps
+        let swaps = select_pair_swaps(&conn, "RICK", "MORTY", 100, 2000, 10).unwrap();
+        assert_eq!(swaps.iter().map(|swap| swap.uuid).collect::<Vec<_>>(), vec![rick_morty]);
+        let swaps = select_pair_swaps(&conn, "RICK", "MORTY", 0, 5000, 1).unwrap();
+        assert_eq!(swaps.iter().map(|swap| swap.uuid).collect::<Vec<_>>(), vec![morty_rick]);
+    }
+}
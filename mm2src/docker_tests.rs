@@ -70,7 +70,7 @@ mod docker_tests {
     use coins::utxo::rpc_clients::{UnspentInfo, UtxoRpcClientEnum, UtxoRpcClientOps};
     use coins::utxo::utxo_standard::{utxo_standard_coin_from_conf_and_request, UtxoStandardCoin};
     use coins::utxo::{coin_daemon_data_dir, dhash160, zcash_params_path, UtxoCoinFields, UtxoCommonOps};
-    use coins::{FoundSwapTxSpend, MarketCoinOps, MmCoin, SwapOps, TransactionEnum};
+    use coins::{FoundSwapTxSpend, HtlcScriptType, MarketCoinOps, MmCoin, SwapOps, TransactionEnum};
     use common::block_on;
     use common::for_tests::enable_electrum;
     use common::mm_number::MmNumber;
@@ -228,7 +228,7 @@ mod docker_tests {
             ));
             let ctx = MmCtxBuilder::new().into_mm_arc();
             let coin = unwrap!(block_on(utxo_standard_coin_from_conf_and_request(
-                &ctx, ticker, &conf, &req, &priv_key,
+                &ctx, ticker, &conf, &req, Some(&priv_key),
             )));
             UtxoAssetDockerOps { ctx, coin }
         }
@@ -298,7 +298,7 @@ mod docker_tests {
         let conf = json!({"asset":ticker,"txversion":4,"overwintered":1,"txfee":1000,"network":"regtest"});
         let req = json!({"method":"enable"});
         let coin = unwrap!(block_on(utxo_standard_coin_from_conf_and_request(
-            &ctx, ticker, &conf, &req, priv_key
+            &ctx, ticker, &conf, &req, Some(priv_key)
         )));
         import_address(&coin);
         (ctx, coin)
@@ -364,14 +364,23 @@ mod docker_tests {
 
         let time_lock = (now_ms() / 1000) as u32 - 3600;
         let tx = coin
-            .send_taker_payment(time_lock, &*coin.my_public_key(), &[0; 20], 1.into(), &None)
+            .send_taker_payment(
+                time_lock,
+                &*coin.my_public_key(),
+                &[0; 20],
+                1.into(),
+                &None,
+                HtlcScriptType::P2SH,
+                false,
+                &[],
+            )
             .wait()
             .unwrap();
 
         unwrap!(coin.wait_for_confirmations(&tx.tx_hex(), 1, false, timeout, 1).wait());
 
         let refund_tx = coin
-            .send_taker_refunds_payment(&tx.tx_hex(), time_lock, &*coin.my_public_key(), &[0; 20], &None)
+            .send_taker_refunds_payment(&tx.tx_hex(), time_lock, &*coin.my_public_key(), &[0; 20], &None, &[])
             .wait()
             .unwrap();
 
@@ -385,7 +394,8 @@ mod docker_tests {
             &[0; 20],
             &tx.tx_hex(),
             0,
-            &None
+            &None,
+            &[],
         )));
         assert_eq!(FoundSwapTxSpend::Refunded(refund_tx), found);
     }
@@ -397,14 +407,23 @@ mod docker_tests {
 
         let time_lock = (now_ms() / 1000) as u32 - 3600;
         let tx = coin
-            .send_maker_payment(time_lock, &*coin.my_public_key(), &[0; 20], 1.into(), &None)
+            .send_maker_payment(
+                time_lock,
+                &*coin.my_public_key(),
+                &[0; 20],
+                1.into(),
+                &None,
+                HtlcScriptType::P2SH,
+                false,
+                &[],
+            )
             .wait()
             .unwrap();
 
         unwrap!(coin.wait_for_confirmations(&tx.tx_hex(), 1, false, timeout, 1).wait());
 
         let refund_tx = coin
-            .send_maker_refunds_payment(&tx.tx_hex(), time_lock, &*coin.my_public_key(), &[0; 20], &None)
+            .send_maker_refunds_payment(&tx.tx_hex(), time_lock, &*coin.my_public_key(), &[0; 20], &None, &[])
             .wait()
             .unwrap();
 
@@ -418,7 +437,8 @@ mod docker_tests {
             &[0; 20],
             &tx.tx_hex(),
             0,
-            &None
+            &None,
+            &[],
         )));
         assert_eq!(FoundSwapTxSpend::Refunded(refund_tx), found);
     }
@@ -431,14 +451,23 @@ mod docker_tests {
 
         let time_lock = (now_ms() / 1000) as u32 - 3600;
         let tx = coin
-            .send_taker_payment(time_lock, &*coin.my_public_key(), &*dhash160(&secret), 1.into(), &None)
+            .send_taker_payment(
+                time_lock,
+                &*coin.my_public_key(),
+                &*dhash160(&secret),
+                1.into(),
+                &None,
+                HtlcScriptType::P2SH,
+                false,
+                &[],
+            )
             .wait()
             .unwrap();
 
         unwrap!(coin.wait_for_confirmations(&tx.tx_hex(), 1, false, timeout, 1).wait());
 
         let spend_tx = coin
-            .send_maker_spends_taker_payment(&tx.tx_hex(), time_lock, &*coin.my_public_key(), &secret, &None)
+            .send_maker_spends_taker_payment(&tx.tx_hex(), time_lock, &*coin.my_public_key(), &secret, &None, &[])
             .wait()
             .unwrap();
 
@@ -452,7 +481,8 @@ mod docker_tests {
             &*dhash160(&secret),
             &tx.tx_hex(),
             0,
-            &None
+            &None,
+            &[],
         )));
         assert_eq!(FoundSwapTxSpend::Spent(spend_tx), found);
     }
@@ -465,14 +495,23 @@ mod docker_tests {
 
         let time_lock = (now_ms() / 1000) as u32 - 3600;
         let tx = coin
-            .send_maker_payment(time_lock, &*coin.my_public_key(), &*dhash160(&secret), 1.into(), &None)
+            .send_maker_payment(
+                time_lock,
+                &*coin.my_public_key(),
+                &*dhash160(&secret),
+                1.into(),
+                &None,
+                HtlcScriptType::P2SH,
+                false,
+                &[],
+            )
             .wait()
             .unwrap();
 
         unwrap!(coin.wait_for_confirmations(&tx.tx_hex(), 1, false, timeout, 1).wait());
 
         let spend_tx = coin
-            .send_taker_spends_maker_payment(&tx.tx_hex(), time_lock, &*coin.my_public_key(), &secret, &None)
+            .send_taker_spends_maker_payment(&tx.tx_hex(), time_lock, &*coin.my_public_key(), &secret, &None, &[])
             .wait()
             .unwrap();
 
@@ -486,7 +525,8 @@ mod docker_tests {
             &*dhash160(&secret),
             &tx.tx_hex(),
             0,
-            &None
+            &None,
+            &[],
         )));
         assert_eq!(FoundSwapTxSpend::Spent(spend_tx), found);
     }
@@ -509,6 +549,9 @@ mod docker_tests {
                     &*dhash160(&secret),
                     1.into(),
                     &coin.swap_contract_address(),
+                    HtlcScriptType::P2SH,
+                    false,
+                    &[],
                 )
                 .wait()
                 .unwrap();
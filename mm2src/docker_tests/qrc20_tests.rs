@@ -52,7 +52,7 @@ impl QtumDockerOps {
             "809465b17d0a4ddb3e4c69e8f23c2cabad868f51f8bed5c765ad1d6516c3306f"
         ));
         let coin = unwrap!(block_on(qtum_coin_from_conf_and_request(
-            &ctx, "QTUM", &conf, &req, &priv_key
+            &ctx, "QTUM", &conf, &req, Some(&priv_key)
         )));
         QtumDockerOps { ctx, coin }
     }
@@ -167,7 +167,7 @@ fn qrc20_coin_from_privkey(ticker: &str, priv_key: &[u8]) -> (MmArc, Qrc20Coin)
         platform,
         &conf,
         &req,
-        &priv_key,
+        Some(&priv_key),
         contract_address,
     )));
 
@@ -217,7 +217,7 @@ fn generate_qtum_coin_with_random_privkey(
     let priv_key = SecretKey::random(&mut rand4::thread_rng()).serialize();
     let ctx = MmCtxBuilder::new().into_mm_arc();
     let coin = unwrap!(block_on(qtum_coin_from_conf_and_request(
-        &ctx, "QTUM", &conf, &req, &priv_key
+        &ctx, "QTUM", &conf, &req, Some(&priv_key)
     )));
 
     let timeout = 30; // timeout if test takes more than 30 seconds to run
@@ -561,6 +561,9 @@ fn test_taker_spends_maker_payment() {
             secret_hash,
             amount.clone(),
             &maker_coin.swap_contract_address(),
+            HtlcScriptType::P2SH,
+            false,
+            &[],
         )
         .wait()
         .unwrap();
@@ -584,6 +587,8 @@ fn test_taker_spends_maker_payment() {
             secret_hash,
             amount.clone(),
             &taker_coin.swap_contract_address(),
+            HtlcScriptType::P2SH,
+            &[],
         )
         .wait());
 
@@ -594,6 +599,7 @@ fn test_taker_spends_maker_payment() {
             maker_pub,
             secret,
             &taker_coin.swap_contract_address(),
+            &[],
         )
         .wait());
     let spend_tx_hash = spend.tx_hash();
@@ -634,6 +640,9 @@ fn test_maker_spends_taker_payment() {
             secret_hash,
             amount.clone(),
             &taker_coin.swap_contract_address(),
+            HtlcScriptType::P2SH,
+            false,
+            &[],
         )
         .wait()
         .unwrap();
@@ -657,6 +666,8 @@ fn test_maker_spends_taker_payment() {
             secret_hash,
             amount.clone(),
             &maker_coin.swap_contract_address(),
+            HtlcScriptType::P2SH,
+            &[],
         )
         .wait());
 
@@ -667,6 +678,7 @@ fn test_maker_spends_taker_payment() {
             taker_pub,
             secret,
             &maker_coin.swap_contract_address(),
+            &[],
         )
         .wait());
     let spend_tx_hash = spend.tx_hash();
@@ -702,6 +714,9 @@ fn test_maker_refunds_payment() {
             secret_hash,
             amount.clone(),
             &coin.swap_contract_address(),
+            HtlcScriptType::P2SH,
+            false,
+            &[],
         )
         .wait()
         .unwrap();
@@ -727,6 +742,7 @@ fn test_maker_refunds_payment() {
             &taker_pub,
             secret_hash,
             &coin.swap_contract_address(),
+            &[],
         )
         .wait());
     let refund_tx_hash = refund.tx_hash();
@@ -760,6 +776,9 @@ fn test_taker_refunds_payment() {
             secret_hash,
             amount.clone(),
             &coin.swap_contract_address(),
+            HtlcScriptType::P2SH,
+            false,
+            &[],
         )
         .wait()
         .unwrap();
@@ -785,6 +804,7 @@ fn test_taker_refunds_payment() {
             &maker_pub,
             secret_hash,
             &coin.swap_contract_address(),
+            &[],
         )
         .wait());
     let refund_tx_hash = refund.tx_hash();
@@ -815,6 +835,9 @@ fn test_check_if_my_payment_sent() {
             secret_hash,
             amount.clone(),
             &coin.swap_contract_address(),
+            HtlcScriptType::P2SH,
+            false,
+            &[],
         )
         .wait()
         .unwrap();
@@ -838,6 +861,8 @@ fn test_check_if_my_payment_sent() {
             secret_hash,
             search_from_block,
             &coin.swap_contract_address(),
+            HtlcScriptType::P2SH,
+            &[],
         )
         .wait());
     assert_eq!(found, Some(payment));
@@ -863,6 +888,9 @@ fn test_search_for_swap_tx_spend_taker_spent() {
             secret_hash,
             amount.clone(),
             &maker_coin.swap_contract_address(),
+            HtlcScriptType::P2SH,
+            false,
+            &[],
         )
         .wait()
         .unwrap();
@@ -885,6 +913,7 @@ fn test_search_for_swap_tx_spend_taker_spent() {
             maker_pub,
             secret,
             &taker_coin.swap_contract_address(),
+            &[],
         )
         .wait());
     let spend_tx_hash = spend.tx_hash();
@@ -903,6 +932,7 @@ fn test_search_for_swap_tx_spend_taker_spent() {
         &payment_tx_hex,
         search_from_block,
         &maker_coin.swap_contract_address(),
+        &[],
     );
     let expected = Ok(Some(FoundSwapTxSpend::Spent(spend)));
     assert_eq!(actual, expected);
@@ -926,6 +956,9 @@ fn test_search_for_swap_tx_spend_maker_refunded() {
             secret_hash,
             amount.clone(),
             &maker_coin.swap_contract_address(),
+            HtlcScriptType::P2SH,
+            false,
+            &[],
         )
         .wait()
         .unwrap();
@@ -948,6 +981,7 @@ fn test_search_for_swap_tx_spend_maker_refunded() {
             &taker_pub,
             secret_hash,
             &maker_coin.swap_contract_address(),
+            &[],
         )
         .wait());
     let refund_tx_hash = refund.tx_hash();
@@ -966,6 +1000,7 @@ fn test_search_for_swap_tx_spend_maker_refunded() {
         &payment_tx_hex,
         search_from_block,
         &maker_coin.swap_contract_address(),
+        &[],
     );
     let expected = Ok(Some(FoundSwapTxSpend::Refunded(refund)));
     assert_eq!(actual, expected);
@@ -989,6 +1024,9 @@ fn test_search_for_swap_tx_spend_not_spent() {
             secret_hash,
             amount.clone(),
             &maker_coin.swap_contract_address(),
+            HtlcScriptType::P2SH,
+            false,
+            &[],
         )
         .wait()
         .unwrap();
@@ -1011,6 +1049,7 @@ fn test_search_for_swap_tx_spend_not_spent() {
         &payment_tx_hex,
         search_from_block,
         &maker_coin.swap_contract_address(),
+        &[],
     );
     // maker payment hasn't been spent or refunded yet
     assert_eq!(actual, Ok(None));
@@ -1036,6 +1075,9 @@ fn test_wait_for_tx_spend() {
             secret_hash,
             amount.clone(),
             &maker_coin.swap_contract_address(),
+            HtlcScriptType::P2SH,
+            false,
+            &[],
         )
         .wait()
         .unwrap();
@@ -1080,6 +1122,7 @@ fn test_wait_for_tx_spend() {
                 &maker_pub_c,
                 secret,
                 &taker_coin.swap_contract_address(),
+                &[],
             )
             .wait());
         unsafe { SPEND_TX = Some(spend) }
@@ -1341,6 +1384,9 @@ fn test_get_max_taker_vol_and_trade_with_dynamic_trade_fee(coin: QtumCoin, priv_
             secret_hash,
             expected_max_taker_vol.to_decimal(),
             &None,
+            HtlcScriptType::P2SH,
+            false,
+            &[],
         )
         .wait()
         .expect("!send_taker_payment");
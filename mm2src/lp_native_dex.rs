@@ -31,18 +31,26 @@ use std::net::{IpAddr, Ipv4Addr, ToSocketAddrs};
 use std::path::Path;
 use std::str;
 use std::str::from_utf8;
+use std::sync::Arc;
 
 use crate::common::executor::{spawn, spawn_boxed, Timer};
+#[cfg(all(unix, feature = "native"))]
+use crate::common::external_signer::UnixSocketSigner;
+use crate::common::external_signer::{ExternalSigner, NoSigner, WatchOnlyKeys};
+use crate::common::keystore::unlock_keystore;
 use crate::common::mm_ctx::{MmArc, MmCtx};
-use crate::common::privkey::key_pair_from_seed;
+use crate::common::privkey::{bip39_seed_from_mnemonic, key_pair_from_seed, ExtendedPrivateKey};
 use crate::common::{slurp_url, MM_DATETIME, MM_VERSION};
 use crate::mm2::database::init_and_migrate_db;
 use crate::mm2::lp_network::{p2p_event_process_loop, P2PContext};
 use crate::mm2::lp_ordermatch::{broadcast_maker_orders_keep_alive_loop, lp_ordermatch_loop, orders_kick_start,
                                 BalanceUpdateOrdermatchHandler};
 use crate::mm2::lp_swap::{running_swaps_num, swap_kick_starts};
+use crate::mm2::lp_webhooks::{validate_webhooks_conf, webhooks_loop};
+use crate::mm2::rpc::event_stream::BalanceUpdateEventStreamHandler;
 use crate::mm2::rpc::spawn_rpc;
 use bitcrypto::sha256;
+use keys::Public;
 
 pub fn lp_ports(netid: u16) -> Result<(u16, u16, u16), String> {
     const LP_RPCPORT: u16 = 7783;
@@ -170,6 +178,15 @@ fn fix_directories(ctx: &MmCtx) -> Result<(), String> {
     if !ensure_dir_is_writable(&dbdir.join("TX_CACHE")) {
         return ERR!("TX_CACHE db dir is not writable");
     }
+    if !ensure_dir_is_writable(&dbdir.join("HD_WALLET")) {
+        return ERR!("HD_WALLET db dir is not writable");
+    }
+    if !ensure_dir_is_writable(&dbdir.join("WEBHOOKS")) {
+        return ERR!("WEBHOOKS db dir is not writable");
+    }
+    if !ensure_dir_is_writable(&dbdir.join("WEBHOOKS").join("PENDING")) {
+        return ERR!("WEBHOOKS/PENDING db dir is not writable");
+    }
     try_s!(ensure_file_is_writable(&dbdir.join("GTC").join("orders")));
     Ok(())
 }
@@ -197,6 +214,8 @@ fn fix_directories(ctx: &MmCtx) -> Result<(), String> {
     writeable_dir!(dbdir.join("SWAPS").join("STATS").join("TAKER"));
     writeable_dir!(dbdir.join("ORDERS").join("MY").join("MAKER"));
     writeable_dir!(dbdir.join("ORDERS").join("MY").join("TAKER"));
+    writeable_dir!(dbdir.join("HD_WALLET"));
+    writeable_dir!(dbdir.join("WEBHOOKS").join("PENDING"));
     Ok(())
 }
 
@@ -227,10 +246,16 @@ fn migrate_db(ctx: &MmArc) -> Result<(), String> {
 #[cfg(feature = "native")]
 fn migration_1(_ctx: &MmArc) -> Result<(), String> { Ok(()) }
 
+/// The derivation path of the key pair used to sign the P2P messages, orders and swaps in the HD wallet mode.
+/// It's a dedicated non-BIP44 path, so the node pubkey isn't linked to the addresses of the wallets sharing the seed.
+const HD_IDENTITY_DERIVATION_PATH: &str = "m/7777'/0'";
+
 /// Resets the context (most of which resides currently in `lp::G` but eventually would move into `MmCtx`).
 /// Restarts the peer connections.
 /// Reloads the coin keys.
 ///
+/// The passphrase is decrypted from the `keystore` file if the `passphrase` isn't set in the config.
+///
 /// Besides the `passphrase` it also allows changing the `seednode` at runtime.  
 /// AG: While there might be value in changing `seednode` at runtime, I'm not sure if changing `gui` is actually necessary.
 ///
@@ -238,18 +263,81 @@ fn migration_1(_ctx: &MmArc) -> Result<(), String> { Ok(()) }
 ///     in order to avoid the possibility of invalid state.
 /// AP: Totally agree, moreover maybe we even `must` deny calling this on a working MM as it's being refactored
 pub fn lp_passphrase_init(ctx: &MmArc) -> Result<(), String> {
-    let passphrase = ctx.conf["passphrase"].as_str();
-    let passphrase = match passphrase {
-        None | Some("") => return ERR!("jeezy says we cant use the nullstring as passphrase and I agree"),
-        Some(s) => s.to_string(),
+    if !ctx.conf["watch_only"].is_null() {
+        return watch_only_init(ctx);
+    }
+
+    let passphrase = match (ctx.conf["passphrase"].as_str(), ctx.conf["keystore"].as_str()) {
+        (None, Some(keystore)) | (Some(""), Some(keystore)) => try_s!(unlock_keystore(Path::new(keystore))),
+        (None, None) | (Some(""), None) => {
+            return ERR!("jeezy says we cant use the nullstring as passphrase and I agree")
+        },
+        (Some(s), _) => s.to_string(),
     };
 
-    let key_pair = try_s!(key_pair_from_seed(&passphrase));
+    let key_pair = if ctx.conf["enable_hd"].as_bool().unwrap_or(false) {
+        // The passphrase is BIP39 mnemonic in the HD wallet mode.
+        let password = ctx.conf["bip39_password"].as_str().unwrap_or("");
+        let seed = try_s!(bip39_seed_from_mnemonic(&passphrase, password));
+        let master_key = try_s!(ExtendedPrivateKey::new_master(&seed));
+        let identity_path = try_s!(HD_IDENTITY_DERIVATION_PATH.parse());
+        let key_pair = try_s!(try_s!(master_key.derive_path(&identity_path)).key_pair());
+        try_s!(ctx.hd_master_key.pin(master_key));
+        key_pair
+    } else {
+        try_s!(key_pair_from_seed(&passphrase))
+    };
     let key_pair = try_s!(ctx.secp256k1_key_pair.pin(key_pair));
     try_s!(ctx.rmd160.pin(key_pair.public().address_hash()));
     Ok(())
 }
 
+/// Initializes the node in the watch-only mode: the node knows its public key only
+/// and every signature is requested from the external signer.
+///
+/// The mode is configured with the `watch_only` config object:
+/// `pubkey` - the compressed node public key in hex, requested from the signer if not set;
+/// `signer_socket` - the path to the Unix socket of the local signing process, see `UnixSocketSigner`.
+/// The node can show the balances and the history and serve the orderbook without the signer,
+/// the orders and swaps require the signer.
+///
+/// Only the single node public key is supported for now: the extended public keys (and so the HD wallet mode),
+/// the bare addresses and the ETH/ERC20 coins aren't supported in the watch-only mode.
+fn watch_only_init(ctx: &MmArc) -> Result<(), String> {
+    if ctx.conf["enable_hd"].as_bool().unwrap_or(false) {
+        return ERR!("The HD wallet mode is not supported in the watch-only mode");
+    }
+    let conf = &ctx.conf["watch_only"];
+    if !conf["xpub"].is_null() || !conf["addresses"].is_null() {
+        return ERR!(
+            "The extended public keys and addresses are not supported in the watch-only mode, set the 'pubkey'"
+        );
+    }
+
+    let signer: Arc<dyn ExternalSigner> = match conf["signer_socket"].as_str() {
+        #[cfg(all(unix, feature = "native"))]
+        Some(path) => Arc::new(UnixSocketSigner::new(path)),
+        #[cfg(not(all(unix, feature = "native")))]
+        Some(_) => return ERR!("The signer_socket is supported on Unix only"),
+        None => Arc::new(NoSigner),
+    };
+
+    let public = match conf["pubkey"].as_str() {
+        Some(pubkey) => {
+            let bytes = try_s!(hex::decode(pubkey));
+            if bytes.len() != 33 {
+                return ERR!("The watch_only pubkey must be the compressed public key");
+            }
+            try_s!(Public::from_slice(&bytes).map_err(|e| ERRL!("{}", e)))
+        },
+        None => try_s!(signer.public_key(&[])),
+    };
+
+    try_s!(ctx.rmd160.pin(public.address_hash()));
+    try_s!(ctx.watch_only.pin(WatchOnlyKeys { public, signer }));
+    Ok(())
+}
+
 /// Tries to serve on the given IP to check if it's available.  
 /// We need this check because our external IP, particularly under NAT,
 /// might be outside of the set of IPs we can open and run a server on.
@@ -329,6 +417,7 @@ fn seed_to_ipv4_string(seed: &str) -> Option<String> {
 pub async fn lp_init(mypubport: u16, ctx: MmArc) -> Result<(), String> {
     log! ({"lp_init] version: {} DT {}", MM_VERSION, MM_DATETIME});
     try_s!(lp_passphrase_init(&ctx));
+    try_s!(validate_webhooks_conf(&ctx));
 
     try_s!(fix_directories(&ctx));
     try_s!(ctx.init_sqlite_connection());
@@ -469,7 +558,8 @@ pub async fn lp_init(mypubport: u16, ctx: MmArc) -> Result<(), String> {
     };
 
     let ctx_on_poll = ctx.clone();
-    let force_p2p_key = if i_am_seed {
+    // The watch-only node has no secret to derive the P2P key from, so it runs with the random P2P identity.
+    let force_p2p_key = if i_am_seed && !ctx.is_watch_only() {
         let key = sha256(&*ctx.secp256k1_key_pair().private().secret);
         Some(key.take())
     } else {
@@ -522,6 +612,8 @@ pub async fn lp_init(mypubport: u16, ctx: MmArc) -> Result<(), String> {
 
     let balance_update_ordermatch_handler = BalanceUpdateOrdermatchHandler::new(ctx.clone());
     register_balance_update_handler(ctx.clone(), Box::new(balance_update_ordermatch_handler)).await;
+    let balance_update_event_stream_handler = BalanceUpdateEventStreamHandler::new(ctx.clone());
+    register_balance_update_handler(ctx.clone(), Box::new(balance_update_event_stream_handler)).await;
 
     try_s!(ctx.initialized.pin(true));
 
@@ -538,6 +630,8 @@ pub async fn lp_init(mypubport: u16, ctx: MmArc) -> Result<(), String> {
 
     spawn(broadcast_maker_orders_keep_alive_loop(ctx.clone()));
 
+    spawn(webhooks_loop(ctx.clone()));
+
     #[cfg(not(feature = "native"))]
     {
         if 1 == 1 {
@@ -22,9 +22,11 @@ use common::HyRes;
 use futures::{channel::oneshot, lock::Mutex as AsyncMutex, StreamExt};
 use mm2_libp2p::atomicdex_behaviour::{AdexBehaviourCmd, AdexBehaviourEvent, AdexCmdTx, AdexEventRx, AdexResponse,
                                       AdexResponseChannel};
-use mm2_libp2p::{decode_message, encode_message, GossipsubMessage, MessageId, PeerId, TOPIC_SEPARATOR};
+use mm2_libp2p::{decode_message, encode_and_sign, encode_and_sign_with, encode_message, GossipsubMessage, MessageId,
+                 PeerId, TOPIC_SEPARATOR};
 #[cfg(test)] use mocktopus::macros::*;
-use serde::de;
+use primitives::hash::H256;
+use serde::{de, Serialize};
 use std::sync::Arc;
 
 use crate::mm2::{lp_ordermatch, lp_swap};
@@ -156,6 +158,22 @@ async fn process_p2p_request(
 }
 
 #[cfg(feature = "native")]
+/// Encodes the message and signs it by the node key.
+/// The message is signed by the external signer if the node is started in the watch-only mode.
+pub fn encode_and_sign_by_node_key<T: Serialize>(ctx: &MmArc, message: &T) -> Result<Vec<u8>, String> {
+    if let Some(watch_only) = ctx.watch_only.as_option() {
+        let signer = watch_only.signer.clone();
+        return encode_and_sign_with(message, &watch_only.public, |hash| {
+            let recoverable = try_s!(signer.sign_hash(&[], &H256::from(*hash)));
+            let mut compact = [0; 64];
+            compact.copy_from_slice(&recoverable[..64]);
+            Ok(compact)
+        });
+    }
+    let key_pair = ctx.secp256k1_key_pair();
+    encode_and_sign(message, &*key_pair.private().secret).map_err(|e| ERRL!("{}", e))
+}
+
 pub fn broadcast_p2p_msg(ctx: &MmArc, topics: Vec<String>, msg: Vec<u8>) {
     let ctx = ctx.clone();
     spawn(async move {
@@ -23,24 +23,24 @@ use async_trait::async_trait;
 use bigdecimal::BigDecimal;
 use blake2::digest::{Update, VariableOutput};
 use blake2::VarBlake2b;
-use coins::utxo::{compressed_pub_key_from_priv_raw, ChecksumType};
 use coins::{address_by_coin_conf_and_pubkey_str, coin_conf, lp_coinfind, BalanceTradeFeeUpdatedHandler,
             FeeApproxStage, MmCoinEnum};
 use common::executor::{spawn, Timer};
 use common::log::error;
 use common::mm_ctx::{from_ctx, MmArc, MmWeak};
 use common::mm_number::{Fraction, MmNumber};
-use common::{bits256, json_dir_entries, log, new_uuid, now_ms, remove_file, write};
+use common::{bits256, json_dir_entries, log, new_uuid, now_ms, remove_file, write, Traceable};
 use futures::{compat::Future01CompatExt, lock::Mutex as AsyncMutex, StreamExt};
 use gstuff::slurp;
 use hash256_std_hasher::Hash256StdHasher;
 use hash_db::{Hasher, EMPTY_PREFIX};
 use http::Response;
-use mm2_libp2p::{decode_signed, encode_and_sign, encode_message, pub_sub_topic, TopicPrefix, TOPIC_SEPARATOR};
+use mm2_libp2p::{decode_signed, encode_message, pub_sub_topic, TopicPrefix, TOPIC_SEPARATOR};
 #[cfg(test)] use mocktopus::macros::*;
 use num_rational::BigRational;
 use num_traits::identities::Zero;
 use parity_util_mem::malloc_size;
+use primitives::hash::H264;
 use rpc::v1::types::H256 as H256Json;
 use serde_json::{self as json, Value as Json};
 use sp_trie::{delta_trie_root, DBValue, HashDBT, MemoryDB, Trie, TrieConfiguration, TrieDB, TrieDBMut, TrieHash,
@@ -56,15 +56,24 @@ use trie_db::NodeCodec as NodeCodecT;
 use uuid::Uuid;
 
 use crate::mm2::{database::my_swaps::insert_new_swap,
-                 lp_network::{broadcast_p2p_msg, request_any_relay, request_one_peer, subscribe_to_topic, P2PRequest},
+                 lp_network::{broadcast_p2p_msg, encode_and_sign_by_node_key, request_any_relay, request_one_peer,
+                              subscribe_to_topic, P2PRequest},
                  lp_swap::{calc_max_maker_vol, check_balance_for_maker_swap, check_balance_for_taker_swap,
-                           check_other_coin_balance_for_swap, is_pubkey_banned, lp_atomic_locktime, run_maker_swap,
-                           run_taker_swap, AtomicLocktimeVersion, CheckBalanceError, MakerSwap, RunMakerSwapInput,
-                           RunTakerSwapInput, SwapConfirmationsSettings, TakerSwap}};
+                           check_other_coin_balance_for_swap, dex_fee_amount_from_taker_coin, is_pubkey_banned,
+                           lp_atomic_locktime, my_swap_file_path, run_maker_swap, run_taker_swap,
+                           AtomicLocktimeVersion, CheckBalanceError, MakerSwap, RunMakerSwapInput, RunTakerSwapInput,
+                           SwapConfirmationsSettings, TakerSwap, TradeRequestError},
+                 lp_webhooks::{queue_webhook_event, WebhookEvent},
+                 rpc::event_stream::{broadcast_my_order_event, EventStreamContext, MmEvent, OrderStatusChange,
+                                     OrderbookOrder}};
 
 #[path = "lp_ordermatch/new_protocol.rs"] mod new_protocol;
 #[path = "lp_ordermatch/order_requests_tracker.rs"]
 mod order_requests_tracker;
+#[path = "lp_ordermatch/orderbook_depth.rs"]
+pub mod orderbook_depth;
+#[path = "lp_ordermatch/simple_market_maker.rs"]
+pub mod simple_market_maker;
 use order_requests_tracker::OrderRequestsTracker;
 
 #[cfg(test)]
@@ -82,6 +91,8 @@ const ORDERBOOK_REQUESTING_TIMEOUT: u64 = MIN_ORDER_KEEP_ALIVE_INTERVAL * 2;
 const INACTIVE_ORDER_TIMEOUT: u64 = 240;
 const MIN_TRADING_VOL: &str = "0.00777";
 const MAX_ORDERS_NUMBER_IN_ORDERBOOK_RESPONSE: usize = 1000;
+/// The max number of the orders of every pair in the `best_orders` response.
+const MAX_BEST_ORDERS_PER_COIN: usize = 20;
 
 /// Alphabetically ordered orderbook pair
 type AlbOrderedOrderbookPair = String;
@@ -388,6 +399,12 @@ pub enum OrdermatchRequest {
         /// Request using this condition
         trie_roots: HashMap<AlbOrderedOrderbookPair, H64>,
     },
+    /// Get the best orders buying or selling the `volume` of the `coin` across all its pairs.
+    BestOrders {
+        coin: String,
+        action: BestOrdersAction,
+        volume: BigRational,
+    },
 }
 
 #[derive(Debug)]
@@ -435,6 +452,9 @@ pub async fn process_peer_request(ctx: MmArc, request: OrdermatchRequest) -> Res
             let response = process_sync_pubkey_orderbook_state(ctx, pubkey, trie_roots).await;
             response.map(|res| res.map(|r| encode_message(&r).expect("Serialization failed")))
         },
+        OrdermatchRequest::BestOrders { coin, action, volume } => {
+            process_best_orders_request(ctx, coin, action, volume).await
+        },
     }
 }
 
@@ -515,6 +535,121 @@ async fn process_get_orderbook_request(ctx: MmArc, base: String, rel: String) ->
     Ok(Some(encoded))
 }
 
+/// The action of the taker looking for the best orders.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BestOrdersAction {
+    /// Buy the coin, i.e. look for the orders selling it.
+    Buy,
+    /// Sell the coin, i.e. look for the orders buying it.
+    Sell,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct BestOrdersRes {
+    /// The best orders by the other coin of the pair.
+    orders: HashMap<String, Vec<OrderbookItem>>,
+}
+
+/// Collects the orders of the `coin` pairs fitting the `volume` by the other coin of the pair,
+/// every list is sorted by price from the best one and limited to `MAX_BEST_ORDERS_PER_COIN`.
+fn best_orders_from_orderbook(
+    orderbook: &Orderbook,
+    coin: &str,
+    action: BestOrdersAction,
+    volume: &BigRational,
+) -> HashMap<String, Vec<OrderbookItem>> {
+    let mut best_orders = HashMap::new();
+    for ((base, rel), uuids) in orderbook.unordered.iter() {
+        let other_coin = match action {
+            BestOrdersAction::Buy if base == coin => rel,
+            BestOrdersAction::Sell if rel == coin => base,
+            _ => continue,
+        };
+        let orders = best_orders.entry(other_coin.clone()).or_insert_with(Vec::new);
+        for order in uuids.iter().filter_map(|uuid| orderbook.order_set.get(uuid)) {
+            if is_best_order_fitting(order, coin, other_coin, action, volume) {
+                orders.push(order.clone());
+            }
+        }
+    }
+
+    best_orders.retain(|_, orders| !orders.is_empty());
+    for orders in best_orders.values_mut() {
+        // the lower price is better for both actions: the taker pays less for the coin
+        // or receives more of the other coin for the coin sold
+        orders.sort_unstable_by(|order1, order2| order1.price.cmp(&order2.price));
+        orders.truncate(MAX_BEST_ORDERS_PER_COIN);
+    }
+    best_orders
+}
+
+/// Checks that the `order` passes the orderbook insertion checks, belongs to the `coin` and `other_coin` pair
+/// in the direction of the `action` and fits the `volume`.
+fn is_best_order_fitting(
+    order: &OrderbookItem,
+    coin: &str,
+    other_coin: &str,
+    action: BestOrdersAction,
+    volume: &BigRational,
+) -> bool {
+    let zero = BigRational::from_integer(0.into());
+    if order.max_volume <= zero || order.price <= zero || order.min_volume < zero {
+        return false;
+    }
+    let (base, rel) = match action {
+        BestOrdersAction::Buy => (coin, other_coin),
+        BestOrdersAction::Sell => (other_coin, coin),
+    };
+    if order.base != base || order.rel != rel {
+        return false;
+    }
+    // the volume in the maker order base coin
+    let base_volume = match action {
+        BestOrdersAction::Buy => volume.clone(),
+        BestOrdersAction::Sell => volume / &order.price,
+    };
+    base_volume >= order.min_volume && base_volume <= order.max_volume
+}
+
+/// Drops the orders returned by a relay that our node would not return itself for the same request,
+/// so the invalid or unrelated orders can't get to the ranking.
+fn filter_relay_best_orders(
+    orders: HashMap<String, Vec<OrderbookItem>>,
+    coin: &str,
+    action: BestOrdersAction,
+    volume: &BigRational,
+) -> HashMap<String, Vec<OrderbookItem>> {
+    orders
+        .into_iter()
+        .filter_map(|(other_coin, orders)| {
+            let orders: Vec<_> = orders
+                .into_iter()
+                .filter(|order| is_best_order_fitting(order, coin, &other_coin, action, volume))
+                .take(MAX_BEST_ORDERS_PER_COIN)
+                .collect();
+            if orders.is_empty() {
+                None
+            } else {
+                Some((other_coin, orders))
+            }
+        })
+        .collect()
+}
+
+async fn process_best_orders_request(
+    ctx: MmArc,
+    coin: String,
+    action: BestOrdersAction,
+    volume: BigRational,
+) -> Result<Option<Vec<u8>>, String> {
+    let ordermatch_ctx = try_s!(OrdermatchContext::from_ctx(&ctx));
+    let orderbook = ordermatch_ctx.orderbook.lock().await;
+    let orders = best_orders_from_orderbook(&orderbook, &coin, action, &volume);
+    let encoded = try_s!(encode_message(&BestOrdersRes { orders }));
+    Ok(Some(encoded))
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 enum DeltaOrFullTrie<Key: Eq + std::hash::Hash, Value> {
     Delta(HashMap<Key, Option<Value>>),
@@ -703,10 +838,15 @@ async fn maker_order_created_p2p_notify(ctx: MmArc, order: &MakerOrder) {
         pair_trie_root: H64::default(),
     };
 
-    let key_pair = ctx.secp256k1_key_pair.or(&&|| panic!());
     let to_broadcast = new_protocol::OrdermatchMessage::MakerOrderCreated(message.clone());
-    let encoded_msg = encode_and_sign(&to_broadcast, &*key_pair.private().secret).unwrap();
-    let order: OrderbookItem = (message, hex::encode(&**key_pair.public())).into();
+    let encoded_msg = match encode_and_sign_by_node_key(&ctx, &to_broadcast) {
+        Ok(msg) => msg,
+        Err(e) => {
+            log::error!("Error {} while signing the maker order created message", e);
+            return;
+        },
+    };
+    let order: OrderbookItem = (message, hex::encode(&**ctx.secp256k1_public())).into();
     insert_or_update_order(&ctx, order).await;
     broadcast_p2p_msg(&ctx, vec![topic], encoded_msg);
 }
@@ -725,8 +865,13 @@ async fn process_my_maker_order_updated(ctx: &MmArc, message: &new_protocol::Mak
 async fn maker_order_updated_p2p_notify(ctx: MmArc, base: &str, rel: &str, message: new_protocol::MakerOrderUpdated) {
     let msg: new_protocol::OrdermatchMessage = message.clone().into();
     let topic = orderbook_topic_from_base_rel(base, rel);
-    let key_pair = ctx.secp256k1_key_pair.or(&&|| panic!());
-    let encoded_msg = encode_and_sign(&msg, &*key_pair.private().secret).unwrap();
+    let encoded_msg = match encode_and_sign_by_node_key(&ctx, &msg) {
+        Ok(msg) => msg,
+        Err(e) => {
+            log::error!("Error {} while signing the maker order updated message", e);
+            return;
+        },
+    };
     process_my_maker_order_updated(&ctx, &message).await;
     broadcast_p2p_msg(&ctx, vec![topic], encoded_msg);
 }
@@ -738,6 +883,7 @@ async fn maker_order_cancelled_p2p_notify(ctx: MmArc, order: &MakerOrder) {
         pair_trie_root: H64::default(),
     });
     delete_my_order(&ctx, order.uuid).await;
+    broadcast_my_order_event(&ctx, order.uuid, "Maker", OrderStatusChange::Cancelled);
     log::debug!("maker_order_cancelled_p2p_notify called, message {:?}", message);
     broadcast_ordermatch_message(
         &ctx,
@@ -836,6 +982,9 @@ pub struct TakerRequest {
     #[serde(default)]
     match_by: MatchBy,
     conf_settings: Option<OrderConfirmationsSettings>,
+    /// Whether the request can be matched with a maker order of lower volume.
+    #[serde(default)]
+    allow_partial_fill: bool,
 }
 
 impl TakerRequest {
@@ -854,6 +1003,7 @@ impl TakerRequest {
             dest_pub_key: Default::default(),
             match_by: message.match_by.into(),
             conf_settings: Some(message.conf_settings),
+            allow_partial_fill: message.allow_partial_fill,
         }
     }
 
@@ -883,6 +1033,7 @@ impl Into<new_protocol::OrdermatchMessage> for TakerRequest {
             uuid: self.uuid.into(),
             match_by: self.match_by.into(),
             conf_settings: self.conf_settings.unwrap(),
+            allow_partial_fill: self.allow_partial_fill,
         })
     }
 }
@@ -902,6 +1053,7 @@ struct TakerRequestBuilder {
     action: TakerAction,
     match_by: MatchBy,
     conf_settings: Option<OrderConfirmationsSettings>,
+    allow_partial_fill: bool,
 }
 
 impl Default for TakerRequestBuilder {
@@ -915,6 +1067,7 @@ impl Default for TakerRequestBuilder {
             action: TakerAction::Buy,
             match_by: MatchBy::Any,
             conf_settings: None,
+            allow_partial_fill: false,
         }
     }
 }
@@ -1002,6 +1155,11 @@ impl TakerRequestBuilder {
         self
     }
 
+    fn with_partial_fill(mut self, allow_partial_fill: bool) -> Self {
+        self.allow_partial_fill = allow_partial_fill;
+        self
+    }
+
     /// Validate fields and build
     fn build(self) -> Result<TakerRequest, TakerRequestBuildError> {
         let min_vol = MmNumber::from(MIN_TRADING_VOL);
@@ -1051,6 +1209,7 @@ impl TakerRequestBuilder {
             dest_pub_key: Default::default(),
             match_by: self.match_by,
             conf_settings: self.conf_settings,
+            allow_partial_fill: self.allow_partial_fill,
         })
     }
 
@@ -1068,6 +1227,7 @@ impl TakerRequestBuilder {
             dest_pub_key: Default::default(),
             match_by: self.match_by,
             conf_settings: self.conf_settings,
+            allow_partial_fill: self.allow_partial_fill,
         }
     }
 }
@@ -1089,12 +1249,40 @@ impl Default for MatchBy {
 enum OrderType {
     FillOrKill,
     GoodTillCancelled,
+    /// The order is converted to the maker one like GoodTillCancelled, but it's cancelled at `expires_at`.
+    GoodTillTime {
+        /// UNIX timestamp in seconds.
+        expires_at: u64,
+    },
+    /// The order can be filled partially, the volume that is not filled within the taker order timeout is cancelled.
+    ImmediateOrCancel,
 }
 
 impl Default for OrderType {
     fn default() -> Self { OrderType::GoodTillCancelled }
 }
 
+impl OrderType {
+    /// Whether the order can be filled by several maker orders.
+    fn allows_partial_fill(&self) -> bool { *self != OrderType::FillOrKill }
+
+    fn expires_at(&self) -> Option<u64> {
+        match self {
+            OrderType::GoodTillTime { expires_at } => Some(*expires_at),
+            _ => None,
+        }
+    }
+
+    /// Whether the volume that is not filled by the taker order timeout should be placed as the maker order.
+    fn converts_to_maker(&self) -> bool {
+        match self {
+            OrderType::GoodTillCancelled => true,
+            OrderType::GoodTillTime { expires_at } => *expires_at > now_ms() / 1000,
+            OrderType::FillOrKill | OrderType::ImmediateOrCancel => false,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 struct TakerOrder {
     created_at: u64,
@@ -1113,7 +1301,49 @@ enum MatchReservedResult {
 }
 
 impl TakerOrder {
-    fn is_cancellable(&self) -> bool { self.matches.is_empty() }
+    fn is_cancellable(&self) -> bool { !self.has_ongoing_matches() }
+
+    /// Whether there are matches the swaps are not started for yet.
+    fn has_ongoing_matches(&self) -> bool { self.matches.values().any(|order_match| order_match.connected.is_none()) }
+
+    /// Returns the volume of the request base coin reserved by the maker.
+    fn reserved_base_amount<'a>(&self, reserved: &'a MakerReserved) -> &'a MmNumber {
+        match self.request.action {
+            TakerAction::Buy => reserved.get_base_amount(),
+            TakerAction::Sell => reserved.get_rel_amount(),
+        }
+    }
+
+    /// Returns the volume of the request base coin that is not reserved by the makers yet.
+    fn available_amount(&self) -> MmNumber {
+        let reserved = self.matches.values().fold(MmNumber::from(0), |reserved, order_match| {
+            &reserved + self.reserved_base_amount(&order_match.reserved)
+        });
+        self.request.get_base_amount() - &reserved
+    }
+
+    /// Returns the volume of the request base coin the swaps are started for.
+    fn filled_amount(&self) -> MmNumber {
+        self.matches
+            .values()
+            .filter(|order_match| order_match.connected.is_some())
+            .fold(MmNumber::from(0), |filled, order_match| {
+                &filled + self.reserved_base_amount(&order_match.reserved)
+            })
+    }
+
+    fn started_swaps(&self) -> Vec<Uuid> {
+        self.matches
+            .values()
+            .filter(|order_match| order_match.connected.is_some())
+            .map(|order_match| order_match.connect.swap_uuid())
+            .collect()
+    }
+
+    /// The order is completed when the whole volume is reserved and all the swaps are started.
+    fn is_completed(&self) -> bool {
+        !self.has_ongoing_matches() && self.available_amount() < MmNumber::from(MIN_TRADING_VOL)
+    }
 
     fn match_reserved(&self, reserved: &MakerReserved) -> MatchReservedResult {
         match &self.request.match_by {
@@ -1130,34 +1360,47 @@ impl TakerOrder {
             },
         }
 
+        if self.matches.contains_key(&reserved.maker_order_uuid) {
+            return MatchReservedResult::NotMatched;
+        }
+
         let my_base_amount = self.request.get_base_amount();
         let my_rel_amount = self.request.get_rel_amount();
-        let other_base_amount = reserved.get_base_amount();
-        let other_rel_amount = reserved.get_rel_amount();
+        // the amounts of the request base and rel coins the maker reserved
+        let (pair_matches, other_base_amount, other_rel_amount) = match self.request.action {
+            TakerAction::Buy => (
+                self.request.base == reserved.base && self.request.rel == reserved.rel,
+                reserved.get_base_amount(),
+                reserved.get_rel_amount(),
+            ),
+            TakerAction::Sell => (
+                self.request.base == reserved.rel && self.request.rel == reserved.base,
+                reserved.get_rel_amount(),
+                reserved.get_base_amount(),
+            ),
+        };
+        if !pair_matches {
+            return MatchReservedResult::NotMatched;
+        }
 
-        match self.request.action {
-            TakerAction::Buy => {
-                if self.request.base == reserved.base
-                    && self.request.rel == reserved.rel
-                    && my_base_amount == other_base_amount
-                    && other_rel_amount <= my_rel_amount
-                {
-                    MatchReservedResult::Matched
-                } else {
-                    MatchReservedResult::NotMatched
-                }
-            },
-            TakerAction::Sell => {
-                if self.request.base == reserved.rel
-                    && self.request.rel == reserved.base
-                    && my_base_amount == other_rel_amount
-                    && my_rel_amount <= other_base_amount
-                {
-                    MatchReservedResult::Matched
-                } else {
-                    MatchReservedResult::NotMatched
-                }
-            },
+        // the price must be the same or better than the requested one
+        let price_matches = match self.request.action {
+            TakerAction::Buy => other_rel_amount * my_base_amount <= my_rel_amount * other_base_amount,
+            TakerAction::Sell => my_rel_amount * other_base_amount <= other_rel_amount * my_base_amount,
+        };
+        if !price_matches {
+            return MatchReservedResult::NotMatched;
+        }
+
+        let volume_matches = if self.request.allow_partial_fill {
+            other_base_amount > &MmNumber::from(0) && other_base_amount <= &self.available_amount()
+        } else {
+            other_base_amount == my_base_amount && self.matches.is_empty()
+        };
+        if volume_matches {
+            MatchReservedResult::Matched
+        } else {
+            MatchReservedResult::NotMatched
         }
     }
 }
@@ -1178,6 +1421,9 @@ pub struct MakerOrder {
     started_swaps: Vec<Uuid>,
     uuid: Uuid,
     conf_settings: Option<OrderConfirmationsSettings>,
+    /// UNIX timestamp in seconds the order is cancelled at.
+    #[serde(default)]
+    expires_at: Option<u64>,
 }
 
 struct MakerOrderBuilder {
@@ -1187,6 +1433,7 @@ struct MakerOrderBuilder {
     base: String,
     rel: String,
     conf_settings: Option<OrderConfirmationsSettings>,
+    expires_at: Option<u64>,
 }
 
 impl Default for MakerOrderBuilder {
@@ -1198,6 +1445,7 @@ impl Default for MakerOrderBuilder {
             min_base_vol: 0.into(),
             price: 0.into(),
             conf_settings: None,
+            expires_at: None,
         }
     }
 }
@@ -1231,6 +1479,10 @@ enum MakerOrderBuildError {
         min: MmNumber,
         max: MmNumber,
     },
+    ExpiresAtInPast {
+        expires_at: u64,
+        now: u64,
+    },
 }
 
 impl fmt::Display for MakerOrderBuildError {
@@ -1270,6 +1522,11 @@ impl fmt::Display for MakerOrderBuildError {
                 max.to_decimal(),
                 min.to_decimal()
             ),
+            MakerOrderBuildError::ExpiresAtInPast { expires_at, now } => write!(
+                f,
+                "Expiration time {} is in the past, current time: {}",
+                expires_at, now
+            ),
         }
     }
 }
@@ -1305,6 +1562,11 @@ impl MakerOrderBuilder {
         self
     }
 
+    fn with_expires_at(mut self, expires_at: Option<u64>) -> Self {
+        self.expires_at = expires_at;
+        self
+    }
+
     /// Validate fields and build
     fn build(self) -> Result<MakerOrder, MakerOrderBuildError> {
         let min_price = MmNumber::from(BigRational::new(1.into(), 100_000_000.into()));
@@ -1362,6 +1624,13 @@ impl MakerOrderBuilder {
             return Err(MakerOrderBuildError::ConfSettingsNotSet);
         }
 
+        if let Some(expires_at) = self.expires_at {
+            let now = now_ms() / 1000;
+            if expires_at <= now {
+                return Err(MakerOrderBuildError::ExpiresAtInPast { expires_at, now });
+            }
+        }
+
         Ok(MakerOrder {
             base: self.base,
             rel: self.rel,
@@ -1373,6 +1642,7 @@ impl MakerOrderBuilder {
             started_swaps: Vec::new(),
             uuid: new_uuid(),
             conf_settings: self.conf_settings,
+            expires_at: self.expires_at,
         })
     }
 
@@ -1389,6 +1659,7 @@ impl MakerOrderBuilder {
             started_swaps: Vec::new(),
             uuid: new_uuid(),
             conf_settings: self.conf_settings,
+            expires_at: self.expires_at,
         }
     }
 }
@@ -1405,8 +1676,89 @@ impl MakerOrder {
         &self.max_base_vol - &reserved
     }
 
+    /// Returns the volume of the matches the swaps are started for.
+    /// This volume is locked by the swaps and isn't available in the balance anymore.
+    fn started_swaps_base_amount(&self) -> MmNumber {
+        self.matches
+            .values()
+            .filter(|order_match| order_match.connected.is_some())
+            .fold(MmNumber::from(0), |started, order_match| {
+                &started + order_match.reserved.get_base_amount()
+            })
+    }
+
+    /// Sets the new price and volumes keeping the matches intact.
+    /// Returns the message that should be broadcast to update the order in the orderbook.
+    fn apply_update(
+        &mut self,
+        new_price: MmNumber,
+        new_max_base_vol: MmNumber,
+        new_min_base_vol: MmNumber,
+    ) -> Result<new_protocol::MakerOrderUpdated, String> {
+        let min_price = MmNumber::from(BigRational::new(1.into(), 100_000_000.into()));
+        let min_vol = MmNumber::from(MIN_TRADING_VOL);
+
+        if new_price < min_price {
+            return ERR!(
+                "Price {} is too low, required: {}",
+                new_price.to_decimal(),
+                min_price.to_decimal()
+            );
+        }
+        if new_min_base_vol < min_vol {
+            return ERR!(
+                "Min base vol {} is too low, required: {}",
+                new_min_base_vol.to_decimal(),
+                min_vol.to_decimal()
+            );
+        }
+
+        let reserved = &self.max_base_vol - &self.available_amount();
+        let new_available = &new_max_base_vol - &reserved;
+        if new_available < new_min_base_vol {
+            return ERR!(
+                "Available volume {} is below min base vol {}, reserved by the matches: {}",
+                new_available.to_decimal(),
+                new_min_base_vol.to_decimal(),
+                reserved.to_decimal()
+            );
+        }
+        let rel_vol = &new_available * &new_price;
+        if rel_vol < min_vol {
+            return ERR!(
+                "Max rel vol {} is too low, required: {}",
+                rel_vol.to_decimal(),
+                min_vol.to_decimal()
+            );
+        }
+
+        let mut updated_msg = new_protocol::MakerOrderUpdated::new(self.uuid);
+        if new_price != self.price {
+            updated_msg = updated_msg.with_new_price(new_price.to_ratio());
+        }
+        // the orderbook contains the volume that is available for the matching
+        if new_available != self.available_amount() {
+            updated_msg = updated_msg.with_new_max_volume(new_available.to_ratio());
+        }
+        if new_min_base_vol != self.min_base_vol {
+            updated_msg = updated_msg.with_new_min_volume(new_min_base_vol.to_ratio());
+        }
+
+        self.price = new_price;
+        self.max_base_vol = new_max_base_vol;
+        self.min_base_vol = new_min_base_vol;
+        Ok(updated_msg)
+    }
+
     fn is_cancellable(&self) -> bool { !self.has_ongoing_matches() }
 
+    fn is_expired(&self) -> bool {
+        match self.expires_at {
+            Some(expires_at) => expires_at <= now_ms() / 1000,
+            None => false,
+        }
+    }
+
     fn has_ongoing_matches(&self) -> bool {
         for (_, order_match) in self.matches.iter() {
             // if there's at least 1 ongoing match the order is not cancellable
@@ -1418,6 +1770,10 @@ impl MakerOrder {
     }
 
     fn match_with_request(&self, taker: &TakerRequest) -> OrderMatchResult {
+        if self.is_expired() {
+            return OrderMatchResult::NotMatched;
+        }
+
         let taker_base_amount = taker.get_base_amount();
         let taker_rel_amount = taker.get_rel_amount();
 
@@ -1436,6 +1792,8 @@ impl MakerOrder {
                     && taker_price >= self.price
                 {
                     OrderMatchResult::Matched((taker_base_amount.clone(), taker_base_amount * &self.price))
+                } else if self.base == taker.base && self.rel == taker.rel && taker_price >= self.price {
+                    self.match_partially(taker, taker_base_amount)
                 } else {
                     OrderMatchResult::NotMatched
                 }
@@ -1450,12 +1808,25 @@ impl MakerOrder {
                     && taker_price >= self.price
                 {
                     OrderMatchResult::Matched((taker_base_amount / &self.price, taker_base_amount.clone()))
+                } else if self.base == taker.rel && self.rel == taker.base && taker_price >= self.price {
+                    self.match_partially(taker, taker_rel_amount)
                 } else {
                     OrderMatchResult::NotMatched
                 }
             },
         }
     }
+
+    /// Reserves the whole available volume if the taker requests more and accepts the partial fill.
+    fn match_partially(&self, taker: &TakerRequest, requested_base_amount: &MmNumber) -> OrderMatchResult {
+        let available = self.available_amount();
+        if taker.allow_partial_fill && requested_base_amount > &available && available >= self.min_base_vol {
+            let rel_amount = &available * &self.price;
+            OrderMatchResult::Matched((available, rel_amount))
+        } else {
+            OrderMatchResult::NotMatched
+        }
+    }
 }
 
 impl Into<MakerOrder> for TakerOrder {
@@ -1463,7 +1834,7 @@ impl Into<MakerOrder> for TakerOrder {
         match self.request.action {
             TakerAction::Sell => MakerOrder {
                 price: (self.request.get_rel_amount() / self.request.get_base_amount()),
-                max_base_vol: self.request.get_base_amount().clone(),
+                max_base_vol: self.available_amount(),
                 min_base_vol: MIN_TRADING_VOL.into(),
                 created_at: now_ms(),
                 base: self.request.base,
@@ -1472,11 +1843,13 @@ impl Into<MakerOrder> for TakerOrder {
                 started_swaps: Vec::new(),
                 uuid: self.request.uuid,
                 conf_settings: self.request.conf_settings,
+                expires_at: self.order_type.expires_at(),
             },
             // The "buy" taker order is recreated with reversed pair as Maker order is always considered as "sell"
             TakerAction::Buy => MakerOrder {
                 price: (self.request.get_base_amount() / self.request.get_rel_amount()),
-                max_base_vol: self.request.get_rel_amount().clone(),
+                max_base_vol: &self.available_amount()
+                    * &(self.request.get_rel_amount() / self.request.get_base_amount()),
                 min_base_vol: MIN_TRADING_VOL.into(),
                 created_at: now_ms(),
                 base: self.request.rel,
@@ -1485,6 +1858,7 @@ impl Into<MakerOrder> for TakerOrder {
                 started_swaps: Vec::new(),
                 uuid: self.request.uuid,
                 conf_settings: self.request.conf_settings.map(|s| s.reversed()),
+                expires_at: self.order_type.expires_at(),
             },
         }
     }
@@ -1496,6 +1870,14 @@ pub struct TakerConnect {
     maker_order_uuid: Uuid,
     sender_pubkey: H256Json,
     dest_pub_key: H256Json,
+    /// Set if the maker reserved a part of the taker order volume.
+    #[serde(default)]
+    swap_uuid: Option<Uuid>,
+}
+
+impl TakerConnect {
+    /// The swap of the whole taker order volume has the taker order uuid.
+    fn swap_uuid(&self) -> Uuid { self.swap_uuid.unwrap_or(self.taker_order_uuid) }
 }
 
 impl From<new_protocol::TakerConnect> for TakerConnect {
@@ -1505,6 +1887,7 @@ impl From<new_protocol::TakerConnect> for TakerConnect {
             maker_order_uuid: message.maker_order_uuid.into(),
             sender_pubkey: Default::default(),
             dest_pub_key: Default::default(),
+            swap_uuid: message.swap_uuid.map(|uuid| uuid.into()),
         }
     }
 }
@@ -1514,6 +1897,7 @@ impl Into<new_protocol::OrdermatchMessage> for TakerConnect {
         new_protocol::OrdermatchMessage::TakerConnect(new_protocol::TakerConnect {
             taker_order_uuid: self.taker_order_uuid.into(),
             maker_order_uuid: self.maker_order_uuid.into(),
+            swap_uuid: self.swap_uuid.map(|uuid| uuid.into()),
         })
     }
 }
@@ -1602,7 +1986,7 @@ impl Into<new_protocol::OrdermatchMessage> for MakerConnected {
 }
 
 pub async fn broadcast_maker_orders_keep_alive_loop(ctx: MmArc) {
-    let my_pubsecp = hex::encode(&**ctx.secp256k1_key_pair().public());
+    let my_pubsecp = hex::encode(&**ctx.secp256k1_public());
     while !ctx.is_stopping() {
         Timer::sleep(MIN_ORDER_KEEP_ALIVE_INTERVAL as f64).await;
         let ordermatch_ctx = OrdermatchContext::from_ctx(&ctx).expect("from_ctx failed");
@@ -1636,8 +2020,13 @@ fn broadcast_ordermatch_message(
     topics: impl IntoIterator<Item = String>,
     msg: new_protocol::OrdermatchMessage,
 ) {
-    let key_pair = ctx.secp256k1_key_pair.or(&&|| panic!());
-    let encoded_msg = encode_and_sign(&msg, &*key_pair.private().secret).unwrap();
+    let encoded_msg = match encode_and_sign_by_node_key(ctx, &msg) {
+        Ok(msg) => msg,
+        Err(e) => {
+            log::error!("Error {} while signing the ordermatch message", e);
+            return;
+        },
+    };
     broadcast_p2p_msg(ctx, topics.into_iter().collect(), encoded_msg);
 }
 
@@ -1810,6 +2199,8 @@ struct Orderbook {
     topics_subscribed_to: HashMap<String, OrderbookRequestingState>,
     /// MemoryDB instance to store Patricia Tries data
     memory_db: MemoryDB<Blake2Hasher64>,
+    /// Is used to send the orderbook changes to the event stream subscribers.
+    event_stream_ctx: Option<Arc<EventStreamContext>>,
 }
 
 fn hashed_null_node<T: TrieConfiguration>() -> TrieHash<T> { <T::Codec as NodeCodecT>::hashed_null_node() }
@@ -1895,14 +2286,42 @@ impl Orderbook {
             .or_insert_with(HashSet::new)
             .insert(order.uuid);
 
+        self.notify_order_updated(&order);
         self.order_set.insert(order.uuid, order);
     }
 
+    fn notify_order_updated(&self, order: &OrderbookItem) {
+        if let Some(event_stream_ctx) = &self.event_stream_ctx {
+            event_stream_ctx.publish_with(|| {
+                MmEvent::OrderbookOrderUpdated(OrderbookOrder {
+                    uuid: order.uuid,
+                    pubkey: order.pubkey.clone(),
+                    base: order.base.clone(),
+                    rel: order.rel.clone(),
+                    price: MmNumber::from(order.price.clone()).to_decimal(),
+                    max_volume: MmNumber::from(order.max_volume.clone()).to_decimal(),
+                    min_volume: MmNumber::from(order.min_volume.clone()).to_decimal(),
+                })
+            });
+        }
+    }
+
+    fn notify_order_removed(&self, order: &OrderbookItem) {
+        if let Some(event_stream_ctx) = &self.event_stream_ctx {
+            event_stream_ctx.publish_with(|| MmEvent::OrderbookOrderRemoved {
+                uuid: order.uuid,
+                base: order.base.clone(),
+                rel: order.rel.clone(),
+            });
+        }
+    }
+
     fn remove_order(&mut self, uuid: Uuid) -> Option<OrderbookItem> {
         let order = match self.order_set.remove(&uuid) {
             Some(order) => order,
             None => return None,
         };
+        self.notify_order_removed(&order);
         let base_rel = (order.base.clone(), order.rel.clone());
 
         // create an `order_to_delete` that allows to find and remove an element from `self.ordered` by hash
@@ -1933,6 +2352,7 @@ impl Orderbook {
             Some(order) => order,
             None => return None,
         };
+        self.notify_order_removed(&order);
         let base_rel = (order.base.clone(), order.rel.clone());
 
         // create an `order_to_delete` that allows to find and remove an element from `self.ordered` by hash
@@ -2044,7 +2464,14 @@ impl OrdermatchContext {
     /// Obtains a reference to this crate context, creating it if necessary.
     fn from_ctx(ctx: &MmArc) -> Result<Arc<OrdermatchContext>, String> {
         Ok(try_s!(from_ctx(&ctx.ordermatch_ctx, move || {
-            Ok(OrdermatchContext::default())
+            let orderbook = Orderbook {
+                event_stream_ctx: Some(try_s!(EventStreamContext::from_ctx(ctx))),
+                ..Orderbook::default()
+            };
+            Ok(OrdermatchContext {
+                orderbook: AsyncMutex::new(orderbook),
+                ..OrdermatchContext::default()
+            })
         })))
     }
 
@@ -2087,9 +2514,11 @@ fn lp_connect_start_bob(ctx: MmArc, maker_match: MakerMatch, maker_order: MakerO
         alice.bytes = maker_match.request.sender_pubkey.0;
         let maker_amount = maker_match.reserved.get_base_amount().to_decimal();
         let taker_amount = maker_match.reserved.get_rel_amount().to_decimal();
-        let privkey = &ctx.secp256k1_key_pair().private().secret;
-        let my_persistent_pub = unwrap!(compressed_pub_key_from_priv_raw(&privkey[..], ChecksumType::DSHA256));
-        let uuid = maker_match.request.uuid;
+        let my_persistent_pub = H264::from(&**ctx.secp256k1_public());
+        let uuid = match &maker_match.connect {
+            Some(connect) => connect.swap_uuid(),
+            None => maker_match.request.uuid,
+        };
         let my_conf_settings = choose_maker_confs_and_notas(
             maker_order.conf_settings,
             &maker_match.request,
@@ -2171,11 +2600,10 @@ fn lp_connected_alice(ctx: MmArc, taker_request: TakerRequest, taker_match: Take
             },
         };
 
-        let privkey = &ctx.secp256k1_key_pair().private().secret;
-        let my_persistent_pub = unwrap!(compressed_pub_key_from_priv_raw(&privkey[..], ChecksumType::DSHA256));
+        let my_persistent_pub = H264::from(&**ctx.secp256k1_public());
         let maker_amount = taker_match.reserved.get_base_amount().clone();
         let taker_amount = taker_match.reserved.get_rel_amount().clone();
-        let uuid = taker_match.reserved.taker_order_uuid;
+        let uuid = taker_match.connect.swap_uuid();
 
         let my_conf_settings =
             choose_taker_confs_and_notas(&taker_request, &taker_match.reserved, &maker_coin, &taker_coin);
@@ -2229,7 +2657,7 @@ fn lp_connected_alice(ctx: MmArc, taker_request: TakerRequest, taker_match: Take
 }
 
 pub async fn lp_ordermatch_loop(ctx: MmArc) {
-    let my_pubsecp = hex::encode(&**ctx.secp256k1_key_pair().public());
+    let my_pubsecp = hex::encode(&**ctx.secp256k1_public());
     loop {
         if ctx.is_stopping() {
             break;
@@ -2239,22 +2667,29 @@ pub async fn lp_ordermatch_loop(ctx: MmArc) {
             let mut my_taker_orders = ordermatch_ctx.my_taker_orders.lock().await;
             let mut my_maker_orders = ordermatch_ctx.my_maker_orders.lock().await;
             let _my_cancelled_orders = ordermatch_ctx.my_cancelled_orders.lock().await;
-            // transform the timed out and unmatched GTC taker orders to maker
+            // transform the timed out GTC and GTT taker orders to maker with the volume that is not filled yet
             *my_taker_orders = my_taker_orders
                 .drain()
                 .filter_map(|(uuid, order)| {
                     if order.created_at + TAKER_ORDER_TIMEOUT * 1000 < now_ms() {
                         delete_my_taker_order(&ctx, &uuid);
-                        if order.matches.is_empty() && order.order_type == OrderType::GoodTillCancelled {
+                        if !order.has_ongoing_matches()
+                            && order.available_amount() >= MmNumber::from(MIN_TRADING_VOL)
+                            && order.order_type.converts_to_maker()
+                        {
                             let maker_order: MakerOrder = order.into();
                             my_maker_orders.insert(uuid, maker_order.clone());
                             save_my_maker_order(&ctx, &maker_order);
+                            broadcast_my_order_event(&ctx, uuid, "Taker", OrderStatusChange::ConvertedToMaker);
                             spawn({
                                 let ctx = ctx.clone();
                                 async move {
                                     maker_order_created_p2p_notify(ctx, &maker_order).await;
                                 }
                             });
+                        } else {
+                            // the volume that is not filled yet is cancelled
+                            broadcast_my_order_event(&ctx, uuid, "Taker", OrderStatusChange::Cancelled);
                         }
                         None
                     } else {
@@ -2276,7 +2711,20 @@ pub async fn lp_ordermatch_loop(ctx: MmArc) {
                 .filter_map(|(uuid, order)| {
                     let ctx = ctx.clone();
                     async move {
-                        if order.available_amount() < order.min_base_vol && !order.has_ongoing_matches() {
+                        // cancel the filled and expired orders
+                        let is_filled = order.available_amount() < order.min_base_vol;
+                        if (is_filled || order.is_expired()) && !order.has_ongoing_matches() {
+                            if is_filled {
+                                let event = WebhookEvent::MakerOrderFilled {
+                                    uuid,
+                                    base: order.base.clone(),
+                                    rel: order.rel.clone(),
+                                    filled_base_amount: order.started_swaps_base_amount().to_decimal(),
+                                };
+                                if let Err(e) = queue_webhook_event(&ctx, event) {
+                                    log::error!("Error {} queueing the webhook of maker order {}", e, uuid);
+                                }
+                            }
                             delete_my_maker_order(&ctx, &order);
                             maker_order_cancelled_p2p_notify(ctx.clone(), &order).await;
                             None
@@ -2363,13 +2811,20 @@ async fn process_maker_reserved(ctx: MmArc, from_pubkey: H256Json, reserved_msg:
     };
 
     // send "connect" message if reserved message targets our pubkey AND
-    // reserved amounts match our order AND order is NOT reserved by someone else (empty matches)
-    if my_order.match_reserved(&reserved_msg) == MatchReservedResult::Matched && my_order.matches.is_empty() {
+    // reserved amounts match our order AND the reserved volume is NOT reserved by someone else
+    if my_order.match_reserved(&reserved_msg) == MatchReservedResult::Matched {
+        // each partial match is a separate swap
+        let swap_uuid = if my_order.reserved_base_amount(&reserved_msg) == my_order.request.get_base_amount() {
+            None
+        } else {
+            Some(new_uuid())
+        };
         let connect = TakerConnect {
             sender_pubkey: H256Json::from(our_public_id.bytes),
             dest_pub_key: reserved_msg.sender_pubkey.clone(),
             taker_order_uuid: reserved_msg.taker_order_uuid,
             maker_order_uuid: reserved_msg.maker_order_uuid,
+            swap_uuid,
         };
         let topic = orderbook_topic_from_base_rel(&my_order.request.base, &my_order.request.rel);
         broadcast_ordermatch_message(&ctx, vec![topic], connect.clone().into());
@@ -2383,6 +2838,7 @@ async fn process_maker_reserved(ctx: MmArc, from_pubkey: H256Json, reserved_msg:
             .matches
             .insert(taker_match.reserved.maker_order_uuid, taker_match);
         save_my_taker_order(&ctx, &my_order);
+        broadcast_my_order_event(&ctx, my_order.request.uuid, "Taker", OrderStatusChange::Matched);
     }
 }
 
@@ -2395,11 +2851,12 @@ async fn process_maker_connected(ctx: MmArc, from_pubkey: H256Json, connected: M
     }
 
     let mut my_taker_orders = ordermatch_ctx.my_taker_orders.lock().await;
-    let my_order_entry = match my_taker_orders.entry(connected.taker_order_uuid) {
+    let mut my_order_entry = match my_taker_orders.entry(connected.taker_order_uuid) {
         Entry::Occupied(e) => e,
         Entry::Vacant(_) => return,
     };
-    let order_match = match my_order_entry.get().matches.get(&connected.maker_order_uuid) {
+    let my_order = my_order_entry.get_mut();
+    let order_match = match my_order.matches.get_mut(&connected.maker_order_uuid) {
         Some(o) => o,
         None => {
             log::warn!(
@@ -2414,11 +2871,26 @@ async fn process_maker_connected(ctx: MmArc, from_pubkey: H256Json, connected: M
         log::error!("Connected message sender pubkey != reserved message sender pubkey");
         return;
     }
+    if order_match.connected.is_some() {
+        log::warn!(
+            "The swap of the match with uuid {} is started already",
+            connected.maker_order_uuid
+        );
+        return;
+    }
+    order_match.connected = Some(connected);
+    order_match.last_updated = now_ms();
     // alice
-    lp_connected_alice(ctx.clone(), my_order_entry.get().request.clone(), order_match.clone());
-    // remove the matched order immediately
-    delete_my_taker_order(&ctx, &my_order_entry.get().request.uuid);
-    my_order_entry.remove();
+    lp_connected_alice(ctx.clone(), my_order.request.clone(), order_match.clone());
+    broadcast_my_order_event(&ctx, my_order.request.uuid, "Taker", OrderStatusChange::SwapStarted);
+    // remove the order immediately if the whole volume is matched
+    if my_order.is_completed() {
+        delete_my_taker_order(&ctx, &my_order.request.uuid);
+        broadcast_my_order_event(&ctx, my_order.request.uuid, "Taker", OrderStatusChange::Completed);
+        my_order_entry.remove();
+    } else {
+        save_my_taker_order(&ctx, my_order);
+    }
 }
 
 async fn process_taker_request(ctx: MmArc, from_pubkey: H256Json, taker_request: TakerRequest) {
@@ -2486,6 +2958,7 @@ async fn process_taker_request(ctx: MmArc, from_pubkey: H256Json, taker_request:
                 };
                 order.matches.insert(maker_match.request.uuid, maker_match);
                 save_my_maker_order(&ctx, &order);
+                broadcast_my_order_event(&ctx, order.uuid, "Maker", OrderStatusChange::Matched);
             }
             return;
         }
@@ -2501,6 +2974,8 @@ async fn process_taker_connect(ctx: MmArc, sender_pubkey: H256Json, connect_msg:
     }
 
     let mut maker_orders = ordermatch_ctx.my_maker_orders.lock().await;
+    let swap_uuid = connect_msg.swap_uuid();
+    let swap_uuid_taken = is_swap_uuid_taken(&ctx, &maker_orders, &connect_msg.taker_order_uuid, &swap_uuid);
     let my_order = match maker_orders.get_mut(&connect_msg.maker_order_uuid) {
         Some(o) => o,
         None => return,
@@ -2521,6 +2996,10 @@ async fn process_taker_connect(ctx: MmArc, sender_pubkey: H256Json, connect_msg:
     }
 
     if order_match.connected.is_none() && order_match.connect.is_none() {
+        if swap_uuid_taken {
+            log::warn!("Swap uuid {} chosen by the taker is already used", swap_uuid);
+            return;
+        }
         let connected = MakerConnected {
             sender_pubkey: our_public_id.bytes.into(),
             dest_pub_key: connect_msg.sender_pubkey.clone(),
@@ -2530,10 +3009,11 @@ async fn process_taker_connect(ctx: MmArc, sender_pubkey: H256Json, connect_msg:
         };
         let topic = orderbook_topic_from_base_rel(&my_order.base, &my_order.rel);
         broadcast_ordermatch_message(&ctx, vec![topic], connected.clone().into());
+        my_order.started_swaps.push(swap_uuid);
         order_match.connect = Some(connect_msg);
         order_match.connected = Some(connected);
-        my_order.started_swaps.push(order_match.request.uuid);
         lp_connect_start_bob(ctx.clone(), order_match.clone(), my_order.clone());
+        broadcast_my_order_event(&ctx, my_order.uuid, "Maker", OrderStatusChange::SwapStarted);
 
         // If volume is less order will be cancelled a bit later
         if my_order.available_amount() >= my_order.min_base_vol {
@@ -2545,6 +3025,24 @@ async fn process_taker_connect(ctx: MmArc, sender_pubkey: H256Json, connect_msg:
     }
 }
 
+/// Checks if the swap uuid chosen by the taker clashes with another match of our maker orders or a swap of our node.
+/// The legacy swap uuid of the match being connected is its taker order uuid, so it doesn't clash.
+fn is_swap_uuid_taken(
+    ctx: &MmArc,
+    maker_orders: &HashMap<Uuid, MakerOrder>,
+    taker_order_uuid: &Uuid,
+    swap_uuid: &Uuid,
+) -> bool {
+    let clashes_with_match = maker_orders.values().any(|order| {
+        order.started_swaps.contains(swap_uuid)
+            || order.matches.iter().any(|(uuid, maker_match)| {
+                let connected_swap_uuid = maker_match.connect.as_ref().map(TakerConnect::swap_uuid);
+                uuid != taker_order_uuid && (uuid == swap_uuid || connected_swap_uuid.as_ref() == Some(swap_uuid))
+            })
+    });
+    clashes_with_match || my_swap_file_path(ctx, swap_uuid).exists()
+}
+
 #[derive(Deserialize, Debug)]
 pub struct AutoBuyInput {
     base: String,
@@ -2571,66 +3069,96 @@ pub struct AutoBuyInput {
 }
 
 pub async fn buy(ctx: MmArc, req: Json) -> Result<Response<Vec<u8>>, String> {
-    let input: AutoBuyInput = try_s!(json::from_value(req));
+    let res = try_s!(buy_rpc(&ctx, req).await);
+    let res = try_s!(json::to_vec(&res));
+    Ok(try_s!(Response::builder().body(res)))
+}
+
+/// Returns the `buy` response keeping the [`TradeRequestError`] type.
+pub async fn buy_rpc(ctx: &MmArc, req: Json) -> Result<Json, TradeRequestError> {
+    let input: AutoBuyInput = try_map!(json::from_value(req), TradeRequestError::InvalidParams);
     if input.base == input.rel {
-        return ERR!("Base and rel must be different coins");
-    }
-    let rel_coin = try_s!(lp_coinfind(&ctx, &input.rel).await);
-    let rel_coin = try_s!(rel_coin.ok_or("Rel coin is not found or inactive"));
-    let base_coin = try_s!(lp_coinfind(&ctx, &input.base).await);
-    let base_coin: MmCoinEnum = try_s!(base_coin.ok_or("Base coin is not found or inactive"));
+        return Err(TradeRequestError::InvalidParams(ERRL!(
+            "Base and rel must be different coins"
+        )));
+    }
+    let rel_coin = try_map!(lp_coinfind(&ctx, &input.rel).await, TradeRequestError::Other);
+    let rel_coin = try_map!(
+        rel_coin.ok_or("Rel coin is not found or inactive"),
+        TradeRequestError::Other
+    );
+    let base_coin = try_map!(lp_coinfind(&ctx, &input.base).await, TradeRequestError::Other);
+    let base_coin: MmCoinEnum = try_map!(
+        base_coin.ok_or("Base coin is not found or inactive"),
+        TradeRequestError::Other
+    );
     if base_coin.wallet_only() {
-        return ERR!("Base coin is wallet only");
+        return Err(TradeRequestError::InvalidParams(ERRL!("Base coin is wallet only")));
     }
     if rel_coin.wallet_only() {
-        return ERR!("Rel coin is wallet only");
+        return Err(TradeRequestError::InvalidParams(ERRL!("Rel coin is wallet only")));
     }
     let my_amount = &input.volume * &input.price;
-    try_s!(
-        check_balance_for_taker_swap(
-            &ctx,
-            &rel_coin,
-            &base_coin,
-            my_amount,
-            None,
-            None,
-            FeeApproxStage::OrderIssue
-        )
+    check_balance_for_taker_swap(
+        &ctx,
+        &rel_coin,
+        &base_coin,
+        my_amount,
+        None,
+        None,
+        FeeApproxStage::OrderIssue,
+    )
+    .await
+    .trace(source!())?;
+    lp_auto_buy(&ctx, &base_coin, &rel_coin, input)
         .await
-    );
-    let res = try_s!(lp_auto_buy(&ctx, &base_coin, &rel_coin, input).await).into_bytes();
-    Ok(try_s!(Response::builder().body(res)))
+        .map_err(TradeRequestError::Other)
 }
 
 pub async fn sell(ctx: MmArc, req: Json) -> Result<Response<Vec<u8>>, String> {
-    let input: AutoBuyInput = try_s!(json::from_value(req));
+    let res = try_s!(sell_rpc(&ctx, req).await);
+    let res = try_s!(json::to_vec(&res));
+    Ok(try_s!(Response::builder().body(res)))
+}
+
+/// Returns the `sell` response keeping the [`TradeRequestError`] type.
+pub async fn sell_rpc(ctx: &MmArc, req: Json) -> Result<Json, TradeRequestError> {
+    let input: AutoBuyInput = try_map!(json::from_value(req), TradeRequestError::InvalidParams);
     if input.base == input.rel {
-        return ERR!("Base and rel must be different coins");
-    }
-    let base_coin = try_s!(lp_coinfind(&ctx, &input.base).await);
-    let base_coin = try_s!(base_coin.ok_or("Base coin is not found or inactive"));
-    let rel_coin = try_s!(lp_coinfind(&ctx, &input.rel).await);
-    let rel_coin = try_s!(rel_coin.ok_or("Rel coin is not found or inactive"));
+        return Err(TradeRequestError::InvalidParams(ERRL!(
+            "Base and rel must be different coins"
+        )));
+    }
+    let base_coin = try_map!(lp_coinfind(&ctx, &input.base).await, TradeRequestError::Other);
+    let base_coin = try_map!(
+        base_coin.ok_or("Base coin is not found or inactive"),
+        TradeRequestError::Other
+    );
+    let rel_coin = try_map!(lp_coinfind(&ctx, &input.rel).await, TradeRequestError::Other);
+    let rel_coin = try_map!(
+        rel_coin.ok_or("Rel coin is not found or inactive"),
+        TradeRequestError::Other
+    );
     if base_coin.wallet_only() {
-        return ERR!("Base coin is wallet only");
+        return Err(TradeRequestError::InvalidParams(ERRL!("Base coin is wallet only")));
     }
     if rel_coin.wallet_only() {
-        return ERR!("Rel coin is wallet only");
+        return Err(TradeRequestError::InvalidParams(ERRL!("Rel coin is wallet only")));
     }
-    try_s!(
-        check_balance_for_taker_swap(
-            &ctx,
-            &base_coin,
-            &rel_coin,
-            input.volume.clone(),
-            None,
-            None,
-            FeeApproxStage::OrderIssue
-        )
+    check_balance_for_taker_swap(
+        &ctx,
+        &base_coin,
+        &rel_coin,
+        input.volume.clone(),
+        None,
+        None,
+        FeeApproxStage::OrderIssue,
+    )
+    .await
+    .trace(source!())?;
+    lp_auto_buy(&ctx, &base_coin, &rel_coin, input)
         .await
-    );
-    let res = try_s!(lp_auto_buy(&ctx, &base_coin, &rel_coin, input).await).into_bytes();
-    Ok(try_s!(Response::builder().body(res)))
+        .map_err(TradeRequestError::Other)
 }
 
 /// Created when maker order is matched with taker request
@@ -2668,6 +3196,7 @@ impl<'a> From<&'a TakerRequest> for TakerRequestForRpc<'a> {
             dest_pub_key: &request.dest_pub_key,
             match_by: &request.match_by,
             conf_settings: &request.conf_settings,
+            allow_partial_fill: request.allow_partial_fill,
         }
     }
 }
@@ -2694,6 +3223,7 @@ pub struct TakerRequestForRpc<'a> {
     dest_pub_key: &'a H256Json,
     match_by: &'a MatchBy,
     conf_settings: &'a Option<OrderConfirmationsSettings>,
+    allow_partial_fill: bool,
 }
 
 pub async fn lp_auto_buy(
@@ -2701,11 +3231,17 @@ pub async fn lp_auto_buy(
     base_coin: &MmCoinEnum,
     rel_coin: &MmCoinEnum,
     input: AutoBuyInput,
-) -> Result<String, String> {
+) -> Result<Json, String> {
     if input.price < MmNumber::from(BigRational::new(1.into(), 100_000_000.into())) {
         return ERR!("Price is too low, minimum is 0.00000001");
     }
 
+    if let Some(expires_at) = input.order_type.expires_at() {
+        if expires_at <= now_ms() / 1000 {
+            return ERR!("The order expiration time {} has already passed", expires_at);
+        }
+    }
+
     let action = match Some(input.method.as_ref()) {
         Some("buy") => TakerAction::Buy,
         Some("sell") => TakerAction::Sell,
@@ -2731,7 +3267,9 @@ pub async fn lp_auto_buy(
         .with_action(action)
         .with_match_by(input.match_by)
         .with_conf_settings(conf_settings)
-        .with_sender_pubkey(H256Json::from(our_public_id.bytes));
+        .with_sender_pubkey(H256Json::from(our_public_id.bytes))
+        // the FOK order must be filled by a single maker order
+        .with_partial_fill(input.order_type.allows_partial_fill());
     let request = try_s!(request_builder.build());
     broadcast_ordermatch_message(
         &ctx,
@@ -2750,9 +3288,10 @@ pub async fn lp_auto_buy(
         order_type: input.order_type,
     };
     save_my_taker_order(ctx, &order);
+    broadcast_my_order_event(ctx, order.request.uuid, "Taker", OrderStatusChange::Created);
     my_taker_orders.insert(order.request.uuid, order);
     drop(my_taker_orders);
-    Ok(result.to_string())
+    Ok(result)
 }
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
@@ -2824,6 +3363,10 @@ struct SetPriceReq {
     base_nota: Option<bool>,
     rel_confs: Option<u64>,
     rel_nota: Option<bool>,
+    /// UNIX timestamp in seconds the order is cancelled at.
+    expires_at: Option<u64>,
+    /// The order lifetime in seconds, an alternative to `expires_at`.
+    ttl: Option<u64>,
 }
 
 #[derive(Debug, Serialize)]
@@ -2849,6 +3392,7 @@ pub struct TakerConnectForRpc<'a> {
     method: String,
     sender_pubkey: &'a H256Json,
     dest_pub_key: &'a H256Json,
+    swap_uuid: Uuid,
 }
 
 impl<'a> From<&'a TakerConnect> for TakerConnectForRpc<'a> {
@@ -2859,6 +3403,7 @@ impl<'a> From<&'a TakerConnect> for TakerConnectForRpc<'a> {
             method: "connect".to_string(),
             sender_pubkey: &connect.sender_pubkey,
             dest_pub_key: &connect.dest_pub_key,
+            swap_uuid: connect.swap_uuid(),
         }
     }
 }
@@ -2939,6 +3484,7 @@ struct MakerOrderForRpc<'a> {
     started_swaps: &'a [Uuid],
     uuid: Uuid,
     conf_settings: &'a Option<OrderConfirmationsSettings>,
+    expires_at: Option<u64>,
 }
 
 impl<'a> From<&'a MakerOrder> for MakerOrderForRpc<'a> {
@@ -2961,31 +3507,53 @@ impl<'a> From<&'a MakerOrder> for MakerOrderForRpc<'a> {
             started_swaps: &order.started_swaps,
             uuid: order.uuid,
             conf_settings: &order.conf_settings,
+            expires_at: order.expires_at,
         }
     }
 }
 
 pub async fn set_price(ctx: MmArc, req: Json) -> Result<Response<Vec<u8>>, String> {
-    let req: SetPriceReq = try_s!(json::from_value(req));
+    let res = try_s!(set_price_rpc(&ctx, req).await);
+    let res = try_s!(json::to_vec(&res));
+    Ok(try_s!(Response::builder().body(res)))
+}
 
-    let base_coin: MmCoinEnum = match try_s!(lp_coinfind(&ctx, &req.base).await) {
+/// Returns the `setprice` response keeping the [`TradeRequestError`] type.
+pub async fn set_price_rpc(ctx: &MmArc, req: Json) -> Result<Json, TradeRequestError> {
+    let req: SetPriceReq = try_map!(json::from_value(req), TradeRequestError::InvalidParams);
+
+    let base_coin: MmCoinEnum = match try_map!(lp_coinfind(&ctx, &req.base).await, TradeRequestError::Other) {
         Some(coin) => coin,
-        None => return ERR!("Base coin {} is not found", req.base),
+        None => return Err(TradeRequestError::Other(ERRL!("Base coin {} is not found", req.base))),
     };
 
-    let rel_coin: MmCoinEnum = match try_s!(lp_coinfind(&ctx, &req.rel).await) {
+    let rel_coin: MmCoinEnum = match try_map!(lp_coinfind(&ctx, &req.rel).await, TradeRequestError::Other) {
         Some(coin) => coin,
-        None => return ERR!("Rel coin {} is not found", req.rel),
+        None => return Err(TradeRequestError::Other(ERRL!("Rel coin {} is not found", req.rel))),
     };
 
     if base_coin.wallet_only() {
-        return ERR!("Base coin is wallet only");
+        return Err(TradeRequestError::InvalidParams(ERRL!("Base coin is wallet only")));
     }
     if rel_coin.wallet_only() {
-        return ERR!("Rel coin is wallet only");
+        return Err(TradeRequestError::InvalidParams(ERRL!("Rel coin is wallet only")));
     }
 
-    let ordermatch_ctx = try_s!(OrdermatchContext::from_ctx(&ctx));
+    let expires_at = match (req.expires_at, req.ttl) {
+        (Some(_), Some(_)) => {
+            return Err(TradeRequestError::InvalidParams(ERRL!(
+                "Only one of 'expires_at' and 'ttl' can be set"
+            )))
+        },
+        (Some(expires_at), None) => Some(expires_at),
+        (None, Some(ttl)) => match (now_ms() / 1000).checked_add(ttl) {
+            Some(expires_at) => Some(expires_at),
+            None => return Err(TradeRequestError::InvalidParams(ERRL!("'ttl' {} is too large", ttl))),
+        },
+        (None, None) => None,
+    };
+
+    let ordermatch_ctx = try_map!(OrdermatchContext::from_ctx(&ctx), TradeRequestError::Other);
     let mut my_orders = ordermatch_ctx.my_maker_orders.lock().await;
     if req.cancel_previous {
         let mut cancelled = vec![];
@@ -3011,32 +3579,34 @@ pub async fn set_price(ctx: MmArc, req: Json) -> Result<Response<Vec<u8>>, Strin
         }
     }
 
-    let my_balance = try_s!(base_coin.my_balance().compat().await);
+    let my_balance = try_map!(base_coin.my_balance().compat().await, TradeRequestError::Other);
     let volume = if req.max {
         // first check if `rel_coin` balance is sufficient
-        let rel_coin_trade_fee = try_s!(
-            rel_coin
-                .get_receiver_trade_fee(FeeApproxStage::OrderIssue)
-                .compat()
-                .await
-        );
-        try_s!(check_other_coin_balance_for_swap(&ctx, &rel_coin, None, rel_coin_trade_fee).await);
+        let rel_coin_trade_fee = rel_coin
+            .get_receiver_trade_fee(FeeApproxStage::OrderIssue)
+            .compat()
+            .await
+            .trace(source!())?;
+        check_other_coin_balance_for_swap(&ctx, &rel_coin, None, rel_coin_trade_fee)
+            .await
+            .trace(source!())?;
         // calculate max maker volume
         // note the `calc_max_maker_vol` returns [`CheckBalanceError::NotSufficientBalance`] error if the balance of `base_coin` is not sufficient
-        try_s!(calc_max_maker_vol(&ctx, &base_coin, &my_balance, FeeApproxStage::OrderIssue).await)
-    } else {
-        try_s!(
-            check_balance_for_maker_swap(
-                &ctx,
-                &base_coin,
-                &rel_coin,
-                req.volume.clone(),
-                None,
-                None,
-                FeeApproxStage::OrderIssue
-            )
+        calc_max_maker_vol(&ctx, &base_coin, &my_balance, FeeApproxStage::OrderIssue)
             .await
-        );
+            .trace(source!())?
+    } else {
+        check_balance_for_maker_swap(
+            &ctx,
+            &base_coin,
+            &rel_coin,
+            req.volume.clone(),
+            None,
+            None,
+            FeeApproxStage::OrderIssue,
+        )
+        .await
+        .trace(source!())?;
         req.volume
     };
 
@@ -3052,19 +3622,127 @@ pub async fn set_price(ctx: MmArc, req: Json) -> Result<Response<Vec<u8>>, Strin
         .with_max_base_vol(volume)
         .with_min_base_vol(req.min_volume)
         .with_price(req.price)
-        .with_conf_settings(conf_settings);
+        .with_conf_settings(conf_settings)
+        .with_expires_at(expires_at);
 
-    let new_order = try_s!(builder.build());
+    let new_order = try_map!(builder.build(), TradeRequestError::Other);
     let request_orderbook = false;
-    try_s!(subscribe_to_orderbook_topic(&ctx, &new_order.base, &new_order.rel, request_orderbook).await);
+    try_map!(
+        subscribe_to_orderbook_topic(&ctx, &new_order.base, &new_order.rel, request_orderbook).await,
+        TradeRequestError::Other
+    );
     save_my_maker_order(&ctx, &new_order);
     maker_order_created_p2p_notify(ctx.clone(), &new_order).await;
-    let rpc_result = MakerOrderForRpc::from(&new_order);
-    let res = try_s!(json::to_vec(&json!({ "result": rpc_result })));
+    broadcast_my_order_event(&ctx, new_order.uuid, "Maker", OrderStatusChange::Created);
+    let res = json!({ "result": MakerOrderForRpc::from(&new_order) });
     my_orders.insert(new_order.uuid, new_order);
+    Ok(res)
+}
+
+#[derive(Deserialize)]
+struct UpdateMakerOrderReq {
+    uuid: Uuid,
+    new_price: Option<MmNumber>,
+    /// The volume added to the max volume of the order, negative to decrease it.
+    volume_delta: Option<MmNumber>,
+    /// Whether to set the max volume available in the balance.
+    #[serde(default)]
+    max: bool,
+    min_volume: Option<MmNumber>,
+    base_confs: Option<u64>,
+    base_nota: Option<bool>,
+    rel_confs: Option<u64>,
+    rel_nota: Option<bool>,
+}
+
+/// Changes the price, volumes and confirmation settings of the maker order keeping its uuid.
+/// The matches that are in progress are not affected.
+pub async fn update_maker_order(ctx: MmArc, req: Json) -> Result<Response<Vec<u8>>, String> {
+    let res = try_s!(update_maker_order_rpc(&ctx, req).await);
+    let res = try_s!(json::to_vec(&res));
     Ok(try_s!(Response::builder().body(res)))
 }
 
+/// Returns the `update_maker_order` response.
+pub async fn update_maker_order_rpc(ctx: &MmArc, req: Json) -> Result<Json, String> {
+    let req: UpdateMakerOrderReq = try_s!(json::from_value(req));
+    if req.max && req.volume_delta.is_some() {
+        return ERR!("Only one of 'max' and 'volume_delta' can be set");
+    }
+
+    let ordermatch_ctx = try_s!(OrdermatchContext::from_ctx(&ctx));
+    let mut my_orders = ordermatch_ctx.my_maker_orders.lock().await;
+    let order = match my_orders.get_mut(&req.uuid) {
+        Some(order) => order,
+        None => return ERR!("Maker order {} is not found", req.uuid),
+    };
+
+    let base_coin: MmCoinEnum = match try_s!(lp_coinfind(&ctx, &order.base).await) {
+        Some(coin) => coin,
+        None => return ERR!("Base coin {} is not found", order.base),
+    };
+    let rel_coin: MmCoinEnum = match try_s!(lp_coinfind(&ctx, &order.rel).await) {
+        Some(coin) => coin,
+        None => return ERR!("Rel coin {} is not found", order.rel),
+    };
+
+    // the volume of the started swaps is locked already, so the balance must cover the rest only
+    let started_volume = order.started_swaps_base_amount();
+    let new_max_base_vol = if req.max {
+        let rel_coin_trade_fee = try_s!(
+            rel_coin
+                .get_receiver_trade_fee(FeeApproxStage::OrderIssue)
+                .compat()
+                .await
+        );
+        try_s!(check_other_coin_balance_for_swap(&ctx, &rel_coin, None, rel_coin_trade_fee).await);
+        let my_balance = try_s!(base_coin.my_balance().compat().await);
+        let max_vol = try_s!(calc_max_maker_vol(&ctx, &base_coin, &my_balance, FeeApproxStage::OrderIssue).await);
+        &started_volume + &max_vol
+    } else {
+        let new_max_base_vol = match &req.volume_delta {
+            Some(delta) => &order.max_base_vol + delta,
+            None => order.max_base_vol.clone(),
+        };
+        try_s!(
+            check_balance_for_maker_swap(
+                &ctx,
+                &base_coin,
+                &rel_coin,
+                &new_max_base_vol - &started_volume,
+                None,
+                None,
+                FeeApproxStage::OrderIssue
+            )
+            .await
+        );
+        new_max_base_vol
+    };
+
+    let new_price = req.new_price.unwrap_or_else(|| order.price.clone());
+    let new_min_base_vol = req.min_volume.unwrap_or_else(|| order.min_base_vol.clone());
+    let updated_msg = try_s!(order.apply_update(new_price, new_max_base_vol, new_min_base_vol));
+
+    let prev_conf_settings = order.conf_settings.unwrap_or_else(|| OrderConfirmationsSettings {
+        base_confs: base_coin.required_confirmations(),
+        base_nota: base_coin.requires_notarization(),
+        rel_confs: rel_coin.required_confirmations(),
+        rel_nota: rel_coin.requires_notarization(),
+    });
+    order.conf_settings = Some(OrderConfirmationsSettings {
+        base_confs: req.base_confs.unwrap_or(prev_conf_settings.base_confs),
+        base_nota: req.base_nota.unwrap_or(prev_conf_settings.base_nota),
+        rel_confs: req.rel_confs.unwrap_or(prev_conf_settings.rel_confs),
+        rel_nota: req.rel_nota.unwrap_or(prev_conf_settings.rel_nota),
+    });
+
+    save_my_maker_order(&ctx, order);
+    maker_order_updated_p2p_notify(ctx.clone(), &order.base, &order.rel, updated_msg).await;
+    broadcast_my_order_event(&ctx, order.uuid, "Maker", OrderStatusChange::Updated);
+    let rpc_result = MakerOrderForRpc::from(&*order);
+    Ok(json!({ "result": rpc_result }))
+}
+
 /// Result of match_order_and_request function
 #[derive(Debug, PartialEq)]
 enum OrderMatchResult {
@@ -3151,6 +3829,7 @@ pub async fn cancel_order(ctx: MmArc, req: Json) -> Result<Response<Vec<u8>>, St
             }
             let order = order.remove();
             delete_my_taker_order(&ctx, &order.request.uuid);
+            broadcast_my_order_event(&ctx, order.request.uuid, "Taker", OrderStatusChange::Cancelled);
             let res = json!({
                 "result": "success"
             });
@@ -3215,6 +3894,11 @@ struct TakerOrderForRpc<'a> {
     matches: HashMap<Uuid, TakerMatchForRpc<'a>>,
     order_type: &'a OrderType,
     cancellable: bool,
+    /// The volume of the request base coin that is not reserved by the makers yet.
+    available_amount: BigDecimal,
+    /// The volume of the request base coin the swaps are started for.
+    filled_amount: BigDecimal,
+    started_swaps: Vec<Uuid>,
 }
 
 impl<'a> From<&'a TakerOrder> for TakerOrderForRpc<'a> {
@@ -3229,6 +3913,9 @@ impl<'a> From<&'a TakerOrder> for TakerOrderForRpc<'a> {
                 .collect(),
             cancellable: order.is_cancellable(),
             order_type: &order.order_type,
+            available_amount: order.available_amount().into(),
+            filled_amount: order.filled_amount().into(),
+            started_swaps: order.started_swaps(),
         }
     }
 }
@@ -3368,6 +4055,7 @@ pub async fn cancel_orders_by(ctx: &MmArc, cancel_by: CancelBy) -> Result<(Vec<U
             if $e {
                 if $order.is_cancellable() {
                     delete_my_taker_order(&ctx, &$order.request.uuid);
+                    broadcast_my_order_event(&ctx, $uuid, "Taker", OrderStatusChange::Cancelled);
                     cancelled.push($uuid);
                     None
                 } else {
@@ -3480,7 +4168,7 @@ async fn subscribe_to_orderbook_topic(
                     // We are subscribed to the topic. Also we didn't request the orderbook,
                     // but enough time has passed for the orderbook to fill by OrdermatchRequest::SyncPubkeyOrderbookState.
                     true
-                }
+                },
                 OrderbookRequestingState::NotRequested { .. } => {
                     // We are subscribed to the topic. Also we didn't request the orderbook,
                     // and the orderbook has not filled up yet.
@@ -3497,6 +4185,12 @@ async fn subscribe_to_orderbook_topic(
     Ok(())
 }
 
+/// Subscribes to the orderbook updates of the pair and requests the orderbook if it's not filled yet.
+pub async fn subscribe_to_orderbook(ctx: &MmArc, base: &str, rel: &str) -> Result<(), String> {
+    let request_orderbook = true;
+    subscribe_to_orderbook_topic(ctx, base, rel, request_orderbook).await
+}
+
 #[derive(Debug, Serialize)]
 pub struct OrderbookEntry {
     coin: String,
@@ -3559,7 +4253,7 @@ pub async fn orderbook(ctx: MmArc, req: Json) -> Result<Response<Vec<u8>>, Strin
     try_s!(subscribe_to_orderbook_topic(&ctx, &req.base, &req.rel, request_orderbook).await);
     let ordermatch_ctx: Arc<OrdermatchContext> = try_s!(OrdermatchContext::from_ctx(&ctx));
     let orderbook = ordermatch_ctx.orderbook.lock().await;
-    let my_pubsecp = hex::encode(&**ctx.secp256k1_key_pair().public());
+    let my_pubsecp = hex::encode(&**ctx.secp256k1_public());
 
     let mut asks = match orderbook.unordered.get(&(req.base.clone(), req.rel.clone())) {
         Some(uuids) => {
@@ -3660,6 +4354,163 @@ pub async fn orderbook(ctx: MmArc, req: Json) -> Result<Response<Vec<u8>>, Strin
     Ok(try_s!(Response::builder().body(response)))
 }
 
+#[derive(Deserialize)]
+struct BestOrdersReq {
+    coin: String,
+    action: BestOrdersAction,
+    volume: MmNumber,
+}
+
+#[derive(Debug, Serialize)]
+struct BestOrdersEntry {
+    uuid: Uuid,
+    pubkey: String,
+    base: String,
+    rel: String,
+    /// The price of the coin in the other coin of the pair.
+    price: BigDecimal,
+    price_rat: BigRational,
+    max_volume: BigDecimal,
+    max_volume_rat: BigRational,
+    min_volume: BigDecimal,
+    min_volume_rat: BigRational,
+    /// The amount of the other coin paid for the requested volume on buy or received for it on sell.
+    other_coin_amount: BigDecimal,
+    /// The dex fee paid in the coin sent by the taker.
+    dex_fee: BigDecimal,
+    /// The price of the coin accounting for the dex fee and the trade fees paid in the swapped coins.
+    effective_price: BigDecimal,
+    effective_price_rat: BigRational,
+    is_mine: bool,
+}
+
+/// The trade fee amount if it's paid in the coin itself, zero otherwise.
+async fn trade_fee_in_coin(coin: &MmCoinEnum) -> Result<MmNumber, String> {
+    let fee = try_s!(coin.get_trade_fee().compat().await);
+    if fee.coin == coin.ticker() {
+        Ok(fee.amount)
+    } else {
+        Ok(0.into())
+    }
+}
+
+/// Returns the best orders to buy or sell the `volume` of the `coin` grouped by the other coin of the pair.
+/// The orders are ranked by the price accounting for the dex fee and the trade fees of the coins,
+/// so the coins must be enabled, the pairs with the not enabled coins are skipped.
+/// The orders known by our node are merged with the ones requested from a relay.
+pub async fn best_orders(ctx: MmArc, req: Json) -> Result<Response<Vec<u8>>, String> {
+    let req: BestOrdersReq = try_s!(json::from_value(req));
+    if req.volume <= MmNumber::from(0) {
+        return ERR!("The volume must be positive");
+    }
+    let coin = match try_s!(lp_coinfind(&ctx, &req.coin).await) {
+        Some(coin) => coin,
+        None => return ERR!("Coin {} is not enabled", req.coin),
+    };
+    let volume = req.volume.to_ratio();
+
+    let mut orders_by_coin: HashMap<String, HashMap<Uuid, OrderbookItem>> = HashMap::new();
+    let mut add_orders = |orders: HashMap<String, Vec<OrderbookItem>>| {
+        for (other_coin, orders) in orders {
+            let coin_orders = orders_by_coin.entry(other_coin).or_insert_with(HashMap::new);
+            // the orders known by our node are added first and are not replaced by the relay ones
+            for order in orders {
+                coin_orders.entry(order.uuid).or_insert(order);
+            }
+        }
+    };
+
+    {
+        let ordermatch_ctx = try_s!(OrdermatchContext::from_ctx(&ctx));
+        let orderbook = ordermatch_ctx.orderbook.lock().await;
+        add_orders(best_orders_from_orderbook(&orderbook, &req.coin, req.action, &volume));
+    }
+
+    // request the pairs our node is not subscribed to
+    let request = OrdermatchRequest::BestOrders {
+        coin: req.coin.clone(),
+        action: req.action,
+        volume: volume.clone(),
+    };
+    match request_any_relay::<BestOrdersRes>(ctx.clone(), P2PRequest::Ordermatch(request)).await {
+        Ok(Some((BestOrdersRes { orders }, _peer_id))) => {
+            add_orders(filter_relay_best_orders(orders, &req.coin, req.action, &volume))
+        },
+        Ok(None) => (),
+        Err(e) => log!("Error " (e) " requesting the best orders of " (req.coin) " from relays"),
+    }
+
+    let my_pubsecp = hex::encode(&**ctx.secp256k1_public());
+    let coin_fee = try_s!(trade_fee_in_coin(&coin).await);
+    let mut result = HashMap::new();
+    for (other_ticker, orders) in orders_by_coin {
+        let other_coin = match try_s!(lp_coinfind(&ctx, &other_ticker).await) {
+            Some(other_coin) => other_coin,
+            None => continue,
+        };
+        let other_coin_fee = try_s!(trade_fee_in_coin(&other_coin).await);
+
+        let mut entries = Vec::with_capacity(orders.len());
+        for (_uuid, order) in orders {
+            let order_price = MmNumber::from(order.price.clone());
+            let (price, other_coin_amount, dex_fee, effective_price) = match req.action {
+                BestOrdersAction::Buy => {
+                    let other_coin_amount = &req.volume * &order_price;
+                    let dex_fee = dex_fee_amount_from_taker_coin(&other_coin, &req.coin, &other_coin_amount);
+                    let paid = &(&other_coin_amount + &dex_fee) + &other_coin_fee;
+                    let received = &req.volume - &coin_fee;
+                    if received <= MmNumber::from(0) {
+                        continue;
+                    }
+                    (order_price, other_coin_amount, dex_fee, &paid / &received)
+                },
+                BestOrdersAction::Sell => {
+                    let other_coin_amount = &req.volume / &order_price;
+                    let dex_fee = dex_fee_amount_from_taker_coin(&coin, &other_ticker, &req.volume);
+                    let paid = &(&req.volume + &dex_fee) + &coin_fee;
+                    let received = &other_coin_amount - &other_coin_fee;
+                    if received <= MmNumber::from(0) {
+                        continue;
+                    }
+                    let price = &MmNumber::from(1) / &order_price;
+                    (price, other_coin_amount, dex_fee, &received / &paid)
+                },
+            };
+            let max_volume = MmNumber::from(order.max_volume.clone());
+            let min_volume = MmNumber::from(order.min_volume.clone());
+            entries.push(BestOrdersEntry {
+                uuid: order.uuid,
+                is_mine: order.pubkey == my_pubsecp,
+                pubkey: order.pubkey,
+                base: order.base,
+                rel: order.rel,
+                price: price.to_decimal(),
+                price_rat: price.to_ratio(),
+                max_volume: max_volume.to_decimal(),
+                max_volume_rat: max_volume.to_ratio(),
+                min_volume: min_volume.to_decimal(),
+                min_volume_rat: min_volume.to_ratio(),
+                other_coin_amount: other_coin_amount.to_decimal(),
+                dex_fee: dex_fee.to_decimal(),
+                effective_price: effective_price.to_decimal(),
+                effective_price_rat: effective_price.to_ratio(),
+            });
+        }
+
+        match req.action {
+            BestOrdersAction::Buy => entries.sort_by(|e1, e2| e1.effective_price_rat.cmp(&e2.effective_price_rat)),
+            BestOrdersAction::Sell => entries.sort_by(|e1, e2| e2.effective_price_rat.cmp(&e1.effective_price_rat)),
+        }
+        entries.truncate(MAX_BEST_ORDERS_PER_COIN);
+        if !entries.is_empty() {
+            result.insert(other_ticker, entries);
+        }
+    }
+
+    let res = try_s!(json::to_vec(&json!({ "result": result })));
+    Ok(try_s!(Response::builder().body(res)))
+}
+
 fn choose_maker_confs_and_notas(
     maker_confs: Option<OrderConfirmationsSettings>,
     taker_req: &TakerRequest,
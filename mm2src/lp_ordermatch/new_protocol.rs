@@ -159,7 +159,6 @@ impl MakerOrderUpdated {
         }
     }
 
-    #[allow(dead_code)]
     pub fn with_new_price(mut self, new_price: BigRational) -> Self {
         self.new_price = Some(new_price);
         self
@@ -170,7 +169,6 @@ impl MakerOrderUpdated {
         self
     }
 
-    #[allow(dead_code)]
     pub fn with_new_min_volume(mut self, new_min_volume: BigRational) -> Self {
         self.new_min_volume = Some(new_min_volume);
         self
@@ -195,6 +193,10 @@ pub struct TakerRequest {
     pub uuid: CompactUuid,
     pub match_by: MatchBy,
     pub conf_settings: OrderConfirmationsSettings,
+    /// Whether the taker accepts the reservation of a part of the requested volume.
+    /// The field is absent in the requests of the peers that don't support the partial fills.
+    #[serde(default)]
+    pub allow_partial_fill: bool,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -212,6 +214,9 @@ pub struct MakerReserved {
 pub struct TakerConnect {
     pub taker_order_uuid: CompactUuid,
     pub maker_order_uuid: CompactUuid,
+    /// The uuid of the swap if it differs from the taker order uuid, that's the case of the partial fills.
+    #[serde(default)]
+    pub swap_uuid: Option<CompactUuid>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -0,0 +1,289 @@
+//! The `orderbook_depth` RPC aggregating the orderbooks of many pairs into the price levels.
+//!
+//! Unlike the `orderbook` RPC the individual orders are not returned, the orders are grouped by price
+//! rounded to the `tick_size` (if set) and the levels are limited to the top `levels` (if set).
+//! The prices are in the rel coin per the base coin and the volumes are in the base coin for both sides.
+
+use bigdecimal::BigDecimal;
+use coins::coin_conf;
+use common::mm_ctx::MmArc;
+use common::mm_number::MmNumber;
+use common::now_ms;
+use http::Response;
+use serde_json::{self as json, Value as Json};
+use std::collections::BTreeMap;
+
+use super::{subscribe_to_orderbook_topic, Orderbook, OrdermatchContext};
+
+#[derive(Deserialize)]
+struct OrderbookDepthReq {
+    /// The list of the `[base, rel]` pairs.
+    pairs: Vec<(String, String)>,
+    /// The price step the prices are rounded to, asks are rounded up and bids are rounded down.
+    #[serde(default)]
+    tick_size: Option<MmNumber>,
+    /// The max number of the levels of every side.
+    #[serde(default)]
+    levels: Option<usize>,
+    /// Whether to return the summaries only, without the levels.
+    #[serde(default)]
+    summary_only: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum DepthSide {
+    Asks,
+    Bids,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct DepthLevel {
+    price: BigDecimal,
+    volume: BigDecimal,
+    /// The volume of this level and all the better ones.
+    cumulative_volume: BigDecimal,
+    /// The number of the orders aggregated into the level.
+    orders: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct DepthSummary {
+    best_ask: Option<BigDecimal>,
+    best_bid: Option<BigDecimal>,
+    /// The difference between the best ask and the best bid.
+    spread: Option<BigDecimal>,
+    ask_volume: BigDecimal,
+    bid_volume: BigDecimal,
+    num_asks: usize,
+    num_bids: usize,
+}
+
+#[derive(Serialize)]
+struct PairDepth {
+    base: String,
+    rel: String,
+    summary: DepthSummary,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    asks: Option<Vec<DepthLevel>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bids: Option<Vec<DepthLevel>>,
+}
+
+/// Rounds the price to the tick, up for asks and down for bids, so the level price is never better than the orders.
+fn round_to_tick(price: &MmNumber, tick_size: &MmNumber, side: DepthSide) -> MmNumber {
+    let ticks = (price / tick_size).to_ratio();
+    let ticks = match side {
+        DepthSide::Asks => ticks.ceil(),
+        DepthSide::Bids => ticks.floor(),
+    };
+    &MmNumber::from(ticks) * tick_size
+}
+
+/// Aggregates the `(price, volume)` orders into the levels sorted from the best price.
+fn aggregate_levels(
+    orders: impl Iterator<Item = (MmNumber, MmNumber)>,
+    side: DepthSide,
+    tick_size: Option<&MmNumber>,
+    max_levels: Option<usize>,
+) -> Vec<DepthLevel> {
+    let mut levels: BTreeMap<MmNumber, (MmNumber, usize)> = BTreeMap::new();
+    for (price, volume) in orders {
+        let price = match tick_size {
+            Some(tick_size) => round_to_tick(&price, tick_size, side),
+            None => price,
+        };
+        let level = levels.entry(price).or_insert_with(|| (MmNumber::from(0), 0));
+        level.0 = &level.0 + &volume;
+        level.1 += 1;
+    }
+
+    let sorted: Box<dyn Iterator<Item = (MmNumber, (MmNumber, usize))>> = match side {
+        DepthSide::Asks => Box::new(levels.into_iter()),
+        DepthSide::Bids => Box::new(levels.into_iter().rev()),
+    };
+    let mut cumulative_volume = MmNumber::from(0);
+    sorted
+        .take(max_levels.unwrap_or(usize::MAX))
+        .map(|(price, (volume, orders))| {
+            cumulative_volume = &cumulative_volume + &volume;
+            DepthLevel {
+                price: price.to_decimal(),
+                volume: volume.to_decimal(),
+                cumulative_volume: cumulative_volume.to_decimal(),
+                orders,
+            }
+        })
+        .collect()
+}
+
+/// Returns the `(price, volume)` of the asks and the bids of the pair in the rel coin per the base coin
+/// and in the base coin respectively.
+fn pair_orders(orderbook: &Orderbook, base: &str, rel: &str) -> (Vec<(MmNumber, MmNumber)>, Vec<(MmNumber, MmNumber)>) {
+    let orders = |base: &str, rel: &str| {
+        orderbook
+            .unordered
+            .get(&(base.to_owned(), rel.to_owned()))
+            .into_iter()
+            .flatten()
+            .filter_map(|uuid| orderbook.order_set.get(uuid))
+            .map(|order| {
+                (
+                    MmNumber::from(order.price.clone()),
+                    MmNumber::from(order.max_volume.clone()),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    let asks = orders(base, rel);
+    // the bids sell the rel coin, so their price and volume are converted to the base coin
+    let bids = orders(rel, base)
+        .into_iter()
+        .map(|(price, volume)| (&MmNumber::from(1) / &price, &volume * &price))
+        .collect();
+    (asks, bids)
+}
+
+fn depth_summary(asks: &[(MmNumber, MmNumber)], bids: &[(MmNumber, MmNumber)]) -> DepthSummary {
+    let best_ask = asks.iter().map(|(price, _)| price).min();
+    let best_bid = bids.iter().map(|(price, _)| price).max();
+    let spread = match (best_ask, best_bid) {
+        (Some(best_ask), Some(best_bid)) => Some((best_ask - best_bid).to_decimal()),
+        _ => None,
+    };
+    let total_volume = |orders: &[(MmNumber, MmNumber)]| {
+        orders
+            .iter()
+            .fold(MmNumber::from(0), |total, (_, volume)| &total + volume)
+            .to_decimal()
+    };
+    DepthSummary {
+        best_ask: best_ask.map(MmNumber::to_decimal),
+        best_bid: best_bid.map(MmNumber::to_decimal),
+        spread,
+        ask_volume: total_volume(asks),
+        bid_volume: total_volume(bids),
+        num_asks: asks.len(),
+        num_bids: bids.len(),
+    }
+}
+
+pub async fn orderbook_depth(ctx: MmArc, req: Json) -> Result<Response<Vec<u8>>, String> {
+    let req: OrderbookDepthReq = try_s!(json::from_value(req));
+    if req.pairs.is_empty() {
+        return ERR!("At least one pair must be requested");
+    }
+    if let Some(ref tick_size) = req.tick_size {
+        if *tick_size <= MmNumber::from(0) {
+            return ERR!("tick_size must be positive");
+        }
+    }
+    if req.levels == Some(0) {
+        return ERR!("levels must be positive");
+    }
+    for (base, rel) in req.pairs.iter() {
+        if base == rel {
+            return ERR!("Base and rel must be different coins");
+        }
+        for ticker in &[base, rel] {
+            if coin_conf(&ctx, ticker).is_null() {
+                return ERR!("Coin {} is not found in config", ticker);
+            }
+        }
+    }
+
+    let request_orderbook = true;
+    for (base, rel) in req.pairs.iter() {
+        try_s!(subscribe_to_orderbook_topic(&ctx, base, rel, request_orderbook).await);
+    }
+
+    let ordermatch_ctx = try_s!(OrdermatchContext::from_ctx(&ctx));
+    let orderbook = ordermatch_ctx.orderbook.lock().await;
+    let (summary_only, tick_size, levels) = (req.summary_only, req.tick_size.as_ref(), req.levels);
+    let result: Vec<_> = req
+        .pairs
+        .into_iter()
+        .map(|(base, rel)| {
+            let (asks, bids) = pair_orders(&orderbook, &base, &rel);
+            let summary = depth_summary(&asks, &bids);
+            let (asks, bids) = if summary_only {
+                (None, None)
+            } else {
+                (
+                    Some(aggregate_levels(asks.into_iter(), DepthSide::Asks, tick_size, levels)),
+                    Some(aggregate_levels(bids.into_iter(), DepthSide::Bids, tick_size, levels)),
+                )
+            };
+            PairDepth {
+                base,
+                rel,
+                summary,
+                asks,
+                bids,
+            }
+        })
+        .collect();
+
+    let res = try_s!(json::to_vec(&json!({
+        "result": result,
+        "timestamp": now_ms() / 1000,
+    })));
+    Ok(try_s!(Response::builder().body(res)))
+}
+
+#[cfg(test)]
+mod orderbook_depth_tests {
+    use super::*;
+
+    fn orders(orders: &[(&'static str, &'static str)]) -> Vec<(MmNumber, MmNumber)> {
+        orders
+            .iter()
+            .map(|(price, volume)| (MmNumber::from(*price), MmNumber::from(*volume)))
+            .collect()
+    }
+
+    fn level(price: &str, volume: &str, cumulative_volume: &str, orders: usize) -> DepthLevel {
+        DepthLevel {
+            price: price.parse().unwrap(),
+            volume: volume.parse().unwrap(),
+            cumulative_volume: cumulative_volume.parse().unwrap(),
+            orders,
+        }
+    }
+
+    #[test]
+    fn test_aggregate_levels() {
+        let asks = orders(&[("1.05", "1"), ("1.01", "2"), ("1.01", "3"), ("1.12", "1")]);
+        let actual = aggregate_levels(asks.clone().into_iter(), DepthSide::Asks, None, None);
+        let expected = vec![
+            level("1.01", "5", "5", 2),
+            level("1.05", "1", "6", 1),
+            level("1.12", "1", "7", 1),
+        ];
+        assert_eq!(actual, expected);
+
+        let tick_size = MmNumber::from("0.1");
+        let actual = aggregate_levels(asks.into_iter(), DepthSide::Asks, Some(&tick_size), Some(1));
+        assert_eq!(actual, vec![level("1.1", "6", "6", 3)]);
+
+        let bids = orders(&[("0.95", "1"), ("0.99", "2"), ("0.91", "3")]);
+        let actual = aggregate_levels(bids.into_iter(), DepthSide::Bids, Some(&tick_size), None);
+        let expected = vec![level("0.9", "6", "6", 3)];
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_depth_summary() {
+        let asks = orders(&[("1.05", "1"), ("1.01", "2")]);
+        let bids = orders(&[("0.95", "1"), ("0.99", "2")]);
+        let summary = depth_summary(&asks, &bids);
+        assert_eq!(summary.best_ask, Some("1.01".parse().unwrap()));
+        assert_eq!(summary.best_bid, Some("0.99".parse().unwrap()));
+        assert_eq!(summary.spread, Some("0.02".parse().unwrap()));
+        assert_eq!(summary.ask_volume, "3".parse().unwrap());
+        assert_eq!(summary.num_bids, 2);
+
+        let summary = depth_summary(&asks, &[]);
+        assert_eq!(summary.best_bid, None);
+        assert_eq!(summary.spread, None);
+    }
+}
@@ -0,0 +1,586 @@
+//! The simple market maker bot keeping a maker order per configured pair priced by the price feed.
+//!
+//! The bot is started by the `start_simple_market_maker_bot` RPC, e.g.
+//!
+//!     {"price_url": "https://example.com/tickers",
+//!      "pairs": [{"base": "KMD", "rel": "BTC", "spread": "1.02", "max_balance_percentage": "0.5"}]}
+//!
+//! Every `refresh_rate` seconds the bot fetches the USD prices of the coins from the `price_url`, calculates
+//! the pair price multiplied by the `spread` and creates the maker order by `setprice` or updates the existing one
+//! by `update_maker_order` when the price or the available volume changes by the `price_update_threshold` or more.
+//! The order of the pair is cancelled if the price is not available, so the bot never quotes the stale price.
+//! The orders expire in a few refresh rates unless the bot refreshes them, so they don't outlive the bot.
+
+use async_trait::async_trait;
+use common::executor::{spawn, Timer};
+use common::mm_ctx::{from_ctx, MmArc};
+use common::mm_number::MmNumber;
+use common::{new_uuid, now_ms, slurp_url};
+use futures::compat::Future01CompatExt;
+use http::Response;
+use serde_json::{self as json, Value as Json};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+use super::{calc_max_maker_vol, cancel_order, lp_coinfind, save_my_maker_order, set_price_rpc, update_maker_order_rpc,
+            FeeApproxStage, OrdermatchContext};
+
+/// The default interval of the order refreshing, in seconds.
+const DEFAULT_REFRESH_RATE: f64 = 30.;
+const MIN_REFRESH_RATE: f64 = 5.;
+/// The lifetime of the bot orders in the refresh rates.
+const ORDER_TTL_REFRESH_RATES: f64 = 3.;
+
+/// The source of the coin prices used by the bot.
+#[async_trait]
+pub trait PriceSource: Send + Sync {
+    /// Returns the USD prices by the coin tickers.
+    async fn usd_prices(&self) -> Result<HashMap<String, MmNumber>, String>;
+}
+
+/// Fetches the prices from the HTTP endpoint replying with the JSON object by the coin tickers,
+/// either `{"KMD": {"last_price": "0.5"}}` or `{"KMD": "0.5"}`.
+pub struct UrlPriceSource {
+    pub url: String,
+}
+
+#[async_trait]
+impl PriceSource for UrlPriceSource {
+    async fn usd_prices(&self) -> Result<HashMap<String, MmNumber>, String> {
+        let (status, _headers, body) = try_s!(slurp_url(&self.url).await);
+        if !status.is_success() {
+            return ERR!("Price feed {} replied with status {}", self.url, status);
+        }
+        let prices: Json = try_s!(json::from_slice(&body));
+        parse_usd_prices(&prices)
+    }
+}
+
+fn parse_usd_prices(prices: &Json) -> Result<HashMap<String, MmNumber>, String> {
+    let prices = match prices.as_object() {
+        Some(prices) => prices,
+        None => return ERR!("Expected the JSON object of the prices, got {}", prices),
+    };
+    Ok(prices
+        .iter()
+        .filter_map(|(ticker, price)| {
+            let price = if price.is_object() { &price["last_price"] } else { price };
+            let price: MmNumber = json::from_value(price.clone()).ok()?;
+            if price > MmNumber::from(0) {
+                Some((ticker.clone(), price))
+            } else {
+                None
+            }
+        })
+        .collect())
+}
+
+fn default_price_update_threshold() -> MmNumber { MmNumber::from((1, 100)) }
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SimpleMakerPairConf {
+    base: String,
+    rel: String,
+    /// The multiplier of the market price, e.g. `1.02` to sell the base 2% above the market.
+    spread: MmNumber,
+    /// The min volume of the order, the `setprice` default is used if not set.
+    #[serde(default)]
+    min_volume: Option<MmNumber>,
+    /// The max volume of the order, the available balance is used if not set.
+    #[serde(default)]
+    max_volume: Option<MmNumber>,
+    /// The share of the available base balance put into the order, from 0 to 1.
+    #[serde(default)]
+    max_balance_percentage: Option<MmNumber>,
+    /// The relative change of the price or the available volume the order is updated at, 1% by default.
+    #[serde(default = "default_price_update_threshold")]
+    price_update_threshold: MmNumber,
+}
+
+fn default_refresh_rate() -> f64 { DEFAULT_REFRESH_RATE }
+
+#[derive(Deserialize)]
+struct StartSimpleMarketMakerReq {
+    pairs: Vec<SimpleMakerPairConf>,
+    /// The feed of the USD prices in the `UrlPriceSource` format.
+    price_url: String,
+    /// The interval of the order refreshing, in seconds.
+    #[serde(default = "default_refresh_rate")]
+    refresh_rate: f64,
+}
+
+impl StartSimpleMarketMakerReq {
+    fn validate(&self) -> Result<(), String> {
+        if self.pairs.is_empty() {
+            return ERR!("At least one pair must be configured");
+        }
+        if self.price_url.is_empty() {
+            return ERR!("price_url must be set");
+        }
+        if self.refresh_rate < MIN_REFRESH_RATE {
+            return ERR!("refresh_rate must be at least {} seconds", MIN_REFRESH_RATE);
+        }
+        let zero = MmNumber::from(0);
+        for (i, pair) in self.pairs.iter().enumerate() {
+            if pair.base == pair.rel {
+                return ERR!("The base and rel of the pair {} must differ", pair.base);
+            }
+            if self.pairs[..i].iter().any(|p| p.base == pair.base && p.rel == pair.rel) {
+                return ERR!("The pair {}/{} is configured twice", pair.base, pair.rel);
+            }
+            if pair.spread <= zero {
+                return ERR!("The spread of the pair {}/{} must be positive", pair.base, pair.rel);
+            }
+            if pair.price_update_threshold <= zero {
+                return ERR!(
+                    "The price_update_threshold of the pair {}/{} must be positive",
+                    pair.base,
+                    pair.rel
+                );
+            }
+            if let Some(ref percentage) = pair.max_balance_percentage {
+                if *percentage <= zero || *percentage > MmNumber::from(1) {
+                    return ERR!(
+                        "The max_balance_percentage of the pair {}/{} must be in (0, 1]",
+                        pair.base,
+                        pair.rel
+                    );
+                }
+            }
+            if let (Some(min_volume), Some(max_volume)) = (&pair.min_volume, &pair.max_volume) {
+                if min_volume > max_volume {
+                    return ERR!(
+                        "The min_volume of the pair {}/{} must not exceed the max_volume",
+                        pair.base,
+                        pair.rel
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+struct PairState {
+    conf: SimpleMakerPairConf,
+    /// The maker order placed by the bot.
+    order_uuid: Option<Uuid>,
+    last_price: Option<MmNumber>,
+    last_error: Option<String>,
+    updated_at: u64,
+}
+
+struct BotState {
+    /// Distinguishes the bot loop of the current start, the previous loop exits once it sees a different one.
+    run_id: Uuid,
+    refresh_rate: f64,
+    price_url: String,
+    started_at: u64,
+    pairs: Vec<PairState>,
+}
+
+#[derive(Default)]
+struct SimpleMarketMakerContext {
+    state: Mutex<Option<BotState>>,
+}
+
+impl SimpleMarketMakerContext {
+    fn from_ctx(ctx: &MmArc) -> Result<Arc<SimpleMarketMakerContext>, String> {
+        Ok(try_s!(from_ctx(&ctx.simple_market_maker_ctx, move || {
+            Ok(SimpleMarketMakerContext::default())
+        })))
+    }
+}
+
+/// The price of the base in the rel multiplied by the spread.
+fn pair_price(usd_prices: &HashMap<String, MmNumber>, conf: &SimpleMakerPairConf) -> Result<MmNumber, String> {
+    let base_price = match usd_prices.get(&conf.base) {
+        Some(price) => price,
+        None => return ERR!("The price of {} is not available", conf.base),
+    };
+    let rel_price = match usd_prices.get(&conf.rel) {
+        Some(price) => price,
+        None => return ERR!("The price of {} is not available", conf.rel),
+    };
+    Ok(&(base_price / rel_price) * &conf.spread)
+}
+
+/// Whether the order value (the price or the volume) differs from the new one by the relative `threshold` or more.
+fn is_update_needed(order_value: &MmNumber, new_value: &MmNumber, threshold: &MmNumber) -> bool {
+    if order_value.is_zero() {
+        return !new_value.is_zero();
+    }
+    let diff = if order_value > new_value {
+        order_value - new_value
+    } else {
+        new_value - order_value
+    };
+    &diff / order_value >= *threshold
+}
+
+/// The lifetime of the bot orders in seconds.
+fn order_ttl(refresh_rate: f64) -> u64 { (refresh_rate * ORDER_TTL_REFRESH_RATES).ceil() as u64 }
+
+/// The volume of the order limited by the `max_balance_percentage` and the `max_volume`.
+async fn pair_volume(ctx: &MmArc, conf: &SimpleMakerPairConf) -> Result<MmNumber, String> {
+    let base_coin = match try_s!(lp_coinfind(ctx, &conf.base).await) {
+        Some(coin) => coin,
+        None => return ERR!("Base coin {} is not enabled", conf.base),
+    };
+    let balance = try_s!(base_coin.my_balance().compat().await);
+    let max_volume = try_s!(calc_max_maker_vol(ctx, &base_coin, &balance, FeeApproxStage::OrderIssue).await);
+    let mut volume = match &conf.max_balance_percentage {
+        Some(percentage) => &max_volume * percentage,
+        None => max_volume,
+    };
+    if let Some(ref max_volume) = conf.max_volume {
+        if volume > *max_volume {
+            volume = max_volume.clone();
+        }
+    }
+    if let Some(ref min_volume) = conf.min_volume {
+        if volume < *min_volume {
+            return ERR!(
+                "The available volume {} is less than the min_volume {}",
+                volume,
+                min_volume
+            );
+        }
+    }
+    Ok(volume)
+}
+
+/// Creates or updates the order of the pair expiring in `ttl` seconds, returns its uuid.
+async fn refresh_pair_order(
+    ctx: &MmArc,
+    conf: &SimpleMakerPairConf,
+    order_uuid: Option<Uuid>,
+    price: &MmNumber,
+    ttl: u64,
+) -> Result<Uuid, String> {
+    let ordermatch_ctx = try_s!(OrdermatchContext::from_ctx(ctx));
+    // the orders lock must be released before `update_maker_order_rpc` takes it again
+    let existing = match order_uuid {
+        Some(uuid) => ordermatch_ctx.my_maker_orders.lock().await.get(&uuid).map(|order| {
+            (
+                order.price.clone(),
+                order.max_base_vol.clone(),
+                order.started_swaps_base_amount(),
+            )
+        }),
+        None => None,
+    };
+
+    let volume = try_s!(pair_volume(ctx, conf).await);
+    match (order_uuid, existing) {
+        (Some(uuid), Some((order_price, max_base_vol, started_volume))) => {
+            let order_volume = &max_base_vol - &started_volume;
+            let threshold = &conf.price_update_threshold;
+            if is_update_needed(&order_price, price, threshold) || is_update_needed(&order_volume, &volume, threshold) {
+                let mut req = json!({
+                    "uuid": uuid,
+                    "new_price": price,
+                    "volume_delta": &volume - &order_volume,
+                });
+                if let Some(ref min_volume) = conf.min_volume {
+                    req["min_volume"] = json!(min_volume);
+                }
+                try_s!(update_maker_order_rpc(ctx, req).await);
+            }
+            // the order might be filled or cancelled in the meantime, it's recreated on the next refresh then
+            if let Some(order) = ordermatch_ctx.my_maker_orders.lock().await.get_mut(&uuid) {
+                order.expires_at = Some(now_ms() / 1000 + ttl);
+                save_my_maker_order(ctx, order);
+            }
+            Ok(uuid)
+        },
+        // the order is not placed yet or was filled or cancelled
+        _ => {
+            let mut req = json!({
+                "base": conf.base,
+                "rel": conf.rel,
+                "price": price,
+                "volume": volume,
+                "cancel_previous": false,
+                "ttl": ttl,
+            });
+            if let Some(ref min_volume) = conf.min_volume {
+                req["min_volume"] = json!(min_volume);
+            }
+            let res = try_s!(set_price_rpc(ctx, req).await);
+            Ok(try_s!(json::from_value(res["result"]["uuid"].clone())))
+        },
+    }
+}
+
+async fn cancel_bot_order(ctx: &MmArc, uuid: Uuid) {
+    if let Err(e) = cancel_order(ctx.clone(), json!({ "uuid": uuid })).await {
+        log!("Simple market maker] Error " (e) " cancelling the order " (uuid));
+    }
+}
+
+async fn simple_market_maker_loop(ctx: MmArc, run_id: Uuid, price_source: Arc<dyn PriceSource>) {
+    let bot_ctx = unwrap!(SimpleMarketMakerContext::from_ctx(&ctx));
+    loop {
+        if ctx.is_stopping() {
+            break;
+        }
+
+        let (refresh_rate, pairs) = match &*unwrap!(bot_ctx.state.lock()) {
+            Some(state) if state.run_id == run_id => (
+                state.refresh_rate,
+                state
+                    .pairs
+                    .iter()
+                    .map(|pair| (pair.conf.clone(), pair.order_uuid))
+                    .collect::<Vec<_>>(),
+            ),
+            _ => break,
+        };
+
+        let usd_prices = price_source.usd_prices().await;
+        let mut results = Vec::with_capacity(pairs.len());
+        for (conf, order_uuid) in pairs {
+            let price = match &usd_prices {
+                Ok(usd_prices) => pair_price(usd_prices, &conf),
+                Err(e) => ERR!("Error fetching the prices: {}", e),
+            };
+            let result = match price {
+                Ok(price) => match refresh_pair_order(&ctx, &conf, order_uuid, &price, order_ttl(refresh_rate)).await {
+                    Ok(uuid) => (Some(uuid), Some(price), None),
+                    Err(e) => (order_uuid, Some(price), Some(e)),
+                },
+                Err(e) => {
+                    if let Some(uuid) = order_uuid {
+                        cancel_bot_order(&ctx, uuid).await;
+                    }
+                    (None, None, Some(e))
+                },
+            };
+            if let Some(ref e) = result.2 {
+                log!("Simple market maker] " (conf.base) "/" (conf.rel) " error: " (e));
+            }
+            results.push(result);
+        }
+
+        let orphaned: Vec<Uuid> = {
+            let mut state = unwrap!(bot_ctx.state.lock());
+            match &mut *state {
+                Some(state) if state.run_id == run_id => {
+                    let now = now_ms() / 1000;
+                    for (pair, (order_uuid, price, error)) in state.pairs.iter_mut().zip(results) {
+                        pair.order_uuid = order_uuid;
+                        pair.last_price = price;
+                        pair.last_error = error;
+                        pair.updated_at = now;
+                    }
+                    Vec::new()
+                },
+                // the bot was stopped while refreshing, so the orders placed in the meantime are not cancelled yet
+                _ => results.into_iter().filter_map(|(order_uuid, ..)| order_uuid).collect(),
+            }
+        };
+        if !orphaned.is_empty() {
+            for uuid in orphaned {
+                cancel_bot_order(&ctx, uuid).await;
+            }
+            break;
+        }
+
+        Timer::sleep(refresh_rate).await;
+    }
+}
+
+/// Starts the bot refreshing the orders with the prices of the `price_source`.
+fn start_bot(ctx: &MmArc, req: StartSimpleMarketMakerReq, price_source: Arc<dyn PriceSource>) -> Result<(), String> {
+    try_s!(req.validate());
+    let bot_ctx = try_s!(SimpleMarketMakerContext::from_ctx(ctx));
+    let run_id = new_uuid();
+    {
+        let mut state = try_s!(bot_ctx.state.lock());
+        if state.is_some() {
+            return ERR!("The simple market maker bot is already running");
+        }
+        *state = Some(BotState {
+            run_id,
+            refresh_rate: req.refresh_rate,
+            price_url: req.price_url,
+            started_at: now_ms() / 1000,
+            pairs: req
+                .pairs
+                .into_iter()
+                .map(|conf| PairState {
+                    conf,
+                    order_uuid: None,
+                    last_price: None,
+                    last_error: None,
+                    updated_at: 0,
+                })
+                .collect(),
+        });
+    }
+    spawn(simple_market_maker_loop(ctx.clone(), run_id, price_source));
+    Ok(())
+}
+
+pub async fn start_simple_market_maker_bot(ctx: MmArc, req: Json) -> Result<Response<Vec<u8>>, String> {
+    let req: StartSimpleMarketMakerReq = try_s!(json::from_value(req));
+    let price_source = Arc::new(UrlPriceSource {
+        url: req.price_url.clone(),
+    });
+    try_s!(start_bot(&ctx, req, price_source));
+    let res = try_s!(json::to_vec(&json!({ "result": "success" })));
+    Ok(try_s!(Response::builder().body(res)))
+}
+
+/// Stops the bot and cancels its orders.
+pub async fn stop_simple_market_maker_bot(ctx: MmArc, _req: Json) -> Result<Response<Vec<u8>>, String> {
+    let bot_ctx = try_s!(SimpleMarketMakerContext::from_ctx(&ctx));
+    let state = try_s!(bot_ctx.state.lock()).take();
+    let state = match state {
+        Some(state) => state,
+        None => return ERR!("The simple market maker bot is not running"),
+    };
+    for uuid in state.pairs.iter().filter_map(|pair| pair.order_uuid) {
+        cancel_bot_order(&ctx, uuid).await;
+    }
+    let res = try_s!(json::to_vec(&json!({ "result": "success" })));
+    Ok(try_s!(Response::builder().body(res)))
+}
+
+pub async fn simple_market_maker_bot_status(ctx: MmArc, _req: Json) -> Result<Response<Vec<u8>>, String> {
+    let bot_ctx = try_s!(SimpleMarketMakerContext::from_ctx(&ctx));
+    let result = match &*try_s!(bot_ctx.state.lock()) {
+        Some(state) => json!({
+            "running": true,
+            "price_url": state.price_url,
+            "refresh_rate": state.refresh_rate,
+            "started_at": state.started_at,
+            "pairs": state.pairs.iter().map(|pair| json!({
+                "base": pair.conf.base,
+                "rel": pair.conf.rel,
+                "order_uuid": pair.order_uuid,
+                "last_price": pair.last_price.as_ref().map(MmNumber::to_decimal),
+                "last_error": pair.last_error,
+                "updated_at": pair.updated_at,
+            })).collect::<Vec<_>>(),
+        }),
+        None => json!({ "running": false }),
+    };
+    let res = try_s!(json::to_vec(&json!({ "result": result })));
+    Ok(try_s!(Response::builder().body(res)))
+}
+
+#[cfg(test)]
+mod simple_market_maker_tests {
+    use super::*;
+    use common::block_on;
+
+    fn pair_conf(base: &str, rel: &str) -> SimpleMakerPairConf {
+        unwrap!(json::from_value(json!({"base": base, "rel": rel, "spread": "1.02"})))
+    }
+
+    #[test]
+    fn test_pair_price() {
+        let usd_prices = unwrap!(parse_usd_prices(&json!({
+            "KMD": {"last_price": "2"},
+            "BTC": 50000,
+            "ZERO": "0",
+        })));
+        assert!(!usd_prices.contains_key("ZERO"));
+
+        let price = unwrap!(pair_price(&usd_prices, &pair_conf("KMD", "BTC")));
+        assert_eq!(price, MmNumber::from((102, 2500000)));
+        assert!(pair_price(&usd_prices, &pair_conf("KMD", "ETH")).is_err());
+    }
+
+    #[test]
+    fn test_is_update_needed() {
+        let threshold = default_price_update_threshold();
+        assert!(!is_update_needed(&100.into(), &100.into(), &threshold));
+        assert!(!is_update_needed(&"100".into(), &"100.99".into(), &threshold));
+        assert!(is_update_needed(&"100".into(), &"101".into(), &threshold));
+        assert!(is_update_needed(&"100".into(), &"98".into(), &threshold));
+        assert!(!is_update_needed(&0.into(), &0.into(), &threshold));
+        assert!(is_update_needed(&0.into(), &"0.5".into(), &threshold));
+    }
+
+    #[test]
+    fn test_order_ttl() {
+        assert_eq!(order_ttl(30.), 90);
+        assert_eq!(order_ttl(5.5), 17);
+    }
+
+    #[test]
+    fn test_start_req_validate() {
+        let price_url = "http://127.0.0.1/tickers";
+        let req: StartSimpleMarketMakerReq = unwrap!(json::from_value(json!({
+            "price_url": price_url,
+            "pairs": [{"base": "KMD", "rel": "BTC", "spread": "1.02", "max_balance_percentage": "0.5"}],
+        })));
+        unwrap!(req.validate());
+
+        // the price feed is not defaulted to any third party
+        let no_price_url = json!({"pairs": [{"base": "KMD", "rel": "BTC", "spread": "1.02"}]});
+        assert!(json::from_value::<StartSimpleMarketMakerReq>(no_price_url).is_err());
+
+        let invalid = vec![
+            json!({"pairs": []}),
+            json!({"pairs": [{"base": "KMD", "rel": "BTC", "spread": "1.02"}], "price_url": ""}),
+            json!({"pairs": [{"base": "KMD", "rel": "KMD", "spread": "1.02"}]}),
+            json!({"pairs": [{"base": "KMD", "rel": "BTC", "spread": "0"}]}),
+            json!({"pairs": [{"base": "KMD", "rel": "BTC", "spread": "1", "max_balance_percentage": "1.5"}]}),
+            json!({"pairs": [{"base": "KMD", "rel": "BTC", "spread": "1", "min_volume": "2", "max_volume": "1"}]}),
+            json!({"pairs": [{"base": "KMD", "rel": "BTC", "spread": "1"}], "refresh_rate": 1}),
+            json!({"pairs": [
+                {"base": "KMD", "rel": "BTC", "spread": "1"},
+                {"base": "KMD", "rel": "BTC", "spread": "1.1"},
+            ]}),
+        ];
+        for mut req in invalid {
+            if req.get("price_url").is_none() {
+                req["price_url"] = price_url.into();
+            }
+            let req: StartSimpleMarketMakerReq = unwrap!(json::from_value(req));
+            assert!(req.validate().is_err());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn test_url_price_source_local_server() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpListener;
+        use std::thread;
+
+        let listener = unwrap!(TcpListener::bind("127.0.0.1:0"));
+        let port = unwrap!(listener.local_addr()).port();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = unwrap!(listener.accept());
+            let mut reader = BufReader::new(unwrap!(stream.try_clone()));
+            loop {
+                let mut line = String::new();
+                unwrap!(reader.read_line(&mut line));
+                if line.trim_end().is_empty() {
+                    break;
+                }
+            }
+            let body = r#"{"KMD": {"last_price": "0.5"}, "BTC": {"last_price": "40000"}}"#;
+            unwrap!(write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            ));
+        });
+
+        let source = UrlPriceSource {
+            url: format!("http://127.0.0.1:{}/tickers", port),
+        };
+        let usd_prices = unwrap!(block_on(source.usd_prices()));
+        unwrap!(server.join());
+        assert_eq!(usd_prices.get("KMD"), Some(&MmNumber::from((1, 2))));
+        assert_eq!(usd_prices.get("BTC"), Some(&MmNumber::from(40000)));
+    }
+}
@@ -57,11 +57,12 @@
 #![cfg_attr(not(feature = "native"), allow(dead_code))]
 
 use crate::mm2::{database::{my_swaps::{insert_new_swap, select_uuids_by_my_swaps_filter},
-                            stats_swaps::add_swap_to_index},
-                 lp_network::broadcast_p2p_msg};
+                            stats_swaps::{add_swap_to_index, select_pair_swaps, StatsPairSwap}},
+                 lp_network::{broadcast_p2p_msg, encode_and_sign_by_node_key}};
 use async_std::sync as async_std_sync;
+use atomic::Atomic;
 use bigdecimal::BigDecimal;
-use coins::{lp_coinfind, MmCoinEnum, TradeFee, TradePreimageError, TransactionEnum};
+use coins::{lp_coinfind, HtlcScriptType, MmCoinEnum, TradeFee, TradePreimageError, TransactionEnum, TxFeeDetails};
 use common::{bits256, block_on, calc_total_pages,
              executor::{spawn, Timer},
              log::{error, info},
@@ -71,17 +72,17 @@ use common::{bits256, block_on, calc_total_pages,
 use futures::compat::Future01CompatExt;
 use futures::future::{abortable, AbortHandle, TryFutureExt};
 use http::Response;
-use mm2_libp2p::{decode_signed, encode_and_sign, pub_sub_topic, TopicPrefix};
+use mm2_libp2p::{decode_signed, pub_sub_topic, TopicPrefix};
 use num_rational::BigRational;
 use primitives::hash::{H160, H256, H264};
-use rpc::v1::types::{Bytes as BytesJson, H256 as H256Json};
+use rpc::v1::types::{Bytes as BytesJson, H256 as H256Json, H264 as H264Json};
 use serde_json::{self as json, Value as Json};
 use std::collections::{HashMap, HashSet};
 use std::ffi::OsStr;
 use std::num::NonZeroUsize;
 use std::path::PathBuf;
 use std::str::FromStr;
-use std::sync::{Arc, Mutex, Weak};
+use std::sync::{atomic::Ordering, Arc, Mutex, Weak};
 use std::time::Duration;
 use std::{fmt, thread};
 use uuid::Uuid;
@@ -94,8 +95,8 @@ pub use maker_swap::{calc_max_maker_vol, check_balance_for_maker_swap, maker_swa
                      stats_maker_swap_dir, MakerSavedSwap, MakerSwap, RunMakerSwapInput};
 use maker_swap::{stats_maker_swap_file_path, MakerSwapEvent};
 pub use taker_swap::{calc_max_taker_vol, check_balance_for_taker_swap, max_taker_vol, max_taker_vol_from_available,
-                     run_taker_swap, stats_taker_swap_dir, taker_swap_trade_preimage, RunTakerSwapInput,
-                     TakerSavedSwap, TakerSwap};
+                     max_taker_vol_rpc, run_taker_swap, stats_taker_swap_dir, taker_swap_trade_preimage,
+                     RunTakerSwapInput, TakerSavedSwap, TakerSwap};
 use taker_swap::{stats_taker_swap_file_path, TakerSwapEvent};
 
 pub const SWAP_PREFIX: TopicPrefix = "swap";
@@ -153,8 +154,13 @@ pub fn broadcast_swap_message_every(ctx: MmArc, topic: String, msg: SwapMsg, int
 
 /// Broadcast the swap message once
 pub fn broadcast_swap_message(ctx: &MmArc, topic: String, msg: SwapMsg) {
-    let key_pair = ctx.secp256k1_key_pair.or(&&|| panic!());
-    let encoded_msg = encode_and_sign(&msg, &*key_pair.private().secret).unwrap();
+    let encoded_msg = match encode_and_sign_by_node_key(ctx, &msg) {
+        Ok(msg) => msg,
+        Err(e) => {
+            error!("Error {} while signing the swap message", e);
+            return;
+        },
+    };
     broadcast_p2p_msg(ctx, vec![topic], encoded_msg);
 }
 
@@ -231,6 +237,197 @@ const _SWAP_DEFAULT_NUM_CONFIRMS: u32 = 1;
 const _SWAP_DEFAULT_MAX_CONFIRMS: u32 = 6;
 /// MM2 checks that swap payment is confirmed every WAIT_CONFIRM_INTERVAL seconds
 const WAIT_CONFIRM_INTERVAL: u64 = 15;
+/// The swap payment confirmations are awaited by chunks of PAYMENT_CONFIRM_CHUNK seconds
+/// to check whether the payment should be or has been replaced in between.
+const PAYMENT_CONFIRM_CHUNK: u64 = 60;
+/// The fee of my payment is bumped automatically if it's not confirmed within `lock_duration / PAYMENT_STALL_LOCK_DIVIDER`.
+const PAYMENT_STALL_LOCK_DIVIDER: u64 = 10;
+/// The max number of the automatic fee bumps of my payment.
+const MAX_AUTO_PAYMENT_FEE_BUMPS: u64 = 3;
+
+/// The result of waiting for the swap payment confirmations.
+pub enum PaymentConfirmation {
+    Confirmed,
+    /// The payment has been replaced with the one paying the higher fee.
+    /// The swap must wait for the replacement confirmations then.
+    Replaced(TransactionIdentifier),
+}
+
+/// Waits for the confirmations until `wait_until` or for PAYMENT_CONFIRM_CHUNK seconds at most.
+/// Returns `false` if the chunk has elapsed before the payment is confirmed.
+async fn wait_for_confirmations_chunk(
+    coin: &MmCoinEnum,
+    payment: &TransactionIdentifier,
+    confirmations: u64,
+    requires_nota: bool,
+    wait_until: u64,
+) -> Result<bool, String> {
+    let chunk_until = std::cmp::min(wait_until, now_ms() / 1000 + PAYMENT_CONFIRM_CHUNK);
+    let wait_f = coin.wait_for_confirmations(
+        &payment.tx_hex,
+        confirmations,
+        requires_nota,
+        chunk_until,
+        WAIT_CONFIRM_INTERVAL,
+    );
+    match wait_f.compat().await {
+        Ok(()) => Ok(true),
+        Err(e) if chunk_until >= wait_until => ERR!("{}", e),
+        Err(_) => Ok(false),
+    }
+}
+
+/// Waits for the payment sent by my node to be confirmed.
+/// The payment fee is bumped if the payment is not confirmed until `stalled_at`
+/// or if the bump is requested by `bump_swap_payment_fee` RPC.
+async fn wait_for_my_payment_confirmations(
+    coin: &MmCoinEnum,
+    payment: &TransactionIdentifier,
+    confirmations: u64,
+    requires_nota: bool,
+    wait_until: u64,
+    mut stalled_at: Option<u64>,
+    bump_requested: &Atomic<bool>,
+) -> Result<PaymentConfirmation, String> {
+    loop {
+        if try_s!(wait_for_confirmations_chunk(coin, payment, confirmations, requires_nota, wait_until).await) {
+            return Ok(PaymentConfirmation::Confirmed);
+        }
+
+        let stalled = matches!(stalled_at, Some(stalled_at) if now_ms() / 1000 >= stalled_at);
+        if !stalled && !bump_requested.swap(false, Ordering::Relaxed) {
+            continue;
+        }
+
+        match coin.bump_payment_fee(&payment.tx_hex).compat().await {
+            Ok(replacement) => {
+                let tx_hash = replacement.tx_hash();
+                info!(
+                    "{} payment {:02x} replaced with {:02x}",
+                    coin.ticker(),
+                    payment.tx_hash,
+                    tx_hash
+                );
+                return Ok(PaymentConfirmation::Replaced(TransactionIdentifier {
+                    tx_hex: replacement.tx_hex().into(),
+                    tx_hash,
+                }));
+            },
+            Err(e) => {
+                error!(
+                    "Error {} bumping the {} payment {:02x} fee",
+                    e,
+                    coin.ticker(),
+                    payment.tx_hash
+                );
+                // don't retry the automatic bump, the payment might be already confirmed partially
+                stalled_at = None;
+            },
+        }
+    }
+}
+
+/// The result of waiting for the swap spend or refund confirmations.
+pub enum SpendConfirmation {
+    Confirmed,
+    /// The spend has been accelerated by the child-pays-for-parent transaction.
+    Accelerated(TransactionIdentifier),
+}
+
+/// Waits for the spend or refund sent by my node to be confirmed.
+/// The child-pays-for-parent transaction is sent once if the spend is not confirmed until `stalled_at`.
+async fn wait_for_my_spend_confirmations(
+    coin: &MmCoinEnum,
+    spend: &TransactionIdentifier,
+    wait_until: u64,
+    mut stalled_at: Option<u64>,
+) -> Result<SpendConfirmation, String> {
+    // we should wait for only one confirmation to make sure our spend transaction is not failed
+    let confirmations = 1;
+    let requires_nota = false;
+    loop {
+        if try_s!(wait_for_confirmations_chunk(coin, spend, confirmations, requires_nota, wait_until).await) {
+            return Ok(SpendConfirmation::Confirmed);
+        }
+
+        match stalled_at {
+            Some(at) if now_ms() / 1000 >= at => (),
+            _ => continue,
+        }
+
+        match coin.send_cpfp_child(&spend.tx_hex).compat().await {
+            Ok(child) => {
+                let tx_hash = child.tx_hash();
+                info!(
+                    "{} spend {:02x} accelerated by the child {:02x}",
+                    coin.ticker(),
+                    spend.tx_hash,
+                    tx_hash
+                );
+                return Ok(SpendConfirmation::Accelerated(TransactionIdentifier {
+                    tx_hex: child.tx_hex().into(),
+                    tx_hash,
+                }));
+            },
+            Err(e) => {
+                error!(
+                    "Error {} accelerating the {} spend {:02x}",
+                    e,
+                    coin.ticker(),
+                    spend.tx_hash
+                );
+                stalled_at = None;
+            },
+        }
+    }
+}
+
+/// Waits for the payment sent by the other side to be confirmed.
+/// The other side rebroadcasts its payment periodically, so the replacement is taken from the swap messages store.
+#[allow(clippy::too_many_arguments)]
+async fn wait_for_other_payment_confirmations(
+    ctx: &MmArc,
+    uuid: &Uuid,
+    coin: &MmCoinEnum,
+    payment: &TransactionIdentifier,
+    confirmations: u64,
+    requires_nota: bool,
+    wait_until: u64,
+    getter: impl Fn(&mut SwapMsgStore) -> Option<Vec<u8>>,
+) -> Result<PaymentConfirmation, String> {
+    loop {
+        if try_s!(wait_for_confirmations_chunk(coin, payment, confirmations, requires_nota, wait_until).await) {
+            return Ok(PaymentConfirmation::Confirmed);
+        }
+
+        let received = {
+            let swap_ctx = unwrap!(SwapsContext::from_ctx(ctx));
+            let mut msgs = unwrap!(swap_ctx.swap_msgs.lock());
+            msgs.get_mut(uuid).and_then(|msg_store| getter(msg_store))
+        };
+        let payload = match received {
+            Some(payload) => payload,
+            None => continue,
+        };
+        match coin.tx_enum_from_bytes(&payload) {
+            Ok(tx) if tx.tx_hash() != payment.tx_hash => {
+                let tx_hash = tx.tx_hash();
+                info!(
+                    "{} payment {:02x} replaced with {:02x}",
+                    coin.ticker(),
+                    payment.tx_hash,
+                    tx_hash
+                );
+                return Ok(PaymentConfirmation::Replaced(TransactionIdentifier {
+                    tx_hex: tx.tx_hex().into(),
+                    tx_hash,
+                }));
+            },
+            Ok(_) => (),
+            Err(e) => error!("Error {} parsing the {} payment", e, coin.ticker()),
+        }
+    }
+}
 
 #[derive(Debug, PartialEq, Serialize)]
 pub enum RecoveredSwapAction {
@@ -261,6 +458,9 @@ pub trait AtomicSwap: Send + Sync {
     fn maker_coin(&self) -> &str;
 
     fn taker_coin(&self) -> &str;
+
+    /// Requests the swap to bump the fee of its payment while waiting for the payment confirmations.
+    fn request_payment_fee_bump(&self);
 }
 
 #[derive(Serialize)]
@@ -434,6 +634,48 @@ impl From<TradePreimageError> for CheckBalanceError {
     }
 }
 
+/// The error of the trading RPC methods (`buy`, `sell`, `setprice`, `trade_preimage`, `max_taker_vol`).
+#[derive(Debug)]
+pub enum TradeRequestError {
+    /// The request can't be parsed or its params are not valid.
+    InvalidParams(String),
+    NotSufficientBalance(String),
+    Other(String),
+}
+
+impl fmt::Display for TradeRequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TradeRequestError::InvalidParams(e) => write!(f, "Invalid params: {}", e),
+            TradeRequestError::NotSufficientBalance(e) => write!(f, "Not sufficient balance: {}", e),
+            TradeRequestError::Other(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Traceable for TradeRequestError {
+    fn trace(self, source: TraceSource) -> Self {
+        match self {
+            TradeRequestError::InvalidParams(e) => TradeRequestError::InvalidParams(source.with_msg(&e)),
+            TradeRequestError::NotSufficientBalance(e) => TradeRequestError::NotSufficientBalance(source.with_msg(&e)),
+            TradeRequestError::Other(e) => TradeRequestError::Other(source.with_msg(&e)),
+        }
+    }
+}
+
+impl From<CheckBalanceError> for TradeRequestError {
+    fn from(orig: CheckBalanceError) -> Self {
+        match orig {
+            CheckBalanceError::NotSufficientBalance(e) => TradeRequestError::NotSufficientBalance(e),
+            CheckBalanceError::Other(e) => TradeRequestError::Other(e),
+        }
+    }
+}
+
+impl From<TradePreimageError> for TradeRequestError {
+    fn from(orig: TradePreimageError) -> Self { CheckBalanceError::from(orig).into() }
+}
+
 pub async fn check_other_coin_balance_for_swap(
     ctx: &MmArc,
     coin: &MmCoinEnum,
@@ -748,6 +990,94 @@ pub struct NegotiationDataMsg {
     payment_locktime: u64,
     secret_hash: [u8; 20],
     persistent_pubkey: Vec<u8>,
+    /// The field is absent in the messages of the peers that don't support P2WSH swap payments,
+    /// so the P2SH payments are used with them.
+    /// Note the new fields must be appended to keep the message compatible with such peers.
+    #[serde(default)]
+    p2wsh_htlc_support: Option<P2wshHtlcSupport>,
+    /// The field is absent in the messages of the peers that lock the swap payments with their persistent pubkey,
+    /// so the persistent pubkeys are used for both sides of the swap with them.
+    #[serde(default)]
+    htlc_pubkeys: Option<SwapHtlcPubkeys>,
+}
+
+/// Whether the swap side is able to use the P2WSH payments of the maker and taker coins.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct P2wshHtlcSupport {
+    maker_coin: bool,
+    taker_coin: bool,
+}
+
+impl P2wshHtlcSupport {
+    fn new(maker_coin: &MmCoinEnum, taker_coin: &MmCoinEnum) -> Option<P2wshHtlcSupport> {
+        let maker_coin = maker_coin.is_p2wsh_htlc_supported();
+        let taker_coin = taker_coin.is_p2wsh_htlc_supported();
+        if maker_coin || taker_coin {
+            Some(P2wshHtlcSupport { maker_coin, taker_coin })
+        } else {
+            None
+        }
+    }
+}
+
+/// The pubkeys the swap side locks the maker and taker payments with.
+/// They are derived from the swap uuid so that the payments of different swaps are not linked to each other.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SwapHtlcPubkeys {
+    maker_coin: Vec<u8>,
+    taker_coin: Vec<u8>,
+}
+
+impl SwapHtlcPubkeys {
+    /// Derives the HTLC pubkeys unique for the swap.
+    /// The negotiation must fail on error rather than fall back to the persistent pubkey linking the swaps.
+    fn new(maker_coin: &MmCoinEnum, taker_coin: &MmCoinEnum, uuid: &Uuid) -> Result<SwapHtlcPubkeys, String> {
+        let maker_coin = try_s!(maker_coin.derive_htlc_pubkey(uuid.as_bytes()));
+        let taker_coin = try_s!(taker_coin.derive_htlc_pubkey(uuid.as_bytes()));
+        Ok(SwapHtlcPubkeys { maker_coin, taker_coin })
+    }
+}
+
+/// Returns the other side HTLC pubkeys of the maker and taker coins respectively
+/// if both sides use the per-swap HTLC keys, otherwise the persistent pubkeys are used.
+fn negotiate_htlc_pubkeys(
+    my_pubkeys: &Option<SwapHtlcPubkeys>,
+    other_pubkeys: &Option<SwapHtlcPubkeys>,
+) -> Result<Option<(H264Json, H264Json)>, String> {
+    match (my_pubkeys, other_pubkeys) {
+        (Some(_), Some(other)) => {
+            if other.maker_coin.len() != 33 || other.taker_coin.len() != 33 {
+                return ERR!("Invalid HTLC pubkeys {:?}", other);
+            }
+            Ok(Some((
+                other.maker_coin.as_slice().into(),
+                other.taker_coin.as_slice().into(),
+            )))
+        },
+        _ => Ok(None),
+    }
+}
+
+/// Returns the HTLC script types of the maker and taker payments respectively.
+/// P2WSH is used for a coin only if both sides support it.
+fn negotiate_htlc_script_types(
+    my_support: &Option<P2wshHtlcSupport>,
+    other_support: &Option<P2wshHtlcSupport>,
+) -> (HtlcScriptType, HtlcScriptType) {
+    let script_type = |p2wsh_supported: bool| {
+        if p2wsh_supported {
+            HtlcScriptType::P2WSH
+        } else {
+            HtlcScriptType::P2SH
+        }
+    };
+    match (my_support, other_support) {
+        (Some(mine), Some(other)) => (
+            script_type(mine.maker_coin && other.maker_coin),
+            script_type(mine.taker_coin && other.taker_coin),
+        ),
+        _ => (HtlcScriptType::P2SH, HtlcScriptType::P2SH),
+    }
 }
 
 /// Data to be exchanged and validated on swap start, the replacement of LP_pubkeys_data, LP_choosei_data, etc.
@@ -841,6 +1171,13 @@ impl SavedSwap {
         }
     }
 
+    fn my_spend_tx(&self) -> Result<Option<(String, TransactionIdentifier)>, String> {
+        match self {
+            SavedSwap::Maker(swap) => swap.my_spend_tx(),
+            SavedSwap::Taker(swap) => swap.my_spend_tx(),
+        }
+    }
+
     fn recover_funds(self, ctx: MmArc) -> Result<RecoveredSwap, String> {
         let maker_ticker = try_s!(self.maker_coin_ticker());
         // Should remove `block_on` when recover_funds is async.
@@ -898,6 +1235,7 @@ impl From<SavedTradeFee> for TradeFee {
         TradeFee {
             coin: orig.coin,
             amount: orig.amount.into(),
+            fee_details: None,
         }
     }
 }
@@ -1005,6 +1343,273 @@ pub fn stats_swap_status(ctx: MmArc, req: Json) -> HyRes {
     )
 }
 
+/// The max number of the trades returned by `trade_history`.
+const MAX_TRADE_HISTORY_LIMIT: usize = 1000;
+/// The max number of the candles returned by `ohlcv`.
+const MAX_OHLCV_CANDLES: u64 = 1000;
+/// The max number of the swaps the candles are calculated from.
+const MAX_OHLCV_SWAPS: usize = 100_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum PairTradeType {
+    /// The taker bought the base coin.
+    Buy,
+    /// The taker sold the base coin.
+    Sell,
+}
+
+/// The swap of the `stats_swaps` table in terms of the requested pair.
+#[derive(Debug, PartialEq)]
+struct PairTrade {
+    uuid: Uuid,
+    trade_type: PairTradeType,
+    /// The price in the rel coin per the base coin.
+    price: MmNumber,
+    base_volume: MmNumber,
+    rel_volume: MmNumber,
+    started_at: u64,
+}
+
+impl PairTrade {
+    fn from_stats_swap(swap: StatsPairSwap) -> Option<PairTrade> {
+        let maker_amount = MmNumber::from(swap.maker_amount);
+        let taker_amount = MmNumber::from(swap.taker_amount);
+        let (trade_type, base_volume, rel_volume) = if swap.base_is_maker_coin {
+            (PairTradeType::Buy, maker_amount, taker_amount)
+        } else {
+            (PairTradeType::Sell, taker_amount, maker_amount)
+        };
+        if base_volume.is_zero() {
+            return None;
+        }
+        Some(PairTrade {
+            uuid: swap.uuid,
+            trade_type,
+            price: &rel_volume / &base_volume,
+            base_volume,
+            rel_volume,
+            started_at: swap.started_at,
+        })
+    }
+}
+
+fn select_pair_trades(
+    ctx: &MmArc,
+    base: &str,
+    rel: &str,
+    from_timestamp: u64,
+    to_timestamp: u64,
+    limit: usize,
+) -> Result<Vec<PairTrade>, String> {
+    let swaps = try_s!(select_pair_swaps(
+        &ctx.sqlite_connection(),
+        base,
+        rel,
+        from_timestamp,
+        to_timestamp,
+        limit
+    ));
+    Ok(swaps.into_iter().filter_map(PairTrade::from_stats_swap).collect())
+}
+
+#[derive(Deserialize)]
+struct TradeHistoryReq {
+    base: String,
+    rel: String,
+    #[serde(default = "ten")]
+    limit: usize,
+    from_timestamp: Option<u64>,
+    to_timestamp: Option<u64>,
+}
+
+/// Returns the latest successful swaps of the pair in both directions observed by the node.
+pub async fn trade_history(ctx: MmArc, req: Json) -> Result<Response<Vec<u8>>, String> {
+    let req: TradeHistoryReq = try_s!(json::from_value(req));
+    if req.base == req.rel {
+        return ERR!("Base and rel must be different coins");
+    }
+    if req.limit == 0 || req.limit > MAX_TRADE_HISTORY_LIMIT {
+        return ERR!("limit must be in the range [1, {}]", MAX_TRADE_HISTORY_LIMIT);
+    }
+    let trades = try_s!(select_pair_trades(
+        &ctx,
+        &req.base,
+        &req.rel,
+        req.from_timestamp.unwrap_or(0),
+        req.to_timestamp.unwrap_or(i64::MAX as u64),
+        req.limit
+    ));
+    let trades: Vec<_> = trades
+        .into_iter()
+        .map(|trade| {
+            json!({
+                "uuid": trade.uuid,
+                "type": trade.trade_type,
+                "price": trade.price.to_decimal(),
+                "base_volume": trade.base_volume.to_decimal(),
+                "rel_volume": trade.rel_volume.to_decimal(),
+                "timestamp": trade.started_at,
+            })
+        })
+        .collect();
+    let res = try_s!(json::to_vec(&json!({
+        "result": {
+            "base": req.base,
+            "rel": req.rel,
+            "trades": trades,
+        }
+    })));
+    Ok(try_s!(Response::builder().body(res)))
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+enum CandleInterval {
+    #[serde(rename = "1m")]
+    OneMinute,
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "15m")]
+    FifteenMinutes,
+    #[serde(rename = "30m")]
+    ThirtyMinutes,
+    #[serde(rename = "1h")]
+    OneHour,
+    #[serde(rename = "4h")]
+    FourHours,
+    #[serde(rename = "1d")]
+    OneDay,
+    #[serde(rename = "1w")]
+    OneWeek,
+}
+
+impl CandleInterval {
+    fn seconds(self) -> u64 {
+        match self {
+            CandleInterval::OneMinute => 60,
+            CandleInterval::FiveMinutes => 300,
+            CandleInterval::FifteenMinutes => 900,
+            CandleInterval::ThirtyMinutes => 1800,
+            CandleInterval::OneHour => 3600,
+            CandleInterval::FourHours => 4 * 3600,
+            CandleInterval::OneDay => 24 * 3600,
+            CandleInterval::OneWeek => 7 * 24 * 3600,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct Candle {
+    /// The start of the candle interval.
+    timestamp: u64,
+    open: BigDecimal,
+    high: BigDecimal,
+    low: BigDecimal,
+    close: BigDecimal,
+    base_volume: BigDecimal,
+    rel_volume: BigDecimal,
+    trades: usize,
+}
+
+/// Aggregates the trades sorted by time into the candles, the intervals without trades are skipped.
+fn ohlcv_candles(trades: &[PairTrade], interval: u64) -> Vec<Candle> {
+    let mut candles: Vec<(u64, Vec<&PairTrade>)> = Vec::new();
+    for trade in trades {
+        let timestamp = trade.started_at / interval * interval;
+        match candles.last_mut() {
+            Some((last_timestamp, candle_trades)) if *last_timestamp == timestamp => candle_trades.push(trade),
+            _ => candles.push((timestamp, vec![trade])),
+        }
+    }
+
+    candles
+        .into_iter()
+        .map(|(timestamp, trades)| {
+            let prices = trades.iter().map(|trade| &trade.price);
+            let (base_volume, rel_volume) = trades
+                .iter()
+                .fold((MmNumber::from(0), MmNumber::from(0)), |(base, rel), trade| {
+                    (&base + &trade.base_volume, &rel + &trade.rel_volume)
+                });
+            Candle {
+                timestamp,
+                open: trades[0].price.to_decimal(),
+                high: prices.clone().max().expect("candle has trades").to_decimal(),
+                low: prices.min().expect("candle has trades").to_decimal(),
+                close: trades[trades.len() - 1].price.to_decimal(),
+                base_volume: base_volume.to_decimal(),
+                rel_volume: rel_volume.to_decimal(),
+                trades: trades.len(),
+            }
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct OhlcvReq {
+    base: String,
+    rel: String,
+    interval: CandleInterval,
+    from_timestamp: Option<u64>,
+    to_timestamp: Option<u64>,
+}
+
+/// Returns the OHLCV candles of the pair calculated from the successful swaps observed by the node.
+/// The prices are in the rel coin per the base coin.
+pub async fn ohlcv(ctx: MmArc, req: Json) -> Result<Response<Vec<u8>>, String> {
+    let req: OhlcvReq = try_s!(json::from_value(req));
+    if req.base == req.rel {
+        return ERR!("Base and rel must be different coins");
+    }
+    let interval = req.interval.seconds();
+    let to_timestamp = req.to_timestamp.unwrap_or_else(|| now_ms() / 1000 + 1);
+    if to_timestamp > i64::MAX as u64 {
+        return ERR!("to_timestamp must not exceed {}", i64::MAX);
+    }
+    let from_timestamp = req
+        .from_timestamp
+        .unwrap_or_else(|| to_timestamp.saturating_sub(interval * MAX_OHLCV_CANDLES));
+    // align the range to the candle intervals
+    let from_timestamp = from_timestamp / interval * interval;
+    if from_timestamp >= to_timestamp {
+        return ERR!("from_timestamp must be less than to_timestamp");
+    }
+    if (to_timestamp - from_timestamp) / interval >= MAX_OHLCV_CANDLES {
+        return ERR!(
+            "The range exceeds {} candles of the {} seconds interval",
+            MAX_OHLCV_CANDLES,
+            interval
+        );
+    }
+
+    let mut trades = try_s!(select_pair_trades(
+        &ctx,
+        &req.base,
+        &req.rel,
+        from_timestamp,
+        to_timestamp,
+        MAX_OHLCV_SWAPS + 1
+    ));
+    // the latest swaps go first, so the truncated result would miss the oldest candles
+    if trades.len() > MAX_OHLCV_SWAPS {
+        return ERR!(
+            "The range contains more than {} swaps, please narrow it down",
+            MAX_OHLCV_SWAPS
+        );
+    }
+    trades.reverse();
+    let candles = ohlcv_candles(&trades, interval);
+    let res = try_s!(json::to_vec(&json!({
+        "result": {
+            "base": req.base,
+            "rel": req.rel,
+            "interval": interval,
+            "candles": candles,
+        }
+    })));
+    Ok(try_s!(Response::builder().body(res)))
+}
+
 #[derive(Deserialize)]
 pub struct TradePreimageRequest {
     base: String,
@@ -1036,6 +1641,13 @@ pub struct TradePreimageResponse {
     taker_fee: Option<DetailedTakerFee>,
     #[serde(skip_serializing_if = "Option::is_none")]
     fee_to_send_taker_fee: Option<TradeFeeResponse>,
+    /// The max fee of the child-pays-for-parent transaction accelerating the swap spend or refund in the base coin.
+    /// It's paid only if the spend stalls, so it isn't included in the balance requirements.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    base_coin_cpfp_fee: Option<TradeFeeResponse>,
+    /// The same in the rel coin.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rel_coin_cpfp_fee: Option<TradeFeeResponse>,
 }
 
 #[derive(Serialize)]
@@ -1043,6 +1655,8 @@ pub struct TradeFeeResponse {
     coin: String,
     #[serde(flatten)]
     amount: DetailedAmount,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fee_details: Option<TxFeeDetails>,
 }
 
 impl From<TradeFee> for TradeFeeResponse {
@@ -1050,6 +1664,7 @@ impl From<TradeFee> for TradeFeeResponse {
         TradeFeeResponse {
             coin: orig.coin,
             amount: DetailedAmount::from(orig.amount),
+            fee_details: orig.fee_details,
         }
     }
 }
@@ -1059,16 +1674,23 @@ construct_detailed!(DetailedVolume, volume);
 construct_detailed!(DetailedTakerFee, taker_fee);
 
 pub async fn trade_preimage(ctx: MmArc, req: Json) -> Result<Response<Vec<u8>>, String> {
-    let req: TradePreimageRequest = try_s!(json::from_value(req));
-    let result = match req.swap_method {
-        TradePreimageMethod::SetPrice => try_s!(maker_swap_trade_preimage(&ctx, req).await),
-        TradePreimageMethod::Buy | TradePreimageMethod::Sell => try_s!(taker_swap_trade_preimage(&ctx, req).await),
-    };
-    let res = json!({ "result": result });
+    let res = try_s!(trade_preimage_rpc(&ctx, req).await);
     let res = try_s!(json::to_vec(&res));
     Ok(try_s!(Response::builder().body(res)))
 }
 
+/// Returns the `trade_preimage` response keeping the [`TradeRequestError`] type.
+pub async fn trade_preimage_rpc(ctx: &MmArc, req: Json) -> Result<Json, TradeRequestError> {
+    let req: TradePreimageRequest = try_map!(json::from_value(req), TradeRequestError::InvalidParams);
+    let result = match req.swap_method {
+        TradePreimageMethod::SetPrice => maker_swap_trade_preimage(ctx, req).await.trace(source!())?,
+        TradePreimageMethod::Buy | TradePreimageMethod::Sell => {
+            taker_swap_trade_preimage(ctx, req).await.trace(source!())?
+        },
+    };
+    Ok(json!({ "result": result }))
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 struct SwapStatus {
     method: String,
@@ -1305,6 +1927,56 @@ pub async fn recover_funds_of_swap(ctx: MmArc, req: Json) -> Result<Response<Vec
     Ok(try_s!(Response::builder().body(res)))
 }
 
+/// Requests the running swap to bump the fee of its payment that is not confirmed yet.
+pub async fn bump_swap_payment_fee(ctx: MmArc, req: Json) -> Result<Response<Vec<u8>>, String> {
+    let uuid: Uuid = try_s!(json::from_value(req["uuid"].clone()));
+    let swap_ctx = try_s!(SwapsContext::from_ctx(&ctx));
+    let swap = {
+        let swaps = try_s!(swap_ctx.running_swaps.lock());
+        swaps
+            .iter()
+            .filter_map(|swap| swap.upgrade())
+            .find(|swap| *swap.uuid() == uuid)
+    };
+    match swap {
+        Some(swap) => swap.request_payment_fee_bump(),
+        None => return ERR!("Swap {} is not running", uuid),
+    }
+    let res = try_s!(json::to_vec(&json!({ "result": "success" })));
+    Ok(try_s!(Response::builder().body(res)))
+}
+
+/// Accelerates my spend or refund of the swap payment that is not confirmed yet
+/// by the child-pays-for-parent transaction.
+pub async fn bump_swap_spend_fee(ctx: MmArc, req: Json) -> Result<Response<Vec<u8>>, String> {
+    let uuid: Uuid = try_s!(json::from_value(req["uuid"].clone()));
+    let path = my_swap_file_path(&ctx, &uuid);
+    let content = try_s!(slurp(&path));
+    if content.is_empty() {
+        return ERR!("swap data is not found");
+    }
+
+    let swap: SavedSwap = try_s!(json::from_slice(&content));
+    let (ticker, spend) = match try_s!(swap.my_spend_tx()) {
+        Some(spend) => spend,
+        None => return ERR!("Swap {} has no spend or refund transaction sent by my node", uuid),
+    };
+    let coin = match lp_coinfind(&ctx, &ticker).await {
+        Ok(Some(c)) => c,
+        Ok(None) => return ERR!("Coin {} is not activated", ticker),
+        Err(e) => return ERR!("Error {} on {} coin find attempt", e, ticker),
+    };
+    let child = try_s!(coin.send_cpfp_child(&spend.tx_hex).compat().await);
+    let res = try_s!(json::to_vec(&json!({
+        "result": {
+            "coin": ticker,
+            "tx_hash": child.tx_hash(),
+            "tx_hex": BytesJson::from(child.tx_hex()),
+        }
+    })));
+    Ok(try_s!(Response::builder().body(res)))
+}
+
 pub async fn import_swaps(ctx: MmArc, req: Json) -> Result<Response<Vec<u8>>, String> {
     let swaps: Vec<SavedSwap> = try_s!(json::from_value(req["swaps"].clone()));
     let mut imported = vec![];
@@ -1437,6 +2109,65 @@ mod lp_swap_tests {
     use super::*;
     use serialization::{deserialize, serialize};
 
+    #[test]
+    fn test_ohlcv_candles() {
+        let swap = |base_is_maker_coin: bool, maker_amount: &str, taker_amount: &str, started_at: u64| {
+            unwrap!(PairTrade::from_stats_swap(StatsPairSwap {
+                uuid: Uuid::new_v4(),
+                base_is_maker_coin,
+                maker_amount: unwrap!(maker_amount.parse()),
+                taker_amount: unwrap!(taker_amount.parse()),
+                started_at,
+            }))
+        };
+
+        let trade = swap(false, "3", "2", 100);
+        assert_eq!(trade.trade_type, PairTradeType::Sell);
+        assert_eq!(trade.price, MmNumber::from((3, 2)));
+        assert_eq!(trade.base_volume, MmNumber::from(2));
+
+        let trades = vec![
+            swap(true, "1", "2", 60),
+            swap(true, "2", "6", 70),
+            swap(false, "1", "1", 80),
+            swap(true, "1", "1.5", 119),
+            swap(true, "1", "2.5", 250),
+        ];
+        let candles = ohlcv_candles(&trades, 60);
+        let expected = vec![
+            Candle {
+                timestamp: 60,
+                open: "2".parse().unwrap(),
+                high: "3".parse().unwrap(),
+                low: "1".parse().unwrap(),
+                close: "1.5".parse().unwrap(),
+                base_volume: "5".parse().unwrap(),
+                rel_volume: "10.5".parse().unwrap(),
+                trades: 4,
+            },
+            Candle {
+                timestamp: 240,
+                open: "2.5".parse().unwrap(),
+                high: "2.5".parse().unwrap(),
+                low: "2.5".parse().unwrap(),
+                close: "2.5".parse().unwrap(),
+                base_volume: "1".parse().unwrap(),
+                rel_volume: "2.5".parse().unwrap(),
+                trades: 1,
+            },
+        ];
+        assert_eq!(candles, expected);
+
+        assert!(PairTrade::from_stats_swap(StatsPairSwap {
+            uuid: Uuid::new_v4(),
+            base_is_maker_coin: true,
+            maker_amount: 0.into(),
+            taker_amount: 1.into(),
+            started_at: 0,
+        })
+        .is_none());
+    }
+
     #[test]
     fn test_dex_fee_amount() {
         let dex_fee_threshold = MmNumber::from("0.0001");
@@ -1477,6 +2208,112 @@ mod lp_swap_tests {
         assert_eq!(data, deserialized);
     }
 
+    #[test]
+    fn test_negotiation_data_msg_p2wsh_htlc_support_compatibility() {
+        // the message of the peers that don't support P2WSH swap payments
+        #[derive(Debug, Deserialize, PartialEq, Serialize)]
+        struct LegacyNegotiationDataMsg {
+            started_at: u64,
+            payment_locktime: u64,
+            secret_hash: [u8; 20],
+            persistent_pubkey: Vec<u8>,
+        }
+
+        let legacy = LegacyNegotiationDataMsg {
+            started_at: 1610000000,
+            payment_locktime: 1610003600,
+            secret_hash: [1; 20],
+            persistent_pubkey: vec![2; 33],
+        };
+        let bytes = unwrap!(rmp_serde::to_vec(&legacy));
+        let msg: NegotiationDataMsg = unwrap!(rmp_serde::from_read_ref(&bytes));
+        assert!(msg.p2wsh_htlc_support.is_none());
+
+        let msg = NegotiationDataMsg {
+            started_at: legacy.started_at,
+            payment_locktime: legacy.payment_locktime,
+            secret_hash: legacy.secret_hash,
+            persistent_pubkey: legacy.persistent_pubkey.clone(),
+            p2wsh_htlc_support: Some(P2wshHtlcSupport {
+                maker_coin: true,
+                taker_coin: false,
+            }),
+            htlc_pubkeys: None,
+        };
+        let bytes = unwrap!(rmp_serde::to_vec(&msg));
+        let decoded: LegacyNegotiationDataMsg = unwrap!(rmp_serde::from_read_ref(&bytes));
+        assert_eq!(decoded, legacy);
+
+        let decoded: NegotiationDataMsg = unwrap!(rmp_serde::from_read_ref(&bytes));
+        let support = unwrap!(decoded.p2wsh_htlc_support);
+        assert!(support.maker_coin);
+        assert!(!support.taker_coin);
+        assert!(decoded.htlc_pubkeys.is_none());
+
+        let msg = NegotiationDataMsg {
+            p2wsh_htlc_support: None,
+            htlc_pubkeys: Some(SwapHtlcPubkeys {
+                maker_coin: vec![3; 33],
+                taker_coin: vec![4; 33],
+            }),
+            ..msg
+        };
+        let bytes = unwrap!(rmp_serde::to_vec(&msg));
+        let decoded: LegacyNegotiationDataMsg = unwrap!(rmp_serde::from_read_ref(&bytes));
+        assert_eq!(decoded, legacy);
+
+        let decoded: NegotiationDataMsg = unwrap!(rmp_serde::from_read_ref(&bytes));
+        assert!(decoded.p2wsh_htlc_support.is_none());
+        let htlc_pubkeys = unwrap!(decoded.htlc_pubkeys);
+        assert_eq!(htlc_pubkeys.maker_coin, vec![3; 33]);
+        assert_eq!(htlc_pubkeys.taker_coin, vec![4; 33]);
+    }
+
+    #[test]
+    fn test_negotiate_htlc_pubkeys() {
+        let pubkeys = |maker_coin: u8, taker_coin: u8| {
+            Some(SwapHtlcPubkeys {
+                maker_coin: vec![maker_coin; 33],
+                taker_coin: vec![taker_coin; 33],
+            })
+        };
+
+        let (maker_coin_pub, taker_coin_pub) = unwrap!(unwrap!(negotiate_htlc_pubkeys(&pubkeys(1, 2), &pubkeys(3, 4))));
+        assert_eq!(maker_coin_pub, H264Json::from(&[3; 33][..]));
+        assert_eq!(taker_coin_pub, H264Json::from(&[4; 33][..]));
+
+        // one of the sides uses the persistent pubkey
+        assert!(unwrap!(negotiate_htlc_pubkeys(&pubkeys(1, 2), &None)).is_none());
+        assert!(unwrap!(negotiate_htlc_pubkeys(&None, &pubkeys(3, 4))).is_none());
+
+        let invalid = Some(SwapHtlcPubkeys {
+            maker_coin: vec![3; 20],
+            taker_coin: vec![4; 33],
+        });
+        assert!(negotiate_htlc_pubkeys(&pubkeys(1, 2), &invalid).is_err());
+    }
+
+    #[test]
+    fn test_negotiate_htlc_script_types() {
+        let support = |maker_coin, taker_coin| Some(P2wshHtlcSupport { maker_coin, taker_coin });
+
+        let actual = negotiate_htlc_script_types(&support(true, true), &support(true, true));
+        assert_eq!(actual, (HtlcScriptType::P2WSH, HtlcScriptType::P2WSH));
+
+        let actual = negotiate_htlc_script_types(&support(true, true), &support(false, true));
+        assert_eq!(actual, (HtlcScriptType::P2SH, HtlcScriptType::P2WSH));
+
+        let actual = negotiate_htlc_script_types(&support(true, false), &support(true, true));
+        assert_eq!(actual, (HtlcScriptType::P2WSH, HtlcScriptType::P2SH));
+
+        // the other side is not aware of P2WSH payments
+        let actual = negotiate_htlc_script_types(&support(true, true), &None);
+        assert_eq!(actual, (HtlcScriptType::P2SH, HtlcScriptType::P2SH));
+
+        let actual = negotiate_htlc_script_types(&None, &support(true, true));
+        assert_eq!(actual, (HtlcScriptType::P2SH, HtlcScriptType::P2SH));
+    }
+
     #[test]
     fn test_lp_atomic_locktime() {
         let maker_coin = "KMD";
@@ -3,15 +3,22 @@
 use super::{ban_pubkey, broadcast_my_swap_status, broadcast_swap_message_every, check_base_coin_balance_for_swap,
             check_my_coin_balance_for_swap, check_other_coin_balance_for_swap, dex_fee_amount_from_taker_coin,
             get_locked_amount, my_swap_file_path, my_swaps_dir, recv_swap_msg, swap_topic, AtomicSwap,
-            CheckBalanceError, DetailedVolume, LockedAmount, MySwapInfo, RecoveredSwap, RecoveredSwapAction,
-            SavedSwap, SavedTradeFee, SwapConfirmationsSettings, SwapError, SwapMsg, SwapsContext, TradeFeeResponse,
-            TradePreimageRequest, TradePreimageResponse, TransactionIdentifier, WAIT_CONFIRM_INTERVAL};
-
-use crate::mm2::{lp_network::subscribe_to_topic, lp_swap::NegotiationDataMsg};
+            CheckBalanceError, DetailedVolume, LockedAmount, MySwapInfo, PaymentConfirmation, RecoveredSwap,
+            RecoveredSwapAction, SavedSwap, SavedTradeFee, SpendConfirmation, SwapConfirmationsSettings, SwapError,
+            SwapMsg, SwapsContext, TradeFeeResponse, TradePreimageRequest, TradePreimageResponse,
+            TransactionIdentifier, MAX_AUTO_PAYMENT_FEE_BUMPS, PAYMENT_STALL_LOCK_DIVIDER};
+
+use crate::mm2::{lp_network::subscribe_to_topic,
+                 lp_swap::{negotiate_htlc_pubkeys, negotiate_htlc_script_types, wait_for_my_payment_confirmations,
+                           wait_for_my_spend_confirmations, wait_for_other_payment_confirmations, NegotiationDataMsg,
+                           P2wshHtlcSupport, SwapHtlcPubkeys},
+                 lp_webhooks::notify_swap_webhooks,
+                 rpc::event_stream::{broadcast_swap_event, SwapType}};
 use atomic::Atomic;
 use bigdecimal::BigDecimal;
 use bitcrypto::dhash160;
-use coins::{lp_coinfind, FeeApproxStage, FoundSwapTxSpend, MmCoinEnum, TradeFee, TradePreimageValue, TransactionEnum};
+use coins::{lp_coinfind, FeeApproxStage, FoundSwapTxSpend, HtlcScriptType, MmCoinEnum, TradeFee, TradePreimageValue,
+            TransactionEnum};
 use common::{bits256, executor::Timer, file_lock::FileLock, mm_ctx::MmArc, mm_number::MmNumber, now_ms, slurp, write,
              Traceable, DEX_FEE_ADDR_RAW_PUBKEY, MM_VERSION};
 use futures::{compat::Future01CompatExt, select, FutureExt};
@@ -49,11 +56,13 @@ fn save_my_maker_swap_event(ctx: &MmArc, swap: &MakerSwap, event: MakerSavedEven
                 "Negotiated".into(),
                 "TakerFeeValidated".into(),
                 "MakerPaymentSent".into(),
+                "MakerPaymentReplaced".into(),
                 "TakerPaymentReceived".into(),
                 "TakerPaymentWaitConfirmStarted".into(),
                 "TakerPaymentValidatedAndConfirmed".into(),
                 "TakerPaymentSpent".into(),
                 "TakerPaymentSpendConfirmStarted".into(),
+                "TakerPaymentSpendAccelerated".into(),
                 "TakerPaymentSpendConfirmed".into(),
                 "Finished".into(),
             ],
@@ -78,10 +87,15 @@ fn save_my_maker_swap_event(ctx: &MmArc, swap: &MakerSwap, event: MakerSavedEven
     };
 
     if let SavedSwap::Maker(mut maker_swap) = swap {
+        let uuid = maker_swap.uuid;
+        let event_json = try_s!(json::to_value(&event));
         maker_swap.events.push(event);
+        let is_success = maker_swap.is_success().ok();
         let new_swap = SavedSwap::Maker(maker_swap);
         let new_content = try_s!(json::to_vec(&new_swap));
         try_s!(write(&path, &new_content));
+        notify_swap_webhooks(ctx, uuid, SwapType::Maker, &event_json, is_success);
+        broadcast_swap_event(ctx, uuid, SwapType::Maker, event_json);
         Ok(())
     } else {
         ERR!("Expected SavedSwap::Maker at {}, got {:?}", path.display(), swap)
@@ -92,6 +106,19 @@ fn save_my_maker_swap_event(ctx: &MmArc, swap: &MakerSwap, event: MakerSavedEven
 pub struct TakerNegotiationData {
     pub taker_payment_locktime: u64,
     pub taker_pubkey: H264Json,
+    /// The negotiated HTLC script type of the maker payment.
+    /// Defaults to P2SH for the swaps saved before the negotiation was introduced.
+    #[serde(default)]
+    pub maker_payment_script_type: HtlcScriptType,
+    /// The negotiated HTLC script type of the taker payment.
+    #[serde(default)]
+    pub taker_payment_script_type: HtlcScriptType,
+    /// The taker HTLC pubkey of the maker coin if the per-swap HTLC keys are negotiated, `taker_pubkey` is used otherwise.
+    #[serde(default)]
+    pub maker_coin_htlc_pubkey: Option<H264Json>,
+    /// The taker HTLC pubkey of the taker coin if the per-swap HTLC keys are negotiated, `taker_pubkey` is used otherwise.
+    #[serde(default)]
+    pub taker_coin_htlc_pubkey: Option<H264Json>,
 }
 
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
@@ -129,7 +156,13 @@ pub struct MakerSwapData {
 
 pub struct MakerSwapMut {
     data: MakerSwapData,
-    other_persistent_pub: H264,
+    /// The taker pubkeys the payments are locked with.
+    other_maker_coin_htlc_pub: H264,
+    other_taker_coin_htlc_pub: H264,
+    /// The data the maker derives the per-swap HTLC keys from, empty if the persistent keys are used.
+    swap_unique_data: Vec<u8>,
+    maker_payment_script_type: HtlcScriptType,
+    taker_payment_script_type: HtlcScriptType,
     taker_fee: Option<TransactionIdentifier>,
     maker_payment: Option<TransactionIdentifier>,
     taker_payment: Option<TransactionIdentifier>,
@@ -148,6 +181,11 @@ pub struct MakerSwap {
     uuid: Uuid,
     taker_payment_lock: Atomic<u64>,
     taker_payment_confirmed: Atomic<bool>,
+    /// The number of times the maker payment has been replaced with the one paying the higher fee.
+    maker_payment_fee_bumps: Atomic<u64>,
+    payment_fee_bump_requested: Atomic<bool>,
+    /// Whether the taker payment spend has been accelerated by the child-pays-for-parent transaction already.
+    taker_payment_spend_accelerated: Atomic<bool>,
     errors: PaMutex<Vec<SwapError>>,
     finished_at: Atomic<u64>,
     mutable: RwLock<MakerSwapMut>,
@@ -168,12 +206,31 @@ impl MakerSwap {
             MakerSwapEvent::Negotiated(data) => {
                 self.taker_payment_lock
                     .store(data.taker_payment_locktime, Ordering::Relaxed);
-                self.w().other_persistent_pub = data.taker_pubkey.into();
+                let mut swap_mut = self.w();
+                match (data.maker_coin_htlc_pubkey, data.taker_coin_htlc_pubkey) {
+                    (Some(maker_coin_htlc_pub), Some(taker_coin_htlc_pub)) => {
+                        swap_mut.other_maker_coin_htlc_pub = maker_coin_htlc_pub.into();
+                        swap_mut.other_taker_coin_htlc_pub = taker_coin_htlc_pub.into();
+                        swap_mut.swap_unique_data = self.uuid.as_bytes().to_vec();
+                    },
+                    _ => {
+                        // the persistent pubkey is used for both coins
+                        swap_mut.other_maker_coin_htlc_pub = data.taker_pubkey.clone().into();
+                        swap_mut.other_taker_coin_htlc_pub = data.taker_pubkey.into();
+                        swap_mut.swap_unique_data = Vec::new();
+                    },
+                }
+                swap_mut.maker_payment_script_type = data.maker_payment_script_type;
+                swap_mut.taker_payment_script_type = data.taker_payment_script_type;
             },
             MakerSwapEvent::NegotiateFailed(err) => self.errors.lock().push(err),
             MakerSwapEvent::TakerFeeValidated(tx) => self.w().taker_fee = Some(tx),
             MakerSwapEvent::TakerFeeValidateFailed(err) => self.errors.lock().push(err),
             MakerSwapEvent::MakerPaymentSent(tx) => self.w().maker_payment = Some(tx),
+            MakerSwapEvent::MakerPaymentReplaced(tx) => {
+                self.maker_payment_fee_bumps.fetch_add(1, Ordering::Relaxed);
+                self.w().maker_payment = Some(tx)
+            },
             MakerSwapEvent::MakerPaymentTransactionFailed(err) => self.errors.lock().push(err),
             MakerSwapEvent::MakerPaymentDataSendFailed(err) => self.errors.lock().push(err),
             MakerSwapEvent::MakerPaymentWaitConfirmFailed(err) => self.errors.lock().push(err),
@@ -187,6 +244,9 @@ impl MakerSwap {
             MakerSwapEvent::TakerPaymentSpent(tx) => self.w().taker_payment_spend = Some(tx),
             MakerSwapEvent::TakerPaymentSpendFailed(err) => self.errors.lock().push(err),
             MakerSwapEvent::TakerPaymentSpendConfirmStarted => (),
+            MakerSwapEvent::TakerPaymentSpendAccelerated(_) => {
+                self.taker_payment_spend_accelerated.store(true, Ordering::Relaxed)
+            },
             MakerSwapEvent::TakerPaymentSpendConfirmed => (),
             MakerSwapEvent::TakerPaymentSpendConfirmFailed(err) => self.errors.lock().push(err),
             MakerSwapEvent::MakerPaymentWaitRefundStarted { .. } => (),
@@ -241,11 +301,18 @@ impl MakerSwap {
             errors: PaMutex::new(Vec::new()),
             finished_at: Atomic::new(0),
             taker_payment_confirmed: Atomic::new(false),
+            maker_payment_fee_bumps: Atomic::new(0),
+            payment_fee_bump_requested: Atomic::new(false),
+            taker_payment_spend_accelerated: Atomic::new(false),
             conf_settings,
             payment_locktime,
             mutable: RwLock::new(MakerSwapMut {
                 data: MakerSwapData::default(),
-                other_persistent_pub: H264::default(),
+                other_maker_coin_htlc_pub: H264::default(),
+                other_taker_coin_htlc_pub: H264::default(),
+                swap_unique_data: Vec::new(),
+                maker_payment_script_type: HtlcScriptType::default(),
+                taker_payment_script_type: HtlcScriptType::default(),
                 taker_fee: None,
                 maker_payment: None,
                 taker_payment: None,
@@ -361,11 +428,22 @@ impl MakerSwap {
     }
 
     async fn negotiate(&self) -> Result<(Option<MakerSwapCommand>, Vec<MakerSwapEvent>), String> {
+        let p2wsh_htlc_support = P2wshHtlcSupport::new(&self.maker_coin, &self.taker_coin);
+        let htlc_pubkeys = match SwapHtlcPubkeys::new(&self.maker_coin, &self.taker_coin, &self.uuid) {
+            Ok(pubkeys) => Some(pubkeys),
+            Err(e) => {
+                return Ok((Some(MakerSwapCommand::Finish), vec![MakerSwapEvent::NegotiateFailed(
+                    ERRL!("Error deriving the HTLC pubkeys: {}", e).into(),
+                )]))
+            },
+        };
         let maker_negotiation_data = SwapMsg::Negotiation(NegotiationDataMsg {
             started_at: self.r().data.started_at,
             payment_locktime: self.r().data.maker_payment_lock,
             secret_hash: dhash160(&self.r().data.secret.0).take(),
             persistent_pubkey: self.my_persistent_pub.to_vec(),
+            p2wsh_htlc_support: p2wsh_htlc_support.clone(),
+            htlc_pubkeys: htlc_pubkeys.clone(),
         });
         const NEGOTIATION_TIMEOUT: u64 = 90;
 
@@ -409,10 +487,27 @@ impl MakerSwap {
             )]));
         }
 
+        let (maker_payment_script_type, taker_payment_script_type) =
+            negotiate_htlc_script_types(&p2wsh_htlc_support, &taker_data.p2wsh_htlc_support);
+        let (maker_coin_htlc_pubkey, taker_coin_htlc_pubkey) =
+            match negotiate_htlc_pubkeys(&htlc_pubkeys, &taker_data.htlc_pubkeys) {
+                Ok(Some((maker_coin_pub, taker_coin_pub))) => (Some(maker_coin_pub), Some(taker_coin_pub)),
+                Ok(None) => (None, None),
+                Err(e) => {
+                    return Ok((Some(MakerSwapCommand::Finish), vec![MakerSwapEvent::NegotiateFailed(
+                        ERRL!("{}", e).into(),
+                    )]))
+                },
+            };
+
         Ok((Some(MakerSwapCommand::WaitForTakerFee), vec![
             MakerSwapEvent::Negotiated(TakerNegotiationData {
                 taker_payment_locktime: taker_data.payment_locktime,
                 taker_pubkey: taker_data.persistent_pubkey.as_slice().into(),
+                maker_payment_script_type,
+                taker_payment_script_type,
+                maker_coin_htlc_pubkey,
+                taker_coin_htlc_pubkey,
             }),
         ]))
     }
@@ -503,10 +598,12 @@ impl MakerSwap {
             .maker_coin
             .check_if_my_payment_sent(
                 self.r().data.maker_payment_lock as u32,
-                &*self.r().other_persistent_pub,
+                &*self.r().other_maker_coin_htlc_pub,
                 &*dhash160(&self.r().data.secret.0),
                 self.r().data.maker_coin_start_block,
                 &self.r().data.maker_coin_swap_contract_address,
+                self.r().maker_payment_script_type,
+                &self.r().swap_unique_data,
             )
             .compat();
         let transaction = match transaction_f.await {
@@ -521,10 +618,14 @@ impl MakerSwap {
                     let before_send_maker_payment = now_ms();
                     let payment_fut = self.maker_coin.send_maker_payment(
                         self.r().data.maker_payment_lock as u32,
-                        &*self.r().other_persistent_pub,
+                        &*self.r().other_maker_coin_htlc_pub,
                         &*dhash160(&self.r().data.secret.0),
                         self.maker_amount.clone(),
                         &self.r().data.maker_coin_swap_contract_address,
+                        self.r().maker_payment_script_type,
+                        // the payment accepted by the taker without confirmations must not be replaceable
+                        self.r().data.maker_payment_confirmations > 0,
+                        &self.r().swap_unique_data,
                     );
 
                     match payment_fut.compat().await {
@@ -569,22 +670,39 @@ impl MakerSwap {
         let abort_send_handle = broadcast_swap_message_every(self.ctx.clone(), swap_topic(&self.uuid), msg, 600.);
 
         let maker_payment_wait_confirm = self.r().data.started_at + (self.r().data.lock_duration * 2) / 5;
-        let f = self.maker_coin.wait_for_confirmations(
-            &unwrap!(self.r().maker_payment.clone()).tx_hex,
+        let stalled_at = if self.maker_payment_fee_bumps.load(Ordering::Relaxed) < MAX_AUTO_PAYMENT_FEE_BUMPS {
+            Some(now_ms() / 1000 + self.r().data.lock_duration / PAYMENT_STALL_LOCK_DIVIDER)
+        } else {
+            None
+        };
+        let maker_payment = unwrap!(self.r().maker_payment.clone());
+        let confirmed_f = wait_for_my_payment_confirmations(
+            &self.maker_coin,
+            &maker_payment,
             self.r().data.maker_payment_confirmations,
             self.r().data.maker_payment_requires_nota.unwrap_or(false),
             maker_payment_wait_confirm,
-            WAIT_CONFIRM_INTERVAL,
+            stalled_at,
+            &self.payment_fee_bump_requested,
         );
-        if let Err(err) = f.compat().await {
-            return Ok((Some(MakerSwapCommand::RefundMakerPayment), vec![
-                MakerSwapEvent::MakerPaymentWaitConfirmFailed(
-                    ERRL!("!wait for maker payment confirmations: {}", err).into(),
-                ),
-                MakerSwapEvent::MakerPaymentWaitRefundStarted {
-                    wait_until: self.wait_refund_until(),
-                },
-            ]));
+        match confirmed_f.await {
+            Ok(PaymentConfirmation::Confirmed) => (),
+            // rebroadcast the replacement to the taker and wait for its confirmations
+            Ok(PaymentConfirmation::Replaced(replacement)) => {
+                return Ok((Some(MakerSwapCommand::WaitForTakerPayment), vec![
+                    MakerSwapEvent::MakerPaymentReplaced(replacement),
+                ]))
+            },
+            Err(err) => {
+                return Ok((Some(MakerSwapCommand::RefundMakerPayment), vec![
+                    MakerSwapEvent::MakerPaymentWaitConfirmFailed(
+                        ERRL!("!wait for maker payment confirmations: {}", err).into(),
+                    ),
+                    MakerSwapEvent::MakerPaymentWaitRefundStarted {
+                        wait_until: self.wait_refund_until(),
+                    },
+                ]))
+            },
         }
 
         // wait for 3/5, we need to leave some time space for transaction to be confirmed
@@ -637,25 +755,36 @@ impl MakerSwap {
         let wait_duration = (self.r().data.lock_duration * 4) / 5;
         let wait_taker_payment = self.r().data.started_at + wait_duration;
 
-        let wait_f = self
-            .taker_coin
-            .wait_for_confirmations(
-                &unwrap!(self.r().taker_payment.clone()).tx_hex,
-                self.r().data.taker_payment_confirmations,
-                self.r().data.taker_payment_requires_nota.unwrap_or(false),
-                wait_taker_payment,
-                WAIT_CONFIRM_INTERVAL,
-            )
-            .compat();
-        if let Err(err) = wait_f.await {
-            return Ok((Some(MakerSwapCommand::RefundMakerPayment), vec![
-                MakerSwapEvent::TakerPaymentWaitConfirmFailed(
-                    ERRL!("!taker_coin.wait_for_confirmations: {}", err).into(),
-                ),
-                MakerSwapEvent::MakerPaymentWaitRefundStarted {
-                    wait_until: self.wait_refund_until(),
-                },
-            ]));
+        let taker_payment = unwrap!(self.r().taker_payment.clone());
+        let wait_f = wait_for_other_payment_confirmations(
+            &self.ctx,
+            &self.uuid,
+            &self.taker_coin,
+            &taker_payment,
+            self.r().data.taker_payment_confirmations,
+            self.r().data.taker_payment_requires_nota.unwrap_or(false),
+            wait_taker_payment,
+            |store| store.taker_payment.take(),
+        );
+        match wait_f.await {
+            Ok(PaymentConfirmation::Confirmed) => (),
+            // the replacement is validated after it's confirmed as the replaced payment would be
+            Ok(PaymentConfirmation::Replaced(replacement)) => {
+                return Ok((Some(MakerSwapCommand::ValidateTakerPayment), vec![
+                    MakerSwapEvent::TakerPaymentReceived(replacement),
+                    MakerSwapEvent::TakerPaymentWaitConfirmStarted,
+                ]))
+            },
+            Err(err) => {
+                return Ok((Some(MakerSwapCommand::RefundMakerPayment), vec![
+                    MakerSwapEvent::TakerPaymentWaitConfirmFailed(
+                        ERRL!("!taker_coin.wait_for_confirmations: {}", err).into(),
+                    ),
+                    MakerSwapEvent::MakerPaymentWaitRefundStarted {
+                        wait_until: self.wait_refund_until(),
+                    },
+                ]))
+            },
         }
 
         let validated_f = self
@@ -663,10 +792,12 @@ impl MakerSwap {
             .validate_taker_payment(
                 &unwrap!(self.r().taker_payment.clone()).tx_hex,
                 self.taker_payment_lock.load(Ordering::Relaxed) as u32,
-                &*self.r().other_persistent_pub,
+                &*self.r().other_taker_coin_htlc_pub,
                 &*dhash160(&self.r().data.secret.0),
                 self.taker_amount.clone(),
                 &self.r().data.taker_coin_swap_contract_address,
+                self.r().taker_payment_script_type,
+                &self.r().swap_unique_data,
             )
             .compat();
 
@@ -701,9 +832,10 @@ impl MakerSwap {
         let spend_fut = self.taker_coin.send_maker_spends_taker_payment(
             &unwrap!(self.r().taker_payment.clone()).tx_hex,
             self.taker_payment_lock.load(Ordering::Relaxed) as u32,
-            &*self.r().other_persistent_pub,
+            &*self.r().other_taker_coin_htlc_pub,
             &self.r().data.secret.0,
             &self.r().data.taker_coin_swap_contract_address,
+            &self.r().swap_unique_data,
         );
 
         let transaction = match spend_fut.compat().await {
@@ -734,25 +866,35 @@ impl MakerSwap {
     }
 
     async fn confirm_taker_payment_spend(&self) -> Result<(Option<MakerSwapCommand>, Vec<MakerSwapEvent>), String> {
-        // we should wait for only one confirmation to make sure our spend transaction is not failed
-        let confirmations = 1;
-        let requires_nota = false;
-        let wait_fut = self.taker_coin.wait_for_confirmations(
-            &unwrap!(self.r().taker_payment_spend.clone()).tx_hex,
-            confirmations,
-            requires_nota,
+        let stalled_at = if self.taker_payment_spend_accelerated.load(Ordering::Relaxed) {
+            None
+        } else {
+            Some(now_ms() / 1000 + self.r().data.lock_duration / PAYMENT_STALL_LOCK_DIVIDER)
+        };
+        let taker_payment_spend = unwrap!(self.r().taker_payment_spend.clone());
+        let confirmed_f = wait_for_my_spend_confirmations(
+            &self.taker_coin,
+            &taker_payment_spend,
             self.wait_refund_until(),
-            WAIT_CONFIRM_INTERVAL,
+            stalled_at,
         );
-        if let Err(err) = wait_fut.compat().await {
-            return Ok((Some(MakerSwapCommand::RefundMakerPayment), vec![
-                MakerSwapEvent::TakerPaymentSpendConfirmFailed(
-                    ERRL!("!wait for taker payment spend confirmations: {}", err).into(),
-                ),
-                MakerSwapEvent::MakerPaymentWaitRefundStarted {
-                    wait_until: self.wait_refund_until(),
-                },
-            ]));
+        match confirmed_f.await {
+            Ok(SpendConfirmation::Confirmed) => (),
+            Ok(SpendConfirmation::Accelerated(child)) => {
+                return Ok((Some(MakerSwapCommand::ConfirmTakerPaymentSpend), vec![
+                    MakerSwapEvent::TakerPaymentSpendAccelerated(child),
+                ]))
+            },
+            Err(err) => {
+                return Ok((Some(MakerSwapCommand::RefundMakerPayment), vec![
+                    MakerSwapEvent::TakerPaymentSpendConfirmFailed(
+                        ERRL!("!wait for taker payment spend confirmations: {}", err).into(),
+                    ),
+                    MakerSwapEvent::MakerPaymentWaitRefundStarted {
+                        wait_until: self.wait_refund_until(),
+                    },
+                ]))
+            },
         }
 
         Ok((Some(MakerSwapCommand::Finish), vec![
@@ -770,9 +912,10 @@ impl MakerSwap {
         let spend_fut = self.maker_coin.send_maker_refunds_payment(
             &unwrap!(self.r().maker_payment.clone()).tx_hex,
             self.r().data.maker_payment_lock as u32,
-            &*self.r().other_persistent_pub,
+            &*self.r().other_maker_coin_htlc_pub,
             &*dhash160(&self.r().data.secret.0),
             &self.r().data.maker_coin_swap_contract_address,
+            &self.r().swap_unique_data,
         );
 
         let transaction = match spend_fut.compat().await {
@@ -837,7 +980,7 @@ impl MakerSwap {
 
         let mut taker = bits256::from([0; 32]);
         taker.bytes = data.taker.0;
-        let my_persistent_pub = H264::from(&**ctx.secp256k1_key_pair().public());
+        let my_persistent_pub = H264::from(&**ctx.secp256k1_public());
         let conf_settings = SwapConfirmationsSettings {
             maker_coin_confs: data.maker_payment_confirmations,
             maker_coin_nota: data
@@ -877,7 +1020,7 @@ impl MakerSwap {
                 .tx_hex;
 
             let timelock = selfi.taker_payment_lock.load(Ordering::Relaxed) as u32;
-            let other_pub = &*selfi.r().other_persistent_pub;
+            let other_pub = &*selfi.r().other_taker_coin_htlc_pub;
             // check if the taker payment is not spent yet
             match selfi.taker_coin.search_for_swap_tx_spend_other(
                 timelock,
@@ -886,6 +1029,7 @@ impl MakerSwap {
                 taker_payment_hex,
                 selfi.r().data.taker_coin_start_block,
                 &selfi.r().data.taker_coin_swap_contract_address,
+                &selfi.r().swap_unique_data,
             ) {
                 Ok(Some(FoundSwapTxSpend::Spent(tx))) => {
                     return ERR!(
@@ -913,6 +1057,7 @@ impl MakerSwap {
                     other_pub,
                     &selfi.r().data.secret.0,
                     &selfi.r().data.taker_coin_swap_contract_address,
+                    &selfi.r().swap_unique_data,
                 )
                 .wait()
                 .map_err(|e| ERRL!("{}", e))
@@ -940,10 +1085,12 @@ impl MakerSwap {
                     .maker_coin
                     .check_if_my_payment_sent(
                         self.r().data.maker_payment_lock as u32,
-                        &*self.r().other_persistent_pub,
+                        &*self.r().other_maker_coin_htlc_pub,
                         &secret_hash.0,
                         self.r().data.maker_coin_start_block,
                         &self.r().data.maker_coin_swap_contract_address,
+                        self.r().maker_payment_script_type,
+                        &self.r().swap_unique_data,
                     )
                     .wait());
                 match maybe_maker_payment {
@@ -955,11 +1102,12 @@ impl MakerSwap {
         // validate that maker payment is not spent
         match self.maker_coin.search_for_swap_tx_spend_my(
             self.r().data.maker_payment_lock as u32,
-            &*self.r().other_persistent_pub,
+            &*self.r().other_maker_coin_htlc_pub,
             &secret_hash.0,
             &maker_payment,
             self.r().data.maker_coin_start_block,
             &self.r().data.maker_coin_swap_contract_address,
+            &self.r().swap_unique_data,
         ) {
             Ok(Some(FoundSwapTxSpend::Spent(_))) => {
                 log!("Warning: MakerPayment spent, but TakerPayment is not yet. Trying to spend TakerPayment");
@@ -990,9 +1138,10 @@ impl MakerSwap {
                     .send_maker_refunds_payment(
                         &maker_payment,
                         self.r().data.maker_payment_lock as u32,
-                        &*self.r().other_persistent_pub,
+                        &*self.r().other_maker_coin_htlc_pub,
                         &secret_hash.0,
                         &self.r().data.maker_coin_swap_contract_address,
+                        &self.r().swap_unique_data,
                     )
                     .wait());
 
@@ -1038,6 +1187,8 @@ impl AtomicSwap for MakerSwap {
     fn maker_coin(&self) -> &str { self.maker_coin.ticker() }
 
     fn taker_coin(&self) -> &str { self.taker_coin.ticker() }
+
+    fn request_payment_fee_bump(&self) { self.payment_fee_bump_requested.store(true, Ordering::Relaxed) }
 }
 
 #[derive(Debug)]
@@ -1065,6 +1216,7 @@ pub enum MakerSwapEvent {
     TakerFeeValidated(TransactionIdentifier),
     TakerFeeValidateFailed(SwapError),
     MakerPaymentSent(TransactionIdentifier),
+    MakerPaymentReplaced(TransactionIdentifier),
     MakerPaymentTransactionFailed(SwapError),
     MakerPaymentDataSendFailed(SwapError),
     MakerPaymentWaitConfirmFailed(SwapError),
@@ -1076,6 +1228,7 @@ pub enum MakerSwapEvent {
     TakerPaymentSpent(TransactionIdentifier),
     TakerPaymentSpendFailed(SwapError),
     TakerPaymentSpendConfirmStarted,
+    TakerPaymentSpendAccelerated(TransactionIdentifier),
     TakerPaymentSpendConfirmed,
     TakerPaymentSpendConfirmFailed(SwapError),
     MakerPaymentWaitRefundStarted { wait_until: u64 },
@@ -1094,6 +1247,7 @@ impl MakerSwapEvent {
             MakerSwapEvent::TakerFeeValidated(_) => "Taker fee validated...".to_owned(),
             MakerSwapEvent::TakerFeeValidateFailed(_) => "Taker fee validate failed...".to_owned(),
             MakerSwapEvent::MakerPaymentSent(_) => "Maker payment sent...".to_owned(),
+            MakerSwapEvent::MakerPaymentReplaced(_) => "Maker payment replaced...".to_owned(),
             MakerSwapEvent::MakerPaymentTransactionFailed(_) => "Maker payment failed...".to_owned(),
             MakerSwapEvent::MakerPaymentDataSendFailed(_) => "Maker payment failed...".to_owned(),
             MakerSwapEvent::MakerPaymentWaitConfirmFailed(_) => {
@@ -1109,6 +1263,7 @@ impl MakerSwapEvent {
             MakerSwapEvent::TakerPaymentSpent(_) => "Taker payment spent...".to_owned(),
             MakerSwapEvent::TakerPaymentSpendFailed(_) => "Taker payment spend failed...".to_owned(),
             MakerSwapEvent::TakerPaymentSpendConfirmStarted => "Taker payment send wait confirm started...".to_owned(),
+            MakerSwapEvent::TakerPaymentSpendAccelerated(_) => "Taker payment spend accelerated...".to_owned(),
             MakerSwapEvent::TakerPaymentSpendConfirmed => "Taker payment spend confirmed...".to_owned(),
             MakerSwapEvent::TakerPaymentSpendConfirmFailed(_) => "Taker payment spend confirm failed...".to_owned(),
             MakerSwapEvent::MakerPaymentWaitRefundStarted { wait_until } => {
@@ -1121,24 +1276,30 @@ impl MakerSwapEvent {
     }
 
     fn should_ban_taker(&self) -> bool {
-        matches!(self,
+        matches!(
+            self,
             MakerSwapEvent::NegotiateFailed(_)
-            | MakerSwapEvent::TakerFeeValidateFailed(_)
-            | MakerSwapEvent::TakerPaymentValidateFailed(_))
+                | MakerSwapEvent::TakerFeeValidateFailed(_)
+                | MakerSwapEvent::TakerPaymentValidateFailed(_)
+        )
     }
 
     fn is_success(&self) -> bool {
-        matches!(self, MakerSwapEvent::Started(_)
-            | MakerSwapEvent::Negotiated(_)
-            | MakerSwapEvent::TakerFeeValidated(_)
-            | MakerSwapEvent::MakerPaymentSent(_)
-            | MakerSwapEvent::TakerPaymentReceived(_)
-            | MakerSwapEvent::TakerPaymentWaitConfirmStarted
-            | MakerSwapEvent::TakerPaymentValidatedAndConfirmed
-            | MakerSwapEvent::TakerPaymentSpent(_)
-            | MakerSwapEvent::TakerPaymentSpendConfirmStarted
-            | MakerSwapEvent::TakerPaymentSpendConfirmed
-            | MakerSwapEvent::Finished
+        matches!(
+            self,
+            MakerSwapEvent::Started(_)
+                | MakerSwapEvent::Negotiated(_)
+                | MakerSwapEvent::TakerFeeValidated(_)
+                | MakerSwapEvent::MakerPaymentSent(_)
+                | MakerSwapEvent::MakerPaymentReplaced(_)
+                | MakerSwapEvent::TakerPaymentReceived(_)
+                | MakerSwapEvent::TakerPaymentWaitConfirmStarted
+                | MakerSwapEvent::TakerPaymentValidatedAndConfirmed
+                | MakerSwapEvent::TakerPaymentSpent(_)
+                | MakerSwapEvent::TakerPaymentSpendConfirmStarted
+                | MakerSwapEvent::TakerPaymentSpendAccelerated(_)
+                | MakerSwapEvent::TakerPaymentSpendConfirmed
+                | MakerSwapEvent::Finished
         )
     }
 
@@ -1162,6 +1323,7 @@ impl MakerSavedEvent {
             MakerSwapEvent::TakerFeeValidated(_) => Some(MakerSwapCommand::SendPayment),
             MakerSwapEvent::TakerFeeValidateFailed(_) => Some(MakerSwapCommand::Finish),
             MakerSwapEvent::MakerPaymentSent(_) => Some(MakerSwapCommand::WaitForTakerPayment),
+            MakerSwapEvent::MakerPaymentReplaced(_) => Some(MakerSwapCommand::WaitForTakerPayment),
             MakerSwapEvent::MakerPaymentTransactionFailed(_) => Some(MakerSwapCommand::Finish),
             MakerSwapEvent::MakerPaymentDataSendFailed(_) => Some(MakerSwapCommand::RefundMakerPayment),
             MakerSwapEvent::MakerPaymentWaitConfirmFailed(_) => Some(MakerSwapCommand::RefundMakerPayment),
@@ -1173,6 +1335,7 @@ impl MakerSavedEvent {
             MakerSwapEvent::TakerPaymentSpent(_) => Some(MakerSwapCommand::ConfirmTakerPaymentSpend),
             MakerSwapEvent::TakerPaymentSpendFailed(_) => Some(MakerSwapCommand::RefundMakerPayment),
             MakerSwapEvent::TakerPaymentSpendConfirmStarted => Some(MakerSwapCommand::ConfirmTakerPaymentSpend),
+            MakerSwapEvent::TakerPaymentSpendAccelerated(_) => Some(MakerSwapCommand::ConfirmTakerPaymentSpend),
             MakerSwapEvent::TakerPaymentSpendConfirmed => Some(MakerSwapCommand::Finish),
             MakerSwapEvent::TakerPaymentSpendConfirmFailed(_) => Some(MakerSwapCommand::RefundMakerPayment),
             MakerSwapEvent::MakerPaymentWaitRefundStarted { .. } => Some(MakerSwapCommand::RefundMakerPayment),
@@ -1225,6 +1388,19 @@ impl MakerSavedSwap {
         }
     }
 
+    /// Returns the coin ticker and my latest transaction spending the swap payment,
+    /// i.e. the taker payment spend or the maker payment refund.
+    pub fn my_spend_tx(&self) -> Result<Option<(String, TransactionIdentifier)>, String> {
+        for event in self.events.iter().rev() {
+            match &event.event {
+                MakerSwapEvent::TakerPaymentSpent(tx) => return Ok(Some((try_s!(self.taker_coin()), tx.clone()))),
+                MakerSwapEvent::MakerPaymentRefunded(tx) => return Ok(Some((try_s!(self.maker_coin()), tx.clone()))),
+                _ => (),
+            }
+        }
+        Ok(None)
+    }
+
     pub fn get_my_info(&self) -> Option<MySwapInfo> {
         match self.events.first() {
             Some(event) => match &event.event {
@@ -1491,38 +1667,48 @@ pub async fn check_balance_for_maker_swap(
 pub async fn maker_swap_trade_preimage(
     ctx: &MmArc,
     req: TradePreimageRequest,
-) -> Result<TradePreimageResponse, String> {
+) -> Result<TradePreimageResponse, CheckBalanceError> {
     let base_coin = match lp_coinfind(&ctx, &req.base).await {
         Ok(Some(t)) => t,
-        Ok(None) => return ERR!("No such coin: {}", req.base),
-        Err(err) => return ERR!("!lp_coinfind({}): {}", req.base, err),
+        Ok(None) => return Err(CheckBalanceError::Other(ERRL!("No such coin: {}", req.base))),
+        Err(err) => return Err(CheckBalanceError::Other(ERRL!("!lp_coinfind({}): {}", req.base, err))),
     };
     let rel_coin = match lp_coinfind(&ctx, &req.rel).await {
         Ok(Some(t)) => t,
-        Ok(None) => return ERR!("No such coin: {}", req.rel),
-        Err(err) => return ERR!("!lp_coinfind({}): {}", req.rel, err),
+        Ok(None) => return Err(CheckBalanceError::Other(ERRL!("No such coin: {}", req.rel))),
+        Err(err) => return Err(CheckBalanceError::Other(ERRL!("!lp_coinfind({}): {}", req.rel, err))),
     };
 
     let volume = if req.max {
-        let balance = try_s!(base_coin.my_balance().compat().await);
-        try_s!(calc_max_maker_vol(&ctx, &base_coin, &balance, FeeApproxStage::TradePreimage).await)
+        let balance = try_map!(base_coin.my_balance().compat().await, CheckBalanceError::Other);
+        calc_max_maker_vol(&ctx, &base_coin, &balance, FeeApproxStage::TradePreimage)
+            .await
+            .trace(source!())?
     } else {
         req.volume
     };
 
     let preimage_value = TradePreimageValue::Exact(volume.to_decimal());
-    let base_coin_fee = try_s!(
-        base_coin
-            .get_sender_trade_fee(preimage_value, FeeApproxStage::TradePreimage)
-            .compat()
-            .await
-    );
-    let rel_coin_fee = try_s!(
-        rel_coin
-            .get_receiver_trade_fee(FeeApproxStage::TradePreimage)
-            .compat()
-            .await
-    );
+    let base_coin_fee = base_coin
+        .get_sender_trade_fee(preimage_value, FeeApproxStage::TradePreimage)
+        .compat()
+        .await
+        .trace(source!())?;
+    let rel_coin_fee = rel_coin
+        .get_receiver_trade_fee(FeeApproxStage::TradePreimage)
+        .compat()
+        .await
+        .trace(source!())?;
+    let base_coin_cpfp_fee = base_coin
+        .get_cpfp_child_fee(FeeApproxStage::TradePreimage)
+        .compat()
+        .await
+        .trace(source!())?;
+    let rel_coin_cpfp_fee = rel_coin
+        .get_cpfp_child_fee(FeeApproxStage::TradePreimage)
+        .compat()
+        .await
+        .trace(source!())?;
 
     let volume = if req.max {
         Some(DetailedVolume::from(volume))
@@ -1535,6 +1721,8 @@ pub async fn maker_swap_trade_preimage(
         volume,
         taker_fee: None,
         fee_to_send_taker_fee: None,
+        base_coin_cpfp_fee: base_coin_cpfp_fee.map(TradeFeeResponse::from),
+        rel_coin_cpfp_fee: rel_coin_cpfp_fee.map(TradeFeeResponse::from),
     })
 }
 
@@ -1620,17 +1808,17 @@ mod maker_swap_tests {
         TestCoin::ticker.mock_safe(|_| MockResult::Return("ticker"));
         TestCoin::swap_contract_address.mock_safe(|_| MockResult::Return(None));
         static mut MY_PAYMENT_SENT_CALLED: bool = false;
-        TestCoin::check_if_my_payment_sent.mock_safe(|_, _, _, _, _, _| {
+        TestCoin::check_if_my_payment_sent.mock_safe(|_, _, _, _, _, _, _, _| {
             unsafe { MY_PAYMENT_SENT_CALLED = true };
             MockResult::Return(Box::new(futures01::future::ok(Some(eth_tx_for_test().into()))))
         });
 
         static mut MAKER_REFUND_CALLED: bool = false;
-        TestCoin::send_maker_refunds_payment.mock_safe(|_, _, _, _, _, _| {
+        TestCoin::send_maker_refunds_payment.mock_safe(|_, _, _, _, _, _, _| {
             unsafe { MAKER_REFUND_CALLED = true };
             MockResult::Return(Box::new(futures01::future::ok(eth_tx_for_test().into())))
         });
-        TestCoin::search_for_swap_tx_spend_my.mock_safe(|_, _, _, _, _, _, _| MockResult::Return(Ok(None)));
+        TestCoin::search_for_swap_tx_spend_my.mock_safe(|_, _, _, _, _, _, _, _| MockResult::Return(Ok(None)));
         let maker_coin = MmCoinEnum::Test(TestCoin {});
         let taker_coin = MmCoinEnum::Test(TestCoin {});
         let (maker_swap, _) = unwrap!(MakerSwap::load_from_saved(
@@ -1664,12 +1852,12 @@ mod maker_swap_tests {
         TestCoin::swap_contract_address.mock_safe(|_| MockResult::Return(None));
         static mut MAKER_REFUND_CALLED: bool = false;
 
-        TestCoin::send_maker_refunds_payment.mock_safe(|_, _, _, _, _, _| {
+        TestCoin::send_maker_refunds_payment.mock_safe(|_, _, _, _, _, _, _| {
             unsafe { MAKER_REFUND_CALLED = true };
             MockResult::Return(Box::new(futures01::future::ok(eth_tx_for_test().into())))
         });
 
-        TestCoin::search_for_swap_tx_spend_my.mock_safe(|_, _, _, _, _, _, _| MockResult::Return(Ok(None)));
+        TestCoin::search_for_swap_tx_spend_my.mock_safe(|_, _, _, _, _, _, _, _| MockResult::Return(Ok(None)));
         let maker_coin = MmCoinEnum::Test(TestCoin {});
         let taker_coin = MmCoinEnum::Test(TestCoin {});
         let (maker_swap, _) = unwrap!(MakerSwap::load_from_saved(
@@ -1701,7 +1889,7 @@ mod maker_swap_tests {
         TestCoin::ticker.mock_safe(|_| MockResult::Return("ticker"));
         TestCoin::swap_contract_address.mock_safe(|_| MockResult::Return(None));
 
-        TestCoin::search_for_swap_tx_spend_my.mock_safe(|_, _, _, _, _, _, _| {
+        TestCoin::search_for_swap_tx_spend_my.mock_safe(|_, _, _, _, _, _, _, _| {
             MockResult::Return(Ok(Some(FoundSwapTxSpend::Refunded(eth_tx_for_test().into()))))
         });
         let maker_coin = MmCoinEnum::Test(TestCoin {});
@@ -1729,13 +1917,13 @@ mod maker_swap_tests {
         TestCoin::swap_contract_address.mock_safe(|_| MockResult::Return(None));
 
         static mut SEARCH_FOR_SWAP_TX_SPEND_MY_CALLED: bool = true;
-        TestCoin::search_for_swap_tx_spend_my.mock_safe(|_, _, _, _, _, _, _| {
+        TestCoin::search_for_swap_tx_spend_my.mock_safe(|_, _, _, _, _, _, _, _| {
             unsafe { SEARCH_FOR_SWAP_TX_SPEND_MY_CALLED = true }
             MockResult::Return(Ok(Some(FoundSwapTxSpend::Spent(eth_tx_for_test().into()))))
         });
 
         static mut SEARCH_FOR_SWAP_TX_SPEND_OTHER_CALLED: bool = true;
-        TestCoin::search_for_swap_tx_spend_other.mock_safe(|_, _, _, _, _, _, _| {
+        TestCoin::search_for_swap_tx_spend_other.mock_safe(|_, _, _, _, _, _, _, _| {
             unsafe { SEARCH_FOR_SWAP_TX_SPEND_OTHER_CALLED = true }
             MockResult::Return(Ok(Some(FoundSwapTxSpend::Refunded(eth_tx_for_test().into()))))
         });
@@ -1769,11 +1957,11 @@ mod maker_swap_tests {
         TestCoin::swap_contract_address.mock_safe(|_| MockResult::Return(None));
 
         static mut MY_PAYMENT_SENT_CALLED: bool = false;
-        TestCoin::check_if_my_payment_sent.mock_safe(|_, _, _, _, _, _| {
+        TestCoin::check_if_my_payment_sent.mock_safe(|_, _, _, _, _, _, _, _| {
             unsafe { MY_PAYMENT_SENT_CALLED = true };
             MockResult::Return(Box::new(futures01::future::ok(Some(eth_tx_for_test().into()))))
         });
-        TestCoin::search_for_swap_tx_spend_my.mock_safe(|_, _, _, _, _, _, _| MockResult::Return(Ok(None)));
+        TestCoin::search_for_swap_tx_spend_my.mock_safe(|_, _, _, _, _, _, _, _| MockResult::Return(Ok(None)));
         let maker_coin = MmCoinEnum::Test(TestCoin {});
         let taker_coin = MmCoinEnum::Test(TestCoin {});
         let (maker_swap, _) = unwrap!(MakerSwap::load_from_saved(
@@ -1802,7 +1990,7 @@ mod maker_swap_tests {
         TestCoin::swap_contract_address.mock_safe(|_| MockResult::Return(None));
 
         static mut MY_PAYMENT_SENT_CALLED: bool = false;
-        TestCoin::check_if_my_payment_sent.mock_safe(|_, _, _, _, _, _| {
+        TestCoin::check_if_my_payment_sent.mock_safe(|_, _, _, _, _, _, _, _| {
             unsafe { MY_PAYMENT_SENT_CALLED = true };
             MockResult::Return(Box::new(futures01::future::ok(None)))
         });
@@ -1855,13 +2043,13 @@ mod maker_swap_tests {
         TestCoin::swap_contract_address.mock_safe(|_| MockResult::Return(None));
 
         static mut SEARCH_FOR_SWAP_TX_SPEND_MY_CALLED: bool = true;
-        TestCoin::search_for_swap_tx_spend_my.mock_safe(|_, _, _, _, _, _, _| {
+        TestCoin::search_for_swap_tx_spend_my.mock_safe(|_, _, _, _, _, _, _, _| {
             unsafe { SEARCH_FOR_SWAP_TX_SPEND_MY_CALLED = true }
             MockResult::Return(Ok(Some(FoundSwapTxSpend::Spent(eth_tx_for_test().into()))))
         });
 
         static mut SEARCH_FOR_SWAP_TX_SPEND_OTHER_CALLED: bool = true;
-        TestCoin::search_for_swap_tx_spend_other.mock_safe(|_, _, _, _, _, _, _| {
+        TestCoin::search_for_swap_tx_spend_other.mock_safe(|_, _, _, _, _, _, _, _| {
             unsafe { SEARCH_FOR_SWAP_TX_SPEND_OTHER_CALLED = true }
             MockResult::Return(Ok(Some(FoundSwapTxSpend::Spent(eth_tx_for_test().into()))))
         });
@@ -1920,19 +2108,19 @@ mod maker_swap_tests {
         TestCoin::swap_contract_address.mock_safe(|_| MockResult::Return(None));
 
         static mut SEARCH_FOR_SWAP_TX_SPEND_MY_CALLED: bool = false;
-        TestCoin::search_for_swap_tx_spend_my.mock_safe(|_, _, _, _, _, _, _| {
+        TestCoin::search_for_swap_tx_spend_my.mock_safe(|_, _, _, _, _, _, _, _| {
             unsafe { SEARCH_FOR_SWAP_TX_SPEND_MY_CALLED = true }
             MockResult::Return(Ok(Some(FoundSwapTxSpend::Spent(eth_tx_for_test().into()))))
         });
 
         static mut SEARCH_FOR_SWAP_TX_SPEND_OTHER_CALLED: bool = false;
-        TestCoin::search_for_swap_tx_spend_other.mock_safe(|_, _, _, _, _, _, _| {
+        TestCoin::search_for_swap_tx_spend_other.mock_safe(|_, _, _, _, _, _, _, _| {
             unsafe { SEARCH_FOR_SWAP_TX_SPEND_OTHER_CALLED = true }
             MockResult::Return(Ok(None))
         });
 
         static mut SEND_MAKER_SPENDS_TAKER_PAYMENT_CALLED: bool = false;
-        TestCoin::send_maker_spends_taker_payment.mock_safe(|_, _, _, _, _, _| {
+        TestCoin::send_maker_spends_taker_payment.mock_safe(|_, _, _, _, _, _, _| {
             unsafe { SEND_MAKER_SPENDS_TAKER_PAYMENT_CALLED = true }
             MockResult::Return(Box::new(futures01::future::ok(eth_tx_for_test().into())))
         });
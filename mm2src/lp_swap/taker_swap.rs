@@ -2,15 +2,20 @@
 
 use super::{ban_pubkey, broadcast_my_swap_status, broadcast_swap_message_every, check_my_coin_balance_for_swap,
             check_other_coin_balance_for_swap, dex_fee_amount_from_taker_coin, dex_fee_rate, dex_fee_threshold,
-            get_locked_amount, my_swap_file_path, my_swaps_dir, recv_swap_msg, swap_topic, AtomicSwap,
-            CheckBalanceError, DetailedTakerFee, DetailedVolume, LockedAmount, MySwapInfo, NegotiationDataMsg,
-            RecoveredSwap, RecoveredSwapAction, SavedSwap, SavedTradeFee, SwapConfirmationsSettings, SwapError,
-            SwapMsg, SwapsContext, TakerFeeAdditionalInfo, TradeFeeResponse, TradePreimageMethod,
-            TradePreimageRequest, TradePreimageResponse, TransactionIdentifier, WAIT_CONFIRM_INTERVAL};
+            get_locked_amount, my_swap_file_path, my_swaps_dir, negotiate_htlc_pubkeys, negotiate_htlc_script_types,
+            recv_swap_msg, swap_topic, wait_for_my_payment_confirmations, wait_for_other_payment_confirmations,
+            AtomicSwap, CheckBalanceError, DetailedTakerFee, DetailedVolume, LockedAmount, MySwapInfo,
+            NegotiationDataMsg, P2wshHtlcSupport, PaymentConfirmation, RecoveredSwap, RecoveredSwapAction, SavedSwap,
+            SavedTradeFee, SwapConfirmationsSettings, SwapError, SwapHtlcPubkeys, SwapMsg, SwapsContext,
+            TakerFeeAdditionalInfo, TradeFeeResponse, TradePreimageMethod, TradePreimageRequest,
+            TradePreimageResponse, TradeRequestError, TransactionIdentifier, MAX_AUTO_PAYMENT_FEE_BUMPS,
+            PAYMENT_STALL_LOCK_DIVIDER};
 use crate::mm2::lp_network::subscribe_to_topic;
+use crate::mm2::lp_webhooks::notify_swap_webhooks;
+use crate::mm2::rpc::event_stream::{broadcast_swap_event, SwapType};
 use atomic::Atomic;
 use bigdecimal::BigDecimal;
-use coins::{lp_coinfind, FeeApproxStage, FoundSwapTxSpend, MmCoinEnum, TradeFee, TradePreimageValue};
+use coins::{lp_coinfind, FeeApproxStage, FoundSwapTxSpend, HtlcScriptType, MmCoinEnum, TradeFee, TradePreimageValue};
 use common::executor::Timer;
 use common::log::{debug, warn};
 use common::mm_ctx::MmArc;
@@ -54,6 +59,7 @@ fn save_my_taker_swap_event(ctx: &MmArc, swap: &TakerSwap, event: TakerSavedEven
                 "MakerPaymentWaitConfirmStarted".into(),
                 "MakerPaymentValidatedAndConfirmed".into(),
                 "TakerPaymentSent".into(),
+                "TakerPaymentReplaced".into(),
                 "TakerPaymentSpent".into(),
                 "MakerPaymentSpent".into(),
                 "Finished".into(),
@@ -79,10 +85,15 @@ fn save_my_taker_swap_event(ctx: &MmArc, swap: &TakerSwap, event: TakerSavedEven
     };
 
     if let SavedSwap::Taker(mut taker_swap) = swap {
+        let uuid = taker_swap.uuid;
+        let event_json = try_s!(json::to_value(&event));
         taker_swap.events.push(event);
+        let is_success = taker_swap.is_success().ok();
         let new_swap = SavedSwap::Taker(taker_swap);
         let new_content = try_s!(json::to_vec(&new_swap));
         try_s!(write(&path, &new_content));
+        notify_swap_webhooks(ctx, uuid, SwapType::Taker, &event_json, is_success);
+        broadcast_swap_event(ctx, uuid, SwapType::Taker, event_json);
         Ok(())
     } else {
         ERR!("Expected SavedSwap::Taker at {}, got {:?}", path.display(), swap)
@@ -111,6 +122,7 @@ impl TakerSavedEvent {
             TakerSwapEvent::MakerPaymentValidateFailed(_) => Some(TakerSwapCommand::Finish),
             TakerSwapEvent::MakerPaymentWaitConfirmFailed(_) => Some(TakerSwapCommand::Finish),
             TakerSwapEvent::TakerPaymentSent(_) => Some(TakerSwapCommand::WaitForTakerPaymentSpend),
+            TakerSwapEvent::TakerPaymentReplaced(_) => Some(TakerSwapCommand::WaitForTakerPaymentSpend),
             TakerSwapEvent::TakerPaymentTransactionFailed(_) => Some(TakerSwapCommand::Finish),
             TakerSwapEvent::TakerPaymentDataSendFailed(_) => Some(TakerSwapCommand::RefundTakerPayment),
             TakerSwapEvent::TakerPaymentSpent(_) => Some(TakerSwapCommand::SpendMakerPayment),
@@ -168,6 +180,19 @@ impl TakerSavedSwap {
         }
     }
 
+    /// Returns the coin ticker and my latest transaction spending the swap payment,
+    /// i.e. the maker payment spend or the taker payment refund.
+    pub fn my_spend_tx(&self) -> Result<Option<(String, TransactionIdentifier)>, String> {
+        for event in self.events.iter().rev() {
+            match &event.event {
+                TakerSwapEvent::MakerPaymentSpent(tx) => return Ok(Some((try_s!(self.maker_coin()), tx.clone()))),
+                TakerSwapEvent::TakerPaymentRefunded(tx) => return Ok(Some((try_s!(self.taker_coin()), tx.clone()))),
+                _ => (),
+            }
+        }
+        Ok(None)
+    }
+
     pub fn get_my_info(&self) -> Option<MySwapInfo> {
         match self.events.first() {
             Some(event) => match &event.event {
@@ -421,7 +446,13 @@ pub struct TakerSwapData {
 
 pub struct TakerSwapMut {
     data: TakerSwapData,
-    other_persistent_pub: H264,
+    /// The maker pubkeys the payments are locked with.
+    other_maker_coin_htlc_pub: H264,
+    other_taker_coin_htlc_pub: H264,
+    /// The data the taker derives the per-swap HTLC keys from, empty if the persistent keys are used.
+    swap_unique_data: Vec<u8>,
+    maker_payment_script_type: HtlcScriptType,
+    taker_payment_script_type: HtlcScriptType,
     taker_fee: Option<TransactionIdentifier>,
     maker_payment: Option<TransactionIdentifier>,
     taker_payment: Option<TransactionIdentifier>,
@@ -443,6 +474,9 @@ pub struct TakerSwap {
     uuid: Uuid,
     maker_payment_lock: Atomic<u64>,
     maker_payment_confirmed: Atomic<bool>,
+    /// The number of times the taker payment has been replaced with the one paying the higher fee.
+    taker_payment_fee_bumps: Atomic<u64>,
+    payment_fee_bump_requested: Atomic<bool>,
     errors: PaMutex<Vec<SwapError>>,
     finished_at: Atomic<u64>,
     mutable: RwLock<TakerSwapMut>,
@@ -461,6 +495,19 @@ pub struct MakerNegotiationData {
     maker_payment_locktime: u64,
     maker_pubkey: H264Json,
     secret_hash: H160Json,
+    /// The negotiated HTLC script type of the maker payment.
+    /// Defaults to P2SH for the swaps saved before the negotiation was introduced.
+    #[serde(default)]
+    maker_payment_script_type: HtlcScriptType,
+    /// The negotiated HTLC script type of the taker payment.
+    #[serde(default)]
+    taker_payment_script_type: HtlcScriptType,
+    /// The maker HTLC pubkey of the maker coin if the per-swap HTLC keys are negotiated, `maker_pubkey` is used otherwise.
+    #[serde(default)]
+    maker_coin_htlc_pubkey: Option<H264Json>,
+    /// The maker HTLC pubkey of the taker coin if the per-swap HTLC keys are negotiated, `maker_pubkey` is used otherwise.
+    #[serde(default)]
+    taker_coin_htlc_pubkey: Option<H264Json>,
 }
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
@@ -479,6 +526,7 @@ pub enum TakerSwapEvent {
     MakerPaymentValidateFailed(SwapError),
     MakerPaymentWaitConfirmFailed(SwapError),
     TakerPaymentSent(TransactionIdentifier),
+    TakerPaymentReplaced(TransactionIdentifier),
     TakerPaymentTransactionFailed(SwapError),
     TakerPaymentDataSendFailed(SwapError),
     TakerPaymentWaitConfirmFailed(SwapError),
@@ -509,6 +557,7 @@ impl TakerSwapEvent {
                 "Maker payment wait for confirmation failed...".to_owned()
             },
             TakerSwapEvent::TakerPaymentSent(_) => "Taker payment sent...".to_owned(),
+            TakerSwapEvent::TakerPaymentReplaced(_) => "Taker payment replaced...".to_owned(),
             TakerSwapEvent::TakerPaymentTransactionFailed(_) => "Taker payment transaction failed...".to_owned(),
             TakerSwapEvent::TakerPaymentDataSendFailed(_) => "Taker payment data send failed...".to_owned(),
             TakerSwapEvent::TakerPaymentWaitConfirmFailed(_) => {
@@ -528,23 +577,28 @@ impl TakerSwapEvent {
     }
 
     fn should_ban_maker(&self) -> bool {
-        matches!(self,
+        matches!(
+            self,
             TakerSwapEvent::NegotiateFailed(_)
-            | TakerSwapEvent::MakerPaymentValidateFailed(_)
-            | TakerSwapEvent::TakerPaymentWaitForSpendFailed(_))
+                | TakerSwapEvent::MakerPaymentValidateFailed(_)
+                | TakerSwapEvent::TakerPaymentWaitForSpendFailed(_)
+        )
     }
 
     fn is_success(&self) -> bool {
-        matches!(self, TakerSwapEvent::Started(_)
-            | TakerSwapEvent::Negotiated(_)
-            | TakerSwapEvent::TakerFeeSent(_)
-            | TakerSwapEvent::MakerPaymentReceived(_)
-            | TakerSwapEvent::MakerPaymentWaitConfirmStarted
-            | TakerSwapEvent::MakerPaymentValidatedAndConfirmed
-            | TakerSwapEvent::TakerPaymentSent(_)
-            | TakerSwapEvent::TakerPaymentSpent(_)
-            | TakerSwapEvent::MakerPaymentSpent(_)
-            | TakerSwapEvent::Finished
+        matches!(
+            self,
+            TakerSwapEvent::Started(_)
+                | TakerSwapEvent::Negotiated(_)
+                | TakerSwapEvent::TakerFeeSent(_)
+                | TakerSwapEvent::MakerPaymentReceived(_)
+                | TakerSwapEvent::MakerPaymentWaitConfirmStarted
+                | TakerSwapEvent::MakerPaymentValidatedAndConfirmed
+                | TakerSwapEvent::TakerPaymentSent(_)
+                | TakerSwapEvent::TakerPaymentReplaced(_)
+                | TakerSwapEvent::TakerPaymentSpent(_)
+                | TakerSwapEvent::MakerPaymentSpent(_)
+                | TakerSwapEvent::Finished
         )
     }
 
@@ -578,8 +632,23 @@ impl TakerSwap {
             TakerSwapEvent::Negotiated(data) => {
                 self.maker_payment_lock
                     .store(data.maker_payment_locktime, Ordering::Relaxed);
-                self.w().other_persistent_pub = data.maker_pubkey.into();
-                self.w().secret_hash = data.secret_hash;
+                let mut swap_mut = self.w();
+                match (data.maker_coin_htlc_pubkey, data.taker_coin_htlc_pubkey) {
+                    (Some(maker_coin_htlc_pub), Some(taker_coin_htlc_pub)) => {
+                        swap_mut.other_maker_coin_htlc_pub = maker_coin_htlc_pub.into();
+                        swap_mut.other_taker_coin_htlc_pub = taker_coin_htlc_pub.into();
+                        swap_mut.swap_unique_data = self.uuid.as_bytes().to_vec();
+                    },
+                    _ => {
+                        // the persistent pubkey is used for both coins
+                        swap_mut.other_maker_coin_htlc_pub = data.maker_pubkey.clone().into();
+                        swap_mut.other_taker_coin_htlc_pub = data.maker_pubkey.into();
+                        swap_mut.swap_unique_data = Vec::new();
+                    },
+                }
+                swap_mut.secret_hash = data.secret_hash;
+                swap_mut.maker_payment_script_type = data.maker_payment_script_type;
+                swap_mut.taker_payment_script_type = data.taker_payment_script_type;
             },
             TakerSwapEvent::NegotiateFailed(err) => self.errors.lock().push(err),
             TakerSwapEvent::TakerFeeSent(tx) => self.w().taker_fee = Some(tx),
@@ -592,6 +661,10 @@ impl TakerSwap {
             TakerSwapEvent::MakerPaymentValidateFailed(err) => self.errors.lock().push(err),
             TakerSwapEvent::MakerPaymentWaitConfirmFailed(err) => self.errors.lock().push(err),
             TakerSwapEvent::TakerPaymentSent(tx) => self.w().taker_payment = Some(tx),
+            TakerSwapEvent::TakerPaymentReplaced(tx) => {
+                self.taker_payment_fee_bumps.fetch_add(1, Ordering::Relaxed);
+                self.w().taker_payment = Some(tx)
+            },
             TakerSwapEvent::TakerPaymentTransactionFailed(err) => self.errors.lock().push(err),
             TakerSwapEvent::TakerPaymentDataSendFailed(err) => self.errors.lock().push(err),
             TakerSwapEvent::TakerPaymentWaitConfirmFailed(err) => self.errors.lock().push(err),
@@ -651,6 +724,8 @@ impl TakerSwap {
             maker,
             uuid,
             maker_payment_confirmed: Atomic::new(false),
+            taker_payment_fee_bumps: Atomic::new(0),
+            payment_fee_bump_requested: Atomic::new(false),
             finished_at: Atomic::new(0),
             maker_payment_lock: Atomic::new(0),
             errors: PaMutex::new(Vec::new()),
@@ -658,7 +733,11 @@ impl TakerSwap {
             payment_locktime,
             mutable: RwLock::new(TakerSwapMut {
                 data: TakerSwapData::default(),
-                other_persistent_pub: H264::default(),
+                other_maker_coin_htlc_pub: H264::default(),
+                other_taker_coin_htlc_pub: H264::default(),
+                swap_unique_data: Vec::new(),
+                maker_payment_script_type: HtlcScriptType::default(),
+                taker_payment_script_type: HtlcScriptType::default(),
                 taker_fee: None,
                 maker_payment: None,
                 taker_payment: None,
@@ -815,11 +894,35 @@ impl TakerSwap {
             )]));
         }
 
+        let p2wsh_htlc_support = P2wshHtlcSupport::new(&self.maker_coin, &self.taker_coin);
+        let (maker_payment_script_type, taker_payment_script_type) =
+            negotiate_htlc_script_types(&p2wsh_htlc_support, &maker_data.p2wsh_htlc_support);
+        let htlc_pubkeys = match SwapHtlcPubkeys::new(&self.maker_coin, &self.taker_coin, &self.uuid) {
+            Ok(pubkeys) => Some(pubkeys),
+            Err(e) => {
+                return Ok((Some(TakerSwapCommand::Finish), vec![TakerSwapEvent::NegotiateFailed(
+                    ERRL!("Error deriving the HTLC pubkeys: {}", e).into(),
+                )]))
+            },
+        };
+        let (maker_coin_htlc_pubkey, taker_coin_htlc_pubkey) =
+            match negotiate_htlc_pubkeys(&htlc_pubkeys, &maker_data.htlc_pubkeys) {
+                Ok(Some((maker_coin_pub, taker_coin_pub))) => (Some(maker_coin_pub), Some(taker_coin_pub)),
+                Ok(None) => (None, None),
+                Err(e) => {
+                    return Ok((Some(TakerSwapCommand::Finish), vec![TakerSwapEvent::NegotiateFailed(
+                        ERRL!("{}", e).into(),
+                    )]))
+                },
+            };
+
         let taker_data = SwapMsg::NegotiationReply(NegotiationDataMsg {
             started_at: self.r().data.started_at,
             secret_hash: maker_data.secret_hash,
             payment_locktime: self.r().data.taker_payment_lock,
             persistent_pubkey: self.my_persistent_pub.to_vec(),
+            p2wsh_htlc_support,
+            htlc_pubkeys,
         });
         let send_abort_handle = broadcast_swap_message_every(
             self.ctx.clone(),
@@ -854,6 +957,10 @@ impl TakerSwap {
                 maker_payment_locktime: maker_data.payment_locktime,
                 maker_pubkey: maker_data.persistent_pubkey.as_slice().into(),
                 secret_hash: maker_data.secret_hash.into(),
+                maker_payment_script_type,
+                taker_payment_script_type,
+                maker_coin_htlc_pubkey,
+                taker_coin_htlc_pubkey,
             },
         )]))
     }
@@ -949,29 +1056,45 @@ impl TakerSwap {
 
     async fn validate_maker_payment(&self) -> Result<(Option<TakerSwapCommand>, Vec<TakerSwapEvent>), String> {
         log!({ "Before wait confirm" });
-        let f = self.maker_coin.wait_for_confirmations(
-            &unwrap!(self.r().maker_payment.clone()).tx_hex,
+        let maker_payment = unwrap!(self.r().maker_payment.clone());
+        let f = wait_for_other_payment_confirmations(
+            &self.ctx,
+            &self.uuid,
+            &self.maker_coin,
+            &maker_payment,
             self.r().data.maker_payment_confirmations,
             self.r().data.maker_payment_requires_nota.unwrap_or(false),
             self.r().data.maker_payment_wait,
-            WAIT_CONFIRM_INTERVAL,
+            |store| store.maker_payment.take(),
         );
-        if let Err(err) = f.compat().await {
-            return Ok((Some(TakerSwapCommand::Finish), vec![
-                TakerSwapEvent::MakerPaymentWaitConfirmFailed(
-                    ERRL!("!wait for maker payment confirmations: {}", err).into(),
-                ),
-            ]));
+        match f.await {
+            Ok(PaymentConfirmation::Confirmed) => (),
+            // the replacement is validated after it's confirmed as the replaced payment would be
+            Ok(PaymentConfirmation::Replaced(replacement)) => {
+                return Ok((Some(TakerSwapCommand::ValidateMakerPayment), vec![
+                    TakerSwapEvent::MakerPaymentReceived(replacement),
+                    TakerSwapEvent::MakerPaymentWaitConfirmStarted,
+                ]))
+            },
+            Err(err) => {
+                return Ok((Some(TakerSwapCommand::Finish), vec![
+                    TakerSwapEvent::MakerPaymentWaitConfirmFailed(
+                        ERRL!("!wait for maker payment confirmations: {}", err).into(),
+                    ),
+                ]))
+            },
         }
         log!({ "After wait confirm" });
 
         let validated_f = self.maker_coin.validate_maker_payment(
             &unwrap!(self.r().maker_payment.clone()).tx_hex,
             self.maker_payment_lock.load(Ordering::Relaxed) as u32,
-            &*self.r().other_persistent_pub,
+            &*self.r().other_maker_coin_htlc_pub,
             &self.r().secret_hash.0,
             self.maker_amount.to_decimal(),
             &self.r().data.maker_coin_swap_contract_address,
+            self.r().maker_payment_script_type,
+            &self.r().swap_unique_data,
         );
         let validated = validated_f.compat().await;
 
@@ -997,10 +1120,12 @@ impl TakerSwap {
 
         let f = self.taker_coin.check_if_my_payment_sent(
             self.r().data.taker_payment_lock as u32,
-            &*self.r().other_persistent_pub,
+            &*self.r().other_taker_coin_htlc_pub,
             &self.r().secret_hash.0,
             self.r().data.taker_coin_start_block,
             &self.r().data.taker_coin_swap_contract_address,
+            self.r().taker_payment_script_type,
+            &self.r().swap_unique_data,
         );
         let transaction = match f.compat().await {
             Ok(res) => match res {
@@ -1008,10 +1133,14 @@ impl TakerSwap {
                 None => {
                     let payment_fut = self.taker_coin.send_taker_payment(
                         self.r().data.taker_payment_lock as u32,
-                        &*self.r().other_persistent_pub,
+                        &*self.r().other_taker_coin_htlc_pub,
                         &self.r().secret_hash.0,
                         self.taker_amount.to_decimal(),
                         &self.r().data.taker_coin_swap_contract_address,
+                        self.r().taker_payment_script_type,
+                        // the payment accepted by the maker without confirmations must not be replaceable
+                        self.r().data.taker_payment_confirmations > 0,
+                        &self.r().swap_unique_data,
                     );
 
                     match payment_fut.compat().await {
@@ -1050,25 +1179,39 @@ impl TakerSwap {
 
         let wait_duration = (self.r().data.lock_duration * 4) / 5;
         let wait_taker_payment = self.r().data.started_at + wait_duration;
-        let wait_f = self
-            .taker_coin
-            .wait_for_confirmations(
-                &unwrap!(self.r().taker_payment.clone()).tx_hex,
-                self.r().data.taker_payment_confirmations,
-                self.r().data.taker_payment_requires_nota.unwrap_or(false),
-                wait_taker_payment,
-                WAIT_CONFIRM_INTERVAL,
-            )
-            .compat();
-        if let Err(err) = wait_f.await {
-            return Ok((Some(TakerSwapCommand::RefundTakerPayment), vec![
-                TakerSwapEvent::TakerPaymentWaitConfirmFailed(
-                    ERRL!("!taker_coin.wait_for_confirmations: {}", err).into(),
-                ),
-                TakerSwapEvent::TakerPaymentWaitRefundStarted {
-                    wait_until: self.wait_refund_until(),
-                },
-            ]));
+        let stalled_at = if self.taker_payment_fee_bumps.load(Ordering::Relaxed) < MAX_AUTO_PAYMENT_FEE_BUMPS {
+            Some(now_ms() / 1000 + self.r().data.lock_duration / PAYMENT_STALL_LOCK_DIVIDER)
+        } else {
+            None
+        };
+        let taker_payment = unwrap!(self.r().taker_payment.clone());
+        let wait_f = wait_for_my_payment_confirmations(
+            &self.taker_coin,
+            &taker_payment,
+            self.r().data.taker_payment_confirmations,
+            self.r().data.taker_payment_requires_nota.unwrap_or(false),
+            wait_taker_payment,
+            stalled_at,
+            &self.payment_fee_bump_requested,
+        );
+        match wait_f.await {
+            Ok(PaymentConfirmation::Confirmed) => (),
+            // rebroadcast the replacement to the maker and wait for its confirmations
+            Ok(PaymentConfirmation::Replaced(replacement)) => {
+                return Ok((Some(TakerSwapCommand::WaitForTakerPaymentSpend), vec![
+                    TakerSwapEvent::TakerPaymentReplaced(replacement),
+                ]))
+            },
+            Err(err) => {
+                return Ok((Some(TakerSwapCommand::RefundTakerPayment), vec![
+                    TakerSwapEvent::TakerPaymentWaitConfirmFailed(
+                        ERRL!("!taker_coin.wait_for_confirmations: {}", err).into(),
+                    ),
+                    TakerSwapEvent::TakerPaymentWaitRefundStarted {
+                        wait_until: self.wait_refund_until(),
+                    },
+                ]))
+            },
         }
 
         let f = self.taker_coin.wait_for_tx_spend(
@@ -1119,9 +1262,10 @@ impl TakerSwap {
         let spend_fut = self.maker_coin.send_taker_spends_maker_payment(
             &unwrap!(self.r().maker_payment.clone()).tx_hex,
             self.maker_payment_lock.load(Ordering::Relaxed) as u32,
-            &*self.r().other_persistent_pub,
+            &*self.r().other_maker_coin_htlc_pub,
             &self.r().secret.0,
             &self.r().data.maker_coin_swap_contract_address,
+            &self.r().swap_unique_data,
         );
         let transaction = match spend_fut.compat().await {
             Ok(t) => t,
@@ -1156,9 +1300,10 @@ impl TakerSwap {
         let refund_fut = self.taker_coin.send_taker_refunds_payment(
             &self.r().taker_payment.clone().unwrap().tx_hex.0,
             self.r().data.taker_payment_lock as u32,
-            &*self.r().other_persistent_pub,
+            &*self.r().other_taker_coin_htlc_pub,
             &self.r().secret_hash.0,
             &self.r().data.taker_coin_swap_contract_address,
+            &self.r().swap_unique_data,
         );
 
         let transaction = match refund_fut.compat().await {
@@ -1222,7 +1367,7 @@ impl TakerSwap {
 
         let mut maker = bits256::from([0; 32]);
         maker.bytes = data.maker.0;
-        let my_persistent_pub = H264::from(&**ctx.secp256k1_key_pair().public());
+        let my_persistent_pub = H264::from(&**ctx.secp256k1_public());
         let conf_settings = SwapConfirmationsSettings {
             maker_coin_confs: data.maker_payment_confirmations,
             maker_coin_nota: data
@@ -1276,11 +1421,12 @@ impl TakerSwap {
             () => {
                 match self.maker_coin.search_for_swap_tx_spend_other(
                     self.maker_payment_lock.load(Ordering::Relaxed) as u32,
-                    &*self.r().other_persistent_pub,
+                    &*self.r().other_maker_coin_htlc_pub,
                     &self.r().secret_hash.0,
                     &maker_payment,
                     self.r().data.maker_coin_start_block,
                     &self.r().data.maker_coin_swap_contract_address,
+                    &self.r().swap_unique_data,
                 ) {
                     Ok(Some(FoundSwapTxSpend::Spent(tx))) => {
                         return ERR!(
@@ -1309,10 +1455,12 @@ impl TakerSwap {
                     .taker_coin
                     .check_if_my_payment_sent(
                         self.r().data.taker_payment_lock as u32,
-                        &*self.r().other_persistent_pub,
+                        &*self.r().other_taker_coin_htlc_pub,
                         &self.r().secret_hash.0,
                         self.r().data.taker_coin_start_block,
                         &self.r().data.taker_coin_swap_contract_address,
+                        self.r().taker_payment_script_type,
+                        &self.r().swap_unique_data,
                     )
                     .wait());
                 match maybe_sent {
@@ -1329,9 +1477,10 @@ impl TakerSwap {
                 .send_taker_spends_maker_payment(
                     &maker_payment,
                     self.maker_payment_lock.load(Ordering::Relaxed) as u32,
-                    &*self.r().other_persistent_pub,
+                    &*self.r().other_maker_coin_htlc_pub,
                     &self.r().secret.0,
                     &self.r().data.maker_coin_swap_contract_address,
+                    &self.r().swap_unique_data,
                 )
                 .wait());
 
@@ -1344,11 +1493,12 @@ impl TakerSwap {
 
         let taker_payment_spend = try_s!(self.taker_coin.search_for_swap_tx_spend_my(
             self.r().data.taker_payment_lock as u32,
-            &*self.r().other_persistent_pub,
+            &*self.r().other_taker_coin_htlc_pub,
             &self.r().secret_hash.0,
             &taker_payment,
             self.r().data.taker_coin_start_block,
             &self.r().data.taker_coin_swap_contract_address,
+            &self.r().swap_unique_data,
         ));
 
         match taker_payment_spend {
@@ -1361,9 +1511,10 @@ impl TakerSwap {
                         .send_taker_spends_maker_payment(
                             &maker_payment,
                             self.maker_payment_lock.load(Ordering::Relaxed) as u32,
-                            &*self.r().other_persistent_pub,
+                            &*self.r().other_maker_coin_htlc_pub,
                             &secret,
                             &self.r().data.maker_coin_swap_contract_address,
+                            &self.r().swap_unique_data,
                         )
                         .wait());
 
@@ -1391,9 +1542,10 @@ impl TakerSwap {
                     .send_taker_refunds_payment(
                         &taker_payment,
                         self.r().data.taker_payment_lock as u32,
-                        &*self.r().other_persistent_pub,
+                        &*self.r().other_taker_coin_htlc_pub,
                         &self.r().secret_hash.0,
                         &self.r().data.taker_coin_swap_contract_address,
+                        &self.r().swap_unique_data,
                     )
                     .wait());
 
@@ -1451,6 +1603,8 @@ impl AtomicSwap for TakerSwap {
     fn maker_coin(&self) -> &str { self.maker_coin.ticker() }
 
     fn taker_coin(&self) -> &str { self.taker_coin.ticker() }
+
+    fn request_payment_fee_bump(&self) { self.payment_fee_bump_requested.store(true, Ordering::Relaxed) }
 }
 
 pub struct TakerSwapPreparedParams {
@@ -1519,51 +1673,81 @@ pub async fn check_balance_for_taker_swap(
 pub async fn taker_swap_trade_preimage(
     ctx: &MmArc,
     req: TradePreimageRequest,
-) -> Result<TradePreimageResponse, String> {
+) -> Result<TradePreimageResponse, CheckBalanceError> {
     let (my_coin_ticker, other_coin_ticker) = match req.swap_method {
-        TradePreimageMethod::SetPrice => return ERR!("Internal error: expected 'sell' or 'buy' method"),
+        TradePreimageMethod::SetPrice => {
+            return Err(CheckBalanceError::Other(ERRL!(
+                "Internal error: expected 'sell' or 'buy' method"
+            )))
+        },
         TradePreimageMethod::Sell => (req.base, req.rel),
         TradePreimageMethod::Buy => (req.rel, req.base),
     };
     let my_coin = match lp_coinfind(&ctx, &my_coin_ticker).await {
         Ok(Some(t)) => t,
-        Ok(None) => return ERR!("No such coin: {}", my_coin_ticker),
-        Err(err) => return ERR!("!lp_coinfind({}): {}", my_coin_ticker, err),
+        Ok(None) => return Err(CheckBalanceError::Other(ERRL!("No such coin: {}", my_coin_ticker))),
+        Err(err) => {
+            return Err(CheckBalanceError::Other(ERRL!(
+                "!lp_coinfind({}): {}",
+                my_coin_ticker,
+                err
+            )))
+        },
     };
     let other_coin = match lp_coinfind(&ctx, &other_coin_ticker).await {
         Ok(Some(t)) => t,
-        Ok(None) => return ERR!("No such coin: {}", other_coin_ticker),
-        Err(err) => return ERR!("!lp_coinfind({}): {}", other_coin_ticker, err),
+        Ok(None) => return Err(CheckBalanceError::Other(ERRL!("No such coin: {}", other_coin_ticker))),
+        Err(err) => {
+            return Err(CheckBalanceError::Other(ERRL!(
+                "!lp_coinfind({}): {}",
+                other_coin_ticker,
+                err
+            )))
+        },
     };
 
     let stage = FeeApproxStage::TradePreimage;
     let volume = if req.max {
-        try_s!(calc_max_taker_vol(&ctx, &my_coin, &other_coin_ticker, stage.clone()).await)
+        calc_max_taker_vol(&ctx, &my_coin, &other_coin_ticker, stage.clone())
+            .await
+            .trace(source!())?
     } else {
         req.volume
     };
 
     let dex_amount = dex_fee_amount_from_taker_coin(&my_coin, &other_coin_ticker, &volume);
-    let fee_to_send_dex_fee = try_s!(
-        my_coin
-            .get_fee_to_send_taker_fee(dex_amount.to_decimal(), stage.clone())
-            .compat()
-            .await
-    );
+    let fee_to_send_dex_fee = my_coin
+        .get_fee_to_send_taker_fee(dex_amount.to_decimal(), stage.clone())
+        .compat()
+        .await
+        .trace(source!())?;
 
     let preimage_value = TradePreimageValue::Exact(volume.to_decimal());
-    let my_coin_trade_fee = try_s!(
-        my_coin
-            .get_sender_trade_fee(preimage_value, stage.clone())
-            .compat()
-            .await
-    );
-    let other_coin_trade_fee = try_s!(other_coin.get_receiver_trade_fee(stage).compat().await);
+    let my_coin_trade_fee = my_coin
+        .get_sender_trade_fee(preimage_value, stage.clone())
+        .compat()
+        .await
+        .trace(source!())?;
+    let other_coin_trade_fee = other_coin
+        .get_receiver_trade_fee(stage.clone())
+        .compat()
+        .await
+        .trace(source!())?;
+    let my_coin_cpfp_fee = my_coin
+        .get_cpfp_child_fee(stage.clone())
+        .compat()
+        .await
+        .trace(source!())?;
+    let other_coin_cpfp_fee = other_coin.get_cpfp_child_fee(stage).compat().await.trace(source!())?;
 
     let (base_coin_fee, rel_coin_fee) = match req.swap_method {
         TradePreimageMethod::Sell => (my_coin_trade_fee, other_coin_trade_fee),
         _ => (other_coin_trade_fee, my_coin_trade_fee),
     };
+    let (base_coin_cpfp_fee, rel_coin_cpfp_fee) = match req.swap_method {
+        TradePreimageMethod::Sell => (my_coin_cpfp_fee, other_coin_cpfp_fee),
+        _ => (other_coin_cpfp_fee, my_coin_cpfp_fee),
+    };
     let volume = if req.max {
         Some(DetailedVolume::from(volume))
     } else {
@@ -1575,6 +1759,8 @@ pub async fn taker_swap_trade_preimage(
         volume,
         taker_fee: Some(DetailedTakerFee::from(dex_amount)),
         fee_to_send_taker_fee: Some(TradeFeeResponse::from(fee_to_send_dex_fee)),
+        base_coin_cpfp_fee: base_coin_cpfp_fee.map(TradeFeeResponse::from),
+        rel_coin_cpfp_fee: rel_coin_cpfp_fee.map(TradeFeeResponse::from),
     })
 }
 
@@ -1585,19 +1771,27 @@ struct MaxTakerVolRequest {
 }
 
 pub async fn max_taker_vol(ctx: MmArc, req: Json) -> Result<Response<Vec<u8>>, String> {
-    let req: MaxTakerVolRequest = try_s!(json::from_value(req));
+    let res = try_s!(max_taker_vol_rpc(&ctx, req).await);
+    let res = try_s!(json::to_vec(&res));
+    Ok(try_s!(Response::builder().body(res)))
+}
+
+/// Returns the `max_taker_vol` response keeping the [`TradeRequestError`] type.
+pub async fn max_taker_vol_rpc(ctx: &MmArc, req: Json) -> Result<Json, TradeRequestError> {
+    let req: MaxTakerVolRequest = try_map!(json::from_value(req), TradeRequestError::InvalidParams);
     let coin = match lp_coinfind(&ctx, &req.coin).await {
         Ok(Some(t)) => t,
-        Ok(None) => return ERR!("No such coin: {}", req.coin),
-        Err(err) => return ERR!("!lp_coinfind({}): {}", req.coin, err),
+        Ok(None) => return Err(TradeRequestError::Other(ERRL!("No such coin: {}", req.coin))),
+        Err(err) => return Err(TradeRequestError::Other(ERRL!("!lp_coinfind({}): {}", req.coin, err))),
     };
     let other_coin = req.trade_with.as_ref().unwrap_or(&req.coin);
-    let max_vol = try_s!(calc_max_taker_vol(&ctx, &coin, other_coin, FeeApproxStage::TradePreimage).await);
+    let max_vol = calc_max_taker_vol(&ctx, &coin, other_coin, FeeApproxStage::TradePreimage)
+        .await
+        .trace(source!())?;
 
-    let res = try_s!(json::to_vec(&json!({
+    Ok(json!({
         "result": max_vol.to_fraction()
-    })));
-    Ok(try_s!(Response::builder().body(res)))
+    }))
 }
 
 /// If we want to calculate the maximum taker volume, we should solve the following equation:
@@ -1738,11 +1932,11 @@ mod taker_swap_tests {
         TestCoin::swap_contract_address.mock_safe(|_| MockResult::Return(None));
 
         static mut MAKER_PAYMENT_SPEND_CALLED: bool = false;
-        TestCoin::send_taker_spends_maker_payment.mock_safe(|_, _, _, _, _, _| {
+        TestCoin::send_taker_spends_maker_payment.mock_safe(|_, _, _, _, _, _, _| {
             unsafe { MAKER_PAYMENT_SPEND_CALLED = true };
             MockResult::Return(Box::new(futures01::future::ok(eth_tx_for_test().into())))
         });
-        TestCoin::search_for_swap_tx_spend_other.mock_safe(|_, _, _, _, _, _, _| MockResult::Return(Ok(None)));
+        TestCoin::search_for_swap_tx_spend_other.mock_safe(|_, _, _, _, _, _, _, _| MockResult::Return(Ok(None)));
         let maker_coin = MmCoinEnum::Test(TestCoin {});
         let taker_coin = MmCoinEnum::Test(TestCoin {});
         let (taker_swap, _) = unwrap!(TakerSwap::load_from_saved(
@@ -1774,19 +1968,19 @@ mod taker_swap_tests {
         TestCoin::swap_contract_address.mock_safe(|_| MockResult::Return(None));
 
         static mut MY_PAYMENT_SENT_CALLED: bool = false;
-        TestCoin::check_if_my_payment_sent.mock_safe(|_, _, _, _, _, _| {
+        TestCoin::check_if_my_payment_sent.mock_safe(|_, _, _, _, _, _, _, _| {
             unsafe { MY_PAYMENT_SENT_CALLED = true };
             MockResult::Return(Box::new(futures01::future::ok(Some(eth_tx_for_test().into()))))
         });
 
         static mut TX_SPEND_CALLED: bool = false;
-        TestCoin::search_for_swap_tx_spend_my.mock_safe(|_, _, _, _, _, _, _| {
+        TestCoin::search_for_swap_tx_spend_my.mock_safe(|_, _, _, _, _, _, _, _| {
             unsafe { TX_SPEND_CALLED = true };
             MockResult::Return(Ok(None))
         });
 
         static mut TAKER_PAYMENT_REFUND_CALLED: bool = false;
-        TestCoin::send_taker_refunds_payment.mock_safe(|_, _, _, _, _, _| {
+        TestCoin::send_taker_refunds_payment.mock_safe(|_, _, _, _, _, _, _| {
             unsafe { TAKER_PAYMENT_REFUND_CALLED = true };
             MockResult::Return(Box::new(futures01::future::ok(eth_tx_for_test().into())))
         });
@@ -1824,22 +2018,22 @@ mod taker_swap_tests {
         TestCoin::extract_secret.mock_safe(|_, _, _| MockResult::Return(Ok(vec![])));
 
         static mut MY_PAYMENT_SENT_CALLED: bool = false;
-        TestCoin::check_if_my_payment_sent.mock_safe(|_, _, _, _, _, _| {
+        TestCoin::check_if_my_payment_sent.mock_safe(|_, _, _, _, _, _, _, _| {
             unsafe { MY_PAYMENT_SENT_CALLED = true };
             MockResult::Return(Box::new(futures01::future::ok(Some(eth_tx_for_test().into()))))
         });
 
         static mut SEARCH_TX_SPEND_CALLED: bool = false;
-        TestCoin::search_for_swap_tx_spend_my.mock_safe(|_, _, _, _, _, _, _| {
+        TestCoin::search_for_swap_tx_spend_my.mock_safe(|_, _, _, _, _, _, _, _| {
             unsafe { SEARCH_TX_SPEND_CALLED = true };
             let tx: UtxoTx = "0100000001de7aa8d29524906b2b54ee2e0281f3607f75662cbc9080df81d1047b78e21dbc00000000d7473044022079b6c50820040b1fbbe9251ced32ab334d33830f6f8d0bf0a40c7f1336b67d5b0220142ccf723ddabb34e542ed65c395abc1fbf5b6c3e730396f15d25c49b668a1a401209da937e5609680cb30bff4a7661364ca1d1851c2506fa80c443f00a3d3bf7365004c6b6304f62b0e5cb175210270e75970bb20029b3879ec76c4acd320a8d0589e003636264d01a7d566504bfbac6782012088a9142fb610d856c19fd57f2d0cffe8dff689074b3d8a882103f368228456c940ac113e53dad5c104cf209f2f102a409207269383b6ab9b03deac68ffffffff01d0dc9800000000001976a9146d9d2b554d768232320587df75c4338ecc8bf37d88ac40280e5c".into();
             MockResult::Return(Ok(Some(FoundSwapTxSpend::Spent(tx.into()))))
         });
 
-        TestCoin::search_for_swap_tx_spend_other.mock_safe(|_, _, _, _, _, _, _| MockResult::Return(Ok(None)));
+        TestCoin::search_for_swap_tx_spend_other.mock_safe(|_, _, _, _, _, _, _, _| MockResult::Return(Ok(None)));
 
         static mut MAKER_PAYMENT_SPEND_CALLED: bool = false;
-        TestCoin::send_taker_spends_maker_payment.mock_safe(|_, _, _, _, _, _| {
+        TestCoin::send_taker_spends_maker_payment.mock_safe(|_, _, _, _, _, _, _| {
             unsafe { MAKER_PAYMENT_SPEND_CALLED = true };
             MockResult::Return(Box::new(futures01::future::ok(eth_tx_for_test().into())))
         });
@@ -1876,13 +2070,13 @@ mod taker_swap_tests {
         TestCoin::swap_contract_address.mock_safe(|_| MockResult::Return(None));
 
         static mut SEARCH_TX_SPEND_CALLED: bool = false;
-        TestCoin::search_for_swap_tx_spend_my.mock_safe(|_, _, _, _, _, _, _| {
+        TestCoin::search_for_swap_tx_spend_my.mock_safe(|_, _, _, _, _, _, _, _| {
             unsafe { SEARCH_TX_SPEND_CALLED = true };
             MockResult::Return(Ok(None))
         });
 
         static mut REFUND_CALLED: bool = false;
-        TestCoin::send_taker_refunds_payment.mock_safe(|_, _, _, _, _, _| {
+        TestCoin::send_taker_refunds_payment.mock_safe(|_, _, _, _, _, _, _| {
             unsafe { REFUND_CALLED = true };
             MockResult::Return(Box::new(futures01::future::ok(eth_tx_for_test().into())))
         });
@@ -1918,7 +2112,7 @@ mod taker_swap_tests {
         TestCoin::swap_contract_address.mock_safe(|_| MockResult::Return(None));
 
         static mut SEARCH_TX_SPEND_CALLED: bool = false;
-        TestCoin::search_for_swap_tx_spend_my.mock_safe(|_, _, _, _, _, _, _| {
+        TestCoin::search_for_swap_tx_spend_my.mock_safe(|_, _, _, _, _, _, _, _| {
             unsafe { SEARCH_TX_SPEND_CALLED = true };
             MockResult::Return(Ok(None))
         });
@@ -1949,16 +2143,16 @@ mod taker_swap_tests {
         TestCoin::extract_secret.mock_safe(|_, _, _| MockResult::Return(Ok(vec![])));
 
         static mut SEARCH_TX_SPEND_CALLED: bool = false;
-        TestCoin::search_for_swap_tx_spend_my.mock_safe(|_, _, _, _, _, _, _| {
+        TestCoin::search_for_swap_tx_spend_my.mock_safe(|_, _, _, _, _, _, _, _| {
             unsafe { SEARCH_TX_SPEND_CALLED = true };
             let tx: UtxoTx = "0100000001de7aa8d29524906b2b54ee2e0281f3607f75662cbc9080df81d1047b78e21dbc00000000d7473044022079b6c50820040b1fbbe9251ced32ab334d33830f6f8d0bf0a40c7f1336b67d5b0220142ccf723ddabb34e542ed65c395abc1fbf5b6c3e730396f15d25c49b668a1a401209da937e5609680cb30bff4a7661364ca1d1851c2506fa80c443f00a3d3bf7365004c6b6304f62b0e5cb175210270e75970bb20029b3879ec76c4acd320a8d0589e003636264d01a7d566504bfbac6782012088a9142fb610d856c19fd57f2d0cffe8dff689074b3d8a882103f368228456c940ac113e53dad5c104cf209f2f102a409207269383b6ab9b03deac68ffffffff01d0dc9800000000001976a9146d9d2b554d768232320587df75c4338ecc8bf37d88ac40280e5c".into();
             MockResult::Return(Ok(Some(FoundSwapTxSpend::Spent(tx.into()))))
         });
 
-        TestCoin::search_for_swap_tx_spend_other.mock_safe(|_, _, _, _, _, _, _| MockResult::Return(Ok(None)));
+        TestCoin::search_for_swap_tx_spend_other.mock_safe(|_, _, _, _, _, _, _, _| MockResult::Return(Ok(None)));
 
         static mut MAKER_PAYMENT_SPEND_CALLED: bool = false;
-        TestCoin::send_taker_spends_maker_payment.mock_safe(|_, _, _, _, _, _| {
+        TestCoin::send_taker_spends_maker_payment.mock_safe(|_, _, _, _, _, _, _| {
             unsafe { MAKER_PAYMENT_SPEND_CALLED = true };
             MockResult::Return(Box::new(futures01::future::ok(eth_tx_for_test().into())))
         });
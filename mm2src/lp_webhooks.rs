@@ -0,0 +1,482 @@
+//! Outgoing webhooks notifying the back office about the swap results and the filled maker orders.
+//!
+//! The webhooks are configured in MM2.json:
+//!
+//!     "webhooks": [{"url": "https://example.com/mm2", "secret": "shared secret"}]
+//!
+//! Every event is POSTed to each configured URL as JSON. The body is signed with HMAC-SHA256 keyed by the webhook
+//! secret, the hex-encoded signature is sent in the `X-MM2-Signature` header.
+//! The pending deliveries are stored in the `WEBHOOKS` DB dir and retried with exponential backoff
+//! until the endpoint replies with a 2xx status, so they survive the restarts.
+
+use bigdecimal::BigDecimal;
+use common::executor::Timer;
+use common::mm_ctx::{from_ctx, MmArc};
+use common::wio::slurp_reqʹ;
+use common::{json_dir_entries, new_uuid, now_ms, remove_file, write};
+use futures::future::{join_all, select, Either};
+use gstuff::slurp;
+use hmac::{Hmac, Mac};
+use http::header::CONTENT_TYPE;
+use http::Request;
+use serde_json::{self as json, Value as Json};
+use sha2::Sha256;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+use crate::mm2::rpc::event_stream::SwapType;
+
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "X-MM2-Signature";
+/// How often the pending deliveries are checked, in seconds.
+const WEBHOOKS_LOOP_INTERVAL: f64 = 1.;
+/// The time given to the endpoint to reply, in seconds.
+const DELIVERY_TIMEOUT: f64 = 30.;
+/// The delivery is dropped after this number of the failed attempts.
+const MAX_DELIVERY_ATTEMPTS: u32 = 12;
+const INITIAL_RETRY_DELAY_MS: u64 = 5000;
+const MAX_RETRY_DELAY_MS: u64 = 3600 * 1000;
+
+#[derive(Clone, Debug, Deserialize)]
+struct WebhookConf {
+    url: String,
+    /// The HMAC key of the delivery signatures, the endpoint can't verify the deliveries without it.
+    secret: String,
+}
+
+fn webhooks_conf(ctx: &MmArc) -> Result<Vec<WebhookConf>, String> {
+    if ctx.conf["webhooks"].is_null() {
+        return Ok(vec![]);
+    }
+    let webhooks: Vec<WebhookConf> = try_s!(json::from_value(ctx.conf["webhooks"].clone()));
+    if let Some(webhook) = webhooks.iter().find(|webhook| webhook.secret.is_empty()) {
+        return ERR!("The 'secret' of the webhook {} must not be empty", webhook.url);
+    }
+    Ok(webhooks)
+}
+
+/// Checks the `webhooks` config at startup, so the node doesn't run with the webhooks that can't be delivered.
+pub fn validate_webhooks_conf(ctx: &MmArc) -> Result<(), String> {
+    try_s!(webhooks_conf(ctx));
+    Ok(())
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", content = "data")]
+pub enum WebhookEvent {
+    /// The swap reached the `Finished` event.
+    SwapFinished {
+        uuid: Uuid,
+        swap_type: SwapType,
+        /// Whether the swap finished without errors.
+        success: bool,
+    },
+    /// The swap event carrying a `SwapError` was saved.
+    SwapFailed {
+        uuid: Uuid,
+        swap_type: SwapType,
+        event_type: String,
+        error: String,
+    },
+    /// The maker or taker payment was refunded.
+    SwapRefunded {
+        uuid: Uuid,
+        swap_type: SwapType,
+        event_type: String,
+    },
+    /// The maker order is fully filled and removed from the orderbook.
+    MakerOrderFilled {
+        uuid: Uuid,
+        base: String,
+        rel: String,
+        filled_base_amount: BigDecimal,
+    },
+}
+
+/// Picks the webhook event corresponding to the saved swap event, if any.
+///
+/// * `saved_event` - the serialized `MakerSavedEvent` or `TakerSavedEvent`.
+/// * `success` - whether the swap is successful, known only when the swap is finished.
+pub fn swap_webhook_event(
+    uuid: Uuid,
+    swap_type: SwapType,
+    saved_event: &Json,
+    success: Option<bool>,
+) -> Option<WebhookEvent> {
+    let event_type = saved_event["event"]["type"].as_str()?;
+    match event_type {
+        "Finished" => Some(WebhookEvent::SwapFinished {
+            uuid,
+            swap_type,
+            success: success.unwrap_or(false),
+        }),
+        "MakerPaymentRefunded" | "TakerPaymentRefunded" => Some(WebhookEvent::SwapRefunded {
+            uuid,
+            swap_type,
+            event_type: event_type.to_owned(),
+        }),
+        _ => {
+            let error = saved_event["event"]["data"]["error"].as_str()?;
+            Some(WebhookEvent::SwapFailed {
+                uuid,
+                swap_type,
+                event_type: event_type.to_owned(),
+                error: error.to_owned(),
+            })
+        },
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+struct WebhookDelivery {
+    uuid: Uuid,
+    url: String,
+    /// The JSON body is kept as it was signed so the retries send exactly the same bytes.
+    body: String,
+    attempts: u32,
+    next_attempt_at: u64,
+}
+
+impl WebhookDelivery {
+    /// Schedules the next attempt after a failed one.
+    /// Returns `false` if the delivery ran out of the attempts.
+    fn schedule_retry(&mut self, now: u64) -> bool {
+        self.attempts += 1;
+        if self.attempts >= MAX_DELIVERY_ATTEMPTS {
+            return false;
+        }
+        self.next_attempt_at = now + retry_delay_ms(self.attempts);
+        true
+    }
+}
+
+/// The delay before the next attempt: 5s, 10s, 20s, ... capped at 1 hour.
+fn retry_delay_ms(attempts: u32) -> u64 {
+    let exp = attempts.saturating_sub(1).min(20);
+    (INITIAL_RETRY_DELAY_MS << exp).min(MAX_RETRY_DELAY_MS)
+}
+
+fn webhook_signature(secret: &str, body: &[u8]) -> Result<String, String> {
+    let mut mac = try_s!(Hmac::<Sha256>::new_varkey(secret.as_bytes()).map_err(|e| ERRL!("{:?}", e)));
+    mac.input(body);
+    Ok(hex::encode(mac.result().code()))
+}
+
+fn pending_webhooks_dir(ctx: &MmArc) -> PathBuf { ctx.dbdir().join("WEBHOOKS").join("PENDING") }
+
+fn pending_webhook_file_path(ctx: &MmArc, uuid: &Uuid) -> PathBuf {
+    pending_webhooks_dir(ctx).join(format!("{}.json", uuid))
+}
+
+fn save_pending_webhook(ctx: &MmArc, delivery: &WebhookDelivery) -> Result<(), String> {
+    let path = pending_webhook_file_path(ctx, &delivery.uuid);
+    let content = try_s!(json::to_vec(delivery));
+    try_s!(write(&path, &content));
+    Ok(())
+}
+
+fn delete_pending_webhook(ctx: &MmArc, uuid: &Uuid) {
+    let path = pending_webhook_file_path(ctx, uuid);
+    if let Err(e) = remove_file(&path) {
+        log!("Could not remove webhook file " (path.display()) ", error " (e));
+    }
+}
+
+#[derive(Default)]
+struct WebhooksContext {
+    pending: Mutex<Vec<WebhookDelivery>>,
+}
+
+impl WebhooksContext {
+    fn from_ctx(ctx: &MmArc) -> Result<Arc<WebhooksContext>, String> {
+        Ok(try_s!(from_ctx(&ctx.webhooks_ctx, move || {
+            Ok(WebhooksContext::default())
+        })))
+    }
+}
+
+/// Persists the deliveries of the `event` to every configured webhook, the deliveries are sent by `webhooks_loop`.
+pub fn queue_webhook_event(ctx: &MmArc, event: WebhookEvent) -> Result<(), String> {
+    let webhooks = try_s!(webhooks_conf(ctx));
+    if webhooks.is_empty() {
+        return Ok(());
+    }
+    let webhooks_ctx = try_s!(WebhooksContext::from_ctx(ctx));
+    let now = now_ms();
+    let body = try_s!(json::to_string(&json!({
+        "id": new_uuid(),
+        "timestamp": now,
+        "event": event,
+    })));
+    for webhook in webhooks {
+        let delivery = WebhookDelivery {
+            uuid: new_uuid(),
+            url: webhook.url,
+            body: body.clone(),
+            attempts: 0,
+            next_attempt_at: now,
+        };
+        try_s!(save_pending_webhook(ctx, &delivery));
+        try_s!(webhooks_ctx.pending.lock()).push(delivery);
+    }
+    Ok(())
+}
+
+/// Queues the webhook event corresponding to the saved swap event, if any.
+pub fn notify_swap_webhooks(ctx: &MmArc, uuid: Uuid, swap_type: SwapType, saved_event: &Json, success: Option<bool>) {
+    if let Some(event) = swap_webhook_event(uuid, swap_type, saved_event, success) {
+        if let Err(e) = queue_webhook_event(ctx, event) {
+            log!("Error " (e) " queueing the webhook of swap " (uuid));
+        }
+    }
+}
+
+async fn deliver_webhook(webhook: &WebhookConf, delivery: &WebhookDelivery) -> Result<(), String> {
+    let signature = try_s!(webhook_signature(&webhook.secret, delivery.body.as_bytes()));
+    let request = try_s!(Request::builder()
+        .method("POST")
+        .uri(&delivery.url)
+        .header(CONTENT_TYPE, "application/json")
+        .header(WEBHOOK_SIGNATURE_HEADER, signature)
+        .body(delivery.body.clone().into_bytes()));
+
+    let (status, _headers, body) = match select(Box::pin(slurp_reqʹ(request)), Timer::sleep(DELIVERY_TIMEOUT)).await {
+        Either::Left((res, _)) => try_s!(res),
+        Either::Right(_) => return ERR!("Timeout waiting for the reply"),
+    };
+    if !status.is_success() {
+        return ERR!("Status {}, body {}", status, String::from_utf8_lossy(&body));
+    }
+    Ok(())
+}
+
+fn load_pending_webhooks(ctx: &MmArc) -> Result<Vec<WebhookDelivery>, String> {
+    let entries = try_s!(json_dir_entries(&pending_webhooks_dir(ctx)));
+    Ok(entries
+        .iter()
+        .filter_map(|entry| json::from_slice(&slurp(&entry.path())).ok())
+        .collect())
+}
+
+/// Sends the delivery, then deletes it on success or reschedules the failed attempt.
+async fn process_delivery(
+    ctx: &MmArc,
+    webhooks_ctx: &WebhooksContext,
+    webhooks: &[WebhookConf],
+    mut delivery: WebhookDelivery,
+) {
+    let webhook = match webhooks.iter().find(|w| w.url == delivery.url) {
+        Some(w) => w,
+        None => {
+            log!("Webhook " (delivery.url) " is not configured anymore, dropping delivery " (delivery.uuid));
+            delete_pending_webhook(ctx, &delivery.uuid);
+            return;
+        },
+    };
+
+    match deliver_webhook(webhook, &delivery).await {
+        Ok(_) => delete_pending_webhook(ctx, &delivery.uuid),
+        Err(e) => {
+            if delivery.schedule_retry(now_ms()) {
+                log!("Webhook " (delivery.url) " delivery " (delivery.uuid) " attempt " (delivery.attempts) " failed: " (e));
+                if let Err(e) = save_pending_webhook(ctx, &delivery) {
+                    log!("Error " (e) " saving webhook delivery " (delivery.uuid));
+                }
+                unwrap!(webhooks_ctx.pending.lock()).push(delivery);
+            } else {
+                log!("Webhook " (delivery.url) " delivery " (delivery.uuid) " failed " (delivery.attempts) " times, giving up: " (e));
+                delete_pending_webhook(ctx, &delivery.uuid);
+            }
+        },
+    }
+}
+
+/// Sends the pending webhook deliveries, retrying the failed ones with backoff.
+pub async fn webhooks_loop(ctx: MmArc) {
+    let webhooks_ctx = unwrap!(WebhooksContext::from_ctx(&ctx));
+    match load_pending_webhooks(&ctx) {
+        Ok(deliveries) => unwrap!(webhooks_ctx.pending.lock()).extend(deliveries),
+        Err(e) => log!("Error " (e) " loading the pending webhook deliveries"),
+    }
+
+    loop {
+        if ctx.is_stopping() {
+            break;
+        }
+
+        let now = now_ms();
+        let due: Vec<WebhookDelivery> = {
+            let mut pending = unwrap!(webhooks_ctx.pending.lock());
+            let (due, rest) = pending.drain(..).partition(|d| d.next_attempt_at <= now);
+            *pending = rest;
+            due
+        };
+
+        if !due.is_empty() {
+            let webhooks = webhooks_conf(&ctx).unwrap_or_default();
+            // the deliveries are sent concurrently so a slow endpoint doesn't delay the others
+            let futures = due
+                .into_iter()
+                .map(|delivery| process_delivery(&ctx, &webhooks_ctx, &webhooks, delivery));
+            join_all(futures).await;
+        }
+
+        Timer::sleep(WEBHOOKS_LOOP_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod webhooks_tests {
+    use super::*;
+    use common::block_on;
+    use common::mm_ctx::MmCtxBuilder;
+
+    #[test]
+    fn test_swap_webhook_event() {
+        let uuid = new_uuid();
+
+        let saved = json!({"timestamp": 1, "event": {"type": "Finished"}});
+        let expected = WebhookEvent::SwapFinished {
+            uuid,
+            swap_type: SwapType::Maker,
+            success: true,
+        };
+        assert_eq!(
+            Some(expected),
+            swap_webhook_event(uuid, SwapType::Maker, &saved, Some(true))
+        );
+
+        let saved = json!({"timestamp": 1, "event": {"type": "TakerPaymentRefunded", "data": {"tx_hex": "00", "tx_hash": "00"}}});
+        let expected = WebhookEvent::SwapRefunded {
+            uuid,
+            swap_type: SwapType::Taker,
+            event_type: "TakerPaymentRefunded".into(),
+        };
+        assert_eq!(Some(expected), swap_webhook_event(uuid, SwapType::Taker, &saved, None));
+
+        let saved = json!({"timestamp": 1, "event": {"type": "NegotiateFailed", "data": {"error": "timeout"}}});
+        let expected = WebhookEvent::SwapFailed {
+            uuid,
+            swap_type: SwapType::Taker,
+            event_type: "NegotiateFailed".into(),
+            error: "timeout".into(),
+        };
+        assert_eq!(Some(expected), swap_webhook_event(uuid, SwapType::Taker, &saved, None));
+
+        let saved =
+            json!({"timestamp": 1, "event": {"type": "MakerPaymentSent", "data": {"tx_hex": "00", "tx_hash": "00"}}});
+        assert_eq!(None, swap_webhook_event(uuid, SwapType::Maker, &saved, None));
+
+        let saved = json!({"timestamp": 1, "event": {"type": "TakerPaymentWaitConfirmStarted"}});
+        assert_eq!(None, swap_webhook_event(uuid, SwapType::Maker, &saved, None));
+    }
+
+    #[test]
+    fn test_webhooks_conf_requires_secret() {
+        let ctx = MmCtxBuilder::new()
+            .with_conf(json!({"webhooks": [{"url": "http://127.0.0.1/hook"}]}))
+            .into_mm_arc();
+        assert!(validate_webhooks_conf(&ctx).is_err());
+
+        let ctx = MmCtxBuilder::new()
+            .with_conf(json!({"webhooks": [{"url": "http://127.0.0.1/hook", "secret": ""}]}))
+            .into_mm_arc();
+        let error = validate_webhooks_conf(&ctx).unwrap_err();
+        assert!(error.contains("must not be empty"));
+
+        let ctx = MmCtxBuilder::new()
+            .with_conf(json!({"webhooks": [{"url": "http://127.0.0.1/hook", "secret": "secret"}]}))
+            .into_mm_arc();
+        assert!(validate_webhooks_conf(&ctx).is_ok());
+    }
+
+    #[test]
+    fn test_webhook_retry_schedule() {
+        assert_eq!(5000, retry_delay_ms(1));
+        assert_eq!(10000, retry_delay_ms(2));
+        assert_eq!(20000, retry_delay_ms(3));
+        assert_eq!(MAX_RETRY_DELAY_MS, retry_delay_ms(11));
+        assert_eq!(MAX_RETRY_DELAY_MS, retry_delay_ms(100));
+
+        let mut delivery = WebhookDelivery {
+            uuid: new_uuid(),
+            url: "http://127.0.0.1".into(),
+            body: "{}".into(),
+            attempts: 0,
+            next_attempt_at: 0,
+        };
+        assert!(delivery.schedule_retry(1000));
+        assert_eq!(1, delivery.attempts);
+        assert_eq!(6000, delivery.next_attempt_at);
+
+        for _ in 1..MAX_DELIVERY_ATTEMPTS - 1 {
+            assert!(delivery.schedule_retry(1000));
+        }
+        assert!(!delivery.schedule_retry(1000));
+    }
+
+    #[test]
+    fn test_webhook_signature() {
+        // https://tools.ietf.org/html/rfc4231#section-4.3
+        let signature = unwrap!(webhook_signature("Jefe", b"what do ya want for nothing?"));
+        assert_eq!(
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            signature
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn test_deliver_webhook_to_local_server() {
+        use std::io::{BufRead, BufReader, Read, Write};
+        use std::net::TcpListener;
+        use std::thread;
+
+        let listener = unwrap!(TcpListener::bind("127.0.0.1:0"));
+        let port = unwrap!(listener.local_addr()).port();
+        let server = thread::spawn(move || {
+            let (stream, _) = unwrap!(listener.accept());
+            let mut reader = BufReader::new(unwrap!(stream.try_clone()));
+            let mut signature = String::new();
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                unwrap!(reader.read_line(&mut line));
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                let mut split = line.splitn(2, ':');
+                let name = split.next().unwrap_or_default().to_lowercase();
+                let value = split.next().unwrap_or_default().trim().to_owned();
+                if name == WEBHOOK_SIGNATURE_HEADER.to_lowercase() {
+                    signature = value;
+                } else if name == "content-length" {
+                    content_length = unwrap!(value.parse());
+                }
+            }
+            let mut body = vec![0; content_length];
+            unwrap!(reader.read_exact(&mut body));
+            let mut stream = stream;
+            unwrap!(stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"));
+            (signature, body)
+        });
+
+        let webhook = WebhookConf {
+            url: format!("http://127.0.0.1:{}/hook", port),
+            secret: "secret".into(),
+        };
+        let delivery = WebhookDelivery {
+            uuid: new_uuid(),
+            url: webhook.url.clone(),
+            body: r#"{"event":{"type":"SwapFinished"}}"#.into(),
+            attempts: 0,
+            next_attempt_at: 0,
+        };
+        unwrap!(block_on(deliver_webhook(&webhook, &delivery)));
+
+        let (signature, body) = unwrap!(server.join());
+        assert_eq!(delivery.body.as_bytes(), &body[..]);
+        assert_eq!(unwrap!(webhook_signature("secret", &body)), signature);
+    }
+}
@@ -40,6 +40,8 @@ use std::str;
 
 #[path = "lp_native_dex.rs"] mod lp_native_dex;
 use self::lp_native_dex::{lp_init, lp_ports};
+use self::rpc::rpc_credentials::rpc_credentials_from_conf;
+#[cfg(feature = "native")] use self::rpc::tls::rpc_tls_config;
 use coins::update_coins_config;
 
 #[path = "database.rs"] pub mod database;
@@ -47,6 +49,7 @@ use coins::update_coins_config;
 
 #[path = "lp_ordermatch.rs"] pub mod lp_ordermatch;
 #[path = "lp_swap.rs"] pub mod lp_swap;
+#[path = "lp_webhooks.rs"] pub mod lp_webhooks;
 #[path = "rpc.rs"] pub mod rpc;
 
 #[cfg(any(test, not(feature = "native")))]
@@ -75,8 +78,11 @@ pub fn lp_main(conf: Json, ctx_cb: &dyn Fn(u32)) -> Result<(), String> {
             return ERR!("rpc_password must not be empty");
         }
     }
+    try_s!(rpc_credentials_from_conf(&conf));
+    #[cfg(feature = "native")]
+    try_s!(rpc_tls_config(&conf));
 
-    if conf["passphrase"].is_string() {
+    if conf["passphrase"].is_string() || conf["keystore"].is_string() || !conf["watch_only"].is_null() {
         let netid = conf["netid"].as_u64().unwrap_or(0) as u16;
         let (_, pubport, _) = try_s!(lp_ports(netid));
         let ctx = MmCtxBuilder::new().with_conf(conf).into_mm_arc();
@@ -123,9 +129,12 @@ fn help() {
             "                     If the field isn't present on the command line then we try loading it from the 'coins' file.\n"
             "  crash          ..  Simulate a crash to check how the crash handling works.\n"
             "  dbdir          ..  MM database path. 'DB' by default.\n"
+            "  disable_show_priv_key ..  `true` to disable the show_priv_key RPC.\n"
             "  gui            ..  The information about GUI app using MM2 instance. Included in swap statuses shared with network.\n"
             "                 ..  It's recommended to put essential info to this field (application name, OS, version, etc).\n"
             "                 ..  e.g. AtomicDEX iOS 1.0.1000.\n"
+            "  keystore       ..  Path to the keystore file with the encrypted passphrase, used if the passphrase is not set.\n"
+            "                     The keystore password is prompted if the MM2_KEYSTORE_PASSWORD env variable is not set.\n"
             "  myipaddr       ..  IP address to bind to for P2P networking.\n"
             "  netid          ..  Subnetwork. Affects ports and keys.\n"
             "  passphrase *   ..  Wallet seed.\n"
@@ -137,8 +146,15 @@ fn help() {
             "  rpcip          ..  IP address to bind to for RPC server. Overrides the 127.0.0.1 default\n"
             "  rpc_password   ..  RPC password used to authorize non-public RPC calls\n"
             "                     MM generates password from passphrase if this field is not set\n"
+            "  rpc_credentials .. Named RPC credentials in addition to the rpc_password, e.g.\n"
+          r#"                     [{"name": "dashboard", "password": "...", "role": "read_only", "rate_limit": 60}]."# "\n"
+            "                     The role is one of `read_only`, `trading` and `admin`, the `allowed_methods` list\n"
+            "                     allows the methods in addition to the role ones, `rate_limit` is the requests per minute.\n"
             "  rpc_local_only ..  MM forbids some RPC requests from not loopback (localhost) IPs as additional security measure.\n"
             "                     Defaults to `true`, set `false` to disable. `Use with caution`.\n"
+            "  rpc_tls        ..  Serve the RPC over TLS, e.g.\n"
+          r#"                     {"cert_path": "rpc.crt", "key_path": "rpc.key", "client_ca_path": "clients_ca.crt"}."# "\n"
+            "                     The PEM encoded client certificate signed by the optional `client_ca_path` is required (mutual TLS).\n"
             "  rpcport        ..  If > 1000 overrides the 7783 default.\n"
             "  i_am_seed      ..  Activate the seed node mode (acting as a relay for mm2 clients).\n"
             "                     Defaults to `false`.\n"
@@ -159,6 +175,7 @@ fn help() {
             "                     Recommended: https://github.com/jl777/coins/blob/master/coins.\n"
             "                     Defaults to `coins`.\n"
             "  MM_LOG         ..  File path. Must end with '.log'. MM will log to this file.\n"
+            "  MM2_KEYSTORE_PASSWORD ..  The password of the keystore file.\n"
             "\n"
             // Generated from https://github.com/KomodoPlatform/developer-docs/tree/sidd.
             // (SHossain, siddhartha-crypto).
@@ -52,6 +52,29 @@ pub fn encode_and_sign<T: Serialize>(message: &T, secret: &[u8; 32]) -> Result<V
     encode_message(&msg)
 }
 
+/// Same as `encode_and_sign`, but the message hash is signed by the `sign` function,
+/// e.g. by the external signer holding the secret of the `pubkey`.
+/// The `sign` function returns the compact `r || s` signature.
+pub fn encode_and_sign_with<T, F>(message: &T, pubkey: &[u8], sign: F) -> Result<Vec<u8>, String>
+where
+    T: Serialize,
+    F: FnOnce(&[u8; 32]) -> Result<[u8; 64], String>,
+{
+    let pubkey = Secp256k1Pubkey::from_slice(pubkey).map_err(|e| format!("Error {} parsing pubkey", e))?;
+    let encoded = encode_message(message).map_err(|e| format!("Error {} encoding message", e))?;
+    let compact = sign(&sha256(&encoded))?;
+    let mut sig = Signature::from_compact(&compact).map_err(|e| format!("Failed to parse signature {}", e))?;
+    // the signatures with the high S are rejected by `decode_signed`
+    sig.normalize_s();
+    let serialized_sig = sig.serialize_compact();
+    let msg = SignedMessageSerdeHelper {
+        pubkey: PublicKey::from(pubkey),
+        signature: &serialized_sig,
+        payload: &encoded,
+    };
+    encode_message(&msg).map_err(|e| format!("Error {} encoding message", e))
+}
+
 pub fn decode_signed<'de, T: de::Deserialize<'de>>(
     encoded: &'de [u8],
 ) -> Result<(T, Signature, PublicKey), rmp_serde::decode::Error> {
@@ -145,3 +168,19 @@ fn signed_message_serde() {
     let (decoded, ..) = decode_signed::<Vec<u8>>(&signed_encoded).unwrap();
     assert_eq!(decoded, initial_msg);
 }
+
+#[test]
+fn signed_with_message_serde() {
+    let secret = SecretKey::from_slice(&[1; 32]).unwrap();
+    let pubkey = Secp256k1Pubkey::from_secret_key(&*SECP_SIGN, &secret);
+    let initial_msg = vec![0u8; 32];
+    let signed_encoded = encode_and_sign_with(&initial_msg, &pubkey.serialize(), |hash| {
+        let sig_hash = SecpMessage::from_slice(hash).unwrap();
+        Ok(SECP_SIGN.sign(&sig_hash, &secret).serialize_compact())
+    })
+    .unwrap();
+
+    let (decoded, _, decoded_pubkey) = decode_signed::<Vec<u8>>(&signed_encoded).unwrap();
+    assert_eq!(decoded, initial_msg);
+    assert_eq!(decoded_pubkey, PublicKey::from(pubkey));
+}
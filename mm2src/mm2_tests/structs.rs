@@ -13,6 +13,8 @@ use uuid::Uuid;
 enum OrderType {
     FillOrKill,
     GoodTillCancelled,
+    GoodTillTime { expires_at: u64 },
+    ImmediateOrCancel,
 }
 
 #[derive(Deserialize)]
@@ -52,6 +54,7 @@ pub struct BuyOrSellRpcResult {
     dest_pub_key: H256Json,
     match_by: MatchBy,
     conf_settings: OrderConfirmationsSettings,
+    allow_partial_fill: bool,
     order_type: OrderType,
 }
 
@@ -70,6 +73,7 @@ pub struct TakerRequest {
     dest_pub_key: H256Json,
     match_by: MatchBy,
     conf_settings: OrderConfirmationsSettings,
+    allow_partial_fill: bool,
 }
 
 #[derive(Deserialize)]
@@ -95,6 +99,7 @@ pub struct TakerConnect {
     method: String,
     sender_pubkey: H256Json,
     dest_pub_key: H256Json,
+    swap_uuid: Uuid,
 }
 
 #[derive(Deserialize)]
@@ -130,6 +135,7 @@ pub struct MakerOrderRpcResult {
     started_swaps: Vec<Uuid>,
     uuid: Uuid,
     conf_settings: Option<OrderConfirmationsSettings>,
+    expires_at: Option<u64>,
 }
 
 #[derive(Deserialize)]
@@ -146,6 +152,9 @@ pub struct TakerOrderRpcResult {
     request: TakerRequest,
     matches: HashMap<Uuid, TakerMatch>,
     order_type: OrderType,
+    available_amount: BigDecimal,
+    filled_amount: BigDecimal,
+    started_swaps: Vec<Uuid>,
 }
 
 #[derive(Deserialize)]
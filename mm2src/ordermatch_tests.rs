@@ -8,7 +8,7 @@ use common::{block_on,
              privkey::key_pair_from_seed};
 use futures::{channel::mpsc, lock::Mutex as AsyncMutex, StreamExt};
 use mm2_libp2p::atomicdex_behaviour::AdexBehaviourCmd;
-use mm2_libp2p::{decode_message, PeerId};
+use mm2_libp2p::{decode_message, encode_and_sign, PeerId};
 use mocktopus::mocking::*;
 use rand::{seq::SliceRandom, thread_rng, Rng};
 use std::collections::HashSet;
@@ -27,6 +27,7 @@ fn test_match_maker_order_and_taker_request() {
         started_swaps: Vec::new(),
         uuid: Uuid::new_v4(),
         conf_settings: None,
+        expires_at: None,
     };
 
     let request = TakerRequest {
@@ -40,6 +41,7 @@ fn test_match_maker_order_and_taker_request() {
         action: TakerAction::Buy,
         match_by: MatchBy::Any,
         conf_settings: None,
+        allow_partial_fill: false,
     };
 
     let actual = maker.match_with_request(&request);
@@ -57,6 +59,7 @@ fn test_match_maker_order_and_taker_request() {
         started_swaps: Vec::new(),
         uuid: Uuid::new_v4(),
         conf_settings: None,
+        expires_at: None,
     };
 
     let request = TakerRequest {
@@ -70,6 +73,7 @@ fn test_match_maker_order_and_taker_request() {
         action: TakerAction::Buy,
         match_by: MatchBy::Any,
         conf_settings: None,
+        allow_partial_fill: false,
     };
 
     let actual = maker.match_with_request(&request);
@@ -87,6 +91,7 @@ fn test_match_maker_order_and_taker_request() {
         started_swaps: Vec::new(),
         uuid: Uuid::new_v4(),
         conf_settings: None,
+        expires_at: None,
     };
 
     let request = TakerRequest {
@@ -100,6 +105,7 @@ fn test_match_maker_order_and_taker_request() {
         action: TakerAction::Buy,
         match_by: MatchBy::Any,
         conf_settings: None,
+        allow_partial_fill: false,
     };
 
     let actual = maker.match_with_request(&request);
@@ -117,6 +123,7 @@ fn test_match_maker_order_and_taker_request() {
         started_swaps: Vec::new(),
         uuid: Uuid::new_v4(),
         conf_settings: None,
+        expires_at: None,
     };
 
     let request = TakerRequest {
@@ -130,6 +137,7 @@ fn test_match_maker_order_and_taker_request() {
         action: TakerAction::Sell,
         match_by: MatchBy::Any,
         conf_settings: None,
+        allow_partial_fill: false,
     };
 
     let actual = maker.match_with_request(&request);
@@ -147,6 +155,7 @@ fn test_match_maker_order_and_taker_request() {
         started_swaps: Vec::new(),
         uuid: Uuid::new_v4(),
         conf_settings: None,
+        expires_at: None,
     };
 
     let request = TakerRequest {
@@ -160,6 +169,7 @@ fn test_match_maker_order_and_taker_request() {
         action: TakerAction::Sell,
         match_by: MatchBy::Any,
         conf_settings: None,
+        allow_partial_fill: false,
     };
 
     let actual = maker.match_with_request(&request);
@@ -177,6 +187,7 @@ fn test_match_maker_order_and_taker_request() {
         started_swaps: Vec::new(),
         uuid: Uuid::new_v4(),
         conf_settings: None,
+        expires_at: None,
     };
 
     let request = TakerRequest {
@@ -190,6 +201,7 @@ fn test_match_maker_order_and_taker_request() {
         action: TakerAction::Sell,
         match_by: MatchBy::Any,
         conf_settings: None,
+        allow_partial_fill: false,
     };
 
     let actual = maker.match_with_request(&request);
@@ -238,6 +250,7 @@ fn test_maker_order_available_amount() {
         started_swaps: Vec::new(),
         uuid: Uuid::new_v4(),
         conf_settings: None,
+        expires_at: None,
     };
     maker.matches.insert(Uuid::new_v4(), MakerMatch {
         request: TakerRequest {
@@ -251,6 +264,7 @@ fn test_maker_order_available_amount() {
             action: TakerAction::Buy,
             match_by: MatchBy::Any,
             conf_settings: None,
+            allow_partial_fill: false,
         },
         reserved: MakerReserved {
             base: "BASE".into(),
@@ -279,6 +293,7 @@ fn test_maker_order_available_amount() {
             action: TakerAction::Buy,
             match_by: MatchBy::Any,
             conf_settings: None,
+            allow_partial_fill: false,
         },
         reserved: MakerReserved {
             base: "BASE".into(),
@@ -316,6 +331,7 @@ fn test_taker_match_reserved() {
         action: TakerAction::Buy,
         match_by: MatchBy::Any,
         conf_settings: None,
+        allow_partial_fill: false,
     };
 
     let order = TakerOrder {
@@ -350,6 +366,7 @@ fn test_taker_match_reserved() {
         action: TakerAction::Sell,
         match_by: MatchBy::Any,
         conf_settings: None,
+        allow_partial_fill: false,
     };
 
     let order = TakerOrder {
@@ -384,6 +401,7 @@ fn test_taker_match_reserved() {
         action: TakerAction::Sell,
         match_by: MatchBy::Any,
         conf_settings: None,
+        allow_partial_fill: false,
     };
 
     let order = TakerOrder {
@@ -418,6 +436,7 @@ fn test_taker_match_reserved() {
         action: TakerAction::Sell,
         match_by: MatchBy::Any,
         conf_settings: None,
+        allow_partial_fill: false,
     };
 
     let order = TakerOrder {
@@ -452,6 +471,7 @@ fn test_taker_match_reserved() {
         action: TakerAction::Buy,
         match_by: MatchBy::Any,
         conf_settings: None,
+        allow_partial_fill: false,
     };
 
     let order = TakerOrder {
@@ -486,6 +506,7 @@ fn test_taker_match_reserved() {
         action: TakerAction::Buy,
         match_by: MatchBy::Any,
         conf_settings: None,
+        allow_partial_fill: false,
     };
 
     let order = TakerOrder {
@@ -520,6 +541,7 @@ fn test_taker_match_reserved() {
         action: TakerAction::Buy,
         match_by: MatchBy::Any,
         conf_settings: None,
+        allow_partial_fill: false,
     };
 
     let order = TakerOrder {
@@ -554,6 +576,7 @@ fn test_taker_match_reserved() {
         action: TakerAction::Buy,
         match_by: MatchBy::Any,
         conf_settings: None,
+        allow_partial_fill: false,
     };
 
     let order = TakerOrder {
@@ -592,6 +615,7 @@ fn test_taker_match_reserved() {
             dest_pub_key: H256Json::default(),
             match_by: MatchBy::Any,
             conf_settings: None,
+            allow_partial_fill: false,
         },
         matches: HashMap::new(),
         order_type: OrderType::GoodTillCancelled,
@@ -625,6 +649,7 @@ fn test_taker_order_cancellable() {
         action: TakerAction::Buy,
         match_by: MatchBy::Any,
         conf_settings: None,
+        allow_partial_fill: false,
     };
 
     let order = TakerOrder {
@@ -647,6 +672,7 @@ fn test_taker_order_cancellable() {
         action: TakerAction::Buy,
         match_by: MatchBy::Any,
         conf_settings: None,
+        allow_partial_fill: false,
     };
 
     let mut order = TakerOrder {
@@ -674,6 +700,7 @@ fn test_taker_order_cancellable() {
             dest_pub_key: H256Json::default(),
             maker_order_uuid: Uuid::new_v4(),
             taker_order_uuid: Uuid::new_v4(),
+            swap_uuid: None,
         },
         connected: None,
     });
@@ -681,6 +708,246 @@ fn test_taker_order_cancellable() {
     assert!(!order.is_cancellable());
 }
 
+fn taker_match_for_test(reserved: MakerReserved, connected: bool) -> TakerMatch {
+    TakerMatch {
+        last_updated: now_ms(),
+        connect: TakerConnect {
+            sender_pubkey: H256Json::default(),
+            dest_pub_key: H256Json::default(),
+            maker_order_uuid: reserved.maker_order_uuid,
+            taker_order_uuid: reserved.taker_order_uuid,
+            swap_uuid: Some(Uuid::new_v4()),
+        },
+        connected: if connected {
+            Some(MakerConnected {
+                sender_pubkey: H256Json::default(),
+                dest_pub_key: H256Json::default(),
+                maker_order_uuid: reserved.maker_order_uuid,
+                taker_order_uuid: reserved.taker_order_uuid,
+                method: "connected".into(),
+            })
+        } else {
+            None
+        },
+        reserved,
+    }
+}
+
+#[test]
+fn test_taker_order_partial_fill() {
+    let uuid = Uuid::new_v4();
+    let request = TakerRequest {
+        base: "BASE".into(),
+        rel: "REL".into(),
+        uuid,
+        dest_pub_key: H256Json::default(),
+        sender_pubkey: H256Json::default(),
+        base_amount: 10.into(),
+        rel_amount: 20.into(),
+        action: TakerAction::Buy,
+        match_by: MatchBy::Any,
+        conf_settings: None,
+        allow_partial_fill: true,
+    };
+
+    let mut order = TakerOrder {
+        request,
+        matches: HashMap::new(),
+        created_at: now_ms(),
+        order_type: OrderType::GoodTillCancelled,
+    };
+
+    let reserved = |base_amount: &'static str, rel_amount: &'static str| MakerReserved {
+        base: "BASE".into(),
+        rel: "REL".into(),
+        base_amount: base_amount.into(),
+        rel_amount: rel_amount.into(),
+        maker_order_uuid: Uuid::new_v4(),
+        taker_order_uuid: uuid,
+        ..MakerReserved::default()
+    };
+
+    let first = reserved("4", "8");
+    assert_eq!(MatchReservedResult::Matched, order.match_reserved(&first));
+    order
+        .matches
+        .insert(first.maker_order_uuid, taker_match_for_test(first.clone(), true));
+    assert_eq!(order.available_amount(), 6.into());
+    assert_eq!(order.filled_amount(), 4.into());
+    assert!(!order.is_completed());
+
+    // the same maker order can't be matched twice
+    assert_eq!(MatchReservedResult::NotMatched, order.match_reserved(&first));
+    // the volume exceeds the available one
+    assert_eq!(
+        MatchReservedResult::NotMatched,
+        order.match_reserved(&reserved("7", "14"))
+    );
+    // the price is worse than the requested one
+    assert_eq!(
+        MatchReservedResult::NotMatched,
+        order.match_reserved(&reserved("6", "13"))
+    );
+
+    let second = reserved("6", "11");
+    assert_eq!(MatchReservedResult::Matched, order.match_reserved(&second));
+    order
+        .matches
+        .insert(second.maker_order_uuid, taker_match_for_test(second.clone(), false));
+    assert_eq!(order.available_amount(), 0.into());
+    assert_eq!(order.filled_amount(), 4.into());
+    assert!(!order.is_cancellable());
+    assert!(!order.is_completed());
+
+    order.matches.get_mut(&second.maker_order_uuid).unwrap().connected =
+        taker_match_for_test(second.clone(), true).connected;
+    assert_eq!(order.filled_amount(), 10.into());
+    assert_eq!(order.started_swaps().len(), 2);
+    assert!(order.is_completed());
+}
+
+#[test]
+fn test_taker_order_without_partial_fill() {
+    let uuid = Uuid::new_v4();
+    let request = TakerRequest {
+        base: "BASE".into(),
+        rel: "REL".into(),
+        uuid,
+        dest_pub_key: H256Json::default(),
+        sender_pubkey: H256Json::default(),
+        base_amount: 10.into(),
+        rel_amount: 10.into(),
+        action: TakerAction::Sell,
+        match_by: MatchBy::Any,
+        conf_settings: None,
+        allow_partial_fill: false,
+    };
+
+    let order = TakerOrder {
+        request,
+        matches: HashMap::new(),
+        created_at: now_ms(),
+        order_type: OrderType::FillOrKill,
+    };
+
+    let reserved = MakerReserved {
+        base: "REL".into(),
+        rel: "BASE".into(),
+        base_amount: 5.into(),
+        rel_amount: 5.into(),
+        maker_order_uuid: Uuid::new_v4(),
+        taker_order_uuid: uuid,
+        ..MakerReserved::default()
+    };
+    assert_eq!(MatchReservedResult::NotMatched, order.match_reserved(&reserved));
+
+    let mut order = order;
+    order.request.allow_partial_fill = true;
+    assert_eq!(MatchReservedResult::Matched, order.match_reserved(&reserved));
+}
+
+#[test]
+fn test_match_maker_order_partially() {
+    let maker = MakerOrder {
+        base: "BASE".into(),
+        rel: "REL".into(),
+        created_at: now_ms(),
+        max_base_vol: 5.into(),
+        min_base_vol: 1.into(),
+        price: 2.into(),
+        matches: HashMap::new(),
+        started_swaps: Vec::new(),
+        uuid: Uuid::new_v4(),
+        conf_settings: None,
+        expires_at: None,
+    };
+
+    let mut request = TakerRequest {
+        base: "BASE".into(),
+        rel: "REL".into(),
+        uuid: Uuid::new_v4(),
+        dest_pub_key: H256Json::default(),
+        sender_pubkey: H256Json::default(),
+        base_amount: 10.into(),
+        rel_amount: 20.into(),
+        action: TakerAction::Buy,
+        match_by: MatchBy::Any,
+        conf_settings: None,
+        allow_partial_fill: false,
+    };
+    assert_eq!(OrderMatchResult::NotMatched, maker.match_with_request(&request));
+
+    request.allow_partial_fill = true;
+    let expected = OrderMatchResult::Matched((5.into(), 10.into()));
+    assert_eq!(expected, maker.match_with_request(&request));
+
+    let request = TakerRequest {
+        base: "REL".into(),
+        rel: "BASE".into(),
+        uuid: Uuid::new_v4(),
+        dest_pub_key: H256Json::default(),
+        sender_pubkey: H256Json::default(),
+        base_amount: 30.into(),
+        rel_amount: 15.into(),
+        action: TakerAction::Sell,
+        match_by: MatchBy::Any,
+        conf_settings: None,
+        allow_partial_fill: true,
+    };
+    let expected = OrderMatchResult::Matched((5.into(), 10.into()));
+    assert_eq!(expected, maker.match_with_request(&request));
+
+    // the available volume is less than the min volume
+    let maker = MakerOrder {
+        min_base_vol: 6.into(),
+        ..maker
+    };
+    assert_eq!(OrderMatchResult::NotMatched, maker.match_with_request(&request));
+}
+
+#[test]
+fn test_partially_filled_taker_order_into_maker() {
+    let uuid = Uuid::new_v4();
+    let request = TakerRequest {
+        base: "BASE".into(),
+        rel: "REL".into(),
+        uuid,
+        dest_pub_key: H256Json::default(),
+        sender_pubkey: H256Json::default(),
+        base_amount: 10.into(),
+        rel_amount: 20.into(),
+        action: TakerAction::Buy,
+        match_by: MatchBy::Any,
+        conf_settings: None,
+        allow_partial_fill: true,
+    };
+    let mut order = TakerOrder {
+        request,
+        matches: HashMap::new(),
+        created_at: now_ms(),
+        order_type: OrderType::GoodTillCancelled,
+    };
+    let reserved = MakerReserved {
+        base: "BASE".into(),
+        rel: "REL".into(),
+        base_amount: 4.into(),
+        rel_amount: 8.into(),
+        maker_order_uuid: Uuid::new_v4(),
+        taker_order_uuid: uuid,
+        ..MakerReserved::default()
+    };
+    order
+        .matches
+        .insert(reserved.maker_order_uuid, taker_match_for_test(reserved, true));
+
+    // the buy order is converted to the sell one of the reversed pair
+    let maker: MakerOrder = order.into();
+    assert_eq!(maker.base, "REL");
+    assert_eq!(maker.rel, "BASE");
+    assert_eq!(maker.max_base_vol, 12.into());
+    assert_eq!(maker.price, "0.5".into());
+}
+
 fn prepare_for_cancel_by(ctx: &MmArc) -> mpsc::Receiver<AdexBehaviourCmd> {
     let (tx, rx) = mpsc::channel(10);
     let p2p_ctx = P2PContext::new(tx);
@@ -701,6 +968,7 @@ fn prepare_for_cancel_by(ctx: &MmArc) -> mpsc::Receiver<AdexBehaviourCmd> {
         price: 0.into(),
         started_swaps: vec![],
         conf_settings: None,
+        expires_at: None,
     });
     maker_orders.insert(Uuid::from_bytes([1; 16]), MakerOrder {
         uuid: Uuid::from_bytes([1; 16]),
@@ -713,6 +981,7 @@ fn prepare_for_cancel_by(ctx: &MmArc) -> mpsc::Receiver<AdexBehaviourCmd> {
         price: 0.into(),
         started_swaps: vec![],
         conf_settings: None,
+        expires_at: None,
     });
     maker_orders.insert(Uuid::from_bytes([2; 16]), MakerOrder {
         uuid: Uuid::from_bytes([2; 16]),
@@ -725,6 +994,7 @@ fn prepare_for_cancel_by(ctx: &MmArc) -> mpsc::Receiver<AdexBehaviourCmd> {
         price: 0.into(),
         started_swaps: vec![],
         conf_settings: None,
+        expires_at: None,
     });
     taker_orders.insert(Uuid::from_bytes([3; 16]), TakerOrder {
         matches: HashMap::new(),
@@ -740,6 +1010,7 @@ fn prepare_for_cancel_by(ctx: &MmArc) -> mpsc::Receiver<AdexBehaviourCmd> {
             sender_pubkey: H256Json::default(),
             match_by: MatchBy::Any,
             conf_settings: None,
+            allow_partial_fill: false,
         },
         order_type: OrderType::GoodTillCancelled,
     });
@@ -822,6 +1093,7 @@ fn test_taker_order_match_by() {
         action: TakerAction::Buy,
         match_by: MatchBy::Orders(not_matching_uuids),
         conf_settings: None,
+        allow_partial_fill: false,
     };
 
     let mut order = TakerOrder {
@@ -885,6 +1157,41 @@ fn lp_connect_start_bob_should_not_be_invoked_if_order_match_already_connected()
     assert!(unsafe { !CONNECT_START_CALLED });
 }
 
+#[test]
+fn lp_connect_start_bob_should_not_be_invoked_if_swap_uuid_is_taken() {
+    let order_json = r#"{"max_base_vol":"1","max_base_vol_rat":[[1,[1]],[1,[1]]],"min_base_vol":"0","min_base_vol_rat":[[0,[]],[1,[1]]],"price":"1","price_rat":[[1,[1]],[1,[1]]],"created_at":1589265312093,"base":"ETH","rel":"JST","matches":{"2f9afe84-7a89-4194-8947-45fba563118f":{"request":{"base":"ETH","rel":"JST","base_amount":"0.1","base_amount_rat":[[1,[1]],[1,[10]]],"rel_amount":"0.2","rel_amount_rat":[[1,[1]],[1,[5]]],"action":"Buy","uuid":"2f9afe84-7a89-4194-8947-45fba563118f","method":"request","sender_pubkey":"031d4256c4bc9f99ac88bf3dba21773132281f65f9bf23a59928bce08961e2f3","dest_pub_key":"0000000000000000000000000000000000000000000000000000000000000000","match_by":{"type":"Any"}},"reserved":{"base":"ETH","rel":"JST","base_amount":"0.1","base_amount_rat":[[1,[1]],[1,[10]]],"rel_amount":"0.1","rel_amount_rat":[[1,[1]],[1,[10]]],"taker_order_uuid":"2f9afe84-7a89-4194-8947-45fba563118f","maker_order_uuid":"5f6516ea-ccaa-453a-9e37-e1c2c0d527e3","method":"reserved","sender_pubkey":"c6a78589e18b482aea046975e6d0acbdea7bf7dbf04d9d5bd67fda917815e3ed","dest_pub_key":"031d4256c4bc9f99ac88bf3dba21773132281f65f9bf23a59928bce08961e2f3"},"connect":null,"connected":null,"last_updated":1589265314408}},"started_swaps":["7c9d6f5e-4b1c-4d8e-9f3a-2b6e8d1c5a47"],"uuid":"5f6516ea-ccaa-453a-9e37-e1c2c0d527e3"}"#;
+    let maker_order: MakerOrder = json::from_str(order_json).unwrap();
+    let ctx = MmCtxBuilder::default()
+        .with_secp256k1_key_pair(
+            key_pair_from_seed("also shoot benefit prefer juice shell elder veteran woman mimic image kidney").unwrap(),
+        )
+        .into_mm_arc();
+    let ordermatch_ctx = OrdermatchContext::from_ctx(&ctx).unwrap();
+    block_on(ordermatch_ctx.my_maker_orders.lock()).insert(maker_order.uuid, maker_order);
+
+    static mut CONNECT_START_CALLED: bool = false;
+    lp_connect_start_bob.mock_safe(|_, _, _| {
+        MockResult::Return(unsafe {
+            CONNECT_START_CALLED = true;
+        })
+    });
+
+    // the taker reuses the uuid of the swap started by the previous match
+    let connect: TakerConnect = json::from_str(r#"{"taker_order_uuid":"2f9afe84-7a89-4194-8947-45fba563118f","maker_order_uuid":"5f6516ea-ccaa-453a-9e37-e1c2c0d527e3","method":"connect","sender_pubkey":"031d4256c4bc9f99ac88bf3dba21773132281f65f9bf23a59928bce08961e2f3","dest_pub_key":"c6a78589e18b482aea046975e6d0acbdea7bf7dbf04d9d5bd67fda917815e3ed","swap_uuid":"7c9d6f5e-4b1c-4d8e-9f3a-2b6e8d1c5a47"}"#).unwrap();
+    block_on(process_taker_connect(
+        ctx.clone(),
+        connect.sender_pubkey.clone(),
+        connect,
+    ));
+    assert!(unsafe { !CONNECT_START_CALLED });
+
+    let maker_order_uuid: Uuid = "5f6516ea-ccaa-453a-9e37-e1c2c0d527e3".parse().unwrap();
+    let taker_order_uuid: Uuid = "2f9afe84-7a89-4194-8947-45fba563118f".parse().unwrap();
+    let maker_orders = block_on(ordermatch_ctx.my_maker_orders.lock());
+    let order_match = &maker_orders[&maker_order_uuid].matches[&taker_order_uuid];
+    assert!(order_match.connect.is_none());
+}
+
 #[test]
 fn should_process_request_only_once() {
     let order_json = r#"{"max_base_vol":"1","max_base_vol_rat":[[1,[1]],[1,[1]]],"min_base_vol":"0","min_base_vol_rat":[[0,[]],[1,[1]]],"price":"1","price_rat":[[1,[1]],[1,[1]]],"created_at":1589265312093,"base":"ETH","rel":"JST","matches":{"2f9afe84-7a89-4194-8947-45fba563118f":{"request":{"base":"ETH","rel":"JST","base_amount":"0.1","base_amount_rat":[[1,[1]],[1,[10]]],"rel_amount":"0.2","rel_amount_rat":[[1,[1]],[1,[5]]],"action":"Buy","uuid":"2f9afe84-7a89-4194-8947-45fba563118f","method":"request","sender_pubkey":"031d4256c4bc9f99ac88bf3dba21773132281f65f9bf23a59928bce08961e2f3","dest_pub_key":"0000000000000000000000000000000000000000000000000000000000000000","match_by":{"type":"Any"}},"reserved":{"base":"ETH","rel":"JST","base_amount":"0.1","base_amount_rat":[[1,[1]],[1,[10]]],"rel_amount":"0.1","rel_amount_rat":[[1,[1]],[1,[10]]],"taker_order_uuid":"2f9afe84-7a89-4194-8947-45fba563118f","maker_order_uuid":"5f6516ea-ccaa-453a-9e37-e1c2c0d527e3","method":"reserved","sender_pubkey":"c6a78589e18b482aea046975e6d0acbdea7bf7dbf04d9d5bd67fda917815e3ed","dest_pub_key":"031d4256c4bc9f99ac88bf3dba21773132281f65f9bf23a59928bce08961e2f3"},"connect":{"taker_order_uuid":"2f9afe84-7a89-4194-8947-45fba563118f","maker_order_uuid":"5f6516ea-ccaa-453a-9e37-e1c2c0d527e3","method":"connect","sender_pubkey":"031d4256c4bc9f99ac88bf3dba21773132281f65f9bf23a59928bce08961e2f3","dest_pub_key":"c6a78589e18b482aea046975e6d0acbdea7bf7dbf04d9d5bd67fda917815e3ed"},"connected":{"taker_order_uuid":"2f9afe84-7a89-4194-8947-45fba563118f","maker_order_uuid":"5f6516ea-ccaa-453a-9e37-e1c2c0d527e3","method":"connected","sender_pubkey":"c6a78589e18b482aea046975e6d0acbdea7bf7dbf04d9d5bd67fda917815e3ed","dest_pub_key":"031d4256c4bc9f99ac88bf3dba21773132281f65f9bf23a59928bce08961e2f3"},"last_updated":1589265314408}},"started_swaps":["2f9afe84-7a89-4194-8947-45fba563118f"],"uuid":"5f6516ea-ccaa-453a-9e37-e1c2c0d527e3"}"#;
@@ -1425,6 +1732,100 @@ fn test_process_get_orderbook_request_limit() {
     assert!(err.contains("Orderbook too large"));
 }
 
+#[test]
+fn test_process_best_orders_request() {
+    let (ctx, _pubkey, _secret) = make_ctx_for_tests();
+    let (pubkey1, secret1) = pubkey_and_secret_for_test("passphrase-1");
+    let (pubkey2, secret2) = pubkey_and_secret_for_test("passphrase-2");
+
+    let ordermatch_ctx = Arc::new(OrdermatchContext::default());
+    let ordermatch_ctx_clone = ordermatch_ctx.clone();
+    OrdermatchContext::from_ctx.mock_safe(move |_| MockResult::Return(Ok(ordermatch_ctx_clone.clone())));
+
+    let mut orderbook = block_on(ordermatch_ctx.orderbook.lock());
+    let rick_morty = make_random_orders(pubkey1.clone(), &secret1, "RICK".into(), "MORTY".into(), 30);
+    let rick_eth = make_random_orders(pubkey2.clone(), &secret2, "RICK".into(), "ETH".into(), 5);
+    let morty_rick = make_random_orders(pubkey2, &secret2, "MORTY".into(), "RICK".into(), 5);
+    for order in rick_morty.iter().chain(rick_eth.iter()).chain(morty_rick.iter()) {
+        orderbook.insert_or_update_order_update_trie(order.clone());
+    }
+    // avoid dead lock on orderbook as process_best_orders_request also acquires it
+    drop(orderbook);
+
+    // the max volume of the random orders is 1
+    let encoded = block_on(process_best_orders_request(
+        ctx.clone(),
+        "RICK".into(),
+        BestOrdersAction::Buy,
+        BigRational::new(1.into(), 2.into()),
+    ))
+    .unwrap()
+    .unwrap();
+    let best_orders = decode_message::<BestOrdersRes>(&encoded).unwrap().orders;
+    assert_eq!(best_orders.len(), 2);
+
+    let best_rick_morty = &best_orders["MORTY"];
+    assert_eq!(best_rick_morty.len(), MAX_BEST_ORDERS_PER_COIN);
+    let mut expected_prices: Vec<_> = rick_morty.iter().map(|order| order.price.clone()).collect();
+    expected_prices.sort();
+    let actual_prices: Vec<_> = best_rick_morty.iter().map(|order| order.price.clone()).collect();
+    assert_eq!(actual_prices, expected_prices[..MAX_BEST_ORDERS_PER_COIN]);
+    assert_eq!(best_orders["ETH"].len(), 5);
+
+    // the volume exceeds the max volume of the orders
+    let encoded = block_on(process_best_orders_request(
+        ctx.clone(),
+        "RICK".into(),
+        BestOrdersAction::Buy,
+        BigRational::from_integer(2.into()),
+    ))
+    .unwrap()
+    .unwrap();
+    assert!(decode_message::<BestOrdersRes>(&encoded).unwrap().orders.is_empty());
+
+    // the MORTY/RICK orders buy RICK, the volume in RICK is converted to MORTY by the order price
+    let encoded = block_on(process_best_orders_request(
+        ctx,
+        "RICK".into(),
+        BestOrdersAction::Sell,
+        BigRational::new(1.into(), 1000.into()),
+    ))
+    .unwrap()
+    .unwrap();
+    let best_orders = decode_message::<BestOrdersRes>(&encoded).unwrap().orders;
+    assert_eq!(best_orders.len(), 1);
+    assert_eq!(best_orders["MORTY"].len(), 5);
+}
+
+#[test]
+fn test_filter_relay_best_orders() {
+    let (pubkey, secret) = pubkey_and_secret_for_test("passphrase-1");
+    let mut rick_morty = make_random_orders(pubkey.clone(), &secret, "RICK".into(), "MORTY".into(), 5);
+    rick_morty[0].price = BigRational::from_integer(0.into());
+    rick_morty[1].max_volume = BigRational::from_integer(0.into());
+    rick_morty[2].min_volume = BigRational::from_integer((-1).into());
+    rick_morty[3].min_volume = BigRational::new(3.into(), 4.into());
+    let valid_uuid = rick_morty[4].uuid;
+    // the orders buying RICK don't fit the RICK buy request
+    let morty_rick = make_random_orders(pubkey.clone(), &secret, "MORTY".into(), "RICK".into(), 3);
+    // the orders listed under the other coin of an unrelated pair
+    let morty_eth = make_random_orders(pubkey, &secret, "MORTY".into(), "ETH".into(), 3);
+
+    let mut orders = HashMap::new();
+    orders.insert("MORTY".to_owned(), rick_morty.into_iter().chain(morty_rick).collect());
+    orders.insert("ETH".to_owned(), morty_eth);
+
+    let filtered = filter_relay_best_orders(
+        orders,
+        "RICK",
+        BestOrdersAction::Buy,
+        &BigRational::new(1.into(), 2.into()),
+    );
+    assert_eq!(filtered.len(), 1);
+    let uuids: Vec<_> = filtered["MORTY"].iter().map(|order| order.uuid).collect();
+    assert_eq!(uuids, vec![valid_uuid]);
+}
+
 #[test]
 fn test_request_and_fill_orderbook() {
     const PUBKEYS_NUMBER: usize = 3;
@@ -2321,3 +2722,166 @@ fn test_remove_and_purge_pubkey_pair_orders() {
     remove_and_purge_pubkey_pair_orders(&mut orderbook, &pubkey, &rick_morty_pair);
     check_if_orderbook_contains_only(&orderbook, &pubkey, &rick_kmd_orders);
 }
+
+#[test]
+fn test_maker_order_expiration() {
+    let now = now_ms() / 1000;
+    let maker_order = MakerOrderBuilder::default()
+        .with_base_coin("BASE".into())
+        .with_rel_coin("REL".into())
+        .with_max_base_vol(10.into())
+        .with_price(1.into())
+        .with_expires_at(Some(now + 60))
+        .build_unchecked();
+    let taker_request = TakerRequestBuilder::default()
+        .with_base_coin("BASE".into())
+        .with_rel_coin("REL".into())
+        .with_base_amount(1.into())
+        .with_rel_amount(1.into())
+        .with_action(TakerAction::Buy)
+        .build_unchecked();
+
+    assert!(!maker_order.is_expired());
+    let expected = OrderMatchResult::Matched((1.into(), 1.into()));
+    assert_eq!(expected, maker_order.match_with_request(&taker_request));
+
+    let maker_order = MakerOrder {
+        expires_at: Some(now - 1),
+        ..maker_order
+    };
+    assert!(maker_order.is_expired());
+    assert_eq!(
+        OrderMatchResult::NotMatched,
+        maker_order.match_with_request(&taker_request)
+    );
+
+    let conf_settings = OrderConfirmationsSettings {
+        base_confs: 1,
+        base_nota: false,
+        rel_confs: 1,
+        rel_nota: false,
+    };
+    let build_result = MakerOrderBuilder::default()
+        .with_base_coin("BASE".into())
+        .with_rel_coin("REL".into())
+        .with_max_base_vol(10.into())
+        .with_min_base_vol(1.into())
+        .with_price(1.into())
+        .with_conf_settings(conf_settings)
+        .with_expires_at(Some(now))
+        .build();
+    match build_result {
+        Err(MakerOrderBuildError::ExpiresAtInPast { .. }) => (),
+        Err(e) => panic!("Unexpected error: {}", e),
+        Ok(_) => panic!("The expired order must not be built"),
+    }
+}
+
+#[test]
+fn test_order_type_time_in_force() {
+    let now = now_ms() / 1000;
+    let gtt = OrderType::GoodTillTime { expires_at: now + 60 };
+    let expired_gtt = OrderType::GoodTillTime { expires_at: now - 1 };
+
+    assert!(!OrderType::FillOrKill.allows_partial_fill());
+    assert!(OrderType::GoodTillCancelled.allows_partial_fill());
+    assert!(gtt.allows_partial_fill());
+    assert!(OrderType::ImmediateOrCancel.allows_partial_fill());
+
+    assert!(!OrderType::FillOrKill.converts_to_maker());
+    assert!(OrderType::GoodTillCancelled.converts_to_maker());
+    assert!(gtt.converts_to_maker());
+    assert!(!expired_gtt.converts_to_maker());
+    assert!(!OrderType::ImmediateOrCancel.converts_to_maker());
+
+    let json = json::to_value(&gtt).unwrap();
+    assert_eq!(json, json!({"type": "GoodTillTime", "data": {"expires_at": now + 60}}));
+    let deserialized: OrderType = json::from_value(json!({"type": "ImmediateOrCancel"})).unwrap();
+    assert_eq!(deserialized, OrderType::ImmediateOrCancel);
+
+    // the GTT taker order is converted to the maker one that expires at the same time
+    let request = TakerRequestBuilder::default()
+        .with_base_coin("BASE".into())
+        .with_rel_coin("REL".into())
+        .with_base_amount(1.into())
+        .with_rel_amount(1.into())
+        .with_action(TakerAction::Sell)
+        .build_unchecked();
+    let taker_order = TakerOrder {
+        created_at: now_ms(),
+        request,
+        matches: HashMap::new(),
+        order_type: gtt,
+    };
+    let maker_order: MakerOrder = taker_order.into();
+    assert_eq!(maker_order.expires_at, Some(now + 60));
+}
+
+fn maker_match_for_test(base_amount: MmNumber, connected: bool) -> MakerMatch {
+    let reserved = MakerReserved {
+        base: "BASE".into(),
+        rel: "REL".into(),
+        base_amount: base_amount.clone(),
+        rel_amount: base_amount,
+        ..MakerReserved::default()
+    };
+    let connected = if connected {
+        Some(MakerConnected {
+            sender_pubkey: H256Json::default(),
+            dest_pub_key: H256Json::default(),
+            maker_order_uuid: reserved.maker_order_uuid,
+            taker_order_uuid: reserved.taker_order_uuid,
+            method: "connected".into(),
+        })
+    } else {
+        None
+    };
+    MakerMatch {
+        request: TakerRequestBuilder::default().build_unchecked(),
+        reserved,
+        connect: None,
+        connected,
+        last_updated: now_ms(),
+    }
+}
+
+#[test]
+fn test_maker_order_apply_update() {
+    let mut maker = MakerOrderBuilder::default()
+        .with_base_coin("BASE".into())
+        .with_rel_coin("REL".into())
+        .with_max_base_vol(10.into())
+        .with_min_base_vol(1.into())
+        .with_price(1.into())
+        .build_unchecked();
+    maker
+        .matches
+        .insert(Uuid::new_v4(), maker_match_for_test(3.into(), true));
+    maker
+        .matches
+        .insert(Uuid::new_v4(), maker_match_for_test(2.into(), false));
+    assert_eq!(maker.started_swaps_base_amount(), 3.into());
+    assert_eq!(maker.available_amount(), 5.into());
+
+    // the available volume can't be less than the min volume
+    let err = maker.apply_update(1.into(), 5.into(), 1.into()).unwrap_err();
+    assert!(err.contains("is below min base vol"), "{}", err);
+    let err = maker
+        .apply_update("0.000000001".into(), 10.into(), 1.into())
+        .unwrap_err();
+    assert!(err.contains("Price"), "{}", err);
+    assert_eq!(maker.price, 1.into());
+    assert_eq!(maker.max_base_vol, 10.into());
+
+    let updated_msg = maker.apply_update(2.into(), 8.into(), 1.into()).unwrap();
+    assert_eq!(updated_msg.uuid(), maker.uuid);
+    assert_eq!(updated_msg.new_price(), Some(2.into()));
+    assert_eq!(updated_msg.new_max_volume(), Some(3.into()));
+    assert_eq!(updated_msg.new_min_volume(), None);
+    assert_eq!(maker.price, 2.into());
+    assert_eq!(maker.max_base_vol, 8.into());
+    assert_eq!(maker.available_amount(), 3.into());
+    // the matches are kept intact
+    assert_eq!(maker.matches.len(), 2);
+    assert_eq!(maker.started_swaps_base_amount(), 3.into());
+}
@@ -19,9 +19,10 @@
 #![cfg_attr(not(feature = "native"), allow(unused_imports))]
 #![cfg_attr(not(feature = "native"), allow(dead_code))]
 
-use coins::{convert_address, convert_utxo_address, get_enabled_coins, get_trade_fee, kmd_rewards_info, my_tx_history,
-            send_raw_transaction, set_required_confirmations, set_requires_notarization, show_priv_key,
-            validate_address, withdraw};
+use coins::{convert_address, convert_utxo_address, finalize_psbt, get_enabled_coins, get_trade_fee,
+            hd_account_balance, hd_receive_addresses, kmd_rewards_info, my_tx_history, send_raw_transaction,
+            set_required_confirmations, set_requires_notarization, show_priv_key, validate_address, withdraw,
+            withdraw_batch};
 use common::mm_ctx::MmArc;
 #[cfg(feature = "native")] use common::wio::{CORE, CPUPOOL};
 use common::{err_to_rpc_json_string, err_tp_rpc_json, HyRes};
@@ -35,14 +36,25 @@ use serde_json::{self as json, Value as Json};
 use std::future::Future as Future03;
 use std::net::SocketAddr;
 
-use crate::mm2::lp_ordermatch::{buy, cancel_all_orders, cancel_order, my_orders, order_status, orderbook, sell,
-                                set_price};
-use crate::mm2::lp_swap::{active_swaps_rpc, all_swaps_uuids_by_filter, coins_needed_for_kick_start, import_swaps,
-                          list_banned_pubkeys, max_taker_vol, my_recent_swaps, my_swap_status, recover_funds_of_swap,
-                          stats_swap_status, trade_preimage, unban_pubkeys};
+use crate::mm2::lp_ordermatch::orderbook_depth::orderbook_depth;
+use crate::mm2::lp_ordermatch::simple_market_maker::{simple_market_maker_bot_status, start_simple_market_maker_bot,
+                                                     stop_simple_market_maker_bot};
+use crate::mm2::lp_ordermatch::{best_orders, buy, cancel_all_orders, cancel_order, my_orders, order_status, orderbook,
+                                sell, set_price, update_maker_order};
+use crate::mm2::lp_swap::{active_swaps_rpc, all_swaps_uuids_by_filter, bump_swap_payment_fee, bump_swap_spend_fee,
+                          coins_needed_for_kick_start, import_swaps, list_banned_pubkeys, max_taker_vol,
+                          my_recent_swaps, my_swap_status, ohlcv, recover_funds_of_swap, stats_swap_status,
+                          trade_history, trade_preimage, unban_pubkeys};
 
+#[path = "rpc/event_stream.rs"] pub mod event_stream;
+#[path = "rpc/jsonrpc.rs"] pub mod jsonrpc;
 #[path = "rpc/lp_commands.rs"] pub mod lp_commands;
+#[path = "rpc/rpc_credentials.rs"] pub mod rpc_credentials;
+#[cfg(feature = "native")]
+#[path = "rpc/tls.rs"]
+pub mod tls;
 use self::lp_commands::*;
+use self::rpc_credentials::authorize_credential;
 use hyper::Body;
 
 /// Lists the RPC method not requiring the "userpass" authentication.  
@@ -77,17 +89,26 @@ macro_rules! unwrap_or_err_response {
     };
 }
 
-fn auth(json: &Json, ctx: &MmArc) -> Result<(), &'static str> {
-    if !PUBLIC_METHODS.contains(&json["method"].as_str()) {
-        if !json["userpass"].is_string() {
-            return Err("Userpass is not set!");
-        }
+/// Authorizes the non-public method call either by the `rpc_password` or by one of the `rpc_credentials`.
+/// Every request is authorized right before the `dispatcher` call.
+fn auth(json: &Json, ctx: &MmArc, client: &SocketAddr) -> Result<(), String> {
+    let method = json["method"].as_str();
+    if PUBLIC_METHODS.contains(&method) {
+        return Ok(());
+    }
+    let method = method.unwrap_or_default();
 
-        if json["userpass"] != ctx.conf["rpc_password"] {
-            return Err("Userpass is invalid!");
-        }
+    let userpass = match json["userpass"].as_str() {
+        Some(userpass) => userpass,
+        None => {
+            log!("RPC auth failed] Userpass of the " (method) " call from " (client) " is not set");
+            return ERR!("Userpass is not set!");
+        },
+    };
+    if ctx.conf["rpc_password"].as_str() == Some(userpass) {
+        return Ok(());
     }
-    Ok(())
+    authorize_credential(ctx, userpass, method, client)
 }
 
 /// Result of `fn dispatcher`.
@@ -120,15 +141,22 @@ pub fn dispatcher(req: Json, ctx: MmArc) -> DispatcherRes {
         // "autoprice" => lp_autoprice (ctx, req),
         "active_swaps" => hyres(active_swaps_rpc(ctx, req)),
         "all_swaps_uuids_by_filter" => all_swaps_uuids_by_filter(ctx, req),
+        "best_orders" => hyres(best_orders(ctx, req)),
+        "bump_swap_payment_fee" => hyres(bump_swap_payment_fee(ctx, req)),
+        "bump_swap_spend_fee" => hyres(bump_swap_spend_fee(ctx, req)),
         "buy" => hyres(buy(ctx, req)),
         "cancel_all_orders" => hyres(cancel_all_orders(ctx, req)),
         "cancel_order" => hyres(cancel_order(ctx, req)),
+        "change_keystore_password" => hyres(change_keystore_password(ctx, req)),
         "coins_needed_for_kick_start" => hyres(coins_needed_for_kick_start(ctx)),
         "convertaddress" => hyres(convert_address(ctx, req)),
         "convert_utxo_address" => hyres(convert_utxo_address(ctx, req)),
+        "create_keystore" => hyres(create_keystore(ctx, req)),
         "disable_coin" => hyres(disable_coin(ctx, req)),
         "electrum" => hyres(electrum(ctx, req)),
         "enable" => hyres(enable(ctx, req)),
+        "export_keystore" => hyres(export_keystore(ctx)),
+        "finalize_psbt" => hyres(finalize_psbt(ctx, req)),
         "get_enabled_coins" => hyres(get_enabled_coins(ctx)),
         "get_gossip_mesh" => hyres(get_gossip_mesh(ctx)),
         "get_gossip_peer_topics" => hyres(get_gossip_peer_topics(ctx)),
@@ -138,6 +166,8 @@ pub fn dispatcher(req: Json, ctx: MmArc) -> DispatcherRes {
         "get_relay_mesh" => hyres(get_relay_mesh(ctx)),
         "get_trade_fee" => hyres(get_trade_fee(ctx, req)),
         // "fundvalue" => lp_fundvalue (ctx, req, false),
+        "hd_account_balance" => hyres(hd_account_balance(ctx, req)),
+        "hd_receive_addresses" => hyres(hd_receive_addresses(ctx, req)),
         "help" => help(),
         "import_swaps" => {
             #[cfg(feature = "native")]
@@ -159,8 +189,10 @@ pub fn dispatcher(req: Json, ctx: MmArc) -> DispatcherRes {
         "my_recent_swaps" => my_recent_swaps(ctx, req),
         "my_swap_status" => my_swap_status(ctx, req),
         "my_tx_history" => my_tx_history(ctx, req),
+        "ohlcv" => hyres(ohlcv(ctx, req)),
         "order_status" => hyres(order_status(ctx, req)),
         "orderbook" => hyres(orderbook(ctx, req)),
+        "orderbook_depth" => hyres(orderbook_depth(ctx, req)),
         "sim_panic" => hyres(sim_panic(req)),
         "recover_funds_of_swap" => {
             #[cfg(feature = "native")]
@@ -179,13 +211,19 @@ pub fn dispatcher(req: Json, ctx: MmArc) -> DispatcherRes {
         "set_required_confirmations" => hyres(set_required_confirmations(ctx, req)),
         "set_requires_notarization" => hyres(set_requires_notarization(ctx, req)),
         "setprice" => hyres(set_price(ctx, req)),
+        "simple_market_maker_bot_status" => hyres(simple_market_maker_bot_status(ctx, req)),
+        "start_simple_market_maker_bot" => hyres(start_simple_market_maker_bot(ctx, req)),
         "stats_swap_status" => stats_swap_status(ctx, req),
         "stop" => stop(ctx),
+        "stop_simple_market_maker_bot" => hyres(stop_simple_market_maker_bot(ctx, req)),
+        "trade_history" => hyres(trade_history(ctx, req)),
         "trade_preimage" => hyres(trade_preimage(ctx, req)),
         "unban_pubkeys" => hyres(unban_pubkeys(ctx, req)),
+        "update_maker_order" => hyres(update_maker_order(ctx, req)),
         "validateaddress" => hyres(validate_address(ctx, req)),
         "version" => version(),
         "withdraw" => hyres(withdraw(ctx, req)),
+        "withdraw_batch" => hyres(withdraw_batch(ctx, req)),
         _ => return DispatcherRes::NoMatch(req),
     })
 }
@@ -235,7 +273,7 @@ async fn process_single_request(ctx: MmArc, req: Json, client: SocketAddr) -> Re
     if local_only && !client.ip().is_loopback() && !PUBLIC_METHODS.contains(&req["method"].as_str()) {
         return ERR!("Selected method can be called from localhost only!");
     }
-    try_s!(auth(&req, &ctx));
+    try_s!(auth(&req, &ctx, &client));
 
     let handler = match dispatcher(req, ctx.clone()) {
         DispatcherRes::Match(handler) => handler,
@@ -267,6 +305,18 @@ async fn rpc_service(req: Request<Body>, ctx_h: u32, client: SocketAddr) -> Resp
         None => HeaderValue::from_static("http://localhost:3000"),
     };
 
+    if req.method() == Method::POST && req.uri().path() == event_stream::EVENT_STREAM_PATH {
+        let mut response = event_stream::event_stream_service(ctx, req, client).await;
+        response.headers_mut().insert(ACCESS_CONTROL_ALLOW_ORIGIN, rpc_cors);
+        return response;
+    }
+
+    if req.method() == Method::POST && req.uri().path() == jsonrpc::JSON_RPC_PATH {
+        let mut response = jsonrpc::json_rpc_service(ctx, req, client).await;
+        response.headers_mut().insert(ACCESS_CONTROL_ALLOW_ORIGIN, rpc_cors);
+        return response;
+    }
+
     // Convert the native Hyper stream into a portable stream of `Bytes`.
     let (req, req_body) = req.into_parts();
     let (mut parts, body) = match process_rpc_request(ctx, req, req_body, client).await {
@@ -286,9 +336,23 @@ async fn rpc_service(req: Request<Body>, ctx_h: u32, client: SocketAddr) -> Resp
 
 #[cfg(feature = "native")]
 pub extern "C" fn spawn_rpc(ctx_h: u32) {
+    use common::executor::Timer;
+    use futures::future::{select, BoxFuture, Either};
+    use futures::StreamExt;
     use hyper::server::conn::AddrStream;
     use hyper::service::{make_service_fn, service_fn};
     use std::convert::Infallible;
+    use tokio::net::TcpStream;
+    use tokio_rustls::server::TlsStream;
+    use tokio_rustls::TlsAcceptor;
+
+    /// The max number of the TLS handshakes processed concurrently.
+    const TLS_HANDSHAKES_LIMIT: usize = 64;
+    /// The connections not completing the TLS handshake in time are dropped, so they can't block the listener.
+    const TLS_HANDSHAKE_TIMEOUT: f64 = 10.;
+    /// The pause after a failed accept, so a persistent error (e.g. the file descriptors exhaustion)
+    /// doesn't turn the listener into a busy loop flooding the log.
+    const ACCEPT_ERROR_PAUSE: f64 = 0.1;
 
     // NB: We need to manually handle the incoming connections in order to get the remote IP address,
     // cf. https://github.com/hyperium/hyper/issues/1410#issuecomment-419510220.
@@ -299,18 +363,9 @@ pub extern "C" fn spawn_rpc(ctx_h: u32) {
     let ctx = unwrap!(MmArc::from_ffi_handle(ctx_h), "No context");
 
     let rpc_ip_port = unwrap!(ctx.rpc_ip_port());
+    // the config is validated by `lp_main` already
+    let tls_config = unwrap!(tls::rpc_tls_config(&ctx.conf), "Invalid rpc_tls config");
     CORE.0.enter(|| {
-        let server = unwrap!(Server::try_bind(&rpc_ip_port), "Can't bind on {}", rpc_ip_port);
-        let make_svc = make_service_fn(move |socket: &AddrStream| {
-            let remote_addr = socket.remote_addr();
-            async move {
-                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| async move {
-                    let res = rpc_service(req, ctx_h, remote_addr).await;
-                    Ok::<_, Infallible>(res)
-                }))
-            }
-        });
-
         let (shutdown_tx, shutdown_rx) = futures::channel::oneshot::channel::<()>();
         let mut shutdown_tx = Some(shutdown_tx);
         ctx.on_stop(Box::new(move || {
@@ -324,11 +379,87 @@ pub extern "C" fn spawn_rpc(ctx_h: u32) {
                 ERR!("on_stop callback called twice!")
             }
         }));
+        let shutdown_signal = shutdown_rx.then(|_| futures::future::ready(()));
 
-        let server = server
-            .http1_half_close(false)
-            .serve(make_svc)
-            .with_graceful_shutdown(shutdown_rx.then(|_| futures::future::ready(())));
+        let server: BoxFuture<'static, Result<(), hyper::Error>> = match tls_config {
+            Some(tls_config) => {
+                let listener = unwrap!(
+                    std::net::TcpListener::bind(&rpc_ip_port),
+                    "Can't bind on {}",
+                    rpc_ip_port
+                );
+                unwrap!(listener.set_nonblocking(true));
+                let listener = unwrap!(tokio::net::TcpListener::from_std(listener));
+                let acceptor = TlsAcceptor::from(tls_config);
+                let incoming = listener
+                    .filter_map(|stream| async move {
+                        match stream {
+                            Ok(stream) => Some(stream),
+                            Err(e) => {
+                                log!("RPC TCP accept failed: "(e));
+                                Timer::sleep(ACCEPT_ERROR_PAUSE).await;
+                                None
+                            },
+                        }
+                    })
+                    .map(move |stream| {
+                        let handshake = acceptor.accept(stream);
+                        async move {
+                            match select(Box::pin(handshake), Timer::sleep(TLS_HANDSHAKE_TIMEOUT)).await {
+                                Either::Left((stream, _timer)) => stream.map_err(|e| ERRL!("{}", e)),
+                                Either::Right(_) => ERR!("TLS handshake timed out"),
+                            }
+                        }
+                    })
+                    .buffer_unordered(TLS_HANDSHAKES_LIMIT)
+                    .filter_map(|stream| {
+                        futures::future::ready(match stream {
+                            Ok(stream) => Some(Ok::<_, std::io::Error>(stream)),
+                            Err(e) => {
+                                log!("RPC TLS handshake failed: "(e));
+                                None
+                            },
+                        })
+                    });
+
+                let make_svc = make_service_fn(move |stream: &TlsStream<TcpStream>| {
+                    let remote_addr = stream
+                        .get_ref()
+                        .0
+                        .peer_addr()
+                        .unwrap_or_else(|_| SocketAddr::from(([0, 0, 0, 0], 0)));
+                    async move {
+                        Ok::<_, Infallible>(service_fn(move |req: Request<Body>| async move {
+                            let res = rpc_service(req, ctx_h, remote_addr).await;
+                            Ok::<_, Infallible>(res)
+                        }))
+                    }
+                });
+
+                Server::builder(hyper::server::accept::from_stream(incoming))
+                    .http1_half_close(false)
+                    .serve(make_svc)
+                    .with_graceful_shutdown(shutdown_signal)
+                    .boxed()
+            },
+            None => {
+                let make_svc = make_service_fn(move |socket: &AddrStream| {
+                    let remote_addr = socket.remote_addr();
+                    async move {
+                        Ok::<_, Infallible>(service_fn(move |req: Request<Body>| async move {
+                            let res = rpc_service(req, ctx_h, remote_addr).await;
+                            Ok::<_, Infallible>(res)
+                        }))
+                    }
+                });
+
+                unwrap!(Server::try_bind(&rpc_ip_port), "Can't bind on {}", rpc_ip_port)
+                    .http1_half_close(false)
+                    .serve(make_svc)
+                    .with_graceful_shutdown(shutdown_signal)
+                    .boxed()
+            },
+        };
 
         let server = server.then(|r| {
             if let Err(err) = r {
@@ -338,8 +469,9 @@ pub extern "C" fn spawn_rpc(ctx_h: u32) {
         });
 
         let rpc_ip_port = unwrap!(ctx.rpc_ip_port());
+        let protocol = if ctx.conf["rpc_tls"].is_null() { "http" } else { "https" };
         CORE.0.spawn({
-            log!(">>>>>>>>>> DEX stats " (rpc_ip_port.ip())":"(rpc_ip_port.port()) " \
+            log!(">>>>>>>>>> DEX stats " (protocol) "://" (rpc_ip_port.ip())":"(rpc_ip_port.port()) " \
                 DEX stats API enabled at unixtime." (gstuff::now_ms() / 1000) " <<<<<<<<<");
             let _ = ctx.rpc_started.pin(true);
             server
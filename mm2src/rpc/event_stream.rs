@@ -0,0 +1,432 @@
+//! The server-sent events stream pushing the swap, order, orderbook and balance updates to the RPC clients.
+//!
+//! The stream is opened by the POST request to the `/event_stream` path of the RPC port, e.g.
+//! `{"userpass": "...", "events": ["swaps", "orders"], "orderbook_pairs": [["RICK", "MORTY"]]}`.
+//! Every event is sent as the `data: <json>` line of the `text/event-stream` response.
+
+use async_trait::async_trait;
+use bigdecimal::BigDecimal;
+use coins::{BalanceTradeFeeUpdatedHandler, MmCoinEnum};
+use common::mm_ctx::{from_ctx, MmArc};
+use futures::channel::mpsc::{channel, Receiver, Sender};
+use serde_json::{self as json, Value as Json};
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+#[cfg(feature = "native")] use super::auth;
+#[cfg(feature = "native")] use common::err_to_rpc_json_string;
+#[cfg(feature = "native")] use common::executor::Timer;
+#[cfg(feature = "native")] use futures::StreamExt;
+#[cfg(feature = "native")]
+use http::header::{HeaderValue, CACHE_CONTROL, CONTENT_TYPE};
+#[cfg(feature = "native")] use hyper::{Body, Request, Response};
+#[cfg(feature = "native")] use std::net::SocketAddr;
+
+/// The path of the RPC server the event stream is served at.
+pub const EVENT_STREAM_PATH: &str = "/event_stream";
+/// The method the event stream requests are authenticated as, whatever `method` is sent by the client.
+pub const EVENT_STREAM_METHOD: &str = "event_stream";
+/// The interval of the comment lines sent to detect the closed connections.
+const KEEP_ALIVE_INTERVAL: f64 = 15.;
+/// The max number of the events queued for a subscriber.
+/// The subscriber that doesn't keep up with the events is disconnected, so a slow client can't exhaust the memory.
+const MAX_QUEUED_EVENTS: usize = 1000;
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Swaps,
+    Orders,
+    Orderbook,
+    Balances,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum SwapType {
+    Maker,
+    Taker,
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub enum OrderStatusChange {
+    Created,
+    Updated,
+    Matched,
+    SwapStarted,
+    ConvertedToMaker,
+    Completed,
+    Cancelled,
+}
+
+/// The status change of the order created by our node.
+#[derive(Clone, Debug, Serialize)]
+pub struct MyOrderEvent {
+    pub uuid: Uuid,
+    /// "Maker" or "Taker".
+    pub order_type: &'static str,
+    pub status: OrderStatusChange,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct OrderbookOrder {
+    pub uuid: Uuid,
+    pub pubkey: String,
+    pub base: String,
+    pub rel: String,
+    pub price: BigDecimal,
+    pub max_volume: BigDecimal,
+    pub min_volume: BigDecimal,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", content = "data")]
+pub enum MmEvent {
+    /// The swap event that was saved to the swap file.
+    SwapEvent {
+        uuid: Uuid,
+        swap_type: SwapType,
+        event: Json,
+    },
+    MyOrder(MyOrderEvent),
+    /// The order is added to the orderbook or its price or volume is changed.
+    OrderbookOrderUpdated(OrderbookOrder),
+    OrderbookOrderRemoved {
+        uuid: Uuid,
+        base: String,
+        rel: String,
+    },
+    BalanceUpdated {
+        coin: String,
+        balance: BigDecimal,
+    },
+}
+
+impl MmEvent {
+    fn kind(&self) -> EventKind {
+        match self {
+            MmEvent::SwapEvent { .. } => EventKind::Swaps,
+            MmEvent::MyOrder(_) => EventKind::Orders,
+            MmEvent::OrderbookOrderUpdated(_) | MmEvent::OrderbookOrderRemoved { .. } => EventKind::Orderbook,
+            MmEvent::BalanceUpdated { .. } => EventKind::Balances,
+        }
+    }
+
+    fn orderbook_pair(&self) -> Option<(&str, &str)> {
+        match self {
+            MmEvent::OrderbookOrderUpdated(order) => Some((order.base.as_str(), order.rel.as_str())),
+            MmEvent::OrderbookOrderRemoved { base, rel, .. } => Some((base.as_str(), rel.as_str())),
+            _ => None,
+        }
+    }
+}
+
+fn all_event_kinds() -> Vec<EventKind> {
+    vec![
+        EventKind::Swaps,
+        EventKind::Orders,
+        EventKind::Orderbook,
+        EventKind::Balances,
+    ]
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EventStreamRequest {
+    /// The kinds of the events to send, all of them by default.
+    #[serde(default = "all_event_kinds")]
+    events: Vec<EventKind>,
+    /// The `[base, rel]` pairs the orderbook updates are sent for, the reversed pairs are included.
+    #[serde(default)]
+    orderbook_pairs: Vec<(String, String)>,
+}
+
+impl EventStreamRequest {
+    fn matches(&self, event: &MmEvent) -> bool {
+        if !self.events.contains(&event.kind()) {
+            return false;
+        }
+        match event.orderbook_pair() {
+            Some((base, rel)) => self
+                .orderbook_pairs
+                .iter()
+                .any(|(b, r)| (b == base && r == rel) || (b == rel && r == base)),
+            None => true,
+        }
+    }
+}
+
+struct EventSubscriber {
+    filter: EventStreamRequest,
+    tx: Sender<MmEvent>,
+}
+
+#[derive(Default)]
+pub struct EventStreamContext {
+    subscribers: Mutex<Vec<EventSubscriber>>,
+}
+
+impl EventStreamContext {
+    /// Obtains a reference to this mod context, creating it if necessary.
+    pub fn from_ctx(ctx: &MmArc) -> Result<Arc<EventStreamContext>, String> {
+        Ok(try_s!(from_ctx(&ctx.event_stream_ctx, move || {
+            Ok(EventStreamContext::default())
+        })))
+    }
+
+    fn subscribe(&self, filter: EventStreamRequest) -> Receiver<MmEvent> {
+        let (tx, rx) = channel(MAX_QUEUED_EVENTS);
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.push(EventSubscriber { filter, tx });
+        rx
+    }
+
+    /// Sends the event to the matching subscribers.
+    /// The event is built only if there is at least one subscriber.
+    pub fn publish_with<F>(&self, event: F)
+    where
+        F: FnOnce() -> MmEvent,
+    {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        // the closed streams are removed even if the event doesn't match their filters
+        subscribers.retain(|subscriber| !subscriber.tx.is_closed());
+        if subscribers.is_empty() {
+            return;
+        }
+        let event = event();
+        *subscribers = subscribers
+            .drain(..)
+            .filter_map(|mut subscriber| {
+                if !subscriber.filter.matches(&event) {
+                    return Some(subscriber);
+                }
+                match subscriber.tx.try_send(event.clone()) {
+                    Ok(_) => Some(subscriber),
+                    Err(e) => {
+                        if e.is_full() {
+                            log!("Event stream subscriber doesn't keep up with the events, disconnecting");
+                        }
+                        None
+                    },
+                }
+            })
+            .collect();
+    }
+}
+
+pub fn broadcast_event<F>(ctx: &MmArc, event: F)
+where
+    F: FnOnce() -> MmEvent,
+{
+    match EventStreamContext::from_ctx(ctx) {
+        Ok(event_stream_ctx) => event_stream_ctx.publish_with(event),
+        Err(e) => log!("Error " (e) " on getting the event stream context"),
+    }
+}
+
+pub fn broadcast_swap_event(ctx: &MmArc, uuid: Uuid, swap_type: SwapType, event: Json) {
+    broadcast_event(ctx, || MmEvent::SwapEvent { uuid, swap_type, event })
+}
+
+pub fn broadcast_my_order_event(ctx: &MmArc, uuid: Uuid, order_type: &'static str, status: OrderStatusChange) {
+    broadcast_event(ctx, || {
+        MmEvent::MyOrder(MyOrderEvent {
+            uuid,
+            order_type,
+            status,
+        })
+    })
+}
+
+pub struct BalanceUpdateEventStreamHandler {
+    ctx: MmArc,
+}
+
+impl BalanceUpdateEventStreamHandler {
+    pub fn new(ctx: MmArc) -> Self { BalanceUpdateEventStreamHandler { ctx } }
+}
+
+#[async_trait]
+impl BalanceTradeFeeUpdatedHandler for BalanceUpdateEventStreamHandler {
+    async fn balance_updated(&self, coin: &MmCoinEnum, new_balance: &BigDecimal) {
+        broadcast_event(&self.ctx, || MmEvent::BalanceUpdated {
+            coin: coin.ticker().to_owned(),
+            balance: new_balance.clone(),
+        })
+    }
+}
+
+#[cfg(feature = "native")]
+fn event_stream_error(status: u16, err: &str) -> Response<Body> {
+    log!("Event stream error response: "(err));
+    let body = err_to_rpc_json_string(err);
+    Response::builder()
+        .status(status)
+        .body(Body::from(body))
+        .expect("Response with static parts should never fail")
+}
+
+/// Authenticates the request with the `rpc_password` and starts streaming the events.
+#[cfg(feature = "native")]
+pub async fn event_stream_service(ctx: MmArc, req: Request<Body>, client: SocketAddr) -> Response<Body> {
+    let local_only = ctx.conf["rpc_local_only"].as_bool().unwrap_or(true);
+    if local_only && !client.ip().is_loopback() {
+        return event_stream_error(403, "Event stream can be opened from localhost only!");
+    }
+
+    let req_bytes = match hyper::body::to_bytes(req.into_body()).await {
+        Ok(bytes) => bytes,
+        Err(e) => return event_stream_error(400, &ERRL!("{}", e)),
+    };
+    let mut req_json: Json = match json::from_slice(&req_bytes) {
+        Ok(json) => json,
+        Err(e) => return event_stream_error(400, &ERRL!("{}", e)),
+    };
+    if !req_json.is_object() {
+        return event_stream_error(400, "Event stream request must be a JSON object");
+    }
+    // the method is set by the server, so the stream can't be opened as a public method claimed by the client
+    req_json["method"] = EVENT_STREAM_METHOD.into();
+    if let Err(e) = auth(&req_json, &ctx, &client) {
+        return event_stream_error(401, &e);
+    }
+    let filter: EventStreamRequest = match json::from_value(req_json) {
+        Ok(filter) => filter,
+        Err(e) => return event_stream_error(400, &ERRL!("{}", e)),
+    };
+
+    for (base, rel) in filter.orderbook_pairs.iter() {
+        if let Err(e) = crate::mm2::lp_ordermatch::subscribe_to_orderbook(&ctx, base, rel).await {
+            return event_stream_error(500, &e);
+        }
+    }
+
+    let event_stream_ctx = match EventStreamContext::from_ctx(&ctx) {
+        Ok(event_stream_ctx) => event_stream_ctx,
+        Err(e) => return event_stream_error(500, &e),
+    };
+    // `None` marks the end of the events, the response ends then instead of sending the keep-alive comments forever
+    let events = event_stream_ctx
+        .subscribe(filter)
+        .map(|event| {
+            let event = json::to_string(&event).expect("Serialization should never fail");
+            Some(format!("data: {}\n\n", event))
+        })
+        .chain(futures::stream::once(futures::future::ready(None)));
+    let keep_alive = futures::stream::unfold((), |_| async {
+        Timer::sleep(KEEP_ALIVE_INTERVAL).await;
+        Some((Some(":\n\n".to_owned()), ()))
+    });
+    let stream = futures::stream::select(events, keep_alive)
+        .take_while(|chunk| futures::future::ready(chunk.is_some()))
+        .map(|chunk| Ok::<_, std::convert::Infallible>(chunk.unwrap_or_default()));
+
+    let mut response = Response::new(Body::wrap_stream(stream));
+    let headers = response.headers_mut();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/event-stream"));
+    headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    response
+}
+
+#[cfg(test)]
+mod event_stream_tests {
+    use super::*;
+
+    fn orderbook_event(base: &str, rel: &str) -> MmEvent {
+        MmEvent::OrderbookOrderRemoved {
+            uuid: Uuid::new_v4(),
+            base: base.into(),
+            rel: rel.into(),
+        }
+    }
+
+    #[test]
+    fn test_event_stream_request_matches() {
+        let filter: EventStreamRequest = json::from_value(json!({
+            "events": ["orderbook", "balances"],
+            "orderbook_pairs": [["RICK", "MORTY"]],
+        }))
+        .unwrap();
+
+        assert!(filter.matches(&orderbook_event("RICK", "MORTY")));
+        assert!(filter.matches(&orderbook_event("MORTY", "RICK")));
+        assert!(!filter.matches(&orderbook_event("RICK", "KMD")));
+        assert!(filter.matches(&MmEvent::BalanceUpdated {
+            coin: "RICK".into(),
+            balance: 1.into(),
+        }));
+        assert!(!filter.matches(&MmEvent::MyOrder(MyOrderEvent {
+            uuid: Uuid::new_v4(),
+            order_type: "Maker",
+            status: OrderStatusChange::Created,
+        })));
+
+        let filter: EventStreamRequest = json::from_value(json!({})).unwrap();
+        assert_eq!(filter.events, all_event_kinds());
+        // the orderbook events are sent for the requested pairs only
+        assert!(!filter.matches(&orderbook_event("RICK", "MORTY")));
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn test_event_stream_method_is_not_public() {
+        let ctx = common::mm_ctx::MmCtxBuilder::new()
+            .with_conf(json!({"rpc_password": "password"}))
+            .into_mm_arc();
+        let client = "127.0.0.1:12345".parse().unwrap();
+        assert!(auth(&json!({ "method": EVENT_STREAM_METHOD }), &ctx, &client).is_err());
+        let req = json!({"method": EVENT_STREAM_METHOD, "userpass": "password"});
+        assert!(auth(&req, &ctx, &client).is_ok());
+    }
+
+    #[test]
+    fn test_publish_removes_closed_subscribers() {
+        let ctx = EventStreamContext::default();
+        let mut rx = ctx.subscribe(json::from_value(json!({"events": ["balances"]})).unwrap());
+        let closed_rx = ctx.subscribe(json::from_value(json!({})).unwrap());
+        drop(closed_rx);
+
+        ctx.publish_with(|| MmEvent::BalanceUpdated {
+            coin: "RICK".into(),
+            balance: 1.into(),
+        });
+        assert_eq!(ctx.subscribers.lock().unwrap().len(), 1);
+        match rx.try_next() {
+            Ok(Some(MmEvent::BalanceUpdated { coin, .. })) => assert_eq!(coin, "RICK"),
+            other => panic!("Unexpected {:?}", other),
+        }
+
+        // the event that doesn't match the filter isn't sent
+        ctx.publish_with(|| orderbook_event("RICK", "MORTY"));
+        assert!(rx.try_next().is_err());
+
+        // the closed subscriber is removed even if the event doesn't match its filter
+        drop(rx);
+        let _rx = ctx.subscribe(json::from_value(json!({"events": ["balances"]})).unwrap());
+        ctx.publish_with(|| orderbook_event("RICK", "MORTY"));
+        assert_eq!(ctx.subscribers.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_publish_disconnects_slow_subscribers() {
+        let ctx = EventStreamContext::default();
+        let mut rx = ctx.subscribe(json::from_value(json!({"events": ["balances"]})).unwrap());
+        // the channel has one more slot for the sender
+        for _ in 0..=MAX_QUEUED_EVENTS {
+            ctx.publish_with(|| MmEvent::BalanceUpdated {
+                coin: "RICK".into(),
+                balance: 1.into(),
+            });
+        }
+        assert_eq!(ctx.subscribers.lock().unwrap().len(), 1);
+
+        ctx.publish_with(|| MmEvent::BalanceUpdated {
+            coin: "RICK".into(),
+            balance: 1.into(),
+        });
+        assert!(ctx.subscribers.lock().unwrap().is_empty());
+        // the queued events are still delivered and then the stream ends
+        for _ in 0..=MAX_QUEUED_EVENTS {
+            assert!(matches!(rx.try_next(), Ok(Some(_))));
+        }
+        assert!(matches!(rx.try_next(), Ok(None)));
+    }
+}
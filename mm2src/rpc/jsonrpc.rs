@@ -0,0 +1,382 @@
+//! JSON-RPC 2.0 endpoint, https://www.jsonrpc.org/specification
+//!
+//! The requests are POSTed to `/jsonrpc`. The method params are passed by name, "userpass" is one of them:
+//!
+//!     {"jsonrpc": "2.0", "id": 1, "method": "my_balance", "params": {"userpass": "...", "coin": "RICK"}}
+//!
+//! Batches and notifications are supported. The methods are the same as the legacy RPC ones,
+//! the legacy `{"result": ...}` wrapper is removed from the responses.
+
+use coins::TradePreimageError;
+use common::mm_ctx::MmArc;
+use futures::compat::Future01CompatExt;
+use futures::future::join_all;
+use serde_json::{self as json, Map, Value as Json};
+use std::net::SocketAddr;
+
+use super::{auth, dispatcher, DispatcherRes, PUBLIC_METHODS};
+use crate::mm2::lp_ordermatch::{buy_rpc, sell_rpc, set_price_rpc};
+use crate::mm2::lp_swap::{max_taker_vol_rpc, trade_preimage_rpc, CheckBalanceError, TradeRequestError};
+
+#[cfg(feature = "native")]
+use http::header::{HeaderValue, CONTENT_TYPE};
+#[cfg(feature = "native")]
+use http::{Request, Response, StatusCode};
+#[cfg(feature = "native")] use hyper::Body;
+
+pub const JSON_RPC_PATH: &str = "/jsonrpc";
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+/// The generic implementation-defined error, the message is the legacy RPC error.
+pub const SERVER_ERROR: i64 = -32000;
+pub const UNAUTHORIZED: i64 = -32001;
+pub const NOT_SUFFICIENT_BALANCE: i64 = -32010;
+
+/// The max number of the requests in a batch, they are processed concurrently.
+const MAX_BATCH_SIZE: usize = 100;
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct JsonRpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Json>,
+}
+
+impl JsonRpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> JsonRpcError {
+        JsonRpcError {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+}
+
+impl From<CheckBalanceError> for JsonRpcError {
+    fn from(e: CheckBalanceError) -> Self {
+        let code = match e {
+            CheckBalanceError::NotSufficientBalance(_) => NOT_SUFFICIENT_BALANCE,
+            CheckBalanceError::Other(_) => SERVER_ERROR,
+        };
+        JsonRpcError::new(code, e.to_string())
+    }
+}
+
+impl From<TradeRequestError> for JsonRpcError {
+    fn from(e: TradeRequestError) -> Self {
+        let code = match e {
+            TradeRequestError::InvalidParams(_) => INVALID_PARAMS,
+            TradeRequestError::NotSufficientBalance(_) => NOT_SUFFICIENT_BALANCE,
+            TradeRequestError::Other(_) => SERVER_ERROR,
+        };
+        JsonRpcError::new(code, e.to_string())
+    }
+}
+
+impl From<TradePreimageError> for JsonRpcError {
+    fn from(e: TradePreimageError) -> Self {
+        let code = match e {
+            TradePreimageError::NotSufficientBalance(_) => NOT_SUFFICIENT_BALANCE,
+            TradePreimageError::Other(_) => SERVER_ERROR,
+        };
+        JsonRpcError::new(code, e.to_string())
+    }
+}
+
+fn response(id: Json, result: Result<Json, JsonRpcError>) -> Json {
+    match result {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err(error) => json!({"jsonrpc": "2.0", "id": id, "error": error}),
+    }
+}
+
+/// Removes the legacy `{"result": ...}` wrapper if it's the only member of the response.
+fn unwrap_legacy_result(res: Json) -> Json {
+    match res {
+        Json::Object(mut map) if map.len() == 1 && map.contains_key("result") => map.remove("result").unwrap(),
+        res => res,
+    }
+}
+
+async fn legacy_dispatch(ctx: MmArc, req: Json) -> Result<Json, JsonRpcError> {
+    let handler = match dispatcher(req, ctx) {
+        DispatcherRes::Match(handler) => handler,
+        DispatcherRes::NoMatch(req) => {
+            return Err(JsonRpcError::new(
+                METHOD_NOT_FOUND,
+                format!("No such method: {}", req["method"]),
+            ))
+        },
+    };
+    let res = handler.compat().await.map_err(|e| JsonRpcError::new(SERVER_ERROR, e))?;
+    let body = match json::from_slice(res.body()) {
+        Ok(body) => body,
+        Err(_) => Json::String(String::from_utf8_lossy(res.body()).into_owned()),
+    };
+    if !res.status().is_success() {
+        let message = match body["error"].as_str() {
+            Some(error) => error.to_owned(),
+            None => body.to_string(),
+        };
+        return Err(JsonRpcError::new(SERVER_ERROR, message));
+    }
+    Ok(body)
+}
+
+/// Calls the method keeping its typed error if it's available, falls back to the legacy dispatcher otherwise.
+async fn dispatch(ctx: MmArc, method: String, req: Json) -> Result<Json, JsonRpcError> {
+    let res = match &method[..] {
+        // Sorted alphanumerically (on the first letter) for readability.
+        "buy" => buy_rpc(&ctx, req).await?,
+        "max_taker_vol" => max_taker_vol_rpc(&ctx, req).await?,
+        "sell" => sell_rpc(&ctx, req).await?,
+        "setprice" => set_price_rpc(&ctx, req).await?,
+        "trade_preimage" => trade_preimage_rpc(&ctx, req).await?,
+        _ => legacy_dispatch(ctx, req).await?,
+    };
+    Ok(unwrap_legacy_result(res))
+}
+
+/// Processes a single JSON-RPC request.
+/// Returns `None` if the request is a notification, i.e. has no "id".
+async fn process_single_request(ctx: MmArc, req: Json, client: SocketAddr) -> Option<Json> {
+    let req = match req {
+        Json::Object(req) => req,
+        _ => {
+            return Some(response(
+                Json::Null,
+                Err(JsonRpcError::new(INVALID_REQUEST, "Invalid Request")),
+            ))
+        },
+    };
+    let id = match req.get("id") {
+        None => None,
+        Some(id @ Json::Null) | Some(id @ Json::Number(_)) | Some(id @ Json::String(_)) => Some(id.clone()),
+        Some(_) => {
+            let error = JsonRpcError::new(INVALID_REQUEST, "'id' must be a string, number or null");
+            return Some(response(Json::Null, Err(error)));
+        },
+    };
+    let result = process_request_object(ctx, req, client).await;
+    id.map(|id| response(id, result))
+}
+
+async fn process_request_object(ctx: MmArc, req: Map<String, Json>, client: SocketAddr) -> Result<Json, JsonRpcError> {
+    if req.get("jsonrpc").and_then(Json::as_str) != Some("2.0") {
+        return Err(JsonRpcError::new(INVALID_REQUEST, "'jsonrpc' must be exactly \"2.0\""));
+    }
+    let method = match req.get("method") {
+        Some(Json::String(method)) => method.clone(),
+        _ => return Err(JsonRpcError::new(INVALID_REQUEST, "'method' must be a string")),
+    };
+    let mut legacy_req = match req.get("params") {
+        None | Some(Json::Null) => Map::new(),
+        Some(Json::Object(params)) => params.clone(),
+        Some(Json::Array(_)) => return Err(JsonRpcError::new(INVALID_PARAMS, "Only the named params are supported")),
+        Some(_) => return Err(JsonRpcError::new(INVALID_REQUEST, "'params' must be an object")),
+    };
+    legacy_req.insert("method".into(), Json::String(method.clone()));
+    let legacy_req = Json::Object(legacy_req);
+
+    let local_only = ctx.conf["rpc_local_only"].as_bool().unwrap_or(true);
+    if local_only && !client.ip().is_loopback() && !PUBLIC_METHODS.contains(&Some(&method[..])) {
+        return Err(JsonRpcError::new(
+            UNAUTHORIZED,
+            "Selected method can be called from localhost only!",
+        ));
+    }
+    auth(&legacy_req, &ctx, &client).map_err(|e| JsonRpcError::new(UNAUTHORIZED, e))?;
+
+    dispatch(ctx, method, legacy_req).await
+}
+
+/// Processes the JSON-RPC request body.
+/// Returns `None` if there is nothing to reply, i.e. the body consists of notifications only.
+/// Each request of a batch is authorized separately, so it counts against the credential rate limit.
+pub async fn process_json_rpc_request(ctx: MmArc, body: &[u8], client: SocketAddr) -> Option<Json> {
+    let req: Json = match json::from_slice(body) {
+        Ok(req) => req,
+        Err(e) => return Some(response(Json::Null, Err(JsonRpcError::new(PARSE_ERROR, e.to_string())))),
+    };
+    match req {
+        Json::Array(requests) => {
+            if requests.is_empty() {
+                let error = JsonRpcError::new(INVALID_REQUEST, "Empty batch");
+                return Some(response(Json::Null, Err(error)));
+            }
+            if requests.len() > MAX_BATCH_SIZE {
+                let error = JsonRpcError::new(
+                    INVALID_REQUEST,
+                    format!("The batch exceeds {} requests", MAX_BATCH_SIZE),
+                );
+                return Some(response(Json::Null, Err(error)));
+            }
+            let futures = requests
+                .into_iter()
+                .map(|req| process_single_request(ctx.clone(), req, client));
+            let responses: Vec<Json> = join_all(futures).await.into_iter().flatten().collect();
+            if responses.is_empty() {
+                None
+            } else {
+                Some(Json::Array(responses))
+            }
+        },
+        req => process_single_request(ctx, req, client).await,
+    }
+}
+
+#[cfg(feature = "native")]
+pub async fn json_rpc_service(ctx: MmArc, req: Request<Body>, client: SocketAddr) -> Response<Body> {
+    let body = match hyper::body::to_bytes(req.into_body()).await {
+        Ok(body) => body,
+        Err(e) => {
+            let res = response(Json::Null, Err(JsonRpcError::new(INTERNAL_ERROR, e.to_string())));
+            return json_rpc_response(Some(res));
+        },
+    };
+    json_rpc_response(process_json_rpc_request(ctx, &body, client).await)
+}
+
+#[cfg(feature = "native")]
+fn json_rpc_response(res: Option<Json>) -> Response<Body> {
+    match res {
+        Some(res) => {
+            let mut response = Response::new(Body::from(res.to_string()));
+            response
+                .headers_mut()
+                .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+            response
+        },
+        None => {
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = StatusCode::NO_CONTENT;
+            response
+        },
+    }
+}
+
+#[cfg(test)]
+mod jsonrpc_tests {
+    use super::*;
+    use common::block_on;
+    use common::mm_ctx::MmCtxBuilder;
+
+    fn process(ctx: &MmArc, body: &str) -> Option<Json> {
+        let client: SocketAddr = "127.0.0.1:1".parse().unwrap();
+        block_on(process_json_rpc_request(ctx.clone(), body.as_bytes(), client))
+    }
+
+    fn ctx() -> MmArc {
+        MmCtxBuilder::new()
+            .with_conf(json!({"rpc_password": "pass"}))
+            .into_mm_arc()
+    }
+
+    #[test]
+    fn test_json_rpc_invalid_requests() {
+        let ctx = ctx();
+
+        let res = process(&ctx, "{").unwrap();
+        assert_eq!(res["error"]["code"], PARSE_ERROR);
+        assert_eq!(res["id"], Json::Null);
+
+        let res = process(&ctx, "[]").unwrap();
+        assert_eq!(res["error"]["code"], INVALID_REQUEST);
+
+        let res = process(&ctx, r#"{"id": 1, "method": "version"}"#).unwrap();
+        assert_eq!(res["error"]["code"], INVALID_REQUEST);
+        assert_eq!(res["id"], 1);
+
+        let res = process(
+            &ctx,
+            r#"{"jsonrpc": "2.0", "id": "a", "method": "version", "params": [1]}"#,
+        )
+        .unwrap();
+        assert_eq!(res["error"]["code"], INVALID_PARAMS);
+        assert_eq!(res["id"], "a");
+
+        let res = process(&ctx, r#"{"jsonrpc": "2.0", "id": 2, "method": "version"}"#).unwrap();
+        assert_eq!(res["error"]["code"], UNAUTHORIZED);
+
+        let res = process(
+            &ctx,
+            r#"{"jsonrpc": "2.0", "id": 3, "method": "no_such_method", "params": {"userpass": "pass"}}"#,
+        )
+        .unwrap();
+        assert_eq!(res["error"]["code"], METHOD_NOT_FOUND);
+    }
+
+    #[test]
+    fn test_json_rpc_batch_and_notifications() {
+        let ctx = ctx();
+
+        let body = r#"[
+            {"jsonrpc": "2.0", "id": 1, "method": "get_enabled_coins", "params": {"userpass": "pass"}},
+            {"jsonrpc": "2.0", "method": "get_enabled_coins", "params": {"userpass": "pass"}},
+            {"jsonrpc": "2.0", "id": "x", "method": "my_balance", "params": {"userpass": "pass", "coin": "RICK"}},
+            1
+        ]"#;
+        let res = process(&ctx, body).unwrap();
+        let expected = json!([
+            {"jsonrpc": "2.0", "id": 1, "result": []},
+            {"jsonrpc": "2.0", "id": "x", "error": res[1]["error"].clone()},
+            {"jsonrpc": "2.0", "id": null, "error": {"code": INVALID_REQUEST, "message": "Invalid Request"}},
+        ]);
+        assert_eq!(expected, res);
+        assert_eq!(res[1]["error"]["code"], SERVER_ERROR);
+
+        let body = r#"{"jsonrpc": "2.0", "method": "get_enabled_coins", "params": {"userpass": "pass"}}"#;
+        assert_eq!(None, process(&ctx, body));
+    }
+
+    #[test]
+    fn test_json_rpc_batch_limits() {
+        let ctx = MmCtxBuilder::new()
+            .with_conf(json!({
+                "rpc_password": "pass",
+                "rpc_credentials": [
+                    {"name": "dashboard", "password": "dashboard", "role": "read_only", "rate_limit": 2},
+                ],
+            }))
+            .into_mm_arc();
+
+        let request = json!({"jsonrpc": "2.0", "id": 1, "method": "get_enabled_coins", "params": {"userpass": "pass"}});
+        let batch = Json::Array(vec![request; MAX_BATCH_SIZE + 1]);
+        let res = process(&ctx, &batch.to_string()).unwrap();
+        assert_eq!(res["error"]["code"], INVALID_REQUEST);
+
+        // every request of the batch counts against the rate limit of 2 requests per minute
+        let request =
+            json!({"jsonrpc": "2.0", "id": 1, "method": "get_enabled_coins", "params": {"userpass": "dashboard"}});
+        let batch = Json::Array(vec![request; 3]);
+        let res = process(&ctx, &batch.to_string()).unwrap();
+        assert_eq!(res[0]["result"], json!([]));
+        assert_eq!(res[1]["result"], json!([]));
+        assert_eq!(res[2]["error"]["code"], UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_json_rpc_error_from_typed_errors() {
+        let error = JsonRpcError::from(CheckBalanceError::NotSufficientBalance("balance 0".into()));
+        assert_eq!(NOT_SUFFICIENT_BALANCE, error.code);
+        assert_eq!("Not sufficient balance: balance 0", error.message);
+
+        let error = JsonRpcError::from(TradePreimageError::Other("error".into()));
+        assert_eq!(SERVER_ERROR, error.code);
+
+        let error = JsonRpcError::from(TradeRequestError::InvalidParams(
+            "Base and rel must be different coins".into(),
+        ));
+        assert_eq!(INVALID_PARAMS, error.code);
+
+        let error = JsonRpcError::from(TradeRequestError::from(CheckBalanceError::NotSufficientBalance(
+            "balance 0".into(),
+        )));
+        assert_eq!(NOT_SUFFICIENT_BALANCE, error.code);
+    }
+}
@@ -22,12 +22,14 @@
 
 use coins::{disable_coin as disable_coin_impl, lp_coinfind, lp_coininit, MmCoinEnum};
 use common::executor::{spawn, Timer};
+use common::keystore::Keystore;
 use common::mm_ctx::MmArc;
 use common::{rpc_err_response, rpc_response, HyRes, MM_DATETIME, MM_VERSION};
 use futures::compat::Future01CompatExt;
 use http::Response;
 use serde_json::{self as json, Value as Json};
 use std::borrow::Cow;
+use std::path::Path;
 
 use crate::mm2::lp_ordermatch::{cancel_orders_by, CancelBy};
 use crate::mm2::lp_swap::active_swaps_using_coin;
@@ -284,6 +286,55 @@ pub async fn get_my_peer_id(ctx: MmArc) -> Result<Response<Vec<u8>>, String> {
     Ok(try_s!(Response::builder().body(res)))
 }
 
+fn keystore_path(ctx: &MmArc) -> Result<&Path, String> {
+    match ctx.conf["keystore"].as_str() {
+        Some(path) => Ok(Path::new(path)),
+        None => ERR!("The keystore path is not set with the 'keystore' config option"),
+    }
+}
+
+/// Encrypts the `passphrase` by the `password` and writes it to the new keystore file
+/// configured with the `keystore` config option.
+pub async fn create_keystore(ctx: MmArc, req: Json) -> Result<Response<Vec<u8>>, String> {
+    let path = try_s!(keystore_path(&ctx));
+    if path.exists() {
+        return ERR!("Keystore {} already exists", path.display());
+    }
+    let password = try_s!(req["password"].as_str().ok_or("No 'password' field"));
+    let passphrase = match req["passphrase"].as_str() {
+        Some(passphrase) if !passphrase.is_empty() => passphrase,
+        _ => return ERR!("No 'passphrase' field"),
+    };
+    let keystore = try_s!(Keystore::encrypt(passphrase, password));
+    try_s!(keystore.write(path));
+    let res = try_s!(json::to_vec(&json!({
+        "result": "success",
+    })));
+    Ok(try_s!(Response::builder().body(res)))
+}
+
+pub async fn change_keystore_password(ctx: MmArc, req: Json) -> Result<Response<Vec<u8>>, String> {
+    let path = try_s!(keystore_path(&ctx));
+    let old_password = try_s!(req["old_password"].as_str().ok_or("No 'old_password' field"));
+    let new_password = try_s!(req["new_password"].as_str().ok_or("No 'new_password' field"));
+    let keystore = try_s!(Keystore::read(path));
+    let keystore = try_s!(keystore.change_password(old_password, new_password));
+    try_s!(keystore.write(path));
+    let res = try_s!(json::to_vec(&json!({
+        "result": "success",
+    })));
+    Ok(try_s!(Response::builder().body(res)))
+}
+
+/// Returns the content of the keystore file, the passphrase stays encrypted.
+pub async fn export_keystore(ctx: MmArc) -> Result<Response<Vec<u8>>, String> {
+    let keystore = try_s!(Keystore::read(try_s!(keystore_path(&ctx))));
+    let res = try_s!(json::to_vec(&json!({
+        "result": keystore,
+    })));
+    Ok(try_s!(Response::builder().body(res)))
+}
+
 // AP: Inventory is not documented and not used as of now, commented out
 /*
 pub fn inventory (ctx: MmArc, req: Json) -> HyRes {
@@ -0,0 +1,272 @@
+//! The named RPC credentials configured with the `rpc_credentials` config option, e.g.
+//! `[{"name": "dashboard", "password": "...", "role": "read_only", "rate_limit": 60}]`.
+//!
+//! Every credential is allowed to call the methods of its `role` and the `allowed_methods`,
+//! and is limited to `rate_limit` requests per minute if it's set.
+//! The `rpc_password` keeps working as the admin credential without the rate limit.
+
+use common::mm_ctx::{from_ctx, MmArc};
+use common::now_ms;
+use serde_json::{self as json, Value as Json};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+const RATE_LIMIT_WINDOW_MS: u64 = 60_000;
+
+/// The methods allowed to the `read_only` role.
+const READ_ONLY_METHODS: &[&str] = &[
+    // Sorted alphanumerically (on the first letter) for readability.
+    "active_swaps",
+    "all_swaps_uuids_by_filter",
+    "best_orders",
+    "coins_needed_for_kick_start",
+    "convertaddress",
+    "convert_utxo_address",
+    // The `/event_stream` requests are always authorized as this method.
+    "event_stream",
+    "get_enabled_coins",
+    "get_gossip_mesh",
+    "get_gossip_peer_topics",
+    "get_gossip_topic_peers",
+    "get_my_peer_id",
+    "get_peers_info",
+    "get_relay_mesh",
+    "get_trade_fee",
+    "hd_account_balance",
+    "hd_receive_addresses",
+    "kmd_rewards_info",
+    "list_banned_pubkeys",
+    "max_taker_vol",
+    "metrics",
+    "my_balance",
+    "my_orders",
+    "my_recent_swaps",
+    "my_swap_status",
+    "my_tx_history",
+    "ohlcv",
+    "order_status",
+    "orderbook",
+    "orderbook_depth",
+    "simple_market_maker_bot_status",
+    "stats_swap_status",
+    "trade_history",
+    "trade_preimage",
+    "validateaddress",
+    "version",
+];
+
+/// The methods allowed to the `trading` role in addition to the `read_only` ones.
+/// The funds can't be withdrawn by the trading credential.
+const TRADING_METHODS: &[&str] = &[
+    // Sorted alphanumerically (on the first letter) for readability.
+    "bump_swap_payment_fee",
+    "bump_swap_spend_fee",
+    "buy",
+    "cancel_all_orders",
+    "cancel_order",
+    "disable_coin",
+    "electrum",
+    "enable",
+    "import_swaps",
+    "recover_funds_of_swap",
+    "sell",
+    "set_required_confirmations",
+    "set_requires_notarization",
+    "setprice",
+    "start_simple_market_maker_bot",
+    "stop_simple_market_maker_bot",
+    "unban_pubkeys",
+    "update_maker_order",
+];
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RpcRole {
+    ReadOnly,
+    Trading,
+    /// Allowed to call every method.
+    Admin,
+}
+
+impl RpcRole {
+    fn allows(self, method: &str) -> bool {
+        match self {
+            RpcRole::ReadOnly => READ_ONLY_METHODS.contains(&method),
+            RpcRole::Trading => READ_ONLY_METHODS.contains(&method) || TRADING_METHODS.contains(&method),
+            RpcRole::Admin => true,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RpcCredential {
+    pub name: String,
+    password: String,
+    /// Only the `allowed_methods` can be called if the role is not set.
+    #[serde(default)]
+    role: Option<RpcRole>,
+    #[serde(default)]
+    allowed_methods: Vec<String>,
+    /// The max number of the requests per minute, unlimited if not set.
+    #[serde(default)]
+    rate_limit: Option<u32>,
+}
+
+impl RpcCredential {
+    fn allows(&self, method: &str) -> bool {
+        self.role.map_or(false, |role| role.allows(method)) || self.allowed_methods.iter().any(|m| m == method)
+    }
+}
+
+/// Parses and validates the `rpc_credentials` config option.
+pub fn rpc_credentials_from_conf(conf: &Json) -> Result<Vec<RpcCredential>, String> {
+    if conf["rpc_credentials"].is_null() {
+        return Ok(Vec::new());
+    }
+    let credentials: Vec<RpcCredential> = try_s!(json::from_value(conf["rpc_credentials"].clone()));
+    for (i, credential) in credentials.iter().enumerate() {
+        if credential.password.is_empty() {
+            return ERR!(
+                "The password of the RPC credential {} must not be empty",
+                credential.name
+            );
+        }
+        if conf["rpc_password"].as_str() == Some(credential.password.as_str()) {
+            return ERR!(
+                "The password of the RPC credential {} must differ from rpc_password",
+                credential.name
+            );
+        }
+        let is_duplicate = credentials[..i]
+            .iter()
+            .any(|other| other.name == credential.name || other.password == credential.password);
+        if is_duplicate {
+            return ERR!(
+                "The name and the password of the RPC credential {} must be unique",
+                credential.name
+            );
+        }
+    }
+    Ok(credentials)
+}
+
+struct RpcCredentialsContext {
+    credentials: Vec<RpcCredential>,
+    /// The start of the current rate limit window and the number of the requests in it by the credential name.
+    rate_limit_windows: Mutex<HashMap<String, (u64, u32)>>,
+}
+
+impl RpcCredentialsContext {
+    fn from_ctx(ctx: &MmArc) -> Result<Arc<RpcCredentialsContext>, String> {
+        Ok(try_s!(from_ctx(&ctx.rpc_credentials_ctx, move || {
+            Ok(RpcCredentialsContext {
+                credentials: try_s!(rpc_credentials_from_conf(&ctx.conf)),
+                rate_limit_windows: Mutex::new(HashMap::new()),
+            })
+        })))
+    }
+}
+
+/// Authorizes the `method` call by the credential with the `userpass` password
+/// and counts the call against the credential rate limit.
+/// The failed attempts are logged.
+pub fn authorize_credential(ctx: &MmArc, userpass: &str, method: &str, client: &SocketAddr) -> Result<(), String> {
+    let credentials_ctx = try_s!(RpcCredentialsContext::from_ctx(ctx));
+    let credential = match credentials_ctx.credentials.iter().find(|c| c.password == userpass) {
+        Some(credential) => credential,
+        None => {
+            log!("RPC auth failed] Invalid userpass of the " (method) " call from " (client));
+            return ERR!("Userpass is invalid!");
+        },
+    };
+
+    if !credential.allows(method) {
+        log!("RPC auth failed] The " (credential.name) " credential is not allowed to call " (method) " from " (client));
+        return ERR!(
+            "Method {} is not allowed for the {} credential",
+            method,
+            credential.name
+        );
+    }
+
+    if let Some(rate_limit) = credential.rate_limit {
+        let now = now_ms();
+        let mut windows = try_s!(credentials_ctx.rate_limit_windows.lock());
+        let (window_started_at, requests) = windows.entry(credential.name.clone()).or_insert((now, 0));
+        if now.saturating_sub(*window_started_at) >= RATE_LIMIT_WINDOW_MS {
+            *window_started_at = now;
+            *requests = 0;
+        }
+        if *requests >= rate_limit {
+            log!("RPC auth failed] The " (credential.name) " credential exceeded the rate limit calling " (method) " from " (client));
+            return ERR!("The rate limit of the {} credential is exceeded", credential.name);
+        }
+        *requests += 1;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod rpc_credentials_tests {
+    use super::*;
+    use common::mm_ctx::MmCtxBuilder;
+
+    #[test]
+    fn test_authorize_credential() {
+        let ctx = MmCtxBuilder::new()
+            .with_conf(json!({
+                "rpc_password": "admin",
+                "rpc_credentials": [
+                    {"name": "dashboard", "password": "dashboard", "role": "read_only", "rate_limit": 2},
+                    {"name": "bot", "password": "bot", "role": "trading", "allowed_methods": ["withdraw"]},
+                    {"name": "stopper", "password": "stopper", "allowed_methods": ["stop"]},
+                ],
+            }))
+            .into_mm_arc();
+        let client = "127.0.0.1:12345".parse().unwrap();
+
+        assert!(authorize_credential(&ctx, "unknown", "my_balance", &client).is_err());
+
+        assert!(authorize_credential(&ctx, "dashboard", "my_balance", &client).is_ok());
+        assert!(authorize_credential(&ctx, "dashboard", "withdraw", &client).is_err());
+        assert!(authorize_credential(&ctx, "dashboard", "orderbook", &client).is_ok());
+        // the rate limit of 2 requests per minute is exceeded
+        assert!(authorize_credential(&ctx, "dashboard", "my_balance", &client).is_err());
+
+        assert!(authorize_credential(&ctx, "bot", "sell", &client).is_ok());
+        assert!(authorize_credential(&ctx, "bot", "my_balance", &client).is_ok());
+        assert!(authorize_credential(&ctx, "bot", "withdraw", &client).is_ok());
+        assert!(authorize_credential(&ctx, "bot", "show_priv_key", &client).is_err());
+
+        assert!(authorize_credential(&ctx, "stopper", "stop", &client).is_ok());
+        assert!(authorize_credential(&ctx, "stopper", "my_balance", &client).is_err());
+        assert!(authorize_credential(&ctx, "stopper", "event_stream", &client).is_err());
+        assert!(authorize_credential(&ctx, "bot", "event_stream", &client).is_ok());
+    }
+
+    #[test]
+    fn test_rpc_credentials_from_conf() {
+        assert!(rpc_credentials_from_conf(&json!({})).unwrap().is_empty());
+        assert!(rpc_credentials_from_conf(&json!({
+            "rpc_credentials": [{"name": "x", "password": "x", "role": "superuser"}],
+        }))
+        .is_err());
+        assert!(rpc_credentials_from_conf(&json!({
+            "rpc_credentials": [{"name": "x", "password": "", "role": "admin"}],
+        }))
+        .is_err());
+        assert!(rpc_credentials_from_conf(&json!({
+            "rpc_password": "x",
+            "rpc_credentials": [{"name": "x", "password": "x", "role": "admin"}],
+        }))
+        .is_err());
+        assert!(rpc_credentials_from_conf(&json!({
+            "rpc_credentials": [
+                {"name": "x", "password": "x", "role": "admin"},
+                {"name": "y", "password": "x", "role": "read_only"},
+            ],
+        }))
+        .is_err());
+    }
+}
@@ -0,0 +1,86 @@
+//! The TLS termination of the RPC listener configured with the `rpc_tls` config option, e.g.
+//! `{"cert_path": "rpc.crt", "key_path": "rpc.key", "client_ca_path": "clients_ca.crt"}`.
+//!
+//! The clients have to present a certificate signed by the `client_ca_path` authority (mutual TLS) if it's set.
+//! Every endpoint served by the `rpc_service`, including the event stream, is served over the TLS connection.
+
+use serde_json::{self as json, Value as Json};
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+use tokio_rustls::rustls::internal::pemfile::{certs, pkcs8_private_keys, rsa_private_keys};
+use tokio_rustls::rustls::{AllowAnyAuthenticatedClient, Certificate, NoClientAuth, PrivateKey, RootCertStore,
+                           ServerConfig};
+
+#[derive(Debug, Deserialize)]
+struct RpcTlsConf {
+    /// The PEM encoded certificate chain of the RPC server.
+    cert_path: String,
+    /// The PEM encoded PKCS8 or RSA private key of the RPC server.
+    key_path: String,
+    /// The PEM encoded certificates of the authorities signing the client certificates.
+    #[serde(default)]
+    client_ca_path: Option<String>,
+}
+
+fn load_certs(path: &str) -> Result<Vec<Certificate>, String> {
+    let file = try_s!(File::open(path));
+    let certs = try_s!(certs(&mut BufReader::new(file)).map_err(|_| ERRL!("Invalid certificate {}", path)));
+    if certs.is_empty() {
+        return ERR!("No certificates found in {}", path);
+    }
+    Ok(certs)
+}
+
+fn load_private_key(path: &str) -> Result<PrivateKey, String> {
+    let file = try_s!(File::open(path));
+    let mut keys = try_s!(pkcs8_private_keys(&mut BufReader::new(file)).map_err(|_| ERRL!("Invalid key {}", path)));
+    if keys.is_empty() {
+        let file = try_s!(File::open(path));
+        keys = try_s!(rsa_private_keys(&mut BufReader::new(file)).map_err(|_| ERRL!("Invalid key {}", path)));
+    }
+    match keys.into_iter().next() {
+        Some(key) => Ok(key),
+        None => ERR!("No private key found in {}", path),
+    }
+}
+
+/// Builds the TLS config of the RPC server, returns `None` if the `rpc_tls` is not configured.
+pub fn rpc_tls_config(conf: &Json) -> Result<Option<Arc<ServerConfig>>, String> {
+    if conf["rpc_tls"].is_null() {
+        return Ok(None);
+    }
+    let tls_conf: RpcTlsConf = try_s!(json::from_value(conf["rpc_tls"].clone()));
+
+    let client_auth = match tls_conf.client_ca_path {
+        Some(ref client_ca_path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in try_s!(load_certs(client_ca_path)) {
+                try_s!(roots.add(&cert));
+            }
+            AllowAnyAuthenticatedClient::new(roots)
+        },
+        None => NoClientAuth::new(),
+    };
+
+    let mut config = ServerConfig::new(client_auth);
+    let certs = try_s!(load_certs(&tls_conf.cert_path));
+    let key = try_s!(load_private_key(&tls_conf.key_path));
+    try_s!(config.set_single_cert(certs, key));
+    Ok(Some(Arc::new(config)))
+}
+
+#[cfg(test)]
+mod rpc_tls_tests {
+    use super::*;
+
+    #[test]
+    fn test_rpc_tls_config() {
+        assert!(rpc_tls_config(&json!({})).unwrap().is_none());
+        assert!(rpc_tls_config(&json!({"rpc_tls": {"cert_path": "rpc.crt"}})).is_err());
+        assert!(rpc_tls_config(&json!({
+            "rpc_tls": {"cert_path": "/nonexistent/rpc.crt", "key_path": "/nonexistent/rpc.key"}
+        }))
+        .is_err());
+    }
+}